@@ -0,0 +1,50 @@
+// Runs a ROM with no window or sound and prints a hash of every frame drawn.  Saving the output
+// from one build and diffing it against another shows whether a change to the core alters what a
+// game draws, and from which frame:
+//
+//   cargo run -p nes --example frame_hashes -- game.nes 600 > before.txt
+
+use std::env;
+use std::fs;
+use std::process;
+
+use nes::prelude::Emulator;
+
+const DEFAULT_FRAMES: u64 = 300;
+
+const USAGE: &str = "Usage: frame_hashes <rom.nes> [frames]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (path, frames) = match args.as_slice() {
+        [path] => (path, DEFAULT_FRAMES),
+        [path, frames] => match frames.parse() {
+            Ok(frames) => (path, frames),
+            Err(_) => exit(USAGE),
+        },
+        _ => exit(USAGE),
+    };
+
+    let rom = fs::read(path).unwrap_or_else(|e| exit(&format!("Couldn't read {}: {}", path, e)));
+    let mut emulator = Emulator::new(rom).unwrap_or_else(|e| exit(&e));
+    for _ in 0..frames {
+        emulator.step_frame();
+        println!(
+            "{:6} {:016x}",
+            emulator.frame_count(),
+            fnv1a(emulator.framebuffer())
+        );
+    }
+}
+
+// Unlike std's hasher, FNV-1a is fixed, so hashes can be compared between Rust versions.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
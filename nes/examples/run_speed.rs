@@ -0,0 +1,78 @@
+// Times how fast `NES::run_cycles_within_frame`, which frontends drive the core with, gets through
+// a number of frames, against bare `tick_multi` calls running the same number of cycles.  The
+// difference is what `run` spends on its checks between ticks, so the two should be close:
+//
+//   cargo run --release -p nes --example run_speed -- game.nes 600
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::process;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use nes::prelude::{AudioOut, Config, EventBus, Screen, NES, ROM};
+
+const DEFAULT_FRAMES: u64 = 600;
+
+// Ticks per `tick_multi` call, about a scanline's worth.
+const TICKS_PER_CALL: u32 = 256;
+
+const USAGE: &str = "Usage: run_speed <rom.nes> [frames]";
+
+struct NoAudio;
+
+impl AudioOut for NoAudio {
+    fn emit(&mut self, _sample: f32) {}
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (path, frames) = match args.as_slice() {
+        [path] => (path, DEFAULT_FRAMES),
+        [path, frames] => match frames.parse() {
+            Ok(frames) => (path, frames),
+            Err(_) => exit(USAGE),
+        },
+        _ => exit(USAGE),
+    };
+
+    let mut nes = new_nes(path);
+    let start = Instant::now();
+    let mut cycles = 0;
+    for _ in 0..frames {
+        cycles += nes.run_cycles_within_frame(u64::MAX).cycles;
+    }
+    report("run_cycles_within_frame", frames, start.elapsed());
+
+    let mut nes = new_nes(path);
+    let start = Instant::now();
+    while nes.master_cycle() < cycles {
+        nes.tick_multi(TICKS_PER_CALL);
+    }
+    report("tick_multi", frames, start.elapsed());
+}
+
+fn new_nes(path: &str) -> NES {
+    let data = fs::read(path).unwrap_or_else(|e| exit(&format!("Couldn't read {}: {}", path, e)));
+    let rom = ROM::parse(data).unwrap_or_else(|e| exit(&e));
+    let event_bus = Rc::new(RefCell::new(EventBus::new()));
+    let screen = Rc::new(RefCell::new(Screen::new()));
+    NES::try_new(event_bus, screen, NoAudio, rom, &Config::default()).unwrap_or_else(|e| exit(&e))
+}
+
+fn report(name: &str, frames: u64, elapsed: Duration) {
+    let seconds = elapsed.as_secs_f64();
+    println!(
+        "{:24} {} frames in {:.3}s, {:.0} fps",
+        name,
+        frames,
+        seconds,
+        frames as f64 / seconds
+    );
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
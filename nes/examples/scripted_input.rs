@@ -0,0 +1,62 @@
+// Plays an input script into a game and watches one byte of RAM, printing each time it changes.
+// This is how a bot or a regression test would drive the core, e.g. to watch the lives counter
+// while a script walks into an enemy:
+//
+//   cargo run -p nes --example scripted_input -- game.nes walk.txt 0x075A
+//
+// Scripts are one command per line, e.g. `frame 120: P1 START` or `frame 200: P1 RIGHT for 60`.
+
+use std::env;
+use std::fs;
+use std::process;
+
+use nes::prelude::{Emulator, InputScript};
+
+// Keep running for a second after the script's last command, to see what it led to.
+const EXTRA_FRAMES: usize = 60;
+
+const USAGE: &str = "Usage: scripted_input <rom.nes> <script.txt> <address>";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (rom_path, script_path, address) = match args.as_slice() {
+        [rom, script, address] => (rom, script, parse_address(address)),
+        _ => exit(USAGE),
+    };
+
+    let rom = fs::read(rom_path).unwrap_or_else(|e| exit(&format!("Couldn't read ROM: {}", e)));
+    let script = fs::read_to_string(script_path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| InputScript::parse(&text))
+        .unwrap_or_else(|e| exit(&format!("Couldn't load script: {}", e)));
+    let mut emulator = Emulator::new(rom).unwrap_or_else(|e| exit(&e));
+
+    let mut last = emulator.read_memory(address);
+    println!("frame      0: ${:04X} = ${:02X}", address, last);
+    for frame in 0..script.len() + EXTRA_FRAMES {
+        emulator.set_input(&script.input(frame));
+        emulator.step_frame();
+
+        let value = emulator.read_memory(address);
+        if value != last {
+            println!(
+                "frame {:6}: ${:04X} = ${:02X}",
+                emulator.frame_count(),
+                address,
+                value
+            );
+            last = value;
+        }
+    }
+}
+
+// Hex, with or without a leading $ or 0x.
+fn parse_address(text: &str) -> u16 {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).unwrap_or_else(|_| exit(&format!("Bad address: {}", text)))
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
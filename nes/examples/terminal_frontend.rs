@@ -0,0 +1,115 @@
+// A minimal frontend built straight on the core rather than the `Emulator` wrapper: it wires up
+// its own screen, sound output and event bus, then runs the game in real time, drawing into the
+// terminal with coloured half-block characters.  Needs a terminal with 24-bit colour, at least
+// 128x61 characters:
+//
+//   cargo run -p nes --release --example terminal_frontend -- game.nes
+//
+// A real frontend would also turn key presses into `Event::KeyDown` and `Event::KeyUp` on the
+// event bus, which the joypads listen to.  Reading keys from a terminal needs platform code, so
+// this one only watches.
+
+use std::cell::RefCell;
+use std::env;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::process;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nes::prelude::{AudioOut, Config, EventBus, Screen, NES, ROM};
+
+// Each character cell shows two pixels, one above the other, taken from every other column and
+// every other pair of rows.
+const COLUMNS: usize = 128;
+const ROWS: usize = 60;
+const WIDTH: usize = 256;
+
+// The emulator hands over every sample it makes.  This only keeps the loudest since the last
+// frame, to show as a level meter.
+#[derive(Default)]
+struct LevelMeter {
+    peak: f32,
+}
+
+impl AudioOut for LevelMeter {
+    fn emit(&mut self, sample: f32) {
+        self.peak = self.peak.max(sample.abs());
+    }
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: terminal_frontend <rom.nes>");
+            process::exit(2);
+        }
+    };
+
+    let event_bus = Rc::new(RefCell::new(EventBus::new()));
+    let screen = Rc::new(RefCell::new(Screen::new()));
+    let meter = Rc::new(RefCell::new(LevelMeter::default()));
+    let rom = ROM::load(&path);
+    let mut nes = NES::new(
+        event_bus,
+        screen.clone(),
+        meter.clone(),
+        rom,
+        &Config::default(),
+    );
+    let frame_time = Duration::from_secs_f64(1.0 / nes.region().frame_rate());
+
+    print!("\x1B[2J");
+    let mut out = String::new();
+    let mut next_frame = Instant::now();
+    loop {
+        nes.run_frame();
+
+        out.clear();
+        out.push_str("\x1B[H");
+        screen.borrow().do_render(|rgb| draw(&mut out, rgb));
+        let level = (meter.borrow().peak * 40.0).min(40.0) as usize;
+        meter.borrow_mut().peak = 0.0;
+        let _ = writeln!(out, "\x1B[0m{:<40}", "#".repeat(level));
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        if stdout
+            .write_all(out.as_bytes())
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            return;
+        }
+
+        next_frame += frame_time;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            // Too far behind to catch up, so don't try.
+            next_frame = now;
+        }
+    }
+}
+
+fn draw(out: &mut String, rgb: &[u8]) {
+    let pixel = |x: usize, y: usize| {
+        let ix = (y * WIDTH + x) * 3;
+        (rgb[ix], rgb[ix + 1], rgb[ix + 2])
+    };
+    for row in 0..ROWS {
+        for column in 0..COLUMNS {
+            let (x, y) = (column * 2, row * 4);
+            let (tr, tg, tb) = pixel(x, y);
+            let (br, bg, bb) = pixel(x, y + 2);
+            let _ = write!(
+                out,
+                "\x1B[38;2;{};{};{}m\x1B[48;2;{};{};{}m\u{2580}",
+                tr, tg, tb, br, bg, bb
+            );
+        }
+        out.push_str("\x1B[0m\n");
+    }
+}
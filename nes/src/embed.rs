@@ -0,0 +1,202 @@
+// A small API for embedding the emulator in other programs, e.g. bots which play games.  It needs
+// no window or sound, runs on the caller's thread and is kept stable as the core changes.
+
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::emulator::config::Config;
+use crate::emulator::controller::Button;
+use crate::emulator::ines::ROM;
+use crate::emulator::io::event::EventBus;
+use crate::emulator::io::nop::DummyAudio;
+use crate::emulator::io::Screen;
+use crate::emulator::movie::FrameInput;
+use crate::emulator::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+use crate::emulator::state::{NESState, SaveState};
+use crate::emulator::NES;
+
+pub const SCREEN_WIDTH: usize = FRAME_WIDTH;
+pub const SCREEN_HEIGHT: usize = FRAME_HEIGHT;
+
+// Players 3 and 4 need a Four Score, which is plugged in the first time they press anything.
+pub const MAX_PLAYERS: usize = 4;
+
+// Which buttons one player is holding.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ButtonState {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl ButtonState {
+    pub fn pressed(button: Button) -> ButtonState {
+        let mut state = ButtonState::default();
+        state.set(button, true);
+        state
+    }
+
+    pub fn set(&mut self, button: Button, down: bool) {
+        match button {
+            Button::A => self.a = down,
+            Button::B => self.b = down,
+            Button::Select => self.select = down,
+            Button::Start => self.start = down,
+            Button::Up => self.up = down,
+            Button::Down => self.down = down,
+            Button::Left => self.left = down,
+            Button::Right => self.right = down,
+        }
+    }
+
+    // The bitmask controllers and movies use, with A in bit 0.
+    pub fn to_bits(self) -> u8 {
+        [
+            self.a,
+            self.b,
+            self.select,
+            self.start,
+            self.up,
+            self.down,
+            self.left,
+            self.right,
+        ]
+        .iter()
+        .enumerate()
+        .filter(|(_, down)| **down)
+        .fold(0, |acc, (ix, _)| acc | (1 << ix))
+    }
+
+    pub fn from_bits(bits: u8) -> ButtonState {
+        ButtonState {
+            a: bits & 0x01 != 0,
+            b: bits & 0x02 != 0,
+            select: bits & 0x04 != 0,
+            start: bits & 0x08 != 0,
+            up: bits & 0x10 != 0,
+            down: bits & 0x20 != 0,
+            left: bits & 0x40 != 0,
+            right: bits & 0x80 != 0,
+        }
+    }
+}
+
+pub struct Emulator {
+    nes: NES,
+    screen: Rc<RefCell<Screen>>,
+    // Copy of the last finished frame, so it can be lent out without holding a borrow.
+    frame: Vec<u8>,
+}
+
+impl Emulator {
+    // Accepts an iNES ROM, optionally zipped.
+    pub fn new(rom_bytes: Vec<u8>) -> Result<Emulator, String> {
+        let rom = ROM::parse(rom_bytes)?;
+        let screen = Rc::new(RefCell::new(Screen::new()));
+        let event_bus = Rc::new(RefCell::new(EventBus::new()));
+        let nes = NES::try_new(
+            event_bus,
+            screen.clone(),
+            DummyAudio {},
+            rom,
+            &Config::default(),
+        )?;
+        for joy in nes.joypads().iter() {
+            joy.borrow_mut().set_keyboard_enabled(false);
+        }
+
+        Ok(Emulator {
+            nes,
+            screen,
+            frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 3],
+        })
+    }
+
+    // Runs until the next frame has been drawn.
+    pub fn step_frame(&mut self) {
+        self.nes.run_frame();
+        let frame = &mut self.frame;
+        self.screen
+            .borrow()
+            .do_render(|data| frame.copy_from_slice(data));
+    }
+
+    // Players count from 0.  Buttons stay held until changed.
+    pub fn set_buttons(&mut self, player: usize, buttons: ButtonState) {
+        assert!(player < MAX_PLAYERS, "No such player: {}", player);
+        if player >= 2 && !self.nes.four_score() {
+            self.nes.set_four_score(true);
+        }
+        self.nes.joypads()[player]
+            .borrow_mut()
+            .set_buttons(buttons.to_bits());
+    }
+
+    // One frame of a movie or input script: resets first if asked, then sets every player's
+    // buttons.  Players 3 and 4 are only touched once the Four Score is in use.
+    pub fn set_input(&mut self, input: &FrameInput) {
+        if input.reset {
+            self.reset();
+        }
+        let players = if self.nes.four_score() || input.joy3 != 0 || input.joy4 != 0 {
+            MAX_PLAYERS
+        } else {
+            2
+        };
+        let bits = [input.joy1, input.joy2, input.joy3, input.joy4];
+        for (player, bits) in bits.iter().enumerate().take(players) {
+            self.set_buttons(player, ButtonState::from_bits(*bits));
+        }
+    }
+
+    // The last frame drawn, as rows of RGB bytes.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.frame
+    }
+
+    // Reads the CPU's view of memory without side effects, so registers always read as 0.
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.nes.peek(address)
+    }
+
+    // Only RAM and cartridge RAM can be written.
+    pub fn write_memory(&mut self, address: u16, byte: u8) {
+        self.nes.poke(address, byte);
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.nes.ppu.borrow().stats().frame_count
+    }
+
+    pub fn reset(&mut self) {
+        self.nes.reset();
+    }
+
+    // States can only be taken between instructions, which may mean finishing an OAM DMA first.
+    pub fn save_state(&mut self) -> NESState {
+        self.nes.request_save_state();
+        loop {
+            if let Some(state) = self.nes.take_save_state() {
+                return state;
+            }
+            self.nes.tick();
+        }
+    }
+
+    pub fn load_state(&mut self, state: NESState) {
+        self.nes.hydrate(state);
+    }
+
+    // For anything not covered above.  The NES's own API may change between versions.
+    pub fn nes(&mut self) -> &mut NES {
+        &mut self.nes
+    }
+}
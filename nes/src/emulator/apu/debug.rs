@@ -1,8 +1,9 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
 
 use crate::emulator::apu::synth::{Noise, Pulse, Triangle, DMC};
-use crate::emulator::apu::APU;
+use crate::emulator::apu::{APU, DAC_HISTORY_CYCLES};
 
 pub struct APUDebug {
     apu: Rc<RefCell<APU>>,
@@ -11,7 +12,7 @@ pub struct APUDebug {
 
 impl APUDebug {
     pub const WAVEFORM_WIDTH: usize = 256;
-    pub const WAVEFORM_HEIGHT: usize = 160;
+    pub const WAVEFORM_HEIGHT: usize = 192;
     const WAVEFORM_SCALE: usize = 64;
 
     pub fn new(apu: Rc<RefCell<APU>>) -> APUDebug {
@@ -47,6 +48,7 @@ impl APUDebug {
         APUDebug::draw_triangle_wave(buffer, &apu.triangle, 0, 64);
         APUDebug::draw_noise(buffer, &apu.noise, dummy_noise, 0, 96);
         APUDebug::draw_dmc(buffer, &apu.dmc, 0, 128);
+        APUDebug::draw_dac_writes(buffer, &apu, 0, 160);
     }
 
     fn draw_pulse_wave(buffer: &mut [u8], pulse: &Pulse, x: usize, y: usize) {
@@ -162,6 +164,44 @@ impl APUDebug {
         }
     }
 
+    // Plots the levels written to $4011 over the last DAC_HISTORY_CYCLES, oldest on the left.
+    fn draw_dac_writes(buffer: &mut [u8], apu: &APU, x: usize, y: usize) {
+        let writes: Vec<&(u64, u8)> = apu.dac_writes().collect();
+        if writes.is_empty() {
+            APUDebug::draw_silence(buffer, x, y);
+            return;
+        }
+
+        let start = apu.total_cycles().saturating_sub(DAC_HISTORY_CYCLES);
+        let mut next = 0;
+        let mut level = 0;
+        let mut prev_y = 0;
+        for dx in 0..APUDebug::WAVEFORM_WIDTH {
+            let t = start + (dx as u64 * DAC_HISTORY_CYCLES) / (APUDebug::WAVEFORM_WIDTH as u64);
+            while next < writes.len() && writes[next].0 <= t {
+                level = writes[next].1;
+                next += 1;
+            }
+
+            // 7 bit level squashed into the 32px tall box.
+            let dy = (31 - level / 4) as usize;
+            if prev_y != 0 && dy != prev_y {
+                // Draw vertical connecting bar.
+                let (from, to) = if dy > prev_y {
+                    (prev_y, dy)
+                } else {
+                    (dy, prev_y)
+                };
+
+                for ix in from..=to {
+                    buffer[((y + ix) * APUDebug::WAVEFORM_WIDTH + x + dx) * 3] = 0xFF;
+                }
+            }
+            prev_y = dy;
+            buffer[((y + dy) * APUDebug::WAVEFORM_WIDTH + x + dx) * 3] = 0xFF;
+        }
+    }
+
     fn draw_silence(buffer: &mut [u8], x: usize, y: usize) {
         // Base volume level is at 15, +8 to center it in the 32px tall box.
         for dx in 0..APUDebug::WAVEFORM_WIDTH {
@@ -0,0 +1,99 @@
+// Which channels get through to the mixer, for picking out one part of a tune or tracking down
+// which channel makes a noise.  Silenced channels keep running, so length counters, sweeps and DMC
+// IRQs behave exactly as if they were heard.
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    // Every sound source on the cartridge.
+    Expansion,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 6] = [
+        Channel::Pulse1,
+        Channel::Pulse2,
+        Channel::Triangle,
+        Channel::Noise,
+        Channel::Dmc,
+        Channel::Expansion,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Pulse1 => "PULSE 1",
+            Channel::Pulse2 => "PULSE 2",
+            Channel::Triangle => "TRIANGLE",
+            Channel::Noise => "NOISE",
+            Channel::Dmc => "DMC",
+            Channel::Expansion => "EXPANSION",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MixerControl {
+    enabled: [bool; 6],
+    // Only this channel is heard, whatever the others are set to.
+    solo: Option<Channel>,
+}
+
+impl Default for MixerControl {
+    fn default() -> MixerControl {
+        MixerControl {
+            enabled: [true; 6],
+            solo: None,
+        }
+    }
+}
+
+impl MixerControl {
+    pub fn new() -> MixerControl {
+        MixerControl::default()
+    }
+
+    pub fn set_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.enabled[channel as usize] = enabled;
+    }
+
+    pub fn is_enabled(&self, channel: Channel) -> bool {
+        self.enabled[channel as usize]
+    }
+
+    // Returns whether the channel is now enabled.
+    pub fn toggle(&mut self, channel: Channel) -> bool {
+        let enabled = !self.is_enabled(channel);
+        self.set_enabled(channel, enabled);
+        enabled
+    }
+
+    pub fn set_solo(&mut self, solo: Option<Channel>) {
+        self.solo = solo;
+    }
+
+    pub fn solo(&self) -> Option<Channel> {
+        self.solo
+    }
+
+    // Solos the channel, or goes back to hearing everything enabled if it already was.  Returns
+    // whether the channel is now soloed.
+    pub fn toggle_solo(&mut self, channel: Channel) -> bool {
+        if self.solo == Some(channel) {
+            self.solo = None;
+        } else {
+            self.solo = Some(channel);
+        }
+        self.solo.is_some()
+    }
+
+    pub fn is_audible(&self, channel: Channel) -> bool {
+        match self.solo {
+            Some(solo) => solo == channel,
+            None => self.is_enabled(channel),
+        }
+    }
+}
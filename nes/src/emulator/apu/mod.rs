@@ -1,14 +1,23 @@
 pub mod debug;
+mod mixer;
 mod synth;
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
 
 use crate::emulator::clock::Ticker;
+use crate::emulator::io::nop::DummyAudio;
 use crate::emulator::memory::{Reader, Writer};
+use crate::emulator::Region;
 
 use self::synth::{Noise, Pulse, Sweep, Triangle, DMC};
 
+pub use self::mixer::{Channel, MixerControl};
+
 pub trait AudioOut {
     fn emit(&mut self, sample: f32);
 }
@@ -19,6 +28,19 @@ impl<A: AudioOut> AudioOut for Rc<RefCell<A>> {
     }
 }
 
+// Sound hardware on the cartridge, mixed in after the APU's own channels.  Clocked once per CPU
+// cycle.  Samples should be on the same 0.0 - 1.0 scale as the APU mixer output.
+pub trait ExpansionAudio {
+    fn clock(&mut self);
+    fn sample(&self) -> f32;
+}
+
+struct ExpansionSource {
+    audio: Box<dyn ExpansionAudio>,
+    // Carts mixed their extra channels in at very different levels, so each source has its own.
+    gain: f32,
+}
+
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 enum SequenceMode {
     FourStep,
@@ -30,8 +52,15 @@ const LENGTH_COUNTER_LOOKUP: [u8; 0x20] = [
     192, 24, 72, 26, 16, 28, 32, 30,
 ];
 
+// How far back the record of $4011 writes goes, in APU cycles.  A little over an NTSC frame.
+pub const DAC_HISTORY_CYCLES: u64 = 16_384;
+
+// Cap on the record of $4011 writes, in case something writes far faster than any real PCM player.
+const MAX_DAC_HISTORY: usize = 4096;
+
 pub struct APU {
     output: Box<dyn AudioOut>,
+    region: Region,
 
     sequence_mode: SequenceMode,
     cycle_counter: u64,
@@ -43,12 +72,21 @@ pub struct APU {
     triangle: Triangle,
     noise: Noise,
     dmc: DMC,
+
+    expansion: Vec<ExpansionSource>,
+    mixer: MixerControl,
+
+    // Raw PCM playback works by writing levels straight to $4011.  Recent writes are kept, along
+    // with the APU cycle they happened on, for the debug view.
+    total_cycles: u64,
+    dac_writes: VecDeque<(u64, u8)>,
 }
 
 impl APU {
     pub fn new(output: Box<dyn AudioOut>, prg_rom: Box<dyn Reader>) -> APU {
         APU {
             output,
+            region: Region::NTSC,
 
             sequence_mode: SequenceMode::FourStep,
             cycle_counter: 0,
@@ -60,21 +98,85 @@ impl APU {
             triangle: Triangle::new(),
             noise: Noise::new(),
             dmc: DMC::new(prg_rom),
+
+            expansion: vec![],
+            mixer: MixerControl::new(),
+
+            total_cycles: 0,
+            dac_writes: VecDeque::new(),
         }
     }
 
+    // Returns an index for adjusting the source's gain later.
+    pub fn add_expansion_audio(&mut self, audio: Box<dyn ExpansionAudio>, gain: f32) -> usize {
+        self.expansion.push(ExpansionSource { audio, gain });
+        self.expansion.len() - 1
+    }
+
+    pub fn set_expansion_gain(&mut self, ix: usize, gain: f32) {
+        self.expansion[ix].gain = gain;
+    }
+
+    // Hands over where samples go, leaving this APU silent.
+    pub fn take_output(&mut self) -> Box<dyn AudioOut> {
+        core::mem::replace(&mut self.output, Box::new(DummyAudio))
+    }
+
+    pub fn mixer_control(&self) -> MixerControl {
+        self.mixer
+    }
+
+    pub fn set_mixer_control(&mut self, mixer: MixerControl) {
+        self.mixer = mixer;
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
     pub fn irq_triggered(&self) -> bool {
         self.irq_flag || self.dmc.irq_flag
     }
 
-    fn clock_linear_and_envelope(&mut self) {
+    // How many bytes the DMC has fetched from PRG since this was last called.  The CPU needs
+    // stalling for each of them.
+    pub fn take_dmc_dma(&mut self) -> u32 {
+        self.dmc.take_dma_reads()
+    }
+
+    // $4011 writes from the last DAC_HISTORY_CYCLES, oldest first, as (APU cycle, level).  The
+    // first entry may be older, giving the level at the start of the window.
+    pub fn dac_writes(&self) -> impl Iterator<Item = &(u64, u8)> {
+        self.dac_writes.iter()
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    fn record_dac_write(&mut self, level: u8) {
+        self.dac_writes.push_back((self.total_cycles, level));
+
+        let window_start = self.total_cycles.saturating_sub(DAC_HISTORY_CYCLES);
+        while self.dac_writes.len() > MAX_DAC_HISTORY
+            || (self.dac_writes.len() > 1 && self.dac_writes[1].0 <= window_start)
+        {
+            self.dac_writes.pop_front();
+        }
+    }
+
+    // Quarter frame clocks go to the envelopes and the triangle's linear counter.
+    fn clock_quarter_frame(&mut self) {
         self.pulse_1.envelope.clock();
         self.pulse_2.envelope.clock();
         self.triangle.clock_linear();
         self.noise.envelope.clock();
     }
 
-    fn clock_length_counters(&mut self) {
+    // Half frame clocks go to the length counters and sweeps, and always come with a quarter
+    // frame clock.
+    fn clock_half_frame(&mut self) {
+        self.clock_quarter_frame();
         self.pulse_1.clock_length();
         self.pulse_2.clock_length();
         self.triangle.clock_length();
@@ -84,33 +186,30 @@ impl APU {
 
 impl Ticker for APU {
     fn tick(&mut self) -> u32 {
+        self.total_cycles += 1;
         self.cycle_counter += 1;
+        let [step_1, step_2, step_3, four_step_end, five_step_end] =
+            self.region.revision().frame_counter_steps;
         match self.sequence_mode {
             SequenceMode::FourStep => match self.cycle_counter {
-                3729 => self.clock_linear_and_envelope(),
-                7457 => {
-                    self.clock_linear_and_envelope();
-                    self.clock_length_counters();
-                }
-                11186 => self.clock_linear_and_envelope(),
-                14915 => {
-                    self.clock_linear_and_envelope();
-                    self.clock_length_counters();
+                c if c == step_1 || c == step_3 => self.clock_quarter_frame(),
+                c if c == step_2 => self.clock_half_frame(),
+                c if c == four_step_end => {
+                    self.clock_half_frame();
                     self.cycle_counter = 0;
-                    self.irq_flag = self.irq_enabled;
+                    // Stays raised until $4015 is read or the IRQ is inhibited.
+                    if self.irq_enabled {
+                        self.irq_flag = true;
+                    }
                 }
                 _ => (),
             },
+            // The 4th step does nothing in this mode, so there's no IRQ.
             SequenceMode::FiveStep => match self.cycle_counter {
-                3729 => self.clock_linear_and_envelope(),
-                7457 => {
-                    self.clock_linear_and_envelope();
-                    self.clock_length_counters();
-                }
-                11186 => self.clock_linear_and_envelope(),
-                18641 => {
-                    self.clock_linear_and_envelope();
-                    self.clock_length_counters();
+                c if c == step_1 || c == step_3 => self.clock_quarter_frame(),
+                c if c == step_2 => self.clock_half_frame(),
+                c if c == five_step_end => {
+                    self.clock_half_frame();
                     self.cycle_counter = 0;
                 }
                 _ => (),
@@ -125,21 +224,56 @@ impl Ticker for APU {
         self.triangle.clock();
         self.dmc.clock();
         self.dmc.clock();
+        for source in self.expansion.iter_mut() {
+            source.audio.clock();
+            source.audio.clock();
+        }
 
         // Mixer.
-        let p1 = self.pulse_1.volume() as f32;
-        let p2 = self.pulse_2.volume() as f32;
-        let t = self.triangle.volume() as f32;
-        let n = self.noise.volume() as f32;
-        let dmc = self.dmc.volume as f32;
-
-        let pulse_out = 0.00752 * (p1 + p2);
-        let tnd_out = (0.00851 * t) + (0.00494 * n) + (0.00335 * dmc);
-        self.output.emit(pulse_out + tnd_out);
+        let mixer = self.mixer;
+        let level = |channel: Channel, level: u8| {
+            if mixer.is_audible(channel) {
+                level as f32
+            } else {
+                0.0
+            }
+        };
+        let p1 = level(Channel::Pulse1, self.pulse_1.volume());
+        let p2 = level(Channel::Pulse2, self.pulse_2.volume());
+        let t = level(Channel::Triangle, self.triangle.volume());
+        let n = level(Channel::Noise, self.noise.volume());
+        let dmc = level(Channel::Dmc, self.dmc.volume);
+
+        let expansion_out: f32 = if mixer.is_audible(Channel::Expansion) {
+            self.expansion
+                .iter()
+                .map(|source| source.audio.sample() * source.gain)
+                .sum()
+        } else {
+            0.0
+        };
+        self.output.emit(mix(p1 + p2, t, n, dmc) + expansion_out);
         1
     }
 }
 
+// The usual approximation of the mixer's resistor network, from the channel levels.  It isn't
+// linear: each channel sounds quieter the more the others in its group are putting out.
+fn mix(pulse: f32, triangle: f32, noise: f32, dmc: f32) -> f32 {
+    let pulse_out = if pulse == 0.0 {
+        0.0
+    } else {
+        95.88 / (8128.0 / pulse + 100.0)
+    };
+    let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+    let tnd_out = if tnd == 0.0 {
+        0.0
+    } else {
+        159.79 / (1.0 / tnd + 100.0)
+    };
+    pulse_out + tnd_out
+}
+
 impl Writer for APU {
     fn write(&mut self, address: u16, byte: u8) {
         match address {
@@ -177,7 +311,9 @@ impl Writer for APU {
                 self.triangle.timer.set_period(new_period);
             }
             0x400B => {
-                self.triangle.length = LENGTH_COUNTER_LOOKUP[(byte >> 3) as usize];
+                if self.triangle.enabled {
+                    self.triangle.length = LENGTH_COUNTER_LOOKUP[(byte >> 3) as usize];
+                }
                 let new_period =
                     (self.triangle.timer.period() & 0x00FF) | (((byte & 0x7) as u16) << 8);
                 self.triangle.timer.set_period(new_period);
@@ -192,23 +328,30 @@ impl Writer for APU {
             }
             0x400E => {
                 self.noise.mode = byte & 0x80 != 0;
+                let lookup = self.region.revision().noise_periods;
+                // The table is in CPU cycles, but the timer counts APU cycles.
                 self.noise
                     .timer
-                    .set_period(Noise::PERIOD_LOOKUP[(byte & 0x0F) as usize]);
+                    .set_period(lookup[(byte & 0x0F) as usize] / 2 - 1);
             }
             0x400F => {
-                self.noise.length = LENGTH_COUNTER_LOOKUP[(byte >> 3) as usize];
+                if self.noise.enabled {
+                    self.noise.length = LENGTH_COUNTER_LOOKUP[(byte >> 3) as usize];
+                }
                 self.noise.envelope.restart();
             }
             0x4010 => {
                 self.dmc.irq_enabled = byte & 0x80 != 0;
+                if !self.dmc.irq_enabled {
+                    self.dmc.irq_flag = false;
+                }
                 self.dmc.loop_flag = byte & 0x40 != 0;
-                self.dmc
-                    .timer
-                    .set_period(DMC::PERIOD_LOOKUP[(byte & 0x0F) as usize]);
+                let lookup = self.region.revision().dmc_periods;
+                self.dmc.timer.set_period(lookup[(byte & 0x0F) as usize]);
             }
             0x4011 => {
                 self.dmc.volume = byte & 0x7F;
+                self.record_dac_write(byte & 0x7F);
             }
             0x4012 => {
                 self.dmc.sample_addr = 0xC000 | ((byte as u16) << 6);
@@ -223,6 +366,7 @@ impl Writer for APU {
                     if self.dmc.bytes_remaining == 0 {
                         self.dmc.restart_sample();
                     }
+                    self.dmc.fill_sample_buffer();
                 } else {
                     self.dmc.enabled = false;
                     self.dmc.bytes_remaining = 0;
@@ -260,14 +404,19 @@ impl Writer for APU {
                 };
 
                 // IRQ inhibit.
-                if byte & 0x70 != 0 {
+                if byte & 0x40 != 0 {
                     self.irq_enabled = false;
                     self.irq_flag = false;
                 } else {
                     self.irq_enabled = true;
                 }
 
+                // Restarting the sequence in 5-step mode clocks everything straight away, which
+                // games use to get in a clock at a time of their choosing.
                 self.cycle_counter = 0;
+                if self.sequence_mode == SequenceMode::FiveStep {
+                    self.clock_half_frame();
+                }
             }
             _ => (),
         }
@@ -295,7 +444,7 @@ impl Reader for APU {
                     status |= 1 << 4
                 };
                 if self.dmc.irq_flag {
-                    status |= 1 << 5
+                    status |= 1 << 7
                 };
                 if self.irq_flag {
                     status |= 1 << 6
@@ -337,3 +486,339 @@ fn write_third_pulse_register(pulse: &mut Pulse, byte: u8) {
     pulse.timer.set_period(new_period);
     pulse.restart();
 }
+
+#[cfg(test)]
+mod test {
+    use core::cell::Cell;
+
+    use super::*;
+    use crate::emulator::hardware::NTSC;
+    use crate::emulator::memory::Memory;
+
+    struct Capture {
+        samples: Vec<f32>,
+    }
+
+    impl AudioOut for Capture {
+        fn emit(&mut self, sample: f32) {
+            self.samples.push(sample);
+        }
+    }
+
+    // Outputs a constant level and counts how often it's clocked.
+    struct Constant {
+        level: f32,
+        clocks: Rc<Cell<u32>>,
+    }
+
+    impl ExpansionAudio for Constant {
+        fn clock(&mut self) {
+            self.clocks.set(self.clocks.get() + 1);
+        }
+
+        fn sample(&self) -> f32 {
+            self.level
+        }
+    }
+
+    #[test]
+    fn test_expansion_audio_mixing() {
+        let output = Rc::new(RefCell::new(Capture { samples: vec![] }));
+        let mut apu = APU::new(
+            Box::new(output.clone()),
+            Box::new(Memory::new_rom(vec![0; 0x4000])),
+        );
+
+        apu.tick();
+        let silence = output.borrow().samples[0];
+
+        let clocks = Rc::new(Cell::new(0));
+        let loud = apu.add_expansion_audio(
+            Box::new(Constant {
+                level: 0.5,
+                clocks: clocks.clone(),
+            }),
+            1.0,
+        );
+        apu.add_expansion_audio(
+            Box::new(Constant {
+                level: 0.25,
+                clocks: clocks.clone(),
+            }),
+            0.5,
+        );
+
+        apu.tick();
+        assert_eq!(output.borrow().samples[1], silence + 0.5 + 0.125);
+
+        // Clocked at CPU rate, which is twice per APU cycle.
+        assert_eq!(clocks.get(), 4);
+
+        apu.set_expansion_gain(loud, 0.0);
+        apu.tick();
+        assert_eq!(output.borrow().samples[2], silence + 0.125);
+    }
+
+    #[test]
+    fn test_dac_writes() {
+        let output = Rc::new(RefCell::new(Capture { samples: vec![] }));
+        let mut apu = APU::new(
+            Box::new(output.clone()),
+            Box::new(Memory::new_rom(vec![0; 0x4000])),
+        );
+
+        apu.tick();
+        let silence = output.borrow().samples[0];
+
+        // A raw PCM player writes a new level every few cycles.  Each one takes effect straight
+        // away, and the top bit is ignored.
+        for level in [0x20, 0x7F, 0xFF, 0x00].iter() {
+            apu.write(0x4011, *level);
+            apu.tick();
+            apu.tick();
+        }
+        let level = |ix: usize| output.borrow().samples[ix] - silence;
+        // The triangle idles on its first step, at level 15.
+        let dac = |level: f32| mix(0.0, 15.0, 0.0, level) - silence;
+        assert!((level(1) - dac(32.0)).abs() < 1e-6);
+        assert!((level(3) - dac(127.0)).abs() < 1e-6);
+        assert_eq!(level(5), level(3));
+        assert_eq!(level(7), 0.0);
+
+        let writes: Vec<(u64, u8)> = apu.dac_writes().cloned().collect();
+        assert_eq!(writes, vec![(1, 0x20), (3, 0x7F), (5, 0x7F), (7, 0x00)]);
+
+        // Only the last write before the window is kept.
+        for _ in 0..DAC_HISTORY_CYCLES {
+            apu.tick();
+        }
+        apu.write(0x4011, 0x10);
+        let writes: Vec<(u64, u8)> = apu.dac_writes().cloned().collect();
+        assert_eq!(writes, vec![(7, 0x00), (DAC_HISTORY_CYCLES + 9, 0x10)]);
+    }
+
+    #[test]
+    fn test_mixer() {
+        assert_eq!(mix(0.0, 0.0, 0.0, 0.0), 0.0);
+        assert!((mix(30.0, 0.0, 0.0, 0.0) - 0.2585).abs() < 1e-4);
+        assert!((mix(0.0, 15.0, 15.0, 127.0) - 0.7415).abs() < 1e-4);
+
+        // Channels sharing a group take the edge off each other.
+        let triangle = mix(0.0, 15.0, 0.0, 0.0);
+        let with_dmc = mix(0.0, 15.0, 0.0, 127.0) - mix(0.0, 0.0, 0.0, 127.0);
+        assert!(with_dmc < triangle * 0.75);
+    }
+
+    #[test]
+    fn test_mixer_control() {
+        let output = Rc::new(RefCell::new(Capture { samples: vec![] }));
+        let mut apu = APU::new(
+            Box::new(output.clone()),
+            Box::new(Memory::new_rom(vec![0; 0x4000])),
+        );
+        let sample = |ix: usize| output.borrow().samples[ix];
+
+        apu.write(0x4011, 0x40);
+        apu.tick();
+
+        // Soloing a channel leaves only it in the mix.  The triangle idles on its first step, at
+        // level 15.
+        let mut mixer = apu.mixer_control();
+        assert!(mixer.toggle_solo(Channel::Dmc));
+        apu.set_mixer_control(mixer);
+        apu.tick();
+        let dmc = apu.dmc.volume as f32;
+        assert!((sample(1) - mix(0.0, 0.0, 0.0, dmc)).abs() < 1e-6);
+        assert!(mixer.toggle_solo(Channel::Triangle));
+        apu.set_mixer_control(mixer);
+        apu.tick();
+        assert!((sample(2) - mix(0.0, 15.0, 0.0, 0.0)).abs() < 1e-6);
+
+        // Muting doesn't count while another channel is soloed.
+        for channel in Channel::ALL.iter() {
+            assert!(!mixer.toggle(*channel));
+        }
+        apu.tick();
+        assert_eq!(sample(3), sample(2));
+        apu.set_mixer_control(mixer);
+        apu.tick();
+        assert_eq!(sample(4), sample(2));
+
+        assert!(!mixer.toggle_solo(Channel::Triangle));
+        apu.set_mixer_control(mixer);
+        apu.tick();
+        assert_eq!(sample(5), 0.0);
+
+        apu.set_mixer_control(MixerControl::new());
+        apu.tick();
+        assert_eq!(sample(6), sample(0));
+    }
+
+    #[test]
+    fn test_triangle_channel() {
+        let mut apu = silent_apu();
+        apu.write(0x4015, 0x04);
+        apu.write(0x4008, 0x7F);
+        apu.write(0x400A, 0x10);
+        apu.write(0x400B, 0x08);
+        apu.clock_quarter_frame();
+        assert_eq!(apu.triangle.linear, 0x7F);
+
+        // A step every 17 CPU cycles walks through all 16 levels.
+        let mut levels = vec![];
+        for _ in 0..17 * 32 / 2 {
+            apu.tick();
+            if !levels.contains(&apu.triangle.volume()) {
+                levels.push(apu.triangle.volume());
+            }
+        }
+        assert_eq!(levels.len(), 16);
+
+        // Silencing holds the level where it was.
+        apu.write(0x4015, 0x00);
+        let held = apu.triangle.volume();
+        for _ in 0..100 {
+            apu.tick();
+            assert_eq!(apu.triangle.volume(), held);
+        }
+
+        // The length counter can't be loaded while the channel is off.
+        apu.write(0x400B, 0x08);
+        assert_eq!(apu.triangle.length, 0);
+    }
+
+    #[test]
+    fn test_noise_channel() {
+        let mut apu = silent_apu();
+        apu.write(0x4015, 0x08);
+        // Constant volume 15, length counter halted, fastest period.
+        apu.write(0x400C, 0x3F);
+        apu.write(0x400E, 0x00);
+        apu.write(0x400F, 0x08);
+
+        // The 15 bit LFSR repeats every 32767 steps, and at this rate it steps every 4 CPU
+        // cycles, which is 2 APU cycles.
+        let mut levels = vec![];
+        for _ in 0..32767 * 4 {
+            apu.tick();
+            levels.push(apu.noise.volume());
+        }
+        assert!(levels.contains(&15) && levels.contains(&0));
+        assert_eq!(levels[..32767 * 2], levels[32767 * 2..]);
+        assert_ne!(levels[..32767], levels[32767..32767 * 2]);
+
+        // Mode 1 taps bit 6 instead, for a much shorter, buzzier loop.  Most states are on a 93 step
+        // loop, including the one we're in.
+        apu.write(0x400E, 0x80);
+        let mut levels = vec![];
+        for _ in 0..93 * 4 {
+            apu.tick();
+            levels.push(apu.noise.volume());
+        }
+        let period = (1..=93 * 2)
+            .find(|period| levels[..levels.len() - period] == levels[*period..])
+            .unwrap();
+        assert_eq!(period, 93 * 2);
+    }
+
+    fn silent_apu() -> APU {
+        APU::new(
+            Box::new(Capture { samples: vec![] }),
+            Box::new(Memory::new_rom(vec![0; 0x10000])),
+        )
+    }
+
+    #[test]
+    fn test_frame_irq() {
+        let mut apu = silent_apu();
+        for _ in 0..NTSC.frame_counter_steps[3] - 1 {
+            apu.tick();
+        }
+        assert!(!apu.irq_triggered());
+        apu.tick();
+        assert!(apu.irq_triggered());
+
+        // It stays up until the status is read.
+        apu.tick();
+        assert!(apu.irq_triggered());
+        assert_eq!(apu.read(0x4015) & 0x40, 0x40);
+        assert!(!apu.irq_triggered());
+        assert_eq!(apu.read(0x4015) & 0x40, 0x00);
+    }
+
+    #[test]
+    fn test_frame_irq_inhibit() {
+        let mut apu = silent_apu();
+        apu.write(0x4017, 0x40);
+        for _ in 0..NTSC.frame_counter_steps[4] * 2 {
+            apu.tick();
+        }
+        assert!(!apu.irq_triggered());
+
+        // Only bit 6 inhibits.
+        apu.write(0x4017, 0x30);
+        for _ in 0..NTSC.frame_counter_steps[3] {
+            apu.tick();
+        }
+        assert!(apu.irq_triggered());
+
+        // Inhibiting also clears a pending IRQ.
+        apu.write(0x4017, 0x40);
+        assert!(!apu.irq_triggered());
+    }
+
+    #[test]
+    fn test_five_step_mode() {
+        let mut apu = silent_apu();
+        apu.write(0x4015, 0x01);
+        apu.write(0x4003, 0x00);
+        assert_eq!(apu.pulse_1.length, 10);
+
+        // Switching to 5-step mode clocks the length counters immediately.
+        apu.write(0x4017, 0x80);
+        assert_eq!(apu.pulse_1.length, 9);
+
+        // Then twice per sequence, at steps 2 and 5, and never raises an IRQ.
+        for _ in 0..NTSC.frame_counter_steps[4] {
+            apu.tick();
+        }
+        assert_eq!(apu.pulse_1.length, 7);
+        assert!(!apu.irq_triggered());
+
+        // 4-step mode waits for the sequence.
+        apu.write(0x4017, 0x00);
+        assert_eq!(apu.pulse_1.length, 7);
+        for _ in 0..NTSC.frame_counter_steps[1] {
+            apu.tick();
+        }
+        assert_eq!(apu.pulse_1.length, 6);
+    }
+
+    #[test]
+    fn test_dmc_fetches_and_irq() {
+        let mut apu = silent_apu();
+        // Fastest rate, IRQ on, a 17 byte sample.
+        apu.write(0x4010, 0x8F);
+        apu.write(0x4013, 0x01);
+
+        // Enabling fetches the first byte straight away.
+        apu.write(0x4015, 0x10);
+        assert_eq!(apu.take_dmc_dma(), 1);
+        assert_eq!(apu.take_dmc_dma(), 0);
+        assert_eq!(apu.read(0x4015) & 0x10, 0x10);
+
+        // Then one byte every 8 output clocks of 54 CPU cycles, i.e. 216 APU cycles.
+        let mut reads = 1;
+        for _ in 0..17 * 216 {
+            apu.tick();
+            reads += apu.take_dmc_dma();
+        }
+        assert_eq!(reads, 17);
+        assert!(apu.irq_triggered());
+        assert_eq!(apu.read(0x4015) & 0x90, 0x80);
+
+        // Turning the IRQ off clears it.
+        apu.write(0x4010, 0x0F);
+        assert!(!apu.irq_triggered());
+    }
+}
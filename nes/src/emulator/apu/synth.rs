@@ -1,3 +1,5 @@
+use alloc::boxed::Box;
+
 use crate::emulator::memory::Reader;
 
 pub struct Divider {
@@ -268,8 +270,11 @@ impl Triangle {
         }
     }
 
+    // The sequencer only steps while both counters are running, so a silenced triangle holds its
+    // last level rather than dropping to zero.  Periods below 2 are ultrasonic, and would only
+    // alias into a whine, so they hold too.
     pub fn clock(&mut self) {
-        if self.timer.clock() {
+        if self.timer.clock() && self.linear > 0 && self.length > 0 && self.timer.period() >= 2 {
             self.sequence_ix = (self.sequence_ix + 1) % 32;
         }
     }
@@ -293,14 +298,6 @@ impl Triangle {
     }
 
     pub fn volume(&self) -> u8 {
-        if !self.enabled {
-            return 0;
-        }
-
-        if self.linear == 0 || self.length == 0 {
-            return 0;
-        }
-
         Triangle::SEQUENCE[self.sequence_ix as usize]
     }
 }
@@ -316,10 +313,6 @@ pub struct Noise {
 }
 
 impl Noise {
-    pub const PERIOD_LOOKUP: [u16; 16] = [
-        4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
-    ];
-
     pub fn new() -> Noise {
         Noise {
             enabled: false,
@@ -336,13 +329,13 @@ impl Noise {
         if self.timer.clock() {
             let bit1 = self.shift_register & 0x1;
             let bit2 = if self.mode {
-                (self.shift_register >> 5) & 0x1
+                (self.shift_register >> 6) & 0x1
             } else {
                 (self.shift_register >> 1) & 0x1
             };
             let feedback = bit1 ^ bit2;
             self.shift_register >>= 1;
-            self.shift_register |= feedback << 13;
+            self.shift_register |= feedback << 14;
         }
     }
 
@@ -386,16 +379,14 @@ pub struct DMC {
     current_addr: u16,
     pub bytes_remaining: u16,
     pub irq_flag: bool,
+    // Sample fetches since the last time the CPU was stalled for them.
+    dma_reads: u32,
 
     shift_register: u8,
     bits_remaining: u8,
 }
 
 impl DMC {
-    pub const PERIOD_LOOKUP: [u16; 16] = [
-        428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
-    ];
-
     pub fn new(prg_rom: Box<dyn Reader>) -> DMC {
         DMC {
             enabled: false,
@@ -404,14 +395,16 @@ impl DMC {
             silence_flag: false,
             timer: Divider::new(0),
             volume: 0,
-            sample_addr: 0,
-            sample_len: 0,
+            // As if $4012 and $4013 were zero.
+            sample_addr: 0xC000,
+            sample_len: 1,
 
             prg_rom,
             sample_buffer: None,
             current_addr: 0,
             bytes_remaining: 0,
             irq_flag: false,
+            dma_reads: 0,
 
             shift_register: 0,
             bits_remaining: 0,
@@ -420,8 +413,9 @@ impl DMC {
 
     pub fn clock(&mut self) {
         if self.timer.clock() {
-            self.clock_memory_reader();
             self.clock_output_unit();
+            // Refills as soon as the output unit empties the buffer.
+            self.fill_sample_buffer();
         }
     }
 
@@ -430,9 +424,15 @@ impl DMC {
         self.current_addr = self.sample_addr;
     }
 
-    fn clock_memory_reader(&mut self) {
+    // Takes the count of sample fetches made since last asked.  Each one halts the CPU while the
+    // DMC borrows the bus.
+    pub fn take_dma_reads(&mut self) -> u32 {
+        core::mem::replace(&mut self.dma_reads, 0)
+    }
+
+    pub fn fill_sample_buffer(&mut self) {
         if self.sample_buffer.is_none() && self.bytes_remaining != 0 {
-            // TODO: Stall the CPU by 4 (or 2) clocks.
+            self.dma_reads += 1;
             let byte = self.prg_rom.read(self.current_addr);
             self.sample_buffer = Some(byte);
             self.current_addr = self.current_addr.wrapping_add(1);
@@ -0,0 +1,232 @@
+// Just enough of the zip format to pull a ROM out of an archive.  Entries are found through the
+// central directory at the end of the file, since local headers can leave their sizes blank.
+use std::io::Read;
+
+use flate2::read::DeflateDecoder;
+
+use crate::emulator::util;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4B50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4B50;
+const END_OF_DIRECTORY_SIGNATURE: u32 = 0x0605_4B50;
+
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const END_OF_DIRECTORY_SIZE: usize = 22;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+pub fn is_zip(data: &[u8]) -> bool {
+    data.len() >= 4 && read_u32(data, 0) == Some(LOCAL_HEADER_SIGNATURE)
+}
+
+// Contents of the first `.nes` file in the archive.
+pub fn extract_rom(data: &[u8]) -> Result<Vec<u8>, String> {
+    let entry = entries(data)?
+        .into_iter()
+        .find(|entry| entry.name.to_lowercase().ends_with(".nes"))
+        .ok_or_else(|| String::from("No .nes file in the archive"))?;
+    entry.extract(data)
+}
+
+struct Entry {
+    name: String,
+    method: u16,
+    crc32: u32,
+    compressed_size: usize,
+    uncompressed_size: usize,
+    local_header_offset: usize,
+}
+
+impl Entry {
+    fn extract(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let header = self.local_header_offset;
+        if read_u32(data, header) != Some(LOCAL_HEADER_SIGNATURE) {
+            return Err(format!("Bad local header for {}", self.name));
+        }
+        let name_len = read_u16(data, header + 26).ok_or_else(truncated)? as usize;
+        let extra_len = read_u16(data, header + 28).ok_or_else(truncated)? as usize;
+        let start = header + LOCAL_HEADER_SIZE + name_len + extra_len;
+        let compressed = data
+            .get(start..start + self.compressed_size)
+            .ok_or_else(truncated)?;
+
+        let contents = match self.method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATED => {
+                let mut contents = Vec::with_capacity(self.uncompressed_size);
+                DeflateDecoder::new(compressed)
+                    .read_to_end(&mut contents)
+                    .map_err(|e| format!("Couldn't decompress {}: {}", self.name, e))?;
+                contents
+            }
+            method => {
+                return Err(format!(
+                    "{} uses unsupported compression method {}",
+                    self.name, method
+                ))
+            }
+        };
+
+        if contents.len() != self.uncompressed_size || util::crc32(&contents) != self.crc32 {
+            return Err(format!("{} is corrupt", self.name));
+        }
+        Ok(contents)
+    }
+}
+
+fn entries(data: &[u8]) -> Result<Vec<Entry>, String> {
+    let end = find_end_of_directory(data).ok_or_else(|| String::from("Not a zip archive"))?;
+    let count = read_u16(data, end + 10).ok_or_else(truncated)? as usize;
+    let mut offset = read_u32(data, end + 16).ok_or_else(truncated)? as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if read_u32(data, offset) != Some(CENTRAL_HEADER_SIGNATURE) {
+            return Err(String::from("Bad central directory"));
+        }
+        let field16 = |at: usize| read_u16(data, offset + at).ok_or_else(truncated);
+        let field32 = |at: usize| read_u32(data, offset + at).ok_or_else(truncated);
+
+        let name_len = field16(28)? as usize;
+        let extra_len = field16(30)? as usize;
+        let comment_len = field16(32)? as usize;
+        let name_start = offset + CENTRAL_HEADER_SIZE;
+        let name = data
+            .get(name_start..name_start + name_len)
+            .ok_or_else(truncated)?;
+
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).to_string(),
+            method: field16(10)?,
+            crc32: field32(16)?,
+            compressed_size: field32(20)? as usize,
+            uncompressed_size: field32(24)? as usize,
+            local_header_offset: field32(42)? as usize,
+        });
+        offset = name_start + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+// The end of directory record sits at the very end, unless the archive has a comment.
+fn find_end_of_directory(data: &[u8]) -> Option<usize> {
+    let last = data.len().checked_sub(END_OF_DIRECTORY_SIZE)?;
+    (0..=last)
+        .rev()
+        .find(|&ix| read_u32(data, ix) == Some(END_OF_DIRECTORY_SIGNATURE))
+}
+
+fn truncated() -> String {
+    String::from("Archive is truncated")
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    data.get(at..at + 2)
+        .map(|b| u16::from(b[0]) | (u16::from(b[1]) << 8))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4).map(|b| {
+        u32::from(b[0]) | (u32::from(b[1]) << 8) | (u32::from(b[2]) << 16) | (u32::from(b[3]) << 24)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    // Builds an archive in memory, compressing entries if asked.
+    fn build_zip(files: &[(&str, &[u8])], deflate: bool) -> Vec<u8> {
+        let mut zip = vec![];
+        let mut directory = vec![];
+        for (name, contents) in files.iter() {
+            let (method, stored) = if deflate {
+                let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+                encoder.write_all(contents).unwrap();
+                (METHOD_DEFLATED, encoder.finish().unwrap())
+            } else {
+                (METHOD_STORED, contents.to_vec())
+            };
+            let crc = util::crc32(contents);
+
+            let offset = zip.len() as u32;
+            zip.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            zip.extend_from_slice(&[20, 0, 0, 0]);
+            zip.extend_from_slice(&method.to_le_bytes());
+            zip.extend_from_slice(&[0; 4]);
+            zip.extend_from_slice(&crc.to_le_bytes());
+            zip.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            zip.extend_from_slice(&[0; 2]);
+            zip.extend_from_slice(name.as_bytes());
+            zip.extend_from_slice(&stored);
+
+            directory.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            directory.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+            directory.extend_from_slice(&method.to_le_bytes());
+            directory.extend_from_slice(&[0; 4]);
+            directory.extend_from_slice(&crc.to_le_bytes());
+            directory.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            directory.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let directory_offset = zip.len() as u32;
+        zip.extend_from_slice(&directory);
+        zip.extend_from_slice(&END_OF_DIRECTORY_SIGNATURE.to_le_bytes());
+        zip.extend_from_slice(&[0; 4]);
+        zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&directory_offset.to_le_bytes());
+        zip.extend_from_slice(&[0; 2]);
+        zip
+    }
+
+    #[test]
+    fn test_extract_first_rom() {
+        let rom: Vec<u8> = b"NES\x1A".iter().cloned().cycle().take(4096).collect();
+        for deflate in [false, true].iter() {
+            let zip = build_zip(
+                &[
+                    ("readme.txt", b"not a rom"),
+                    ("Game (U).NES", &rom),
+                    ("other.nes", b"NES\x1A"),
+                ],
+                *deflate,
+            );
+            assert!(is_zip(&zip));
+            assert_eq!(extract_rom(&zip), Ok(rom.clone()));
+        }
+    }
+
+    #[test]
+    fn test_extract_errors() {
+        assert!(!is_zip(b"NES\x1A"));
+        assert_eq!(
+            extract_rom(b"NES\x1A"),
+            Err(String::from("Not a zip archive"))
+        );
+
+        let zip = build_zip(&[("readme.txt", b"not a rom")], false);
+        assert_eq!(
+            extract_rom(&zip),
+            Err(String::from("No .nes file in the archive"))
+        );
+
+        let mut zip = build_zip(&[("game.nes", b"NES\x1A")], false);
+        zip[LOCAL_HEADER_SIZE + "game.nes".len()] ^= 0xFF;
+        assert_eq!(extract_rom(&zip), Err(String::from("game.nes is corrupt")));
+    }
+}
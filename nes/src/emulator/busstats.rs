@@ -0,0 +1,171 @@
+// Counts CPU bus traffic to each device on the memory map, frame by frame.  Shows where a game
+// spends its bus cycles, such as hammering PPUDATA, and whether mirrors and optional devices get
+// the accesses they should.  Counting happens where the memory map routes each access, so it sees
+// exactly what the devices see, including DMA.
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Device {
+    Ram,
+    PpuRegisters,
+    // APU, OAMDMA and controller ports.
+    IoRegisters,
+    ExpansionRam,
+    PrgRam,
+    PrgRom,
+    // Nothing answers, so the read sees open bus.
+    Unmapped,
+}
+
+impl Device {
+    pub const ALL: [Device; 7] = [
+        Device::Ram,
+        Device::PpuRegisters,
+        Device::IoRegisters,
+        Device::ExpansionRam,
+        Device::PrgRam,
+        Device::PrgRom,
+        Device::Unmapped,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Device::Ram => "RAM",
+            Device::PpuRegisters => "PPU",
+            Device::IoRegisters => "APU/IO",
+            Device::ExpansionRam => "EXP RAM",
+            Device::PrgRam => "PRG RAM",
+            Device::PrgRom => "PRG ROM",
+            Device::Unmapped => "OPEN BUS",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DeviceStats {
+    pub reads: u32,
+    pub writes: u32,
+    // CPU cycle of the most recent access, in this frame or any before it.
+    pub last_access: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct BusStats {
+    enabled: bool,
+    // CPU cycle of the access being made.
+    cycle: u64,
+    current: [DeviceStats; Device::ALL.len()],
+    last_frame: [DeviceStats; Device::ALL.len()],
+    frames: u64,
+}
+
+impl BusStats {
+    pub fn new() -> BusStats {
+        BusStats::default()
+    }
+
+    // Counting costs a little on every access, so it's off until asked for.  Turning it on starts
+    // from nothing.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        *self = BusStats {
+            enabled,
+            ..BusStats::default()
+        };
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    #[inline]
+    pub fn set_cycle(&mut self, cycle: u64) {
+        self.cycle = cycle;
+    }
+
+    #[inline]
+    pub fn record_read(&mut self, device: Device) {
+        if self.enabled {
+            let stats = &mut self.current[device as usize];
+            stats.reads += 1;
+            stats.last_access = Some(self.cycle);
+        }
+    }
+
+    #[inline]
+    pub fn record_write(&mut self, device: Device) {
+        if self.enabled {
+            let stats = &mut self.current[device as usize];
+            stats.writes += 1;
+            stats.last_access = Some(self.cycle);
+        }
+    }
+
+    // Makes this frame's counts the ones reported, and starts counting the next.
+    pub fn end_frame(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        self.last_frame = self.current;
+        for stats in self.current.iter_mut() {
+            stats.reads = 0;
+            stats.writes = 0;
+        }
+        self.frames += 1;
+    }
+
+    // Accesses to `device` during the last finished frame.
+    pub fn frame(&self, device: Device) -> DeviceStats {
+        self.last_frame[device as usize]
+    }
+
+    // Frames finished since counting started.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BusStats, Device, DeviceStats};
+
+    #[test]
+    fn test_counts_per_frame() {
+        let mut stats = BusStats::new();
+        stats.record_read(Device::Ram);
+        stats.end_frame();
+        assert_eq!(stats.frames(), 0);
+
+        stats.set_enabled(true);
+        stats.set_cycle(10);
+        stats.record_read(Device::Ram);
+        stats.set_cycle(12);
+        stats.record_write(Device::Ram);
+        stats.record_write(Device::PpuRegisters);
+        stats.end_frame();
+        assert_eq!(
+            stats.frame(Device::Ram),
+            DeviceStats {
+                reads: 1,
+                writes: 1,
+                last_access: Some(12),
+            }
+        );
+        assert_eq!(stats.frame(Device::PpuRegisters).writes, 1);
+        assert_eq!(stats.frame(Device::PrgRom), DeviceStats::default());
+
+        // The last access is remembered through quiet frames.
+        stats.set_cycle(40);
+        stats.record_read(Device::PpuRegisters);
+        stats.end_frame();
+        assert_eq!(
+            stats.frame(Device::Ram),
+            DeviceStats {
+                reads: 0,
+                writes: 0,
+                last_access: Some(12),
+            }
+        );
+        assert_eq!(stats.frame(Device::PpuRegisters).reads, 1);
+        assert_eq!(stats.frame(Device::PpuRegisters).writes, 0);
+        assert_eq!(stats.frames(), 2);
+    }
+}
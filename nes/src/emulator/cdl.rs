@@ -0,0 +1,140 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::emulator::memory::{Mapper, MapperRef};
+
+// A code/data log: marks each byte of PRG-ROM as code once the CPU runs it, and as data once an
+// instruction reads it.  Bytes are logged by where they are in the cartridge rather than where
+// the CPU saw them, so banks switched in and out are followed.  Flags use the same bits as FCEUX's
+// .cdl files, so the PRG-ROM part can be compared against logs made there.
+
+pub const CODE: u8 = 0x01;
+pub const DATA: u8 = 0x02;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CdlCounts {
+    pub code: usize,
+    pub data: usize,
+    // Bytes logged as both are counted in each.
+    pub unused: usize,
+}
+
+pub struct CodeDataLog {
+    mapper: MapperRef,
+    flags: Vec<u8>,
+}
+
+impl CodeDataLog {
+    pub fn new(mapper: MapperRef, prg_rom_len: usize) -> CodeDataLog {
+        CodeDataLog {
+            mapper,
+            flags: vec![0; prg_rom_len],
+        }
+    }
+
+    #[inline]
+    pub fn record_code(&mut self, address: u16) {
+        self.mark(address, CODE);
+    }
+
+    #[inline]
+    pub fn record_data(&mut self, address: u16) {
+        self.mark(address, DATA);
+    }
+
+    fn mark(&mut self, address: u16, flag: u8) {
+        if address < 0x8000 {
+            return;
+        }
+        let offset = self.mapper.prg_rom_offset(address);
+        if let Some(flags) = offset.and_then(|offset| self.flags.get_mut(offset)) {
+            *flags |= flag;
+        }
+    }
+
+    // Size of PRG-ROM.
+    pub fn len(&self) -> usize {
+        self.flags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    // CODE and DATA bits for one byte of PRG-ROM.
+    pub fn flags(&self, offset: usize) -> u8 {
+        self.flags.get(offset).cloned().unwrap_or(0)
+    }
+
+    pub fn counts(&self) -> CdlCounts {
+        let mut counts = CdlCounts::default();
+        for flags in self.flags.iter() {
+            if flags & CODE != 0 {
+                counts.code += 1;
+            }
+            if flags & DATA != 0 {
+                counts.data += 1;
+            }
+            if flags & (CODE | DATA) == 0 {
+                counts.unused += 1;
+            }
+        }
+        counts
+    }
+
+    // One flag byte per byte of PRG-ROM.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.flags
+    }
+
+    pub fn clear(&mut self) {
+        self.flags.iter_mut().for_each(|flags| *flags = 0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::emulator::mappers::UXROM;
+    use crate::emulator::memory::Memory;
+    use crate::emulator::ppu::MirrorMode;
+
+    #[test]
+    fn test_logs_by_rom_offset() {
+        let uxrom = UXROM::new(
+            Memory::new_rom(vec![0; 0x10000]),
+            Memory::new_ram(0x2000),
+            MirrorMode::Vertical,
+        );
+        let mapper: MapperRef = Rc::new(RefCell::new(uxrom));
+        let mut cdl = CodeDataLog::new(mapper.clone(), 0x10000);
+
+        cdl.record_code(0x8000);
+        cdl.record_data(0xC001);
+        // The same CPU address reaches a different byte after a bank switch.
+        mapper.borrow_mut().write_prg(0x8000, 2);
+        cdl.record_code(0x8000);
+        cdl.record_data(0x8000);
+        // Outside PRG-ROM.
+        cdl.record_code(0x0200);
+
+        assert_eq!(cdl.flags(0x0000), CODE);
+        assert_eq!(cdl.flags(0x8000), CODE | DATA);
+        assert_eq!(cdl.flags(0xC001), DATA);
+        assert_eq!(cdl.flags(0x0200), 0);
+        assert_eq!(
+            cdl.counts(),
+            CdlCounts {
+                code: 2,
+                data: 2,
+                unused: 0x10000 - 3,
+            }
+        );
+
+        cdl.clear();
+        assert_eq!(cdl.counts().unused, 0x10000);
+    }
+}
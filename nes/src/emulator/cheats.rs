@@ -0,0 +1,255 @@
+// Game Genie and raw cheat codes.  Cheats don't change memory, they change what the CPU sees when
+// it reads it, the same way the real Game Genie sat between the cartridge and the console.
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cheat {
+    // The code as it was entered.
+    pub code: String,
+    pub description: String,
+    pub address: u16,
+    pub value: u8,
+    // Only substitute `value` when the real byte is this.  Lets a ROM patch avoid hitting other
+    // banks mapped at the same address.
+    pub compare: Option<u8>,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    // Accepts 6 or 8 letter Game Genie codes, or raw `AAAA:VV` and `AAAA?CC:VV` codes in hex.
+    pub fn parse(code: &str) -> Result<Cheat, String> {
+        let code = code.trim().to_uppercase();
+        let (address, value, compare) = if code.contains(':') {
+            decode_raw(&code)?
+        } else {
+            decode_game_genie(&code)?
+        };
+        Ok(Cheat {
+            code,
+            description: String::new(),
+            address,
+            value,
+            compare,
+            enabled: true,
+        })
+    }
+}
+
+fn decode_game_genie(code: &str) -> Result<(u16, u8, Option<u8>), String> {
+    let n: Vec<u16> = code
+        .chars()
+        .map(|c| {
+            GAME_GENIE_LETTERS
+                .find(c)
+                .map(|ix| ix as u16)
+                .ok_or_else(|| format!("'{}' isn't a Game Genie letter", c))
+        })
+        .collect::<Result<_, _>>()?;
+    if n.len() != 6 && n.len() != 8 {
+        return Err(format!(
+            "Game Genie codes are 6 or 8 letters, got {}",
+            n.len()
+        ));
+    }
+
+    // The bits of each letter are scattered all over the address and data.
+    let address = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+    let data = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7);
+
+    if n.len() == 6 {
+        Ok((address, (data | (n[5] & 8)) as u8, None))
+    } else {
+        let compare = ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8);
+        Ok((address, (data | (n[7] & 8)) as u8, Some(compare as u8)))
+    }
+}
+
+fn decode_raw(code: &str) -> Result<(u16, u8, Option<u8>), String> {
+    let hex_u16 = |s: &str| {
+        u16::from_str_radix(s, 16).map_err(|_| format!("Bad address '{}' in cheat {}", s, code))
+    };
+    let hex_u8 = |s: &str| {
+        u8::from_str_radix(s, 16).map_err(|_| format!("Bad byte '{}' in cheat {}", s, code))
+    };
+
+    let mut parts = code.splitn(2, ':');
+    let target = parts.next().unwrap_or("");
+    let value = hex_u8(parts.next().unwrap_or(""))?;
+    match target.find('?') {
+        None => Ok((hex_u16(target)?, value, None)),
+        Some(ix) => Ok((
+            hex_u16(&target[..ix])?,
+            value,
+            Some(hex_u8(&target[ix + 1..])?),
+        )),
+    }
+}
+
+// The cheats loaded for a game.  Shared with the CPU's memory map, which asks it about every read.
+#[derive(Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+    // Enabled cheats by address, so the common case of a read with no cheat is one lookup.
+    active: BTreeMap<u16, Vec<(u8, Option<u8>)>>,
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats::default()
+    }
+
+    // One cheat per line: the code, then optionally a description.  Lines starting with `#` are
+    // comments, and a code starting with `-` is loaded disabled.
+    pub fn from_text(text: &str) -> Result<Cheats, String> {
+        let mut cheats = Cheats::new();
+        for (line_ix, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let code = parts.next().unwrap_or("");
+            let (code, enabled) = match code.strip_prefix('-') {
+                Some(code) => (code, false),
+                None => (code, true),
+            };
+            let mut cheat =
+                Cheat::parse(code).map_err(|e| format!("Line {}: {}", line_ix + 1, e))?;
+            cheat.description = String::from(parts.next().unwrap_or("").trim());
+            cheat.enabled = enabled;
+            cheats.add(cheat);
+        }
+        Ok(cheats)
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+        self.rebuild();
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn set_enabled(&mut self, ix: usize, enabled: bool) {
+        if let Some(cheat) = self.cheats.get_mut(ix) {
+            cheat.enabled = enabled;
+            self.rebuild();
+        }
+    }
+
+    // Turns everything off if any cheat is on, otherwise turns everything on.  Returns whether
+    // cheats are now on.
+    pub fn toggle_all(&mut self) -> bool {
+        let enable = !self.cheats.iter().any(|cheat| cheat.enabled);
+        for cheat in self.cheats.iter_mut() {
+            cheat.enabled = enable;
+        }
+        self.rebuild();
+        enable && !self.cheats.is_empty()
+    }
+
+    // What the CPU should see when `byte` is read from `address`.
+    #[inline]
+    pub fn apply(&self, address: u16, byte: u8) -> u8 {
+        if self.active.is_empty() {
+            return byte;
+        }
+        match self.active.get(&address) {
+            None => byte,
+            Some(patches) => patches
+                .iter()
+                .find(|(_, compare)| compare.unwrap_or(byte) == byte)
+                .map_or(byte, |(value, _)| *value),
+        }
+    }
+
+    fn rebuild(&mut self) {
+        self.active.clear();
+        for cheat in self.cheats.iter().filter(|cheat| cheat.enabled) {
+            self.active
+                .entry(cheat.address)
+                .or_default()
+                .push((cheat.value, cheat.compare));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_game_genie() {
+        let cheat = Cheat::parse("SXIOPO").unwrap();
+        assert_eq!(
+            (cheat.address, cheat.value, cheat.compare),
+            (0x91D9, 0xAD, None)
+        );
+
+        let cheat = Cheat::parse("zexpygla").unwrap();
+        assert_eq!(
+            (cheat.address, cheat.value, cheat.compare),
+            (0x94A7, 0x02, Some(0x03))
+        );
+
+        assert!(Cheat::parse("SXIOP").is_err());
+        assert!(Cheat::parse("SXIOPB").is_err());
+    }
+
+    #[test]
+    fn test_decode_raw() {
+        let cheat = Cheat::parse("075A:09").unwrap();
+        assert_eq!(
+            (cheat.address, cheat.value, cheat.compare),
+            (0x075A, 0x09, None)
+        );
+
+        let cheat = Cheat::parse("C123?AB:CD").unwrap();
+        assert_eq!(
+            (cheat.address, cheat.value, cheat.compare),
+            (0xC123, 0xCD, Some(0xAB))
+        );
+
+        assert!(Cheat::parse("075A:").is_err());
+        assert!(Cheat::parse("XYZ:01").is_err());
+    }
+
+    #[test]
+    fn test_apply_and_toggle() {
+        let mut cheats = Cheats::from_text(
+            "# Lives\n\
+             075A:09 Nine lives\n\
+             -94A7?03:02 Starts off\n",
+        )
+        .unwrap();
+        assert_eq!(cheats.cheats().len(), 2);
+        assert_eq!(cheats.cheats()[0].description, "Nine lives");
+
+        assert_eq!(cheats.apply(0x075A, 0x02), 0x09);
+        assert_eq!(cheats.apply(0x94A7, 0x03), 0x03);
+
+        cheats.set_enabled(1, true);
+        assert_eq!(cheats.apply(0x94A7, 0x03), 0x02);
+        // The compare byte doesn't match, so the read goes through untouched.
+        assert_eq!(cheats.apply(0x94A7, 0x04), 0x04);
+
+        assert!(!cheats.toggle_all());
+        assert_eq!(cheats.apply(0x075A, 0x02), 0x02);
+        assert!(cheats.toggle_all());
+        assert_eq!(cheats.apply(0x075A, 0x02), 0x09);
+    }
+}
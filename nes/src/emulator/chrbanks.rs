@@ -0,0 +1,107 @@
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::emulator::memory::ChrBanks;
+
+// Follows CHR bank switches frame by frame, so debug views can show what the pattern tables held
+// while each frame was drawn.  Games animate tiles by swapping banks every few frames, and some
+// swap mid-frame too, e.g. for a status bar.
+
+// How many frames to look back over for animation.
+pub const HISTORY_FRAMES: usize = 60;
+
+pub struct ChrBankTracker {
+    this_frame: Vec<ChrBanks>,
+    last_frame: Vec<ChrBanks>,
+    // Every set of banks mapped during each recent frame, oldest first.
+    history: VecDeque<Vec<ChrBanks>>,
+}
+
+impl ChrBankTracker {
+    pub fn new(current: ChrBanks) -> ChrBankTracker {
+        ChrBankTracker {
+            this_frame: vec![current],
+            last_frame: vec![],
+            history: VecDeque::new(),
+        }
+    }
+
+    // Call whenever the mapper may have switched banks.
+    pub fn record(&mut self, banks: ChrBanks) {
+        if !self.this_frame.contains(&banks) {
+            self.this_frame.push(banks);
+        }
+    }
+
+    pub fn end_frame(&mut self, current: ChrBanks) {
+        self.last_frame = core::mem::replace(&mut self.this_frame, vec![current]);
+        self.history.push_back(self.last_frame.clone());
+        while self.history.len() > HISTORY_FRAMES {
+            self.history.pop_front();
+        }
+    }
+
+    // Each different set of banks mapped during the last frame, in the order they appeared.
+    pub fn last_frame(&self) -> &[ChrBanks] {
+        &self.last_frame
+    }
+
+    // Every bank one 1KB slot has started a frame with recently, in the order first seen.  More
+    // than one means the slot is animated.
+    pub fn slot_history(&self, slot: usize) -> Vec<usize> {
+        let mut banks = vec![];
+        for frame in self.history.iter() {
+            if !banks.contains(&frame[0][slot]) {
+                banks.push(frame[0][slot]);
+            }
+        }
+        banks
+    }
+
+    // Every set of banks seen recently, whether at the start of a frame or part way through.
+    pub fn recent_sets(&self) -> Vec<ChrBanks> {
+        let mut sets: Vec<ChrBanks> = vec![];
+        for banks in self.history.iter().flatten() {
+            if !sets.contains(banks) {
+                sets.push(*banks);
+            }
+        }
+        sets
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChrBankTracker;
+
+    const FIRST: [usize; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+    const SECOND: [usize; 8] = [0, 1, 2, 3, 12, 13, 6, 7];
+
+    #[test]
+    fn test_switches_within_frame() {
+        let mut tracker = ChrBankTracker::new(FIRST);
+        tracker.record(SECOND);
+        tracker.record(SECOND);
+        tracker.record(FIRST);
+        tracker.end_frame(FIRST);
+        assert_eq!(tracker.last_frame(), &[FIRST, SECOND]);
+
+        tracker.end_frame(FIRST);
+        assert_eq!(tracker.last_frame(), &[FIRST]);
+        assert_eq!(tracker.recent_sets(), vec![FIRST, SECOND]);
+    }
+
+    #[test]
+    fn test_animated_slots() {
+        let mut tracker = ChrBankTracker::new(FIRST);
+        for ix in 0..8 {
+            let next = if ix % 2 == 0 { SECOND } else { FIRST };
+            tracker.record(next);
+            tracker.end_frame(next);
+        }
+        assert_eq!(tracker.slot_history(0), vec![0]);
+        assert_eq!(tracker.slot_history(4), vec![4, 12]);
+        assert_eq!(tracker.slot_history(5), vec![5, 13]);
+    }
+}
@@ -1,8 +1,9 @@
-use std::cell::RefCell;
-use std::cmp::Ordering;
-use std::collections::BinaryHeap;
-use std::rc::Rc;
-use std::vec::Vec;
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::cmp::Ordering;
 
 pub trait Ticker {
     // Returns how many master clock cycles while ticking.
@@ -34,6 +35,8 @@ impl<T: Ticker> Ticker for Rc<RefCell<T>> {
     }
 }
 
+// Runs tickers in order of master cycle.  Each one says how long its tick took, and isn't ticked
+// again until everything due before then has had its turn.
 pub struct Clock {
     // Timing.
     elapsed_cycles: u64,
@@ -66,6 +69,23 @@ impl Clock {
         }
     }
 
+    // The first cycle any ticker is still due on.  Every ticker has run right up to here.
+    pub fn next_cycle(&self) -> u64 {
+        self.turn_order
+            .peek()
+            .map_or(self.elapsed_cycles, |node| node.next_tick_cycle)
+    }
+
+    // Ticks everything that is due strictly before `cycle`.
+    pub fn run_until(&mut self, cycle: u64) {
+        loop {
+            match self.turn_order.peek() {
+                Some(node) if node.next_tick_cycle < cycle => self.tick(),
+                _ => break,
+            };
+        }
+    }
+
     pub fn manage(&mut self, ticker: ScaledTicker) {
         self.tickers.push(ticker);
         let node = TickNode {
@@ -76,6 +96,41 @@ impl Clock {
     }
 }
 
+// Drives a clock from inside another ticker, one bus cycle at a time.  The CPU owns one of these
+// holding the PPU and APU, and catches them up before each memory access so that the access sees
+// them exactly as they are on that cycle.
+pub struct BusClock {
+    clock: Clock,
+    cycle_factor: u32,
+
+    // Master cycle the current instruction started on.
+    start: u64,
+}
+
+impl BusClock {
+    pub fn new(clock: Clock, cycle_factor: u32) -> BusClock {
+        BusClock {
+            clock,
+            cycle_factor,
+            start: 0,
+        }
+    }
+
+    // Runs everything due before the given bus cycle of the current instruction.
+    #[inline]
+    pub fn run_to(&mut self, bus_cycle: u32) {
+        let target = self.start + (bus_cycle as u64) * (self.cycle_factor as u64);
+        self.clock.run_until(target);
+    }
+
+    // Completes an instruction which took `cycles` bus cycles.
+    #[inline]
+    pub fn finish(&mut self, cycles: u32) {
+        self.run_to(cycles);
+        self.start += (cycles as u64) * (self.cycle_factor as u64);
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct TickNode {
     ticker_ix: usize,
@@ -97,10 +152,10 @@ impl PartialOrd for TickNode {
 
 #[cfg(test)]
 mod test {
-    use std::cell::RefCell;
-    use std::rc::Rc;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
 
-    use crate::emulator::clock::{Clock, ScaledTicker, Ticker};
+    use crate::emulator::clock::{BusClock, Clock, ScaledTicker, Ticker};
 
     struct DummyTicker {
         value: u16,
@@ -162,4 +217,50 @@ mod test {
         assert_eq!(ticker1.borrow().value, 4);
         assert_eq!(ticker3.borrow().value, 2);
     }
+
+    #[test]
+    fn test_next_cycle() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.next_cycle(), 0);
+
+        let ticker1 = Rc::new(RefCell::new(DummyTicker::new()));
+        let ticker3 = Rc::new(RefCell::new(DummyTicker::new()));
+        clock.manage(ScaledTicker::new(Box::new(ticker1.clone()), 1));
+        clock.manage(ScaledTicker::new(Box::new(ticker3.clone()), 3));
+
+        // Both are due straight away.
+        assert_eq!(clock.next_cycle(), 0);
+        clock.tick();
+        clock.tick();
+        assert_eq!(clock.next_cycle(), 1);
+
+        // The slow ticker is next due on 3, once the fast one has caught up to it.
+        clock.run_until(3);
+        assert_eq!(clock.next_cycle(), 3);
+        assert_eq!(ticker1.borrow().value, 3);
+        assert_eq!(ticker3.borrow().value, 1);
+    }
+
+    #[test]
+    fn test_bus_clock() {
+        let mut clock = Clock::new();
+        let ticker = Rc::new(RefCell::new(DummyTicker::new()));
+        clock.manage(ScaledTicker::new(Box::new(ticker.clone()), 4));
+        let mut bus = BusClock::new(clock, 12);
+
+        // Nothing has happened yet at the start of the first bus cycle.
+        bus.run_to(0);
+        assert_eq!(ticker.borrow().value, 0);
+
+        // Ticks at 0, 4 and 8 are all before the second bus cycle.
+        bus.run_to(1);
+        assert_eq!(ticker.borrow().value, 3);
+
+        bus.finish(2);
+        assert_eq!(ticker.borrow().value, 6);
+
+        // The next instruction picks up where the last one finished.
+        bus.run_to(1);
+        assert_eq!(ticker.borrow().value, 9);
+    }
 }
@@ -1,5 +1,6 @@
-use std::collections::VecDeque;
-use std::mem;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::mem;
 
 pub struct RingBuffer<T> {
     capacity: usize,
@@ -21,6 +22,18 @@ impl<T> RingBuffer<T> {
         }
     }
 
+    // Shrinking drops the oldest items.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.data.len() > capacity {
+            self.data.pop_front();
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
     pub fn flush_vec(&mut self) -> Vec<T> {
         let deque = mem::replace(&mut self.data, VecDeque::new());
         Vec::from(deque)
@@ -33,4 +46,9 @@ impl<T> RingBuffer<T> {
     pub fn len(&self) -> usize {
         self.data.len()
     }
+
+    // Oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.data.iter()
+    }
 }
@@ -0,0 +1,49 @@
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::emulator::Region;
+
+pub const DEFAULT_SAMPLE_RATE: f32 = 44_100.0;
+pub const DEFAULT_MAX_SPEED: u64 = 5;
+
+// Settings for one emulated NES.  Frontends keep it in their own config file, so every field has a
+// default and any of them can be left out.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Forces the console to emulate: NTSC, with an RP2A03 CPU and RP2C02 PPU, or PAL, with an
+    // RP2A07 and RP2C07.  Either chip's name works too.  Otherwise it comes from the ROM header.
+    pub region: Option<Region>,
+    // ROM to run when the frontend isn't given one.
+    #[cfg(feature = "std")]
+    pub rom_path: Option<PathBuf>,
+    // Rate the frontend resamples audio to.
+    pub sample_rate: f32,
+    // Fastest the frontend will run, as a multiple of real time.
+    pub max_speed: u64,
+    // Runs the undocumented opcodes we know instead of jamming the CPU on them.
+    pub illegal_opcodes: bool,
+    // Work RAM at $4020-$5FFF, which a few boards add.  Frontends set it per game.
+    pub expansion_ram: bool,
+    // A .pal file to use instead of the built-in colours.
+    #[cfg(feature = "std")]
+    pub palette_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            region: None,
+            #[cfg(feature = "std")]
+            rom_path: None,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            max_speed: DEFAULT_MAX_SPEED,
+            illegal_opcodes: false,
+            expansion_ram: false,
+            #[cfg(feature = "std")]
+            palette_file: None,
+        }
+    }
+}
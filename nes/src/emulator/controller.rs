@@ -1,10 +1,14 @@
-use std::collections::HashMap;
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use serde::{Deserialize, Serialize};
 
 use crate::emulator::io::event::{Event, EventHandler, Key};
 use crate::emulator::memory::{Reader, Writer};
 use crate::emulator::state::{ControllerState, SaveState};
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub enum Button {
     Start,
     Select,
@@ -16,13 +20,48 @@ pub enum Button {
     Right,
 }
 
-pub type KeyMap = HashMap<Key, Button>;
+pub type KeyMap = BTreeMap<Key, Button>;
+
+pub type KeyState = BTreeMap<Button, bool>;
+
+pub fn default_keymap() -> KeyMap {
+    [
+        (Key::Z, Button::A),
+        (Key::X, Button::B),
+        (Key::A, Button::Start),
+        (Key::S, Button::Select),
+        (Key::Up, Button::Up),
+        (Key::Down, Button::Down),
+        (Key::Left, Button::Left),
+        (Key::Right, Button::Right),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+// Four Score signatures for the ports on $4016 and $4017, in the order they're shifted out, so
+// the first port reads 0,0,0,1,0,0,0,0.
+pub const FOUR_SCORE_SIGNATURES: [u8; 2] = [0x08, 0x04];
 
-pub type KeyState = HashMap<Button, bool>;
+// The Four Score adapter.  Each port reports its own controller, then the one behind it, then a
+// signature byte so games can tell the adapter is there.
+struct FourScore {
+    extra: Rc<RefCell<Controller>>,
+    signature: u8,
+}
 
 pub struct Controller {
     keymap: KeyMap,
     keystate: KeyState,
+    // Turbo keys press their button on and off every `turbo_rate` frames while held.
+    turbo_keymap: KeyMap,
+    turbo_state: KeyState,
+    turbo_rate: u32,
+    turbo_frames: u32,
+    turbo_pressed: bool,
+    keyboard_enabled: bool,
+    four_score: Option<FourScore>,
     strobe_ix: u8,
     register: u8,
 }
@@ -42,25 +81,135 @@ impl Controller {
     pub fn new(keymap: KeyMap) -> Controller {
         Controller {
             keymap,
-            keystate: HashMap::new(),
+            keystate: BTreeMap::new(),
+            turbo_keymap: BTreeMap::new(),
+            turbo_state: BTreeMap::new(),
+            turbo_rate: 0,
+            turbo_frames: 0,
+            turbo_pressed: true,
+            keyboard_enabled: true,
+            four_score: None,
             strobe_ix: 0,
             register: 0,
         }
     }
+
+    pub fn keymap(&self) -> &KeyMap {
+        &self.keymap
+    }
+
+    pub fn set_keymap(&mut self, keymap: KeyMap) {
+        self.keymap = keymap;
+
+        // Forget held buttons, otherwise a key released after the swap could leave its old button
+        // stuck down.
+        self.keystate.clear();
+    }
+
+    // A rate of 0 disables turbo.
+    pub fn set_turbo(&mut self, keymap: KeyMap, rate_frames: u32) {
+        self.turbo_keymap = keymap;
+        self.turbo_rate = rate_frames;
+        self.turbo_state.clear();
+    }
+
+    pub fn turbo_rate(&self) -> u32 {
+        self.turbo_rate
+    }
+
+    // Whether held turbo buttons currently read as pressed.
+    pub fn turbo_pressed(&self) -> bool {
+        self.turbo_rate != 0 && self.turbo_pressed
+    }
+
+    // Turbo buttons held down, as a bitmask in strobe order.
+    pub fn turbo_buttons(&self) -> u8 {
+        Controller::mask(&self.turbo_state)
+    }
+
+    // Advances the turbo timer.  Called once at the end of every frame.
+    pub fn end_frame(&mut self) {
+        if self.turbo_rate == 0 {
+            return;
+        }
+        self.turbo_frames += 1;
+        if self.turbo_frames >= self.turbo_rate {
+            self.turbo_frames = 0;
+            self.turbo_pressed = !self.turbo_pressed;
+        }
+    }
+
+    // Held buttons as a bitmask in strobe order, so bit 0 is A.
+    pub fn buttons(&self) -> u8 {
+        let turbo = if self.turbo_pressed() {
+            self.turbo_buttons()
+        } else {
+            0
+        };
+        Controller::mask(&self.keystate) | turbo
+    }
+
+    fn mask(state: &KeyState) -> u8 {
+        Controller::STROBE_ORDER
+            .iter()
+            .enumerate()
+            .filter(|(_, button)| *state.get(button).unwrap_or(&false))
+            .fold(0, |acc, (ix, _)| acc | (1 << ix))
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        for (ix, button) in Controller::STROBE_ORDER.iter().enumerate() {
+            self.keystate.insert(*button, buttons & (1 << ix) != 0);
+        }
+    }
+
+    // Reads report `extra` after this controller, followed by `signature`.
+    pub fn plug_in_four_score(&mut self, extra: Rc<RefCell<Controller>>, signature: u8) {
+        self.four_score = Some(FourScore { extra, signature });
+        self.strobe_ix = 0;
+    }
+
+    pub fn unplug_four_score(&mut self) {
+        self.four_score = None;
+        self.strobe_ix = 0;
+    }
+
+    // When disabled, key events are ignored and buttons only change via `set_buttons`.
+    pub fn set_keyboard_enabled(&mut self, enabled: bool) {
+        self.keyboard_enabled = enabled;
+        if !enabled {
+            self.turbo_state.clear();
+        }
+    }
 }
 
 impl EventHandler for Controller {
     fn handle_event(&mut self, event: Event) {
+        if !self.keyboard_enabled {
+            return;
+        }
+
         match event {
             Event::KeyDown(key) => {
                 if let Some(button) = self.keymap.get(&key) {
                     self.keystate.insert(*button, true);
                 }
+                if let Some(button) = self.turbo_keymap.get(&key) {
+                    // Start each burst with a press, so a quick tap still registers.
+                    if self.turbo_buttons() == 0 {
+                        self.turbo_frames = 0;
+                        self.turbo_pressed = true;
+                    }
+                    self.turbo_state.insert(*button, true);
+                }
             }
             Event::KeyUp(key) => {
                 if let Some(button) = self.keymap.get(&key) {
                     self.keystate.insert(*button, false);
                 }
+                if let Some(button) = self.turbo_keymap.get(&key) {
+                    self.turbo_state.insert(*button, false);
+                }
             }
         }
     }
@@ -72,12 +221,25 @@ impl Reader for Controller {
         if self.register & 1 != 0 {
             self.strobe_ix = 0;
         }
-        let button = Controller::STROBE_ORDER[self.strobe_ix as usize];
-        let is_pressed = *self.keystate.get(&button).unwrap_or(&false);
-        let byte = if is_pressed { 1 } else { 0 };
-        self.strobe_ix += 1;
-        self.strobe_ix %= 8;
-        byte
+        match self.four_score {
+            None => {
+                let byte = (self.buttons() >> self.strobe_ix) & 1;
+                self.strobe_ix += 1;
+                self.strobe_ix %= 8;
+                byte
+            }
+            Some(ref four_score) => {
+                let byte = match self.strobe_ix {
+                    0..=7 => (self.buttons() >> self.strobe_ix) & 1,
+                    8..=15 => (four_score.extra.borrow().buttons() >> (self.strobe_ix - 8)) & 1,
+                    16..=23 => (four_score.signature >> (self.strobe_ix - 16)) & 1,
+                    // The adapter reads as 1 once it's said everything.
+                    _ => 1,
+                };
+                self.strobe_ix = (self.strobe_ix + 1).min(24);
+                byte
+            }
+        }
     }
 }
 
@@ -97,7 +259,101 @@ impl<'de> SaveState<'de, ControllerState> for Controller {
     }
 
     fn hydrate(&mut self, state: ControllerState) {
-        self.strobe_ix = state.strobe_ix;
+        // The state may have been saved with the Four Score in, and reads past 8 only make sense
+        // with it.
+        self.strobe_ix = match self.four_score {
+            Some(_) => state.strobe_ix.min(24),
+            None => state.strobe_ix % 8,
+        };
         self.register = state.register;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_turbo_toggles_every_n_frames() {
+        let mut controller = Controller::new(default_keymap());
+        let turbo: KeyMap = [(Key::C, Button::A)].iter().cloned().collect();
+        controller.set_turbo(turbo, 2);
+
+        controller.handle_event(Event::KeyDown(Key::C));
+        let mut presses = vec![];
+        for _ in 0..8 {
+            presses.push(controller.buttons());
+            controller.end_frame();
+        }
+        assert_eq!(presses, vec![1, 1, 0, 0, 1, 1, 0, 0]);
+
+        // Holding the normal A key as well keeps it down throughout.
+        controller.handle_event(Event::KeyDown(Key::Z));
+        controller.end_frame();
+        controller.end_frame();
+        assert!(!controller.turbo_pressed());
+        assert_eq!(controller.buttons(), 1);
+
+        controller.handle_event(Event::KeyUp(Key::Z));
+        controller.handle_event(Event::KeyUp(Key::C));
+        assert_eq!(controller.buttons(), 0);
+    }
+
+    #[test]
+    fn test_four_score_report() {
+        let extra = Rc::new(RefCell::new(Controller::new(BTreeMap::new())));
+        extra.borrow_mut().set_buttons(0x81); // A and Right.
+        let mut controller = Controller::new(BTreeMap::new());
+        controller.set_buttons(0x02); // B.
+        controller.plug_in_four_score(extra, FOUR_SCORE_SIGNATURES[0]);
+
+        controller.write(0x4016, 1);
+        controller.write(0x4016, 0);
+        let bits: Vec<u8> = (0..26).map(|_| controller.read(0x4016)).collect();
+        assert_eq!(
+            bits,
+            vec![
+                0, 1, 0, 0, 0, 0, 0, 0, // This controller.
+                1, 0, 0, 0, 0, 0, 0, 1, // The one behind it.
+                0, 0, 0, 1, 0, 0, 0, 0, // Signature.
+                1, 1,
+            ]
+        );
+
+        // Without the adapter the report repeats every 8 reads, as before.
+        controller.unplug_four_score();
+        let bits: Vec<u8> = (0..10).map(|_| controller.read(0x4016)).collect();
+        assert_eq!(bits, vec![0, 1, 0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_hydrate_four_score_state_without_adapter() {
+        let extra = Rc::new(RefCell::new(Controller::new(BTreeMap::new())));
+        let mut controller = Controller::new(BTreeMap::new());
+        controller.set_buttons(0x02); // B.
+        controller.plug_in_four_score(extra, FOUR_SCORE_SIGNATURES[0]);
+        controller.write(0x4016, 1);
+        controller.write(0x4016, 0);
+        for _ in 0..25 {
+            controller.read(0x4016);
+        }
+        let state = controller.freeze();
+
+        controller.unplug_four_score();
+        controller.hydrate(state);
+        let bits: Vec<u8> = (0..9).map(|_| controller.read(0x4016)).collect();
+        assert_eq!(bits, vec![0, 1, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_turbo_ignored_without_keyboard() {
+        let mut controller = Controller::new(default_keymap());
+        let turbo: KeyMap = [(Key::V, Button::B)].iter().cloned().collect();
+        controller.set_turbo(turbo, 1);
+        controller.handle_event(Event::KeyDown(Key::V));
+        assert_eq!(controller.buttons(), 2);
+
+        controller.set_keyboard_enabled(false);
+        assert_eq!(controller.buttons(), 0);
+    }
+}
@@ -8,14 +8,15 @@ pub type AddressingMode = fn(cpu: &mut cpu::CPU) -> (u16, u32);
 
 fn load_memory_from_pc(cpu: &mut cpu::CPU) -> u8 {
     let addr = cpu.pc;
-    cpu.load_memory(addr)
+    cpu.read_bus(addr)
 }
 
 // Implied: no operand.
 // Due to a quirk in the nature of the processor, even when doing implied addressing,
 // the CPU will read the next byte of memory and then discard it.
 pub fn implied(cpu: &mut cpu::CPU) -> (u16, u32) {
-    let _ = load_memory_from_pc(cpu);
+    let pc = cpu.pc;
+    cpu.dummy_read(pc);
     (0, 0)
 }
 
@@ -43,14 +44,11 @@ pub fn zero_page(cpu: &mut cpu::CPU) -> (u16, u32) {
 }
 
 // Relative: one byte operand indicates address relative to PC.
-// Only used by branch instructions.
+// Only used by branch instructions, which do the extra reads for a taken branch themselves.
 pub fn relative(cpu: &mut cpu::CPU) -> (u16, u32) {
     let offset: u8 = load_memory_from_pc(cpu);
     cpu.pc += 1;
 
-    // Quirk in CPU means we unnecessarily read this memory.
-    let _ = load_memory_from_pc(cpu);
-
     // Signed addition.
     // TODO: Find out if wrapping is the correct behaviour.
     let is_negative = (offset & 0b1000_0000) != 0;
@@ -69,19 +67,17 @@ pub fn relative(cpu: &mut cpu::CPU) -> (u16, u32) {
     }
 }
 
-// Absolute indexed: same as absolute addressing, but adds an index register to the
-// address.
-fn absolute_indexed_load(cpu: &mut cpu::CPU, offset: u8) -> (u16, u32) {
-    let bal = load_memory_from_pc(cpu);
-    cpu.pc += 1;
-    let bah = load_memory_from_pc(cpu);
-    cpu.pc += 1;
-
+// Adds an index to a base address.  The CPU reads from the address before fixing up the high
+// byte, and only skips that read when the page didn't change and the instruction won't write
+// there.  Returns the extra cycle for the fix up if it happened.
+fn add_index(cpu: &mut cpu::CPU, bah: u8, bal: u8, offset: u8, always_fix: bool) -> (u16, u32) {
     let (adl, carry) = bal.overflowing_add(offset);
-    if carry {
+    if carry || always_fix {
         // Quirk in CPU means we unnecessarily read this memory.
-        let _ = cpu.load_memory(util::combine_bytes(bah, adl));
+        cpu.dummy_read(util::combine_bytes(bah, adl));
+    }
 
+    if carry {
         let (adh, _) = bah.overflowing_add(1);
         (util::combine_bytes(adh, adl), 1)
     } else {
@@ -89,14 +85,37 @@ fn absolute_indexed_load(cpu: &mut cpu::CPU, offset: u8) -> (u16, u32) {
     }
 }
 
+// Absolute indexed: same as absolute addressing, but adds an index register to the
+// address.
+fn absolute_indexed_load(cpu: &mut cpu::CPU, offset: u8, always_fix: bool) -> (u16, u32) {
+    let bal = load_memory_from_pc(cpu);
+    cpu.pc += 1;
+    let bah = load_memory_from_pc(cpu);
+    cpu.pc += 1;
+
+    add_index(cpu, bah, bal, offset, always_fix)
+}
+
 pub fn absolute_indexed_x(cpu: &mut cpu::CPU) -> (u16, u32) {
     let offset = cpu.x;
-    absolute_indexed_load(cpu, offset)
+    absolute_indexed_load(cpu, offset, false)
 }
 
 pub fn absolute_indexed_y(cpu: &mut cpu::CPU) -> (u16, u32) {
     let offset = cpu.y;
-    absolute_indexed_load(cpu, offset)
+    absolute_indexed_load(cpu, offset, false)
+}
+
+// Versions of the indexed modes for instructions which write to the address.  These always take
+// the extra cycle.
+pub fn absolute_indexed_x_write(cpu: &mut cpu::CPU) -> (u16, u32) {
+    let offset = cpu.x;
+    absolute_indexed_load(cpu, offset, true)
+}
+
+pub fn absolute_indexed_y_write(cpu: &mut cpu::CPU) -> (u16, u32) {
+    let offset = cpu.y;
+    absolute_indexed_load(cpu, offset, true)
 }
 
 // Zero page indexed: same as zero page, but adds an index register to the address.
@@ -107,7 +126,7 @@ fn zero_page_indexed_load(cpu: &mut cpu::CPU, offset: u8) -> (u16, u32) {
     cpu.pc += 1;
 
     // Quirk in CPU means we unnecessarily read this memory.
-    let _ = cpu.load_memory(low_byte as u16);
+    cpu.dummy_read(low_byte as u16);
 
     let adjusted = (low_byte as u16) + (offset as u16);
     (adjusted & 0x00FF, 0)
@@ -138,7 +157,7 @@ pub fn zero_page_indexed_y(cpu: &mut cpu::CPU) -> (u16, u32) {
 
 // Utility function to load a byte from page zero, with auto wrapping.
 fn load_byte_from_page_zero(cpu: &mut cpu::CPU, addr: u16) -> u8 {
-    cpu.load_memory(addr & 0x00FF)
+    cpu.read_bus(addr & 0x00FF)
 }
 
 // Loads a 16-bit address form the given address.  Takes into account wrapping within the page.
@@ -146,7 +165,10 @@ fn load_addr_within_page(cpu: &mut cpu::CPU, addr: u16) -> u16 {
     let high = addr & 0xFF00;
     let (low, _) = addr.overflowing_add(1);
     let addr_2 = high | (low & 0x00FF);
-    util::combine_bytes(cpu.load_memory(addr_2), cpu.load_memory(addr))
+    // Low byte first, as each read is a bus cycle of its own.
+    let low_byte = cpu.read_bus(addr);
+    let high_byte = cpu.read_bus(addr_2);
+    util::combine_bytes(high_byte, low_byte)
 }
 
 pub fn indexed_indirect(cpu: &mut cpu::CPU) -> (u16, u32) {
@@ -154,7 +176,7 @@ pub fn indexed_indirect(cpu: &mut cpu::CPU) -> (u16, u32) {
     cpu.pc += 1;
 
     // Quirk in CPU means we unnecessarily read this memory.
-    let _ = cpu.load_memory(bal as u16);
+    cpu.dummy_read(bal as u16);
 
     // Wrap within page 0.
     let addr = ((bal as u16) + (cpu.x as u16)) & 0x00FF;
@@ -162,22 +184,22 @@ pub fn indexed_indirect(cpu: &mut cpu::CPU) -> (u16, u32) {
     (target, 0)
 }
 
-pub fn indirect_indexed(cpu: &mut cpu::CPU) -> (u16, u32) {
+fn indirect_indexed_load(cpu: &mut cpu::CPU, always_fix: bool) -> (u16, u32) {
     let ial = load_memory_from_pc(cpu);
     cpu.pc += 1;
     let bal = load_byte_from_page_zero(cpu, ial as u16);
     let bah = load_byte_from_page_zero(cpu, (ial as u16) + 1);
 
-    let (adl, carry) = bal.overflowing_add(cpu.y);
-    if carry {
-        // Quirk in CPU means we unnecessarily read this memory.
-        let _ = cpu.load_memory(util::combine_bytes(bah, adl));
+    let offset = cpu.y;
+    add_index(cpu, bah, bal, offset, always_fix)
+}
 
-        let (adh, _) = bah.overflowing_add(1);
-        (util::combine_bytes(adh, adl), 1)
-    } else {
-        (util::combine_bytes(bah, adl), 0)
-    }
+pub fn indirect_indexed(cpu: &mut cpu::CPU) -> (u16, u32) {
+    indirect_indexed_load(cpu, false)
+}
+
+pub fn indirect_indexed_write(cpu: &mut cpu::CPU) -> (u16, u32) {
+    indirect_indexed_load(cpu, true)
 }
 
 pub fn indirect(cpu: &mut cpu::CPU) -> (u16, u32) {
@@ -0,0 +1,551 @@
+// Turns machine code back into assembly, for traces and debuggers.  Only the official opcodes are
+// known; anything else comes out as a `.DB` byte.
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::emulator::cpu::opcodes;
+use crate::emulator::memory::Reader;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Relative,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndexedIndirect,
+    IndirectIndexed,
+}
+
+impl Mode {
+    pub fn operand_bytes(self) -> u16 {
+        match self {
+            Mode::Implied | Mode::Accumulator => 0,
+            Mode::Absolute | Mode::AbsoluteX | Mode::AbsoluteY | Mode::Indirect => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    // None for opcodes we don't know.
+    pub mnemonic: Option<&'static str>,
+    pub mode: Mode,
+    // Little-endian operand bytes put together.  For branches this is the raw offset.
+    pub operand: u16,
+    // Where the instruction points, where that can be worked out without knowing the registers:
+    // branch destinations, absolute and zero page addresses, and indirect jump destinations.
+    pub target: Option<u16>,
+}
+
+impl Instruction {
+    // Decodes from bytes already fetched.  Operand bytes the instruction doesn't use are ignored.
+    pub fn from_bytes(address: u16, opcode: u8, b1: u8, b2: u8) -> Instruction {
+        let (mnemonic, mode) = match decode(opcode) {
+            Some((mnemonic, mode)) => (Some(mnemonic), mode),
+            None => (None, Mode::Implied),
+        };
+        let operand = match mode.operand_bytes() {
+            0 => 0,
+            1 => b1 as u16,
+            _ => ((b2 as u16) << 8) | b1 as u16,
+        };
+        let target = match mode {
+            Mode::Relative => Some(
+                address
+                    .wrapping_add(2)
+                    .wrapping_add(operand as u8 as i8 as u16),
+            ),
+            Mode::ZeroPage | Mode::Absolute => Some(operand),
+            _ => None,
+        };
+
+        Instruction {
+            address,
+            opcode,
+            mnemonic,
+            mode,
+            operand,
+            target,
+        }
+    }
+
+    // Length in bytes, including the opcode.
+    pub fn size(&self) -> u16 {
+        1 + self.mode.operand_bytes()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.opcode];
+        match self.mode.operand_bytes() {
+            0 => (),
+            1 => bytes.push(self.operand as u8),
+            _ => bytes.extend_from_slice(&[self.operand as u8, (self.operand >> 8) as u8]),
+        }
+        bytes
+    }
+
+    // Just the operand, as written in assembly.
+    pub fn operand_text(&self) -> String {
+        match self.mode {
+            Mode::Implied => String::new(),
+            Mode::Accumulator => String::from("A"),
+            Mode::Immediate => format!("#${:02X}", self.operand),
+            Mode::ZeroPage => format!("${:02X}", self.operand),
+            Mode::ZeroPageX => format!("${:02X},X", self.operand),
+            Mode::ZeroPageY => format!("${:02X},Y", self.operand),
+            // Branches are written with their destination rather than the offset.
+            Mode::Relative => format!("${:04X}", self.target.unwrap_or(0)),
+            Mode::Absolute => format!("${:04X}", self.operand),
+            Mode::AbsoluteX => format!("${:04X},X", self.operand),
+            Mode::AbsoluteY => format!("${:04X},Y", self.operand),
+            Mode::Indirect => format!("(${:04X})", self.operand),
+            Mode::IndexedIndirect => format!("(${:02X},X)", self.operand),
+            Mode::IndirectIndexed => format!("(${:02X}),Y", self.operand),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = match self.mnemonic {
+            None => return write!(f, ".DB ${:02X}", self.opcode),
+            Some(mnemonic) => mnemonic,
+        };
+        match self.mode {
+            Mode::Implied => write!(f, "{}", mnemonic),
+            Mode::Indirect if self.target.is_some() => write!(
+                f,
+                "{} {} = ${:04X}",
+                mnemonic,
+                self.operand_text(),
+                self.target.unwrap_or(0)
+            ),
+            _ => write!(f, "{} {}", mnemonic, self.operand_text()),
+        }
+    }
+}
+
+// Decodes the instruction at `pc`.  Reads go straight to memory, so avoid pointing this at
+// registers which change when read.
+pub fn disassemble<R: Reader + ?Sized>(memory: &mut R, pc: u16) -> Instruction {
+    let opcode = memory.read(pc);
+    let len = match decode(opcode) {
+        Some((_, mode)) => mode.operand_bytes(),
+        None => 0,
+    };
+    let b1 = if len > 0 {
+        memory.read(pc.wrapping_add(1))
+    } else {
+        0
+    };
+    let b2 = if len > 1 {
+        memory.read(pc.wrapping_add(2))
+    } else {
+        0
+    };
+    let mut instruction = Instruction::from_bytes(pc, opcode, b1, b2);
+
+    if instruction.mode == Mode::Indirect {
+        // The 6502 never carries into the high byte when fetching the pointer.
+        let pointer = instruction.operand;
+        let lo = memory.read(pointer);
+        let hi = memory.read((pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF));
+        instruction.target = Some(((hi as u16) << 8) | lo as u16);
+    }
+
+    instruction
+}
+
+// Decodes `count` instructions one after another, starting at `pc`.
+pub fn disassemble_range<R: Reader + ?Sized>(
+    memory: &mut R,
+    pc: u16,
+    count: usize,
+) -> Vec<Instruction> {
+    let mut instructions = Vec::with_capacity(count);
+    let mut pc = pc;
+    for _ in 0..count {
+        let instruction = disassemble(memory, pc);
+        pc = pc.wrapping_add(instruction.size());
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+// Decodes up to `before` instructions leading up to `pc`, then `pc` itself and `after` more.
+// There's no telling where earlier instructions start, so this guesses: of the starting points
+// which decode cleanly and land on `pc`, the furthest back wins, since runs of code re-align.
+pub fn disassemble_around<R: Reader + ?Sized>(
+    memory: &mut R,
+    pc: u16,
+    before: usize,
+    after: usize,
+) -> Vec<Instruction> {
+    let mut leading = Vec::new();
+    for back in (1..=before as u16 * 3).rev() {
+        let mut address = pc.wrapping_sub(back);
+        let mut instructions = Vec::new();
+        while address != pc {
+            let instruction = disassemble(memory, address);
+            if decode(instruction.opcode).is_none() || pc.wrapping_sub(address) < instruction.size()
+            {
+                break;
+            }
+            address = address.wrapping_add(instruction.size());
+            instructions.push(instruction);
+        }
+        if address == pc {
+            leading = instructions;
+            break;
+        }
+    }
+
+    let skip = leading.len().saturating_sub(before);
+    let mut instructions: Vec<Instruction> = leading.into_iter().skip(skip).collect();
+    instructions.extend(disassemble_range(memory, pc, after + 1));
+    instructions
+}
+
+pub fn decode(opcode: u8) -> Option<(&'static str, Mode)> {
+    use self::Mode::*;
+
+    let decoded = match opcode {
+        // ADC
+        opcodes::ADC_IMM => ("ADC", Immediate),
+        opcodes::ADC_ZPG => ("ADC", ZeroPage),
+        opcodes::ADC_ZPG_X => ("ADC", ZeroPageX),
+        opcodes::ADC_ABS => ("ADC", Absolute),
+        opcodes::ADC_ABS_X => ("ADC", AbsoluteX),
+        opcodes::ADC_ABS_Y => ("ADC", AbsoluteY),
+        opcodes::ADC_IX_IND => ("ADC", IndexedIndirect),
+        opcodes::ADC_IND_IX => ("ADC", IndirectIndexed),
+
+        // AND
+        opcodes::AND_IMM => ("AND", Immediate),
+        opcodes::AND_ZPG => ("AND", ZeroPage),
+        opcodes::AND_ZPG_X => ("AND", ZeroPageX),
+        opcodes::AND_ABS => ("AND", Absolute),
+        opcodes::AND_ABS_X => ("AND", AbsoluteX),
+        opcodes::AND_ABS_Y => ("AND", AbsoluteY),
+        opcodes::AND_IX_IND => ("AND", IndexedIndirect),
+        opcodes::AND_IND_IX => ("AND", IndirectIndexed),
+
+        // ASL
+        opcodes::ASL_A => ("ASL", Accumulator),
+        opcodes::ASL_ZPG => ("ASL", ZeroPage),
+        opcodes::ASL_ZPG_X => ("ASL", ZeroPageX),
+        opcodes::ASL_ABS => ("ASL", Absolute),
+        opcodes::ASL_ABS_X => ("ASL", AbsoluteX),
+
+        // BCC, BCS, BEQ
+        opcodes::BCC => ("BCC", Relative),
+        opcodes::BCS => ("BCS", Relative),
+        opcodes::BEQ => ("BEQ", Relative),
+        //
+        // BIT
+        opcodes::BIT_ZPG => ("BIT", ZeroPage),
+        opcodes::BIT_ABS => ("BIT", Absolute),
+
+        // BMI, BNE, BPL, BVC, BVS
+        opcodes::BMI => ("BMI", Relative),
+        opcodes::BNE => ("BNE", Relative),
+        opcodes::BPL => ("BPL", Relative),
+        opcodes::BVC => ("BVC", Relative),
+        opcodes::BVS => ("BVS", Relative),
+
+        // BRK
+        opcodes::BRK => ("BRK", Implied),
+
+        // CLC, CLD, CLI
+        opcodes::CLC => ("CLC", Implied),
+        opcodes::CLD => ("CLD", Implied),
+        opcodes::CLI => ("CLI", Implied),
+        opcodes::CLV => ("CLV", Implied),
+
+        // CMP
+        opcodes::CMP_IMM => ("CMP", Immediate),
+        opcodes::CMP_ZPG => ("CMP", ZeroPage),
+        opcodes::CMP_ZPG_X => ("CMP", ZeroPageX),
+        opcodes::CMP_ABS => ("CMP", Absolute),
+        opcodes::CMP_ABS_X => ("CMP", AbsoluteX),
+        opcodes::CMP_ABS_Y => ("CMP", AbsoluteY),
+        opcodes::CMP_IX_IND => ("CMP", IndexedIndirect),
+        opcodes::CMP_IND_IX => ("CMP", IndirectIndexed),
+
+        // CPX
+        opcodes::CPX_IMM => ("CPX", Immediate),
+        opcodes::CPX_ZPG => ("CPX", ZeroPage),
+        opcodes::CPX_ABS => ("CPX", Absolute),
+
+        // CPY
+        opcodes::CPY_IMM => ("CPY", Immediate),
+        opcodes::CPY_ZPG => ("CPY", ZeroPage),
+        opcodes::CPY_ABS => ("CPY", Absolute),
+
+        // DEC
+        opcodes::DEC_ZPG => ("DEC", ZeroPage),
+        opcodes::DEC_ZPG_X => ("DEC", ZeroPageX),
+        opcodes::DEC_ABS => ("DEC", Absolute),
+        opcodes::DEC_ABS_X => ("DEC", AbsoluteX),
+
+        // DEX, INY
+        opcodes::DEX => ("DEX", Implied),
+        opcodes::DEY => ("DEY", Implied),
+
+        // EOR
+        opcodes::EOR_IMM => ("EOR", Immediate),
+        opcodes::EOR_ZPG => ("EOR", ZeroPage),
+        opcodes::EOR_ZPG_X => ("EOR", ZeroPageX),
+        opcodes::EOR_ABS => ("EOR", Absolute),
+        opcodes::EOR_ABS_X => ("EOR", AbsoluteX),
+        opcodes::EOR_ABS_Y => ("EOR", AbsoluteY),
+        opcodes::EOR_IX_IND => ("EOR", IndexedIndirect),
+        opcodes::EOR_IND_IX => ("EOR", IndirectIndexed),
+
+        // INC
+        opcodes::INC_ZPG => ("INC", ZeroPage),
+        opcodes::INC_ZPG_X => ("INC", ZeroPageX),
+        opcodes::INC_ABS => ("INC", Absolute),
+        opcodes::INC_ABS_X => ("INC", AbsoluteX),
+
+        // INX, INY
+        opcodes::INX => ("INX", Implied),
+        opcodes::INY => ("INY", Implied),
+
+        // JMP
+        opcodes::JMP_ABS => ("JMP", Absolute),
+        opcodes::JMP_IND => ("JMP", Indirect),
+
+        // JSR
+        opcodes::JSR => ("JSR", Absolute),
+
+        // LDA
+        opcodes::LDA_IMM => ("LDA", Immediate),
+        opcodes::LDA_ZPG => ("LDA", ZeroPage),
+        opcodes::LDA_ZPG_X => ("LDA", ZeroPageX),
+        opcodes::LDA_ABS => ("LDA", Absolute),
+        opcodes::LDA_ABS_X => ("LDA", AbsoluteX),
+        opcodes::LDA_ABS_Y => ("LDA", AbsoluteY),
+        opcodes::LDA_IX_IND => ("LDA", IndexedIndirect),
+        opcodes::LDA_IND_IX => ("LDA", IndirectIndexed),
+
+        // LDX
+        opcodes::LDX_IMM => ("LDX", Immediate),
+        opcodes::LDX_ZPG => ("LDX", ZeroPage),
+        opcodes::LDX_ZPG_Y => ("LDX", ZeroPageY),
+        opcodes::LDX_ABS => ("LDX", Absolute),
+        opcodes::LDX_ABS_Y => ("LDX", AbsoluteY),
+
+        // LDY
+        opcodes::LDY_IMM => ("LDY", Immediate),
+        opcodes::LDY_ZPG => ("LDY", ZeroPage),
+        opcodes::LDY_ZPG_X => ("LDY", ZeroPageX),
+        opcodes::LDY_ABS => ("LDY", Absolute),
+        opcodes::LDY_ABS_X => ("LDY", AbsoluteX),
+
+        // LSR
+        opcodes::LSR_A => ("LSR", Accumulator),
+        opcodes::LSR_ZPG => ("LSR", ZeroPage),
+        opcodes::LSR_ZPG_X => ("LSR", ZeroPageX),
+        opcodes::LSR_ABS => ("LSR", Absolute),
+        opcodes::LSR_ABS_X => ("LSR", AbsoluteX),
+
+        // NOP
+        opcodes::NOP => ("NOP", Implied),
+
+        // ORA
+        opcodes::ORA_IMM => ("ORA", Immediate),
+        opcodes::ORA_ZPG => ("ORA", ZeroPage),
+        opcodes::ORA_ZPG_X => ("ORA", ZeroPageX),
+        opcodes::ORA_ABS => ("ORA", Absolute),
+        opcodes::ORA_ABS_X => ("ORA", AbsoluteX),
+        opcodes::ORA_ABS_Y => ("ORA", AbsoluteY),
+        opcodes::ORA_IX_IND => ("ORA", IndexedIndirect),
+        opcodes::ORA_IND_IX => ("ORA", IndirectIndexed),
+
+        // PHA, PLA, PHP, PLP
+        opcodes::PHA => ("PHA", Implied),
+        opcodes::PLA => ("PLA", Implied),
+        opcodes::PHP => ("PHP", Implied),
+        opcodes::PLP => ("PLP", Implied),
+
+        // ROL
+        opcodes::ROL_A => ("ROL", Accumulator),
+        opcodes::ROL_ZPG => ("ROL", ZeroPage),
+        opcodes::ROL_ZPG_X => ("ROL", ZeroPageX),
+        opcodes::ROL_ABS => ("ROL", Absolute),
+        opcodes::ROL_ABS_X => ("ROL", AbsoluteX),
+
+        // ROR
+        opcodes::ROR_A => ("ROR", Accumulator),
+        opcodes::ROR_ZPG => ("ROR", ZeroPage),
+        opcodes::ROR_ZPG_X => ("ROR", ZeroPageX),
+        opcodes::ROR_ABS => ("ROR", Absolute),
+        opcodes::ROR_ABS_X => ("ROR", AbsoluteX),
+
+        // RTI, RTS
+        opcodes::RTI => ("RTI", Implied),
+        opcodes::RTS => ("RTS", Implied),
+
+        // SBC
+        opcodes::SBC_IMM => ("SBC", Immediate),
+        opcodes::SBC_ZPG => ("SBC", ZeroPage),
+        opcodes::SBC_ZPG_X => ("SBC", ZeroPageX),
+        opcodes::SBC_ABS => ("SBC", Absolute),
+        opcodes::SBC_ABS_X => ("SBC", AbsoluteX),
+        opcodes::SBC_ABS_Y => ("SBC", AbsoluteY),
+        opcodes::SBC_IX_IND => ("SBC", IndexedIndirect),
+        opcodes::SBC_IND_IX => ("SBC", IndirectIndexed),
+
+        // SEC, SED, SEI
+        opcodes::SEC => ("SEC", Implied),
+        opcodes::SED => ("SED", Implied),
+        opcodes::SEI => ("SEI", Implied),
+
+        // STA
+        opcodes::STA_ZPG => ("STA", ZeroPage),
+        opcodes::STA_ZPG_X => ("STA", ZeroPageX),
+        opcodes::STA_ABS => ("STA", Absolute),
+        opcodes::STA_ABS_X => ("STA", AbsoluteX),
+        opcodes::STA_ABS_Y => ("STA", AbsoluteY),
+        opcodes::STA_IX_IND => ("STA", IndexedIndirect),
+        opcodes::STA_IND_IX => ("STA", IndirectIndexed),
+
+        // STX
+        opcodes::STX_ZPG => ("STX", ZeroPage),
+        opcodes::STX_ZPG_Y => ("STX", ZeroPageY),
+        opcodes::STX_ABS => ("STX", Absolute),
+
+        // STY
+        opcodes::STY_ZPG => ("STY", ZeroPage),
+        opcodes::STY_ZPG_X => ("STY", ZeroPageX),
+        opcodes::STY_ABS => ("STY", Absolute),
+
+        // TAX, TXA, TAY, TYA, TSX, TXS
+        opcodes::TAX => ("TAX", Implied),
+        opcodes::TXA => ("TXA", Implied),
+        opcodes::TAY => ("TAY", Implied),
+        opcodes::TYA => ("TYA", Implied),
+        opcodes::TSX => ("TSX", Implied),
+        opcodes::TXS => ("TXS", Implied),
+
+        _ => return None,
+    };
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::emulator::memory::Memory;
+
+    fn memory_with(pc: u16, program: &[u8]) -> Memory {
+        let mut memory = Memory::new_ram(0x10000);
+        for (ix, byte) in program.iter().enumerate() {
+            memory.put(pc as usize + ix, *byte);
+        }
+        memory
+    }
+
+    #[test]
+    fn test_all_official_opcodes_decode() {
+        let known = (0..=255u8).filter(|op| decode(*op).is_some()).count();
+        assert_eq!(known, 151);
+    }
+
+    #[test]
+    fn test_formatting() {
+        let cases: &[(&[u8], &str)] = &[
+            (&[0xEA], "NOP"),
+            (&[0x0A], "ASL A"),
+            (&[0xA9, 0x42], "LDA #$42"),
+            (&[0xB5, 0x10], "LDA $10,X"),
+            (&[0xB6, 0x10], "LDX $10,Y"),
+            (&[0xBD, 0x34, 0x12], "LDA $1234,X"),
+            (&[0x81, 0x20], "STA ($20,X)"),
+            (&[0x91, 0x20], "STA ($20),Y"),
+            (&[0x20, 0x00, 0xC0], "JSR $C000"),
+            (&[0x02], ".DB $02"),
+        ];
+        for (program, expected) in cases.iter() {
+            let mut memory = memory_with(0x8000, program);
+            let instruction = disassemble(&mut memory, 0x8000);
+            assert_eq!(instruction.to_string(), *expected);
+            assert_eq!(instruction.bytes(), program.to_vec());
+        }
+    }
+
+    #[test]
+    fn test_resolved_targets() {
+        // Branches backwards and forwards.
+        let mut memory = memory_with(0x8010, &[0xD0, 0xFE, 0x10, 0x05]);
+        let listing = disassemble_range(&mut memory, 0x8010, 2);
+        assert_eq!(listing[0].to_string(), "BNE $8010");
+        assert_eq!(listing[1].address, 0x8012);
+        assert_eq!(listing[1].target, Some(0x8019));
+
+        // The indirect JMP pointer at $02FF wraps around to $0200 for its high byte.
+        let mut memory = memory_with(0x8000, &[0x6C, 0xFF, 0x02]);
+        memory.put(0x02FF, 0x34);
+        memory.put(0x0200, 0x12);
+        memory.put(0x0300, 0x56);
+        let instruction = disassemble(&mut memory, 0x8000);
+        assert_eq!(instruction.target, Some(0x1234));
+        assert_eq!(instruction.to_string(), "JMP ($02FF) = $1234");
+    }
+
+    #[test]
+    fn test_disassemble_around() {
+        // LDA #$01, STA $0200, INX, then a KIL and a NOP.
+        let program = [0xA9, 0x01, 0x8D, 0x00, 0x02, 0xE8, 0x02, 0xEA];
+        let mut memory = memory_with(0x8000, &program);
+        let listing = disassemble_around(&mut memory, 0x8006, 2, 1);
+        let addresses: Vec<u16> = listing.iter().map(|i| i.address).collect();
+        assert_eq!(addresses, vec![0x8002, 0x8005, 0x8006, 0x8007]);
+        assert_eq!(listing[0].to_string(), "STA $0200");
+        assert_eq!(listing[2].to_string(), ".DB $02");
+    }
+
+    #[test]
+    fn test_trace_columns() {
+        let mut line = String::new();
+        super::super::trace::write_trace_frame(
+            &mut line,
+            &super::super::trace::TraceFrame {
+                pc: 0xC000,
+                opcode: 0xF0,
+                arg1: 0x04,
+                arg2: 0xFF,
+                a: 0x01,
+                x: 0x02,
+                y: 0x03,
+                p: 0x24,
+                sp: 0xFD,
+                scanline: 241,
+                dot: 9,
+                cycle: 27_384,
+            },
+        );
+        assert_eq!(
+            line,
+            "C000  F0 04     BEQ $C006                       A:01 X:02 Y:03 P:24 SP:FD PPU:241,  9 CYC:27384"
+        );
+        assert_eq!(super::super::trace::parse_a(&line), 0x01);
+        assert_eq!(super::super::trace::parse_sp(&line), 0xFD);
+        assert_eq!(super::super::trace::parse_ppu(&line), Some((241, 9)));
+        assert_eq!(super::super::trace::parse_cpu_cycle(&line), Some(27_384));
+    }
+}
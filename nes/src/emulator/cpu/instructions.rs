@@ -1,4 +1,7 @@
+use alloc::string::ToString;
+
 use crate::emulator::cpu;
+use crate::emulator::eventlog::{Severity, Subsystem};
 use crate::emulator::util;
 
 pub type Operation = fn(&mut cpu::CPU, cpu::addressing::AddressingMode) -> u32;
@@ -19,6 +22,19 @@ fn update_negative_flag(cpu: &mut cpu::CPU, result: u8) {
     }
 }
 
+// Read-modify-write instructions write the unmodified value back while they work out the result,
+// then write the result on the next cycle.
+fn read_modify_write<F>(cpu: &mut cpu::CPU, addr: u16, modify: F) -> u8
+where
+    F: FnOnce(&mut cpu::CPU, u8) -> u8,
+{
+    let byte = cpu.read_bus(addr);
+    cpu.write_bus(addr, byte);
+    let res = modify(cpu, byte);
+    cpu.write_bus(addr, res);
+    res
+}
+
 fn load_status_from_stack(cpu: &mut cpu::CPU) {
     let bits_from_stack = cpu.stack_pop() & 0b1100_1111;
     let bits_from_register = cpu.p.as_byte() & 0b0011_0000;
@@ -31,7 +47,7 @@ fn load_status_from_stack(cpu: &mut cpu::CPU) {
 // A -> M
 pub fn lda(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
-    let res = cpu.load_memory(addr);
+    let res = cpu.read_bus(addr);
     update_zero_flag(cpu, res);
     update_negative_flag(cpu, res);
     cpu.a = res;
@@ -44,7 +60,7 @@ pub fn lda(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 pub fn sta(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, _) = load_addr(cpu);
     let byte = cpu.a;
-    cpu.store_memory(addr, byte);
+    cpu.write_bus(addr, byte);
     // STA doesn't incur the extra "oops" cycle.
     // Or more correctly, it always does, but it's taken into account by the
     // instruction timings already, so we ignore it here.
@@ -57,7 +73,7 @@ pub fn sta(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 // A + M + C -> A, C
 pub fn adc(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
-    let mem = cpu.load_memory(addr);
+    let mem = cpu.read_bus(addr);
 
     let carry_val: u8 = if cpu.p.is_set(cpu::flags::Flag::C) {
         1
@@ -114,7 +130,7 @@ pub fn adc(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 // Borrow = Complement of carry
 pub fn sbc(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
-    let mem = cpu.load_memory(addr);
+    let mem = cpu.read_bus(addr);
 
     let carry_val: u8 = if cpu.p.is_set(cpu::flags::Flag::C) {
         1
@@ -170,7 +186,7 @@ pub fn sbc(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 // A /\ M -> A
 pub fn and(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
-    let mem = cpu.load_memory(addr);
+    let mem = cpu.read_bus(addr);
     let res = mem & cpu.a;
     update_zero_flag(cpu, res);
     update_negative_flag(cpu, res);
@@ -182,7 +198,7 @@ pub fn and(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 // A \/ M -> A
 pub fn ora(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
-    let mem = cpu.load_memory(addr);
+    let mem = cpu.read_bus(addr);
     let res = mem | cpu.a;
     update_zero_flag(cpu, res);
     update_negative_flag(cpu, res);
@@ -194,7 +210,7 @@ pub fn ora(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 // A \-/ M -> A
 pub fn eor(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
-    let mem = cpu.load_memory(addr);
+    let mem = cpu.read_bus(addr);
     let res = mem ^ cpu.a;
     update_zero_flag(cpu, res);
     update_negative_flag(cpu, res);
@@ -271,6 +287,14 @@ fn branch_if(
 ) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
     if should_branch {
+        // Quirk in CPU means we unnecessarily read the next opcode, and then from the wrong page
+        // if the branch crosses one.
+        let pc = cpu.pc;
+        cpu.dummy_read(pc);
+        if addr_cycles > 0 {
+            cpu.dummy_read((pc & 0xFF00) | (addr & 0x00FF));
+        }
+
         cpu.pc = addr;
         addr_cycles + 1
     } else {
@@ -333,7 +357,7 @@ fn compare_instruction(
     compare_with: u8,
 ) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
-    let mem = cpu.load_memory(addr);
+    let mem = cpu.read_bus(addr);
 
     let diff = compare_with.wrapping_sub(mem);
     update_zero_flag(cpu, diff);
@@ -359,7 +383,7 @@ pub fn cmp(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 // M /\ A, M7 -> N, M6 -> V
 pub fn bit(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
-    let mem = cpu.load_memory(addr);
+    let mem = cpu.read_bus(addr);
 
     // N is set to bit 7 of the memory being tested.
     update_negative_flag(cpu, mem);
@@ -387,7 +411,7 @@ pub fn bit(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 // M -> X
 pub fn ldx(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
-    let mem = cpu.load_memory(addr);
+    let mem = cpu.read_bus(addr);
 
     update_zero_flag(cpu, mem);
     update_negative_flag(cpu, mem);
@@ -400,7 +424,7 @@ pub fn ldx(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 // M -> Y
 pub fn ldy(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
-    let mem = cpu.load_memory(addr);
+    let mem = cpu.read_bus(addr);
 
     update_zero_flag(cpu, mem);
     update_negative_flag(cpu, mem);
@@ -414,7 +438,7 @@ pub fn ldy(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 pub fn stx(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
     let byte = cpu.x;
-    cpu.store_memory(addr, byte);
+    cpu.write_bus(addr, byte);
     addr_cycles
 }
 
@@ -423,7 +447,7 @@ pub fn stx(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 pub fn sty(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, addr_cycles) = load_addr(cpu);
     let byte = cpu.y;
-    cpu.store_memory(addr, byte);
+    cpu.write_bus(addr, byte);
     addr_cycles
 }
 
@@ -525,28 +549,34 @@ pub fn tya(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 
 // JSR: Jump to Subroutine
 // PC + 2v, (PC + 1) -> PCL, (PC + 2) -> PCH
-pub fn jsr(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
-    let (addr, addr_cycles) = load_addr(cpu);
-
-    // load_addr will leave the PC pointing at the next opcode.
-    // JSR is actually supposed to store the previous address.
-    cpu.pc -= 1;
+pub fn jsr(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
+    // Reads its own operand, since the high byte isn't fetched until after the return address
+    // is pushed.
+    let pc = cpu.pc;
+    let addr_low = cpu.read_bus(pc);
+    cpu.pc += 1;
+    cpu.stack_dummy_read();
 
-    // Store PC on stack.
+    // JSR stores the address of its last byte, which is where the PC now points.
     let pc_high = (cpu.pc >> 8) as u8;
     let pc_low = cpu.pc as u8;
     cpu.stack_push(pc_high);
     cpu.stack_push(pc_low);
 
     // Jump to target address.
-    cpu.pc = addr;
+    let pc = cpu.pc;
+    let addr_high = cpu.read_bus(pc);
+    cpu.pc = util::combine_bytes(addr_high, addr_low);
 
-    addr_cycles
+    0
 }
 
 // RTS: Return from Subroutine
 // PC^, INC PC
-pub fn rts(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
+pub fn rts(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
+    let _ = load_addr(cpu);
+    cpu.stack_dummy_read();
+
     // Load PC from stack.
     let pc_low = cpu.stack_pop();
     let pc_high = cpu.stack_pop();
@@ -554,6 +584,8 @@ pub fn rts(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 
     // JSR stores the address of the end of the JSR instruction.
     // So we need to increment the PC by 1 to point at the next opcode.
+    let pc = cpu.pc;
+    cpu.dummy_read(pc);
     cpu.pc += 1;
 
     0
@@ -561,7 +593,8 @@ pub fn rts(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 
 // PHA: Push Accumulator on Stack
 // Av
-pub fn pha(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
+pub fn pha(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
+    let _ = load_addr(cpu);
     let byte = cpu.a;
     cpu.stack_push(byte);
     0
@@ -569,7 +602,10 @@ pub fn pha(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 
 // PLA: Pull Accumulator from Stack
 // A^
-pub fn pla(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
+pub fn pla(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
+    let _ = load_addr(cpu);
+    cpu.stack_dummy_read();
+
     let byte = cpu.stack_pop();
     update_negative_flag(cpu, byte);
     update_zero_flag(cpu, byte);
@@ -596,7 +632,8 @@ pub fn tsx(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 
 // PHP: Push Processor Status on Stack
 // Pv
-pub fn php(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
+pub fn php(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
+    let _ = load_addr(cpu);
     let byte = cpu.p.as_byte();
     // Set the B flag to the value we push, but do not modify the status register.
     // Bit 5 is always set.
@@ -607,7 +644,10 @@ pub fn php(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 // PLP: Pull Processor Status from Stack
 // P^
 // Make sure to ignore bits 4 and 5 since these are unused.
-pub fn plp(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
+pub fn plp(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
+    let _ = load_addr(cpu);
+    cpu.stack_dummy_read();
+
     load_status_from_stack(cpu);
     0
 }
@@ -616,7 +656,10 @@ pub fn plp(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 
 // RTI: Return from Interrupt
 // ^P ^PC
-pub fn rti(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
+pub fn rti(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
+    let _ = load_addr(cpu);
+    cpu.stack_dummy_read();
+
     load_status_from_stack(cpu);
 
     let pcl = cpu.stack_pop();
@@ -627,7 +670,10 @@ pub fn rti(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 
 // BRK: Break Command
 // PC+2v (FFFE) -> PCL, (FFFF) -> PCH
-pub fn brk(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
+pub fn brk(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
+    // Reads the padding byte after the opcode.
+    let _ = load_addr(cpu);
+
     // Note: I'm not sure why it stores PC+2, but that's what the documentation says.
     // PC was incremented by 1 already for us before this function, so just add one.
     let pch = (cpu.pc >> 8) as u8;
@@ -645,8 +691,8 @@ pub fn brk(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
     cpu.p.set(cpu::flags::Flag::I);
 
     // Load interrupt vector.
-    let pcl = cpu.load_memory(0xFFFE);
-    let pch = cpu.load_memory(0xFFFF);
+    let pcl = cpu.read_bus(0xFFFE);
+    let pch = cpu.read_bus(0xFFFF);
     cpu.pc = util::combine_bytes(pch, pcl);
     0
 }
@@ -670,10 +716,11 @@ fn shift_set_flags(cpu: &mut cpu::CPU, res: u8, carry: bool) {
 // LSR: Logical Shift Right
 pub fn lsr(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, _) = load_addr(cpu);
-    let byte = cpu.load_memory(addr);
-    let (res, carry) = util::shift_right(byte);
-    shift_set_flags(cpu, res, carry);
-    cpu.store_memory(addr, res);
+    read_modify_write(cpu, addr, |cpu, byte| {
+        let (res, carry) = util::shift_right(byte);
+        shift_set_flags(cpu, res, carry);
+        res
+    });
 
     // Ignore whether the addressing added a whoops cycle since it's always triggered on write
     // instructions.
@@ -691,10 +738,11 @@ pub fn lsra(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 // ASL: Arithmetic Shift Left
 pub fn asl(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, _) = load_addr(cpu);
-    let byte = cpu.load_memory(addr);
-    let (res, carry) = util::shift_left(byte);
-    shift_set_flags(cpu, res, carry);
-    cpu.store_memory(addr, res);
+    read_modify_write(cpu, addr, |cpu, byte| {
+        let (res, carry) = util::shift_left(byte);
+        shift_set_flags(cpu, res, carry);
+        res
+    });
 
     // Ignore whether the addressing added a whoops cycle since it's always triggered on write
     // instructions.
@@ -712,10 +760,11 @@ pub fn asla(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 // ROR: Rotate Right
 pub fn ror(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, _) = load_addr(cpu);
-    let byte = cpu.load_memory(addr);
-    let (res, carry) = util::rotate_right(byte, cpu.p.is_set(cpu::flags::Flag::C));
-    shift_set_flags(cpu, res, carry);
-    cpu.store_memory(addr, res);
+    read_modify_write(cpu, addr, |cpu, byte| {
+        let (res, carry) = util::rotate_right(byte, cpu.p.is_set(cpu::flags::Flag::C));
+        shift_set_flags(cpu, res, carry);
+        res
+    });
 
     // Ignore whether the addressing added a whoops cycle since it's always triggered on write
     // instructions.
@@ -733,10 +782,11 @@ pub fn rora(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 // ROL: Rotate Left
 pub fn rol(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, _) = load_addr(cpu);
-    let byte = cpu.load_memory(addr);
-    let (res, carry) = util::rotate_left(byte, cpu.p.is_set(cpu::flags::Flag::C));
-    shift_set_flags(cpu, res, carry);
-    cpu.store_memory(addr, res);
+    read_modify_write(cpu, addr, |cpu, byte| {
+        let (res, carry) = util::rotate_left(byte, cpu.p.is_set(cpu::flags::Flag::C));
+        shift_set_flags(cpu, res, carry);
+        res
+    });
 
     // Ignore whether the addressing added a whoops cycle since it's always triggered on write
     // instructions.
@@ -755,9 +805,7 @@ pub fn rola(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
 // M + 1 -> M
 pub fn inc(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, _) = load_addr(cpu);
-    let byte = cpu.load_memory(addr);
-    let res = byte.wrapping_add(1);
-    cpu.store_memory(addr, res);
+    let res = read_modify_write(cpu, addr, |_, byte| byte.wrapping_add(1));
     update_zero_flag(cpu, res);
     update_negative_flag(cpu, res);
 
@@ -770,9 +818,7 @@ pub fn inc(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 // M - 1 -> M
 pub fn dec(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
     let (addr, _) = load_addr(cpu);
-    let byte = cpu.load_memory(addr);
-    let res = byte.wrapping_sub(1);
-    cpu.store_memory(addr, res);
+    let res = read_modify_write(cpu, addr, |_, byte| byte.wrapping_sub(1));
     update_zero_flag(cpu, res);
     update_negative_flag(cpu, res);
 
@@ -785,3 +831,23 @@ pub fn dec(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u3
 pub fn nop(_: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
     0
 }
+
+// KIL: Locks up the CPU until it's reset.  The PC is left on the opcode.
+pub fn kil(cpu: &mut cpu::CPU, _: cpu::addressing::AddressingMode) -> u32 {
+    cpu.pc = cpu.instruction_start;
+    let opcode = cpu.load_memory(cpu.pc);
+    let jam = cpu::Jam { pc: cpu.pc, opcode };
+    cpu.jam = Some(jam);
+    if let Some(ref log) = cpu.event_log {
+        log.borrow_mut()
+            .record(Severity::Error, Subsystem::Cpu, jam.to_string());
+    }
+    0
+}
+
+// IGN: Unofficial NOP which reads its operand and ignores it.
+pub fn ign(cpu: &mut cpu::CPU, load_addr: cpu::addressing::AddressingMode) -> u32 {
+    let (addr, addr_cycles) = load_addr(cpu);
+    let _ = cpu.read_bus(addr);
+    addr_cycles
+}
@@ -1,31 +1,78 @@
 mod addressing;
+pub mod disassembler;
 mod flags;
 mod instructions;
+pub mod nestest;
 mod opcodes;
 mod trace;
 
 #[cfg(test)]
 mod test;
 
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt;
+#[cfg(feature = "std")]
 use std::io::{BufWriter, Write};
+#[cfg(feature = "std")]
 use std::time::Instant;
 
+use serde::{Deserialize, Serialize};
+
+use crate::emulator::busstats::BusStats;
+use crate::emulator::cdl::CodeDataLog;
 use crate::emulator::clock;
 use crate::emulator::components::bitfield::BitField;
 use crate::emulator::components::ringbuffer::RingBuffer;
+use crate::emulator::eventlog::EventLog;
+use crate::emulator::heatmap::AccessHeatmap;
 use crate::emulator::memory::ReadWriter;
 use crate::emulator::state;
 use crate::emulator::util;
+use crate::emulator::vramcheck::VramWriteLog;
+use crate::emulator::watchpoints::{Access, Watchpoints};
 
 // Program vector locations.
 pub const START_VECTOR: u16 = 0xFFFC;
 pub const IRQ_VECTOR: u16 = 0xFFFE;
 pub const NMI_VECTOR: u16 = 0xFFFA;
 
-// Only buffer the last ~1 second of trace to prevent blowing up.
+// By default only buffer the last ~1 second of trace to prevent blowing up.
 // Even this produces a ~150mb trace file!
-const TRACE_FRAME_SIZE: usize = 10;
-const MAX_TRACE_FRAMES: usize = 2_000_000 * TRACE_FRAME_SIZE;
+pub const DEFAULT_TRACE_INSTRUCTIONS: usize = 2_000_000;
+
+// Nintendulator counts the 7 cycles of the reset sequence before the first instruction, so we do
+// the same to keep traces comparable.
+const RESET_CYCLES: u64 = 7;
+
+// Where the PPU is, as (scanline, dot), for tracing.
+pub type TracePosition = Box<dyn Fn() -> (u16, u16)>;
+
+// The opcodes which lock up a real 6502, known as KIL or JAM.
+const KIL_OPCODES: [u8; 12] = [
+    0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2,
+];
+
+// Where the CPU stopped.  Only a reset gets it going again.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Jam {
+    pub pc: u16,
+    pub opcode: u8,
+}
+
+impl Jam {
+    // False for opcodes which work on a real CPU, but which we don't emulate.
+    pub fn is_kil(&self) -> bool {
+        KIL_OPCODES.contains(&self.opcode)
+    }
+}
+
+impl fmt::Display for Jam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CPU jammed on ${:02X} at ${:04X}", self.opcode, self.pc)
+    }
+}
 
 pub enum Flag {
     N = 1 << 7, // Negative
@@ -66,6 +113,8 @@ pub struct CPU {
 
     // Decimal arithmetic enabled?
     dec_arith_on: bool,
+    // Whether undocumented opcodes run, rather than being treated as a bug.
+    illegal_opcodes: bool,
 
     // IRQ triggered?
     irq_flip_flop: bool,
@@ -73,10 +122,37 @@ pub struct CPU {
     // NMI triggered?
     nmi_flip_flop: bool,
 
+    // Set by KIL, or an opcode we can't run.
+    jam: Option<Jam>,
+
+    // Rest of the system, kept in step with our memory accesses.
+    bus_clock: Option<clock::BusClock>,
+
+    // Bus cycles used so far by the current instruction.
+    bus_cycles: u32,
+
+    // Total cycles run, only used for tracing.
+    cycles: u64,
+
+    // Counts every bus access while set.
+    heatmap: Option<Rc<RefCell<AccessHeatmap>>>,
+
+    // Told the cycle of each bus access while set, for the memory map to count against.
+    bus_stats: Option<Rc<RefCell<BusStats>>>,
+
+    // Marks PRG-ROM as code or data while set.  Reads within the bytes of the instruction being
+    // run are code, anything else it reads is data.
+    code_data_log: Option<Rc<RefCell<CodeDataLog>>>,
+    vram_write_log: Option<Rc<RefCell<VramWriteLog>>>,
+    watchpoints: Option<Rc<RefCell<Watchpoints>>>,
+    event_log: Option<Rc<RefCell<EventLog>>>,
+    instruction_start: u16,
+    instruction_len: u16,
+
     // Debug tracing execution.
-    // Format: a x y sp pch pcl p opcode arg1 arg2
     is_tracing: bool,
-    trace_buffer: RingBuffer<u8>,
+    trace_buffer: RingBuffer<trace::TraceFrame>,
+    trace_position: Option<TracePosition>,
 }
 
 pub fn new(memory: Box<dyn ReadWriter>) -> CPU {
@@ -91,16 +167,37 @@ pub fn new(memory: Box<dyn ReadWriter>) -> CPU {
         pc: 0,
         p,
         dec_arith_on: true,
+        illegal_opcodes: false,
         irq_flip_flop: false,
+        jam: None,
         nmi_flip_flop: false,
+        bus_clock: None,
+        bus_cycles: 0,
+        cycles: RESET_CYCLES,
+        heatmap: None,
+        bus_stats: None,
+        code_data_log: None,
+        vram_write_log: None,
+        watchpoints: None,
+        event_log: None,
+        instruction_start: 0,
+        instruction_len: 0,
         is_tracing: false,
-        trace_buffer: RingBuffer::new(MAX_TRACE_FRAMES),
+        trace_buffer: RingBuffer::new(DEFAULT_TRACE_INSTRUCTIONS),
+        trace_position: None,
     }
 }
 
 impl clock::Ticker for CPU {
     #[inline]
     fn tick(&mut self) -> u32 {
+        self.bus_cycles = 0;
+        if self.jam.is_some() {
+            // Interrupts can't get in either, but the rest of the system carries on.
+            self.finish_bus_cycles(1);
+            self.cycles += 1;
+            return 1;
+        }
         let instr_cycles = self.execute_next_instruction();
         let irq_cycles = if self.should_non_maskable_interrupt() {
             self.non_maskable_interrupt()
@@ -109,12 +206,16 @@ impl clock::Ticker for CPU {
         } else {
             0
         };
-        instr_cycles + irq_cycles
+        let cycles = instr_cycles + irq_cycles;
+        self.finish_bus_cycles(cycles);
+        self.cycles += cycles as u64;
+        cycles
     }
 }
 
 impl CPU {
     pub fn startup_sequence(&mut self) -> u32 {
+        self.jam = None;
         self.load_vector_to_pc(START_VECTOR);
 
         // Disable interrupts at startup.  The programmer should re-enable once they have completed
@@ -123,6 +224,65 @@ impl CPU {
         0
     }
 
+    pub fn jam(&self) -> Option<Jam> {
+        self.jam
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // Once set, every memory access an instruction makes first runs this clock up to the cycle the
+    // access happens on.  Without one, instructions run as if all at once.
+    pub fn set_bus_clock(&mut self, bus_clock: clock::BusClock) {
+        self.bus_clock = Some(bus_clock);
+    }
+
+    // Copies one byte for OAM DMA.  The CPU is halted meanwhile, but the copy still takes a read
+    // and a write cycle on the bus.  Returns elapsed cycles.
+    pub fn dma_copy(&mut self, from: u16, to: u16) -> u32 {
+        self.bus_cycles = 0;
+        let byte = self.read_bus(from);
+        self.write_bus(to, byte);
+        self.finish_bus_cycles(2);
+        self.cycles += 2;
+        2
+    }
+
+    // Halts the CPU while the DMC fetches a sample byte.  The bus clock keeps running, so the PPU
+    // and APU carry on meanwhile.  Returns elapsed cycles.
+    pub fn stall(&mut self, cycles: u32) -> u32 {
+        self.bus_cycles = 0;
+        self.finish_bus_cycles(cycles);
+        self.cycles += cycles as u64;
+        cycles
+    }
+
+    // Peeks for tracing and debugging don't count, only accesses instructions and DMA make.
+    pub fn set_heatmap(&mut self, heatmap: Option<Rc<RefCell<AccessHeatmap>>>) {
+        self.heatmap = heatmap;
+    }
+
+    pub fn set_bus_stats(&mut self, bus_stats: Option<Rc<RefCell<BusStats>>>) {
+        self.bus_stats = bus_stats;
+    }
+
+    pub fn set_vram_write_log(&mut self, vram_write_log: Option<Rc<RefCell<VramWriteLog>>>) {
+        self.vram_write_log = vram_write_log;
+    }
+
+    pub fn set_watchpoints(&mut self, watchpoints: Option<Rc<RefCell<Watchpoints>>>) {
+        self.watchpoints = watchpoints;
+    }
+
+    pub fn set_event_log(&mut self, event_log: Option<Rc<RefCell<EventLog>>>) {
+        self.event_log = event_log;
+    }
+
+    pub fn set_code_data_log(&mut self, code_data_log: Option<Rc<RefCell<CodeDataLog>>>) {
+        self.code_data_log = code_data_log;
+    }
+
     pub fn load_program(&mut self, program: &[u8]) {
         for (ix, byte) in program.iter().enumerate() {
             self.memory.write(ix as u16, *byte);
@@ -137,6 +297,10 @@ impl CPU {
         self.dec_arith_on = true;
     }
 
+    pub fn set_illegal_opcodes(&mut self, enabled: bool) {
+        self.illegal_opcodes = enabled;
+    }
+
     pub fn trigger_irq(&mut self) {
         self.irq_flip_flop = true;
     }
@@ -145,17 +309,22 @@ impl CPU {
         self.nmi_flip_flop = true;
     }
 
-    // Note: Only used by nestest test.
+    // Note: Only used by the nestest harness.
     pub fn peek_next_instruction(&mut self) -> (u8, Option<u8>, Option<u8>) {
         // Note since addressing modes modify the PC themselves we have to hack a bit here
         // to figure out which bytes form the next instruction.
         // Should probably refactor addressing modes so we can just query how many bytes it is.
+        // Detach the bus clock too, so that peeking doesn't use up any time.
         let saved_pc = self.pc;
+        let saved_bus_cycles = self.bus_cycles;
+        let bus_clock = self.bus_clock.take();
         let opcode = self.memory.read(self.pc);
-        let (_, addressing_mode, _) = CPU::decode_instruction(opcode);
+        let (_, addressing_mode, _) = self.decode(opcode);
         let (_, _) = addressing_mode(self);
         let num_bytes = self.pc - saved_pc;
         self.pc = saved_pc;
+        self.bus_cycles = saved_bus_cycles;
+        self.bus_clock = bus_clock;
 
         // Now we have the number of bytes, lets trace out the instruction.
         let b1 = if num_bytes > 0 {
@@ -174,14 +343,17 @@ impl CPU {
 
     // Returns number of elapsed cycles.
     fn execute_next_instruction(&mut self) -> u32 {
-        self.trace_registers();
-
-        let opcode = self.memory.read(self.pc);
-        self.trace_byte(opcode);
-        self.trace_args();
+        self.instruction_start = self.pc;
+        self.instruction_len = 1;
+        let opcode = self.read_bus(self.pc);
+        if self.code_data_log.is_some() {
+            self.instruction_len +=
+                disassembler::decode(opcode).map_or(0, |(_, mode)| mode.operand_bytes());
+        }
+        self.trace_instruction(opcode);
 
         self.pc += 1;
-        let (operation, addressing_mode, cycles) = CPU::decode_instruction(opcode);
+        let (operation, addressing_mode, cycles) = self.decode(opcode);
         let extra_cycles = operation(self, addressing_mode);
 
         cycles + extra_cycles
@@ -196,6 +368,11 @@ impl CPU {
     }
 
     fn interrupt_to_vector(&mut self, vector: u16) -> u32 {
+        // The CPU fetches the next opcode twice and throws it away before taking the interrupt.
+        let pc = self.pc;
+        self.dummy_read(pc);
+        self.dummy_read(pc);
+
         // Store processor state.
         let pch = (self.pc >> 8) as u8;
         let pcl = self.pc as u8;
@@ -226,6 +403,33 @@ impl CPU {
         should
     }
 
+    fn decode(&self, opcode: u8) -> (instructions::Operation, addressing::AddressingMode, u32) {
+        match CPU::decode_unofficial_instruction(opcode) {
+            Some(decoded) if self.illegal_opcodes => decoded,
+            _ => CPU::decode_instruction(opcode),
+        }
+    }
+
+    // Only the unofficial NOPs so far, which some games use as padding or to skip a byte.
+    fn decode_unofficial_instruction(
+        opcode: u8,
+    ) -> Option<(instructions::Operation, addressing::AddressingMode, u32)> {
+        let decoded: (instructions::Operation, addressing::AddressingMode, u32) = match opcode {
+            0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => (instructions::nop, addressing::implied, 2),
+            0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 => (instructions::ign, addressing::immediate, 2),
+            0x04 | 0x44 | 0x64 => (instructions::ign, addressing::zero_page, 3),
+            0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4 => {
+                (instructions::ign, addressing::zero_page_indexed, 4)
+            }
+            0x0C => (instructions::ign, addressing::absolute, 4),
+            0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => {
+                (instructions::ign, addressing::absolute_indexed_x, 4)
+            }
+            _ => return None,
+        };
+        Some(decoded)
+    }
+
     fn decode_instruction(
         opcode: u8,
     ) -> (instructions::Operation, addressing::AddressingMode, u32) {
@@ -256,7 +460,7 @@ impl CPU {
             opcodes::ASL_ZPG => (instructions::asl, addressing::zero_page, 5),
             opcodes::ASL_ZPG_X => (instructions::asl, addressing::zero_page_indexed, 6),
             opcodes::ASL_ABS => (instructions::asl, addressing::absolute, 6),
-            opcodes::ASL_ABS_X => (instructions::asl, addressing::absolute_indexed_x, 7),
+            opcodes::ASL_ABS_X => (instructions::asl, addressing::absolute_indexed_x_write, 7),
 
             // BCC, BCS, BEQ
             opcodes::BCC => (instructions::bcc, addressing::relative, 2),
@@ -307,7 +511,7 @@ impl CPU {
             opcodes::DEC_ZPG => (instructions::dec, addressing::zero_page, 5),
             opcodes::DEC_ZPG_X => (instructions::dec, addressing::zero_page_indexed, 6),
             opcodes::DEC_ABS => (instructions::dec, addressing::absolute, 6),
-            opcodes::DEC_ABS_X => (instructions::dec, addressing::absolute_indexed_x, 7),
+            opcodes::DEC_ABS_X => (instructions::dec, addressing::absolute_indexed_x_write, 7),
 
             // DEX, INY
             opcodes::DEX => (instructions::dex, addressing::implied, 2),
@@ -327,7 +531,7 @@ impl CPU {
             opcodes::INC_ZPG => (instructions::inc, addressing::zero_page, 5),
             opcodes::INC_ZPG_X => (instructions::inc, addressing::zero_page_indexed, 6),
             opcodes::INC_ABS => (instructions::inc, addressing::absolute, 6),
-            opcodes::INC_ABS_X => (instructions::inc, addressing::absolute_indexed_x, 7),
+            opcodes::INC_ABS_X => (instructions::inc, addressing::absolute_indexed_x_write, 7),
 
             // INX, INY
             opcodes::INX => (instructions::inx, addressing::implied, 2),
@@ -369,7 +573,7 @@ impl CPU {
             opcodes::LSR_ZPG => (instructions::lsr, addressing::zero_page, 5),
             opcodes::LSR_ZPG_X => (instructions::lsr, addressing::zero_page_indexed, 6),
             opcodes::LSR_ABS => (instructions::lsr, addressing::absolute, 6),
-            opcodes::LSR_ABS_X => (instructions::lsr, addressing::absolute_indexed_x, 7),
+            opcodes::LSR_ABS_X => (instructions::lsr, addressing::absolute_indexed_x_write, 7),
 
             // NOP
             opcodes::NOP => (instructions::nop, addressing::implied, 2),
@@ -395,14 +599,14 @@ impl CPU {
             opcodes::ROL_ZPG => (instructions::rol, addressing::zero_page, 5),
             opcodes::ROL_ZPG_X => (instructions::rol, addressing::zero_page_indexed, 6),
             opcodes::ROL_ABS => (instructions::rol, addressing::absolute, 6),
-            opcodes::ROL_ABS_X => (instructions::rol, addressing::absolute_indexed_x, 7),
+            opcodes::ROL_ABS_X => (instructions::rol, addressing::absolute_indexed_x_write, 7),
 
             // ROR
             opcodes::ROR_A => (instructions::rora, addressing::implied, 2),
             opcodes::ROR_ZPG => (instructions::ror, addressing::zero_page, 5),
             opcodes::ROR_ZPG_X => (instructions::ror, addressing::zero_page_indexed, 6),
             opcodes::ROR_ABS => (instructions::ror, addressing::absolute, 6),
-            opcodes::ROR_ABS_X => (instructions::ror, addressing::absolute_indexed_x, 7),
+            opcodes::ROR_ABS_X => (instructions::ror, addressing::absolute_indexed_x_write, 7),
 
             // RTI, RTS
             opcodes::RTI => (instructions::rti, addressing::implied, 6),
@@ -427,10 +631,10 @@ impl CPU {
             opcodes::STA_ZPG => (instructions::sta, addressing::zero_page, 3),
             opcodes::STA_ZPG_X => (instructions::sta, addressing::zero_page_indexed, 4),
             opcodes::STA_ABS => (instructions::sta, addressing::absolute, 4),
-            opcodes::STA_ABS_X => (instructions::sta, addressing::absolute_indexed_x, 5),
-            opcodes::STA_ABS_Y => (instructions::sta, addressing::absolute_indexed_y, 5),
+            opcodes::STA_ABS_X => (instructions::sta, addressing::absolute_indexed_x_write, 5),
+            opcodes::STA_ABS_Y => (instructions::sta, addressing::absolute_indexed_y_write, 5),
             opcodes::STA_IX_IND => (instructions::sta, addressing::indexed_indirect, 6),
-            opcodes::STA_IND_IX => (instructions::sta, addressing::indirect_indexed, 6),
+            opcodes::STA_IND_IX => (instructions::sta, addressing::indirect_indexed_write, 6),
 
             // STX
             opcodes::STX_ZPG => (instructions::stx, addressing::zero_page, 3),
@@ -450,10 +654,17 @@ impl CPU {
             opcodes::TSX => (instructions::tsx, addressing::implied, 2),
             opcodes::TXS => (instructions::txs, addressing::implied, 2),
 
-            _ => panic!("Unknown opcode: {:X}", opcode),
+            // Opcodes we don't emulate stop the CPU like KIL does, rather than carry on with
+            // something made up.
+            _ => (instructions::kil, addressing::implied, 2),
         }
     }
 
+    // Direct memory access for tests and debugging.  Takes no time.
+    pub fn disassemble(&mut self, pc: u16) -> disassembler::Instruction {
+        disassembler::disassemble(&mut *self.memory, pc)
+    }
+
     pub fn load_memory(&mut self, address: u16) -> u8 {
         self.memory.read(address)
     }
@@ -462,59 +673,154 @@ impl CPU {
         self.memory.write(address, byte);
     }
 
+    // Memory access as part of an instruction.  Each one is a bus cycle, and happens after the
+    // rest of the system has caught up to that cycle.
+    fn read_bus(&mut self, address: u16) -> u8 {
+        if let Some(ref cdl) = self.code_data_log {
+            if address.wrapping_sub(self.instruction_start) < self.instruction_len {
+                cdl.borrow_mut().record_code(address);
+            } else {
+                cdl.borrow_mut().record_data(address);
+            }
+        }
+        self.bus_read(address)
+    }
+
+    // A read the CPU makes and throws away.  It still takes a bus cycle, and can still have side
+    // effects on registers, but it isn't the program reading data.
+    fn dummy_read(&mut self, address: u16) {
+        let _ = self.bus_read(address);
+    }
+
+    fn bus_read(&mut self, address: u16) -> u8 {
+        self.sync_bus();
+        if let Some(ref heatmap) = self.heatmap {
+            heatmap.borrow_mut().record_read(address);
+        }
+        let byte = self.memory.read(address);
+        if let Some(ref watchpoints) = self.watchpoints {
+            watchpoints
+                .borrow_mut()
+                .record(Access::Read, address, byte, self.instruction_start);
+        }
+        byte
+    }
+
+    fn write_bus(&mut self, address: u16, byte: u8) {
+        self.sync_bus();
+        if let Some(ref heatmap) = self.heatmap {
+            heatmap.borrow_mut().record_write(address);
+        }
+        if let Some(ref log) = self.vram_write_log {
+            // PPUDATA and its mirrors.
+            if address & 0xE007 == 0x2007 {
+                log.borrow_mut().record(self.instruction_start, byte);
+            }
+        }
+        if let Some(ref watchpoints) = self.watchpoints {
+            watchpoints
+                .borrow_mut()
+                .record(Access::Write, address, byte, self.instruction_start);
+        }
+        if let Some(ref log) = self.event_log {
+            log.borrow_mut()
+                .register_write(self.instruction_start, address, byte);
+        }
+        self.memory.write(address, byte);
+    }
+
+    #[inline]
+    fn sync_bus(&mut self) {
+        if let Some(ref mut bus_clock) = self.bus_clock {
+            bus_clock.run_to(self.bus_cycles);
+        }
+        if let Some(ref stats) = self.bus_stats {
+            stats
+                .borrow_mut()
+                .set_cycle(self.cycles + self.bus_cycles as u64);
+        }
+        self.bus_cycles += 1;
+    }
+
+    // Instructions don't always use the bus on every cycle, so make up the difference at the end.
+    fn finish_bus_cycles(&mut self, cycles: u32) {
+        if let Some(ref mut bus_clock) = self.bus_clock {
+            bus_clock.finish(cycles);
+        }
+        self.bus_cycles = 0;
+    }
+
     fn stack_push(&mut self, byte: u8) {
         let addr = 0x0100 | (self.sp as u16);
         self.sp = self.sp.wrapping_sub(1);
-        self.store_memory(addr, byte);
+        self.write_bus(addr, byte);
     }
 
     fn stack_pop(&mut self) -> u8 {
         self.sp = self.sp.wrapping_add(1);
         let addr = 0x0100 | (self.sp as u16);
-        self.load_memory(addr)
+        self.read_bus(addr)
+    }
+
+    // Pulls take a cycle to move the stack pointer, which reads the stack without using it.
+    fn stack_dummy_read(&mut self) {
+        let addr = 0x0100 | (self.sp as u16);
+        self.dummy_read(addr);
     }
 
     fn load_vector_to_pc(&mut self, vector: u16) {
-        let vector_low = self.load_memory(vector);
-        let vector_high = self.load_memory(vector + 1);
+        let vector_low = self.read_bus(vector);
+        let vector_high = self.read_bus(vector + 1);
         self.pc = util::combine_bytes(vector_high, vector_low);
     }
 }
 
 // CPU Debug tracing functions.
 impl CPU {
-    fn trace_byte(&mut self, byte: u8) {
-        if self.is_tracing {
-            self.trace_buffer.push(byte);
-        }
-    }
-
-    fn trace_registers(&mut self) {
-        if self.is_tracing {
-            self.trace_buffer.push(self.a);
-            self.trace_buffer.push(self.x);
-            self.trace_buffer.push(self.y);
-            self.trace_buffer.push(self.sp);
-            let (pch, pcl) = util::split_word(self.pc);
-            self.trace_buffer.push(pch);
-            self.trace_buffer.push(pcl);
-            self.trace_buffer.push(self.p.as_byte());
-        }
-    }
-
-    fn trace_args(&mut self) {
+    fn trace_instruction(&mut self, opcode: u8) {
         if self.is_tracing {
             // Note, we trace garbage bytes if the instruction has less than 2 args, but the
             // decoder will ignore them.
             // TODO: Trace these actually as we read them so we don't double-read.
             let pc = self.pc;
-            let arg1 = self.load_memory(pc + 1);
-            self.trace_buffer.push(arg1);
-            let arg2 = self.load_memory(pc + 2);
-            self.trace_buffer.push(arg2);
+            let (scanline, dot) = self.trace_position.as_ref().map_or((0, 0), |f| f());
+            let frame = trace::TraceFrame {
+                pc,
+                opcode,
+                arg1: self.load_memory(pc.wrapping_add(1)),
+                arg2: self.load_memory(pc.wrapping_add(2)),
+                a: self.a,
+                x: self.x,
+                y: self.y,
+                p: self.p.as_byte(),
+                sp: self.sp,
+                scanline,
+                dot,
+                cycle: self.cycles,
+            };
+            self.trace_buffer.push(frame);
         }
     }
 
+    // Lets traces show where the PPU was when each instruction started.
+    pub fn set_trace_position(&mut self, position: TracePosition) {
+        self.trace_position = Some(position);
+    }
+
+    // How many of the most recent instructions to keep.  Each is about 90 bytes once written
+    // out.
+    pub fn set_trace_capacity(&mut self, instructions: usize) {
+        self.trace_buffer.set_capacity(instructions);
+    }
+
+    pub fn trace_capacity(&self) -> usize {
+        self.trace_buffer.capacity()
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
     pub fn start_tracing(&mut self) {
         self.is_tracing = true;
     }
@@ -523,22 +829,16 @@ impl CPU {
         self.is_tracing = false;
     }
 
+    #[cfg(feature = "std")]
     pub fn flush_trace<W: Write>(&mut self, w: &mut W) {
         let mut buf = BufWriter::new(w);
-        println!("Flushing {} instructions.", self.trace_buffer.len() / 10);
+        println!("Flushing {} instructions.", self.trace_buffer.len());
         let before = Instant::now();
-        {
-            let trace_bytes = self.trace_buffer.flush_vec();
-            let mut frames = trace_bytes.chunks(TRACE_FRAME_SIZE);
-            while let Some(args) = frames.next() {
-                match args {
-                    [_, _, _, _, _, _, _, _, _, _] => {
-                        trace::write_trace_frame(&mut buf, args);
-                        write!(buf, "\n").unwrap();
-                    }
-                    _ => (),
-                };
-            }
+        let mut line = String::new();
+        for frame in self.trace_buffer.flush_vec() {
+            line.clear();
+            trace::write_trace_frame(&mut line, &frame);
+            writeln!(buf, "{}", line).unwrap();
         }
         let elapsed = before.elapsed();
         let elapsed_ns = elapsed.as_secs() * 1_000_000_000 + (elapsed.subsec_nanos() as u64);
@@ -567,6 +867,7 @@ impl<'de> state::SaveState<'de, state::CPUState> for CPU {
             dec_arith_on: self.dec_arith_on,
             irq_flip_flop: self.irq_flip_flop,
             nmi_flip_flop: self.nmi_flip_flop,
+            jam: self.jam,
         }
     }
 
@@ -580,5 +881,6 @@ impl<'de> state::SaveState<'de, state::CPUState> for CPU {
         self.dec_arith_on = s.dec_arith_on;
         self.irq_flip_flop = s.irq_flip_flop;
         self.nmi_flip_flop = s.nmi_flip_flop;
+        self.jam = s.jam;
     }
 }
@@ -0,0 +1,187 @@
+// Runs nestest in automation mode and checks the CPU against a golden nestest.log, one instruction
+// at a time.  Accepts both the old log format and Nintendulator's, as written by our own tracer.
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use core::fmt;
+
+use crate::emulator::clock::Ticker;
+use crate::emulator::cpu;
+use crate::emulator::cpu::trace;
+use crate::emulator::cpu::RESET_CYCLES;
+use crate::emulator::ines::ROM;
+use crate::emulator::memory::{Memory, Reader};
+
+// Automation mode skips the menu and starts straight into the tests.
+pub const AUTOMATION_START: u16 = 0xC000;
+
+// Log lines before the first undocumented opcode.  We don't implement those, so by default
+// verification stops here.
+pub const DOCUMENTED_INSTRUCTIONS: usize = 5003;
+
+// Where the CPU first disagreed with the log.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Divergence {
+    // 1-based line number in the log.
+    pub line: usize,
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+    pub log_line: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "nestest diverged at line {}: {} was {} but expected {}\n  {}",
+            self.line, self.field, self.actual, self.expected, self.log_line
+        )
+    }
+}
+
+pub struct Harness {
+    cpu: cpu::CPU,
+    cycles: u64,
+}
+
+impl Harness {
+    // Maps the 16KB PRG bank at both $8000 and $C000, as NROM-128 does, and sets up the register
+    // state the log expects.
+    pub fn new(rom: &ROM) -> Harness {
+        let mut prg = rom.prg_rom();
+        let prg_size = rom.prg_rom_size_bytes() as usize;
+
+        let mut program = vec![0; 0x10000];
+        for ix in 0..0x8000 {
+            program[0x8000 + ix] = prg.read((ix % prg_size) as u16);
+        }
+        program[cpu::START_VECTOR as usize] = AUTOMATION_START as u8;
+        program[cpu::START_VECTOR as usize + 1] = (AUTOMATION_START >> 8) as u8;
+
+        let mut cpu = cpu::new(Box::new(Memory::new_ram(0x10000)));
+        cpu.disable_bcd();
+        cpu.load_program(&program);
+        cpu.startup_sequence();
+        cpu.p.load_byte(0x24);
+        cpu.sp = 0xFD;
+
+        Harness { cpu, cycles: 0 }
+    }
+
+    // E.g. to trace a run for comparison against the log.
+    pub fn cpu(&mut self) -> &mut cpu::CPU {
+        &mut self.cpu
+    }
+
+    // Checks each line of the log in turn, executing one instruction after each.  Returns the
+    // number of instructions verified.
+    pub fn verify(&mut self, log: &str, max_instructions: usize) -> Result<usize, Divergence> {
+        let mut verified = 0;
+        for (ix, line) in log.lines().take(max_instructions).enumerate() {
+            self.check(ix + 1, line)?;
+            self.cycles += self.cpu.tick() as u64;
+            verified += 1;
+        }
+        Ok(verified)
+    }
+
+    fn check(&mut self, line_number: usize, line: &str) -> Result<(), Divergence> {
+        let diverged = |field, expected: String, actual: String| Divergence {
+            line: line_number,
+            field,
+            expected,
+            actual,
+            log_line: String::from(line),
+        };
+
+        let hex8 = |b: u8| format!("{:02X}", b);
+
+        let pc = trace::parse_pc(line);
+        if self.cpu.pc != pc {
+            return Err(diverged(
+                "PC",
+                format!("{:04X}", pc),
+                format!("{:04X}", self.cpu.pc),
+            ));
+        }
+
+        // The log marks undocumented opcodes with a '*'.  Decoding one would jam the CPU, so report
+        // it instead.
+        if line.as_bytes().get(15) == Some(&b'*') {
+            return Err(diverged(
+                "opcode",
+                format!("{} (undocumented)", hex8(trace::parse_opcode(line))),
+                String::from("unimplemented"),
+            ));
+        }
+
+        let (opcode, b1, b2) = self.cpu.peek_next_instruction();
+        if opcode != trace::parse_opcode(line) {
+            return Err(diverged(
+                "opcode",
+                hex8(trace::parse_opcode(line)),
+                hex8(opcode),
+            ));
+        }
+        if let Some(b) = b1 {
+            if b != trace::parse_instruction_byte_1(line) {
+                return Err(diverged(
+                    "operand 1",
+                    hex8(trace::parse_instruction_byte_1(line)),
+                    hex8(b),
+                ));
+            }
+        }
+        if let Some(b) = b2 {
+            if b != trace::parse_instruction_byte_2(line) {
+                return Err(diverged(
+                    "operand 2",
+                    hex8(trace::parse_instruction_byte_2(line)),
+                    hex8(b),
+                ));
+            }
+        }
+
+        let registers = [
+            ("A", self.cpu.a, trace::parse_a(line)),
+            ("X", self.cpu.x, trace::parse_x(line)),
+            ("Y", self.cpu.y, trace::parse_y(line)),
+            ("P", self.cpu.p.as_byte(), trace::parse_p(line)),
+            ("SP", self.cpu.sp, trace::parse_sp(line)),
+        ];
+        for (name, actual, expected) in registers.iter() {
+            if actual != expected {
+                return Err(diverged(name, hex8(*expected), hex8(*actual)));
+            }
+        }
+
+        // Nintendulator's logs count CPU cycles, including the reset sequence.
+        if let Some(expected) = trace::parse_cpu_cycle(line) {
+            let actual = self.cycles + RESET_CYCLES;
+            if actual != expected {
+                return Err(diverged("CYC", expected.to_string(), actual.to_string()));
+            }
+            return Ok(());
+        }
+
+        // Older logs' CYC column is the PPU dot, not a CPU cycle count, so convert.
+        let ppu_x = (self.cycles * 3) % 341;
+        let expected_x = trace::parse_cyc(line);
+        if ppu_x != expected_x {
+            return Err(diverged(
+                "CYC",
+                expected_x.to_string(),
+                format!("{} (after {} CPU cycles)", ppu_x, self.cycles),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// Runs the ROM against the log, stopping at the first divergence.
+pub fn verify(rom: &ROM, log: &str, max_instructions: usize) -> Result<usize, Divergence> {
+    Harness::new(rom).verify(log, max_instructions)
+}
@@ -0,0 +1,254 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::emulator::clock;
+use crate::emulator::clock::Ticker;
+use crate::emulator::cpu;
+use crate::emulator::cpu::test::PROGRAM_ROOT;
+use crate::emulator::memory::{Memory, Reader, Writer};
+
+// Counts elapsed CPU cycles, as seen by the rest of the system.
+struct CycleCounter {
+    cycles: Rc<Cell<u32>>,
+}
+
+impl Ticker for CycleCounter {
+    fn tick(&mut self) -> u32 {
+        self.cycles.set(self.cycles.get() + 1);
+        1
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Access {
+    Read(u16),
+    Write(u16, u8),
+}
+
+// Memory which logs what cycle each access happened on.
+struct BusLog {
+    memory: Memory,
+    cycles: Rc<Cell<u32>>,
+    log: Rc<RefCell<Vec<(u32, Access)>>>,
+}
+
+impl Reader for BusLog {
+    fn read(&mut self, address: u16) -> u8 {
+        self.log
+            .borrow_mut()
+            .push((self.cycles.get(), Access::Read(address)));
+        self.memory.read(address)
+    }
+}
+
+impl Writer for BusLog {
+    fn write(&mut self, address: u16, byte: u8) {
+        self.log
+            .borrow_mut()
+            .push((self.cycles.get(), Access::Write(address, byte)));
+        self.memory.write(address, byte)
+    }
+}
+
+struct Harness {
+    cpu: cpu::CPU,
+    cycles: Rc<Cell<u32>>,
+    log: Rc<RefCell<Vec<(u32, Access)>>>,
+}
+
+impl Harness {
+    fn new(program: &[u8]) -> Harness {
+        let cycles = Rc::new(Cell::new(0));
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut cpu = cpu::new(Box::new(BusLog {
+            memory: Memory::new_ram(0x10000),
+            cycles: cycles.clone(),
+            log: log.clone(),
+        }));
+
+        let mut bus_clock = clock::Clock::new();
+        bus_clock.manage(clock::ScaledTicker::new(
+            Box::new(CycleCounter {
+                cycles: cycles.clone(),
+            }),
+            1,
+        ));
+        cpu.set_bus_clock(clock::BusClock::new(bus_clock, 1));
+
+        for (ix, byte) in program.iter().enumerate() {
+            cpu.store_memory(PROGRAM_ROOT + ix as u16, *byte);
+        }
+        cpu.pc = PROGRAM_ROOT;
+
+        Harness { cpu, cycles, log }
+    }
+
+    // Runs one instruction, returning its length and every access it made, numbered by the cycle
+    // within the instruction that it happened on.
+    fn step(&mut self) -> (u32, Vec<(u32, Access)>) {
+        self.log.borrow_mut().clear();
+        let start = self.cycles.get();
+        let cycles = self.cpu.tick();
+        assert_eq!(self.cycles.get() - start, cycles);
+
+        let accesses = self
+            .log
+            .borrow()
+            .iter()
+            .map(|(cycle, access)| (cycle - start, *access))
+            .collect();
+        (cycles, accesses)
+    }
+}
+
+fn writes(accesses: &[(u32, Access)]) -> Vec<(u32, Access)> {
+    accesses
+        .iter()
+        .filter(|(_, access)| matches!(access, Access::Write(_, _)))
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn test_read_happens_on_last_cycle() {
+    // LDA $1234
+    let mut harness = Harness::new(&[0xAD, 0x34, 0x12]);
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 4);
+    assert_eq!(accesses.last(), Some(&(3, Access::Read(0x1234))));
+}
+
+#[test]
+fn test_page_crossing_read() {
+    // LDA $12FF,X with X = 1.
+    let mut harness = Harness::new(&[0xBD, 0xFF, 0x12]);
+    harness.cpu.x = 1;
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 5);
+    assert_eq!(accesses[3], (3, Access::Read(0x1200)));
+    assert_eq!(accesses[4], (4, Access::Read(0x1300)));
+}
+
+#[test]
+fn test_indexed_store_always_takes_fixup_cycle() {
+    // STA $2000,X with X = 7.
+    let mut harness = Harness::new(&[0x9D, 0x00, 0x20]);
+    harness.cpu.x = 7;
+    harness.cpu.a = 0x42;
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 5);
+    assert_eq!(writes(&accesses), vec![(4, Access::Write(0x2007, 0x42))]);
+
+    // STA ($10),Y
+    let mut harness = Harness::new(&[0x91, 0x10]);
+    harness.cpu.store_memory(0x10, 0x00);
+    harness.cpu.store_memory(0x11, 0x20);
+    harness.cpu.y = 1;
+    harness.cpu.a = 0x42;
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 6);
+    assert_eq!(writes(&accesses), vec![(5, Access::Write(0x2001, 0x42))]);
+}
+
+#[test]
+fn test_read_modify_write_writes_twice() {
+    // INC $0300
+    let mut harness = Harness::new(&[0xEE, 0x00, 0x03]);
+    harness.cpu.store_memory(0x0300, 0x41);
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 6);
+    assert_eq!(
+        writes(&accesses),
+        vec![
+            (4, Access::Write(0x0300, 0x41)),
+            (5, Access::Write(0x0300, 0x42)),
+        ]
+    );
+}
+
+#[test]
+fn test_jsr_pushes_before_reading_high_byte() {
+    let mut harness = Harness::new(&[0x20, 0xEF, 0xBE]);
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 6);
+    assert_eq!(
+        accesses,
+        vec![
+            (0, Access::Read(PROGRAM_ROOT)),
+            (1, Access::Read(PROGRAM_ROOT + 1)),
+            (2, Access::Read(0x01FD)),
+            (3, Access::Write(0x01FD, (PROGRAM_ROOT >> 8) as u8)),
+            (4, Access::Write(0x01FC, 0x02)),
+            (5, Access::Read(PROGRAM_ROOT + 2)),
+        ]
+    );
+}
+
+#[test]
+fn test_pla_and_pha_timing() {
+    // PHA; PLA
+    let mut harness = Harness::new(&[0x48, 0x68]);
+    harness.cpu.a = 0x99;
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 3);
+    assert_eq!(writes(&accesses), vec![(2, Access::Write(0x01FD, 0x99))]);
+
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 4);
+    assert_eq!(accesses.last(), Some(&(3, Access::Read(0x01FD))));
+}
+
+#[test]
+fn test_branch_timing() {
+    // BNE -4, taken back across a page.
+    let mut harness = Harness::new(&[0xD0, 0xFC]);
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 4);
+    assert_eq!(accesses.len(), 4);
+
+    // BEQ, not taken.
+    let mut harness = Harness::new(&[0xF0, 0x7F]);
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 2);
+    assert_eq!(accesses.len(), 2);
+}
+
+#[test]
+fn test_no_access_after_the_instruction_ends() {
+    // A mix of addressing modes.  No access may run over into the next instruction's cycles.
+    let program = [
+        0xA9, 0x01, // LDA #$01
+        0x85, 0x10, // STA $10
+        0xB5, 0x10, // LDA $10,X
+        0x0E, 0x00, 0x03, // ASL $0300
+        0x1E, 0x00, 0x03, // ASL $0300,X
+        0xA1, 0x10, // LDA ($10,X)
+        0xB1, 0x10, // LDA ($10),Y
+        0x6C, 0x00, 0x03, // JMP ($0300)
+    ];
+    let mut harness = Harness::new(&program);
+    for _ in 0..8 {
+        let (cycles, accesses) = harness.step();
+        assert!(accesses.iter().all(|(cycle, _)| *cycle < cycles));
+    }
+}
+
+#[test]
+fn test_pointer_read_low_byte_first() {
+    // JMP ($02FF), whose high byte wraps round to $0200.
+    let mut harness = Harness::new(&[0x6C, 0xFF, 0x02]);
+    let (cycles, accesses) = harness.step();
+    assert_eq!(cycles, 5);
+    assert_eq!(
+        &accesses[3..],
+        &[(3, Access::Read(0x02FF)), (4, Access::Read(0x0200))]
+    );
+
+    // LDA ($10),Y
+    let mut harness = Harness::new(&[0xB1, 0x10]);
+    let (_, accesses) = harness.step();
+    assert_eq!(
+        &accesses[2..4],
+        &[(2, Access::Read(0x0010)), (3, Access::Read(0x0011))]
+    );
+}
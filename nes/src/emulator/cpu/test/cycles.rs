@@ -0,0 +1,178 @@
+use crate::emulator::cpu::flags::Flag;
+use crate::emulator::cpu::test::load_data;
+use crate::emulator::cpu::test::load_program;
+use crate::emulator::cpu::test::new_cpu;
+use crate::emulator::cpu::test::run_instructions;
+
+// -- One test per addressing mode, covering a read, a write and a read-modify-write instruction
+// -- where the mode has them.
+
+#[test]
+fn test_immediate_cycles() {
+    assert_cycles!([0xA9, 0x01], 2); // LDA #$01
+    assert_cycles!([0x69, 0x01], 2); // ADC #$01
+    assert_cycles!([0xC9, 0x01], 2); // CMP #$01
+}
+
+#[test]
+fn test_implied_and_accumulator_cycles() {
+    assert_cycles!([0xE8], 2); // INX
+    assert_cycles!([0xAA], 2); // TAX
+    assert_cycles!([0x18], 2); // CLC
+    assert_cycles!([0xEA], 2); // NOP
+    assert_cycles!([0x0A], 2); // ASL A
+    assert_cycles!([0x6A], 2); // ROR A
+}
+
+#[test]
+fn test_zero_page_cycles() {
+    assert_cycles!([0xA5, 0x10], 3); // LDA $10
+    assert_cycles!([0x85, 0x10], 3); // STA $10
+    assert_cycles!([0xE6, 0x10], 5); // INC $10
+    assert_cycles!([0x24, 0x10], 3); // BIT $10
+}
+
+#[test]
+fn test_zero_page_indexed_cycles() {
+    // Indexing always costs a cycle, even though it can't cross a page.
+    assert_cycles!([0xB5, 0xFF], 4, |cpu| cpu.x = 2); // LDA $FF,X
+    assert_cycles!([0x95, 0x10], 4); // STA $10,X
+    assert_cycles!([0xF6, 0x10], 6); // INC $10,X
+    assert_cycles!([0xB6, 0x10], 4); // LDX $10,Y
+    assert_cycles!([0x96, 0x10], 4); // STX $10,Y
+}
+
+#[test]
+fn test_absolute_cycles() {
+    assert_cycles!([0xAD, 0x00, 0x03], 4); // LDA $0300
+    assert_cycles!([0x8D, 0x00, 0x03], 4); // STA $0300
+    assert_cycles!([0xEE, 0x00, 0x03], 6); // INC $0300
+    assert_cycles!([0x4C, 0x00, 0x03], 3); // JMP $0300
+}
+
+#[test]
+fn test_absolute_indexed_cycles() {
+    // Reads only pay for the fixup when the index crosses a page.
+    assert_cycles!([0xBD, 0x00, 0x03], 4, |cpu| cpu.x = 0xFF); // LDA $0300,X
+    assert_cycles!([0xBD, 0x01, 0x03], 5, |cpu| cpu.x = 0xFF);
+    assert_cycles!([0xB9, 0x00, 0x03], 4, |cpu| cpu.y = 0xFF); // LDA $0300,Y
+    assert_cycles!([0xB9, 0x01, 0x03], 5, |cpu| cpu.y = 0xFF);
+
+    // Writes and read-modify-writes always do.
+    assert_cycles!([0x9D, 0x00, 0x03], 5); // STA $0300,X
+    assert_cycles!([0x99, 0x00, 0x03], 5); // STA $0300,Y
+    assert_cycles!([0xFE, 0x00, 0x03], 7); // INC $0300,X
+    assert_cycles!([0xFE, 0xFF, 0x03], 7, |cpu| cpu.x = 1);
+}
+
+#[test]
+fn test_indexed_indirect_cycles() {
+    let setup = |cpu: &mut crate::emulator::cpu::CPU| {
+        cpu.x = 4;
+        load_data(&mut cpu.memory, 0x14, &[0x00, 0x03]);
+    };
+    assert_cycles!([0xA1, 0x10], 6, setup); // LDA ($10,X)
+    assert_cycles!([0x81, 0x10], 6, setup); // STA ($10,X)
+}
+
+#[test]
+fn test_indirect_indexed_cycles() {
+    let pointer_to = |address: u16, y: u8| {
+        move |cpu: &mut crate::emulator::cpu::CPU| {
+            cpu.y = y;
+            load_data(
+                &mut cpu.memory,
+                0x10,
+                &[address as u8, (address >> 8) as u8],
+            );
+        }
+    };
+    assert_cycles!([0xB1, 0x10], 5, pointer_to(0x0300, 0xFF)); // LDA ($10),Y
+    assert_cycles!([0xB1, 0x10], 6, pointer_to(0x0301, 0xFF));
+    assert_cycles!([0x91, 0x10], 6, pointer_to(0x0300, 0x00)); // STA ($10),Y
+    assert_cycles!([0x91, 0x10], 6, pointer_to(0x0301, 0xFF));
+}
+
+#[test]
+fn test_indirect_cycles() {
+    assert_cycles!([0x6C, 0x00, 0x03], 5, |cpu| {
+        load_data(&mut cpu.memory, 0x0300, &[0x00, 0x04]);
+    }); // JMP ($0300)
+}
+
+#[test]
+fn test_branch_cycles() {
+    // Not taken.
+    assert_cycles!([0xD0, 0x10], 2, |cpu| cpu.p.set(Flag::Z)); // BNE
+    assert_cycles!([0xF0, 0x10], 2); // BEQ
+
+    // Taken, within the page.
+    assert_cycles!([0xD0, 0x10], 3); // BNE
+    assert_cycles!([0xB0, 0x10], 3, |cpu| cpu.p.set(Flag::C)); // BCS
+
+    // Taken, into the previous page.
+    assert_cycles!([0xD0, 0xFC], 4); // BNE
+    assert_cycles!([0x30, 0x80], 4, |cpu| cpu.p.set(Flag::N)); // BMI
+}
+
+#[test]
+fn test_stack_cycles() {
+    assert_cycles!([0x48], 3); // PHA
+    assert_cycles!([0x08], 3); // PHP
+    assert_cycles!([0x68], 4); // PLA
+    assert_cycles!([0x28], 4); // PLP
+    assert_cycles!([0x20, 0x00, 0x03], 6); // JSR $0300
+
+    // Return to $0300.  RTS adds one to the address it pulls.
+    assert_cycles!([0x60], 6, |cpu| {
+        cpu.sp = 0xFA;
+        load_data(&mut cpu.memory, 0x01FB, &[0xFF, 0x02]);
+    }); // RTS
+    assert_cycles!([0x40], 6, |cpu| {
+        cpu.sp = 0xFA;
+        load_data(&mut cpu.memory, 0x01FB, &[0x00, 0x00, 0x03]);
+    }); // RTI
+
+    assert_cycles!([0x00], 7, |cpu| {
+        load_data(&mut cpu.memory, 0xFFFE, &[0x00, 0x03]);
+    }); // BRK
+}
+
+#[test]
+fn test_program_total_cycles() {
+    // A typical copy loop.  The final branch isn't taken, so is a cycle shorter.
+    let copy_loop = [
+        0xA2, 0x00, // LDX #$00
+        0xA0, 0x08, // LDY #$08
+        0xBD, 0x00, 0x03, // LDA $0300,X
+        0x9D, 0x00, 0x04, // STA $0400,X
+        0xE8, // INX
+        0x88, // DEY
+        0xD0, 0xF6, // BNE -10
+    ];
+    assert_cycles!(copy_loop, 2 + 2 + 8 * (4 + 5 + 2 + 2 + 3) - 1);
+}
+
+#[test]
+fn test_illegal_nop_cycles() {
+    let enable = |cpu: &mut crate::emulator::cpu::CPU| cpu.set_illegal_opcodes(true);
+    assert_cycles!([0x1A], 2, enable); // NOP
+    assert_cycles!([0x80, 0x10], 2, enable); // NOP #$10
+    assert_cycles!([0x04, 0x10], 3, enable); // NOP $10
+    assert_cycles!([0x14, 0x10], 4, enable); // NOP $10,X
+    assert_cycles!([0x0C, 0x00, 0x03], 4, enable); // NOP $0300
+    assert_cycles!([0x1C, 0xFF, 0x03], 5, |cpu| {
+        cpu.set_illegal_opcodes(true);
+        cpu.x = 1;
+    }); // NOP $03FF,X
+}
+
+#[test]
+fn test_illegal_opcodes_off_by_default() {
+    let mut cpu = new_cpu();
+    load_program(&mut cpu, &[0x1A]);
+    run_instructions(&mut cpu, 1);
+    let jam = cpu.jam().expect("CPU should have jammed");
+    assert_eq!(jam.opcode, 0x1A);
+    assert!(!jam.is_kil());
+}
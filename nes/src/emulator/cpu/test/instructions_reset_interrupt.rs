@@ -1,7 +1,10 @@
+use crate::emulator::clock::Ticker;
 use crate::emulator::cpu::test::load_data;
 use crate::emulator::cpu::test::load_program;
 use crate::emulator::cpu::test::new_cpu;
 use crate::emulator::cpu::test::run_instructions;
+use crate::emulator::cpu::test::PROGRAM_ROOT;
+use crate::emulator::cpu::Jam;
 
 #[test]
 fn test_rti() {
@@ -35,3 +38,30 @@ fn test_brk() {
     assert_eq!(cpu.stack_pop(), (stored_pc >> 8) as u8); // PCH was stored.
     assert_eq!(cycles, 7);
 }
+
+#[test]
+fn test_kil() {
+    let mut cpu = new_cpu();
+    load_data(&mut cpu.memory, 0xFFFC, &[0x00, 0xF0]);
+    load_program(&mut cpu, &[0xEA, 0x02, 0xEA]);
+    run_instructions(&mut cpu, 2);
+    assert_eq!(
+        cpu.jam(),
+        Some(Jam {
+            pc: PROGRAM_ROOT + 1,
+            opcode: 0x02
+        })
+    );
+    assert!(cpu.jam().unwrap().is_kil());
+
+    // Stuck until reset, but time still passes.
+    cpu.trigger_nmi();
+    for _ in 0..10 {
+        assert_eq!(cpu.tick(), 1);
+    }
+    assert_eq!(cpu.pc, PROGRAM_ROOT + 1);
+
+    cpu.startup_sequence();
+    assert_eq!(cpu.jam(), None);
+    assert_eq!(cpu.pc, PROGRAM_ROOT);
+}
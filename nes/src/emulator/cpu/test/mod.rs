@@ -1,3 +1,25 @@
+// Runs a short program from PROGRAM_ROOT until the PC leaves it, and checks how many cycles it
+// took in total.  An optional closure can set up registers and memory first.
+macro_rules! assert_cycles {
+    ($program:expr, $expected:expr) => {
+        assert_cycles!($program, $expected, |_| ())
+    };
+    ($program:expr, $expected:expr, $setup:expr) => {{
+        let program: &[u8] = &$program;
+        let setup: &dyn Fn(&mut crate::emulator::cpu::CPU) = &$setup;
+        let mut cpu = crate::emulator::cpu::test::new_cpu();
+        setup(&mut cpu);
+        let cycles = crate::emulator::cpu::test::run_until_exit(&mut cpu, program);
+        assert_eq!(
+            cycles, $expected,
+            "wrong cycle count for program {:02X?}",
+            program
+        );
+    }};
+}
+
+mod bus_cycles;
+mod cycles;
 mod instructions_accumulator;
 mod instructions_arithmetic;
 mod instructions_branch;
@@ -48,6 +70,22 @@ fn run_program(cpu: &mut cpu::CPU, program: &[u8]) -> u32 {
     panic!("Program didn't terminate after 1000 ticks");
 }
 
+// Like run_program, but also stops if the program jumps or branches backwards out of itself.
+fn run_until_exit(cpu: &mut cpu::CPU, program: &[u8]) -> u32 {
+    let program_end = PROGRAM_ROOT + program.len() as u16;
+    load_program(cpu, program);
+
+    let mut cycles = 0;
+    for _ in 1..1000 {
+        if cpu.pc < PROGRAM_ROOT || cpu.pc >= program_end {
+            return cycles;
+        }
+        cycles += cpu.execute_next_instruction();
+    }
+
+    panic!("Program didn't terminate after 1000 ticks");
+}
+
 fn run_instructions(cpu: &mut cpu::CPU, num_instructions: u32) -> u32 {
     let mut cycles = 0;
     for _ in 0..num_instructions {
@@ -1,105 +1,85 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::fs;
 
 use crate::emulator::clock::Ticker;
-use crate::emulator::cpu;
-
-use crate::emulator::cpu::test::new_cpu;
+use crate::emulator::cpu::nestest;
+use crate::emulator::ines::ROM;
 use crate::emulator::test::test_resource_path;
 
-#[test]
-fn test_nestest() {
-    println!("Hello, world!");
-    let mut cpu = new_cpu();
-    cpu.disable_bcd();
-
-    load_rom(&mut cpu);
-
-    let mut trace_lines = load_trace();
-
-    cpu.startup_sequence();
-
-    // TODO(rnorris): Figure out if these starting conditions are universal.
-    cpu.p.load_byte(0x24);
-    cpu.sp = 0xFD;
-
-    let mut cycles: u64 = 0;
-
-    // At instruction 5004 it starts testing undocumented opcodes which we don't support.
-    for _ in 0..5003 {
-        // We know there are more than 5000 lines.
-        let line = trace_lines.next().unwrap();
-        assert_state(&mut cpu, cycles, line);
-
-        let new_cycles = cpu.tick();
-        cycles += new_cycles as u64;
+fn load_trace() -> String {
+    let path = test_resource_path("nestest/nestest.trace");
+    match fs::read_to_string(&path) {
+        Err(cause) => panic!("Couldn't open {}: {}", path.display(), cause),
+        Ok(trace) => trace,
     }
 }
 
-fn assert_state(cpu: &mut cpu::CPU, cycles: u64, line: String) {
-    // Check PC.
-    assert_eq!(cpu.pc, cpu::trace::parse_pc(&line));
-
-    // Check next instruction.
-    let (opcode, b1, b2) = cpu.peek_next_instruction();
-    assert_eq!(opcode, cpu::trace::parse_opcode(&line));
-    match b1 {
-        Some(b) => assert_eq!(b, cpu::trace::parse_instruction_byte_1(&line)),
-        None => (),
-    }
+fn load_rom() -> ROM {
+    ROM::load(test_resource_path("nestest/nestest.nes"))
+}
 
-    match b2 {
-        Some(b) => assert_eq!(b, cpu::trace::parse_instruction_byte_2(&line)),
-        None => (),
+#[test]
+fn test_nestest() {
+    let result = nestest::verify(&load_rom(), &load_trace(), nestest::DOCUMENTED_INSTRUCTIONS);
+    match result {
+        Ok(verified) => assert_eq!(verified, nestest::DOCUMENTED_INSTRUCTIONS),
+        Err(divergence) => panic!("{}", divergence),
     }
-
-    // Check registers.
-    assert_eq!(cpu.a, cpu::trace::parse_a(&line));
-    assert_eq!(cpu.x, cpu::trace::parse_x(&line));
-    assert_eq!(cpu.y, cpu::trace::parse_y(&line));
-    assert_eq!(cpu.p.as_byte(), cpu::trace::parse_p(&line));
-    assert_eq!(cpu.sp, cpu::trace::parse_sp(&line));
-
-    // Check CYC.
-    // Note that this is actually the PPU x-coordinate, not CPU cycles.
-    // So we have to convert.
-    let ppu_x = (cycles * 3) % 341;
-    assert_eq!(ppu_x, cpu::trace::parse_cyc(&line));
 }
 
-fn load_rom(cpu: &mut cpu::CPU) {
-    let path = test_resource_path("nestest/nestest.nes");
-    let mut file = match File::open(&path) {
-        Err(cause) => panic!("Couldn't open {}: {}", path.display(), cause),
-        Ok(file) => file,
-    };
-
-    let mut contents = vec![];
-    match file.read_to_end(&mut contents) {
-        Err(cause) => panic!("Failed to read file: {}", cause),
-        Ok(_) => (),
-    };
-
-    // Hack for now.  Later should properly map memory.
-    let mut program = [0; 65536];
-    for ix in 0..0x4000 {
-        program[0x8000 + ix] = contents[0x0010 + ix];
-        program[0xC000 + ix] = contents[0x0010 + ix];
+#[test]
+fn test_nestest_reports_first_divergence() {
+    // Corrupt the accumulator on line 3 and the X register on line 5.  Only the first should be
+    // reported.
+    let mut lines: Vec<String> = load_trace().lines().take(10).map(String::from).collect();
+    lines[2].replace_range(50..52, "7E");
+    lines[4].replace_range(55..57, "7E");
+    let trace = lines.join("\n");
+
+    let divergence = nestest::verify(&load_rom(), &trace, 10).unwrap_err();
+    assert_eq!(divergence.line, 3);
+    assert_eq!(divergence.field, "A");
+    assert_eq!(divergence.expected, "7E");
+    assert_eq!(divergence.actual, "00");
+    assert_eq!(divergence.log_line, lines[2]);
+}
 
-        // Override the startup vector so we start from the right place.
-        program[0xFFFC] = 0x00;
-        program[0xFFFD] = 0xC0;
+fn trace_nestest(instructions: usize, capacity: usize) -> String {
+    let mut harness = nestest::Harness::new(&load_rom());
+    harness.cpu().set_trace_capacity(capacity);
+    harness.cpu().start_tracing();
+    for _ in 0..instructions {
+        harness.cpu().tick();
     }
-    cpu.load_program(&program);
+    let mut trace = vec![];
+    harness.cpu().flush_trace(&mut trace);
+    String::from_utf8(trace).unwrap()
 }
 
-fn load_trace() -> impl Iterator<Item = String> {
-    let path = test_resource_path("nestest/nestest.trace");
-    let file = match File::open(&path) {
-        Err(cause) => panic!("Couldn't open {}: {}", path.display(), cause),
-        Ok(file) => file,
-    };
+#[test]
+fn test_nestest_verifies_own_trace() {
+    let trace = trace_nestest(100, 100);
+    assert_eq!(
+        trace.lines().next(),
+        Some("C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:7")
+    );
+    assert_eq!(nestest::verify(&load_rom(), &trace, 100), Ok(100));
+
+    let corrupted = trace.replacen("CYC:7", "CYC:8", 1);
+    let divergence = nestest::verify(&load_rom(), &corrupted, 100).unwrap_err();
+    assert_eq!(divergence.line, 1);
+    assert_eq!(divergence.field, "CYC");
+}
 
-    let reader = BufReader::new(file);
-    reader.lines().map(|l| l.unwrap())
+#[test]
+fn test_trace_capacity() {
+    let trace = trace_nestest(100, 10);
+    assert_eq!(trace.lines().count(), 10);
+    assert_eq!(
+        trace,
+        trace_nestest(100, 100)
+            .lines()
+            .skip(90)
+            .map(|l| format!("{}\n", l))
+            .collect::<String>()
+    );
 }
@@ -0,0 +1,68 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use crate::emulator::memory::{sram_mask, Mapper, Memory, Reader, Writer, EXPANSION_RAM_START};
+
+// CPU memory as debug tools see it.  Holds its own references to RAM and the cartridge, so a tool
+// can keep one around and look at memory between ticks without borrowing the NES.  Registers read
+// as 0 and cheats aren't applied.  Only RAM and cartridge RAM can be poked, since writes anywhere
+// else would hit registers.
+#[derive(Clone)]
+pub struct DebugMemory {
+    ram: Rc<RefCell<Memory>>,
+    sram: Rc<RefCell<Memory>>,
+    expansion_ram: Option<Rc<RefCell<Memory>>>,
+    mapper: Rc<RefCell<dyn Mapper>>,
+}
+
+impl DebugMemory {
+    pub fn new(
+        ram: Rc<RefCell<Memory>>,
+        sram: Rc<RefCell<Memory>>,
+        expansion_ram: Option<Rc<RefCell<Memory>>>,
+        mapper: Rc<RefCell<dyn Mapper>>,
+    ) -> DebugMemory {
+        DebugMemory {
+            ram,
+            sram,
+            expansion_ram,
+            mapper,
+        }
+    }
+
+    // PRG-RAM smaller than 8KB repeats to fill $6000-$7FFF, the same as on the bus.
+    fn sram_offset(&self, address: u16) -> u16 {
+        (address - 0x6000) & sram_mask(self.sram.borrow().len())
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.ram.borrow_mut().read(address & 0x7FF),
+            0x4020..=0x5FFF => self.expansion_ram.as_ref().map_or(0, |ram| {
+                ram.borrow_mut().read(address - EXPANSION_RAM_START)
+            }),
+            0x6000..=0x7FFF => {
+                let offset = self.sram_offset(address);
+                self.sram.borrow_mut().read(offset)
+            }
+            0x8000..=0xFFFF => self.mapper.borrow_mut().read_prg(address),
+            _ => 0,
+        }
+    }
+
+    pub fn poke(&self, address: u16, byte: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram.borrow_mut().write(address & 0x7FF, byte),
+            0x4020..=0x5FFF => {
+                if let Some(ref ram) = self.expansion_ram {
+                    ram.borrow_mut().write(address - EXPANSION_RAM_START, byte);
+                }
+            }
+            0x6000..=0x7FFF => {
+                let offset = self.sram_offset(address);
+                self.sram.borrow_mut().write(offset, byte);
+            }
+            _ => (),
+        }
+    }
+}
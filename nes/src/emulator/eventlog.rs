@@ -0,0 +1,256 @@
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use core::cell::RefCell;
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::emulator::components::ringbuffer::RingBuffer;
+use crate::emulator::ppu::PPU;
+
+// A record of notable things the emulator did, for finding out why a game went wrong without
+// tracing every instruction: interrupts, bank switches, writes to the registers which change how
+// the PPU and APU run, and anything that went wrong.  Only the newest events are kept, so it can be
+// left running.
+
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+// Registers whose writes are logged, by the address they're written at.  Games write most of these
+// every frame, so only writes which change the value are kept.
+const REGISTERS: [(u16, &str, Subsystem); 4] = [
+    (0x2000, "PPUCTRL", Subsystem::Ppu),
+    (0x2001, "PPUMASK", Subsystem::Ppu),
+    (0x4015, "SND_CHN", Subsystem::Apu),
+    (0x4017, "FRAME_COUNTER", Subsystem::Apu),
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum Severity {
+    // Happens every frame or more.
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 4] = [
+        Severity::Debug,
+        Severity::Info,
+        Severity::Warning,
+        Severity::Error,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Debug => "DEBUG",
+            Severity::Info => "INFO",
+            Severity::Warning => "WARN",
+            Severity::Error => "ERROR",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Subsystem {
+    Cpu,
+    Ppu,
+    Apu,
+    Mapper,
+    // Whatever is running the emulator.
+    Frontend,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 5] = [
+        Subsystem::Cpu,
+        Subsystem::Ppu,
+        Subsystem::Apu,
+        Subsystem::Mapper,
+        Subsystem::Frontend,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Cpu => "CPU",
+            Subsystem::Ppu => "PPU",
+            Subsystem::Apu => "APU",
+            Subsystem::Mapper => "MAPPER",
+            Subsystem::Frontend => "FRONTEND",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LogEvent {
+    pub frame: u64,
+    pub scanline: u16,
+    pub dot: u16,
+    pub severity: Severity,
+    pub subsystem: Subsystem,
+    pub message: String,
+}
+
+impl fmt::Display for LogEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>6} {:3}:{:3} {:<5} {:<8} {}",
+            self.frame,
+            self.scanline,
+            self.dot,
+            self.severity.name(),
+            self.subsystem.name(),
+            self.message
+        )
+    }
+}
+
+// Which events to show.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventFilter {
+    pub min_severity: Severity,
+    // One bit per subsystem.
+    subsystems: u8,
+}
+
+impl Default for EventFilter {
+    fn default() -> EventFilter {
+        EventFilter {
+            min_severity: Severity::Debug,
+            subsystems: 0xFF,
+        }
+    }
+}
+
+impl EventFilter {
+    pub fn shows(&self, subsystem: Subsystem) -> bool {
+        self.subsystems & subsystem.bit() != 0
+    }
+
+    pub fn toggle(&mut self, subsystem: Subsystem) {
+        self.subsystems ^= subsystem.bit();
+    }
+
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        event.severity >= self.min_severity && self.shows(event.subsystem)
+    }
+}
+
+pub struct EventLog {
+    ppu: Rc<RefCell<PPU>>,
+    events: RingBuffer<LogEvent>,
+    // Events pushed out of the log to make room.
+    dropped: u64,
+    // Last value written to each of `REGISTERS`.
+    registers: [Option<u8>; 4],
+    // Whether the APU and the mapper were asserting IRQ last time we looked.
+    apu_irq: bool,
+    mapper_irq: bool,
+}
+
+impl EventLog {
+    pub fn new(ppu: Rc<RefCell<PPU>>, capacity: usize) -> EventLog {
+        EventLog {
+            ppu,
+            events: RingBuffer::new(capacity),
+            dropped: 0,
+            registers: [None; 4],
+            apu_irq: false,
+            mapper_irq: false,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.events.capacity()
+    }
+
+    // Stamps the event with where the PPU has got to, or with zeroes if it's borrowed.
+    pub fn record(&mut self, severity: Severity, subsystem: Subsystem, message: String) {
+        let (frame, scanline, dot) = self.ppu.try_borrow().map_or((0, 0, 0), |ppu| {
+            let stats = ppu.stats();
+            (stats.frame_count, stats.scanline, stats.dot)
+        });
+        if self.events.len() == self.events.capacity() {
+            self.dropped += 1;
+        }
+        self.events.push(LogEvent {
+            frame,
+            scanline,
+            dot,
+            severity,
+            subsystem,
+            message,
+        });
+    }
+
+    // Called by the CPU for every write.  PPU registers are mirrored all the way up to $3FFF.
+    pub fn register_write(&mut self, pc: u16, address: u16, byte: u8) {
+        let address = if address & 0xE000 == 0x2000 {
+            address & 0xE007
+        } else {
+            address
+        };
+        let ix = match REGISTERS.iter().position(|(at, _, _)| *at == address) {
+            None => return,
+            Some(ix) => ix,
+        };
+        if self.registers[ix] == Some(byte) {
+            return;
+        }
+        self.registers[ix] = Some(byte);
+        let (_, name, subsystem) = REGISTERS[ix];
+        self.record(
+            Severity::Debug,
+            subsystem,
+            format!("${:04X} set {} to ${:02X}", pc, name, byte),
+        );
+    }
+
+    // IRQ is held until acknowledged, so only the moment each source raises it is logged.
+    pub fn irq_lines(&mut self, apu: bool, mapper: bool) {
+        if apu && !self.apu_irq {
+            self.record(Severity::Debug, Subsystem::Apu, String::from("IRQ"));
+        }
+        if mapper && !self.mapper_irq {
+            self.record(Severity::Debug, Subsystem::Mapper, String::from("IRQ"));
+        }
+        self.apu_irq = apu;
+        self.mapper_irq = mapper;
+    }
+
+    // Oldest first.
+    pub fn events(&self) -> impl DoubleEndedIterator<Item = &LogEvent> {
+        self.events.iter()
+    }
+
+    pub fn filtered<'a>(
+        &'a self,
+        filter: &'a EventFilter,
+    ) -> impl DoubleEndedIterator<Item = &'a LogEvent> {
+        self.events().filter(move |event| filter.matches(event))
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.len() == 0
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.dropped = 0;
+    }
+}
@@ -0,0 +1,73 @@
+use crate::emulator::{
+    NES_APU_CLOCK_FACTOR, NES_CPU_CLOCK_FACTOR, NES_MASTER_CLOCK_HZ, NES_PPU_CLOCK_FACTOR,
+    PAL_APU_CLOCK_FACTOR, PAL_CPU_CLOCK_FACTOR, PAL_MASTER_CLOCK_HZ, PAL_PPU_CLOCK_FACTOR,
+};
+
+// The chips each region's consoles were built with, and everything the emulator models about how
+// they differ.  The CPU has the APU built in, and it always comes paired with the PPU from the same
+// console since both run off its crystal, so picking a region picks all three.
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Revision {
+    pub cpu: &'static str,
+    pub ppu: &'static str,
+
+    pub master_clock_hz: u64,
+    // Master clocks per CPU cycle, APU cycle and PPU dot.
+    pub cpu_clock_factor: u32,
+    pub apu_clock_factor: u32,
+    pub ppu_clock_factor: u32,
+
+    // APU cycles to each of the frame counter's first three steps, then to the end of the four and
+    // five step sequences.
+    pub frame_counter_steps: [u64; 5],
+    // Timer periods selected by $400E and $4010, in CPU cycles.
+    pub noise_periods: [u16; 16],
+    pub dmc_periods: [u16; 16],
+
+    // Including vblank and the pre-render line.
+    pub scanlines_per_frame: u16,
+    // Whether the pre-render line before an odd frame is a dot short while rendering is enabled.
+    pub skips_odd_frame_dot: bool,
+    // Whether PPUMASK's red and green emphasis bits are the other way round.
+    pub swaps_emphasis: bool,
+}
+
+// NTSC consoles and the Famicom.
+pub const NTSC: Revision = Revision {
+    cpu: "RP2A03",
+    ppu: "RP2C02",
+    master_clock_hz: NES_MASTER_CLOCK_HZ,
+    cpu_clock_factor: NES_CPU_CLOCK_FACTOR,
+    apu_clock_factor: NES_APU_CLOCK_FACTOR,
+    ppu_clock_factor: NES_PPU_CLOCK_FACTOR,
+    frame_counter_steps: [3729, 7457, 11186, 14915, 18641],
+    noise_periods: [
+        4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+    ],
+    dmc_periods: [
+        428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+    ],
+    scanlines_per_frame: 262,
+    skips_odd_frame_dot: true,
+    swaps_emphasis: false,
+};
+
+pub const PAL: Revision = Revision {
+    cpu: "RP2A07",
+    ppu: "RP2C07",
+    master_clock_hz: PAL_MASTER_CLOCK_HZ,
+    cpu_clock_factor: PAL_CPU_CLOCK_FACTOR,
+    apu_clock_factor: PAL_APU_CLOCK_FACTOR,
+    ppu_clock_factor: PAL_PPU_CLOCK_FACTOR,
+    frame_counter_steps: [4157, 8314, 12470, 16627, 20782],
+    noise_periods: [
+        4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+    ],
+    dmc_periods: [
+        398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+    ],
+    scanlines_per_frame: 312,
+    skips_odd_frame_dot: false,
+    swaps_emphasis: true,
+};
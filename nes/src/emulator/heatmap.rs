@@ -0,0 +1,150 @@
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Counts CPU bus accesses per address over the last few frames, to show which parts of RAM and
+// PRG-ROM a game really uses.  Counts are kept for the whole address space, with RAM's mirrors
+// folded into the first 2KB.
+
+const ADDRESS_SPACE: usize = 0x10000;
+
+// Accesses to one address during one frame.
+#[derive(Clone, Copy, Debug)]
+struct FrameCount {
+    address: u16,
+    reads: u32,
+    writes: u32,
+}
+
+pub struct AccessHeatmap {
+    window: usize,
+    // Accesses so far this frame.
+    reads: Vec<u32>,
+    writes: Vec<u32>,
+    // Only the addresses touched in each finished frame, oldest first, so they can be taken off
+    // the totals once they fall out of the window.
+    history: VecDeque<Vec<FrameCount>>,
+    total_reads: Vec<u32>,
+    total_writes: Vec<u32>,
+}
+
+impl AccessHeatmap {
+    // Counts accesses over the last `frames` frames.
+    pub fn new(frames: usize) -> AccessHeatmap {
+        AccessHeatmap {
+            window: frames.max(1),
+            reads: vec![0; ADDRESS_SPACE],
+            writes: vec![0; ADDRESS_SPACE],
+            history: VecDeque::new(),
+            total_reads: vec![0; ADDRESS_SPACE],
+            total_writes: vec![0; ADDRESS_SPACE],
+        }
+    }
+
+    #[inline]
+    pub fn record_read(&mut self, address: u16) {
+        self.reads[fold_mirrors(address)] += 1;
+    }
+
+    #[inline]
+    pub fn record_write(&mut self, address: u16) {
+        self.writes[fold_mirrors(address)] += 1;
+    }
+
+    // Moves this frame's accesses into the totals, dropping the oldest frame if the window is
+    // full.
+    pub fn end_frame(&mut self) {
+        let mut counts = vec![];
+        for address in 0..ADDRESS_SPACE {
+            let (reads, writes) = (self.reads[address], self.writes[address]);
+            if reads == 0 && writes == 0 {
+                continue;
+            }
+            self.total_reads[address] += reads;
+            self.total_writes[address] += writes;
+            counts.push(FrameCount {
+                address: address as u16,
+                reads,
+                writes,
+            });
+        }
+        self.reads.iter_mut().for_each(|count| *count = 0);
+        self.writes.iter_mut().for_each(|count| *count = 0);
+
+        self.history.push_back(counts);
+        while self.history.len() > self.window {
+            if let Some(oldest) = self.history.pop_front() {
+                for count in oldest {
+                    self.total_reads[count.address as usize] -= count.reads;
+                    self.total_writes[count.address as usize] -= count.writes;
+                }
+            }
+        }
+    }
+
+    // Reads of `address` over the finished frames in the window.  RAM mirrors count as the
+    // address they mirror.
+    pub fn reads(&self, address: u16) -> u32 {
+        self.total_reads[fold_mirrors(address)]
+    }
+
+    pub fn writes(&self, address: u16) -> u32 {
+        self.total_writes[fold_mirrors(address)]
+    }
+
+    // How many frames the counts cover so far, up to the window size.
+    pub fn frames(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    pub fn clear(&mut self) {
+        *self = AccessHeatmap::new(self.window);
+    }
+}
+
+fn fold_mirrors(address: u16) -> usize {
+    match address {
+        0x0000..=0x1FFF => (address & 0x7FF) as usize,
+        _ => address as usize,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AccessHeatmap;
+
+    #[test]
+    fn test_counts_over_window() {
+        let mut heatmap = AccessHeatmap::new(2);
+        heatmap.record_read(0x0010);
+        heatmap.record_write(0x0810);
+        heatmap.end_frame();
+        heatmap.record_read(0x8000);
+        heatmap.record_read(0x8000);
+        heatmap.end_frame();
+        assert_eq!(heatmap.reads(0x0010), 1);
+        assert_eq!(heatmap.writes(0x0010), 1);
+        assert_eq!(heatmap.reads(0x8000), 2);
+        assert_eq!(heatmap.frames(), 2);
+
+        // The first frame falls out of the window.
+        heatmap.end_frame();
+        assert_eq!(heatmap.reads(0x0010), 0);
+        assert_eq!(heatmap.writes(0x0010), 0);
+        assert_eq!(heatmap.reads(0x8000), 2);
+        assert_eq!(heatmap.frames(), 2);
+    }
+
+    #[test]
+    fn test_unfinished_frame_not_counted() {
+        let mut heatmap = AccessHeatmap::new(4);
+        heatmap.record_write(0x6000);
+        assert_eq!(heatmap.writes(0x6000), 0);
+        heatmap.end_frame();
+        assert_eq!(heatmap.writes(0x6000), 1);
+    }
+}
@@ -0,0 +1,98 @@
+// Formats memory as a classic hex dump, for the debugger and for logging:
+//
+//   $C000: 4C F5 C5 60 78 D8 A2 FF  9A AD 02 20 10 FB AD 02  |L..`x...... ....|
+//
+// Bytes come from a peek function, so dumping never has side effects on registers.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HexDumpOptions {
+    pub bytes_per_row: usize,
+    // Adds a wider gap every this many bytes.  Zero turns it off.
+    pub group: usize,
+    // Shows printable bytes as ASCII at the end of each row.
+    pub ascii: bool,
+}
+
+impl Default for HexDumpOptions {
+    fn default() -> HexDumpOptions {
+        HexDumpOptions {
+            bytes_per_row: 16,
+            group: 8,
+            ascii: true,
+        }
+    }
+}
+
+// Dumps addresses `start` up to but not including `end`, one row per line.  Rows are counted from
+// `start` rather than lined up to a multiple of the row length.
+pub fn hexdump<F>(start: usize, end: usize, options: &HexDumpOptions, mut peek: F) -> String
+where
+    F: FnMut(usize) -> u8,
+{
+    let per_row = options.bytes_per_row.max(1);
+    let mut out = String::new();
+    let mut row_start = start;
+    while row_start < end {
+        let row_end = end.min(row_start.saturating_add(per_row));
+        let bytes: Vec<u8> = (row_start..row_end).map(&mut peek).collect();
+
+        out.push_str(&format!("${:04X}:", row_start));
+        for ix in 0..per_row {
+            if ix != 0 && options.group != 0 && ix % options.group == 0 {
+                out.push(' ');
+            }
+            match bytes.get(ix) {
+                Some(byte) => out.push_str(&format!(" {:02X}", byte)),
+                // Pad a short last row so the ASCII lines up.
+                None if options.ascii => out.push_str("   "),
+                None => break,
+            }
+        }
+
+        if options.ascii {
+            out.push_str("  |");
+            for byte in bytes.iter() {
+                let printable = byte.is_ascii_graphic() || *byte == b' ';
+                out.push(if printable { *byte as char } else { '.' });
+            }
+            out.push('|');
+        }
+        out.push('\n');
+        row_start = row_end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hexdump_rows() {
+        let data = b"Hello, NES!\x00\x01\x02\xFF world";
+        let dump = hexdump(0, data.len(), &HexDumpOptions::default(), |ix| data[ix]);
+        assert_eq!(
+            dump,
+            "$0000: 48 65 6C 6C 6F 2C 20 4E  45 53 21 00 01 02 FF 20  |Hello, NES!.... |\n\
+             $0010: 77 6F 72 6C 64                                    |world|\n"
+        );
+    }
+
+    #[test]
+    fn test_hexdump_options() {
+        let options = HexDumpOptions {
+            bytes_per_row: 4,
+            group: 0,
+            ascii: false,
+        };
+        let dump = hexdump(0xFFFA, 0x10000, &options, |ix| (ix & 0xFF) as u8);
+        assert_eq!(dump, "$FFFA: FA FB FC FD\n$FFFE: FE FF\n");
+
+        assert_eq!(hexdump(10, 10, &options, |_| 0), "");
+        assert_eq!(hexdump(10, 5, &options, |_| 0), "");
+    }
+}
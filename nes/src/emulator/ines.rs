@@ -1,19 +1,170 @@
-use std::cell::RefCell;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+#[cfg(feature = "std")]
 use std::fs::File;
+#[cfg(feature = "std")]
 use std::io::Read;
+#[cfg(feature = "std")]
 use std::path::Path;
-use std::rc::Rc;
-use std::vec::Vec;
 
+#[cfg(feature = "std")]
+use crate::emulator::archive;
 use crate::emulator::mappers;
 use crate::emulator::memory::{Mapper, Memory};
 use crate::emulator::ppu;
+use crate::emulator::util;
+use crate::emulator::Region;
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+
+// Everything in $6000-$7FFF, which is as much PRG-RAM as we map.
+pub const PRG_RAM_SIZE: usize = 0x2000;
+
+// Trainers are copied into PRG-RAM at $7000 before the game starts.
+const TRAINER_OFFSET: usize = 0x1000;
+
+// The parts of a ROM header we understand.  PRG sizes are in 16KB banks, CHR in 8KB banks.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Header {
+    pub prg_rom_banks: u16,
+    pub chr_rom_banks: u16,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: ppu::MirrorMode,
+    pub four_screen: bool,
+    pub battery: bool,
+    pub trainer: bool,
+    pub region: Region,
+    // Work RAM at $6000-$7FFF, battery backed if `battery` is set.
+    pub prg_ram_size: u32,
+}
+
+impl Header {
+    // Always writes NES 2.0, which can express everything above unambiguously.
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(b"NES\x1A");
+        bytes[4] = (self.prg_rom_banks & 0xFF) as u8;
+        bytes[5] = (self.chr_rom_banks & 0xFF) as u8;
+        bytes[6] = ((self.mapper & 0x0F) << 4) as u8
+            | if self.four_screen { 0x08 } else { 0 }
+            | if self.trainer { 0x04 } else { 0 }
+            | if self.battery { 0x02 } else { 0 }
+            | if self.mirroring == ppu::MirrorMode::Vertical {
+                0x01
+            } else {
+                0
+            };
+        bytes[7] = (self.mapper & 0xF0) as u8 | 0x08;
+        bytes[8] = (self.submapper << 4) | ((self.mapper >> 8) & 0x0F) as u8;
+        bytes[9] = (((self.chr_rom_banks >> 8) & 0x0F) << 4) as u8
+            | ((self.prg_rom_banks >> 8) & 0x0F) as u8;
+        // PRG-RAM goes in the battery backed half if the cart has one.
+        let shift = prg_ram_shift(self.prg_ram_size);
+        bytes[10] = if self.battery { shift << 4 } else { shift };
+        // 8KB of CHR-RAM when there's no CHR-ROM.
+        bytes[11] = if self.chr_rom_banks == 0 { 0x07 } else { 0 };
+        bytes[12] = match self.region {
+            Region::NTSC => 0,
+            Region::PAL => 1,
+        };
+        bytes
+    }
+}
+
+// NES 2.0 gives RAM sizes as 64 << shift, with 0 meaning none.  Sizes in between round up.
+fn prg_ram_shift(size: u32) -> u8 {
+    if size == 0 {
+        return 0;
+    }
+    (1..15).find(|shift| 64u32 << shift >= size).unwrap_or(15)
+}
+
+// Checksums identifying a dump.  The whole-ROM sums cover PRG and CHR together, leaving out the
+// header and trainer, so the same game matches however its header has been fixed up.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Fingerprint {
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+    pub prg_crc32: u32,
+    pub prg_sha1: [u8; 20],
+    // Both zero-length sums for games with CHR-RAM.
+    pub chr_crc32: u32,
+    pub chr_sha1: [u8; 20],
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CRC32 {:08X} SHA1 ", self.crc32)?;
+        for byte in self.sha1.iter() {
+            write!(f, "{:02X}", byte)?;
+        }
+        write!(
+            f,
+            " (PRG {:08X}, CHR {:08X})",
+            self.prg_crc32, self.chr_crc32
+        )
+    }
+}
+
+// Signs of a bad dump or a header that doesn't match the data behind it.  None of these stop the
+// game loading, though truncated ROMs will panic once the missing part is read.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HeaderProblem {
+    Truncated { expected: usize, actual: usize },
+    // Bytes past the end of CHR-ROM, beyond any NES 2.0 miscellaneous ROM.  Often a sign of a
+    // wrong PRG or CHR size.
+    TrailingData { bytes: usize },
+    NoPrgRom,
+    // Real boards only had power of two PRG sizes, so anything else is usually a bad header or
+    // an overdump.
+    OddPrgSize { banks: u16 },
+    // Almost every trainer is from a hacked dump made for copiers.
+    Trainer,
+    // Junk in reserved header bytes, which we ignore.  See `has_dirty_header`.
+    DirtyHeader,
+}
+
+impl fmt::Display for HeaderProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderProblem::Truncated { expected, actual } => write!(
+                f,
+                "ROM is truncated: header says {} bytes but there are only {}",
+                expected, actual
+            ),
+            HeaderProblem::TrailingData { bytes } => write!(
+                f,
+                "{} bytes after CHR-ROM, the PRG or CHR size may be wrong",
+                bytes
+            ),
+            HeaderProblem::NoPrgRom => write!(f, "Header says there's no PRG-ROM"),
+            HeaderProblem::OddPrgSize { banks } => write!(
+                f,
+                "{}KB of PRG-ROM isn't a power of two, the header may be wrong",
+                u32::from(*banks) * 16
+            ),
+            HeaderProblem::Trainer => write!(f, "ROM has a trainer, so is probably a hacked dump"),
+            HeaderProblem::DirtyHeader => write!(
+                f,
+                "Junk in reserved header bytes, only the first 7 are used"
+            ),
+        }
+    }
+}
 
 pub struct ROM {
     data: Vec<u8>,
 }
 
 impl ROM {
+    // Zipped ROMs are unpacked, taking the first .nes file inside.
+    #[cfg(feature = "std")]
     pub fn load<P: AsRef<Path>>(path: P) -> ROM {
         let mut file = match File::open(path) {
             Err(cause) => panic!("Couldn't open file: {}", cause),
@@ -26,6 +177,13 @@ impl ROM {
             Ok(_) => (),
         };
 
+        if archive::is_zip(&contents) {
+            contents = match archive::extract_rom(&contents) {
+                Err(cause) => panic!("Couldn't load ROM from zip: {}", cause),
+                Ok(rom) => rom,
+            };
+        }
+
         ROM::from_bytes(contents)
     }
 
@@ -33,13 +191,96 @@ impl ROM {
         ROM { data }
     }
 
+    // Like `from_bytes`, but unpacks zips and checks the data looks like an iNES ROM first, for
+    // callers which can't trust where it came from.  Zips need the `std` feature.
+    pub fn parse(data: Vec<u8>) -> Result<ROM, String> {
+        #[cfg(feature = "std")]
+        let data = if archive::is_zip(&data) {
+            archive::extract_rom(&data)?
+        } else {
+            data
+        };
+        if data.len() < HEADER_SIZE || data[0..4] != *b"NES\x1A" {
+            return Err(String::from("Not an iNES ROM"));
+        }
+
+        let rom = ROM::from_bytes(data);
+        let size = rom.expected_size();
+        if rom.data.len() < size {
+            return Err(HeaderProblem::Truncated {
+                expected: size,
+                actual: rom.data.len(),
+            }
+            .to_string());
+        }
+        Ok(rom)
+    }
+
+    // Bytes the header accounts for, up to the end of CHR-ROM.
+    fn expected_size(&self) -> usize {
+        self.prg_start() + (self.prg_rom_size_bytes() + self.chr_rom_size_bytes()) as usize
+    }
+
+    // Anything about the header or data which suggests a bad dump.
+    pub fn diagnostics(&self) -> Vec<HeaderProblem> {
+        let mut problems = vec![];
+        let expected = self.expected_size();
+        if self.data.len() < expected {
+            problems.push(HeaderProblem::Truncated {
+                expected,
+                actual: self.data.len(),
+            });
+        } else if self.data.len() > expected {
+            let misc_roms = self.is_nes2() && self.data[14] & 0x03 != 0;
+            if !misc_roms {
+                problems.push(HeaderProblem::TrailingData {
+                    bytes: self.data.len() - expected,
+                });
+            }
+        }
+
+        let header = self.header();
+        if header.prg_rom_banks == 0 {
+            problems.push(HeaderProblem::NoPrgRom);
+        } else if !header.prg_rom_banks.is_power_of_two() {
+            problems.push(HeaderProblem::OddPrgSize {
+                banks: header.prg_rom_banks,
+            });
+        }
+        if header.trainer {
+            problems.push(HeaderProblem::Trainer);
+        }
+        if self.has_dirty_header() {
+            problems.push(HeaderProblem::DirtyHeader);
+        }
+        problems
+    }
+
     pub fn mapper_number(&self) -> u8 {
         ((self.data[6] & 0xF0) >> 4) | (self.data[7] & 0xF0)
     }
 
+    // Only NES 2.0 headers have room for a submapper, so anything else is submapper 0.
+    pub fn submapper(&self) -> u8 {
+        if self.is_nes2() {
+            self.data[8] >> 4
+        } else {
+            0
+        }
+    }
+
+    // PRG-ROM follows the header and the trainer, if there is one.
+    fn prg_start(&self) -> usize {
+        if self.data[6] & 0x04 != 0 {
+            HEADER_SIZE + TRAINER_SIZE
+        } else {
+            HEADER_SIZE
+        }
+    }
+
     pub fn prg_rom(&self) -> Memory {
         let size = self.prg_rom_size_bytes();
-        let start = 16 as usize;
+        let start = self.prg_start();
         let end = start + size as usize;
         Memory::new_rom(self.data[start..end].to_vec())
     }
@@ -56,7 +297,7 @@ impl ROM {
             // Cartridge uses chr_ram.
             Memory::new_ram(0x2000)
         } else {
-            let start = (16 + prg_size) as usize;
+            let start = self.prg_start() + prg_size as usize;
             let end = start + size as usize;
             Memory::new_rom(self.data[start..end].to_vec())
         }
@@ -67,43 +308,362 @@ impl ROM {
     }
 
     pub fn mirror_mode(&self) -> ppu::MirrorMode {
-        if self.data[6] & 0x1 == 0 {
+        if self.data[6] & 0x08 != 0 {
+            // Four-screen overrides the mirroring bit.
+            ppu::MirrorMode::FourScreen
+        } else if self.data[6] & 0x1 == 0 {
             ppu::MirrorMode::Horizontal
         } else {
             ppu::MirrorMode::Vertical
         }
     }
 
-    pub fn get_mapper(&self) -> Rc<RefCell<dyn Mapper>> {
-        let prg_rom = self.prg_rom();
-        let chr_mem = self.chr_mem();
-        let mirror_mode = self.mirror_mode();
-
-        match self.mapper_number() {
-            0 => Rc::new(RefCell::new(mappers::NROM::new(
-                prg_rom,
-                chr_mem,
-                mirror_mode,
-            ))),
-            1 => Rc::new(RefCell::new(mappers::MMC1::new(prg_rom, chr_mem))),
-            2 => Rc::new(RefCell::new(mappers::UXROM::new(
-                prg_rom,
-                chr_mem,
-                mirror_mode,
-            ))),
-            3 => Rc::new(RefCell::new(mappers::CNROM::new(
-                prg_rom,
-                chr_mem,
-                mirror_mode,
-            ))),
-            4 => Rc::new(RefCell::new(mappers::MMC3::new(prg_rom, chr_mem))),
-            7 => Rc::new(RefCell::new(mappers::AXROM::new(prg_rom, chr_mem))),
-            11 => Rc::new(RefCell::new(mappers::ColorDreams::new(
-                prg_rom,
-                chr_mem,
-                mirror_mode,
-            ))),
-            _ => panic!("Unknown mapper: {}", self.mapper_number()),
+    // Size of the battery-backed PRG-RAM in bytes.  NES 2.0 gives it as a shift count, while
+    // iNES 1.0 only has the battery bit, which always meant 8KB.
+    pub fn prg_nvram_size_bytes(&self) -> u32 {
+        if self.is_nes2() {
+            match self.data[10] >> 4 {
+                0 => 0,
+                shift => 64 << shift,
+            }
+        } else if self.data[6] & 0x02 != 0 {
+            PRG_RAM_SIZE as u32
+        } else {
+            0
+        }
+    }
+
+    // All the work RAM at $6000-$7FFF, battery backed or not.  iNES 1.0 never said, and always
+    // meant 8KB, which is also assumed for NES 2.0 headers giving none.
+    pub fn prg_ram_size_bytes(&self) -> u32 {
+        if !self.is_nes2() {
+            return PRG_RAM_SIZE as u32;
+        }
+        let volatile = match self.data[10] & 0x0F {
+            0 => 0,
+            shift => 64 << shift,
+        };
+        match volatile + self.prg_nvram_size_bytes() {
+            0 => PRG_RAM_SIZE as u32,
+            size => size,
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.data[6] & 0x02 != 0 || self.prg_nvram_size_bytes() > 0
+    }
+
+    // What PRG-RAM should hold at first power-on, if the ROM says.  NES 2.0 ROMs with non-volatile
+    // PRG-RAM can ship a default save as miscellaneous ROM data after CHR-ROM, and a trainer is
+    // always loaded at $7000 on top of that.
+    pub fn default_prg_ram(&self) -> Option<Vec<u8>> {
+        let mut image = vec![0; PRG_RAM_SIZE];
+        let mut found = false;
+
+        let misc_roms = if self.is_nes2() {
+            self.data[14] & 0x03
+        } else {
+            0
+        };
+        let nvram_size = self.prg_nvram_size_bytes() as usize;
+        if misc_roms > 0 && nvram_size > 0 {
+            let start =
+                self.prg_start() + (self.prg_rom_size_bytes() + self.chr_rom_size_bytes()) as usize;
+            if start < self.data.len() {
+                let misc = &self.data[start..];
+                let len = misc.len().min(nvram_size).min(PRG_RAM_SIZE);
+                image[..len].copy_from_slice(&misc[..len]);
+                found = true;
+            }
+        }
+
+        if self.data[6] & 0x04 != 0 && self.data.len() >= HEADER_SIZE + TRAINER_SIZE {
+            image[TRAINER_OFFSET..TRAINER_OFFSET + TRAINER_SIZE]
+                .copy_from_slice(&self.data[HEADER_SIZE..HEADER_SIZE + TRAINER_SIZE]);
+            found = true;
+        }
+
+        if found {
+            Some(image)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_nes2(&self) -> bool {
+        self.data[7] & 0x0C == 0x08
+    }
+
+    pub fn region(&self) -> Region {
+        if self.is_nes2() {
+            // NES 2.0 puts the CPU/PPU timing in the low 2 bits of byte 12.
+            // 0 = NTSC, 1 = PAL, 2 = multi-region, 3 = Dendy.
+            // Multi-region carts run fine on NTSC, and Dendy is closest to PAL.
+            match self.data[12] & 0x3 {
+                1 | 3 => Region::PAL,
+                _ => Region::NTSC,
+            }
+        } else if self.data[9] & 0x1 == 1 {
+            // Plain iNES only has the (rarely set) TV system bit in byte 9.
+            Region::PAL
+        } else {
+            Region::NTSC
+        }
+    }
+
+    // Old dumping tools scribbled their name over bytes 7-15 of iNES 1.0 headers, so when any of
+    // the reserved bytes are set we ignore everything past byte 6.
+    fn has_dirty_header(&self) -> bool {
+        !self.is_nes2() && self.data[12..HEADER_SIZE].iter().any(|b| *b != 0)
+    }
+
+    pub fn header(&self) -> Header {
+        let d = &self.data;
+        let dirty = self.has_dirty_header();
+
+        let mut header = Header {
+            prg_rom_banks: d[4] as u16,
+            chr_rom_banks: d[5] as u16,
+            mapper: (d[6] >> 4) as u16,
+            submapper: self.submapper(),
+            mirroring: if d[6] & 0x1 == 0 {
+                ppu::MirrorMode::Horizontal
+            } else {
+                ppu::MirrorMode::Vertical
+            },
+            four_screen: d[6] & 0x08 != 0,
+            battery: d[6] & 0x02 != 0,
+            trainer: d[6] & 0x04 != 0,
+            region: if dirty { Region::NTSC } else { self.region() },
+            prg_ram_size: self.prg_ram_size_bytes(),
+        };
+
+        if !dirty {
+            header.mapper |= (d[7] & 0xF0) as u16;
         }
+
+        if self.is_nes2() {
+            header.mapper |= ((d[8] & 0x0F) as u16) << 8;
+            header.prg_rom_banks |= ((d[9] & 0x0F) as u16) << 8;
+            header.chr_rom_banks |= ((d[9] & 0xF0) as u16) << 4;
+        }
+
+        header
+    }
+
+    // A copy of this ROM with the header replaced.
+    pub fn with_header(&self, header: &Header) -> ROM {
+        let mut data = header.to_bytes().to_vec();
+        data.extend_from_slice(&self.data[HEADER_SIZE..]);
+        ROM { data }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    // Checksum of everything after the header and trainer, which is how ROM databases key games.
+    pub fn crc32(&self) -> u32 {
+        let start = self.prg_start();
+        util::crc32(&self.data[start.min(self.data.len())..])
+    }
+
+    // Hashes the PRG and CHR data, each on its own and together.  Missing data, as in a truncated
+    // ROM, is left out.
+    pub fn fingerprint(&self) -> Fingerprint {
+        let start = self.prg_start().min(self.data.len());
+        let prg_end = (start + self.prg_rom_size_bytes() as usize).min(self.data.len());
+        let chr_end = (prg_end + self.chr_rom_size_bytes() as usize).min(self.data.len());
+        let prg = &self.data[start..prg_end];
+        let chr = &self.data[prg_end..chr_end];
+        Fingerprint {
+            crc32: self.crc32(),
+            sha1: util::sha1(&self.data[start..]),
+            prg_crc32: util::crc32(prg),
+            prg_sha1: util::sha1(prg),
+            chr_crc32: util::crc32(chr),
+            chr_sha1: util::sha1(chr),
+        }
+    }
+
+    pub fn get_mapper(&self) -> Result<Rc<RefCell<dyn Mapper>>, String> {
+        mappers::from_ines(self.mapper_number(), self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rom_with_header(header: [u8; HEADER_SIZE]) -> ROM {
+        let mut data = header.to_vec();
+        data.extend(vec![0xEA; 16384 + 8192]);
+        ROM::from_bytes(data)
+    }
+
+    #[test]
+    fn test_parse() {
+        let header = [
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let rom = rom_with_header(header);
+        assert!(ROM::parse(rom.bytes().to_vec()).is_ok());
+
+        let truncated = rom.bytes()[..HEADER_SIZE + 16384].to_vec();
+        assert!(ROM::parse(truncated).is_err());
+        assert!(ROM::parse(vec![0; 64]).is_err());
+        assert!(ROM::parse(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let rom = rom_with_header([
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x43, 0x10, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+        let header = rom.header();
+        assert_eq!(header.mapper, 0x14);
+        assert_eq!(header.mirroring, ppu::MirrorMode::Vertical);
+        assert!(header.battery);
+        assert!(!header.trainer);
+
+        let fixed = rom.with_header(&header);
+        assert!(fixed.is_nes2());
+        assert_eq!(fixed.header(), header);
+        assert_eq!(fixed.mapper_number(), 0x14);
+        assert_eq!(fixed.bytes()[HEADER_SIZE..], rom.bytes()[HEADER_SIZE..]);
+        assert_eq!(fixed.crc32(), rom.crc32());
+    }
+
+    #[test]
+    fn test_nes2_fields() {
+        let header = Header {
+            prg_rom_banks: 0x120,
+            chr_rom_banks: 0x0,
+            mapper: 0x1AB,
+            submapper: 3,
+            mirroring: ppu::MirrorMode::Horizontal,
+            four_screen: true,
+            battery: false,
+            trainer: false,
+            region: Region::PAL,
+            prg_ram_size: 0x800,
+        };
+        let rom = rom_with_header([0; HEADER_SIZE]).with_header(&header);
+        assert_eq!(rom.header(), header);
+        assert_eq!(rom.mirror_mode(), ppu::MirrorMode::FourScreen);
+        assert_eq!(rom.region(), Region::PAL);
+        assert_eq!(rom.prg_ram_size_bytes(), 0x800);
+        assert_eq!(rom.prg_nvram_size_bytes(), 0);
+    }
+
+    #[test]
+    fn test_dirty_header_ignores_junk() {
+        // "DiskDude!" starting at byte 7.
+        let mut header = [
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        header[7..16].copy_from_slice(b"DiskDude!");
+        let rom = rom_with_header(header);
+        assert_eq!(rom.header().mapper, 4);
+        assert_eq!(rom.header().region, Region::NTSC);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut data = rom_with_header([
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ])
+        .bytes()
+        .to_vec();
+        data[HEADER_SIZE + 16384..]
+            .iter_mut()
+            .for_each(|b| *b = 0x55);
+        let rom = ROM::from_bytes(data);
+
+        let fingerprint = rom.fingerprint();
+        assert_eq!(fingerprint.crc32, rom.crc32());
+        assert_eq!(fingerprint.prg_crc32, util::crc32(&[0xEA; 16384]));
+        assert_eq!(fingerprint.chr_sha1, util::sha1(&[0x55; 8192]));
+        assert_ne!(fingerprint.prg_sha1, fingerprint.sha1);
+
+        // Fixing the header leaves the fingerprint alone.
+        let fixed = rom.with_header(&rom.header());
+        assert_eq!(fixed.fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut header = [
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        assert_eq!(rom_with_header(header).diagnostics(), vec![]);
+
+        let mut data = rom_with_header(header).bytes().to_vec();
+        data.extend(vec![0; 100]);
+        assert_eq!(
+            ROM::from_bytes(data).diagnostics(),
+            vec![HeaderProblem::TrailingData { bytes: 100 }]
+        );
+
+        // Says 48KB of PRG and a trainer, neither of which is there.
+        header[4] = 3;
+        header[6] = 0x04;
+        assert_eq!(
+            rom_with_header(header).diagnostics(),
+            vec![
+                HeaderProblem::Truncated {
+                    expected: HEADER_SIZE + TRAINER_SIZE + 3 * 16384 + 8192,
+                    actual: HEADER_SIZE + 16384 + 8192,
+                },
+                HeaderProblem::OddPrgSize { banks: 3 },
+                HeaderProblem::Trainer,
+            ]
+        );
+
+        header[4] = 0;
+        header[6] = 0;
+        header[7..16].copy_from_slice(b"DiskDude!");
+        assert_eq!(
+            rom_with_header(header).diagnostics(),
+            vec![
+                HeaderProblem::TrailingData { bytes: 16384 },
+                HeaderProblem::NoPrgRom,
+                HeaderProblem::DirtyHeader,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_prg_ram() {
+        let mut header = [
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x02, 0x08, 0, 0, 0x70, 0, 0, 0, 0, 0,
+        ];
+        // Battery, but nothing to put in it.
+        let rom = rom_with_header(header);
+        assert_eq!(rom.prg_nvram_size_bytes(), 0x2000);
+        assert!(rom.has_battery());
+        assert_eq!(rom.default_prg_ram(), None);
+
+        // One misc ROM holding the default save.
+        header[14] = 1;
+        let mut data = rom_with_header(header).bytes().to_vec();
+        data.extend(vec![0x55; 0x100]);
+        let image = ROM::from_bytes(data).default_prg_ram().unwrap();
+        assert_eq!(image.len(), PRG_RAM_SIZE);
+        assert_eq!(image[0xFF], 0x55);
+        assert_eq!(image[0x100], 0x00);
+
+        // A trainer lands at $7000 and pushes PRG-ROM along.
+        let mut data = vec![
+            0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        data.extend(vec![0xAA; TRAINER_SIZE]);
+        data.extend(vec![0xEA; 16384 + 8192]);
+        let rom = ROM::from_bytes(data);
+        assert!(!rom.has_battery());
+        let image = rom.default_prg_ram().unwrap();
+        assert_eq!(image[TRAINER_OFFSET - 1], 0x00);
+        assert_eq!(image[TRAINER_OFFSET], 0xAA);
+        assert_eq!(rom.prg_rom().get(0), 0xEA);
     }
 }
@@ -0,0 +1,229 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::emulator::movie::{FrameInput, Movie};
+
+// A small text format for writing controller input by hand, for headless runs and tests where
+// recording a movie would be overkill.  One command per line:
+//
+//   # Boot, start the game, then walk right.
+//   frame 120: P1 START
+//   frame 180: P1 A+RIGHT for 10
+//   frame 240: RESET
+//   frame 600: WAIT
+//
+// Frames count from 0 at power-on.  Buttons are held for one frame unless `for` says otherwise,
+// and commands which overlap are combined.  P3 and P4 need a Four Score, so using them turns it
+// on.  WAIT presses nothing, but makes sure the script runs at least that long.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct InputScript {
+    frames: Vec<FrameInput>,
+    four_score: bool,
+}
+
+// In the controller's strobe order, so each button's bit is its index.
+const BUTTONS: [&str; 8] = ["A", "B", "SELECT", "START", "UP", "DOWN", "LEFT", "RIGHT"];
+
+enum Command {
+    Press(usize, u8),
+    Reset,
+    Wait,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<InputScript, String> {
+        let mut script = InputScript::default();
+        for (line_ix, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            script
+                .parse_line(line)
+                .map_err(|e| format!("Line {}: {}", line_ix + 1, e))?;
+        }
+        Ok(script)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let mut parts = line.splitn(2, ':');
+        let start = parts.next().unwrap_or("").trim();
+        let rest = match parts.next() {
+            None => return Err(String::from("expected 'frame <n>: <command>'")),
+            Some(rest) => rest,
+        };
+
+        let start = match start.split_whitespace().collect::<Vec<&str>>()[..] {
+            [keyword, n] if keyword.eq_ignore_ascii_case("frame") => n
+                .parse::<usize>()
+                .map_err(|_| format!("bad frame number '{}'", n))?,
+            _ => return Err(format!("expected 'frame <n>', got '{}'", start)),
+        };
+
+        let words: Vec<&str> = rest.split_whitespace().collect();
+        let (words, length) = match words[..] {
+            [ref command @ .., keyword, n] if keyword.eq_ignore_ascii_case("for") => {
+                let length = n
+                    .parse::<usize>()
+                    .map_err(|_| format!("bad frame count '{}'", n))?;
+                if length == 0 {
+                    return Err(String::from("can't hold for 0 frames"));
+                }
+                (command.to_vec(), length)
+            }
+            _ => (words, 1),
+        };
+
+        let command = match words[..] {
+            [word] if word.eq_ignore_ascii_case("reset") => Command::Reset,
+            [word] if word.eq_ignore_ascii_case("wait") => Command::Wait,
+            [player, buttons] => Command::Press(parse_player(player)?, parse_buttons(buttons)?),
+            _ => return Err(format!("unknown command '{}'", rest.trim())),
+        };
+
+        if length > 1 {
+            if let Command::Reset = command {
+                return Err(String::from("RESET can't be held"));
+            }
+        }
+
+        let end = start + length;
+        if self.frames.len() < end {
+            self.frames.resize(end, FrameInput::default());
+        }
+        let frames = &mut self.frames[start..end];
+        match command {
+            Command::Press(player, buttons) => {
+                if player >= 2 {
+                    self.four_score = true;
+                }
+                for frame in frames.iter_mut() {
+                    *joy_mut(frame, player) |= buttons;
+                }
+            }
+            Command::Reset => frames[0].reset = true,
+            Command::Wait => (),
+        }
+        Ok(())
+    }
+
+    // How many frames the script covers.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn four_score(&self) -> bool {
+        self.four_score
+    }
+
+    // Input for one frame.  Nothing is pressed after the script ends.
+    pub fn input(&self, frame: usize) -> FrameInput {
+        self.frames.get(frame).cloned().unwrap_or_default()
+    }
+
+    // As a movie, so it can be played back with a MovieSession.
+    pub fn to_movie(&self, rom_filename: &str, pal: bool) -> Movie {
+        let mut movie = Movie::new(rom_filename, pal);
+        movie.four_score = self.four_score;
+        movie.frames = self.frames.clone();
+        movie
+    }
+}
+
+fn parse_player(word: &str) -> Result<usize, String> {
+    match word.to_ascii_uppercase().as_str() {
+        "P1" => Ok(0),
+        "P2" => Ok(1),
+        "P3" => Ok(2),
+        "P4" => Ok(3),
+        _ => Err(format!("unknown player '{}'", word)),
+    }
+}
+
+fn parse_buttons(word: &str) -> Result<u8, String> {
+    let mut buttons = 0;
+    for name in word.split('+') {
+        let ix = BUTTONS
+            .iter()
+            .position(|button| button.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown button '{}'", name))?;
+        buttons |= 1 << ix;
+    }
+    Ok(buttons)
+}
+
+fn joy_mut(frame: &mut FrameInput, player: usize) -> &mut u8 {
+    match player {
+        0 => &mut frame.joy1,
+        1 => &mut frame.joy2,
+        2 => &mut frame.joy3,
+        _ => &mut frame.joy4,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = InputScript::parse(
+            "# Comments and blank lines are skipped.\n\
+             \n\
+             frame 2: P1 A+RIGHT for 3\n\
+             frame 3: p2 start  # Overlaps the first.\n\
+             frame 4: P1 B\n\
+             frame 6: RESET\n\
+             frame 9: WAIT\n",
+        )
+        .unwrap();
+
+        assert_eq!(script.len(), 10);
+        assert!(!script.four_score());
+        assert_eq!(script.input(0), FrameInput::default());
+        assert_eq!(script.input(2).joy1, 0x81);
+        assert_eq!(script.input(3).joy1, 0x81);
+        assert_eq!(script.input(3).joy2, 0x08);
+        assert_eq!(script.input(4).joy1, 0x83);
+        assert_eq!(script.input(5).joy1, 0x00);
+        assert!(script.input(6).reset);
+        assert!(!script.input(7).reset);
+        assert_eq!(script.input(100), FrameInput::default());
+    }
+
+    #[test]
+    fn test_four_score_players() {
+        let script = InputScript::parse("frame 0: P4 UP").unwrap();
+        assert!(script.four_score());
+        assert_eq!(script.input(0).joy4, 0x10);
+
+        let movie = script.to_movie("game.nes", false);
+        assert!(movie.four_score);
+        assert_eq!(movie.frames.len(), 1);
+    }
+
+    #[test]
+    fn test_rejects_bad_lines() {
+        let bad = [
+            "P1 A",
+            "frame x: P1 A",
+            "frame 1: P5 A",
+            "frame 1: P1 TURBO",
+            "frame 1: P1 A for 0",
+            "frame 1: RESET for 2",
+            "frame 1: JUMP",
+        ];
+        for line in bad.iter() {
+            assert!(InputScript::parse(line).is_err(), "{}", line);
+        }
+        assert_eq!(
+            InputScript::parse("\nframe 1: P1 A+Z"),
+            Err(String::from("Line 2: unknown button 'Z'"))
+        );
+    }
+}
@@ -0,0 +1,142 @@
+// Tracks which 8x8 tiles of the screen changed between two frames, so frontends which are slow to
+// draw (terminals, the network, wasm) can redraw just those.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+pub const TILES_WIDE: usize = 32;
+pub const TILES_HIGH: usize = 30;
+const TILE_SIZE: u32 = 8;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirtyTiles {
+    tiles: Vec<bool>,
+}
+
+// A region of the screen in pixels.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DirtyTiles {
+    pub fn clean() -> DirtyTiles {
+        DirtyTiles {
+            tiles: vec![false; TILES_WIDE * TILES_HIGH],
+        }
+    }
+
+    pub fn all() -> DirtyTiles {
+        DirtyTiles {
+            tiles: vec![true; TILES_WIDE * TILES_HIGH],
+        }
+    }
+
+    pub fn merge(&mut self, other: &DirtyTiles) {
+        for (tile, other) in self.tiles.iter_mut().zip(other.tiles.iter()) {
+            *tile |= *other;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for tile in self.tiles.iter_mut() {
+            *tile = false;
+        }
+    }
+
+    pub fn mark_pixel(&mut self, x: u32, y: u32) {
+        let tile_x = (x / TILE_SIZE) as usize;
+        let tile_y = (y / TILE_SIZE) as usize;
+        self.tiles[tile_x + tile_y * TILES_WIDE] = true;
+    }
+
+    pub fn is_dirty(&self, tile_x: usize, tile_y: usize) -> bool {
+        self.tiles[tile_x + tile_y * TILES_WIDE]
+    }
+
+    pub fn any(&self) -> bool {
+        self.tiles.iter().any(|dirty| *dirty)
+    }
+
+    // Rows of tiles with at least one change in them.
+    pub fn rows(&self) -> Vec<usize> {
+        (0..TILES_HIGH)
+            .filter(|ty| (0..TILES_WIDE).any(|tx| self.is_dirty(tx, *ty)))
+            .collect()
+    }
+
+    // Changed areas of the screen, with each run of dirty tiles along a tile row merged into one.
+    pub fn rects(&self) -> Vec<Rect> {
+        let mut rects = vec![];
+        for ty in 0..TILES_HIGH {
+            let mut tx = 0;
+            while tx < TILES_WIDE {
+                if !self.is_dirty(tx, ty) {
+                    tx += 1;
+                    continue;
+                }
+
+                let start = tx;
+                while tx < TILES_WIDE && self.is_dirty(tx, ty) {
+                    tx += 1;
+                }
+                rects.push(Rect {
+                    x: start as u32 * TILE_SIZE,
+                    y: ty as u32 * TILE_SIZE,
+                    width: (tx - start) as u32 * TILE_SIZE,
+                    height: TILE_SIZE,
+                });
+            }
+        }
+        rects
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rects_merge_runs() {
+        let mut dirty = DirtyTiles::clean();
+        assert!(!dirty.any());
+        assert_eq!(dirty.rects(), vec![]);
+
+        // Two adjacent tiles on row 0 and one on the far right of the last row.
+        dirty.mark_pixel(0, 0);
+        dirty.mark_pixel(15, 7);
+        dirty.mark_pixel(255, 239);
+
+        assert!(dirty.any());
+        assert!(dirty.is_dirty(1, 0));
+        assert!(!dirty.is_dirty(2, 0));
+        assert_eq!(dirty.rows(), vec![0, 29]);
+        assert_eq!(
+            dirty.rects(),
+            vec![
+                Rect {
+                    x: 0,
+                    y: 0,
+                    width: 16,
+                    height: 8,
+                },
+                Rect {
+                    x: 248,
+                    y: 232,
+                    width: 8,
+                    height: 8,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_all_is_one_rect_per_row() {
+        let rects = DirtyTiles::all().rects();
+        assert_eq!(rects.len(), TILES_HIGH);
+        assert!(rects.iter().all(|r| r.x == 0 && r.width == 256));
+    }
+}
@@ -1,6 +1,11 @@
-use std::cell::RefCell;
-use std::collections::VecDeque;
-use std::rc::Rc;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use serde::{Deserialize, Serialize};
 
 // Framework agnostic internal event types.
 
@@ -10,7 +15,7 @@ pub enum Event {
     KeyUp(Key),
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub enum Key {
     A,
     B,
@@ -49,6 +54,18 @@ pub enum Key {
     Num8,
     Num9,
     Num0,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
     Up,
     Down,
     Left,
@@ -1,45 +1,52 @@
+pub mod dirty;
 pub mod event;
 pub mod nop;
 pub mod palette;
 
-use std::collections::VecDeque;
-use std::f32::consts::PI;
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f32::consts::PI;
 
 use crate::emulator::apu;
 use crate::emulator::ppu;
-use crate::emulator::state::{SaveState, ScreenState};
-use crate::emulator::NES_APU_CLOCK_FACTOR;
+use crate::emulator::{Region, NES_APU_CLOCK_FACTOR};
+
+use self::dirty::DirtyTiles;
+use self::palette::Palette;
 
 pub trait Graphics {
     fn draw_screen(&mut self, pixel_data: &[u8]);
 }
 
 pub struct Screen {
-    scanline: u32,
-    dot: u32,
-    screen_buffer: [u8; 256 * 240 * 3],
-    backup_buffer: [u8; 256 * 240 * 3],
+    buffer: [u8; 256 * 240 * 3],
     double_buffering: bool,
+
+    // Only tracked when a frontend asks for it.  Everything changed since the frontend last
+    // rendered.
+    dirty_tracking: bool,
+    dirty: DirtyTiles,
+
+    palette: Palette,
+
+    // Frames left undrawn between each one drawn.  The PPU still runs them, only the conversion
+    // to RGB is skipped.
+    frame_skip: u32,
+    frames: u32,
 }
 
 impl ppu::VideoOut for Screen {
-    fn emit(&mut self, c: ppu::Colour) {
-        let x = self.dot;
-        let y = self.scanline;
-
-        let (r, g, b) = palette::convert_colour(c);
-
-        self.screen_buffer[((x + y * 256) * 3) as usize] = r;
-        self.screen_buffer[((x + y * 256) * 3 + 1) as usize] = g;
-        self.screen_buffer[((x + y * 256) * 3 + 2) as usize] = b;
-
-        self.dot = (self.dot + 1) % 256;
-        if self.dot == 0 {
-            self.scanline = (self.scanline + 1) % 240;
-            if self.scanline == 0 && self.double_buffering {
-                // Flip the buffer.s
-                std::mem::swap(&mut self.screen_buffer, &mut self.backup_buffer);
-            }
+    fn end_frame(&mut self, frame: &[ppu::Colour]) {
+        if self.double_buffering && !self.skipping() {
+            self.draw_rows(frame, 0, ppu::FRAME_HEIGHT);
+        }
+        self.frames = self.frames.wrapping_add(1);
+    }
+
+    fn end_scanline(&mut self, scanline: u16, frame: &[ppu::Colour]) {
+        if !self.double_buffering && !self.skipping() {
+            self.draw_rows(frame, scanline as usize, scanline as usize + 1);
         }
     }
 }
@@ -47,39 +54,68 @@ impl ppu::VideoOut for Screen {
 impl Screen {
     pub fn new() -> Screen {
         Screen {
-            scanline: 0,
-            dot: 0,
-            screen_buffer: [0; 256 * 240 * 3],
-            backup_buffer: [0; 256 * 240 * 3],
+            buffer: [0; 256 * 240 * 3],
             double_buffering: true,
+            dirty_tracking: false,
+            dirty: DirtyTiles::all(),
+            palette: Palette::default(),
+            frame_skip: 0,
+            frames: 0,
+        }
+    }
+
+    fn skipping(&self) -> bool {
+        !self.frames.is_multiple_of(self.frame_skip + 1)
+    }
+
+    fn draw_rows(&mut self, frame: &[ppu::Colour], from: usize, to: usize) {
+        for y in from..to {
+            for x in 0..ppu::FRAME_WIDTH {
+                let ix = x + y * ppu::FRAME_WIDTH;
+                let (r, g, b) = self.palette.convert(frame[ix]);
+                let pixel = &mut self.buffer[ix * 3..ix * 3 + 3];
+                if self.dirty_tracking && pixel != [r, g, b] {
+                    self.dirty.mark_pixel(x as u32, y as u32);
+                }
+                pixel.copy_from_slice(&[r, g, b]);
+            }
         }
     }
 
     pub fn do_render<F: FnOnce(&[u8]) -> ()>(&self, render: F) {
-        let buffer = if self.double_buffering {
-            &self.backup_buffer
-        } else {
-            &self.screen_buffer
-        };
-        render(buffer);
+        render(&self.buffer);
+    }
+
+    // Like `do_render`, but also passes the tiles which changed since the last call.  Dirty
+    // tracking must be turned on first, otherwise every tile is always reported as dirty.
+    pub fn do_render_dirty<F: FnOnce(&[u8], &DirtyTiles)>(&mut self, render: F) {
+        render(&self.buffer, &self.dirty);
+        if self.dirty_tracking {
+            self.dirty.clear();
+        }
     }
 
+    pub fn set_dirty_tracking(&mut self, on: bool) {
+        // The frontend has nothing drawn yet, so start off with a full redraw.
+        self.dirty_tracking = on;
+        self.dirty = DirtyTiles::all();
+    }
+
+    // With double buffering off, the picture is updated as each scanline is drawn rather than
+    // once a frame is complete, so a slowed-down frame can be watched being drawn.
     pub fn set_double_buffering(&mut self, on: bool) {
         self.double_buffering = on;
     }
-}
 
-impl<'de> SaveState<'de, ScreenState> for Screen {
-    fn freeze(&mut self) -> ScreenState {
-        ScreenState {
-            scanline: self.scanline,
-            dot: self.dot,
-        }
+    // Draws only every `skip + 1`th frame, for running faster than the display can show.
+    pub fn set_frame_skip(&mut self, skip: u32) {
+        self.frame_skip = skip;
+        self.frames = 0;
     }
 
-    fn hydrate(&mut self, state: ScreenState) {
-        self.scanline = state.scanline;
-        self.dot = state.dot;
+    // Takes effect from the next scanline drawn.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
     }
 }
 
@@ -91,11 +127,16 @@ pub struct SimpleAudioOut {
     high_pass_filter_1: HighPassFilter,
     high_pass_filter_2: HighPassFilter,
     enabled: bool,
+    apu_clock_factor: u32,
+    speedup: u32,
 }
 
 impl SimpleAudioOut {
     const APU_CLOCK: f32 = 1_789_772.0 / 2.0;
 
+    // Samples faded in and out at each join when running fast.  Under 2ms at common rates.
+    const FADE_SAMPLES: usize = 64;
+
     pub fn new(sample_rate: f32) -> SimpleAudioOut {
         SimpleAudioOut {
             buffer: Vec::new(),
@@ -326,6 +367,8 @@ impl SimpleAudioOut {
             high_pass_filter_1: HighPassFilter::new(440.0, sample_rate),
             high_pass_filter_2: HighPassFilter::new(90.0, sample_rate),
             enabled: true,
+            apu_clock_factor: NES_APU_CLOCK_FACTOR,
+            speedup: 1,
         }
     }
 
@@ -344,23 +387,45 @@ impl SimpleAudioOut {
 
         let mut buf = Vec::with_capacity(num_samples as usize);
 
-        // Need to downsample all the samples we collected this frame.
+        // Running faster than real time, there's more audio than time to play it in.  Rather than
+        // squeezing it all in and pitching up, only the latest stretch is played and the rest is
+        // dropped.
+        let speedup = self.speedup.max(1) as usize;
         let total = self.buffer.len();
-        let apu_cycles = master_cycles / (NES_APU_CLOCK_FACTOR as u64);
+        let start = total - total / speedup;
+        let master_cycles = master_cycles / speedup as u64;
+
+        // Need to downsample all the samples we collected this frame.
+        let apu_cycles = master_cycles / (self.apu_clock_factor as u64);
         let step = (apu_cycles as f64) / (num_samples as f64);
 
         let mut counter = 0.0;
-        for ix in 0..total {
+        for ix in start..total {
             self.fir_filter.shift(self.buffer[ix]);
 
             counter += 1.0;
             if counter >= step {
                 counter -= step;
+                // The console's own output stage blocks DC.  Without this, a game stepping the
+                // DMC level with $4011 writes moves the whole waveform and pops.
                 let sample = self.fir_filter.compute();
+                let sample = self.high_pass_filter_1.process(sample);
+                let sample = self.high_pass_filter_2.process(sample);
                 buf.push(sample);
             }
         }
 
+        // Stretches don't join up, so fade each one in and out rather than clicking.
+        if speedup > 1 {
+            let fade = SimpleAudioOut::FADE_SAMPLES.min(buf.len() / 2);
+            for ix in 0..fade {
+                let gain = ix as f32 / fade as f32;
+                let len = buf.len();
+                buf[ix] *= gain;
+                buf[len - 1 - ix] *= gain;
+            }
+        }
+
         consume(&buf);
         self.buffer.clear();
     }
@@ -369,6 +434,16 @@ impl SimpleAudioOut {
         self.enabled = enabled;
     }
 
+    // How many times faster than real time the emulator is running, rounded to a whole number.
+    pub fn set_speedup(&mut self, speedup: u32) {
+        self.speedup = speedup;
+    }
+
+    // The APU runs at a different rate relative to the master clock on PAL consoles.
+    pub fn set_region(&mut self, region: Region) {
+        self.apu_clock_factor = region.apu_clock_factor();
+    }
+
     fn queue_sample(&mut self, sample: f32) {
         self.buffer.push(sample);
     }
@@ -465,3 +540,61 @@ impl FIRFilter {
         sample
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::emulator::apu::AudioOut;
+    use crate::emulator::ppu::VideoOut;
+
+    #[test]
+    fn test_frame_skip() {
+        let mut screen = Screen::new();
+        screen.set_frame_skip(2);
+        for frame in 0..6 {
+            let mut colour = ppu::Colour::default();
+            colour.em_r = frame % 2 == 1;
+            screen.end_frame(&[colour; ppu::FRAME_WIDTH * ppu::FRAME_HEIGHT]);
+
+            // Only frames 0 and 3 are drawn.
+            colour.em_r = frame >= 3;
+            let (r, g, b) = Palette::default().convert(colour);
+            screen.do_render(|data| assert_eq!(data[..3], [r, g, b]));
+        }
+    }
+
+    // Feeds in a 1kHz square wave for the given number of frames, and returns what comes out.
+    fn play(audio: &mut SimpleAudioOut, frames: u64) -> Vec<f32> {
+        let master_cycles = frames * 357_366;
+        for ix in 0..master_cycles / u64::from(NES_APU_CLOCK_FACTOR) {
+            audio.emit(if ix % 895 < 447 { 0.5 } else { -0.5 });
+        }
+        let mut samples = vec![];
+        audio.consume(master_cycles, 735, |data| samples = data.to_vec());
+        samples
+    }
+
+    fn zero_crossings(samples: &[f32]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count()
+    }
+
+    #[test]
+    fn test_speedup_keeps_pitch() {
+        let mut audio = SimpleAudioOut::new(44_100.0);
+        let normal = play(&mut audio, 1);
+
+        // Four frames' worth of audio, played back in the time of one.
+        audio.set_speedup(4);
+        let fast = play(&mut audio, 4);
+        assert!((fast.len() as i64 - normal.len() as i64).abs() <= 1);
+        let crossings = zero_crossings(&normal) as i64;
+        assert!((zero_crossings(&fast) as i64 - crossings).abs() <= 2);
+
+        // Faded in and out at the ends.
+        assert_eq!(fast[0], 0.0);
+        assert_eq!(fast[fast.len() - 1], 0.0);
+    }
+}
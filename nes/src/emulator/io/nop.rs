@@ -1,7 +1,14 @@
 use crate::emulator::apu::AudioOut;
+use crate::emulator::ppu::{Colour, VideoOut};
 
 pub struct DummyAudio;
 
 impl AudioOut for DummyAudio {
     fn emit(&mut self, _sample: f32) {}
 }
+
+pub struct DummyVideo;
+
+impl VideoOut for DummyVideo {
+    fn end_frame(&mut self, _frame: &[Colour]) {}
+}
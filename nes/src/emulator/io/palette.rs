@@ -1,3 +1,14 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
 use crate::emulator::ppu::Colour;
 
 // Palette generated by https://bisqwit.iki.fi/utils/nespalette.php
@@ -101,18 +112,247 @@ pub const PALETTE: [u8; 1536] = [
 ];
 
 pub fn convert_colour(c: Colour) -> (u8, u8, u8) {
-    let mut byte = c.as_byte() as usize;
+    let ix = palette_index(c) * 3;
+    (PALETTE[ix], PALETTE[ix + 1], PALETTE[ix + 2])
+}
+
+// Built-in palettes.  The colour-blind variants are the standard palette daltonised for each kind
+// of colour blindness: colours which would look alike are pushed apart into channels that can
+// still be told apart.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PaletteKind {
+    #[default]
+    Standard,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+    Greyscale,
+}
+
+impl PaletteKind {
+    pub const ALL: [PaletteKind; 5] = [
+        PaletteKind::Standard,
+        PaletteKind::Protanopia,
+        PaletteKind::Deuteranopia,
+        PaletteKind::Tritanopia,
+        PaletteKind::Greyscale,
+    ];
+
+    pub fn next(self) -> PaletteKind {
+        let ix = PaletteKind::ALL
+            .iter()
+            .position(|k| *k == self)
+            .unwrap_or(0);
+        PaletteKind::ALL[(ix + 1) % PaletteKind::ALL.len()]
+    }
+}
+
+// RGB for every colour the PPU can output, including all the emphasis combinations.
+#[derive(Clone)]
+pub struct Palette {
+    rgb: Vec<u8>,
+}
+
+impl Palette {
+    pub fn new(kind: PaletteKind) -> Palette {
+        Palette {
+            rgb: PALETTE.to_vec(),
+        }
+        .filtered(kind)
+    }
+
+    // Loads a .pal file: raw RGB triples for either the 64 basic colours, or all 512 including
+    // emphasis.  Emphasis for a 64 colour file is approximated by dimming the other channels.
+    #[cfg(feature = "std")]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Palette, String> {
+        let bytes = fs::read(&path).map_err(|e| e.to_string())?;
+        Palette::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Palette, String> {
+        let rgb = match bytes.len() {
+            BASIC_PAL_SIZE => (0..PALETTE.len() / 3)
+                .flat_map(|ix| {
+                    let base = &bytes[(ix % 64) * 3..(ix % 64) * 3 + 3];
+                    emphasise(base, ix >> 6)
+                })
+                .collect(),
+            FULL_PAL_SIZE => bytes.to_vec(),
+            len => {
+                return Err(format!(
+                    "Palette should be {} or {} bytes, not {}",
+                    BASIC_PAL_SIZE, FULL_PAL_SIZE, len
+                ))
+            }
+        };
+        Ok(Palette { rgb })
+    }
+
+    // The same palette adjusted for one of the built-in kinds, e.g. for colour blindness.
+    pub fn filtered(&self, kind: PaletteKind) -> Palette {
+        let rgb = self
+            .rgb
+            .chunks(3)
+            .flat_map(|c| {
+                let (r, g, b) = match kind {
+                    PaletteKind::Standard => (c[0], c[1], c[2]),
+                    PaletteKind::Greyscale => {
+                        let luma = 0.299 * c[0] as f32 + 0.587 * c[1] as f32 + 0.114 * c[2] as f32;
+                        let luma = libm::roundf(luma) as u8;
+                        (luma, luma, luma)
+                    }
+                    _ => daltonise(kind, c[0], c[1], c[2]),
+                };
+                vec![r, g, b]
+            })
+            .collect();
+        Palette { rgb }
+    }
+
+    pub fn convert(&self, c: Colour) -> (u8, u8, u8) {
+        let ix = palette_index(c) * 3;
+        (self.rgb[ix], self.rgb[ix + 1], self.rgb[ix + 2])
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new(PaletteKind::Standard)
+    }
+}
+
+const BASIC_PAL_SIZE: usize = 64 * 3;
+const FULL_PAL_SIZE: usize = 512 * 3;
+
+// How much emphasis dims the channels it doesn't emphasise.
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+// `emphasis` is the three emphasis bits, red lowest, as in `palette_index`.
+fn emphasise(rgb: &[u8], emphasis: usize) -> Vec<u8> {
+    if emphasis == 0 {
+        return rgb.to_vec();
+    }
+    rgb.iter()
+        .enumerate()
+        .map(|(channel, value)| {
+            if emphasis & (1 << channel) != 0 {
+                *value
+            } else {
+                libm::roundf(*value as f32 * EMPHASIS_ATTENUATION) as u8
+            }
+        })
+        .collect()
+}
+
+// Index into a 512 colour palette: the 64 colours, then the same again for each emphasis setting.
+fn palette_index(c: Colour) -> usize {
+    let mut ix = c.as_byte() as usize;
     if c.em_r {
-        byte |= 0x40
+        ix |= 0x40
     };
     if c.em_g {
-        byte |= 0x80
+        ix |= 0x80
     };
     if c.em_b {
-        byte |= 0x100
+        ix |= 0x100
+    };
+    ix
+}
+
+// Fidaner, Lin and Ozguven's method.  Simulate what the viewer sees in LMS space, then spread the
+// difference from the original over the green and blue channels.
+fn daltonise(kind: PaletteKind, r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+
+    let l = 17.8824 * r + 43.5161 * g + 4.11935 * b;
+    let m = 3.45565 * r + 27.1554 * g + 3.86714 * b;
+    let s = 0.0299566 * r + 0.184309 * g + 1.46709 * b;
+
+    let (l, m, s) = match kind {
+        PaletteKind::Protanopia => (2.02344 * m - 2.52581 * s, m, s),
+        PaletteKind::Deuteranopia => (l, 0.494207 * l + 1.24827 * s, s),
+        PaletteKind::Tritanopia => (l, m, -0.395913 * l + 0.801109 * m),
+        _ => (l, m, s),
     };
-    let r = PALETTE[byte * 3];
-    let g = PALETTE[byte * 3 + 1];
-    let b = PALETTE[byte * 3 + 2];
-    (r, g, b)
+
+    let sim_r = 0.08094445 * l - 0.1305044 * m + 0.1167211 * s;
+    let sim_g = -0.01024853 * l + 0.05401933 * m - 0.1136147 * s;
+    let sim_b = -0.0003652969 * l - 0.004121615 * m + 0.6935114 * s;
+
+    let (err_r, err_g, err_b) = (r - sim_r, g - sim_g, b - sim_b);
+    let to_byte = |v: f32| libm::roundf(v).clamp(0.0, 255.0) as u8;
+    (
+        to_byte(r),
+        to_byte(g + 0.7 * err_r + err_g),
+        to_byte(b + 0.7 * err_r + err_b),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(palette: &Palette, ix: usize) -> (u8, u8, u8) {
+        (
+            palette.rgb[ix * 3],
+            palette.rgb[ix * 3 + 1],
+            palette.rgb[ix * 3 + 2],
+        )
+    }
+
+    #[test]
+    fn test_standard_matches_table() {
+        assert_eq!(Palette::default().rgb, PALETTE.to_vec());
+    }
+
+    #[test]
+    fn test_alternate_palettes() {
+        let (r, g, b) = entry(&Palette::new(PaletteKind::Greyscale), 0x16);
+        assert!(r == g && g == b);
+
+        let red = (
+            PALETTE[0x16 * 3],
+            PALETTE[0x16 * 3 + 1],
+            PALETTE[0x16 * 3 + 2],
+        );
+        for kind in [
+            PaletteKind::Protanopia,
+            PaletteKind::Deuteranopia,
+            PaletteKind::Tritanopia,
+        ]
+        .iter()
+        {
+            let palette = Palette::new(*kind);
+            // Greys look the same to everyone, so they barely move.
+            let (r, g, b) = entry(&palette, 0x00);
+            assert!((g as i32 - r as i32).abs() <= 2 && (b as i32 - r as i32).abs() <= 2);
+            // Colours which get confused are shifted.
+            assert_ne!(entry(&palette, 0x16), red);
+        }
+
+        assert_eq!(PaletteKind::Greyscale.next(), PaletteKind::Standard);
+    }
+
+    #[test]
+    fn test_full_pal_file() {
+        let palette = Palette::from_bytes(&PALETTE).unwrap();
+        assert_eq!(palette.rgb, PALETTE.to_vec());
+    }
+
+    #[test]
+    fn test_basic_pal_file() {
+        let mut bytes = vec![0; 64 * 3];
+        bytes[0x16 * 3..0x16 * 3 + 3].copy_from_slice(&[200, 100, 50]);
+        let palette = Palette::from_bytes(&bytes).unwrap();
+        assert_eq!(entry(&palette, 0x16), (200, 100, 50));
+        // Red emphasis keeps red and dims the rest.
+        assert_eq!(entry(&palette, 0x56), (200, 82, 41));
+        // With all three, nothing is dimmed.
+        assert_eq!(entry(&palette, 0x1D6), (200, 100, 50));
+    }
+
+    #[test]
+    fn test_bad_pal_file() {
+        assert!(Palette::from_bytes(&[0; 100]).is_err());
+    }
 }
@@ -11,6 +11,7 @@ pub struct AXROM {
     chr_mem: Memory,
     mirror_mode: MirrorMode,
     prg_bank: u8,
+    bank_switched: bool,
 }
 
 impl AXROM {
@@ -20,8 +21,15 @@ impl AXROM {
             chr_mem,
             mirror_mode: MirrorMode::SingleLower,
             prg_bank: 0,
+            bank_switched: false,
         }
     }
+
+    fn prg_address(&self, address: u16) -> usize {
+        let base = (self.prg_bank as usize) << 15;
+        let rel = (address & 0x7FFF) as usize;
+        (base | rel) % self.prg_rom.len()
+    }
 }
 
 impl Mapper for AXROM {
@@ -34,9 +42,11 @@ impl Mapper for AXROM {
     }
 
     fn read_prg(&mut self, address: u16) -> u8 {
-        let base = (self.prg_bank as usize) << 15;
-        let rel = (address & 0x7FFF) as usize;
-        self.prg_rom.get((base | rel) % self.prg_rom.len())
+        self.prg_rom.get(self.prg_address(address))
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_address(address))
     }
 
     fn write_prg(&mut self, _address: u16, byte: u8) {
@@ -46,11 +56,16 @@ impl Mapper for AXROM {
         } else {
             MirrorMode::SingleUpper
         };
+        self.bank_switched = true;
     }
 
     fn mirror_mode(&self) -> MirrorMode {
         self.mirror_mode
     }
+
+    fn take_bank_switch(&mut self) -> bool {
+        core::mem::replace(&mut self.bank_switched, false)
+    }
 }
 
 impl<'de> SaveState<'de, MapperState> for AXROM {
@@ -1,4 +1,4 @@
-use crate::emulator::memory::{Mapper, Memory};
+use crate::emulator::memory::{ChrBanks, Mapper, Memory};
 use crate::emulator::ppu::MirrorMode;
 use crate::emulator::state::{CNROMState, MapperState, SaveState};
 
@@ -10,6 +10,7 @@ pub struct CNROM {
     chr_mem: Memory,
     mirror_mode: MirrorMode,
     chr_bank: u8,
+    bank_switched: bool,
 }
 
 impl CNROM {
@@ -19,8 +20,13 @@ impl CNROM {
             chr_mem,
             mirror_mode,
             chr_bank: 0,
+            bank_switched: false,
         }
     }
+
+    fn prg_address(&self, address: u16) -> usize {
+        (address - 0x8000) as usize % self.prg_rom.len()
+    }
 }
 
 impl Mapper for CNROM {
@@ -34,17 +40,38 @@ impl Mapper for CNROM {
     }
 
     fn read_prg(&mut self, address: u16) -> u8 {
-        self.prg_rom
-            .get(((address - 0x8000) % self.prg_rom.len() as u16) as usize)
+        self.prg_rom.get(self.prg_address(address))
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_address(address))
     }
 
     fn write_prg(&mut self, _address: u16, byte: u8) {
         self.chr_bank = byte & 0x03;
+        self.bank_switched = true;
     }
 
     fn mirror_mode(&self) -> MirrorMode {
         self.mirror_mode
     }
+
+    fn take_bank_switch(&mut self) -> bool {
+        core::mem::replace(&mut self.bank_switched, false)
+    }
+
+    fn chr_banks(&self) -> Option<ChrBanks> {
+        let first = ((self.chr_bank as usize) << 3) % (self.chr_mem.len() / 0x400).max(1);
+        let mut banks = [0; 8];
+        for (ix, bank) in banks.iter_mut().enumerate() {
+            *bank = first + ix;
+        }
+        Some(banks)
+    }
+
+    fn peek_chr(&self, offset: usize) -> u8 {
+        self.chr_mem.get(offset % self.chr_mem.len())
+    }
 }
 
 impl<'de> SaveState<'de, MapperState> for CNROM {
@@ -1,4 +1,4 @@
-use crate::emulator::memory::{Mapper, Memory};
+use crate::emulator::memory::{ChrBanks, Mapper, Memory};
 use crate::emulator::ppu::MirrorMode;
 use crate::emulator::state::{ColorDreamsState, MapperState, SaveState};
 
@@ -11,6 +11,7 @@ pub struct ColorDreams {
     prg_bank: u8,
     chr_bank: u8,
     mirror_mode: MirrorMode,
+    bank_switched: bool,
 }
 
 impl ColorDreams {
@@ -21,8 +22,15 @@ impl ColorDreams {
             prg_bank: 0,
             chr_bank: 0,
             mirror_mode,
+            bank_switched: false,
         }
     }
+
+    fn prg_address(&self, address: u16) -> usize {
+        let base = (self.prg_bank as usize) << 15;
+        let offset = (address & 0x7FFF) as usize;
+        (base | offset) % self.prg_rom.len()
+    }
 }
 
 impl Mapper for ColorDreams {
@@ -37,19 +45,39 @@ impl Mapper for ColorDreams {
     }
 
     fn read_prg(&mut self, address: u16) -> u8 {
-        let base = (self.prg_bank as usize) << 15;
-        let offset = (address & 0x7FFF) as usize;
-        self.prg_rom.get(base | offset)
+        self.prg_rom.get(self.prg_address(address))
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_address(address))
     }
 
     fn write_prg(&mut self, _address: u16, byte: u8) {
         self.prg_bank = byte & 0x3;
         self.chr_bank = (byte & 0xF0) >> 4;
+        self.bank_switched = true;
     }
 
     fn mirror_mode(&self) -> MirrorMode {
         self.mirror_mode
     }
+
+    fn take_bank_switch(&mut self) -> bool {
+        core::mem::replace(&mut self.bank_switched, false)
+    }
+
+    fn chr_banks(&self) -> Option<ChrBanks> {
+        let first = ((self.chr_bank as usize) << 3) % (self.chr_mem.len() / 0x400).max(1);
+        let mut banks = [0; 8];
+        for (ix, bank) in banks.iter_mut().enumerate() {
+            *bank = first + ix;
+        }
+        Some(banks)
+    }
+
+    fn peek_chr(&self, offset: usize) -> u8 {
+        self.chr_mem.get(offset % self.chr_mem.len())
+    }
 }
 
 impl<'de> SaveState<'de, MapperState> for ColorDreams {
@@ -0,0 +1,103 @@
+use crate::emulator::memory::{ChrBanks, Mapper, Memory};
+use crate::emulator::ppu::MirrorMode;
+use crate::emulator::state::{GXROMState, MapperState, SaveState};
+
+// iNES Mapper 66: GxROM
+// Up to 4 switchable 32kb PRG ROM banks.
+// Up to 4 switchable 8kb CHR ROM banks.
+// Both are selected by a single register at $8000-$FFFF: --PP--CC.
+pub struct GXROM {
+    prg_rom: Memory,
+    chr_mem: Memory,
+    mirror_mode: MirrorMode,
+    prg_bank: u8,
+    chr_bank: u8,
+    bank_switched: bool,
+}
+
+impl GXROM {
+    pub fn new(prg_rom: Memory, chr_mem: Memory, mirror_mode: MirrorMode) -> GXROM {
+        GXROM {
+            prg_rom,
+            chr_mem,
+            mirror_mode,
+            prg_bank: 0,
+            chr_bank: 0,
+            bank_switched: false,
+        }
+    }
+
+    fn prg_address(&self, address: u16) -> usize {
+        let base = (self.prg_bank as usize) << 15;
+        let offset = (address & 0x7FFF) as usize;
+        (base | offset) % self.prg_rom.len()
+    }
+}
+
+impl Mapper for GXROM {
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let base = (self.chr_bank as usize) << 13;
+        let offset = (address & 0x1FFF) as usize;
+        self.chr_mem.get((base | offset) % self.chr_mem.len())
+    }
+
+    fn write_chr(&mut self, address: u16, byte: u8) {
+        self.chr_mem.put(address as usize, byte);
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.prg_rom.get(self.prg_address(address))
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_address(address))
+    }
+
+    fn write_prg(&mut self, _address: u16, byte: u8) {
+        self.prg_bank = (byte & 0x30) >> 4;
+        self.chr_bank = byte & 0x03;
+        self.bank_switched = true;
+    }
+
+    fn mirror_mode(&self) -> MirrorMode {
+        self.mirror_mode
+    }
+
+    fn take_bank_switch(&mut self) -> bool {
+        core::mem::replace(&mut self.bank_switched, false)
+    }
+
+    fn chr_banks(&self) -> Option<ChrBanks> {
+        let first = ((self.chr_bank as usize) << 3) % (self.chr_mem.len() / 0x400).max(1);
+        let mut banks = [0; 8];
+        for (ix, bank) in banks.iter_mut().enumerate() {
+            *bank = first + ix;
+        }
+        Some(banks)
+    }
+
+    fn peek_chr(&self, offset: usize) -> u8 {
+        self.chr_mem.get(offset % self.chr_mem.len())
+    }
+}
+
+impl<'de> SaveState<'de, MapperState> for GXROM {
+    fn freeze(&mut self) -> MapperState {
+        MapperState::GXROM(GXROMState {
+            prg_bank: self.prg_bank,
+            chr_bank: self.chr_bank,
+            chr_mem: self.chr_mem.freeze(),
+        })
+    }
+
+    fn hydrate(&mut self, state: MapperState) {
+        match state {
+            MapperState::GXROM(s) => {
+                self.prg_bank = s.prg_bank;
+                self.chr_bank = s.chr_bank;
+                self.chr_mem.hydrate(s.chr_mem);
+            }
+            _ => panic!("Incompatible mapper state for GXROM mapper: {:?}", state),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::emulator::memory::{Mapper, Memory};
+use crate::emulator::memory::{ChrBanks, Mapper, Memory};
 use crate::emulator::ppu::MirrorMode;
 use crate::emulator::state::{MMC1State, MapperState, SaveState};
 
@@ -20,6 +20,8 @@ pub struct MMC1 {
 
     prg_offsets: [u32; 2],
     chr_offsets: [u32; 2],
+
+    bank_switched: bool,
 }
 
 impl MMC1 {
@@ -48,6 +50,7 @@ impl MMC1 {
             chr_bank_2: 0,
             prg_offsets: [0; 2],
             chr_offsets: [0; 2],
+            bank_switched: false,
         };
         mapper.update_offsets();
         //mapper.prg_offsets[1] = mapper.prg_offset((mapper.prg_rom.len() as u32) / 0x4000 - 1);
@@ -91,6 +94,13 @@ impl MMC1 {
     fn chr_offset(&self, index: u32) -> u32 {
         (index % ((self.chr_mem.len() as u32) / 0x1000)) * 0x1000
     }
+
+    fn prg_address(&self, address: u16) -> usize {
+        let rel = address - 0x8000;
+        let bank = rel / 0x4000;
+        let offset = rel % 0x4000;
+        (self.prg_offsets[bank as usize] + (offset as u32)) as usize
+    }
 }
 
 impl Mapper for MMC1 {
@@ -113,11 +123,11 @@ impl Mapper for MMC1 {
     }
 
     fn read_prg(&mut self, address: u16) -> u8 {
-        let rel = address - 0x8000;
-        let bank = rel / 0x4000;
-        let offset = rel % 0x4000;
-        let final_addr = self.prg_offsets[bank as usize] + (offset as u32);
-        self.prg_rom.get(final_addr as usize)
+        self.prg_rom.get(self.prg_address(address))
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_address(address))
     }
 
     fn write_prg(&mut self, address: u16, byte: u8) {
@@ -152,6 +162,7 @@ impl Mapper for MMC1 {
             self.load_register = 0;
             self.write_index = 0;
             self.update_offsets();
+            self.bank_switched = true;
         }
     }
 
@@ -164,6 +175,22 @@ impl Mapper for MMC1 {
             _ => panic!("Unexpected mirror control: 0b{:b}", self.control),
         }
     }
+
+    fn take_bank_switch(&mut self) -> bool {
+        core::mem::replace(&mut self.bank_switched, false)
+    }
+
+    fn chr_banks(&self) -> Option<ChrBanks> {
+        let mut banks = [0; 8];
+        for (ix, bank) in banks.iter_mut().enumerate() {
+            *bank = self.chr_offsets[ix / 4] as usize / 0x400 + ix % 4;
+        }
+        Some(banks)
+    }
+
+    fn peek_chr(&self, offset: usize) -> u8 {
+        self.chr_mem.get(offset % self.chr_mem.len())
+    }
 }
 
 impl<'de> SaveState<'de, MapperState> for MMC1 {
@@ -0,0 +1,173 @@
+use crate::emulator::memory::{ChrBanks, Mapper, Memory};
+use crate::emulator::ppu::MirrorMode;
+use crate::emulator::state::{MMC2State, MapperState, SaveState};
+
+// iNES Mappers 9 and 10: MMC2 (Punch-Out!!) and MMC4 (Fire Emblem).
+// Each half of the pattern tables has two 4kb CHR banks registers, and a latch which picks
+// between them.  The latch flips when the PPU fetches tile $FD or $FE from that half, so a game
+// can switch banks part way through a scanline by placing those tiles.
+//   - $A000-$AFFF: PRG bank.  MMC2 switches 8kb at $8000, MMC4 16kb at $8000.
+//   - $B000-$BFFF, $C000-$CFFF: CHR bank for $0000 when the latch holds $FD, $FE.
+//   - $D000-$DFFF, $E000-$EFFF: CHR bank for $1000 when the latch holds $FD, $FE.
+//   - $F000-$FFFF: Mirroring, 0 = vertical, 1 = horizontal.
+// The rest of PRG ROM is fixed to the last banks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MMC2Chip {
+    MMC2,
+    MMC4,
+}
+
+const LATCH_FD: u8 = 0xFD;
+const LATCH_FE: u8 = 0xFE;
+
+pub struct MMC2 {
+    chip: MMC2Chip,
+    prg_rom: Memory,
+    chr_mem: Memory,
+    mirror_mode: MirrorMode,
+    prg_bank: u8,
+    // Indexed by pattern table, then latch: [$0000 FD, $0000 FE], [$1000 FD, $1000 FE].
+    chr_banks: [[u8; 2]; 2],
+    latches: [u8; 2],
+    bank_switched: bool,
+}
+
+impl MMC2 {
+    pub fn new(chip: MMC2Chip, prg_rom: Memory, chr_mem: Memory, mirror_mode: MirrorMode) -> MMC2 {
+        MMC2 {
+            chip,
+            prg_rom,
+            chr_mem,
+            mirror_mode,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [LATCH_FE; 2],
+            bank_switched: false,
+        }
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let table = ((address >> 12) & 1) as usize;
+        let latch = (self.latches[table] - LATCH_FD) as usize;
+        let base = (self.chr_banks[table][latch] as usize) << 12;
+        (base | (address & 0x0FFF) as usize) % self.chr_mem.len()
+    }
+
+    // The fixed banks are the last ones in PRG ROM.
+    fn prg_offset(&self, address: u16) -> usize {
+        let len = self.prg_rom.len();
+        let offset = match (self.chip, address) {
+            (MMC2Chip::MMC2, 0x8000..=0x9FFF) => {
+                ((self.prg_bank as usize) << 13) | (address & 0x1FFF) as usize
+            }
+            (MMC2Chip::MMC2, _) => len - 0x6000 + (address - 0xA000) as usize,
+            (MMC2Chip::MMC4, 0x8000..=0xBFFF) => {
+                ((self.prg_bank as usize) << 14) | (address & 0x3FFF) as usize
+            }
+            (MMC2Chip::MMC4, _) => len - 0x4000 + (address - 0xC000) as usize,
+        };
+        offset % len
+    }
+}
+
+impl Mapper for MMC2 {
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.chr_mem.get(self.chr_offset(address))
+    }
+
+    fn write_chr(&mut self, address: u16, byte: u8) {
+        let offset = self.chr_offset(address);
+        self.chr_mem.put(offset, byte);
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.prg_rom.get(self.prg_offset(address))
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_offset(address))
+    }
+
+    fn write_prg(&mut self, address: u16, byte: u8) {
+        match address & 0xF000 {
+            0xA000 => self.prg_bank = byte & 0x0F,
+            0xB000 => self.chr_banks[0][0] = byte & 0x1F,
+            0xC000 => self.chr_banks[0][1] = byte & 0x1F,
+            0xD000 => self.chr_banks[1][0] = byte & 0x1F,
+            0xE000 => self.chr_banks[1][1] = byte & 0x1F,
+            0xF000 => {
+                self.mirror_mode = if byte & 1 == 0 {
+                    MirrorMode::Vertical
+                } else {
+                    MirrorMode::Horizontal
+                };
+                return;
+            }
+            _ => return,
+        }
+        self.bank_switched = true;
+    }
+
+    fn mirror_mode(&self) -> MirrorMode {
+        self.mirror_mode
+    }
+
+    // The latch only changes once the fetch is done, so the tile which flips it is still drawn
+    // from the old bank.  MMC2 only watches the exact address of the $0FD8/$0FE8 fetches in the
+    // lower table, where MMC4 watches all eight rows as it does for the upper.
+    fn pattern_fetched(&mut self, address: u16) {
+        let table = ((address >> 12) & 1) as usize;
+        let exact = self.chip == MMC2Chip::MMC2 && table == 0;
+        let row_mask = if exact { 0x0FFF } else { 0x0FF8 };
+        let latch = match address & row_mask {
+            0x0FD8 => LATCH_FD,
+            0x0FE8 => LATCH_FE,
+            _ => return,
+        };
+        if self.latches[table] != latch {
+            self.latches[table] = latch;
+            self.bank_switched = true;
+        }
+    }
+
+    fn take_bank_switch(&mut self) -> bool {
+        core::mem::replace(&mut self.bank_switched, false)
+    }
+
+    fn chr_banks(&self) -> Option<ChrBanks> {
+        let mut banks = [0; 8];
+        for (ix, bank) in banks.iter_mut().enumerate() {
+            *bank = self.chr_offset((ix as u16) << 10) / 0x400;
+        }
+        Some(banks)
+    }
+
+    fn peek_chr(&self, offset: usize) -> u8 {
+        self.chr_mem.get(offset % self.chr_mem.len())
+    }
+}
+
+impl<'de> SaveState<'de, MapperState> for MMC2 {
+    fn freeze(&mut self) -> MapperState {
+        MapperState::MMC2(MMC2State {
+            prg_bank: self.prg_bank,
+            chr_banks: self.chr_banks,
+            latches: self.latches,
+            mirror_mode: self.mirror_mode,
+            chr_mem: self.chr_mem.freeze(),
+        })
+    }
+
+    fn hydrate(&mut self, state: MapperState) {
+        match state {
+            MapperState::MMC2(s) => {
+                self.prg_bank = s.prg_bank;
+                self.chr_banks = s.chr_banks;
+                self.latches = s.latches;
+                self.mirror_mode = s.mirror_mode;
+                self.chr_mem.hydrate(s.chr_mem);
+            }
+            _ => panic!("Incompatible mapper state for MMC2 mapper: {:?}", state),
+        }
+    }
+}
@@ -1,7 +1,29 @@
-use crate::emulator::memory::{Mapper, Memory};
+use crate::emulator::memory::{ChrBanks, Mapper, Memory};
 use crate::emulator::ppu::MirrorMode;
 use crate::emulator::state::{MMC3State, MapperState, SaveState};
 
+// The IRQ counter differs between chip revisions when the reload value is 0.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MMC3Revision {
+    // Sharp MMC3B and MMC3C.  IRQ on every clock that leaves the counter at 0, so a reload value
+    // of 0 fires every scanline.
+    Sharp,
+
+    // NEC chips and the MMC3A.  IRQ only when the counter steps down to 0, or when it is reloaded
+    // by a write to $C001, so a reload value of 0 fires once.
+    NEC,
+}
+
+impl MMC3Revision {
+    // NES 2.0 submapper 4 marks carts that need the older behaviour.
+    pub fn from_submapper(submapper: u8) -> MMC3Revision {
+        match submapper {
+            4 => MMC3Revision::NEC,
+            _ => MMC3Revision::Sharp,
+        }
+    }
+}
+
 // 1x 8kb PRG RAM - right now we have this sram outside the mappers, so ignored here.
 // 4x 8kb switchable PRG ROM
 // 2x 2kb switchable CHR ROM (we will treat this as 4x 1kb)
@@ -23,15 +45,20 @@ pub struct MMC3 {
     irq_reload_flag: bool,
     irq_counter_reload: u8,
     irq_enabled: bool,
+    revision: MMC3Revision,
 
     ppu_a12: bool,
     ppu_a12_low_counter: u8,
 
     mirror_mode: MirrorMode,
+
+    bank_switched: bool,
 }
 
 impl MMC3 {
-    pub fn new(prg_rom: Memory, chr_mem: Memory) -> MMC3 {
+    // Four-screen boards hardwire their nametables, so pass `MirrorMode::FourScreen` to have
+    // writes to the mirroring register ignored.  Any other mode is just the power-on default.
+    pub fn new(prg_rom: Memory, chr_mem: Memory, mirror_mode: MirrorMode) -> MMC3 {
         let mut m = MMC3 {
             prg_rom,
             chr_mem,
@@ -44,9 +71,14 @@ impl MMC3 {
             irq_reload_flag: false,
             irq_counter_reload: 0,
             irq_enabled: false,
+            revision: MMC3Revision::Sharp,
             ppu_a12: false,
             ppu_a12_low_counter: 0,
-            mirror_mode: MirrorMode::Horizontal,
+            mirror_mode: match mirror_mode {
+                MirrorMode::FourScreen => MirrorMode::FourScreen,
+                _ => MirrorMode::Horizontal,
+            },
+            bank_switched: false,
         };
         let num_banks = m.prg_rom.len() / 0x2000;
         m.bank_registers[8] = ((num_banks - 2) * 0x2000) as usize;
@@ -54,7 +86,13 @@ impl MMC3 {
         m
     }
 
+    pub fn set_revision(&mut self, revision: MMC3Revision) {
+        self.revision = revision;
+    }
+
     fn clock_irq(&mut self) {
+        let previous = self.irq_counter;
+        let reloading = self.irq_reload_flag;
         if self.irq_counter == 0 || self.irq_reload_flag {
             self.irq_counter = self.irq_counter_reload;
             self.irq_reload_flag = false;
@@ -62,14 +100,17 @@ impl MMC3 {
             self.irq_counter = self.irq_counter.saturating_sub(1);
         }
 
-        if self.irq_counter == 0 {
+        let fire = match self.revision {
+            MMC3Revision::Sharp => self.irq_counter == 0,
+            MMC3Revision::NEC => self.irq_counter == 0 && (previous > 0 || reloading),
+        };
+        if fire {
             self.irq_flag = self.irq_enabled;
         }
     }
-}
 
-impl Mapper for MMC3 {
-    fn read_chr(&mut self, address: u16) -> u8 {
+    // Where in CHR memory a pattern table address is currently mapped.
+    fn chr_address(&self, address: u16) -> usize {
         let (bank_ix, bank_size) = match address {
             // CHR banks.
             0x0000..=0x03FF => {
@@ -133,26 +174,11 @@ impl Mapper for MMC3 {
 
         let base = self.bank_registers[bank_ix];
         let offset = (address % bank_size) as usize;
-
-        // Update A12 and clock IRQ.
-        let a12 = address & 0x1000 == 0x1000;
-        if a12 && !self.ppu_a12 && self.ppu_a12_low_counter > 12 {
-            self.clock_irq();
-        } else if !a12 && !self.ppu_a12 {
-            self.ppu_a12_low_counter = self.ppu_a12_low_counter.saturating_add(1);
-        } else if a12 {
-            self.ppu_a12_low_counter = 0;
-        }
-        self.ppu_a12 = a12;
-
-        self.chr_mem.get(base + offset)
-    }
-
-    fn write_chr(&mut self, address: u16, byte: u8) {
-        self.chr_mem.put(address as usize, byte);
+        base + offset
     }
 
-    fn read_prg(&mut self, address: u16) -> u8 {
+    // Where in PRG ROM a CPU address in $8000-$FFFF is currently mapped.
+    fn prg_address(&self, address: u16) -> usize {
         let (bank_ix, bank_size) = match address {
             // PRG banks.
             0x8000..=0x9FFF => {
@@ -189,7 +215,38 @@ impl Mapper for MMC3 {
         let base = self.bank_registers[bank_ix];
         let offset = (address % bank_size) as usize;
 
-        self.prg_rom.get(base + offset)
+        base + offset
+    }
+}
+
+impl Mapper for MMC3 {
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let chr_address = self.chr_address(address);
+
+        // Update A12 and clock IRQ.
+        let a12 = address & 0x1000 == 0x1000;
+        if a12 && !self.ppu_a12 && self.ppu_a12_low_counter > 12 {
+            self.clock_irq();
+        } else if !a12 && !self.ppu_a12 {
+            self.ppu_a12_low_counter = self.ppu_a12_low_counter.saturating_add(1);
+        } else if a12 {
+            self.ppu_a12_low_counter = 0;
+        }
+        self.ppu_a12 = a12;
+
+        self.chr_mem.get(chr_address)
+    }
+
+    fn write_chr(&mut self, address: u16, byte: u8) {
+        self.chr_mem.put(address as usize, byte);
+    }
+
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.prg_rom.get(self.prg_address(address))
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_address(address))
     }
 
     fn write_prg(&mut self, address: u16, byte: u8) {
@@ -206,6 +263,7 @@ impl Mapper for MMC3 {
                     self.bank_select = (byte & 0x07) as usize;
                     self.prg_inversion = byte & 0x40 == 0x40;
                     self.chr_inversion = byte & 0x80 == 0x80;
+                    self.bank_switched = true;
                 } else {
                     // 0x8000, odd => Bank data
                     // Handle PRG and CHR separately.
@@ -221,15 +279,18 @@ impl Mapper for MMC3 {
                         self.bank_registers[self.bank_select] =
                             ((byte as usize) << 10) % self.chr_mem.len();
                     }
+                    self.bank_switched = true;
                 }
             }
             0xA000 => {
                 if address & 0x1 == 0 {
-                    // 0xA000, even => mirror mode.
-                    self.mirror_mode = match byte & 0x1 == 0 {
-                        true => MirrorMode::Vertical,
-                        false => MirrorMode::Horizontal,
-                    };
+                    // 0xA000, even => mirror mode.  Hardwired on four-screen boards.
+                    if self.mirror_mode != MirrorMode::FourScreen {
+                        self.mirror_mode = match byte & 0x1 == 0 {
+                            true => MirrorMode::Vertical,
+                            false => MirrorMode::Horizontal,
+                        };
+                    }
                 } else {
                     // 0xA000, odd => PRG RAM protect
                     // Unimplemented for compatibility with MMC6.
@@ -266,6 +327,22 @@ impl Mapper for MMC3 {
     fn irq_triggered(&self) -> bool {
         self.irq_flag
     }
+
+    fn take_bank_switch(&mut self) -> bool {
+        core::mem::replace(&mut self.bank_switched, false)
+    }
+
+    fn chr_banks(&self) -> Option<ChrBanks> {
+        let mut banks = [0; 8];
+        for (ix, bank) in banks.iter_mut().enumerate() {
+            *bank = self.chr_address(ix as u16 * 0x400) / 0x400;
+        }
+        Some(banks)
+    }
+
+    fn peek_chr(&self, offset: usize) -> u8 {
+        self.chr_mem.get(offset % self.chr_mem.len())
+    }
 }
 
 impl<'de> SaveState<'de, MapperState> for MMC3 {
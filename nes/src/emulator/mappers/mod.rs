@@ -1,3 +1,11 @@
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use core::cell::RefCell;
+
+use crate::emulator::ines::ROM;
+pub use crate::emulator::memory::Mapper;
+
 // In iNES mapper number order.
 
 // #0 NROM
@@ -18,12 +26,61 @@ pub use self::cnrom::CNROM;
 
 // #4 MMC3
 mod mmc3;
-pub use self::mmc3::MMC3;
+pub use self::mmc3::{MMC3Revision, MMC3};
 
 // #7 AxROM
 mod axrom;
 pub use self::axrom::AXROM;
 
+// #9 MMC2, #10 MMC4
+mod mmc2;
+pub use self::mmc2::{MMC2Chip, MMC2};
+
 // #11 ColorDreams
 mod color_dreams;
 pub use self::color_dreams::ColorDreams;
+
+// #66 GxROM
+mod gxrom;
+pub use self::gxrom::GXROM;
+
+// Builds the mapper for an iNES mapper number.  Adding a mapper only needs a new arm here.
+// Fails for mapper numbers with no arm, so a frontend can say so rather than crash.
+pub fn from_ines(number: u8, rom: &ROM) -> Result<Rc<RefCell<dyn Mapper>>, String> {
+    let prg_rom = rom.prg_rom();
+    let chr_mem = rom.chr_mem();
+    let mirror_mode = rom.mirror_mode();
+
+    let mapper: Rc<RefCell<dyn Mapper>> = match number {
+        0 => Rc::new(RefCell::new(NROM::new(prg_rom, chr_mem, mirror_mode))),
+        1 => Rc::new(RefCell::new(MMC1::new(prg_rom, chr_mem))),
+        2 => Rc::new(RefCell::new(UXROM::new(prg_rom, chr_mem, mirror_mode))),
+        3 => Rc::new(RefCell::new(CNROM::new(prg_rom, chr_mem, mirror_mode))),
+        4 => {
+            let mut mmc3 = MMC3::new(prg_rom, chr_mem, mirror_mode);
+            mmc3.set_revision(MMC3Revision::from_submapper(rom.submapper()));
+            Rc::new(RefCell::new(mmc3))
+        }
+        7 => Rc::new(RefCell::new(AXROM::new(prg_rom, chr_mem))),
+        9 => Rc::new(RefCell::new(MMC2::new(
+            MMC2Chip::MMC2,
+            prg_rom,
+            chr_mem,
+            mirror_mode,
+        ))),
+        10 => Rc::new(RefCell::new(MMC2::new(
+            MMC2Chip::MMC4,
+            prg_rom,
+            chr_mem,
+            mirror_mode,
+        ))),
+        11 => Rc::new(RefCell::new(ColorDreams::new(
+            prg_rom,
+            chr_mem,
+            mirror_mode,
+        ))),
+        66 => Rc::new(RefCell::new(GXROM::new(prg_rom, chr_mem, mirror_mode))),
+        _ => return Err(format!("Unsupported mapper: {}", number)),
+    };
+    Ok(mapper)
+}
@@ -19,6 +19,11 @@ impl NROM {
             mirror_mode,
         }
     }
+
+    // Offset in PRG ROM for a CPU address in $8000-$FFFF.  16KB carts appear twice.
+    fn prg_address(&self, address: u16) -> usize {
+        (address - 0x8000) as usize % self.prg_rom.len()
+    }
 }
 
 impl Mapper for NROM {
@@ -31,8 +36,11 @@ impl Mapper for NROM {
     }
 
     fn read_prg(&mut self, address: u16) -> u8 {
-        self.prg_rom
-            .get(((address - 0x8000) % self.prg_rom.len() as u16) as usize)
+        self.prg_rom.get(self.prg_address(address))
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_address(address))
     }
 
     fn write_prg(&mut self, _address: u16, _byte: u8) {
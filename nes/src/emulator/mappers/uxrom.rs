@@ -10,6 +10,7 @@ pub struct UXROM {
     chr_mem: Memory,
     mirror_mode: MirrorMode,
     prg_bank: u8,
+    bank_switched: bool,
 }
 
 impl UXROM {
@@ -19,8 +20,20 @@ impl UXROM {
             chr_mem,
             mirror_mode,
             prg_bank: 0,
+            bank_switched: false,
         }
     }
+
+    // The last 16KB is fixed at $C000.
+    fn prg_address(&self, address: u16) -> usize {
+        let base = if address & 0x4000 == 0 {
+            (self.prg_bank as usize) << 14
+        } else {
+            (self.prg_rom.len() - 1) << 14
+        };
+        let rel = (address & 0x3FFF) as usize;
+        (base | rel) % self.prg_rom.len()
+    }
 }
 
 impl Mapper for UXROM {
@@ -33,22 +46,25 @@ impl Mapper for UXROM {
     }
 
     fn read_prg(&mut self, address: u16) -> u8 {
-        let base = if address & 0x4000 == 0 {
-            (self.prg_bank as usize) << 14
-        } else {
-            (self.prg_rom.len() - 1) << 14
-        };
-        let rel = (address & 0x3FFF) as usize;
-        self.prg_rom.get((base | rel) % self.prg_rom.len())
+        self.prg_rom.get(self.prg_address(address))
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_address(address))
     }
 
     fn write_prg(&mut self, _address: u16, byte: u8) {
         self.prg_bank = byte;
+        self.bank_switched = true;
     }
 
     fn mirror_mode(&self) -> MirrorMode {
         self.mirror_mode
     }
+
+    fn take_bank_switch(&mut self) -> bool {
+        core::mem::replace(&mut self.bank_switched, false)
+    }
 }
 
 impl<'de> SaveState<'de, MapperState> for UXROM {
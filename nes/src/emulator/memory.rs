@@ -1,6 +1,14 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::emulator::apu::ExpansionAudio;
+use crate::emulator::busstats::{BusStats, Device};
+use crate::emulator::cheats::Cheats;
+use crate::emulator::hexdump::{hexdump, HexDumpOptions};
 use crate::emulator::ppu::{MirrorMode, Mirrorer};
 use crate::emulator::state::{MapperState, MemoryState, SaveState};
 
@@ -50,6 +58,10 @@ impl IORegisters {
         }
     }
 
+    pub fn oamdma_pending(&self) -> bool {
+        self.oamdma.is_some()
+    }
+
     pub fn get_oamdma(&mut self) -> Option<u8> {
         let res = self.oamdma;
         self.oamdma = None;
@@ -61,7 +73,6 @@ impl Reader for IORegisters {
     fn read(&mut self, address: u16) -> u8 {
         match address {
             0x4000..=0x4013 | 0x4015 => self.apu.read(address),
-            0x4014 => self.oamdma.unwrap_or(0),
             0x4016 => self.joy1.read(address),
             0x4017 => self.joy2.read(address),
             _ => 0,
@@ -85,12 +96,33 @@ impl Writer for IORegisters {
     }
 }
 
+pub const EXPANSION_RAM_START: u16 = 0x4020;
+pub const EXPANSION_RAM_SIZE: usize = 0x6000 - EXPANSION_RAM_START as usize;
+
+// Mask taking an offset into $6000-$7FFF to one into `size` bytes of PRG-RAM.  The chip is wired
+// up as the next power of two, so e.g. 6KB mirrors like 8KB.
+pub fn sram_mask(size: usize) -> u16 {
+    (size.clamp(1, 0x2000).next_power_of_two() - 1) as u16
+}
+
+// A device the CPU memory map routes to, and the address the device sees.
+type Mapped<'a> = Option<(&'a mut Box<dyn ReadWriter>, u16)>;
+
 pub struct CPUMemory {
     ram: Box<dyn ReadWriter>,
     ppu_registers: Box<dyn ReadWriter>,
     io_registers: Box<dyn ReadWriter>,
     sram: Box<dyn ReadWriter>,
+    // Boards with less than 8KB of PRG-RAM see it repeated across $6000-$7FFF.
+    sram_mask: u16,
+    // Only a few boards put RAM at $4020-$5FFF.  Without it, reads there are open bus.
+    expansion_ram: Option<Box<dyn ReadWriter>>,
     prg_rom: Box<dyn ReadWriter>,
+    cheats: Option<Rc<RefCell<Cheats>>>,
+    stats: Option<Rc<RefCell<BusStats>>>,
+
+    // Last value seen on the data bus.  Bits not driven by the device being read keep this value.
+    open_bus: u8,
 }
 
 impl CPUMemory {
@@ -106,33 +138,99 @@ impl CPUMemory {
             ppu_registers,
             io_registers,
             sram,
+            sram_mask: 0x1FFF,
+            expansion_ram: None,
             prg_rom,
+            cheats: None,
+            stats: None,
+            open_bus: 0,
         }
     }
 
-    fn map(&mut self, address: u16) -> Option<(&mut Box<dyn ReadWriter>, u16)> {
+    pub fn set_cheats(&mut self, cheats: Rc<RefCell<Cheats>>) {
+        self.cheats = Some(cheats);
+    }
+
+    pub fn set_stats(&mut self, stats: Rc<RefCell<BusStats>>) {
+        self.stats = Some(stats);
+    }
+
+    // Size of PRG-RAM, no bigger than 8KB.  See `sram_mask`.
+    pub fn set_sram_size(&mut self, size: usize) {
+        self.sram_mask = sram_mask(size);
+    }
+
+    pub fn set_expansion_ram(&mut self, ram: Box<dyn ReadWriter>) {
+        self.expansion_ram = Some(ram);
+    }
+
+    // The CPU memory map is fixed, so a match over address ranges is all the dispatch we need.
+    // This is constant time; there's no list of mounted modules to scan.  Mirrored ranges are
+    // masked down here, so devices only ever see their own base addresses.
+    fn map(&mut self, address: u16) -> (Device, Mapped<'_>) {
         match address {
-            0x0000..=0x1FFF => Some((&mut self.ram, address & 0x7FF)),
-            0x2000..=0x3FFF => Some((&mut self.ppu_registers, address & 0x7)),
-            0x4000..=0x401F => Some((&mut self.io_registers, address)),
-            0x6000..=0x7FFF => Some((&mut self.sram, address - 0x6000)),
-            0x8000..=0xFFFF => Some((&mut self.prg_rom, address)),
-            _ => None,
+            0x0000..=0x1FFF => (Device::Ram, Some((&mut self.ram, address & 0x7FF))),
+            0x2000..=0x3FFF => (
+                Device::PpuRegisters,
+                Some((&mut self.ppu_registers, address & 0x7)),
+            ),
+            0x4000..=0x401F => (Device::IoRegisters, Some((&mut self.io_registers, address))),
+            0x4020..=0x5FFF => match self.expansion_ram {
+                Some(ref mut ram) => (
+                    Device::ExpansionRam,
+                    Some((ram, address - EXPANSION_RAM_START)),
+                ),
+                None => (Device::Unmapped, None),
+            },
+            0x6000..=0x7FFF => (
+                Device::PrgRam,
+                Some((&mut self.sram, (address - 0x6000) & self.sram_mask)),
+            ),
+            0x8000..=0xFFFF => (Device::PrgRom, Some((&mut self.prg_rom, address))),
         }
     }
 }
 
 impl Reader for CPUMemory {
     fn read(&mut self, address: u16) -> u8 {
-        self.map(address)
-            .map(|(mem, addr)| mem.read(addr))
-            .unwrap_or(0)
+        let open_bus = self.open_bus;
+        let (device, mapped) = self.map(address);
+        let byte = match mapped {
+            // Controller ports only drive the low 5 bits.  The rest is usually the high byte of
+            // the address, left over from fetching the operand.
+            Some((mem, addr)) if address == 0x4016 || address == 0x4017 => {
+                (mem.read(addr) & 0x1F) | (open_bus & 0xE0)
+            }
+            // Bit 5 of the APU status isn't connected.
+            Some((mem, addr)) if address == 0x4015 => (mem.read(addr) & 0xDF) | (open_bus & 0x20),
+            // The other APU registers and OAMDMA are write only, and $4018-$401F are only enabled
+            // in test mode, so nothing drives the bus.
+            Some(_) if (0x4000..=0x401F).contains(&address) => open_bus,
+            Some((mem, addr)) => mem.read(addr),
+            None => open_bus,
+        };
+        if let Some(ref stats) = self.stats {
+            stats.borrow_mut().record_read(device);
+        }
+        let byte = match self.cheats {
+            Some(ref cheats) => cheats.borrow().apply(address, byte),
+            None => byte,
+        };
+        self.open_bus = byte;
+        byte
     }
 }
 
 impl Writer for CPUMemory {
     fn write(&mut self, address: u16, byte: u8) {
-        self.map(address).map(|(mem, addr)| mem.write(addr, byte));
+        self.open_bus = byte;
+        let (device, mapped) = self.map(address);
+        if let Some((mem, addr)) = mapped {
+            mem.write(addr, byte);
+        }
+        if let Some(ref stats) = self.stats {
+            stats.borrow_mut().record_write(device);
+        }
     }
 }
 
@@ -155,6 +253,16 @@ impl PPUMemory {
         }
     }
 
+    // A read by the PPU itself, as opposed to a debugger looking.  The mapper gets to see pattern
+    // table fetches.
+    pub fn fetch(&mut self, address: u16) -> u8 {
+        let byte = self.read(address);
+        if address & 0x3FFF < 0x2000 {
+            self.mirrorer.pattern_fetched(address & 0x3FFF);
+        }
+        byte
+    }
+
     fn map(&mut self, address: u16) -> Option<(&mut Box<dyn ReadWriter>, u16)> {
         // Whole thing is mirrored above $4000.
         match address & 0x3FFF {
@@ -163,11 +271,15 @@ impl PPUMemory {
                 // Nametable and nametable mirrors.
                 // Note that we don't just literally mirror the address horizontally/vertically.
                 // We need to make sure we always read from one of just 2 banks of memory.
+                // Four-screen carts are the exception, and use banks 2 and 3 as well.  VRAM
+                // already has room for those below the palettes, so it stands in for the
+                // cartridge's extra 2KB.
                 let nt_bank = match self.mirrorer.mirror_mode() {
                     MirrorMode::SingleLower => 0,
                     MirrorMode::SingleUpper => 1,
                     MirrorMode::Vertical => (address & 0x0400) >> 10,
                     MirrorMode::Horizontal => (address & 0x0800) >> 11,
+                    MirrorMode::FourScreen => (address & 0x0C00) >> 10,
                 };
                 let mirrored_addr = (nt_bank << 10) | (address & 0x03FF);
                 Some((&mut self.vram, mirrored_addr & 0x3FFF))
@@ -210,8 +322,42 @@ pub trait Mapper: SaveState<'static, MapperState> {
     fn irq_triggered(&self) -> bool {
         false
     }
+
+    // True if a bank register has been written since the last call.  Lets debug views and
+    // caches notice bank switches without polling every bank register.
+    fn take_bank_switch(&mut self) -> bool {
+        false
+    }
+
+    // Mappers with their own sound channels expose them here to be mixed with the APU.
+    fn expansion_audio(&mut self) -> Option<&mut dyn ExpansionAudio> {
+        None
+    }
+
+    // Which 1KB bank of CHR each 1KB of the pattern tables shows, for debug views.  Only mappers
+    // which switch CHR banks have an answer.
+    fn chr_banks(&self) -> Option<ChrBanks> {
+        None
+    }
+
+    // Reads CHR by its offset in the cartridge, whatever is mapped in, without side effects.
+    fn peek_chr(&self, _offset: usize) -> u8 {
+        0
+    }
+
+    // The PPU just read this pattern table address while drawing, or through PPUDATA.
+    fn pattern_fetched(&mut self, _address: u16) {}
+
+    // Where in PRG-ROM a CPU address in $8000-$FFFF currently reads from, for the code/data log
+    // and bank views.
+    fn prg_rom_offset(&self, _address: u16) -> Option<usize> {
+        None
+    }
 }
 
+// The 1KB CHR bank shown at $0000, $0400, ... $1C00.
+pub type ChrBanks = [usize; 8];
+
 pub type MapperRef = Rc<RefCell<dyn Mapper>>;
 
 impl Mapper for MapperRef {
@@ -234,6 +380,30 @@ impl Mapper for MapperRef {
     fn mirror_mode(&self) -> MirrorMode {
         self.borrow().mirror_mode()
     }
+
+    fn irq_triggered(&self) -> bool {
+        self.borrow().irq_triggered()
+    }
+
+    fn take_bank_switch(&mut self) -> bool {
+        self.borrow_mut().take_bank_switch()
+    }
+
+    fn chr_banks(&self) -> Option<ChrBanks> {
+        self.borrow().chr_banks()
+    }
+
+    fn peek_chr(&self, offset: usize) -> u8 {
+        self.borrow().peek_chr(offset)
+    }
+
+    fn pattern_fetched(&mut self, address: u16) {
+        self.borrow_mut().pattern_fetched(address)
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.borrow().prg_rom_offset(address)
+    }
 }
 
 impl SaveState<'static, MapperState> for MapperRef {
@@ -250,6 +420,25 @@ impl Mirrorer for MapperRef {
     fn mirror_mode(&self) -> MirrorMode {
         self.borrow().mirror_mode()
     }
+    fn pattern_fetched(&mut self, address: u16) {
+        self.borrow_mut().pattern_fetched(address)
+    }
+}
+
+// Lets the APU hold on to the cartridge's sound channels.
+impl ExpansionAudio for MapperRef {
+    fn clock(&mut self) {
+        if let Some(audio) = self.borrow_mut().expansion_audio() {
+            audio.clock();
+        }
+    }
+
+    fn sample(&self) -> f32 {
+        match self.borrow_mut().expansion_audio() {
+            Some(audio) => audio.sample(),
+            None => 0.0,
+        }
+    }
 }
 
 pub struct PrgMapper<M: Mapper> {
@@ -332,14 +521,21 @@ impl Memory {
         self.data.len()
     }
 
-    pub fn debug_print(&self, start_addr: u16, num_bytes: u16) {
-        let end_addr = start_addr - 1 + num_bytes;
-        println!(
-            "Memory [{:X}..{:X}]: {:?}",
-            start_addr,
-            end_addr,
-            &self.data[(start_addr as usize)..(end_addr as usize)]
-        );
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    // Overwrites the start of memory, e.g. to restore a save file.  Anything past the end is
+    // dropped.
+    pub fn load(&mut self, bytes: &[u8]) {
+        let len = bytes.len().min(self.data.len());
+        self.data[..len].copy_from_slice(&bytes[..len]);
+    }
+
+    // Hex dump of up to `len` bytes from `start`, stopping at the end of memory.
+    pub fn hexdump(&self, start: usize, len: usize, options: &HexDumpOptions) -> String {
+        let end = start.saturating_add(len).min(self.data.len());
+        hexdump(start, end, options, |address| self.data[address])
     }
 }
 
@@ -379,3 +575,289 @@ fn test_get_and_set() {
     ram.write(1234, 23);
     assert_eq!(ram.read(1234), 23);
 }
+
+#[test]
+fn test_hexdump_clamps_to_memory() {
+    let rom = Memory::new_rom(vec![0xAB; 4]);
+    let options = HexDumpOptions {
+        ascii: false,
+        ..HexDumpOptions::default()
+    };
+    assert_eq!(rom.hexdump(0, 0, &options), "");
+    assert_eq!(rom.hexdump(2, 100, &options), "$0002: AB AB\n");
+    assert_eq!(rom.hexdump(10, 1, &options), "");
+    assert_eq!(rom.hexdump(1, usize::MAX, &options), "$0001: AB AB AB\n");
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    use super::*;
+    use crate::emulator::controller::{default_keymap, Controller};
+    use crate::emulator::mappers::{MMC1, MMC3};
+
+    fn new_cpu_memory(joy1: Rc<RefCell<Controller>>) -> CPUMemory {
+        let joy2 = Controller::new(default_keymap());
+        // Plain RAM stands in for the APU, which sees full addresses.
+        let io_registers = IORegisters::new(
+            Box::new(Memory::new_ram(0x4018)),
+            Box::new(joy1),
+            Box::new(joy2),
+        );
+        CPUMemory::new(
+            Box::new(Memory::new_ram(0x800)),
+            Box::new(Memory::new_ram(0x8)),
+            Box::new(io_registers),
+            Box::new(Memory::new_ram(0x2000)),
+            Box::new(Memory::new_ram(0x10000)),
+        )
+    }
+
+    #[test]
+    fn test_controller_port_open_bus() {
+        let joy1 = Rc::new(RefCell::new(Controller::new(default_keymap())));
+        joy1.borrow_mut().set_buttons(0x01); // A held.
+        let mut memory = new_cpu_memory(joy1);
+
+        // As if fetching the operand of LDA $4016.
+        memory.write(0x0000, 0x40);
+        memory.read(0x0000);
+        assert_eq!(memory.read(0x4016), 0x41);
+        assert_eq!(memory.read(0x4016), 0x40);
+
+        memory.write(0x0000, 0xFF);
+        memory.read(0x0000);
+        assert_eq!(memory.read(0x4017), 0xE0);
+    }
+
+    #[test]
+    fn test_unmapped_reads_open_bus() {
+        let joy1 = Rc::new(RefCell::new(Controller::new(default_keymap())));
+        let mut memory = new_cpu_memory(joy1);
+        memory.write(0x0000, 0x5A);
+        memory.read(0x0000);
+        assert_eq!(memory.read(0x5000), 0x5A);
+    }
+
+    #[test]
+    fn test_oamdma_reads_open_bus() {
+        let joy1 = Rc::new(RefCell::new(Controller::new(default_keymap())));
+        let mut memory = new_cpu_memory(joy1);
+        memory.write(0x4014, 0x02);
+        memory.write(0x0000, 0x40);
+        memory.read(0x0000);
+        assert_eq!(memory.read(0x4014), 0x40);
+    }
+
+    #[test]
+    fn test_io_registers_read_open_bus() {
+        let joy1 = Rc::new(RefCell::new(Controller::new(default_keymap())));
+        let mut memory = new_cpu_memory(joy1);
+        for address in (0x4000..=0x4013).chain(0x4018..=0x401F) {
+            memory.write(0x0000, 0xA5);
+            memory.read(0x0000);
+            assert_eq!(memory.read(address), 0xA5, "${:04X}", address);
+        }
+
+        // Only bit 5 of $4015 is left floating.
+        memory.write(0x0000, 0xFF);
+        memory.read(0x0000);
+        assert_eq!(memory.read(0x4015) & 0x20, 0x20);
+        memory.write(0x0000, 0x00);
+        memory.read(0x0000);
+        assert_eq!(memory.read(0x4015) & 0x20, 0x00);
+    }
+
+    #[test]
+    fn test_cartridge_ram() {
+        let joy1 = Rc::new(RefCell::new(Controller::new(default_keymap())));
+        let mut memory = new_cpu_memory(joy1);
+
+        // Nothing answers at $4020-$5FFF on most boards, so the write is still on the bus.
+        memory.write(0x5000, 0x12);
+        assert_eq!(memory.read(0x5000), 0x12);
+
+        memory.set_expansion_ram(Box::new(Memory::new_ram(EXPANSION_RAM_SIZE)));
+        memory.write(0x4020, 0x34);
+        memory.write(0x5FFF, 0x56);
+        assert_eq!(memory.read(0x4020), 0x34);
+        assert_eq!(memory.read(0x5FFF), 0x56);
+
+        // 2KB of PRG-RAM repeats four times.
+        memory.set_sram_size(0x800);
+        memory.write(0x6001, 0x78);
+        assert_eq!(memory.read(0x6801), 0x78);
+        assert_eq!(memory.read(0x7801), 0x78);
+    }
+
+    #[test]
+    fn test_ram_and_ppu_register_mirrors() {
+        let joy1 = Rc::new(RefCell::new(Controller::new(default_keymap())));
+        let mut memory = new_cpu_memory(joy1);
+
+        // 2KB of RAM repeats four times up to $1FFF.
+        memory.write(0x1FFF, 0x12);
+        assert_eq!(memory.read(0x07FF), 0x12);
+        assert_eq!(memory.read(0x0FFF), 0x12);
+        memory.write(0x0001, 0x34);
+        assert_eq!(memory.read(0x1801), 0x34);
+
+        // The 8 PPU registers repeat every 8 bytes up to $3FFF.
+        memory.write(0x3FFE, 0x56);
+        assert_eq!(memory.read(0x2006), 0x56);
+        assert_eq!(memory.read(0x200E), 0x56);
+        memory.write(0x2001, 0x78);
+        assert_eq!(memory.read(0x3FF9), 0x78);
+    }
+
+    #[test]
+    fn test_cheats_patch_reads() {
+        let joy1 = Rc::new(RefCell::new(Controller::new(default_keymap())));
+        let mut memory = new_cpu_memory(joy1);
+        let cheats = Rc::new(RefCell::new(Cheats::from_text("0010:42").unwrap()));
+        memory.set_cheats(cheats.clone());
+
+        memory.write(0x0010, 0x01);
+        assert_eq!(memory.read(0x0010), 0x42);
+        assert_eq!(memory.read(0x0011), 0x00);
+
+        cheats.borrow_mut().set_enabled(0, false);
+        assert_eq!(memory.read(0x0010), 0x01);
+    }
+
+    struct FixedMirrorer(MirrorMode);
+
+    impl Mirrorer for FixedMirrorer {
+        fn mirror_mode(&self) -> MirrorMode {
+            self.0
+        }
+    }
+
+    fn new_ppu_memory(mode: MirrorMode) -> PPUMemory {
+        PPUMemory::new(
+            Box::new(Memory::new_ram(0x2000)),
+            Box::new(FixedMirrorer(mode)),
+            Box::new(Memory::new_ram(0x2000)),
+        )
+    }
+
+    #[test]
+    fn test_four_screen_nametables_are_distinct() {
+        let mut memory = new_ppu_memory(MirrorMode::FourScreen);
+        for table in 0..4 {
+            memory.write(0x2000 + table * 0x400, table as u8 + 1);
+        }
+        for table in 0..4 {
+            assert_eq!(memory.read(0x2000 + table * 0x400), table as u8 + 1);
+            // $3000-$3EFF still mirrors $2000-$2EFF.
+            assert_eq!(memory.read(0x3000 + table * 0x400), table as u8 + 1);
+        }
+        // And none of them alias the palettes.
+        assert_eq!(memory.read(0x3F00), 0);
+    }
+
+    #[test]
+    fn test_vertical_mirroring_shares_two_nametables() {
+        let mut memory = new_ppu_memory(MirrorMode::Vertical);
+        memory.write(0x2000, 1);
+        memory.write(0x2400, 2);
+        assert_eq!(memory.read(0x2800), 1);
+        assert_eq!(memory.read(0x2C00), 2);
+    }
+
+    // Writes each nametable's number into it, in order, then reads them all back.  Tables which
+    // share memory all end up showing the last one written.
+    fn nametable_layout(memory: &mut PPUMemory) -> [u8; 4] {
+        for table in 0..4 {
+            memory.write(0x2000 + table * 0x400, table as u8);
+        }
+        let mut tables = [0; 4];
+        for (table, byte) in tables.iter_mut().enumerate() {
+            *byte = memory.read(0x2000 + table as u16 * 0x400);
+        }
+        tables
+    }
+
+    fn mapper_ppu_memory(mapper: MapperRef) -> PPUMemory {
+        PPUMemory::new(
+            Box::new(Memory::new_ram(0x2000)),
+            Box::new(mapper),
+            Box::new(Memory::new_ram(0x2000)),
+        )
+    }
+
+    #[test]
+    fn test_mmc3_switches_mirroring() {
+        let mmc3 = MMC3::new(
+            Memory::new_rom(vec![0; 0x8000]),
+            Memory::new_ram(0x2000),
+            MirrorMode::Vertical,
+        );
+        let mapper: MapperRef = Rc::new(RefCell::new(mmc3));
+        let mut memory = mapper_ppu_memory(mapper.clone());
+        // Powers on horizontal, whatever the header says.
+        assert_eq!(nametable_layout(&mut memory), [1, 1, 3, 3]);
+
+        mapper.borrow_mut().write_prg(0xA000, 0);
+        assert_eq!(nametable_layout(&mut memory), [2, 3, 2, 3]);
+        mapper.borrow_mut().write_prg(0xA000, 1);
+        assert_eq!(nametable_layout(&mut memory), [1, 1, 3, 3]);
+    }
+
+    #[test]
+    fn test_mmc1_switches_mirroring() {
+        let mmc1 = MMC1::new(Memory::new_rom(vec![0; 0x8000]), Memory::new_ram(0x2000));
+        let mapper: MapperRef = Rc::new(RefCell::new(mmc1));
+        let mut memory = mapper_ppu_memory(mapper.clone());
+        // Control is loaded a bit at a time, low bit first.
+        let set_control = |control: u8| {
+            for bit in 0..5 {
+                mapper.borrow_mut().write_prg(0x8000, control >> bit);
+            }
+        };
+
+        set_control(0b0_1100);
+        assert_eq!(nametable_layout(&mut memory), [3, 3, 3, 3]);
+        memory.write(0x2000, 0x10);
+        // Upper is a different table to lower.
+        set_control(0b0_1101);
+        memory.write(0x2000, 0x11);
+        set_control(0b0_1100);
+        assert_eq!(memory.read(0x2C00), 0x10);
+
+        set_control(0b0_1110);
+        assert_eq!(nametable_layout(&mut memory), [2, 3, 2, 3]);
+        set_control(0b0_1111);
+        assert_eq!(nametable_layout(&mut memory), [1, 1, 3, 3]);
+    }
+
+    // Remembers the pattern fetches it's told about.
+    struct FetchRecorder(Rc<RefCell<Vec<u16>>>);
+
+    impl Mirrorer for FetchRecorder {
+        fn mirror_mode(&self) -> MirrorMode {
+            MirrorMode::Vertical
+        }
+
+        fn pattern_fetched(&mut self, address: u16) {
+            self.0.borrow_mut().push(address);
+        }
+    }
+
+    #[test]
+    fn test_only_pattern_fetches_reach_mapper() {
+        let fetches = Rc::new(RefCell::new(vec![]));
+        let mut memory = PPUMemory::new(
+            Box::new(Memory::new_ram(0x2000)),
+            Box::new(FetchRecorder(fetches.clone())),
+            Box::new(Memory::new_ram(0x2000)),
+        );
+        memory.read(0x0FD8);
+        memory.fetch(0x2000);
+        memory.fetch(0x0FD8);
+        memory.fetch(0x5FE8);
+        assert_eq!(*fetches.borrow(), vec![0x0FD8, 0x1FE8]);
+    }
+}
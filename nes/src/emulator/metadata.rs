@@ -0,0 +1,168 @@
+// Identifies the machine a movie or save state was made on, so a replay on a differently set up
+// emulator is caught up front rather than turning into a mysterious desync later.
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::emulator::ines::ROM;
+use crate::emulator::Region;
+
+pub const EMULATOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Settings which change how a game runs, beyond the ROM contents and the input.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AccuracyConfig {
+    pub region: Region,
+    // After any header fixes, e.g. from a ROM database.
+    pub mapper: u8,
+    pub submapper: u8,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub emulator_version: String,
+    // CRC-32 of the PRG and CHR data, as ROM databases use.
+    pub rom_crc32: u32,
+    pub accuracy: AccuracyConfig,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Mismatch {
+    EmulatorVersion {
+        recorded: String,
+        current: String,
+    },
+    Rom {
+        recorded: u32,
+        current: u32,
+    },
+    Accuracy {
+        recorded: AccuracyConfig,
+        current: AccuracyConfig,
+    },
+}
+
+impl Metadata {
+    pub fn new(rom: &ROM, region: Region) -> Metadata {
+        Metadata {
+            emulator_version: String::from(EMULATOR_VERSION),
+            rom_crc32: rom.crc32(),
+            accuracy: AccuracyConfig {
+                region,
+                mapper: rom.mapper_number(),
+                submapper: rom.submapper(),
+            },
+        }
+    }
+
+    // Everything about `self` which differs from the machine it's being used on.
+    pub fn compare(&self, current: &Metadata) -> Vec<Mismatch> {
+        let mut mismatches = vec![];
+        if self.emulator_version != current.emulator_version {
+            mismatches.push(Mismatch::EmulatorVersion {
+                recorded: self.emulator_version.clone(),
+                current: current.emulator_version.clone(),
+            });
+        }
+        if self.rom_crc32 != current.rom_crc32 {
+            mismatches.push(Mismatch::Rom {
+                recorded: self.rom_crc32,
+                current: current.rom_crc32,
+            });
+        }
+        if self.accuracy != current.accuracy {
+            mismatches.push(Mismatch::Accuracy {
+                recorded: self.accuracy.clone(),
+                current: current.accuracy.clone(),
+            });
+        }
+        mismatches
+    }
+
+    // Fails on anything which is bound to desync.  A different emulator version only might, so
+    // it comes back as a warning.
+    pub fn check(&self, current: &Metadata) -> Result<Vec<Mismatch>, Mismatch> {
+        let mismatches = self.compare(current);
+        match mismatches.iter().find(|m| m.is_fatal()) {
+            Some(fatal) => Err(fatal.clone()),
+            None => Ok(mismatches),
+        }
+    }
+}
+
+impl Mismatch {
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Mismatch::EmulatorVersion { .. } => false,
+            Mismatch::Rom { .. } | Mismatch::Accuracy { .. } => true,
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::EmulatorVersion { recorded, current } => write!(
+                f,
+                "made with emulator version {}, this is {}",
+                recorded, current
+            ),
+            Mismatch::Rom { recorded, current } => write!(
+                f,
+                "made with a ROM with CRC {:08X}, this one is {:08X}",
+                recorded, current
+            ),
+            Mismatch::Accuracy { recorded, current } => {
+                write!(
+                    f,
+                    "made with settings {:?}, these are {:?}",
+                    recorded, current
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn metadata(version: &str, crc: u32, region: Region) -> Metadata {
+        Metadata {
+            emulator_version: String::from(version),
+            rom_crc32: crc,
+            accuracy: AccuracyConfig {
+                region,
+                mapper: 4,
+                submapper: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let current = metadata(EMULATOR_VERSION, 0x1234, Region::NTSC);
+        assert_eq!(current.check(&current), Ok(vec![]));
+
+        // A different version is only a warning.
+        let old = metadata("0.0.1", 0x1234, Region::NTSC);
+        let warnings = old.check(&current).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(!warnings[0].is_fatal());
+
+        let other_rom = metadata(EMULATOR_VERSION, 0x5678, Region::NTSC);
+        assert_eq!(
+            other_rom.check(&current),
+            Err(Mismatch::Rom {
+                recorded: 0x5678,
+                current: 0x1234
+            })
+        );
+
+        let pal = metadata(EMULATOR_VERSION, 0x1234, Region::PAL);
+        assert!(pal.check(&current).is_err());
+    }
+}
@@ -1,39 +1,143 @@
 #![allow(dead_code)]
 pub mod apu;
+#[cfg(feature = "std")]
+pub mod archive;
+pub mod busstats;
+pub mod cdl;
+pub mod cheats;
+pub mod chrbanks;
 pub mod clock;
 pub mod components;
+pub mod config;
 pub mod controller;
 pub mod cpu;
+pub mod debugmem;
+pub mod eventlog;
+pub mod hardware;
+pub mod heatmap;
+pub mod hexdump;
 pub mod ines;
+pub mod inputscript;
 pub mod io;
 pub mod mappers;
 pub mod memory;
+pub mod metadata;
+pub mod movie;
 pub mod ppu;
+#[cfg(feature = "std")]
+pub mod soak;
 pub mod state;
+pub mod testrom;
 pub mod util;
+pub mod vramcheck;
+pub mod watchpoints;
 
 #[cfg(test)]
 mod test;
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::{Ref, RefCell};
+
+use serde::{Deserialize, Serialize};
 
 use crate::emulator::apu::AudioOut;
-use crate::emulator::controller::Button;
-use crate::emulator::io::event::{EventBus, Key};
+use crate::emulator::io::event::EventBus;
 use crate::emulator::io::Screen;
-use crate::emulator::memory::{IORegisters, Writer};
+use crate::emulator::memory::{IORegisters, Reader, Writer};
 use crate::emulator::state::{NESState, SaveState};
 
 // Timings (NTSC).
 // Master clock = 21.477272 MHz ~= 46.5ns per clock.
 // CPU clock = 12 master clocks.
 // PPU clock = 4 master clocks.
+// APU clock = 24 master clocks, every other CPU cycle.
+// Everything counts the same master cycles, so three dots always fit exactly in one CPU cycle.
 pub const NES_MASTER_CLOCK_HZ: u64 = 21_477_272;
 pub const NES_CPU_CLOCK_FACTOR: u32 = 12;
 pub const NES_APU_CLOCK_FACTOR: u32 = 24;
 pub const NES_PPU_CLOCK_FACTOR: u32 = 4;
 
+// Timings (PAL).
+// Master clock = 26.601712 MHz ~= 37.6ns per clock.
+// CPU clock = 16 master clocks.
+// PPU clock = 5 master clocks.
+// APU clock = 32 master clocks.
+// So the PPU runs 3.2 dots per CPU cycle rather than exactly 3.
+pub const PAL_MASTER_CLOCK_HZ: u64 = 26_601_712;
+pub const PAL_CPU_CLOCK_FACTOR: u32 = 16;
+pub const PAL_APU_CLOCK_FACTOR: u32 = 32;
+pub const PAL_PPU_CLOCK_FACTOR: u32 = 5;
+
+// Which console is emulated.  Config files can name it by either of its chips too.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Region {
+    #[serde(alias = "RP2A03", alias = "RP2C02")]
+    NTSC,
+    #[serde(alias = "RP2A07", alias = "RP2C07")]
+    PAL,
+}
+
+impl Region {
+    // "ntsc" or "pal", or the name of either chip in the console, with or without the "RP".
+    pub fn parse(name: &str) -> Result<Region, String> {
+        let name = name.to_uppercase();
+        let chip = name.strip_prefix("RP").unwrap_or(&name);
+        for region in [Region::NTSC, Region::PAL].iter() {
+            let revision = region.revision();
+            if name == format!("{:?}", region)
+                || chip == &revision.cpu[2..]
+                || chip == &revision.ppu[2..]
+            {
+                return Ok(*region);
+            }
+        }
+        Err(format!(
+            "Unknown hardware '{}', expected NTSC, PAL or a CPU or PPU such as 2A03 or 2C07",
+            name
+        ))
+    }
+
+    // Everything about the console which depends on the region.
+    pub fn revision(self) -> &'static hardware::Revision {
+        match self {
+            Region::NTSC => &hardware::NTSC,
+            Region::PAL => &hardware::PAL,
+        }
+    }
+
+    pub fn master_clock_hz(self) -> u64 {
+        self.revision().master_clock_hz
+    }
+
+    pub fn cpu_clock_factor(self) -> u32 {
+        self.revision().cpu_clock_factor
+    }
+
+    pub fn apu_clock_factor(self) -> u32 {
+        self.revision().apu_clock_factor
+    }
+
+    pub fn ppu_clock_factor(self) -> u32 {
+        self.revision().ppu_clock_factor
+    }
+
+    // Total scanlines per frame, including vblank and the pre-render line.
+    pub fn scanlines_per_frame(self) -> u16 {
+        self.revision().scanlines_per_frame
+    }
+
+    // Frames per second, ignoring the dot NTSC skips on odd frames.
+    pub fn frame_rate(self) -> f64 {
+        let dots_per_frame = 341 * u64::from(self.scanlines_per_frame());
+        self.master_clock_hz() as f64 / (dots_per_frame * u64::from(self.ppu_clock_factor())) as f64
+    }
+}
+
 pub struct NES {
     clock: clock::Clock,
     pub cpu: Rc<RefCell<cpu::CPU>>,
@@ -42,32 +146,148 @@ pub struct NES {
     pub mapper: Rc<RefCell<dyn memory::Mapper>>,
     pub ram: Rc<RefCell<memory::Memory>>,
     pub sram: Rc<RefCell<memory::Memory>>,
+    pub expansion_ram: Option<Rc<RefCell<memory::Memory>>>,
     pub vram: Rc<RefCell<memory::Memory>>,
     pub screen: Rc<RefCell<Screen>>,
     pub joy1: Rc<RefCell<controller::Controller>>,
     pub joy2: Rc<RefCell<controller::Controller>>,
+    // Only read through a Four Score.
+    pub joy3: Rc<RefCell<controller::Controller>>,
+    pub joy4: Rc<RefCell<controller::Controller>>,
+    pub cheats: Rc<RefCell<cheats::Cheats>>,
+    region: Region,
+    metadata: metadata::Metadata,
+    // Whether PRG-RAM should outlive the emulator in a save file.
+    battery: bool,
     nmi_pin: bool,
+    four_score: bool,
+    dma: Rc<RefCell<DMAController>>,
+    // Set while a save state is waiting for the CPU to reach an instruction boundary.
+    save_requested: bool,
+    captured_state: Option<NESState>,
+    breakpoints: BTreeSet<u16>,
+    // The cartridge's expansion audio source in the APU mixer, if it has one.
+    cartridge_audio: Option<usize>,
+    heatmap: Option<Rc<RefCell<heatmap::AccessHeatmap>>>,
+    // Always attached to the memory map, but only counts while enabled.
+    bus_stats: Rc<RefCell<busstats::BusStats>>,
+    prg_rom_len: usize,
+    code_data_log: Option<Rc<RefCell<cdl::CodeDataLog>>>,
+    chr_banks: Option<chrbanks::ChrBankTracker>,
+    vram_write_log: Option<Rc<RefCell<vramcheck::VramWriteLog>>>,
+    watchpoints: Option<Rc<RefCell<watchpoints::Watchpoints>>>,
+    event_log: Option<Rc<RefCell<eventlog::EventLog>>>,
+    config: config::Config,
+}
+
+// What happened during a call to `NES::run_cycles`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RunResult {
+    pub cycles: u64,
+    pub frames_completed: u64,
+    // Set if the run stopped early because the CPU reached a breakpoint.
+    pub breakpoint: Option<u16>,
+    // Set if the run stopped early because an instruction touched a watched address.
+    pub watchpoint_hit: bool,
 }
 
 impl NES {
+    // Panics if the cartridge can't be emulated; see `try_new`.
     pub fn new<A>(
         event_bus: Rc<RefCell<EventBus>>,
         screen: Rc<RefCell<Screen>>,
         audio: A,
         rom: ines::ROM,
+        config: &config::Config,
     ) -> NES
     where
         A: AudioOut + 'static,
     {
+        NES::try_new(event_bus, screen, audio, rom, config)
+            .unwrap_or_else(|cause| panic!("{}", cause))
+    }
+
+    // Like `new`, but fails with the reason if the cartridge can't be emulated, e.g. because it
+    // uses a mapper which isn't supported.
+    pub fn try_new<A>(
+        event_bus: Rc<RefCell<EventBus>>,
+        screen: Rc<RefCell<Screen>>,
+        audio: A,
+        rom: ines::ROM,
+        config: &config::Config,
+    ) -> Result<NES, String>
+    where
+        A: AudioOut + 'static,
+    {
+        // Before registering the joypads, so a failure leaves the event bus as it was.
+        let mapper = rom.get_mapper()?;
+
+        // Create controllers.
+        let joypads = [
+            Rc::new(RefCell::new(controller::Controller::new(
+                controller::default_keymap(),
+            ))),
+            Rc::new(RefCell::new(controller::Controller::new(
+                [].iter().cloned().collect(),
+            ))),
+            Rc::new(RefCell::new(controller::Controller::new(
+                [].iter().cloned().collect(),
+            ))),
+            Rc::new(RefCell::new(controller::Controller::new(
+                [].iter().cloned().collect(),
+            ))),
+        ];
+        for joy in joypads.iter() {
+            event_bus.borrow_mut().register(Box::new(joy.clone()));
+        }
+
+        Ok(NES::build(
+            joypads,
+            screen,
+            Box::new(audio),
+            rom,
+            mapper,
+            config,
+        ))
+    }
+
+    // Wires up a console around the cartridge.  The joypads, screen and audio output are passed
+    // in so they can be kept when the cartridge is swapped.
+    fn build(
+        joypads: [Rc<RefCell<controller::Controller>>; 4],
+        screen: Rc<RefCell<Screen>>,
+        audio: Box<dyn AudioOut>,
+        rom: ines::ROM,
+        mapper: Rc<RefCell<dyn memory::Mapper>>,
+        config: &config::Config,
+    ) -> NES {
+        let region = config.region.unwrap_or(rom.region());
+
         // Create master clock.
         let mut clock = clock::Clock::new();
 
-        // Load ROM into memory.
-        let mapper = rom.get_mapper();
+        let metadata = metadata::Metadata::new(&rom, region);
 
         // Create RAM modules.
         let ram = Rc::new(RefCell::new(memory::Memory::new_ram(0x800)));
-        let sram = Rc::new(RefCell::new(memory::Memory::new_ram(0x2000)));
+        // No mapper here banks PRG-RAM, so only 8KB of a bigger chip could ever be seen.  NES 2.0
+        // sizes add up volatile and battery backed RAM, which can come to a size no chip has.
+        let sram_size = (rom.prg_ram_size_bytes() as usize)
+            .next_power_of_two()
+            .min(ines::PRG_RAM_SIZE);
+        let sram = Rc::new(RefCell::new(memory::Memory::new_ram(sram_size)));
+        if let Some(image) = rom.default_prg_ram() {
+            sram.borrow_mut().load(&image);
+        }
+        let battery = rom.has_battery();
+        let prg_rom_len = rom.prg_rom_size_bytes() as usize;
+        let expansion_ram = if config.expansion_ram {
+            Some(Rc::new(RefCell::new(memory::Memory::new_ram(
+                memory::EXPANSION_RAM_SIZE,
+            ))))
+        } else {
+            None
+        };
         let vram = Rc::new(RefCell::new(memory::Memory::new_ram(0x2000)));
 
         // Create graphics output module and PPU.
@@ -81,36 +301,25 @@ impl NES {
             ppu_memory,
             Box::new(screen.clone()),
         )));
+        ppu.borrow_mut().set_region(region);
 
         // Create APU.
         let apu = Rc::new(RefCell::new(apu::APU::new(
-            Box::new(audio),
+            audio,
             Box::new(memory::PrgMapper::new(mapper.clone())),
         )));
+        apu.borrow_mut().set_region(region);
+        let has_expansion_audio = mapper.borrow_mut().expansion_audio().is_some();
+        let cartridge_audio = if has_expansion_audio {
+            Some(
+                apu.borrow_mut()
+                    .add_expansion_audio(Box::new(mapper.clone()), 1.0),
+            )
+        } else {
+            None
+        };
 
-        // Create controllers.
-        let joy1 = Rc::new(RefCell::new(controller::Controller::new(
-            [
-                (Key::Z, Button::A),
-                (Key::X, Button::B),
-                (Key::A, Button::Start),
-                (Key::S, Button::Select),
-                (Key::Up, Button::Up),
-                (Key::Down, Button::Down),
-                (Key::Left, Button::Left),
-                (Key::Right, Button::Right),
-            ]
-            .iter()
-            .cloned()
-            .collect(),
-        )));
-
-        let joy2 = Rc::new(RefCell::new(controller::Controller::new(
-            [].iter().cloned().collect(),
-        )));
-
-        event_bus.borrow_mut().register(Box::new(joy1.clone()));
-        event_bus.borrow_mut().register(Box::new(joy2.clone()));
+        let [joy1, joy2, joy3, joy4] = joypads;
 
         // Create CPU.
         let io_registers = Rc::new(RefCell::new(memory::IORegisters::new(
@@ -119,27 +328,53 @@ impl NES {
             Box::new(joy2.clone()),
         )));
 
-        let cpu_memory = memory::CPUMemory::new(
+        let cheats = Rc::new(RefCell::new(cheats::Cheats::new()));
+        let mut cpu_memory = memory::CPUMemory::new(
             Box::new(ram.clone()),
             Box::new(ppu.clone()),
             Box::new(io_registers.clone()),
             Box::new(sram.clone()),
             Box::new(memory::PrgMapper::new(mapper.clone())),
         );
+        cpu_memory.set_cheats(cheats.clone());
+        let bus_stats = Rc::new(RefCell::new(busstats::BusStats::new()));
+        cpu_memory.set_stats(bus_stats.clone());
+        cpu_memory.set_sram_size(sram_size);
+        if let Some(ref ram) = expansion_ram {
+            cpu_memory.set_expansion_ram(Box::new(ram.clone()));
+        }
 
         let cpu = Rc::new(RefCell::new(cpu::new(Box::new(cpu_memory))));
         cpu.borrow_mut().disable_bcd();
+        cpu.borrow_mut().set_illegal_opcodes(config.illegal_opcodes);
         cpu.borrow_mut().startup_sequence();
 
-        let dma_controller = DMAController::new(io_registers.clone(), cpu.clone());
+        let dma = Rc::new(RefCell::new(DMAController::new(
+            io_registers.clone(),
+            cpu.clone(),
+            apu.clone(),
+        )));
+
+        // Wire up the clock timings.  The CPU drives the PPU and APU itself, catching them up on
+        // every bus cycle, so its memory accesses see them at exactly the right time.
+        let mut bus_clock = clock::Clock::new();
+        let ppu_ticker = clock::ScaledTicker::new(Box::new(ppu.clone()), region.ppu_clock_factor());
+        let apu_ticker = clock::ScaledTicker::new(Box::new(apu.clone()), region.apu_clock_factor());
+        bus_clock.manage(apu_ticker);
+        bus_clock.manage(ppu_ticker);
+        cpu.borrow_mut()
+            .set_bus_clock(clock::BusClock::new(bus_clock, region.cpu_clock_factor()));
+
+        // Instructions are traced between bus cycles, when nothing else has the PPU borrowed.
+        let trace_ppu = ppu.clone();
+        cpu.borrow_mut().set_trace_position(Box::new(move || {
+            trace_ppu
+                .try_borrow()
+                .map_or((0, 0), |ppu| (ppu.scanline, ppu.cycle))
+        }));
 
-        // Wire up the clock timings.
-        let cpu_ticker = clock::ScaledTicker::new(Box::new(dma_controller), NES_CPU_CLOCK_FACTOR);
-        let ppu_ticker = clock::ScaledTicker::new(Box::new(ppu.clone()), NES_PPU_CLOCK_FACTOR);
-        let apu_ticker = clock::ScaledTicker::new(Box::new(apu.clone()), NES_APU_CLOCK_FACTOR);
+        let cpu_ticker = clock::ScaledTicker::new(Box::new(dma.clone()), region.cpu_clock_factor());
         clock.manage(cpu_ticker);
-        clock.manage(apu_ticker);
-        clock.manage(ppu_ticker);
 
         NES {
             clock,
@@ -149,37 +384,257 @@ impl NES {
             mapper,
             ram,
             sram,
+            expansion_ram,
             vram,
             screen,
             joy1,
             joy2,
+            joy3,
+            joy4,
+            four_score: false,
+            cheats,
+            region,
+            metadata,
+            battery,
             nmi_pin: false,
+            dma,
+            save_requested: false,
+            captured_state: None,
+            breakpoints: BTreeSet::new(),
+            cartridge_audio,
+            heatmap: None,
+            bus_stats,
+            prg_rom_len,
+            code_data_log: None,
+            chr_banks: None,
+            vram_write_log: None,
+            watchpoints: None,
+            event_log: None,
+            config: config.clone(),
         }
     }
 
+    // Mixing level for the cartridge's own sound channels.  Does nothing for carts without any.
+    pub fn set_expansion_audio_gain(&mut self, gain: f32) {
+        if let Some(ix) = self.cartridge_audio {
+            self.apu.borrow_mut().set_expansion_gain(ix, gain);
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn config(&self) -> &config::Config {
+        &self.config
+    }
+
+    // What to stamp on movies and save states, to check against when they're loaded.
+    pub fn metadata(&self) -> metadata::Metadata {
+        self.metadata.clone()
+    }
+
+    // PRG-RAM contents to write to a save file, then any expansion RAM, or None if the cart has
+    // no battery.
+    pub fn battery_ram(&self) -> Option<Vec<u8>> {
+        if !self.battery {
+            return None;
+        }
+        let mut data = self.sram.borrow().bytes().to_vec();
+        if let Some(ref ram) = self.expansion_ram {
+            data.extend_from_slice(ram.borrow().bytes());
+        }
+        Some(data)
+    }
+
+    // Restores a save file over whatever default save the ROM came with.  A short file only
+    // replaces the start of PRG-RAM.  Call before the first tick.
+    pub fn load_battery_ram(&mut self, data: &[u8]) {
+        let (prg_ram, rest) = data.split_at(data.len().min(self.sram.borrow().len()));
+        self.sram.borrow_mut().load(prg_ram);
+        if let Some(ref ram) = self.expansion_ram {
+            ram.borrow_mut().load(rest);
+        }
+    }
+
+    // Side effect free access to CPU memory, which can be kept and used between ticks.
+    pub fn debug_memory(&self) -> debugmem::DebugMemory {
+        debugmem::DebugMemory::new(
+            self.ram.clone(),
+            self.sram.clone(),
+            self.expansion_ram.clone(),
+            self.mapper.clone(),
+        )
+    }
+
+    // Reads CPU memory without side effects, for tools watching the game.  Registers read as 0,
+    // and cheats aren't applied.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.debug_memory().peek(address)
+    }
+
+    // Hex dump of CPU memory through `peek`, so it's safe to call at any time.  Stops at the top
+    // of the address space.
+    pub fn hexdump(&self, start: u16, len: usize, options: &hexdump::HexDumpOptions) -> String {
+        let end = (start as usize).saturating_add(len).min(0x10000);
+        let memory = self.debug_memory();
+        hexdump::hexdump(start as usize, end, options, |address| {
+            memory.peek(address as u16)
+        })
+    }
+
+    // Instructions either side of `pc`, read through `peek`.  See `disassemble_around` for how the
+    // ones before are guessed.
+    pub fn disassemble_around(
+        &self,
+        pc: u16,
+        before: usize,
+        after: usize,
+    ) -> Vec<cpu::disassembler::Instruction> {
+        cpu::disassembler::disassemble_around(&mut Peeker(self), pc, before, after)
+    }
+
+    // Counterpart to `peek` for debug tools.  Only RAM and cartridge RAM can be poked, since writes
+    // anywhere else would hit registers.
+    pub fn poke(&mut self, address: u16, byte: u8) {
+        self.debug_memory().poke(address, byte);
+    }
+
+    // Same again for the PPU's address space.
+    pub fn peek_ppu(&self, address: u16) -> u8 {
+        self.ppu.borrow_mut().peek_vram(address)
+    }
+
+    pub fn poke_ppu(&mut self, address: u16, byte: u8) {
+        self.ppu.borrow_mut().poke_vram(address, byte);
+    }
+
     #[inline]
     pub fn tick(&mut self) -> u64 {
         let cycles = self.clock.tick();
-        if self.ppu.borrow().nmi_triggered() {
+        let (nmi, fault) = {
+            let mut ppu = self.ppu.borrow_mut();
+            (ppu.nmi_triggered(), ppu.take_fault())
+        };
+        if let Some(fault) = fault {
+            self.log_event(
+                eventlog::Severity::Error,
+                eventlog::Subsystem::Ppu,
+                fault.to_string(),
+            );
+        }
+        if nmi {
             if self.nmi_pin == false {
                 self.cpu.borrow_mut().trigger_nmi();
                 self.nmi_pin = true;
+                if let Some(ref log) = self.event_log {
+                    log.borrow_mut().record(
+                        eventlog::Severity::Debug,
+                        eventlog::Subsystem::Ppu,
+                        String::from("NMI"),
+                    );
+                }
             }
         } else {
             self.nmi_pin = false;
         }
 
-        if self.apu.borrow().irq_triggered() {
+        let apu_irq = self.apu.borrow().irq_triggered();
+        if apu_irq {
             self.cpu.borrow_mut().trigger_irq();
         }
 
-        if self.mapper.borrow().irq_triggered() {
+        let mapper_irq = self.mapper.borrow().irq_triggered();
+        if mapper_irq {
             self.cpu.borrow_mut().trigger_irq();
         }
 
+        if let Some(ref log) = self.event_log {
+            log.borrow_mut().irq_lines(apu_irq, mapper_irq);
+        }
+
+        if self.chr_banks.is_some() || self.event_log.is_some() {
+            let switched = self.mapper.borrow_mut().take_bank_switch();
+            if switched {
+                self.bank_switched();
+            }
+        }
+
+        if self.save_requested {
+            self.capture_requested_state();
+        }
+
         cycles
     }
 
+    // False while an OAM DMA is stalling the CPU.  The copy's progress isn't part of a save state,
+    // so states must only be taken between instructions.
+    pub fn at_instruction_boundary(&self) -> bool {
+        !self.dma.borrow().in_progress()
+    }
+
+    fn bank_switched(&mut self) {
+        let mapper = self.mapper.borrow();
+        let chr_banks = mapper.chr_banks();
+        if let (Some(tracker), Some(banks)) = (self.chr_banks.as_mut(), chr_banks) {
+            tracker.record(banks);
+        }
+        if let Some(ref log) = self.event_log {
+            // Which 8KB of PRG-ROM each quarter of $8000-$FFFF reads from.
+            let prg: Vec<String> = [0x8000, 0xA000, 0xC000, 0xE000]
+                .iter()
+                .map(|address| match mapper.prg_rom_offset(*address) {
+                    Some(offset) => format!("{:02X}", offset / 0x2000),
+                    None => String::from("--"),
+                })
+                .collect();
+            let mut message = format!("Banks switched: PRG {}", prg.join(" "));
+            if let Some(banks) = chr_banks {
+                let chr: Vec<String> = banks.iter().map(|bank| format!("{:02X}", bank)).collect();
+                message.push_str(&format!(" CHR {}", chr.join(" ")));
+            }
+            log.borrow_mut().record(
+                eventlog::Severity::Info,
+                eventlog::Subsystem::Mapper,
+                message,
+            );
+        }
+    }
+
+    // Asks for a save state at the next instruction boundary, which may be right now.  Collect it
+    // with `take_save_state` once it's ready.  Safe to call at any point between ticks.
+    pub fn request_save_state(&mut self) {
+        self.save_requested = true;
+        self.capture_requested_state();
+    }
+
+    pub fn take_save_state(&mut self) -> Option<NESState> {
+        self.captured_state.take()
+    }
+
+    fn capture_requested_state(&mut self) {
+        if self.at_instruction_boundary() {
+            self.save_requested = false;
+            self.captured_state = Some(self.freeze());
+        }
+    }
+
+    // Master cycles run so far.  The CPU has finished every instruction before this point, and the
+    // PPU and APU have been run right up to it.
+    pub fn master_cycle(&self) -> u64 {
+        self.clock.next_cycle()
+    }
+
+    // Runs until `master_cycle` and returns the cycle it stopped on.  Instructions aren't split, so
+    // this can go past the target by up to one instruction, but the PPU and APU are always caught
+    // up exactly with the CPU.  Does nothing if the target has already gone by.
+    pub fn tick_to(&mut self, master_cycle: u64) -> u64 {
+        while self.master_cycle() < master_cycle {
+            self.tick();
+        }
+        self.master_cycle()
+    }
+
     pub fn tick_multi(&mut self, ticks: u32) -> u64 {
         let mut cycles = 0u64;
         for _ in 0..ticks {
@@ -188,6 +643,404 @@ impl NES {
         cycles
     }
 
+    // Runs for at least `cycles` master cycles, stopping early if a breakpoint is hit.  Prefer this
+    // to calling `tick` in a loop from frontends.
+    pub fn run_cycles(&mut self, cycles: u64) -> RunResult {
+        self.run(cycles, false)
+    }
+
+    // Like `run_cycles`, but also stops as soon as a frame ends, so that input can be changed
+    // exactly between frames.
+    pub fn run_cycles_within_frame(&mut self, cycles: u64) -> RunResult {
+        self.run(cycles, true)
+    }
+
+    fn run(&mut self, cycles: u64, stop_at_frame_end: bool) -> RunResult {
+        let start_frame = self.ppu.borrow().stats().frame_count;
+        let stepping = !self.breakpoints.is_empty() || self.watchpoints.is_some();
+        let mut pc = self.cpu.borrow().pc();
+        let mut result = RunResult::default();
+
+        while result.cycles < cycles {
+            // Ticks in batches, only looking at the frame count once enough cycles have gone by
+            // that a frame could have ended.
+            let mut batch_end = cycles;
+            if stop_at_frame_end {
+                batch_end = batch_end.min(result.cycles + self.cycles_before_frame_end().max(1));
+            }
+
+            if stepping {
+                while result.cycles < batch_end {
+                    result.cycles += self.tick();
+
+                    // Only trigger on arriving at the address, so that running again resumes.
+                    let new_pc = self.cpu.borrow().pc();
+                    if new_pc != pc && self.breakpoints.contains(&new_pc) {
+                        result.breakpoint = Some(new_pc);
+                        break;
+                    }
+                    pc = new_pc;
+                    if let Some(ref watchpoints) = self.watchpoints {
+                        if watchpoints.borrow().has_hits() {
+                            result.watchpoint_hit = true;
+                            break;
+                        }
+                    }
+                }
+                if result.breakpoint.is_some() || result.watchpoint_hit {
+                    break;
+                }
+            } else {
+                while result.cycles < batch_end {
+                    result.cycles += self.tick();
+                }
+            }
+
+            if stop_at_frame_end && self.ppu.borrow().stats().frame_count != start_frame {
+                break;
+            }
+        }
+
+        result.frames_completed = self.ppu.borrow().stats().frame_count - start_frame;
+        for _ in 0..result.frames_completed {
+            self.end_frame();
+        }
+        result
+    }
+
+    // Master cycles which can certainly be run without a frame ending.  Kept a scanline short, as
+    // the PPU can be a few dots either side of the master clock between ticks.
+    fn cycles_before_frame_end(&self) -> u64 {
+        let dots = self.ppu.borrow().dots_until_frame_end().saturating_sub(341);
+        u64::from(dots) * u64::from(self.region.ppu_clock_factor())
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    // Stops `run_cycles` after any instruction which makes this kind of access to `address`.
+    // Collect what happened with `take_memory_accesses`.
+    pub fn add_watchpoint(&mut self, address: u16, access: watchpoints::Access) {
+        let watchpoints = match self.watchpoints {
+            Some(ref watchpoints) => watchpoints.clone(),
+            None => {
+                let watchpoints = Rc::new(RefCell::new(watchpoints::Watchpoints::default()));
+                self.cpu
+                    .borrow_mut()
+                    .set_watchpoints(Some(watchpoints.clone()));
+                self.watchpoints = Some(watchpoints.clone());
+                watchpoints
+            }
+        };
+        watchpoints.borrow_mut().watch(address, access);
+    }
+
+    // Checking every access costs a little, so it stops once nothing is watched.
+    pub fn remove_watchpoint(&mut self, address: u16, access: watchpoints::Access) {
+        let empty = match self.watchpoints {
+            None => return,
+            Some(ref watchpoints) => {
+                watchpoints.borrow_mut().unwatch(address, access);
+                watchpoints.borrow().is_empty()
+            }
+        };
+        if empty {
+            self.cpu.borrow_mut().set_watchpoints(None);
+            self.watchpoints = None;
+        }
+    }
+
+    // Accesses to watched addresses since the last call, oldest first.
+    pub fn take_memory_accesses(&mut self) -> Vec<watchpoints::MemoryAccess> {
+        self.watchpoints.as_ref().map_or(Vec::new(), |watchpoints| {
+            watchpoints.borrow_mut().take_hits()
+        })
+    }
+
+    // Runs until the PPU starts a new frame.  Returns cycles elapsed.
+    pub fn run_frame(&mut self) -> u64 {
+        let frame = self.ppu.borrow().stats().frame_count;
+        let mut cycles = 0u64;
+        while self.ppu.borrow().stats().frame_count == frame {
+            cycles += self.tick();
+        }
+        self.end_frame();
+        cycles
+    }
+
+    fn end_frame(&mut self) {
+        if let Some(ref heatmap) = self.heatmap {
+            heatmap.borrow_mut().end_frame();
+        }
+        self.bus_stats.borrow_mut().end_frame();
+        if let Some(ref mut tracker) = self.chr_banks {
+            if let Some(banks) = self.mapper.borrow().chr_banks() {
+                tracker.end_frame(banks);
+            }
+        }
+        self.joy1.borrow_mut().end_frame();
+        self.joy2.borrow_mut().end_frame();
+        self.joy3.borrow_mut().end_frame();
+        self.joy4.borrow_mut().end_frame();
+    }
+
+    // Starts counting CPU memory accesses over the last `frames` frames.  Slows emulation a little,
+    // so it's off until asked for.
+    pub fn enable_heatmap(&mut self, frames: usize) {
+        let heatmap = Rc::new(RefCell::new(heatmap::AccessHeatmap::new(frames)));
+        self.cpu.borrow_mut().set_heatmap(Some(heatmap.clone()));
+        self.heatmap = Some(heatmap);
+    }
+
+    pub fn disable_heatmap(&mut self) {
+        self.cpu.borrow_mut().set_heatmap(None);
+        self.heatmap = None;
+    }
+
+    pub fn heatmap(&self) -> Option<Ref<'_, heatmap::AccessHeatmap>> {
+        self.heatmap.as_ref().map(|heatmap| heatmap.borrow())
+    }
+
+    // Starts counting CPU bus accesses to each device, frame by frame.  Starts again from nothing
+    // if already counting.
+    pub fn enable_bus_stats(&mut self) {
+        self.bus_stats.borrow_mut().set_enabled(true);
+        self.cpu
+            .borrow_mut()
+            .set_bus_stats(Some(self.bus_stats.clone()));
+    }
+
+    pub fn disable_bus_stats(&mut self) {
+        self.bus_stats.borrow_mut().set_enabled(false);
+        self.cpu.borrow_mut().set_bus_stats(None);
+    }
+
+    pub fn bus_stats(&self) -> Option<Ref<'_, busstats::BusStats>> {
+        let stats = self.bus_stats.borrow();
+        if stats.enabled() {
+            Some(stats)
+        } else {
+            None
+        }
+    }
+
+    // Starts marking which bytes of PRG-ROM are run as code and which are read as data.  Keeps
+    // the existing log if there is one.
+    pub fn enable_code_data_log(&mut self) {
+        if self.code_data_log.is_some() {
+            return;
+        }
+        let cdl = Rc::new(RefCell::new(cdl::CodeDataLog::new(
+            self.mapper.clone(),
+            self.prg_rom_len,
+        )));
+        self.cpu.borrow_mut().set_code_data_log(Some(cdl.clone()));
+        self.code_data_log = Some(cdl);
+    }
+
+    pub fn disable_code_data_log(&mut self) {
+        self.cpu.borrow_mut().set_code_data_log(None);
+        self.code_data_log = None;
+    }
+
+    pub fn code_data_log(&self) -> Option<Ref<'_, cdl::CodeDataLog>> {
+        self.code_data_log.as_ref().map(|cdl| cdl.borrow())
+    }
+
+    pub fn clear_code_data_log(&mut self) {
+        if let Some(ref cdl) = self.code_data_log {
+            cdl.borrow_mut().clear();
+        }
+    }
+
+    pub fn prg_rom_len(&self) -> usize {
+        self.prg_rom_len
+    }
+
+    // Where in PRG-ROM a CPU address is currently mapped from, if anywhere.
+    pub fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+        self.mapper.borrow().prg_rom_offset(address)
+    }
+
+    // Starts following CHR bank switches.  Does nothing for mappers which don't switch CHR.
+    pub fn enable_chr_bank_tracking(&mut self) {
+        self.chr_banks = self
+            .mapper
+            .borrow()
+            .chr_banks()
+            .map(chrbanks::ChrBankTracker::new);
+    }
+
+    pub fn disable_chr_bank_tracking(&mut self) {
+        self.chr_banks = None;
+    }
+
+    pub fn chr_bank_tracker(&self) -> Option<&chrbanks::ChrBankTracker> {
+        self.chr_banks.as_ref()
+    }
+
+    // Starts logging writes to PPUDATA made while the PPU is rendering.
+    pub fn enable_vram_write_check(&mut self) {
+        let log = Rc::new(RefCell::new(vramcheck::VramWriteLog::new(self.ppu.clone())));
+        self.cpu.borrow_mut().set_vram_write_log(Some(log.clone()));
+        self.vram_write_log = Some(log);
+    }
+
+    pub fn disable_vram_write_check(&mut self) {
+        self.cpu.borrow_mut().set_vram_write_log(None);
+        self.vram_write_log = None;
+    }
+
+    // Writes made while rendering since the last call.  Empty if the check is off.
+    pub fn take_rendering_writes(&mut self) -> Vec<vramcheck::RenderingWrite> {
+        self.vram_write_log
+            .as_ref()
+            .map_or_else(Vec::new, |log| log.borrow_mut().take_writes())
+    }
+
+    // Starts keeping the last `capacity` notable events: interrupts, bank switches, changes to
+    // the main PPU and APU registers and CPU jams.  Keeps the existing log if there is one.
+    pub fn enable_event_log(&mut self, capacity: usize) {
+        if self.event_log.is_some() {
+            return;
+        }
+        let log = Rc::new(RefCell::new(eventlog::EventLog::new(
+            self.ppu.clone(),
+            capacity,
+        )));
+        self.cpu.borrow_mut().set_event_log(Some(log.clone()));
+        self.event_log = Some(log);
+    }
+
+    pub fn disable_event_log(&mut self) {
+        self.cpu.borrow_mut().set_event_log(None);
+        self.event_log = None;
+    }
+
+    pub fn event_log(&self) -> Option<Ref<'_, eventlog::EventLog>> {
+        self.event_log.as_ref().map(|log| log.borrow())
+    }
+
+    pub fn clear_event_log(&mut self) {
+        if let Some(ref log) = self.event_log {
+            log.borrow_mut().clear();
+        }
+    }
+
+    // Adds to the event log, if it's on.  Frontends can log their own problems here too, so they
+    // show up alongside what the emulator was doing.
+    pub fn log_event(
+        &self,
+        severity: eventlog::Severity,
+        subsystem: eventlog::Subsystem,
+        message: String,
+    ) {
+        if let Some(ref log) = self.event_log {
+            log.borrow_mut().record(severity, subsystem, message);
+        }
+    }
+
+    // Plugs controllers 3 and 4 in behind 1 and 2, for four player games.
+    pub fn set_four_score(&mut self, enabled: bool) {
+        self.four_score = enabled;
+        if enabled {
+            self.joy1
+                .borrow_mut()
+                .plug_in_four_score(self.joy3.clone(), controller::FOUR_SCORE_SIGNATURES[0]);
+            self.joy2
+                .borrow_mut()
+                .plug_in_four_score(self.joy4.clone(), controller::FOUR_SCORE_SIGNATURES[1]);
+        } else {
+            self.joy1.borrow_mut().unplug_four_score();
+            self.joy2.borrow_mut().unplug_four_score();
+        }
+    }
+
+    pub fn four_score(&self) -> bool {
+        self.four_score
+    }
+
+    pub fn joypads(&self) -> [Rc<RefCell<controller::Controller>>; 4] {
+        [
+            self.joy1.clone(),
+            self.joy2.clone(),
+            self.joy3.clone(),
+            self.joy4.clone(),
+        ]
+    }
+
+    // Set once the CPU has run an opcode which stops it, until the next reset.
+    pub fn cpu_jam(&self) -> Option<cpu::Jam> {
+        self.cpu.borrow().jam()
+    }
+
+    // Swaps the cartridge, as if the power had been cycled with the new one in.  The joypads,
+    // screen, audio output, Four Score and mixer settings carry on, as do any debugging aids which
+    // were on and the size of the CPU trace.  Breakpoints, watchpoints and cheats belonged to the
+    // old game, so they go with it.  The CPU, PPU, APU and mapper are all new, so anything holding
+    // on to the old ones needs to fetch them again.  Fails, leaving the old cartridge in, if the
+    // new one can't be emulated.
+    pub fn load_rom(&mut self, rom: ines::ROM) -> Result<(), String> {
+        let config = self.config.clone();
+        self.load_rom_with_config(rom, &config)
+    }
+
+    // Like `load_rom`, for games which need their own settings, e.g. a different region.
+    pub fn load_rom_with_config(
+        &mut self,
+        rom: ines::ROM,
+        config: &config::Config,
+    ) -> Result<(), String> {
+        let mapper = rom.get_mapper()?;
+        let audio = self.apu.borrow_mut().take_output();
+        let mut nes = NES::build(
+            self.joypads(),
+            self.screen.clone(),
+            audio,
+            rom,
+            mapper,
+            config,
+        );
+
+        nes.set_four_score(self.four_score);
+        let trace_capacity = self.cpu.borrow().trace_capacity();
+        nes.cpu.borrow_mut().set_trace_capacity(trace_capacity);
+        let mixer = self.apu.borrow().mixer_control();
+        nes.apu.borrow_mut().set_mixer_control(mixer);
+        if let Some(ref heatmap) = self.heatmap {
+            nes.enable_heatmap(heatmap.borrow().window());
+        }
+        if self.bus_stats.borrow().enabled() {
+            nes.enable_bus_stats();
+        }
+        if self.code_data_log.is_some() {
+            nes.enable_code_data_log();
+        }
+        if self.chr_banks.is_some() {
+            nes.enable_chr_bank_tracking();
+        }
+        if self.vram_write_log.is_some() {
+            nes.enable_vram_write_check();
+        }
+        if let Some(ref log) = self.event_log {
+            nes.enable_event_log(log.borrow().capacity());
+        }
+        *self = nes;
+        Ok(())
+    }
+
     pub fn reset(&mut self) {
         // Silence APU.
         self.apu.borrow_mut().write(0x4015, 0x00);
@@ -197,25 +1050,47 @@ impl NES {
     }
 }
 
+// Lets the disassembler read memory without side effects.
+struct Peeker<'a>(&'a NES);
+
+impl Reader for Peeker<'_> {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0.peek(address)
+    }
+}
+
+// CPU cycles lost to each DMC sample fetch.  During OAM DMA the CPU is already halted, so the
+// fetch only costs the cycles it takes on the bus.
+const DMC_STALL_CYCLES: u32 = 4;
+const DMC_STALL_CYCLES_DURING_OAM_DMA: u32 = 2;
+
 pub struct DMAController {
     copies_remaining: u16,
     base_address: u16,
     io_registers: Rc<RefCell<IORegisters>>,
     cpu: Rc<RefCell<cpu::CPU>>,
+    apu: Rc<RefCell<apu::APU>>,
 }
 
 impl DMAController {
     pub fn new(
         io_registers: Rc<RefCell<IORegisters>>,
         cpu: Rc<RefCell<cpu::CPU>>,
+        apu: Rc<RefCell<apu::APU>>,
     ) -> DMAController {
         DMAController {
             copies_remaining: 0,
             base_address: 0,
             io_registers,
             cpu,
+            apu,
         }
     }
+
+    // Includes a DMA which has been requested but not started yet.
+    pub fn in_progress(&self) -> bool {
+        self.copies_remaining > 0 || self.io_registers.borrow().oamdma_pending()
+    }
 }
 
 impl clock::Ticker for DMAController {
@@ -229,15 +1104,23 @@ impl clock::Ticker for DMAController {
             }
         }
 
+        // The DMC reads its samples part way through an instruction, but the stall is taken
+        // once the instruction is done.
+        let dmc_reads = self.apu.borrow_mut().take_dmc_dma();
+        if dmc_reads > 0 {
+            let stall = if self.copies_remaining > 0 {
+                DMC_STALL_CYCLES_DURING_OAM_DMA
+            } else {
+                DMC_STALL_CYCLES
+            };
+            return self.cpu.borrow_mut().stall(dmc_reads * stall);
+        }
+
         if self.copies_remaining > 0 {
             // CPU is suspended during copy.
-            let byte = self
-                .cpu
-                .borrow_mut()
-                .load_memory(self.base_address.wrapping_add(256 - self.copies_remaining));
-            self.cpu.borrow_mut().store_memory(0x2004, byte);
+            let from = self.base_address.wrapping_add(256 - self.copies_remaining);
             self.copies_remaining -= 1;
-            2
+            self.cpu.borrow_mut().dma_copy(from, 0x2004)
         } else {
             self.cpu.borrow_mut().tick()
         }
@@ -253,9 +1136,14 @@ impl<'de> SaveState<'de, NESState> for NES {
             ram: self.ram.borrow_mut().freeze(),
             sram: self.sram.borrow_mut().freeze(),
             vram: self.vram.borrow_mut().freeze(),
-            screen: self.screen.borrow_mut().freeze(),
+            expansion_ram: self
+                .expansion_ram
+                .as_ref()
+                .map(|ram| ram.borrow_mut().freeze()),
             joy1: self.joy1.borrow_mut().freeze(),
             joy2: self.joy2.borrow_mut().freeze(),
+            nmi_pin: self.nmi_pin,
+            metadata: Some(self.metadata()),
         }
     }
 
@@ -266,8 +1154,14 @@ impl<'de> SaveState<'de, NESState> for NES {
         self.ram.borrow_mut().hydrate(state.ram);
         self.sram.borrow_mut().hydrate(state.sram);
         self.vram.borrow_mut().hydrate(state.vram);
-        self.screen.borrow_mut().hydrate(state.screen);
+        if let (Some(ram), Some(saved)) = (self.expansion_ram.as_ref(), state.expansion_ram) {
+            ram.borrow_mut().hydrate(saved);
+        }
         self.joy1.borrow_mut().hydrate(state.joy1);
         self.joy2.borrow_mut().hydrate(state.joy2);
+        self.nmi_pin = state.nmi_pin;
+        // Anything captured before the load is from the old timeline.
+        self.save_requested = false;
+        self.captured_state = None;
     }
 }
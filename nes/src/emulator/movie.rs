@@ -0,0 +1,397 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::emulator::metadata::{AccuracyConfig, Metadata};
+use crate::emulator::{Region, NES};
+
+// Controller input for a single frame.  Button masks use the controller's strobe order, so bit 0
+// is A and bit 7 is Right.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FrameInput {
+    pub reset: bool,
+    pub joy1: u8,
+    pub joy2: u8,
+    // Only used with a Four Score.
+    pub joy3: u8,
+    pub joy4: u8,
+}
+
+// A recorded sequence of per-frame inputs, starting from power-on.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Movie {
+    pub rom_filename: String,
+    pub rom_checksum: Option<String>,
+    pub pal: bool,
+    pub four_score: bool,
+    pub rerecord_count: u32,
+    // How the recording emulator was set up.  Missing from movies made elsewhere.
+    pub metadata: Option<Metadata>,
+    pub frames: Vec<FrameInput>,
+}
+
+// FM2 writes buttons as "RLDUTSBA", i.e. the reverse of our bit order.
+const FM2_BUTTONS: [char; 8] = ['R', 'L', 'D', 'U', 'T', 'S', 'B', 'A'];
+
+fn buttons_to_fm2(buttons: u8) -> String {
+    FM2_BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(ix, c)| if buttons & (0x80 >> ix) != 0 { *c } else { '.' })
+        .collect()
+}
+
+fn buttons_from_fm2(field: &str) -> Result<u8, String> {
+    if field.is_empty() {
+        return Ok(0);
+    }
+
+    if field.chars().count() != 8 {
+        return Err(format!("Bad controller field: '{}'", field));
+    }
+
+    // Anything other than a space or '.' counts as pressed.
+    Ok(field
+        .chars()
+        .enumerate()
+        .filter(|(_, c)| *c != '.' && *c != ' ')
+        .fold(0, |acc, (ix, _)| acc | (0x80 >> ix)))
+}
+
+impl Movie {
+    pub fn new(rom_filename: &str, pal: bool) -> Movie {
+        Movie {
+            rom_filename: String::from(rom_filename),
+            pal,
+            ..Movie::default()
+        }
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut out = String::new();
+        out.push_str("version 3\n");
+        out.push_str("emuVersion 0\n");
+        out.push_str(&format!("rerecordCount {}\n", self.rerecord_count));
+        out.push_str(&format!("palFlag {}\n", if self.pal { 1 } else { 0 }));
+        out.push_str(&format!("romFilename {}\n", self.rom_filename));
+        if let Some(ref checksum) = self.rom_checksum {
+            out.push_str(&format!("romChecksum {}\n", checksum));
+        }
+        out.push_str("guid 00000000-0000-0000-0000-000000000000\n");
+        out.push_str(&format!(
+            "fourscore {}\n",
+            if self.four_score { 1 } else { 0 }
+        ));
+        out.push_str("port0 1\n");
+        out.push_str("port1 1\n");
+        out.push_str("port2 0\n");
+        // Our own keys, which other emulators skip over.
+        if let Some(ref metadata) = self.metadata {
+            out.push_str(&format!("nesEmuVersion {}\n", metadata.emulator_version));
+            out.push_str(&format!("nesRomCrc32 {:08X}\n", metadata.rom_crc32));
+            out.push_str(&format!("nesRegion {:?}\n", metadata.accuracy.region));
+            out.push_str(&format!("nesMapper {}\n", metadata.accuracy.mapper));
+            out.push_str(&format!("nesSubmapper {}\n", metadata.accuracy.submapper));
+        }
+
+        for frame in self.frames.iter() {
+            out.push_str(&format!("|{}|", if frame.reset { 1 } else { 0 }));
+            let ports = if self.four_score {
+                vec![frame.joy1, frame.joy2, frame.joy3, frame.joy4]
+            } else {
+                vec![frame.joy1, frame.joy2]
+            };
+            for buttons in ports {
+                out.push_str(&buttons_to_fm2(buttons));
+                out.push('|');
+            }
+            out.push_str("|\n");
+        }
+
+        out
+    }
+
+    pub fn from_fm2(text: &str) -> Result<Movie, String> {
+        let mut movie = Movie::default();
+        let mut version = None;
+        let mut crc = None;
+        let mut region = None;
+        let mut mapper = None;
+        let mut submapper = None;
+
+        for (line_ix, line) in text.lines().enumerate() {
+            if line.starts_with('|') {
+                let fields: Vec<&str> = line.split('|').collect();
+                let ports = if movie.four_score { 4 } else { 2 };
+                if fields.len() < ports + 2 {
+                    return Err(format!("Line {}: malformed input record", line_ix + 1));
+                }
+                let port = |ix: usize| {
+                    if ix < ports {
+                        buttons_from_fm2(fields[ix + 2])
+                            .map_err(|e| format!("Line {}: {}", line_ix + 1, e))
+                    } else {
+                        Ok(0)
+                    }
+                };
+
+                let commands: u8 = fields[1]
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Line {}: bad command field", line_ix + 1))?;
+
+                movie.frames.push(FrameInput {
+                    // Soft and hard resets are treated alike.
+                    reset: commands & 0x03 != 0,
+                    joy1: port(0)?,
+                    joy2: port(1)?,
+                    joy3: port(2)?,
+                    joy4: port(3)?,
+                });
+                continue;
+            }
+
+            let mut parts = line.splitn(2, ' ');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("").trim();
+            match key {
+                "romFilename" => movie.rom_filename = String::from(value),
+                "romChecksum" => movie.rom_checksum = Some(String::from(value)),
+                "palFlag" => movie.pal = value == "1",
+                "rerecordCount" => movie.rerecord_count = value.parse().unwrap_or(0),
+                "fourscore" => movie.four_score = value == "1",
+                "nesEmuVersion" => version = Some(String::from(value)),
+                "nesRomCrc32" => crc = u32::from_str_radix(value, 16).ok(),
+                "nesRegion" => {
+                    region = match value {
+                        "NTSC" => Some(Region::NTSC),
+                        "PAL" => Some(Region::PAL),
+                        _ => return Err(format!("Unknown region '{}'", value)),
+                    }
+                }
+                "nesMapper" => mapper = value.parse().ok(),
+                "nesSubmapper" => submapper = value.parse().ok(),
+                _ => (),
+            }
+        }
+
+        // Only trusted if complete, since a partial set can't be checked properly.
+        if let (Some(version), Some(crc), Some(region), Some(mapper), Some(submapper)) =
+            (version, crc, region, mapper, submapper)
+        {
+            movie.metadata = Some(Metadata {
+                emulator_version: version,
+                rom_crc32: crc,
+                accuracy: AccuracyConfig {
+                    region,
+                    mapper,
+                    submapper,
+                },
+            });
+        }
+
+        Ok(movie)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MovieMode {
+    Recording,
+    Playback,
+}
+
+// Drives the NES a frame at a time, either capturing input into a movie or feeding it back out.
+// While a session is active the controllers ignore keyboard events, so input only ever changes at
+// frame boundaries and playback is exact.
+pub struct MovieSession {
+    movie: Movie,
+    mode: MovieMode,
+    frame_ix: usize,
+}
+
+impl MovieSession {
+    pub fn record(nes: &mut NES, mut movie: Movie) -> MovieSession {
+        movie.metadata = Some(nes.metadata());
+        movie.four_score = nes.four_score();
+        MovieSession::start(nes, movie, MovieMode::Recording)
+    }
+
+    pub fn play(nes: &mut NES, movie: Movie) -> MovieSession {
+        nes.set_four_score(movie.four_score);
+        MovieSession::start(nes, movie, MovieMode::Playback)
+    }
+
+    fn start(nes: &mut NES, movie: Movie, mode: MovieMode) -> MovieSession {
+        for joy in nes.joypads().iter() {
+            joy.borrow_mut().set_keyboard_enabled(false);
+        }
+        MovieSession {
+            movie,
+            mode,
+            frame_ix: 0,
+        }
+    }
+
+    pub fn mode(&self) -> MovieMode {
+        self.mode
+    }
+
+    pub fn frame_ix(&self) -> usize {
+        self.frame_ix
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.mode == MovieMode::Playback && self.frame_ix >= self.movie.frames.len()
+    }
+
+    // Latches the input for the next frame and runs until that frame is over.  When recording,
+    // `live` is the input to capture; during playback it is ignored.  Returns cycles elapsed.
+    pub fn step(&mut self, nes: &mut NES, live: FrameInput) -> u64 {
+        let input = match self.mode {
+            MovieMode::Recording => {
+                self.movie.frames.push(live);
+                live
+            }
+            MovieMode::Playback => match self.movie.frames.get(self.frame_ix) {
+                Some(input) => *input,
+                None => FrameInput::default(),
+            },
+        };
+        self.frame_ix += 1;
+
+        if input.reset {
+            nes.reset();
+        }
+        nes.joy1.borrow_mut().set_buttons(input.joy1);
+        nes.joy2.borrow_mut().set_buttons(input.joy2);
+        nes.joy3.borrow_mut().set_buttons(input.joy3);
+        nes.joy4.borrow_mut().set_buttons(input.joy4);
+        nes.run_frame()
+    }
+
+    // Hands control back to the keyboard and returns the movie.
+    pub fn finish(self, nes: &mut NES) -> Movie {
+        for joy in nes.joypads().iter() {
+            let mut joy = joy.borrow_mut();
+            joy.set_buttons(0);
+            joy.set_keyboard_enabled(true);
+        }
+        self.movie
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fm2_round_trip() {
+        let mut movie = Movie::new("game.nes", false);
+        movie.frames.push(FrameInput::default());
+        movie.frames.push(FrameInput {
+            reset: false,
+            joy1: 0x01 | 0x08 | 0x80, // A, Start, Right.
+            joy2: 0x10,               // Up.
+            ..FrameInput::default()
+        });
+        movie.frames.push(FrameInput {
+            reset: true,
+            joy1: 0,
+            joy2: 0,
+            ..FrameInput::default()
+        });
+
+        let text = movie.to_fm2();
+        assert!(text.contains("|0|R...T..A|...U....||\n"));
+        assert!(text.contains("|1|........|........||\n"));
+        assert_eq!(Movie::from_fm2(&text), Ok(movie));
+    }
+
+    #[test]
+    fn test_fm2_four_score_round_trip() {
+        let mut movie = Movie::new("game.nes", false);
+        movie.four_score = true;
+        movie.frames.push(FrameInput {
+            reset: false,
+            joy1: 0x01,
+            joy2: 0x02,
+            joy3: 0x04,
+            joy4: 0x08,
+        });
+
+        let text = movie.to_fm2();
+        assert!(text.contains("fourscore 1\n"));
+        assert!(text.contains("|0|.......A|......B.|.....S..|....T...||\n"));
+        assert_eq!(Movie::from_fm2(&text), Ok(movie));
+
+        // Every port has to be there.
+        let short = "fourscore 1\n|0|........|........|\n";
+        assert!(Movie::from_fm2(short).is_err());
+    }
+
+    #[test]
+    fn test_fm2_metadata_round_trip() {
+        let mut movie = Movie::new("game.nes", true);
+        movie.metadata = Some(Metadata {
+            emulator_version: String::from("1.2.3"),
+            rom_crc32: 0x0BAD_F00D,
+            accuracy: AccuracyConfig {
+                region: Region::PAL,
+                mapper: 4,
+                submapper: 1,
+            },
+        });
+
+        let text = movie.to_fm2();
+        assert!(text.contains("nesRomCrc32 0BADF00D\n"));
+        assert_eq!(Movie::from_fm2(&text), Ok(movie));
+
+        // A partial set is dropped rather than checked against.
+        let partial = text.replace("nesMapper 4\n", "");
+        assert_eq!(Movie::from_fm2(&partial).unwrap().metadata, None);
+    }
+
+    #[test]
+    fn test_fm2_import() {
+        let text = "version 3\n\
+                    palFlag 1\n\
+                    romFilename Some Game\n\
+                    romChecksum base64:AAAA\n\
+                    port0 1\n\
+                    port1 0\n\
+                    |0|.L..xx..|||\n\
+                    |2|........|||\n";
+        let movie = Movie::from_fm2(text).unwrap();
+        assert_eq!(movie.rom_filename, "Some Game");
+        assert_eq!(movie.rom_checksum, Some(String::from("base64:AAAA")));
+        assert!(movie.pal);
+        assert_eq!(
+            movie.frames,
+            vec![
+                FrameInput {
+                    reset: false,
+                    joy1: 0x40 | 0x08 | 0x04,
+                    joy2: 0,
+                    ..FrameInput::default()
+                },
+                FrameInput {
+                    reset: true,
+                    joy1: 0,
+                    joy2: 0,
+                    ..FrameInput::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fm2_rejects_garbage_input() {
+        assert!(Movie::from_fm2("|x|........|||\n").is_err());
+        assert!(Movie::from_fm2("|0|...|||\n").is_err());
+    }
+}
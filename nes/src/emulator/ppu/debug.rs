@@ -1,13 +1,195 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
 
 use crate::emulator::io::palette;
-use crate::emulator::memory::Reader;
+use crate::emulator::memory::{ChrBanks, MapperRef, Reader};
 use crate::emulator::ppu::flags;
-use crate::emulator::ppu::{Colour, PPU};
+use crate::emulator::ppu::{Colour, PPUFault, FRAME_HEIGHT, FRAME_WIDTH, PPU};
 
 pub struct PPUDebug {
     ppu: Rc<RefCell<PPU>>,
+    // Banks to show in the pattern tables instead of whatever's mapped in.
+    frozen_chr: Option<(MapperRef, ChrBanks)>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PPUStats {
+    pub frame_count: u64,
+    pub odd_frame: bool,
+    pub warmed_up: bool,
+    pub scanline: u16,
+    pub dot: u16,
+    pub fault_count: u64,
+    pub last_fault: Option<PPUFault>,
+}
+
+impl PPU {
+    pub fn stats(&self) -> PPUStats {
+        PPUStats {
+            frame_count: self.frame_count,
+            odd_frame: self.frame_count % 2 == 1,
+            warmed_up: self.warmed_up,
+            scanline: self.scanline,
+            dot: self.cycle,
+            fault_count: self.fault_count,
+            last_fault: self.last_fault,
+        }
+    }
+
+    // Where a write to PPUDATA would go right now, and whether it would clash with rendering.
+    pub fn vram_write_target(&self) -> (u16, bool) {
+        (self.v & 0x3FFF, self.is_rendering())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SpriteEvalPhase {
+    // Filling secondary OAM.
+    Copy,
+    // Secondary OAM is full, looking for a ninth sprite.
+    Overflow,
+}
+
+// One byte of OAM compared against the scanline as a sprite's Y.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SpriteCheck {
+    pub dot: u16,
+    pub phase: SpriteEvalPhase,
+    // Sprite and byte within it.  In the overflow phase `m` drifts, so the byte compared isn't
+    // always really a Y.
+    pub n: u8,
+    pub m: u8,
+    pub byte: u8,
+    pub in_range: bool,
+}
+
+// Everything sprite evaluation did on one scanline.  The sprites it picks are drawn on the next.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SpriteEvalTrace {
+    pub scanline: u16,
+    pub checks: Vec<SpriteCheck>,
+    // OAM indices of the sprites copied to secondary OAM, in order.
+    pub copied: Vec<u8>,
+    // The check which set the overflow flag, if any.
+    pub overflow: Option<SpriteCheck>,
+    pub secondary_oam: Vec<u8>,
+}
+
+// Records evaluation on a chosen scanline, keeping the most recent complete trace.
+#[derive(Default)]
+pub struct SpriteTracer {
+    scanline: Option<u16>,
+    building: Option<SpriteEvalTrace>,
+    last: Option<SpriteEvalTrace>,
+}
+
+impl SpriteTracer {
+    pub fn start(&mut self, scanline: u16) {
+        if self.scanline == Some(scanline) {
+            self.building = Some(SpriteEvalTrace {
+                scanline,
+                ..SpriteEvalTrace::default()
+            });
+        }
+    }
+
+    pub fn check(&mut self, check: SpriteCheck) {
+        if let Some(trace) = self.building.as_mut() {
+            trace.checks.push(check);
+            if check.in_range {
+                match check.phase {
+                    SpriteEvalPhase::Copy => trace.copied.push(check.n),
+                    SpriteEvalPhase::Overflow => {
+                        if trace.overflow.is_none() {
+                            trace.overflow = Some(check);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn finish(&mut self, secondary_oam: &[u8]) {
+        if let Some(mut trace) = self.building.take() {
+            trace.secondary_oam = secondary_oam.to_vec();
+            self.last = Some(trace);
+        }
+    }
+}
+
+impl PPU {
+    // Traces sprite evaluation on `scanline` every frame, or stops tracing if None.
+    pub fn set_sprite_trace_scanline(&mut self, scanline: Option<u16>) {
+        self.sprite_tracer = SpriteTracer {
+            scanline,
+            ..SpriteTracer::default()
+        };
+    }
+
+    pub fn sprite_trace(&self) -> Option<&SpriteEvalTrace> {
+        self.sprite_tracer.last.as_ref()
+    }
+}
+
+// A run of scanlines drawn from one window onto the nametables, each line one row below the last.
+// Games with a status bar have one above or below the playfield.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ScrollSplit {
+    pub first_line: u16,
+    pub lines: u16,
+    // Top left of the window, in pixels across the 512x480 plane of all four nametables.
+    pub x: u16,
+    pub y: u16,
+}
+
+impl PPU {
+    // Called on dot 320, just before the first tiles of the coming scanline are fetched.  By then
+    // the scroll has been reloaded from t, and any writes made during hblank have landed.
+    pub(super) fn record_scroll(&mut self) {
+        let scanline = match self.coming_scanline() {
+            None => return,
+            Some(scanline) => scanline,
+        };
+        self.scroll_lines[scanline as usize] = if self.rendering_is_enabled() {
+            let v = self.v;
+            let x = ((v >> 10) & 1) * 256 + (v & 0x1F) * 8 + self.fine_x as u16;
+            // Coarse Y can be set past the bottom of a nametable, into its attributes.
+            let y = ((v >> 11) & 1) * 240 + ((v >> 5) & 0x1F) * 8 + ((v >> 12) & 7);
+            Some((x, y % 480))
+        } else {
+            None
+        };
+    }
+
+    // Lines the PPU hasn't reached yet this frame are from the frame before.  Lines drawn with
+    // rendering off aren't in any split.
+    pub fn scroll_splits(&self) -> Vec<ScrollSplit> {
+        let mut splits: Vec<ScrollSplit> = vec![];
+        for (line, scroll) in self.scroll_lines.iter().enumerate() {
+            let (x, y) = match scroll {
+                None => continue,
+                Some(scroll) => *scroll,
+            };
+            if let Some(split) = splits.last_mut() {
+                let follows = split.first_line + split.lines == line as u16
+                    && split.x == x
+                    && (split.y + split.lines) % 480 == y;
+                if follows {
+                    split.lines += 1;
+                    continue;
+                }
+            }
+            splits.push(ScrollSplit {
+                first_line: line as u16,
+                lines: 1,
+                x,
+                y,
+            });
+        }
+        splits
+    }
 }
 
 #[derive(Clone)]
@@ -40,7 +222,14 @@ impl PPUDebug {
     pub const PALETTE_HEIGHT: usize = 32;
 
     pub fn new(ppu: Rc<RefCell<PPU>>) -> PPUDebug {
-        PPUDebug { ppu }
+        PPUDebug {
+            ppu,
+            frozen_chr: None,
+        }
+    }
+
+    pub fn stats(&self) -> PPUStats {
+        self.ppu.borrow().stats()
     }
 
     pub fn do_render<F>(&mut self, render: F)
@@ -52,14 +241,88 @@ impl PPUDebug {
 
         let mut buffers = PPUDebugRender::new();
 
-        PPUDebug::fill_pattern_buffer(&mut buffers.patterns, &pattern_tables);
+        match self.frozen_chr {
+            Some((ref mapper, banks)) => {
+                let mapper = mapper.borrow();
+                let mut frozen_tables = [0; 0x2000];
+                for (ix, byte) in frozen_tables.iter_mut().enumerate() {
+                    *byte = mapper.peek_chr(banks[ix / 0x400] * 0x400 + ix % 0x400);
+                }
+                PPUDebug::fill_pattern_buffer(&mut buffers.patterns, &frozen_tables);
+            }
+            None => PPUDebug::fill_pattern_buffer(&mut buffers.patterns, &pattern_tables),
+        }
         PPUDebug::fill_nametable_buffer(self.ppu.clone(), &mut buffers.nametables, &pattern_tables);
+        PPUDebug::draw_scroll_splits(&self.ppu.borrow().scroll_splits(), &mut buffers.nametables);
         PPUDebug::fill_sprite_buffer(self.ppu.clone(), &mut buffers.sprites, &pattern_tables);
         PPUDebug::fill_palette_buffer(self.ppu.clone(), &mut buffers.palettes);
 
         render(&buffers);
     }
 
+    // Shows these CHR banks in the pattern tables until unfrozen, e.g. to look at one frame of an
+    // animation.  The other views still show what's mapped in.
+    pub fn freeze_chr(&mut self, frozen: Option<(MapperRef, ChrBanks)>) {
+        self.frozen_chr = frozen;
+    }
+
+    // Both pattern tables side by side, coloured with one of the background palettes.  Returns
+    // RGB pixels, PATTERN_WIDTH x PATTERN_HEIGHT.
+    pub fn export_pattern_tables(&mut self, palette_ix: u8) -> Vec<u8> {
+        let mut pattern_tables = [0; 0x2000];
+        self.hydrate_pattern_tables(&mut pattern_tables);
+
+        let mut ppu = self.ppu.borrow_mut();
+        let mut rgb = vec![0; PPUDebug::PATTERN_WIDTH * PPUDebug::PATTERN_HEIGHT * 3];
+        for y in 0..PPUDebug::PATTERN_HEIGHT {
+            for x in 0..PPUDebug::PATTERN_WIDTH {
+                let tile = ((y / 8) << 4) | ((x % 128) / 8);
+                let base = (x / 128) << 12;
+                let pixel = tile_pixel(&pattern_tables, base | (tile << 4), x % 8, y % 8);
+                let ix = (y * PPUDebug::PATTERN_WIDTH + x) * 3;
+                write_pixel(&mut rgb[ix..ix + 3], ppu.palette_colour(palette_ix, pixel));
+            }
+        }
+        rgb
+    }
+
+    // One of the four nametables as the game would draw it, ignoring scroll and sprites.
+    // Returns RGB pixels, FRAME_WIDTH x FRAME_HEIGHT.
+    pub fn export_nametable(&mut self, table: u16) -> Vec<u8> {
+        let mut pattern_tables = [0; 0x2000];
+        self.hydrate_pattern_tables(&mut pattern_tables);
+
+        let mut ppu = self.ppu.borrow_mut();
+        let base = if ppu.ppuctrl.is_set(flags::PPUCTRL::B) {
+            0x1000
+        } else {
+            0
+        };
+        let mut rgb = vec![0; FRAME_WIDTH * FRAME_HEIGHT * 3];
+        for row in 0..30 {
+            for column in 0..32 {
+                let nt_byte = ppu
+                    .memory
+                    .read(0x2000 | (table << 10) | (row << 5) | column);
+                let attribute_addr = 0x23C0 | (table << 10) | ((row >> 2) << 3) | (column >> 2);
+                let attr_shift = ((row << 1) & 0x4) | (column & 0x2);
+                let palette_ix = (ppu.memory.read(attribute_addr) >> attr_shift) & 0x3;
+
+                for line in 0..8 {
+                    for pixel_x in 0..8 {
+                        let tile_addr = base | (usize::from(nt_byte) << 4);
+                        let pixel = tile_pixel(&pattern_tables, tile_addr, pixel_x, line);
+                        let x = usize::from(column) * 8 + pixel_x;
+                        let y = usize::from(row) * 8 + line;
+                        let ix = (y * FRAME_WIDTH + x) * 3;
+                        write_pixel(&mut rgb[ix..ix + 3], ppu.palette_colour(palette_ix, pixel));
+                    }
+                }
+            }
+        }
+        rgb
+    }
+
     fn hydrate_pattern_tables(&mut self, target: &mut [u8]) {
         let mut ppu = self.ppu.borrow_mut();
         for ix in 0..0x2000 {
@@ -119,6 +382,36 @@ impl PPUDebug {
         }
     }
 
+    // Outlines the part of the nametables each split showed, wrapping around the edges like the
+    // scroll does.  Neighbouring splits alternate colours, so a split a line high still shows up.
+    fn draw_scroll_splits(splits: &[ScrollSplit], buffer: &mut [u8]) {
+        const COLOURS: [[u8; 3]; 2] = [[0xFF, 0xFF, 0x00], [0x00, 0xFF, 0xFF]];
+        let mut mark = |x: u16, y: u16, colour: [u8; 3]| {
+            let x = usize::from(x) % PPUDebug::NAMETABLE_WIDTH;
+            let y = usize::from(y) % PPUDebug::NAMETABLE_HEIGHT;
+            let ix = (y * PPUDebug::NAMETABLE_WIDTH + x) * 3;
+            buffer[ix..ix + 3].copy_from_slice(&colour);
+        };
+
+        // Two pixels thick, so the lines survive the view being scaled down.
+        for (ix, split) in splits.iter().enumerate() {
+            let colour = COLOURS[ix % 2];
+            for row in 0..split.lines {
+                let y = split.y + row;
+                let edge = row < 2 || row + 2 >= split.lines;
+                if edge {
+                    for column in 0..FRAME_WIDTH as u16 {
+                        mark(split.x + column, y, colour);
+                    }
+                } else {
+                    for column in &[0, 1, 254, 255] {
+                        mark(split.x + column, y, colour);
+                    }
+                }
+            }
+        }
+    }
+
     fn fill_sprite_buffer(ppu_cell: Rc<RefCell<PPU>>, buffer: &mut [u8], pattern_tables: &[u8]) {
         let ppu = ppu_cell.borrow_mut();
         for sprite_ix in 0..64 {
@@ -254,3 +547,32 @@ impl PPUDebug {
         }
     }
 }
+
+impl PPU {
+    // Colour 0 of every palette is the shared backdrop colour.
+    fn palette_colour(&mut self, palette_ix: u8, pixel: u8) -> Colour {
+        let addr = if pixel == 0 {
+            0x3F00
+        } else {
+            0x3F00 | (u16::from(palette_ix) << 2) | u16::from(pixel)
+        };
+        Colour {
+            byte: self.memory.read(addr),
+            em_r: false,
+            em_g: false,
+            em_b: false,
+        }
+    }
+}
+
+// 2-bit colour of a pixel in the tile starting at `tile_addr`.
+fn tile_pixel(pattern_tables: &[u8], tile_addr: usize, x: usize, y: usize) -> u8 {
+    let low = pattern_tables[tile_addr | y];
+    let high = pattern_tables[tile_addr | 0x8 | y];
+    (((high >> (7 - x)) & 0x1) << 1) | ((low >> (7 - x)) & 0x1)
+}
+
+fn write_pixel(target: &mut [u8], colour: Colour) {
+    let (r, g, b) = palette::convert_colour(colour);
+    target.copy_from_slice(&[r, g, b]);
+}
@@ -1,21 +1,49 @@
 pub mod debug;
 mod flags;
+pub mod raster;
 mod registers;
 mod state;
 
 #[cfg(test)]
 mod test;
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
 
 use serde::{Deserialize, Serialize};
 
 use crate::emulator::clock;
 use crate::emulator::components::bitfield::BitField;
 use crate::emulator::components::latch;
-use crate::emulator::memory::{PPUMemory, Reader};
+use crate::emulator::memory::{PPUMemory, Reader, Writer};
 use crate::emulator::util;
+use crate::emulator::Region;
+
+// Impossible counter values found while ticking, e.g. after loading a save state from a PPU with
+// a different number of scanlines.  The PPU recovers by wrapping the counter and carrying on, and
+// the NES reports each one in the event log.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PPUFault {
+    ScanlineOutOfRange(u16),
+    CycleOutOfRange(u16),
+}
+
+impl fmt::Display for PPUFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PPUFault::ScanlineOutOfRange(scanline) => {
+                write!(f, "PPU scanline {} out of range, wrapped", scanline)
+            }
+            PPUFault::CycleOutOfRange(cycle) => {
+                write!(f, "PPU dot {} out of range, wrapped", cycle)
+            }
+        }
+    }
+}
 
 // Colours represented as a single byte:
 // 76543210
@@ -23,6 +51,7 @@ use crate::emulator::util;
 // ||||++++- Hue (phase, determines NTSC/PAL chroma)
 // ||++----- Value (voltage, determines NTSC/PAL luma)
 // ++------- Unimplemented, reads back as 0
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct Colour {
     byte: u8,
 
@@ -46,31 +75,60 @@ impl Colour {
     }
 }
 
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+
+// The I/O latch is just charge on the data lines, and each line leaks away roughly 600ms after it
+// was last driven.
+const BUS_LATCH_DECAY_DOTS: u32 = 3_200_000;
+
+// Receives the picture from the PPU.  The PPU draws into its own 256x240 buffer of colours, row by
+// row, and hands the whole buffer over as each scanline and each frame is finished.
 pub trait VideoOut {
-    fn emit(&mut self, c: Colour);
+    fn end_frame(&mut self, frame: &[Colour]);
+
+    // For outputs which want to show a frame while it is still being drawn.  Only rows up to and
+    // including `scanline` are up to date.
+    fn end_scanline(&mut self, _scanline: u16, _frame: &[Colour]) {}
 }
 
 impl<V: VideoOut> VideoOut for Rc<RefCell<V>> {
-    fn emit(&mut self, c: Colour) {
-        self.borrow_mut().emit(c);
+    fn end_frame(&mut self, frame: &[Colour]) {
+        self.borrow_mut().end_frame(frame);
+    }
+
+    fn end_scanline(&mut self, scanline: u16, frame: &[Colour]) {
+        self.borrow_mut().end_scanline(scanline, frame);
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+// How the cartridge wires the PPU's 2KB of VRAM to the four nametables.  Memory asks the mapper
+// on every access, so boards like MMC1 and MMC3 can switch it mid-frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum MirrorMode {
     SingleLower,
     SingleUpper,
     Vertical,
     Horizontal,
+    // Cart supplies its own extra nametable RAM, so all four nametables are distinct.
+    FourScreen,
 }
 
 pub trait Mirrorer {
     fn mirror_mode(&self) -> MirrorMode;
+
+    // Called after the PPU itself reads from the pattern tables, for mappers which switch banks
+    // based on what's being drawn.  Debug views don't trigger it.
+    fn pattern_fetched(&mut self, _address: u16) {}
 }
 
 pub struct PPU {
-    // Device to output rendered pixels to.
+    // Device to output rendered pixels to, and the frame being drawn for it.
     output: Box<dyn VideoOut>,
+    frame: Vec<Colour>,
+
+    // PAL PPUs swap the red and green emphasis bits.
+    region: Region,
 
     // --- Registers.
 
@@ -86,6 +144,10 @@ pub struct PPU {
     // See note in flags.rs for detail on meaning of each bit.
     ppustatus: BitField,
 
+    // PPUSTATUS flags set by the latest tick.  A read during the same dot still sees them clear.
+    // Only lasts until the next tick, so it's left out of save states.
+    ppustatus_just_set: u8,
+
     oamaddr: u8,
     write_latch: latch::Latch,
 
@@ -155,13 +217,28 @@ pub struct PPU {
 
     // --- Counters for tracking the current rendering stage.
 
-    // There are 262 scanlines in total. 0-239 are visible, 240-260 occur durng vblank, and 261 is
-    // idle.
+    // There are 262 scanlines in total on NTSC. 0-239 are visible, 240-260 occur durng vblank, and
+    // 261 is idle.
+    // PAL has 312 scanlines, with the extra 50 all going to vblank (241-310), and 311 is idle.
     pub scanline: u16,
+    scanlines_per_frame: u16,
 
     // Each scanline takes 341 cycles to render.
     pub cycle: u16,
 
+    // Number of frames completed since power on, used to track even/odd frame parity.
+    frame_count: u64,
+
+    // After power on the PPU needs roughly one frame to warm up, ending when it first reaches the
+    // pre-render scanline again.
+    warmed_up: bool,
+
+    // Count of counter faults recovered from, and the most recent one.
+    fault_count: u64,
+    last_fault: Option<PPUFault>,
+    // The newest fault, until the NES passes it on to the event log.
+    pending_fault: Option<PPUFault>,
+
     // -- Internal State --
 
     // Byte fetched from nametable indicating which tile to fetch from pattern table.
@@ -182,6 +259,11 @@ pub struct PPU {
     num_sprites: u8,
     sprite_0_next_line: bool,
     sprite_0_this_line: bool,
+    sprite_tracer: debug::SpriteTracer,
+
+    // Where in the nametables each visible scanline of the latest frame was drawn from, or None
+    // where rendering was off.
+    scroll_lines: Vec<Option<(u16, u16)>>,
 
     // Bytes read from $2007 are delayed in this buffer.
     ppudata_read_buffer: u8,
@@ -189,6 +271,15 @@ pub struct PPU {
     // Internal memory latch, causes reads from write-only registers to return the previously read
     // value.
     bus_latch: u8,
+
+    // Dot each bit of the latch was last driven on.  Bits decay separately, since reads only
+    // refresh the bits the register drives.
+    bus_latch_refreshed: [u64; 8],
+
+    // Dots since power on.  Only used to time the latch decay.
+    dots: u64,
+
+    scanline_callback: Option<raster::ScanlineCallback>,
 }
 
 impl clock::Ticker for PPU {
@@ -202,9 +293,12 @@ impl PPU {
     pub fn new(memory: PPUMemory, output: Box<dyn VideoOut>) -> PPU {
         PPU {
             output: output,
+            frame: vec![Colour::default(); FRAME_WIDTH * FRAME_HEIGHT],
+            region: Region::NTSC,
             ppuctrl: BitField::new(),
             ppumask: BitField::new(),
             ppustatus: BitField::new(),
+            ppustatus_just_set: 0,
             oamaddr: 0,
             write_latch: latch::new(),
             memory,
@@ -226,7 +320,13 @@ impl PPU {
             sprites_attribute: [0; 8],
             sprites_x: [0; 8],
             scanline: 261,
+            scanlines_per_frame: Region::NTSC.scanlines_per_frame(),
             cycle: 0,
+            frame_count: 0,
+            warmed_up: false,
+            fault_count: 0,
+            last_fault: None,
+            pending_fault: None,
             tmp_pattern_coords: 0,
             tmp_attribute_byte: 0,
             tmp_oam_byte: 0,
@@ -238,8 +338,68 @@ impl PPU {
             num_sprites: 0,
             sprite_0_next_line: false,
             sprite_0_this_line: false,
+            sprite_tracer: debug::SpriteTracer::default(),
+            scroll_lines: vec![None; FRAME_HEIGHT],
             ppudata_read_buffer: 0,
             bus_latch: 0,
+            bus_latch_refreshed: [0; 8],
+            dots: 0,
+            scanline_callback: None,
+        }
+    }
+
+    // Runs `callback` before each visible scanline.  See `raster::ScanlineRegisters` for exactly
+    // when.  Pass None to remove it.
+    pub fn set_scanline_callback(&mut self, callback: Option<raster::ScanlineCallback>) {
+        self.scanline_callback = callback;
+    }
+
+    fn run_scanline_callback(&mut self) {
+        let next_scanline = match self.coming_scanline() {
+            None => return,
+            Some(scanline) => scanline,
+        };
+
+        if let Some(mut callback) = self.scanline_callback.take() {
+            callback(next_scanline, &mut raster::ScanlineRegisters::new(self));
+            self.scanline_callback = Some(callback);
+        }
+    }
+
+    // The visible scanline after this one, if there is one.
+    fn coming_scanline(&self) -> Option<u16> {
+        let next_scanline = if self.is_pre_render_scanline() {
+            0
+        } else {
+            self.scanline + 1
+        };
+        if (next_scanline as usize) < FRAME_HEIGHT {
+            Some(next_scanline)
+        } else {
+            None
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.scanlines_per_frame = region.scanlines_per_frame();
+        self.scanline = self.pre_render_scanline();
+        self.cycle = 0;
+    }
+
+    // Nametables and palettes, for debug tools.  Pattern tables read as 0, since CHR reads can
+    // clock a mapper's IRQ counter.
+    pub fn peek_vram(&mut self, address: u16) -> u8 {
+        match address & 0x3FFF {
+            0x0000..=0x1FFF => 0,
+            _ => self.memory.read(address),
+        }
+    }
+
+    pub fn poke_vram(&mut self, address: u16, byte: u8) {
+        match address & 0x3FFF {
+            0x0000..=0x1FFF => (),
+            _ => self.memory.write(address, byte),
         }
     }
 
@@ -247,33 +407,75 @@ impl PPU {
         self.ppustatus.is_set(flags::PPUSTATUS::V) && self.ppuctrl.is_set(flags::PPUCTRL::V)
     }
 
+    // The fewest dots which could go by before `frame_count` next goes up, allowing for the dot
+    // NTSC skips on odd frames.
+    pub fn dots_until_frame_end(&self) -> u32 {
+        let scanlines_left = u32::from(self.scanlines_per_frame.saturating_sub(self.scanline));
+        (scanlines_left * 341).saturating_sub(u32::from(self.cycle) + 1)
+    }
+
     // Returns how many PPU cycles the tick took.
     fn tick_internal(&mut self) -> u16 {
+        let pre_render_scanline = self.pre_render_scanline();
+        if self.scanline > pre_render_scanline {
+            self.fault(PPUFault::ScanlineOutOfRange(self.scanline));
+            self.scanline %= self.scanlines_per_frame;
+        }
+
+        if self.cycle > 340 {
+            self.fault(PPUFault::CycleOutOfRange(self.cycle));
+            self.cycle = 0;
+            self.next_scanline();
+        }
+
+        self.ppustatus_just_set = 0;
         let cycles = match self.scanline {
-            0..=239 | 261 => self.tick_render_scanline(),
+            0..=239 => self.tick_render_scanline(),
             240 => self.tick_idle_scanline(),
-            241..=260 => self.tick_vblank_scanline(),
-            _ => panic!(
-                "Scanline index should never exceed 261.  Got {}.",
-                self.scanline
-            ),
+            s if s < pre_render_scanline => self.tick_vblank_scanline(),
+            _ => self.tick_render_scanline(),
         };
 
-        self.cycle = self.cycle + cycles;
+        self.cycle += cycles;
+
+        self.dots += cycles as u64;
 
-        if self.cycle > 341 {
-            panic!("Cycle index should never exceed 341.  Got: {}.", self.cycle);
+        if self.cycle == 340 && self.skips_last_pre_render_dot() {
+            self.cycle = 341;
         }
 
-        if self.cycle == 341 {
+        if self.cycle >= 341 {
             self.cycle = 0;
-            self.scanline = (self.scanline + 1) % 262;
+            self.next_scanline();
         }
 
         cycles
     }
 
+    fn next_scanline(&mut self) {
+        self.scanline = (self.scanline + 1) % self.scanlines_per_frame;
+        if self.scanline == 0 {
+            self.frame_count += 1;
+        } else if self.is_pre_render_scanline() && self.frame_count > 0 {
+            self.warmed_up = true;
+        }
+    }
+
+    fn fault(&mut self, fault: PPUFault) {
+        self.fault_count += 1;
+        self.last_fault = Some(fault);
+        self.pending_fault = Some(fault);
+    }
+
+    pub fn take_fault(&mut self) -> Option<PPUFault> {
+        self.pending_fault.take()
+    }
+
     fn tick_render_scanline(&mut self) -> u16 {
+        if self.cycle == 257 && self.scanline_callback.is_some() {
+            self.run_scanline_callback();
+        }
+
         // Rendering stages.
         let cycles = match self.cycle {
             // Cycle 0 is an idle cycle.
@@ -290,26 +492,24 @@ impl PPU {
             // the shift registers.
             321..=336 => self.tick_prefetch_tiles_cycle(),
 
-            // Finally, here two bytes are fetched, but the purpose is unknown.
-            337..=340 => self.tick_unknown_fetch(),
-
-            _ => panic!(
-                "PPU cycle index should never exceed 341.  Got {}.",
-                self.cycle
-            ),
+            // Finally, here two bytes are fetched over cycles 337-340, but the purpose is unknown.
+            _ => self.tick_unknown_fetch(),
         };
 
         // Sprite evaluation.
         // Does not occur on the pre-render scanline or if rendering totally disabled.
-        if self.scanline != 261 && self.rendering_is_enabled() {
+        if !self.is_pre_render_scanline() && self.rendering_is_enabled() {
             self.sprite_evaluation();
         }
 
         // Scrolling.
         self.handle_scrolling();
+        if self.cycle == 320 {
+            self.record_scroll();
+        }
 
         // On dot 1 of the pre-render scanline, clear vblank flag and sprite overflow flag.
-        if self.scanline == 261 && self.cycle == 1 {
+        if self.is_pre_render_scanline() && self.cycle == 1 {
             self.ppustatus.clear(flags::PPUSTATUS::V);
             self.ppustatus.clear(flags::PPUSTATUS::O);
             self.ppustatus.clear(flags::PPUSTATUS::S);
@@ -320,20 +520,21 @@ impl PPU {
 
     fn tick_idle_scanline(&mut self) -> u16 {
         // PPU does nothing on the idle scanline.
-        // Just idle for 341 cycles.
-        341
+        // Just idle for the rest of it.
+        341 - self.cycle
     }
 
     fn tick_vblank_scanline(&mut self) -> u16 {
         if self.scanline == 241 && self.cycle == 1 {
             // Set VBlank flag.
-            self.ppustatus.set(flags::PPUSTATUS::V);
+            self.set_status(flags::PPUSTATUS::V);
         }
-        // Otherwise idle.
-        if self.cycle == 0 {
+        // Otherwise idle, stopping at cycles 1 and 2 so the flag gets set and can be read in the
+        // dot it was set.
+        if self.cycle <= 1 {
             1
         } else {
-            340
+            341 - self.cycle
         }
     }
 
@@ -350,11 +551,19 @@ impl PPU {
 
         self.fetch_tile_data();
 
-        // Actually render and emit one pixel.
-        // Unless this is scanline 261, which is just a dummy scanline.
-        if self.scanline != 261 {
+        // Actually render one pixel into the frame.
+        // Unless this is the pre-render scanline, which is just a dummy scanline.
+        if !self.is_pre_render_scanline() {
             let pixel = self.render_pixel();
-            self.output.emit(pixel);
+            let x = (self.cycle - 1) as usize;
+            self.frame[self.scanline as usize * FRAME_WIDTH + x] = pixel;
+
+            if x == FRAME_WIDTH - 1 {
+                self.output.end_scanline(self.scanline, &self.frame);
+                if self.scanline as usize == FRAME_HEIGHT - 1 {
+                    self.output.end_frame(&self.frame);
+                }
+            }
         }
 
         // Finally shift all the registers.
@@ -440,13 +649,13 @@ impl PPU {
             // 3. Tile bitmap low.
             5 => {
                 let addr = self.pattern_address_low();
-                self.tile_latch_low = self.memory.read(addr);
+                self.tile_latch_low = self.memory.fetch(addr);
             }
 
             // 4. Tile bitmap high.
             7 => {
                 let addr = self.pattern_address_high();
-                self.tile_latch_high = self.memory.read(addr);
+                self.tile_latch_high = self.memory.fetch(addr);
             }
 
             // Do nothing on inbetween cycles.
@@ -496,7 +705,7 @@ impl PPU {
             && self.sprite_0_this_line
             && self.cycle != 256
         {
-            self.ppustatus.set(flags::PPUSTATUS::S);
+            self.set_status(flags::PPUSTATUS::S);
         }
 
         let colour_addr = if sprite_colour != 0 && (sprite_attribute & 0x20 == 0 || bg_colour == 0)
@@ -511,16 +720,25 @@ impl PPU {
             0x3F00
         };
 
-        let mut colour_byte = self.memory.read(colour_addr);
+        // Palette RAM is only 6 bits wide.
+        let mut colour_byte = self.memory.read(colour_addr) & 0x3F;
         if self.ppumask.is_set(flags::PPUMASK::GR) {
             // Grescale mode.
             colour_byte &= 0x30;
         }
 
+        let (mut em_r, mut em_g) = (
+            self.ppumask.is_set(flags::PPUMASK::R),
+            self.ppumask.is_set(flags::PPUMASK::G),
+        );
+        if self.region.revision().swaps_emphasis {
+            core::mem::swap(&mut em_r, &mut em_g);
+        }
+
         Colour {
             byte: colour_byte,
-            em_r: self.ppumask.is_set(flags::PPUMASK::R),
-            em_g: self.ppumask.is_set(flags::PPUMASK::G),
+            em_r,
+            em_g,
             em_b: self.ppumask.is_set(flags::PPUMASK::B),
         }
     }
@@ -531,16 +749,24 @@ impl PPU {
             0 => self.sprite_reset_state(),
             // These 2 phases do not occur on the pre-render scanline.
             1..=64 => {
-                if self.scanline != 261 {
+                if !self.is_pre_render_scanline() {
                     self.sprite_init_cycle()
                 }
             }
             65..=256 => {
-                if self.scanline != 261 {
+                if !self.is_pre_render_scanline() {
+                    if self.cycle == 65 {
+                        self.sprite_tracer.start(self.scanline);
+                    }
                     self.sprite_evaluation_cycle()
                 }
             }
-            257..=320 => self.sprite_fetch_cycle(),
+            257..=320 => {
+                if self.cycle == 257 {
+                    self.sprite_tracer.finish(&self.secondary_oam);
+                }
+                self.sprite_fetch_cycle()
+            }
             _ => (),
         }
     }
@@ -593,7 +819,10 @@ impl PPU {
                 } else {
                     // Check if sprite is in range.
                     // If not then skip over it.
-                    if (self.tmp_oam_byte as u16) < min_y || self.tmp_oam_byte as u16 > max_y {
+                    let in_range =
+                        (self.tmp_oam_byte as u16) >= min_y && self.tmp_oam_byte as u16 <= max_y;
+                    self.trace_sprite_check(debug::SpriteEvalPhase::Copy, in_range);
+                    if !in_range {
                         self.sprite_n += 1;
                     } else {
                         // Track if sprite 0 is visible.
@@ -614,11 +843,11 @@ impl PPU {
 
                 if self.sprite_n >= 64 {
                     // We've seen all sprites.  Go to phase 2.
+                    // This wins even if the last sprite was the 8th, since there's nothing left
+                    // to overflow.
                     self.sprite_eval_phase = 2;
                     self.sprite_n = 0;
-                }
-
-                if self.sprites_copied == 8 {
+                } else if self.sprites_copied == 8 {
                     // We've filled up secondary OAM.  Go to phase 1.
                     self.sprite_eval_phase = 1;
                 }
@@ -626,12 +855,18 @@ impl PPU {
             1 => {
                 // Phase 1: Sprite overflow.
                 // Keep looping through like before, checking for overflow.
-                // But this time don't write anything, and m gets incremented incorrectly.
+                // But this time don't write anything, and m gets incremented incorrectly, so the
+                // scan walks diagonally through OAM and compares tile, attribute and X bytes as
+                // if they were Y.  Real hardware misses some overflows and invents others this
+                // way, and games and test ROMs can tell the difference.
                 if self.sprite_queued_copies > 0 {
                     self.sprite_m += 1;
                     self.sprite_queued_copies -= 1;
                 } else {
-                    if (self.tmp_oam_byte as u16) < min_y || self.tmp_oam_byte as u16 > max_y {
+                    let in_range =
+                        (self.tmp_oam_byte as u16) >= min_y && self.tmp_oam_byte as u16 <= max_y;
+                    self.trace_sprite_check(debug::SpriteEvalPhase::Overflow, in_range);
+                    if !in_range {
                         // Erroneously increment m, causing sprite overflow bug.
                         // Note that m wraps itself here.
                         self.sprite_n += 1;
@@ -639,7 +874,7 @@ impl PPU {
                         self.sprite_m %= 4;
                     } else {
                         // In range, set sprite overflow flag.
-                        self.ppustatus.set(flags::PPUSTATUS::O);
+                        self.set_status(flags::PPUSTATUS::O);
                         self.sprite_m += 1;
                         self.sprite_queued_copies = 3;
                     }
@@ -665,6 +900,17 @@ impl PPU {
         }
     }
 
+    fn trace_sprite_check(&mut self, phase: debug::SpriteEvalPhase, in_range: bool) {
+        self.sprite_tracer.check(debug::SpriteCheck {
+            dot: self.cycle,
+            phase,
+            n: self.sprite_n,
+            m: self.sprite_m,
+            byte: self.tmp_oam_byte,
+            in_range,
+        });
+    }
+
     fn sprite_fetch_cycle(&mut self) {
         // Loading the sprite data for next scanline into registers.
         // Technically this data should be handled 1 byte per cycle.
@@ -718,8 +964,8 @@ impl PPU {
         let tile_addr_low = pattern_table_base | ((tile_index as u16) << 4) | offset;
         let tile_addr_high = tile_addr_low | 0b1000 | offset;
 
-        let mut tile_byte_low = self.memory.read(tile_addr_low);
-        let mut tile_byte_high = self.memory.read(tile_addr_high);
+        let mut tile_byte_low = self.memory.fetch(tile_addr_low);
+        let mut tile_byte_high = self.memory.fetch(tile_addr_high);
 
         if attribute & 0x40 != 0 {
             // Horizontal flip.
@@ -755,7 +1001,7 @@ impl PPU {
 
         // If rendering is enabled, between dots 280 to 304 of the pre-render scanline, the PPU repeatedly copies the
         // vertical bits from t to v.
-        if self.scanline == 261 && self.cycle >= 280 && self.cycle <= 304 {
+        if self.is_pre_render_scanline() && self.cycle >= 280 && self.cycle <= 304 {
             let vertical_bitmask = 0b1111011_11100000;
             self.v = self.v & !vertical_bitmask;
             self.v = self.v | (self.t & vertical_bitmask);
@@ -901,11 +1147,37 @@ impl PPU {
         self.ppumask.is_set(flags::PPUMASK::S) || self.ppumask.is_set(flags::PPUMASK::BG)
     }
 
+    fn pre_render_scanline(&self) -> u16 {
+        self.scanlines_per_frame - 1
+    }
+
+    fn is_pre_render_scanline(&self) -> bool {
+        self.scanline == self.pre_render_scanline()
+    }
+
+    // On NTSC, the pre-render scanline before an odd frame is a dot short while rendering is
+    // enabled.  PAL frames are always the full length.
+    fn skips_last_pre_render_dot(&self) -> bool {
+        self.region.revision().skips_odd_frame_dot
+            && self.is_pre_render_scanline()
+            && self.frame_count.is_multiple_of(2)
+            && self.rendering_is_enabled()
+    }
+
+    fn set_status(&mut self, flag: flags::PPUSTATUS) {
+        let mask = flag as u8;
+        if !self.ppustatus.is_set(mask) {
+            self.ppustatus.set(mask);
+            self.ppustatus_just_set |= mask;
+        }
+    }
+
     fn is_vblanking(&self) -> bool {
         self.scanline >= 241
     }
 
+    // The pre-render line fetches just like a visible one, so it counts as rendering.
     fn is_rendering(&self) -> bool {
-        !self.is_vblanking() && self.rendering_is_enabled()
+        (self.scanline < 240 || self.is_pre_render_scanline()) && self.rendering_is_enabled()
     }
 }
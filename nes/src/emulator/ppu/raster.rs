@@ -0,0 +1,57 @@
+// Hook for experimenting with raster effects.  A callback registered with
+// `PPU::set_scanline_callback` runs once for each visible scanline, with access to just the
+// registers that mid-frame tricks normally poke.
+use alloc::boxed::Box;
+
+use crate::emulator::ppu::PPU;
+
+pub type ScanlineCallback = Box<dyn FnMut(u16, &mut ScanlineRegisters)>;
+
+// Bits of v and t holding the horizontal and vertical parts of the scroll.
+const HORIZONTAL_BITS: u16 = 0b0000100_00011111;
+const VERTICAL_BITS: u16 = 0b1111011_11100000;
+
+// The callback runs on dot 257 of the line before, just ahead of the PPU loading the horizontal
+// scroll and prefetching the first tiles.  That's the point in hblank where games change the
+// scroll, so anything set here applies to the whole of the coming scanline.
+pub struct ScanlineRegisters<'a> {
+    ppu: &'a mut PPU,
+}
+
+impl<'a> ScanlineRegisters<'a> {
+    pub(super) fn new(ppu: &'a mut PPU) -> ScanlineRegisters<'a> {
+        ScanlineRegisters { ppu }
+    }
+
+    pub fn mask(&self) -> u8 {
+        self.ppu.ppumask.as_byte()
+    }
+
+    // As if the game had written to $2001.
+    pub fn set_mask(&mut self, byte: u8) {
+        self.ppu.ppumask.load_byte(byte);
+    }
+
+    // Scroll the coming scanline will be drawn with, in pixels across the 512x480 plane of all four
+    // nametables.
+    pub fn scroll(&self) -> (u16, u16) {
+        let (t, v) = (self.ppu.t, self.ppu.v);
+        let x = ((t >> 10) & 1) * 256 + (t & 0x1F) * 8 + self.ppu.fine_x as u16;
+        let y = ((v >> 11) & 1) * 240 + ((v >> 5) & 0x1F) * 8 + ((v >> 12) & 7);
+        (x, y)
+    }
+
+    // Moves the picture so the coming scanline shows row `y` of the plane, starting from column
+    // `x`.  Lines after it carry on from there, and the change sticks until the game sets the
+    // scroll again.
+    pub fn set_scroll(&mut self, x: u16, y: u16) {
+        let x = x % 512;
+        let y = y % 480;
+        let horizontal = ((x / 256) << 10) | ((x % 256) >> 3);
+        let vertical = ((y % 8) << 12) | ((y / 240) << 11) | (((y % 240) >> 3) << 5);
+
+        self.ppu.fine_x = (x & 7) as u8;
+        self.ppu.t = (self.ppu.t & !HORIZONTAL_BITS) | horizontal;
+        self.ppu.v = (self.ppu.v & !VERTICAL_BITS) | vertical;
+    }
+}
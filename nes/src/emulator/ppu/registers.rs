@@ -2,7 +2,7 @@ use crate::emulator::components::latch;
 use crate::emulator::memory::Reader;
 use crate::emulator::memory::Writer;
 use crate::emulator::ppu::flags;
-use crate::emulator::ppu::PPU;
+use crate::emulator::ppu::{BUS_LATCH_DECAY_DOTS, PPU};
 
 impl PPU {
     fn ppuaddr_increment(&self) -> u16 {
@@ -15,6 +15,51 @@ impl PPU {
             1
         }
     }
+
+    // Only the bits in `mask` are driven, so only they get their decay timers reset.
+    fn refresh_bus_latch(&mut self, byte: u8, mask: u8) {
+        self.bus_latch = (byte & mask) | (self.bus_latch & !mask);
+        for bit in 0..8 {
+            if mask & (1 << bit) != 0 {
+                self.bus_latch_refreshed[bit] = self.dots;
+            }
+        }
+    }
+
+    // The latch with any bits which haven't been driven for a while faded to 0.
+    pub(super) fn decayed_bus_latch(&mut self) -> u8 {
+        for bit in 0..8 {
+            if self.bus_latch_decay_remaining(bit) == 0 {
+                self.bus_latch &= !(1 << bit);
+            }
+        }
+        self.bus_latch
+    }
+
+    pub(super) fn bus_latch_decay_remaining(&self, bit: usize) -> u32 {
+        let elapsed = self.dots.wrapping_sub(self.bus_latch_refreshed[bit]);
+        (BUS_LATCH_DECAY_DOTS as u64).saturating_sub(elapsed) as u32
+    }
+
+    pub(super) fn set_bus_latch_decay_remaining(&mut self, bit: usize, remaining: u32) {
+        let remaining = remaining.min(BUS_LATCH_DECAY_DOTS);
+        self.bus_latch_refreshed[bit] = self
+            .dots
+            .wrapping_add(remaining as u64)
+            .wrapping_sub(BUS_LATCH_DECAY_DOTS as u64);
+    }
+
+    // Every PPUDATA access bumps v.  While rendering, the PPU is busy using v itself, and the
+    // access triggers a coarse X and a Y increment at the same time instead of the usual +1/+32.
+    fn increment_ppudata_address(&mut self) {
+        if self.is_rendering() {
+            self.increment_coarse_x();
+            self.increment_y();
+        } else {
+            let inc = self.ppuaddr_increment();
+            self.v = self.v.wrapping_add(inc);
+        }
+    }
 }
 
 impl Reader for PPU {
@@ -22,8 +67,9 @@ impl Reader for PPU {
         // PPU gets mounted between 0x2000 and 0x3FFF.
         // There are only 8 registers, mirrorred every 8 bytes, so we only care about the 3 low
         // bits of the address.
-        // TODO: Reads of write-only registers should return the contents of an internal latch.
-        let byte = match address % 8 {
+        // Each readable register returns its value along with a mask of the bits it actually
+        // drives.  The rest come from the I/O latch, i.e. whatever was last on the bus.
+        let driven = match address % 8 {
             // PPUCTRL - write-only
             0 => None,
 
@@ -32,15 +78,16 @@ impl Reader for PPU {
 
             // PPUSTATUS
             // Only top 3 bits contain data.
-            // TODO: Bottom 5 bits should be filled from internal latch.
             2 => {
-                let byte = self.ppustatus.as_byte() & 0b1110_0000;
+                // Flags set this dot aren't visible yet.  If that's the vblank flag it gets
+                // cleared below without ever being seen, as happens reading right as vblank starts.
+                let byte = self.ppustatus.as_byte() & !self.ppustatus_just_set;
 
                 // After reading PPUSTATUS, vblank flag is cleared.
                 // And ppuaddr latch is reset.
                 self.ppustatus.clear(flags::PPUSTATUS::V);
                 self.write_latch.reset();
-                Some(byte)
+                Some((byte, 0b1110_0000))
             }
 
             // OAMADDR - write-only
@@ -51,7 +98,7 @@ impl Reader for PPU {
                 // Reads during vblank read from OAM but do not increment OAMADDR.
                 if self.is_vblanking() {
                     let addr = self.oamaddr;
-                    Some(self.oam[addr as usize])
+                    Some((self.oam[addr as usize], 0xFF))
                 } else {
                     None
                 }
@@ -65,35 +112,25 @@ impl Reader for PPU {
 
             // PPUDATA
             7 => {
-                // Note that
                 // Read from ppu memory and increment v.
                 let addr = self.v;
-                let byte = self.memory.read(addr);
-
-                if self.is_rendering() {
-                    // v is modified strangely if we're accessing it during rendering.
-                    self.increment_coarse_x();
-                    self.increment_y();
-                } else {
-                    // Amount to increment by is determined by PPUCTRL.
-                    let inc = self.ppuaddr_increment();
-                    self.v = self.v.wrapping_add(inc);
-                }
+                let byte = self.memory.fetch(addr);
+                self.increment_ppudata_address();
 
                 if addr < 0x3F00 {
                     // Reading from before palettes, buffer the read.
                     let byte_to_return = self.ppudata_read_buffer;
                     self.ppudata_read_buffer = byte;
-                    Some(byte_to_return)
+                    Some((byte_to_return, 0xFF))
                 } else {
                     // Reading from palettes, return immediately, but grab the nametable byte
-                    // "behind" the palettes into the buffer.
+                    // "behind" the palettes into the buffer.  Palette entries are only 6 bits.
                     self.ppudata_read_buffer = self.memory.read(addr & 0x2FFF);
                     if self.ppumask.is_set(flags::PPUMASK::GR) {
                         // In greyscale mode, palette bytes read through PPUDATA also go grey.
-                        Some(byte & 0x30)
+                        Some((byte & 0x30, 0b0011_1111))
                     } else {
-                        Some(byte)
+                        Some((byte, 0b0011_1111))
                     }
                 }
             }
@@ -101,19 +138,20 @@ impl Reader for PPU {
             _ => panic!("Unexpected PPU register address: {}", address),
         };
 
-        match byte {
-            Some(b) => {
-                self.bus_latch = b;
-                b
+        let latch = self.decayed_bus_latch();
+        match driven {
+            Some((byte, mask)) => {
+                self.refresh_bus_latch(byte, mask);
+                (byte & mask) | (latch & !mask)
             }
-            None => self.bus_latch,
+            None => latch,
         }
     }
 }
 
 impl Writer for PPU {
     fn write(&mut self, address: u16, byte: u8) {
-        self.bus_latch = byte;
+        self.refresh_bus_latch(byte, 0xFF);
         match address % 8 {
             // PPUCTRL
             0 => {
@@ -200,16 +238,7 @@ impl Writer for PPU {
             7 => {
                 // Write byte and increment VRAM address.
                 self.memory.write(self.v, byte);
-
-                if self.is_rendering() {
-                    // v is modified strangely if we're accessing it during rendering.
-                    self.increment_coarse_x();
-                    self.increment_y();
-                } else {
-                    // Amount to increment by is determined by PPUCTRL.
-                    let inc = self.ppuaddr_increment();
-                    self.v = self.v.wrapping_add(inc);
-                }
+                self.increment_ppudata_address();
             }
 
             _ => panic!("Unexpected PPU register address: {}", address),
@@ -28,6 +28,8 @@ impl<'de> SaveState<'de, PPUState> for PPU {
             sprites_x: self.sprites_x.to_vec(),
             scanline: self.scanline,
             cycle: self.cycle,
+            frame_count: self.frame_count,
+            warmed_up: self.warmed_up,
             tmp_pattern_coords: self.tmp_pattern_coords,
             tmp_attribute_byte: self.tmp_attribute_byte,
             tmp_oam_byte: self.tmp_oam_byte,
@@ -40,7 +42,10 @@ impl<'de> SaveState<'de, PPUState> for PPU {
             sprite_0_next_line: self.sprite_0_next_line,
             sprite_0_this_line: self.sprite_0_this_line,
             ppudata_read_buffer: self.ppudata_read_buffer,
-            bus_latch: self.bus_latch,
+            bus_latch: self.decayed_bus_latch(),
+            bus_latch_decay_bits: (0..8)
+                .map(|bit| self.bus_latch_decay_remaining(bit))
+                .collect(),
         }
     }
 
@@ -73,6 +78,8 @@ impl<'de> SaveState<'de, PPUState> for PPU {
         self.sprites_x.copy_from_slice(state.sprites_x.as_slice());
         self.scanline = state.scanline;
         self.cycle = state.cycle;
+        self.frame_count = state.frame_count;
+        self.warmed_up = state.warmed_up;
         self.tmp_pattern_coords = state.tmp_pattern_coords;
         self.tmp_attribute_byte = state.tmp_attribute_byte;
         self.tmp_oam_byte = state.tmp_oam_byte;
@@ -86,5 +93,10 @@ impl<'de> SaveState<'de, PPUState> for PPU {
         self.sprite_0_this_line = state.sprite_0_this_line;
         self.ppudata_read_buffer = state.ppudata_read_buffer;
         self.bus_latch = state.bus_latch;
+        // States from before the latch decayed have no timers, so it's decayed already.
+        for bit in 0..8 {
+            let remaining = state.bus_latch_decay_bits.get(bit).cloned().unwrap_or(0);
+            self.set_bus_latch_decay_remaining(bit, remaining);
+        }
     }
 }
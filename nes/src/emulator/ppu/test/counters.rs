@@ -0,0 +1,99 @@
+use crate::emulator::clock::Ticker;
+use crate::emulator::io::nop::DummyVideo;
+use crate::emulator::memory::Writer;
+use crate::emulator::ppu::test::new_ppu;
+use crate::emulator::ppu::PPUFault;
+use crate::emulator::Region;
+
+// Tiny xorshift PRNG so the batch sizes are random but the test is reproducible.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn check_counters_over_random_batches(region: Region, rendering: bool, seed: u64) {
+    let mut ppu = new_ppu(Box::new(DummyVideo));
+    ppu.set_region(region);
+    if rendering {
+        ppu.write(0x2001, 0x18);
+    }
+
+    let skips_dots = region == Region::NTSC && rendering;
+    let frame_cycles = (region.scanlines_per_frame() as u64) * 341;
+    let start = (ppu.scanline as u64) * 341 + (ppu.cycle as u64);
+    let mut rng = XorShift(seed);
+    let mut total = 0u64;
+
+    while total < 3_000_000 {
+        let batch = 1 + rng.next() % 2000;
+        for _ in 0..batch {
+            total += ppu.tick() as u64;
+        }
+
+        // The counters must always agree with the number of cycles actually run.  On NTSC with
+        // rendering on, every odd frame starts a dot early.
+        let stats = ppu.stats();
+        let skipped = if skips_dots {
+            stats.frame_count.div_ceil(2)
+        } else {
+            0
+        };
+        let position = start + total + skipped;
+        assert_eq!(stats.fault_count, 0);
+        assert_eq!(stats.frame_count, position / frame_cycles);
+        assert_eq!(stats.scanline as u64, (position % frame_cycles) / 341);
+        assert_eq!(stats.dot as u64, position % 341);
+    }
+}
+
+#[test]
+fn test_counters_consistent_ntsc() {
+    check_counters_over_random_batches(Region::NTSC, true, 0x1234_5678);
+    check_counters_over_random_batches(Region::NTSC, false, 0x8765_4321);
+}
+
+#[test]
+fn test_counters_consistent_pal() {
+    check_counters_over_random_batches(Region::PAL, true, 0xDEAD_BEEF);
+    check_counters_over_random_batches(Region::PAL, false, 0xFEED_FACE);
+}
+
+#[test]
+fn test_bad_counters_recover() {
+    // Like loading a PAL save state into an NTSC PPU.
+    let mut ppu = new_ppu(Box::new(DummyVideo));
+    ppu.scanline = 300;
+    ppu.tick();
+    assert_eq!(ppu.stats().fault_count, 1);
+    assert_eq!(
+        ppu.stats().last_fault,
+        Some(PPUFault::ScanlineOutOfRange(300))
+    );
+    assert!(ppu.scanline < 262);
+
+    ppu.scanline = 10;
+    ppu.cycle = 400;
+    ppu.tick();
+    assert_eq!(ppu.stats().fault_count, 2);
+    assert_eq!(ppu.stats().last_fault, Some(PPUFault::CycleOutOfRange(400)));
+    assert_eq!(ppu.take_fault(), Some(PPUFault::CycleOutOfRange(400)));
+    assert_eq!(ppu.take_fault(), None);
+    assert_eq!(ppu.scanline, 11);
+    assert!(ppu.cycle < 341);
+
+    // Mid-scanline positions in vblank and the idle line just run to the end of the line.
+    for &scanline in [240, 250].iter() {
+        ppu.scanline = scanline;
+        ppu.cycle = 7;
+        ppu.tick();
+        assert_eq!(ppu.scanline, scanline + 1);
+        assert_eq!(ppu.cycle, 0);
+    }
+    assert_eq!(ppu.stats().fault_count, 2);
+}
@@ -0,0 +1,55 @@
+use crate::emulator::io::nop::DummyVideo;
+use crate::emulator::io::palette;
+use crate::emulator::memory::Writer;
+use crate::emulator::ppu::test::new_ppu;
+use crate::emulator::ppu::PPU;
+use crate::emulator::Region;
+
+// Rendering is left off, so every pixel is the backdrop colour at $3F00.
+fn ppu_with_backdrop(colour: u8, ppumask: u8) -> PPU {
+    let mut ppu = new_ppu(Box::new(DummyVideo));
+    ppu.memory.write(0x3F00, colour);
+    ppu.write(0x2001, ppumask);
+    ppu
+}
+
+#[test]
+fn test_greyscale_strips_hue() {
+    let mut ppu = ppu_with_backdrop(0x27, 0x00);
+    assert_eq!(ppu.render_pixel().as_byte(), 0x27);
+
+    let mut ppu = ppu_with_backdrop(0x27, 0x01);
+    assert_eq!(ppu.render_pixel().as_byte(), 0x20);
+}
+
+#[test]
+fn test_palette_entries_are_six_bits() {
+    let mut ppu = ppu_with_backdrop(0xE7, 0x00);
+    assert_eq!(ppu.render_pixel().as_byte(), 0x27);
+}
+
+#[test]
+fn test_emphasis_bits() {
+    let mut ppu = ppu_with_backdrop(0x20, 0x20 | 0x80);
+    let colour = ppu.render_pixel();
+    assert!(colour.em_r);
+    assert!(!colour.em_g);
+    assert!(colour.em_b);
+
+    // Each emphasis combination has its own palette.
+    let plain = palette::convert_colour(ppu_with_backdrop(0x20, 0x00).render_pixel());
+    let red = palette::convert_colour(ppu_with_backdrop(0x20, 0x20).render_pixel());
+    let green = palette::convert_colour(ppu_with_backdrop(0x20, 0x40).render_pixel());
+    assert_ne!(plain, red);
+    assert_ne!(plain, green);
+    assert_ne!(red, green);
+}
+
+#[test]
+fn test_pal_swaps_red_and_green_emphasis() {
+    let mut ppu = ppu_with_backdrop(0x20, 0x20);
+    ppu.set_region(Region::PAL);
+    let colour = ppu.render_pixel();
+    assert!(!colour.em_r);
+    assert!(colour.em_g);
+}
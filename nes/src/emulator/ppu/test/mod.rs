@@ -1,5 +1,11 @@
 mod background;
+mod counters;
 mod data;
+mod mask;
+mod ppudata;
+mod raster;
+mod sprites;
+mod timing;
 
 use crate::emulator::memory;
 use crate::emulator::memory::Writer;
@@ -20,23 +26,20 @@ fn load_data_into_vram(ppu: &mut PPU, addr: u16, bytes: &[u8]) {
     }
 }
 
-struct ImageCapture {
-    dot: u64,
-}
+struct ImageCapture;
 
 impl ImageCapture {
     pub fn new() -> ImageCapture {
-        ImageCapture { dot: 0 }
+        ImageCapture
     }
 }
 
 impl VideoOut for ImageCapture {
-    fn emit(&mut self, c: Colour) {
-        if self.dot % 256 < 40 {
-            print!("{:03} ", c.byte);
-        }
-        self.dot += 1;
-        if self.dot % 256 == 0 {
+    fn end_frame(&mut self, frame: &[Colour]) {
+        for row in frame.chunks(256) {
+            for c in row.iter().take(40) {
+                print!("{:03} ", c.byte);
+            }
             println!();
         }
     }
@@ -0,0 +1,164 @@
+use crate::emulator::clock::Ticker;
+use crate::emulator::io::nop::DummyVideo;
+use crate::emulator::memory::{Reader, Writer};
+use crate::emulator::ppu::flags;
+use crate::emulator::ppu::test::{load_data_into_vram, new_ppu};
+use crate::emulator::ppu::PPU;
+
+fn ppu_at(scanline: u16, rendering: bool) -> PPU {
+    let mut ppu = new_ppu(Box::new(DummyVideo));
+    ppu.scanline = scanline;
+    ppu.cycle = 100;
+    ppu.write(0x2001, if rendering { 0x18 } else { 0x00 });
+    ppu
+}
+
+#[test]
+fn test_ppudata_increment_outside_rendering() {
+    let mut ppu = ppu_at(241, true);
+    ppu.v = 0x2000;
+    ppu.write(0x2007, 0xAA);
+    assert_eq!(ppu.v, 0x2001);
+
+    // PPUCTRL bit 2 selects +32.
+    ppu.write(0x2000, 0x04);
+    ppu.read(0x2007);
+    assert_eq!(ppu.v, 0x2021);
+
+    // Rendering disabled means a visible scanline behaves normally too.
+    let mut ppu = ppu_at(100, false);
+    ppu.v = 0x2000;
+    ppu.write(0x2007, 0xAA);
+    assert_eq!(ppu.v, 0x2001);
+}
+
+#[test]
+fn test_ppudata_increment_during_rendering() {
+    // Both coarse X and fine Y step, whatever PPUCTRL says.
+    let mut ppu = ppu_at(100, true);
+    ppu.write(0x2000, 0x04);
+    ppu.v = 0x2000;
+    ppu.write(0x2007, 0xAA);
+    assert_eq!(ppu.v, 0x3001);
+
+    // Reads behave the same way.
+    ppu.read(0x2007);
+    assert_eq!(ppu.v, 0x4002);
+}
+
+#[test]
+fn test_ppudata_increment_during_rendering_wraps() {
+    // Coarse X 31 and fine Y 7 both wrap, flipping the horizontal nametable and moving down a row.
+    let mut ppu = ppu_at(10, true);
+    ppu.v = 0x7000 | 0x001F;
+    ppu.write(0x2007, 0xAA);
+    assert_eq!(ppu.v, 0x0400 | 0x0020);
+
+    // Coarse Y 29 wraps to the next vertical nametable.
+    ppu.v = 0x7000 | (29 << 5);
+    ppu.write(0x2007, 0xAA);
+    assert_eq!(ppu.v, 0x0800 | 0x0001);
+}
+
+#[test]
+fn test_ppudata_increment_on_pre_render_line() {
+    let mut ppu = ppu_at(261, true);
+    ppu.v = 0x0000;
+    ppu.read(0x2007);
+    assert_eq!(ppu.v, 0x1001);
+
+    // The post-render line is idle though.
+    let mut ppu = ppu_at(240, true);
+    ppu.v = 0x0000;
+    ppu.read(0x2007);
+    assert_eq!(ppu.v, 0x0001);
+}
+
+#[test]
+fn test_ppudata_reads_are_buffered() {
+    let mut ppu = ppu_at(241, false);
+    load_data_into_vram(&mut ppu, 0x2000, &[0x11, 0x22]);
+    load_data_into_vram(&mut ppu, 0x2F00, &[0x33]);
+    load_data_into_vram(&mut ppu, 0x3F00, &[0x2A]);
+
+    ppu.write(0x2006, 0x20);
+    ppu.write(0x2006, 0x00);
+    // The first read returns whatever was in the buffer from before.
+    assert_eq!(ppu.read(0x2007), 0x00);
+    assert_eq!(ppu.read(0x2007), 0x11);
+    assert_eq!(ppu.read(0x2007), 0x22);
+
+    // Palette reads skip the buffer, but still fill it from the nametable underneath.  The top 2
+    // bits aren't driven, so come from the last value on the bus.
+    ppu.write(0x2006, 0x3F);
+    ppu.write(0x2006, 0x00);
+    assert_eq!(ppu.read(0x2007), 0x2A);
+    ppu.write(0x2006, 0x3F);
+    ppu.write(0x2006, 0x00);
+    ppu.write(0x2003, 0xC0);
+    assert_eq!(ppu.read(0x2007), 0xC0 | 0x2A);
+    ppu.write(0x2006, 0x20);
+    ppu.write(0x2006, 0x00);
+    assert_eq!(ppu.read(0x2007), 0x33);
+}
+
+#[test]
+fn test_open_bus_reads() {
+    let mut ppu = ppu_at(241, false);
+
+    // Write-only registers read back the last value written to any register.
+    ppu.write(0x2003, 0x5A);
+    assert_eq!(ppu.read(0x2000), 0x5A);
+    assert_eq!(ppu.read(0x2005), 0x5A);
+
+    // PPUSTATUS only drives its top 3 bits.
+    ppu.ppustatus.set(flags::PPUSTATUS::V);
+    ppu.write(0x2000, 0x1F);
+    assert_eq!(ppu.read(0x2002), 0x80 | 0x1F);
+
+    // The bits it did drive stay on the bus.
+    assert_eq!(ppu.read(0x2006), 0x80 | 0x1F);
+}
+
+#[test]
+fn test_open_bus_decays() {
+    let mut ppu = ppu_at(241, false);
+    ppu.write(0x2000, 0x00);
+    ppu.write(0x2003, 0xFF);
+
+    // Still there after a frame.
+    for _ in 0..(341 * 262) {
+        ppu.tick();
+    }
+    assert_eq!(ppu.read(0x2000), 0xFF);
+
+    // Gone after the best part of a second.
+    for _ in 0..(341 * 262 * 40) {
+        ppu.tick();
+    }
+    assert_eq!(ppu.read(0x2000), 0x00);
+}
+
+#[test]
+fn test_open_bus_decays_per_bit() {
+    let mut ppu = ppu_at(241, false);
+    ppu.write(0x2000, 0x1F);
+
+    // Most of the way to decaying, reading PPUSTATUS refreshes just its top 3 bits.
+    for _ in 0..(341 * 262 * 20) {
+        ppu.tick();
+    }
+    ppu.ppustatus.set(flags::PPUSTATUS::V);
+    assert_eq!(ppu.read(0x2002), 0x80 | 0x1F);
+
+    // So the low bits fade first.
+    for _ in 0..(341 * 262 * 20) {
+        ppu.tick();
+    }
+    assert_eq!(ppu.read(0x2000), 0x80);
+
+    for _ in 0..(341 * 262 * 20) {
+        ppu.tick();
+    }
+    assert_eq!(ppu.read(0x2000), 0x00);
+}
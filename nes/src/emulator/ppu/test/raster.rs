@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::clock::Ticker;
+use crate::emulator::io::nop::DummyVideo;
+use crate::emulator::memory::Writer;
+use crate::emulator::ppu::debug::ScrollSplit;
+use crate::emulator::ppu::test::new_ppu;
+use crate::emulator::ppu::PPU;
+
+fn run_frame(ppu: &mut PPU) {
+    let frame = ppu.stats().frame_count;
+    while ppu.stats().frame_count == frame {
+        ppu.tick();
+    }
+}
+
+// Scanline 0's callback runs at the end of the pre-render line, so frames are counted from there.
+fn run_to_pre_render(ppu: &mut PPU) {
+    ppu.tick();
+    while !(ppu.scanline == 261 && ppu.cycle == 0) {
+        ppu.tick();
+    }
+}
+
+#[test]
+fn test_callback_runs_for_each_visible_scanline() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    let seen = Rc::new(RefCell::new(vec![]));
+    let log = seen.clone();
+    ppu.set_scanline_callback(Some(Box::new(move |scanline, _| {
+        log.borrow_mut().push(scanline)
+    })));
+
+    run_to_pre_render(&mut ppu);
+    seen.borrow_mut().clear();
+    run_to_pre_render(&mut ppu);
+    assert_eq!(*seen.borrow(), (0..240).collect::<Vec<u16>>());
+
+    ppu.set_scanline_callback(None);
+    run_to_pre_render(&mut ppu);
+    assert_eq!(seen.borrow().len(), 240);
+}
+
+#[test]
+fn test_mid_frame_scroll_and_mask() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    ppu.write(0x2001, 0x18);
+
+    let seen = Rc::new(RefCell::new(vec![]));
+    let log = seen.clone();
+    ppu.set_scanline_callback(Some(Box::new(move |scanline, registers| {
+        log.borrow_mut()
+            .push((scanline, registers.scroll(), registers.mask()));
+        match scanline {
+            100 => registers.set_scroll(300, 200),
+            150 => registers.set_mask(0x00),
+            _ => (),
+        }
+    })));
+
+    run_frame(&mut ppu);
+    run_frame(&mut ppu);
+
+    let seen = seen.borrow();
+    let at = |scanline: u16| {
+        seen.iter()
+            .rev()
+            .find(|s| s.0 == scanline)
+            .cloned()
+            .unwrap()
+    };
+
+    // With no scroll set, each line shows the next row down.
+    assert_eq!(at(99), (99, (0, 99), 0x18));
+    // The PPU carries on down from wherever the scroll was moved to.
+    assert_eq!(at(101), (101, (300, 201), 0x18));
+    assert_eq!(at(140), (140, (300, 240), 0x18));
+    // Rendering is off from line 150, so the scroll stops moving.
+    assert_eq!(at(150), (150, (300, 250), 0x18));
+    assert_eq!(at(151), (151, (300, 250), 0x00));
+    assert_eq!(at(239), (239, (300, 250), 0x00));
+}
+
+#[test]
+fn test_scroll_splits() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    ppu.write(0x2001, 0x18);
+
+    // A status bar along the top, then the playfield from the bottom nametables, then rendering
+    // off for the last few lines.
+    ppu.set_scanline_callback(Some(Box::new(move |scanline, registers| match scanline {
+        0 => {
+            registers.set_mask(0x18);
+            registers.set_scroll(0, 0);
+        }
+        32 => registers.set_scroll(100, 240),
+        200 => registers.set_mask(0x00),
+        _ => (),
+    })));
+
+    run_frame(&mut ppu);
+    run_frame(&mut ppu);
+
+    assert_eq!(
+        ppu.scroll_splits(),
+        vec![
+            ScrollSplit {
+                first_line: 0,
+                lines: 32,
+                x: 0,
+                y: 0,
+            },
+            ScrollSplit {
+                first_line: 32,
+                lines: 168,
+                x: 100,
+                y: 240,
+            },
+        ]
+    );
+}
@@ -0,0 +1,91 @@
+use crate::emulator::clock::Ticker;
+use crate::emulator::io::nop::DummyVideo;
+use crate::emulator::memory::Writer;
+use crate::emulator::ppu::debug::SpriteEvalPhase;
+use crate::emulator::ppu::test::new_ppu;
+use crate::emulator::ppu::{flags, PPU};
+
+// All sprites evaluated on this scanline cover it if their Y is here.
+const SCANLINE: u16 = 100;
+const OFFSCREEN: u8 = 0xF0;
+
+// Evaluates sprites for one scanline with the given OAM and reports the overflow flag.
+fn overflow_with_oam(oam: &[u8; 256]) -> bool {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    // Show sprites and background.
+    ppu.write(0x2001, 0x18);
+    ppu.oam = *oam;
+    run_to(&mut ppu, SCANLINE, 257);
+    ppu.ppustatus.is_set(flags::PPUSTATUS::O)
+}
+
+fn run_to(ppu: &mut PPU, scanline: u16, dot: u16) {
+    while !(ppu.scanline == scanline && ppu.cycle == dot) {
+        ppu.tick();
+    }
+}
+
+// Puts sprites `first..first + count` on the scanline and everything else off screen.  Every byte
+// of the off screen sprites is out of range, so the diagonal scan can't trip over them.
+fn oam_with_sprites(first: usize, count: usize) -> [u8; 256] {
+    let mut oam = [OFFSCREEN; 256];
+    for sprite in first..first + count {
+        oam[sprite * 4] = SCANLINE as u8;
+    }
+    oam
+}
+
+#[test]
+fn test_overflow_on_ninth_sprite() {
+    assert!(!overflow_with_oam(&oam_with_sprites(0, 8)));
+    assert!(overflow_with_oam(&oam_with_sprites(0, 9)));
+}
+
+#[test]
+fn test_no_overflow_when_eighth_sprite_is_last() {
+    // Once the scan has run off the end of OAM it's over, so the decoy in sprite 1's tile byte
+    // is never looked at.
+    let mut oam = oam_with_sprites(56, 8);
+    oam[4 + 1] = SCANLINE as u8;
+    assert!(!overflow_with_oam(&oam));
+}
+
+#[test]
+fn test_diagonal_scan_misses_overflow() {
+    // After 8 hits, sprite 8 is off screen, so the check on sprite 9 looks at its tile byte
+    // instead of its Y.
+    let mut oam = oam_with_sprites(0, 8);
+    oam[9 * 4] = SCANLINE as u8;
+    assert!(!overflow_with_oam(&oam));
+}
+
+#[test]
+fn test_diagonal_scan_invents_overflow() {
+    // Only 8 sprites are on the scanline, but sprite 9's tile byte looks like an in range Y.
+    let mut oam = oam_with_sprites(0, 8);
+    oam[9 * 4 + 1] = SCANLINE as u8;
+    assert!(overflow_with_oam(&oam));
+}
+
+#[test]
+fn test_sprite_trace() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    ppu.write(0x2001, 0x18);
+    ppu.oam = oam_with_sprites(2, 9);
+    ppu.set_sprite_trace_scanline(Some(SCANLINE));
+    run_to(&mut ppu, SCANLINE + 1, 0);
+
+    let trace = ppu.sprite_trace().unwrap();
+    assert_eq!(trace.scanline, SCANLINE);
+    assert_eq!(trace.copied, vec![2, 3, 4, 5, 6, 7, 8, 9]);
+    let overflow = trace.overflow.unwrap();
+    assert_eq!(
+        (overflow.phase, overflow.n, overflow.m),
+        (SpriteEvalPhase::Overflow, 10, 0)
+    );
+
+    // Sprites 0 and 1 were rejected on the way.
+    assert!(!trace.checks[0].in_range);
+    assert!(!trace.checks[1].in_range);
+    assert_eq!(trace.secondary_oam[0], SCANLINE as u8);
+}
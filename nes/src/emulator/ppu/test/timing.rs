@@ -0,0 +1,160 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::clock::Ticker;
+use crate::emulator::io::nop::DummyVideo;
+use crate::emulator::memory::{Reader, Writer};
+use crate::emulator::ppu::test::new_ppu;
+use crate::emulator::ppu::{flags, Colour, VideoOut, PPU};
+use crate::emulator::Region;
+
+fn run_to(ppu: &mut PPU, scanline: u16, dot: u16) {
+    while !(ppu.scanline == scanline && ppu.cycle == dot) {
+        ppu.tick();
+    }
+}
+
+fn cycles_between_vblanks(ppu: &mut PPU) -> u64 {
+    // Run until the start of vblank so we measure a whole frame.
+    run_to(ppu, 241, 1);
+
+    let mut cycles = 0u64;
+    loop {
+        cycles += ppu.tick() as u64;
+        if ppu.scanline == 241 && ppu.cycle == 1 {
+            return cycles;
+        }
+    }
+}
+
+#[test]
+fn test_ntsc_frame_length() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    assert_eq!(cycles_between_vblanks(&mut ppu), 262 * 341);
+}
+
+#[test]
+fn test_pal_frame_length() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    ppu.set_region(Region::PAL);
+    assert_eq!(cycles_between_vblanks(&mut ppu), 312 * 341);
+}
+
+#[test]
+fn test_ntsc_odd_frames_skip_a_dot_when_rendering() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    ppu.write(0x2001, 0x18);
+
+    // Frames alternate between full length and one dot short.
+    let first = cycles_between_vblanks(&mut ppu);
+    let second = cycles_between_vblanks(&mut ppu);
+    assert_eq!(first + second, 2 * 262 * 341 - 1);
+    assert_eq!(first.max(second), 262 * 341);
+
+    // The short frame is the one leading up to an odd frame.
+    run_to(&mut ppu, 261, 339);
+    let skips = !ppu.stats().odd_frame;
+    ppu.tick();
+    assert_eq!(ppu.stats().scanline, if skips { 0 } else { 261 });
+    assert_eq!(ppu.stats().dot, if skips { 0 } else { 340 });
+}
+
+#[test]
+fn test_pal_never_skips_a_dot() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    ppu.set_region(Region::PAL);
+    ppu.write(0x2001, 0x18);
+    assert_eq!(cycles_between_vblanks(&mut ppu), 312 * 341);
+    assert_eq!(cycles_between_vblanks(&mut ppu), 312 * 341);
+}
+
+#[test]
+fn test_pre_render_clears_status_flags() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    run_to(&mut ppu, 261, 1);
+    ppu.ppustatus.set(flags::PPUSTATUS::V);
+    ppu.ppustatus.set(flags::PPUSTATUS::S);
+    ppu.ppustatus.set(flags::PPUSTATUS::O);
+
+    // Running dot 1 clears them all.
+    ppu.tick();
+    assert!(!ppu.ppustatus.is_set(flags::PPUSTATUS::V));
+    assert!(!ppu.ppustatus.is_set(flags::PPUSTATUS::S));
+    assert!(!ppu.ppustatus.is_set(flags::PPUSTATUS::O));
+}
+
+#[test]
+fn test_vblank_flag_not_visible_until_dot_after_set() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+
+    // Reading in the dot the flag is set misses it, and stops it being seen all frame.
+    run_to(&mut ppu, 241, 1);
+    ppu.tick();
+    assert_eq!(ppu.read(0x2002) & 0x80, 0x00);
+    ppu.tick();
+    assert_eq!(ppu.read(0x2002) & 0x80, 0x00);
+
+    // Reading a dot later sees it.
+    run_to(&mut ppu, 241, 1);
+    ppu.tick();
+    ppu.tick();
+    assert_eq!(ppu.read(0x2002) & 0x80, 0x80);
+}
+
+#[test]
+fn test_warm_up_and_frame_parity() {
+    let mut ppu = new_ppu(Box::new(DummyVideo {}));
+    let stats = ppu.stats();
+    assert_eq!(stats.frame_count, 0);
+    assert!(!stats.warmed_up);
+
+    // Still warming up during the first vblank.
+    run_to(&mut ppu, 241, 1);
+    assert!(!ppu.stats().warmed_up);
+
+    // Reaching the pre-render scanline again completes warm-up.
+    run_to(&mut ppu, 261, 0);
+    let stats = ppu.stats();
+    assert!(stats.warmed_up);
+    assert_eq!(stats.scanline, 261);
+    assert_eq!(stats.dot, 0);
+
+    // Frames alternate between even and odd.
+    let parity = stats.odd_frame;
+    run_to(&mut ppu, 241, 1);
+    assert_eq!(ppu.stats().odd_frame, !parity);
+    cycles_between_vblanks(&mut ppu);
+    assert_eq!(ppu.stats().odd_frame, parity);
+}
+
+#[derive(Default)]
+struct FrameCounter {
+    scanlines: Vec<u16>,
+    frames: u32,
+}
+
+impl VideoOut for FrameCounter {
+    fn end_frame(&mut self, frame: &[Colour]) {
+        assert_eq!(frame.len(), 256 * 240);
+        assert_eq!(self.scanlines.last(), Some(&239));
+        self.frames += 1;
+    }
+
+    fn end_scanline(&mut self, scanline: u16, _frame: &[Colour]) {
+        self.scanlines.push(scanline);
+    }
+}
+
+#[test]
+fn test_frame_delivered_after_last_visible_scanline() {
+    let counter = Rc::new(RefCell::new(FrameCounter::default()));
+    let mut ppu = new_ppu(Box::new(counter.clone()));
+
+    // From the pre-render line, through a whole frame, and into vblank.
+    run_to(&mut ppu, 0, 0);
+    run_to(&mut ppu, 241, 1);
+
+    let counter = counter.borrow();
+    assert_eq!(counter.frames, 1);
+    assert_eq!(counter.scanlines, (0..240).collect::<Vec<u16>>());
+}
@@ -0,0 +1,157 @@
+// Long-running stress test.  Mashes random buttons into a ROM for as many frames as asked, hashing
+// the machine state along the way.  Everything is driven by a seeded PRNG, so the same seed always
+// gives the same run, and any panic or CPU jam comes back with a movie that replays straight into
+// it.
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::panic;
+use std::rc::Rc;
+
+use crate::emulator::config::Config;
+use crate::emulator::ines::ROM;
+use crate::emulator::io;
+use crate::emulator::io::event::EventBus;
+use crate::emulator::movie::{FrameInput, Movie, MovieSession};
+use crate::emulator::state::SaveState;
+use crate::emulator::{Region, NES};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SoakOptions {
+    pub seed: u64,
+    pub frames: u64,
+    // Record a state hash every this many frames.  Zero turns hashing off.
+    pub hash_interval: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Crash {
+    // Frame the panic or jam happened on, counting from 0.
+    pub frame: u64,
+    pub message: String,
+    // Every input up to and including the crashing frame.
+    pub movie: Movie,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SoakReport {
+    pub frames: u64,
+    // (frame, hash) pairs.  Two runs with the same seed should produce identical lists.
+    pub hashes: Vec<(u64, u64)>,
+    pub crash: Option<Crash>,
+}
+
+// xorshift64*.  Tiny and plenty random enough for button mashing.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // The state must never be zero.
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // True one time in `n`.
+    fn one_in(&mut self, n: u64) -> bool {
+        self.next() < u64::MAX / n
+    }
+}
+
+// Produces input that looks a bit like a person playing: buttons are held for a while rather than
+// changing every frame, and there's the occasional reset.
+struct InputGenerator {
+    rng: Rng,
+    held: u8,
+}
+
+impl InputGenerator {
+    fn next(&mut self) -> FrameInput {
+        if self.rng.one_in(8) {
+            self.held = (self.rng.next() >> 32) as u8;
+        }
+        FrameInput {
+            reset: self.rng.one_in(20_000),
+            joy1: self.held,
+            joy2: 0,
+            ..FrameInput::default()
+        }
+    }
+}
+
+// Fails if the ROM can't be emulated at all.
+pub fn soak(rom: &ROM, rom_filename: &str, options: &SoakOptions) -> Result<SoakReport, String> {
+    let screen = Rc::new(RefCell::new(io::Screen::new()));
+    let event_bus = Rc::new(RefCell::new(EventBus::new()));
+    let rom = ROM::from_bytes(rom.bytes().to_vec());
+    let mut nes = NES::try_new(
+        event_bus,
+        screen,
+        io::nop::DummyAudio {},
+        rom,
+        &Config::default(),
+    )?;
+
+    let movie = Movie::new(rom_filename, nes.region() == Region::PAL);
+    let mut session = MovieSession::record(&mut nes, movie);
+    let mut input = InputGenerator {
+        rng: Rng::new(options.seed),
+        held: 0,
+    };
+
+    let mut report = SoakReport::default();
+    for frame in 0..options.frames {
+        let live = input.next();
+        // The NES is thrown away after a panic, so it doesn't matter what state it's left in.
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            session.step(&mut nes, live);
+        }));
+
+        let message = match result {
+            Err(payload) => Some(if let Some(s) = payload.downcast_ref::<&str>() {
+                String::from(*s)
+            } else if let Some(s) = payload.downcast_ref::<String>() {
+                s.clone()
+            } else {
+                String::from("unknown panic")
+            }),
+            Ok(()) => nes.cpu_jam().map(|jam| jam.to_string()),
+        };
+        if let Some(message) = message {
+            report.crash = Some(Crash {
+                frame,
+                message,
+                movie: session.movie().clone(),
+            });
+            return Ok(report);
+        }
+
+        report.frames += 1;
+        if options.hash_interval != 0 && report.frames % options.hash_interval == 0 {
+            report.hashes.push((report.frames, state_hash(&mut nes)));
+        }
+    }
+
+    Ok(report)
+}
+
+// Hashes the parts of the machine a game can see, plus the picture.
+pub fn state_hash(nes: &mut NES) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    let cpu = nes.cpu.borrow_mut().freeze();
+    hasher.write(&[cpu.a, cpu.x, cpu.y, cpu.sp, cpu.p]);
+    hasher.write_u16(cpu.pc);
+
+    for memory in [&nes.ram, &nes.sram, &nes.vram].iter() {
+        hasher.write(&memory.borrow_mut().freeze().data);
+    }
+
+    nes.screen.borrow().do_render(|frame| hasher.write(frame));
+    hasher.finish()
+}
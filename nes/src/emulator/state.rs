@@ -1,8 +1,12 @@
 // This file contains the save states API.
 // Changes could break old save states.
 
+use alloc::vec::Vec;
+
 use serde::{Deserialize, Serialize};
 
+use crate::emulator::cpu::Jam;
+use crate::emulator::metadata::Metadata;
 use crate::emulator::ppu::MirrorMode;
 
 pub trait SaveState<'de, T: Serialize + Deserialize<'de>> {
@@ -18,9 +22,18 @@ pub struct NESState {
     pub ram: MemoryState,
     pub sram: MemoryState,
     pub vram: MemoryState,
-    pub screen: ScreenState,
+    #[serde(default)]
+    pub expansion_ram: Option<MemoryState>,
     pub joy1: ControllerState,
     pub joy2: ControllerState,
+
+    // Whether the PPU's NMI line was already high, so a load doesn't fire an NMI twice.
+    #[serde(default)]
+    pub nmi_pin: bool,
+
+    // Missing from states saved before it was recorded, which are loaded unchecked.
+    #[serde(default)]
+    pub metadata: Option<Metadata>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -40,6 +53,8 @@ pub struct CPUState {
     pub dec_arith_on: bool,
     pub irq_flip_flop: bool,
     pub nmi_flip_flop: bool,
+    #[serde(default)]
+    pub jam: Option<Jam>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -81,6 +96,13 @@ pub struct PPUState {
 
     pub scanline: u16,
     pub cycle: u16,
+
+    #[serde(default)]
+    pub frame_count: u64,
+
+    #[serde(default)]
+    pub warmed_up: bool,
+
     pub tmp_pattern_coords: u8,
     pub tmp_attribute_byte: u8,
     pub tmp_oam_byte: u8,
@@ -94,12 +116,10 @@ pub struct PPUState {
     pub sprite_0_this_line: bool,
     pub ppudata_read_buffer: u8,
     pub bus_latch: u8,
-}
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct ScreenState {
-    pub scanline: u32,
-    pub dot: u32,
+    // Dots until each bit of the latch decays, bit 0 first.
+    #[serde(default)]
+    pub bus_latch_decay_bits: Vec<u32>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -117,6 +137,8 @@ pub enum MapperState {
     MMC3(MMC3State),
     AXROM(AXROMState),
     ColorDreams(ColorDreamsState),
+    GXROM(GXROMState),
+    MMC2(MMC2State),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -132,6 +154,15 @@ pub struct MMC1State {
     pub chr_mem: MemoryState,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MMC2State {
+    pub prg_bank: u8,
+    pub chr_banks: [[u8; 2]; 2],
+    pub latches: [u8; 2],
+    pub mirror_mode: MirrorMode,
+    pub chr_mem: MemoryState,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct UXROMState {
     pub prg_bank: u8,
@@ -174,3 +205,10 @@ pub struct ColorDreamsState {
     pub chr_bank: u8,
     pub chr_mem: MemoryState,
 }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GXROMState {
+    pub prg_bank: u8,
+    pub chr_bank: u8,
+    pub chr_mem: MemoryState,
+}
@@ -0,0 +1,39 @@
+use crate::emulator::busstats::Device;
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::test_resource_path;
+
+#[test]
+fn test_bus_stats_count_per_device() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    assert!(nes.bus_stats().is_none());
+
+    nes.enable_bus_stats();
+    for _ in 0..10 {
+        nes.run_frame();
+    }
+
+    {
+        let stats = nes.bus_stats().unwrap();
+        assert_eq!(stats.frames(), 10);
+
+        let prg = stats.frame(Device::PrgRom);
+        let ram = stats.frame(Device::Ram);
+        assert!(prg.reads > 0);
+        assert_eq!(prg.writes, 0);
+        assert!(ram.writes > 0);
+        // The menu waits for vblank on PPUSTATUS.
+        assert!(stats.frame(Device::PpuRegisters).reads > 0);
+
+        // NROM has nothing at $4020-$5FFF.
+        assert_eq!(stats.frame(Device::ExpansionRam).last_access, None);
+
+        // The game is always fetching code.
+        let cycles = nes.cpu.borrow().cycles();
+        let last_prg = prg.last_access.unwrap();
+        assert!(last_prg <= cycles && cycles - last_prg < 10);
+    }
+
+    nes.disable_bus_stats();
+    assert!(nes.bus_stats().is_none());
+}
@@ -0,0 +1,98 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::config::Config;
+use crate::emulator::ines::ROM;
+use crate::emulator::io;
+use crate::emulator::io::event::EventBus;
+use crate::emulator::memory::EXPANSION_RAM_SIZE;
+use crate::emulator::state::SaveState;
+use crate::emulator::test::nrom_image;
+use crate::emulator::NES;
+
+// NROM cart with a NES 2.0 header giving `prg_ram_sizes` as byte 10, so 0x50 asks for 2KB of
+// battery backed PRG-RAM.  Writes $AB to $6005 and reads it back through the mirror at $7805 into
+// $10, then does the same with $CD at $4020, into $11.
+fn ram_rom(prg_ram_sizes: u8) -> ROM {
+    let program = [
+        0xA9, 0xAB, // LDA #$AB
+        0x8D, 0x05, 0x60, // STA $6005
+        0xAD, 0x05, 0x78, // LDA $7805
+        0x85, 0x10, // STA $10
+        0xA9, 0xCD, // LDA #$CD
+        0x8D, 0x20, 0x40, // STA $4020
+        0xAD, 0x20, 0x40, // LDA $4020
+        0x85, 0x11, // STA $11
+        0x4C, 0x14, 0x80, // JMP $8014
+    ];
+
+    let mut data = nrom_image(&program);
+    // Battery and NES 2.0.
+    data[6] = 0x02;
+    data[7] = 0x08;
+    data[10] = prg_ram_sizes;
+    ROM::from_bytes(data)
+}
+
+fn new_nes(rom: ROM, expansion_ram: bool) -> NES {
+    NES::new(
+        Rc::new(RefCell::new(EventBus::new())),
+        Rc::new(RefCell::new(io::Screen::new())),
+        io::nop::DummyAudio {},
+        rom,
+        &Config {
+            expansion_ram,
+            ..Config::default()
+        },
+    )
+}
+
+#[test]
+fn test_small_prg_ram_mirrors() {
+    let mut nes = new_nes(ram_rom(0x50), false);
+    nes.run_frame();
+
+    assert_eq!(nes.peek(0x0010), 0xAB);
+    assert_eq!(nes.peek(0x6805), 0xAB);
+    assert_eq!(nes.battery_ram().map(|data| data.len()), Some(0x800));
+
+    // Nothing at $4020, so the read saw the last byte on the bus.
+    assert_eq!(nes.peek(0x0011), 0x40);
+    assert_eq!(nes.peek(0x4020), 0);
+}
+
+#[test]
+fn test_odd_prg_ram_size_rounds_up() {
+    // 64 << 6 bytes of volatile PRG-RAM and 64 << 5 battery backed, 6KB in all.
+    let mut nes = new_nes(ram_rom(0x56), false);
+    nes.run_frame();
+
+    // It's wired up as 8KB, so $7805 isn't a mirror of $6005.
+    assert_eq!(nes.peek(0x6005), 0xAB);
+    assert_eq!(nes.peek(0x0010), 0x00);
+    assert_eq!(nes.peek(0x7805), 0x00);
+    assert_eq!(nes.battery_ram().map(|data| data.len()), Some(0x2000));
+}
+
+#[test]
+fn test_expansion_ram_is_saved() {
+    let mut nes = new_nes(ram_rom(0x50), true);
+    nes.run_frame();
+    assert_eq!(nes.peek(0x0011), 0xCD);
+
+    let save = nes.battery_ram().unwrap();
+    assert_eq!(save.len(), 0x800 + EXPANSION_RAM_SIZE);
+    assert_eq!(save[0x005], 0xAB);
+    assert_eq!(save[0x800], 0xCD);
+
+    let mut restored = new_nes(ram_rom(0x50), true);
+    restored.load_battery_ram(&save);
+    assert_eq!(restored.peek(0x6005), 0xAB);
+    assert_eq!(restored.peek(0x4020), 0xCD);
+
+    // Save states cover it too.
+    let state = nes.freeze();
+    nes.poke(0x4020, 0x00);
+    nes.hydrate(state);
+    assert_eq!(nes.peek(0x4020), 0xCD);
+}
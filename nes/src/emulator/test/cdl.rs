@@ -0,0 +1,74 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::cdl::{CODE, DATA};
+use crate::emulator::config::Config;
+use crate::emulator::ines::ROM;
+use crate::emulator::io;
+use crate::emulator::io::event::EventBus;
+use crate::emulator::NES;
+
+// UxROM cart with 3 PRG banks.  The fixed bank switches in bank 1, reads a byte from it and calls
+// a subroutine in it, then loops forever.
+fn bank_switching_rom() -> ROM {
+    let program = [
+        0xA9, 0x01, // LDA #$01
+        0x8D, 0x00, 0x80, // STA $8000
+        0xAD, 0x10, 0x80, // LDA $8010
+        0x20, 0x20, 0x80, // JSR $8020
+        0x4C, 0x0B, 0xC0, // JMP $C00B
+    ];
+
+    let mut data = vec![
+        b'N', b'E', b'S', 0x1A, 3, 0, 0x20, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut prg = vec![0xEA; 0xC000];
+    // RTS, in bank 1.
+    prg[0x4020] = 0x60;
+    prg[0x8000..0x8000 + program.len()].copy_from_slice(&program);
+    // Reset vector to $C000.
+    prg[0xBFFC] = 0x00;
+    prg[0xBFFD] = 0xC0;
+    data.extend(prg);
+    ROM::from_bytes(data)
+}
+
+#[test]
+fn test_code_data_log_follows_banks() {
+    let mut nes = NES::new(
+        Rc::new(RefCell::new(EventBus::new())),
+        Rc::new(RefCell::new(io::Screen::new())),
+        io::nop::DummyAudio {},
+        bank_switching_rom(),
+        &Config::default(),
+    );
+    assert!(nes.code_data_log().is_none());
+    assert_eq!(nes.prg_rom_len(), 0xC000);
+    assert_eq!(nes.prg_rom_offset(0xC000), Some(0x8000));
+    assert_eq!(nes.prg_rom_offset(0x6000), None);
+
+    nes.enable_code_data_log();
+    nes.run_frame();
+    assert_eq!(nes.prg_rom_offset(0x8020), Some(0x4020));
+
+    {
+        let cdl = nes.code_data_log().unwrap();
+        assert_eq!(cdl.len(), 0xC000);
+        for offset in 0x8000..0x800E {
+            assert_eq!(cdl.flags(offset), CODE, "${:04X}", offset);
+        }
+        assert_eq!(cdl.flags(0x4010), DATA);
+        assert_eq!(cdl.flags(0x4020), CODE);
+        // Nothing ran from bank 0, even though it was mapped in at first.
+        assert_eq!(cdl.flags(0x0020), 0);
+
+        let counts = cdl.counts();
+        assert_eq!((counts.code, counts.data), (15, 1));
+        assert_eq!(counts.unused, 0xC000 - 16);
+    }
+
+    nes.clear_code_data_log();
+    assert_eq!(nes.code_data_log().unwrap().counts().unused, 0xC000);
+    nes.disable_code_data_log();
+    assert!(nes.code_data_log().is_none());
+}
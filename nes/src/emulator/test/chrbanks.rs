@@ -0,0 +1,35 @@
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::test_resource_path;
+
+#[test]
+fn test_chr_bank_tracking() {
+    let path = test_resource_path("mappers/M3_P32K_C32K_H.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    assert!(nes.chr_bank_tracker().is_none());
+
+    nes.enable_chr_bank_tracking();
+    for _ in 0..10 {
+        nes.run_frame();
+    }
+
+    {
+        let tracker = nes.chr_bank_tracker().unwrap();
+        assert!(!tracker.last_frame().is_empty());
+        assert!(!tracker.recent_sets().is_empty());
+        for banks in tracker.recent_sets() {
+            // 32KB of CHR is 32 1KB banks.
+            assert!(banks.iter().all(|&bank| bank < 32));
+        }
+    }
+
+    nes.disable_chr_bank_tracking();
+    assert!(nes.chr_bank_tracker().is_none());
+}
+
+#[test]
+fn test_chr_bank_tracking_needs_switchable_chr() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    nes.enable_chr_bank_tracking();
+    assert!(nes.chr_bank_tracker().is_none());
+}
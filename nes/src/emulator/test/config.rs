@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::config::Config;
+use crate::emulator::ines::ROM;
+use crate::emulator::io;
+use crate::emulator::io::event::EventBus;
+use crate::emulator::test::test_resource_path;
+use crate::emulator::{Region, NES};
+
+fn nes_with_config(config: &Config) -> NES {
+    let rom = ROM::load(test_resource_path("nestest/nestest.nes"));
+    let event_bus = Rc::new(RefCell::new(EventBus::new()));
+    let screen = Rc::new(RefCell::new(io::Screen::new()));
+    NES::new(event_bus, screen, io::nop::DummyAudio {}, rom, config)
+}
+
+#[test]
+fn test_region_from_rom_by_default() {
+    let nes = nes_with_config(&Config::default());
+    assert_eq!(nes.region(), Region::NTSC);
+}
+
+#[test]
+fn test_config_overrides_region() {
+    let config = Config {
+        region: Some(Region::PAL),
+        ..Config::default()
+    };
+    let nes = nes_with_config(&config);
+    assert_eq!(nes.region(), Region::PAL);
+    assert_eq!(nes.config(), &config);
+}
+
+#[test]
+fn test_parse_hardware() {
+    for name in &["NTSC", "ntsc", "2A03", "rp2a03", "RP2C02"] {
+        assert_eq!(Region::parse(name), Ok(Region::NTSC), "{}", name);
+    }
+    for name in &["pal", "2a07", "2C07", "RP2C07"] {
+        assert_eq!(Region::parse(name), Ok(Region::PAL), "{}", name);
+    }
+    assert!(Region::parse("2C03").is_err());
+    assert!(Region::parse("dendy").is_err());
+}
@@ -0,0 +1,38 @@
+use crate::emulator::io::dirty::DirtyTiles;
+use crate::emulator::io::event::{Event, Key};
+
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::run_for;
+use crate::emulator::test::test_resource_path;
+use crate::emulator::NES;
+
+fn take_dirty(nes: &mut NES) -> DirtyTiles {
+    let mut dirty = DirtyTiles::clean();
+    nes.screen
+        .borrow_mut()
+        .do_render_dirty(|_, tiles| dirty = tiles.clone());
+    dirty
+}
+
+#[test]
+fn test_dirty_tiles() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, event_bus, _) = prepare_ete_test(&path);
+    nes.screen.borrow_mut().set_dirty_tracking(true);
+
+    // Nothing has been drawn yet, so the first render is a full redraw.
+    run_for(&mut nes, 2_000_000);
+    assert_eq!(take_dirty(&mut nes), DirtyTiles::all());
+
+    // The menu is static.
+    nes.run_frame();
+    nes.run_frame();
+    assert!(!take_dirty(&mut nes).any());
+
+    // Starting the tests redraws part of the screen, but not all of it.
+    event_bus.borrow_mut().broadcast(Event::KeyDown(Key::A));
+    run_for(&mut nes, 7_000_000);
+    let dirty = take_dirty(&mut nes);
+    assert!(dirty.any());
+    assert_ne!(dirty, DirtyTiles::all());
+}
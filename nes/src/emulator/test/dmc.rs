@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::config::Config;
+use crate::emulator::ines::ROM;
+use crate::emulator::io;
+use crate::emulator::io::event::EventBus;
+use crate::emulator::state::SaveState;
+use crate::emulator::test::nrom_with_program;
+use crate::emulator::NES;
+
+// NROM cart which optionally starts a 17 byte DMC sample at the fastest rate, then counts in X and
+// Y for as long as it's left running.
+fn counting_rom(play_sample: bool) -> ROM {
+    let enable = if play_sample { 0x10 } else { 0x00 };
+    let program = [
+        0xA9, 0x0F, // LDA #$0F
+        0x8D, 0x10, 0x40, // STA $4010
+        0xA9, 0x01, // LDA #$01
+        0x8D, 0x13, 0x40, // STA $4013
+        0xA9, enable, // LDA #enable
+        0x8D, 0x15, 0x40, // STA $4015
+        0xE8, // INX
+        0xD0, 0xFD, // BNE -3
+        0xC8, // INY
+        0x4C, 0x0F, 0x80, // JMP $800F
+    ];
+
+    nrom_with_program(&program)
+}
+
+// How far the counter gets by the end of the first whole frame.
+fn count_for_a_frame(play_sample: bool) -> u32 {
+    let mut nes = NES::new(
+        Rc::new(RefCell::new(EventBus::new())),
+        Rc::new(RefCell::new(io::Screen::new())),
+        io::nop::DummyAudio {},
+        counting_rom(play_sample),
+        &Config::default(),
+    );
+    nes.run_frame();
+    nes.run_frame();
+    let cpu = nes.cpu.borrow_mut().freeze();
+    (cpu.y as u32) << 8 | cpu.x as u32
+}
+
+#[test]
+fn test_dmc_fetches_stall_cpu() {
+    let free = count_for_a_frame(false);
+    let stalled = count_for_a_frame(true);
+
+    // Each of the 17 fetches takes 4 cycles from a 5 cycle loop.
+    let lost_cycles = (free - stalled) * 5;
+    assert!(
+        (17 * 4 - 5..=17 * 4 + 5).contains(&lost_cycles),
+        "Lost {} cycles",
+        lost_cycles
+    );
+}
@@ -0,0 +1,99 @@
+use std::fs;
+
+use crate::embed::{ButtonState, Emulator};
+use crate::emulator::controller::Button;
+use crate::emulator::movie::FrameInput;
+use crate::emulator::test::test_resource_path;
+
+fn nestest() -> Emulator {
+    let rom = fs::read(test_resource_path("nestest/nestest.nes")).unwrap();
+    Emulator::new(rom).unwrap()
+}
+
+#[test]
+fn test_embed_rejects_bad_roms() {
+    assert!(Emulator::new(vec![]).is_err());
+    assert!(Emulator::new(b"NES\x1A".to_vec()).is_err());
+
+    // Mapper 5, MMC5, which isn't emulated.
+    let mut mmc5 = vec![
+        b'N', b'E', b'S', 0x1A, 1, 1, 0x50, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    mmc5.extend(vec![0; 0x6000]);
+    assert_eq!(
+        Emulator::new(mmc5).err(),
+        Some(String::from("Unsupported mapper: 5"))
+    );
+}
+
+#[test]
+fn test_embed_runs_frames() {
+    let mut emulator = nestest();
+    emulator.step_frame();
+    emulator.step_frame();
+    assert_eq!(emulator.frame_count(), 2);
+    assert!(emulator.framebuffer().iter().any(|&byte| byte != 0));
+
+    emulator.write_memory(0x0300, 0x42);
+    assert_eq!(emulator.read_memory(0x0300), 0x42);
+
+    let mut buttons = ButtonState::pressed(Button::Start);
+    buttons.set(Button::A, true);
+    assert_eq!(buttons.to_bits(), 0x09);
+    emulator.set_buttons(0, buttons);
+    assert_eq!(emulator.nes().joy1.borrow().buttons(), 0x09);
+
+    emulator.set_buttons(3, ButtonState::pressed(Button::Left));
+    assert!(emulator.nes().four_score());
+    assert_eq!(emulator.nes().joy4.borrow().buttons(), 0x40);
+}
+
+#[test]
+fn test_embed_frame_input() {
+    for bits in 0..=0xFF {
+        assert_eq!(ButtonState::from_bits(bits).to_bits(), bits);
+    }
+
+    let mut emulator = nestest();
+    emulator.set_input(&FrameInput {
+        joy1: 0x81,
+        joy2: 0x02,
+        ..FrameInput::default()
+    });
+    assert_eq!(emulator.nes().joy1.borrow().buttons(), 0x81);
+    assert_eq!(emulator.nes().joy2.borrow().buttons(), 0x02);
+    assert!(!emulator.nes().four_score());
+
+    emulator.set_input(&FrameInput {
+        joy3: 0x10,
+        ..FrameInput::default()
+    });
+    assert!(emulator.nes().four_score());
+    assert_eq!(emulator.nes().joy1.borrow().buttons(), 0);
+    assert_eq!(emulator.nes().joy3.borrow().buttons(), 0x10);
+}
+
+#[test]
+fn test_embed_save_states() {
+    let mut emulator = nestest();
+    for _ in 0..10 {
+        emulator.step_frame();
+    }
+    let state = emulator.save_state();
+
+    let run = |emulator: &mut Emulator| {
+        emulator.set_buttons(0, ButtonState::pressed(Button::Down));
+        for _ in 0..5 {
+            emulator.step_frame();
+        }
+        let ram: Vec<u8> = (0..0x800)
+            .map(|address| emulator.read_memory(address))
+            .collect();
+        (emulator.framebuffer().to_vec(), ram)
+    };
+
+    let first = run(&mut emulator);
+    emulator.load_state(state);
+    emulator.set_buttons(0, ButtonState::default());
+    assert_eq!(run(&mut emulator), first);
+}
@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::config::Config;
+use crate::emulator::eventlog::{EventFilter, Severity, Subsystem, DEFAULT_CAPACITY};
+use crate::emulator::ines::ROM;
+use crate::emulator::io;
+use crate::emulator::io::event::EventBus;
+use crate::emulator::state::SaveState;
+use crate::emulator::test::nrom_image;
+use crate::emulator::{Region, NES};
+
+// NROM cart which turns on NMIs and rendering, writing PPUMASK twice, then runs `then`.  NMIs
+// return straight away.
+fn nmi_rom(then: &[u8]) -> ROM {
+    let mut program = vec![
+        0xA9, 0x80, // LDA #$80
+        0x8D, 0x00, 0x20, // STA $2000
+        0xA9, 0x1E, // LDA #$1E
+        0x8D, 0x01, 0x20, // STA $2001
+        0x8D, 0x01, 0x20, // STA $2001
+    ];
+    program.extend_from_slice(then);
+
+    let mut data = nrom_image(&program);
+    // RTI at $BF00, and the NMI vector pointing to it.  PRG-ROM starts after the 16 byte header.
+    let prg = &mut data[16..];
+    prg[0x3F00] = 0x40;
+    prg[0x3FFA] = 0x00;
+    prg[0x3FFB] = 0xBF;
+    ROM::from_bytes(data)
+}
+
+fn new_nes(then: &[u8]) -> NES {
+    new_nes_with_config(then, &Config::default())
+}
+
+fn new_nes_with_config(then: &[u8], config: &Config) -> NES {
+    NES::new(
+        Rc::new(RefCell::new(EventBus::new())),
+        Rc::new(RefCell::new(io::Screen::new())),
+        io::nop::DummyAudio {},
+        nmi_rom(then),
+        config,
+    )
+}
+
+// JMP to itself.
+const SPIN: [u8; 3] = [0x4C, 0x0D, 0x80];
+
+#[test]
+fn test_logs_register_changes_and_nmis() {
+    let mut nes = new_nes(&SPIN);
+    nes.enable_event_log(DEFAULT_CAPACITY);
+    for _ in 0..3 {
+        nes.run_frame();
+    }
+
+    let log = nes.event_log().unwrap();
+    let messages: Vec<String> = log
+        .events()
+        .filter(|event| event.message != "NMI")
+        .map(|event| event.message.clone())
+        .collect();
+    // The second write to PPUMASK didn't change it.  The APU's frame IRQ is on from power up, and
+    // nothing acknowledges it, so it's only raised once.
+    assert_eq!(
+        messages,
+        vec![
+            "$8002 set PPUCTRL to $80",
+            "$8007 set PPUMASK to $1E",
+            "IRQ"
+        ]
+    );
+
+    let nmis: Vec<_> = log
+        .events()
+        .filter(|event| event.message == "NMI")
+        .collect();
+    assert!(nmis.len() >= 2);
+    for nmi in nmis {
+        assert_eq!(nmi.subsystem, Subsystem::Ppu);
+        assert_eq!(nmi.severity, Severity::Debug);
+        assert!((241..261).contains(&nmi.scanline), "{}", nmi);
+    }
+}
+
+#[test]
+fn test_filter() {
+    let mut nes = new_nes(&SPIN);
+    nes.enable_event_log(DEFAULT_CAPACITY);
+    nes.run_frame();
+    nes.run_frame();
+    nes.log_event(
+        Severity::Warning,
+        Subsystem::Frontend,
+        String::from("Something odd"),
+    );
+
+    let log = nes.event_log().unwrap();
+    let mut filter = EventFilter::default();
+    assert_eq!(log.filtered(&filter).count(), log.len());
+
+    filter.min_severity = Severity::Info;
+    let shown: Vec<_> = log.filtered(&filter).collect();
+    assert_eq!(shown.len(), 1);
+    assert_eq!(shown[0].message, "Something odd");
+
+    filter.toggle(Subsystem::Frontend);
+    assert!(!filter.shows(Subsystem::Frontend));
+    assert_eq!(log.filtered(&filter).count(), 0);
+}
+
+#[test]
+fn test_logs_jams() {
+    // KIL.
+    let mut nes = new_nes(&[0x02]);
+    nes.enable_event_log(DEFAULT_CAPACITY);
+    nes.run_frame();
+
+    let log = nes.event_log().unwrap();
+    let errors: Vec<_> = log
+        .events()
+        .filter(|event| event.severity == Severity::Error)
+        .collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].subsystem, Subsystem::Cpu);
+    assert_eq!(errors[0].message, "CPU jammed on $02 at $800D");
+}
+
+#[test]
+fn test_logs_ppu_faults() {
+    // A PAL state from late in vblank has a scanline an NTSC PPU never reaches.
+    let pal = Config {
+        region: Some(Region::PAL),
+        ..Config::default()
+    };
+    let mut pal_nes = new_nes_with_config(&SPIN, &pal);
+    pal_nes.run_frame();
+    while pal_nes.ppu.borrow().stats().scanline < 300 {
+        pal_nes.tick();
+    }
+    let state = pal_nes.freeze();
+
+    let mut nes = new_nes(&SPIN);
+    nes.enable_event_log(DEFAULT_CAPACITY);
+    nes.hydrate(state);
+    nes.run_frame();
+
+    let log = nes.event_log().unwrap();
+    let errors: Vec<_> = log
+        .events()
+        .filter(|event| event.severity == Severity::Error)
+        .collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].subsystem, Subsystem::Ppu);
+    assert!(
+        errors[0].message.starts_with("PPU scanline 30"),
+        "{}",
+        errors[0]
+    );
+}
+
+#[test]
+fn test_keeps_newest_events() {
+    let mut nes = new_nes(&SPIN);
+    nes.enable_event_log(4);
+    for _ in 0..10 {
+        nes.run_frame();
+    }
+
+    let log = nes.event_log().unwrap();
+    assert_eq!(log.len(), 4);
+    assert!(log.dropped() > 0);
+    assert!(log.events().all(|event| event.message == "NMI"));
+    let frames: Vec<u64> = log.events().map(|event| event.frame).collect();
+    assert!(frames.windows(2).all(|pair| pair[1] == pair[0] + 1));
+    assert!(frames[0] > 3);
+}
+
+#[test]
+fn test_log_off_by_default() {
+    let mut nes = new_nes(&SPIN);
+    nes.run_frame();
+    nes.log_event(Severity::Error, Subsystem::Frontend, String::from("Lost"));
+    assert!(nes.event_log().is_none());
+}
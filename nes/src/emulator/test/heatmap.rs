@@ -0,0 +1,33 @@
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::test_resource_path;
+
+#[test]
+fn test_heatmap_counts_accesses() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    assert!(nes.heatmap().is_none());
+
+    nes.enable_heatmap(4);
+    for _ in 0..10 {
+        nes.run_frame();
+    }
+
+    {
+        let heatmap = nes.heatmap().unwrap();
+        assert_eq!(heatmap.frames(), 4);
+
+        let ram_writes: u32 = (0x0000..0x0800).map(|a| heatmap.writes(a)).sum();
+        let prg_reads: u32 = (0x8000..=0xFFFF).map(|a| heatmap.reads(a)).sum();
+        assert!(ram_writes > 0);
+        assert!(prg_reads > 0);
+
+        // RAM mirrors count towards the address they mirror.
+        assert_eq!(heatmap.writes(0x0800), heatmap.writes(0x0000));
+
+        // Nothing's mapped here.
+        assert_eq!(heatmap.reads(0x5000), 0);
+    }
+
+    nes.disable_heatmap();
+    assert!(nes.heatmap().is_none());
+}
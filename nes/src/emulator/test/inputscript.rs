@@ -0,0 +1,26 @@
+use crate::emulator::inputscript::InputScript;
+use crate::emulator::movie::MovieSession;
+
+use crate::emulator::test::assert_image;
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::test_resource_path;
+
+#[test]
+fn test_script_runs_nestest() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let script = InputScript::parse(
+        "# Start the tests from the menu, then give them time to finish.\n\
+         frame 8: P1 START\n\
+         frame 29: WAIT\n",
+    )
+    .unwrap();
+
+    let (mut nes, _, image) = prepare_ete_test(&path);
+    let mut session = MovieSession::play(&mut nes, script.to_movie("nestest.nes", false));
+    while !session.is_finished() {
+        session.step(&mut nes, Default::default());
+    }
+    session.finish(&mut nes);
+
+    assert_image(&image, test_resource_path("nestest/capture_02_passed.bmp"));
+}
@@ -0,0 +1,89 @@
+use std::rc::Rc;
+
+use crate::emulator::apu::Channel;
+use crate::emulator::config::Config;
+use crate::emulator::ines;
+use crate::emulator::io::event::{Event, Key};
+use crate::emulator::test::{prepare_ete_test, run_blargg_test_rom, test_resource_path};
+use crate::emulator::{Region, NES};
+
+// Returns the new ROM's CRC.
+fn swap_in(nes: &mut NES, name: &str) -> u32 {
+    let rom = ines::ROM::load(test_resource_path(name));
+    let crc = rom.crc32();
+    nes.load_rom(rom).unwrap();
+    crc
+}
+
+#[test]
+fn test_load_rom_runs_new_game() {
+    let (mut nes, _, _) = prepare_ete_test(test_resource_path("nestest/nestest.nes"));
+    for _ in 0..10 {
+        nes.run_frame();
+    }
+
+    let crc = swap_in(&mut nes, "ppu_sprite_hit/rom_singles/01-basics.nes");
+    assert_eq!(nes.metadata().rom_crc32, crc);
+    assert_eq!(nes.ppu.borrow().stats().frame_count, 0);
+    let (status, output) = run_blargg_test_rom(&mut nes, 100_000_000);
+    assert_eq!(status, 0x00);
+    assert_eq!(output, "\n01-basics\n\nPassed\n");
+}
+
+#[test]
+fn test_load_rom_keeps_frontend_setup() {
+    let (mut nes, event_bus, _) = prepare_ete_test(test_resource_path("nestest/nestest.nes"));
+    let joy1 = nes.joy1.clone();
+    nes.set_four_score(true);
+    nes.enable_heatmap(4);
+    nes.enable_event_log(16);
+    nes.cpu.borrow_mut().set_trace_capacity(32);
+    nes.add_breakpoint(0xC000);
+    let mut mixer = nes.apu.borrow().mixer_control();
+    mixer.toggle(Channel::Noise);
+    nes.apu.borrow_mut().set_mixer_control(mixer);
+
+    swap_in(&mut nes, "ppu_sprite_hit/rom_singles/01-basics.nes");
+    assert!(Rc::ptr_eq(&nes.joy1, &joy1));
+    assert!(nes.four_score());
+    assert_eq!(nes.heatmap().map(|heatmap| heatmap.window()), Some(4));
+    assert_eq!(nes.event_log().map(|log| log.capacity()), Some(16));
+    assert_eq!(nes.cpu.borrow().trace_capacity(), 32);
+    assert!(!nes.has_breakpoint(0xC000));
+    assert_eq!(nes.apu.borrow().mixer_control(), mixer);
+
+    // The event bus still reaches the joypads.
+    event_bus.borrow_mut().broadcast(Event::KeyDown(Key::Z));
+    assert_ne!(nes.joy1.borrow().buttons(), 0);
+}
+
+#[test]
+fn test_load_rom_with_config() {
+    let (mut nes, _, _) = prepare_ete_test(test_resource_path("nestest/nestest.nes"));
+    let rom = ines::ROM::load(test_resource_path(
+        "ppu_sprite_hit/rom_singles/01-basics.nes",
+    ));
+    let config = Config {
+        region: Some(Region::PAL),
+        ..Config::default()
+    };
+    nes.load_rom_with_config(rom, &config).unwrap();
+    assert_eq!(nes.region(), Region::PAL);
+    assert_eq!(nes.config(), &config);
+}
+
+#[test]
+fn test_load_rom_keeps_old_game_if_new_one_is_unsupported() {
+    let (mut nes, _, _) = prepare_ete_test(test_resource_path("nestest/nestest.nes"));
+    let crc = nes.metadata().rom_crc32;
+
+    // Mapper 5, MMC5, which isn't emulated.
+    let mut data = vec![
+        b'N', b'E', b'S', 0x1A, 1, 1, 0x50, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    data.extend(vec![0; 0x6000]);
+    let result = nes.load_rom(ines::ROM::from_bytes(data));
+    assert_eq!(result, Err(String::from("Unsupported mapper: 5")));
+    assert_eq!(nes.metadata().rom_crc32, crc);
+    nes.run_frame();
+}
@@ -48,3 +48,275 @@ test_mapper!(uxrom, "M2_P128K_V", 150_000_000);
 test_mapper!(cnrom, "M3_P32K_C32K_H", 100_000_000);
 test_mapper!(mmc3, "M4_P256K_C256K", 200_000_000);
 test_mapper!(axrom, "M7_P128K", 120_000_000);
+
+mod factory {
+    use crate::emulator::ines::ROM;
+    use crate::emulator::mappers;
+    use crate::emulator::ppu::MirrorMode;
+
+    use crate::emulator::test::test_resource_path;
+
+    #[test]
+    fn test_bank_switch_notification() {
+        let rom = ROM::load(test_resource_path("mappers/M2_P128K_V.nes"));
+        let mapper = mappers::from_ines(rom.mapper_number(), &rom).unwrap();
+        let mut mapper = mapper.borrow_mut();
+        assert_eq!(mapper.mirror_mode(), MirrorMode::Vertical);
+        assert!(!mapper.take_bank_switch());
+
+        mapper.write_prg(0x8000, 3);
+        assert!(mapper.take_bank_switch());
+        assert!(!mapper.take_bank_switch());
+    }
+
+    #[test]
+    fn test_chr_banks() {
+        let rom = ROM::load(test_resource_path("mappers/M3_P32K_C32K_H.nes"));
+        let mapper = mappers::from_ines(3, &rom).unwrap();
+        let mut mapper = mapper.borrow_mut();
+        assert_eq!(mapper.chr_banks(), Some([0, 1, 2, 3, 4, 5, 6, 7]));
+        mapper.write_prg(0x8000, 2);
+        assert_eq!(mapper.chr_banks(), Some([16, 17, 18, 19, 20, 21, 22, 23]));
+        assert_eq!(mapper.peek_chr(16 * 0x400 + 5), mapper.read_chr(5));
+
+        // CHR-RAM carts never switch.
+        let rom = ROM::load(test_resource_path("mappers/M2_P128K_V.nes"));
+        assert_eq!(
+            mappers::from_ines(2, &rom).unwrap().borrow().chr_banks(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mmc3_chr_banks() {
+        let rom = ROM::load(test_resource_path("mappers/M4_P256K_C256K.nes"));
+        let mapper = mappers::from_ines(4, &rom).unwrap();
+        let mut mapper = mapper.borrow_mut();
+
+        // R0 selects a 2KB bank at $0000, R2 a 1KB bank at $1000.
+        mapper.write_prg(0x8000, 0);
+        mapper.write_prg(0x8001, 10);
+        mapper.write_prg(0x8000, 2);
+        mapper.write_prg(0x8001, 33);
+        let banks = mapper.chr_banks().unwrap();
+        assert_eq!(&banks[0..2], &[10, 11]);
+        assert_eq!(banks[4], 33);
+
+        // Inverting swaps the halves.
+        mapper.write_prg(0x8000, 0x80);
+        let banks = mapper.chr_banks().unwrap();
+        assert_eq!(&banks[4..6], &[10, 11]);
+        assert_eq!(banks[0], 33);
+    }
+
+    #[test]
+    fn test_unsupported_mapper() {
+        // Mapper 5, MMC5, which isn't emulated.
+        let mut data = vec![
+            b'N', b'E', b'S', 0x1A, 1, 1, 0x50, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        data.extend(vec![0; 0x6000]);
+        let rom = ROM::from_bytes(data);
+        assert_eq!(
+            mappers::from_ines(rom.mapper_number(), &rom).err(),
+            Some(String::from("Unsupported mapper: 5"))
+        );
+    }
+
+    #[test]
+    fn test_axrom_mirroring_is_dynamic() {
+        let rom = ROM::load(test_resource_path("mappers/M7_P128K.nes"));
+        let mapper = mappers::from_ines(7, &rom).unwrap();
+        let mut mapper = mapper.borrow_mut();
+        assert_eq!(mapper.mirror_mode(), MirrorMode::SingleLower);
+        mapper.write_prg(0x8000, 0x10);
+        assert_eq!(mapper.mirror_mode(), MirrorMode::SingleUpper);
+    }
+
+    #[test]
+    fn test_gxrom_banking() {
+        // Two 32kb PRG banks and four 8kb CHR banks, each filled with its own index.
+        let mut data = vec![
+            b'N', b'E', b'S', 0x1A, 4, 4, 0x21, 0x40, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        for bank in 0..2 {
+            data.extend(vec![bank as u8; 0x8000]);
+        }
+        for bank in 0..4 {
+            data.extend(vec![0x10 | bank as u8; 0x2000]);
+        }
+        let rom = ROM::from_bytes(data);
+        assert_eq!(rom.mapper_number(), 66);
+
+        let mapper = mappers::from_ines(rom.mapper_number(), &rom).unwrap();
+        let mut mapper = mapper.borrow_mut();
+        assert_eq!(mapper.mirror_mode(), MirrorMode::Vertical);
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        assert_eq!(mapper.read_chr(0x0000), 0x10);
+
+        mapper.write_prg(0x8000, 0x13);
+        assert_eq!(mapper.read_prg(0x8000), 1);
+        assert_eq!(mapper.read_prg(0xFFFF), 1);
+        assert_eq!(mapper.read_chr(0x1FFF), 0x13);
+    }
+
+    // Two 16kb PRG units and four 4kb CHR banks, each bank filled with its own index.
+    fn mmc2_rom(mapper: u8) -> ROM {
+        let mut data = vec![
+            b'N',
+            b'E',
+            b'S',
+            0x1A,
+            2,
+            2,
+            mapper << 4,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        for bank in 0..4 {
+            data.extend(vec![bank as u8; 0x2000]);
+        }
+        for bank in 0..4 {
+            data.extend(vec![0x10 | bank as u8; 0x1000]);
+        }
+        ROM::from_bytes(data)
+    }
+
+    #[test]
+    fn test_mmc2_latches() {
+        let rom = mmc2_rom(9);
+        let mapper = mappers::from_ines(rom.mapper_number(), &rom).unwrap();
+        let mut mapper = mapper.borrow_mut();
+
+        // 8kb switchable at $8000, then the last three banks.
+        mapper.write_prg(0xA000, 1);
+        assert_eq!(mapper.read_prg(0x8000), 1);
+        assert_eq!(mapper.read_prg(0xA000), 1);
+        assert_eq!(mapper.read_prg(0xC000), 2);
+        assert_eq!(mapper.read_prg(0xE000), 3);
+
+        mapper.write_prg(0xB000, 0);
+        mapper.write_prg(0xC000, 1);
+        mapper.write_prg(0xD000, 2);
+        mapper.write_prg(0xE000, 3);
+        assert!(mapper.take_bank_switch());
+
+        // Both latches start on $FE.
+        assert_eq!(mapper.read_chr(0x0000), 0x11);
+        assert_eq!(mapper.read_chr(0x1000), 0x13);
+
+        mapper.pattern_fetched(0x0FD8);
+        assert_eq!(mapper.read_chr(0x0000), 0x10);
+        assert!(mapper.take_bank_switch());
+        assert_eq!(mapper.chr_banks(), Some([0, 1, 2, 3, 12, 13, 14, 15]));
+
+        // The lower table only flips on the exact address, the upper on any row of the tile.
+        mapper.pattern_fetched(0x0FEA);
+        assert_eq!(mapper.read_chr(0x0000), 0x10);
+        mapper.pattern_fetched(0x1FDF);
+        assert_eq!(mapper.read_chr(0x1000), 0x12);
+        mapper.pattern_fetched(0x1FE8);
+        assert_eq!(mapper.read_chr(0x1000), 0x13);
+
+        assert_eq!(mapper.mirror_mode(), MirrorMode::Horizontal);
+        mapper.write_prg(0xF000, 0);
+        assert_eq!(mapper.mirror_mode(), MirrorMode::Vertical);
+    }
+
+    #[test]
+    fn test_mmc4_banking() {
+        let rom = mmc2_rom(10);
+        let mapper = mappers::from_ines(rom.mapper_number(), &rom).unwrap();
+        let mut mapper = mapper.borrow_mut();
+
+        // 16kb switchable at $8000, the last 16kb fixed at $C000.
+        assert_eq!(mapper.read_prg(0xBFFF), 1);
+        mapper.write_prg(0xA000, 1);
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.read_prg(0xA000), 3);
+        assert_eq!(mapper.read_prg(0xC000), 2);
+
+        mapper.write_prg(0xB000, 2);
+        mapper.write_prg(0xC000, 3);
+        assert_eq!(mapper.read_chr(0x0000), 0x13);
+        mapper.pattern_fetched(0x0FDC);
+        assert_eq!(mapper.read_chr(0x0000), 0x12);
+    }
+
+    #[test]
+    fn test_mmc3_four_screen_ignores_mirroring_writes() {
+        let mut data = vec![
+            b'N', b'E', b'S', 0x1A, 2, 1, 0x48, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        data.extend(vec![0; 2 * 0x4000 + 0x2000]);
+        let rom = ROM::from_bytes(data);
+        assert_eq!(rom.mirror_mode(), MirrorMode::FourScreen);
+
+        let mapper = mappers::from_ines(rom.mapper_number(), &rom).unwrap();
+        let mut mapper = mapper.borrow_mut();
+        assert_eq!(mapper.mirror_mode(), MirrorMode::FourScreen);
+        mapper.write_prg(0xA000, 0x01);
+        assert_eq!(mapper.mirror_mode(), MirrorMode::FourScreen);
+    }
+
+    // Counts how many of `scanlines` A12 rises raise an IRQ, with the reload value set to 0.
+    fn mmc3_irqs_with_zero_reload(submapper: u8, scanlines: u32) -> u32 {
+        let mut data = vec![
+            b'N',
+            b'E',
+            b'S',
+            0x1A,
+            2,
+            1,
+            0x40,
+            0x08,
+            submapper << 4,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+        data.extend(vec![0; 2 * 0x4000 + 0x2000]);
+        let rom = ROM::from_bytes(data);
+        assert_eq!(rom.submapper(), submapper);
+
+        let mapper = mappers::from_ines(rom.mapper_number(), &rom).unwrap();
+        let mut mapper = mapper.borrow_mut();
+        mapper.write_prg(0xC000, 0x00);
+        mapper.write_prg(0xC001, 0x00);
+        mapper.write_prg(0xE001, 0x00);
+
+        let mut irqs = 0;
+        for _ in 0..scanlines {
+            // Background from $0000, then sprites from $1000.
+            for _ in 0..16 {
+                mapper.read_chr(0x0000);
+            }
+            mapper.read_chr(0x1000);
+
+            if mapper.irq_triggered() {
+                irqs += 1;
+                // Acknowledge.
+                mapper.write_prg(0xE000, 0x00);
+                mapper.write_prg(0xE001, 0x00);
+            }
+        }
+        irqs
+    }
+
+    #[test]
+    fn test_mmc3_irq_revisions() {
+        assert_eq!(mmc3_irqs_with_zero_reload(0, 5), 5);
+        assert_eq!(mmc3_irqs_with_zero_reload(4, 5), 1);
+    }
+}
@@ -1,11 +1,32 @@
+mod busstats;
+mod cartridge_ram;
+mod cdl;
+mod chrbanks;
+mod config;
+mod dirty_tiles;
+mod dmc;
+mod embed;
+mod eventlog;
+mod heatmap;
 mod image_capture;
+mod inputscript;
 mod instr_misc;
 mod instr_test_v5;
 mod instr_timing;
+mod load_rom;
 mod mappers;
+mod movie;
 mod nestest;
+mod oam_dma;
+mod peek;
 mod ppu_sprite_hit;
 mod ppu_sprite_overflow;
+mod run_cycles;
+mod save_states;
+mod soak;
+mod testrom;
+mod tile_export;
+mod vramcheck;
 
 use std::cell::RefCell;
 use std::env;
@@ -16,9 +37,11 @@ use std::rc::Rc;
 
 use md5::{Digest, Md5};
 
+use crate::emulator::config::Config;
 use crate::emulator::ines;
 use crate::emulator::io;
 use crate::emulator::io::event::EventBus;
+use crate::emulator::testrom::{run_test_rom, TestRomResult, TestRomStatus};
 use crate::emulator::NES;
 
 use image_capture::ImageCapture;
@@ -36,10 +59,31 @@ fn prepare_ete_test<P: AsRef<Path>>(path: P) -> (NES, Rc<RefCell<EventBus>>, Ima
     let output = Rc::new(RefCell::new(io::Screen::new()));
     let audio = io::nop::DummyAudio {};
     let image = ImageCapture::new(output.clone());
-    let nes = NES::new(event_bus.clone(), output, audio, rom);
+    let nes = NES::new(event_bus.clone(), output, audio, rom, &Config::default());
     (nes, event_bus, image)
 }
 
+// iNES image of an NROM cart with 16KB of PRG-ROM and 8KB of blank CHR-ROM.  `program` is at
+// $8000 and the reset vector points to it, and the rest of PRG-ROM is NOPs.  For tests which
+// need to tweak the header or vectors before building the ROM.
+fn nrom_image(program: &[u8]) -> Vec<u8> {
+    let mut data = vec![
+        b'N', b'E', b'S', 0x1A, 1, 1, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut prg = vec![0xEA; 0x4000];
+    prg[..program.len()].copy_from_slice(program);
+    // Reset vector to $8000.
+    prg[0x3FFC] = 0x00;
+    prg[0x3FFD] = 0x80;
+    data.extend(prg);
+    data.extend(vec![0; 0x2000]);
+    data
+}
+
+fn nrom_with_program(program: &[u8]) -> ines::ROM {
+    ines::ROM::from_bytes(nrom_image(program))
+}
+
 fn load_and_run_blargg_test_rom<P: AsRef<Path>>(rom_path: P) -> (u8, String) {
     load_and_run_blargg_test_rom_with_cycles(rom_path, 100_000_000)
 }
@@ -53,12 +97,10 @@ fn load_and_run_blargg_test_rom_with_cycles<P: AsRef<Path>>(
 }
 
 fn run_blargg_test_rom(nes: &mut NES, max_cycles: u64) -> (u8, String) {
+    // Run until the ROM signs its status byte.
     let mut cycles = 0;
-    // Run until the status byte says the test is running.
-    let mut status = nes.cpu.borrow_mut().load_memory(0x6000);
-    while status != 0x80 {
+    while TestRomResult::poll(nes).status == TestRomStatus::NoSignature {
         cycles += nes.tick();
-        status = nes.cpu.borrow_mut().load_memory(0x6000);
 
         if cycles > 20_000_000 {
             panic!(
@@ -69,42 +111,17 @@ fn run_blargg_test_rom(nes: &mut NES, max_cycles: u64) -> (u8, String) {
     }
 
     // Run until completion.
-    while status == 0x80 {
-        cycles += nes.tick();
-        status = nes.cpu.borrow_mut().load_memory(0x6000);
-
-        cycles += 1;
-        if cycles > max_cycles {
-            let output = collect_test_output(nes);
-            panic!(
-                "Test took too long to end.  Gave up after {} cycles.  Current output: {}",
-                cycles, output
-            );
-        }
-    }
-
-    let output = collect_test_output(nes);
-    println!("{}", output);
-
-    (status, output)
-}
-
-fn collect_test_output(nes: &mut NES) -> String {
-    // Collect output.
-    let mut text_buf = vec![];
-    for ix in 0..1000 {
-        let byte = nes.cpu.borrow_mut().load_memory(0x6004 + ix);
-        if byte == 0x00 {
-            break;
-        } else {
-            text_buf.push(byte);
-        }
-    }
+    let result = run_test_rom(nes, max_cycles);
+    let status = match result.status {
+        TestRomStatus::Finished(status) => status,
+        _ => panic!(
+            "Test took too long to end.  Gave up after {} cycles.  Current output: {}",
+            max_cycles, result.text
+        ),
+    };
+    println!("{}", result.text);
 
-    match String::from_utf8(text_buf) {
-        Err(cause) => panic!("Error converting output to string: {}", cause),
-        Ok(string) => string,
-    }
+    (status, result.text)
 }
 
 pub fn assert_image(capture: &ImageCapture, exp_file: PathBuf) {
@@ -0,0 +1,48 @@
+use crate::emulator::movie::{FrameInput, Movie, MovieSession};
+
+use crate::emulator::test::assert_image;
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::test_resource_path;
+
+#[test]
+fn test_movie_record_and_playback() {
+    let path = test_resource_path("nestest/nestest.nes");
+
+    // Record pressing Start on the nestest menu, which kicks off the tests.
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    let mut session = MovieSession::record(&mut nes, Movie::new("nestest.nes", false));
+    for frame in 0..30 {
+        let input = FrameInput {
+            reset: false,
+            joy1: if frame == 8 { 0x08 } else { 0x00 },
+            joy2: 0,
+            ..FrameInput::default()
+        };
+        session.step(&mut nes, input);
+    }
+    let movie = session.finish(&mut nes);
+    assert_eq!(movie.frames.len(), 30);
+
+    // Play it back through FM2 on a fresh machine, feeding in junk live input which should be
+    // ignored.
+    let movie = Movie::from_fm2(&movie.to_fm2()).unwrap();
+    let (mut nes_2, _, image_2) = prepare_ete_test(&path);
+    let recorded = movie.metadata.clone().unwrap();
+    assert_eq!(recorded.check(&nes_2.metadata()), Ok(vec![]));
+    let mut session = MovieSession::play(&mut nes_2, movie);
+    while !session.is_finished() {
+        let junk = FrameInput {
+            reset: true,
+            joy1: 0xFF,
+            joy2: 0xFF,
+            ..FrameInput::default()
+        };
+        session.step(&mut nes_2, junk);
+    }
+    session.finish(&mut nes_2);
+
+    assert_image(
+        &image_2,
+        test_resource_path("nestest/capture_02_passed.bmp"),
+    );
+}
@@ -0,0 +1,80 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::config::Config;
+use crate::emulator::ines::ROM;
+use crate::emulator::io;
+use crate::emulator::io::event::EventBus;
+use crate::emulator::state::SaveState;
+use crate::emulator::NES;
+
+// UxROM cart which switches bank 2 in at $8000, fills RAM at $0200 with each index inverted, then
+// starts an OAM DMA from `page` and stores what reading $4014 back gives at $10.  Bank 2 holds
+// each index at its start, and the other banks are filled with $FF.
+fn dma_rom(page: u8) -> ROM {
+    let program = [
+        0xA9, 0x02, // LDA #$02
+        0x8D, 0x00, 0x80, // STA $8000
+        0xA2, 0x00, // LDX #$00
+        0x8A, // TXA
+        0x49, 0xFF, // EOR #$FF
+        0x9D, 0x00, 0x02, // STA $0200,X
+        0xE8, // INX
+        0xD0, 0xF7, // BNE -9
+        0xA9, page, // LDA #page
+        0x8D, 0x14, 0x40, // STA $4014
+        0xAD, 0x14, 0x40, // LDA $4014
+        0x85, 0x10, // STA $10
+        0x4C, 0x1A, 0xC0, // JMP $C01A
+    ];
+
+    let mut data = vec![
+        b'N', b'E', b'S', 0x1A, 4, 1, 0x20, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let mut prg = vec![0xFF; 0x10000];
+    for ix in 0..0x100 {
+        prg[0x8000 + ix] = ix as u8;
+    }
+    // The last bank is fixed at $C000, and holds the program and reset vector.
+    prg[0xC000..0xC000 + program.len()].copy_from_slice(&program);
+    prg[0xFFFC] = 0x00;
+    prg[0xFFFD] = 0xC0;
+    data.extend(prg);
+    data.extend(vec![0; 0x2000]);
+    ROM::from_bytes(data)
+}
+
+fn run_dma(page: u8) -> NES {
+    let mut nes = NES::new(
+        Rc::new(RefCell::new(EventBus::new())),
+        Rc::new(RefCell::new(io::Screen::new())),
+        io::nop::DummyAudio {},
+        dma_rom(page),
+        &Config::default(),
+    );
+    nes.run_frame();
+    nes.run_frame();
+    nes
+}
+
+#[test]
+fn test_oam_dma_from_prg_rom() {
+    let nes = run_dma(0x80);
+    let oam = nes.ppu.borrow_mut().freeze().oam;
+    // Read through the mapper, from the bank switched in rather than the first in the ROM.
+    assert_eq!(oam, (0..=255).collect::<Vec<u8>>());
+}
+
+#[test]
+fn test_oam_dma_from_ram_mirror() {
+    let nes = run_dma(0x0A);
+    let oam = nes.ppu.borrow_mut().freeze().oam;
+    assert_eq!(oam, (0..=255).map(|ix: u8| !ix).collect::<Vec<u8>>());
+}
+
+#[test]
+fn test_oam_dma_register_reads_open_bus() {
+    let nes = run_dma(0x80);
+    // The high byte of the operand was the last thing on the bus.
+    assert_eq!(nes.peek(0x10), 0x40);
+}
@@ -0,0 +1,53 @@
+use crate::emulator::hexdump::HexDumpOptions;
+use crate::emulator::state::SaveState;
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::test_resource_path;
+
+#[test]
+fn test_peek_has_no_side_effects() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    nes.run_frame();
+
+    // RAM is mirrored, same as through the CPU.
+    nes.cpu.borrow_mut().store_memory(0x0010, 0x42);
+    assert_eq!(nes.peek(0x0010), 0x42);
+    assert_eq!(nes.peek(0x0810), 0x42);
+
+    let reset_vector = nes.cpu.borrow_mut().load_memory(0xFFFC);
+    assert_eq!(nes.peek(0xFFFC), reset_vector);
+
+    // Peeking PPUSTATUS doesn't acknowledge vblank.
+    let status = nes.ppu.borrow_mut().freeze().ppustatus;
+    assert_eq!(nes.peek(0x2002), 0);
+    assert_eq!(nes.ppu.borrow_mut().freeze().ppustatus, status);
+
+    // Neither does dumping it.
+    let dump = nes.hexdump(0x2000, 8, &HexDumpOptions::default());
+    assert!(dump.starts_with("$2000: 00 00 00 00 00 00 00 00"));
+    assert_eq!(nes.ppu.borrow_mut().freeze().ppustatus, status);
+
+    // Dumps stop at the top of memory rather than wrapping.
+    let dump = nes.hexdump(0xFFF0, 0x100, &HexDumpOptions::default());
+    assert_eq!(dump.lines().count(), 1);
+}
+
+#[test]
+fn test_poke() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+
+    nes.poke(0x0812, 0x99);
+    assert_eq!(nes.cpu.borrow_mut().load_memory(0x0012), 0x99);
+
+    // ROM is left alone.
+    let byte = nes.peek(0xC000);
+    nes.poke(0xC000, !byte);
+    assert_eq!(nes.peek(0xC000), byte);
+
+    // Palette entries go through the PPU's mirroring.
+    nes.poke_ppu(0x3F10, 0x21);
+    assert_eq!(nes.peek_ppu(0x3F00), 0x21);
+    nes.poke_ppu(0x2005, 0x33);
+    assert_eq!(nes.peek_ppu(0x2005), 0x33);
+}
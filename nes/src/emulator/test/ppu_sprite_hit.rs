@@ -2,7 +2,6 @@ use crate::emulator::test::load_and_run_blargg_test_rom;
 use crate::emulator::test::test_resource_path;
 
 // -- ppu_sprite_hit test ROMs --
-// TODO: Get tests 09 and 10 to pass once my CPU/PPU timing is PPU-cycle accurate.
 #[test]
 fn test_ppu_sprite_hit_01() {
     let path = test_resource_path("ppu_sprite_hit/rom_singles/01-basics.nes");
@@ -74,3 +73,21 @@ fn test_ppu_sprite_hit_08() {
     assert_eq!(status, 0x00);
     assert_eq!(output, "\n08-double_height\n\nPassed\n");
 }
+
+#[test]
+fn test_ppu_sprite_hit_09() {
+    let path = test_resource_path("ppu_sprite_hit/rom_singles/09-timing.nes");
+    let (status, output) = load_and_run_blargg_test_rom(path);
+
+    assert_eq!(status, 0x00);
+    assert_eq!(output, "\n09-timing\n\nPassed\n");
+}
+
+#[test]
+fn test_ppu_sprite_hit_10() {
+    let path = test_resource_path("ppu_sprite_hit/rom_singles/10-timing_order.nes");
+    let (status, output) = load_and_run_blargg_test_rom(path);
+
+    assert_eq!(status, 0x00);
+    assert_eq!(output, "\n10-timing_order\n\nPassed\n");
+}
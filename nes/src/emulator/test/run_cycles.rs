@@ -0,0 +1,124 @@
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::test_resource_path;
+use crate::emulator::watchpoints::Access;
+use crate::emulator::NES;
+
+#[test]
+fn test_run_cycles_counts_frames() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+
+    // 10 NTSC frames' worth, give or take the overshoot of the last tick.
+    let result = nes.run_cycles(10 * 262 * 341 * 4);
+    assert!(result.cycles >= 10 * 262 * 341 * 4);
+    assert_eq!(result.frames_completed, 10);
+    assert_eq!(result.breakpoint, None);
+}
+
+#[test]
+fn test_run_cycles_within_frame_stops_at_frame_end() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    nes.run_frame();
+
+    let frame_cycles = 262 * 341 * 4;
+    let result = nes.run_cycles_within_frame(10 * frame_cycles);
+    assert_eq!(result.frames_completed, 1);
+    assert!(result.cycles <= frame_cycles + 341 * 4);
+
+    // Short of the frame end, it's the same as `run_cycles`.
+    let result = nes.run_cycles_within_frame(1000);
+    assert_eq!(result.frames_completed, 0);
+    assert!(result.cycles >= 1000);
+}
+
+#[test]
+fn test_run_cycles_within_frame_stops_on_the_same_tick_as_run_frame() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut stepped, _, _) = prepare_ete_test(&path);
+    let (mut batched, _, _) = prepare_ete_test(&path);
+
+    // Enough frames to cover both lengths of NTSC frame once rendering is on.
+    for _ in 0..10 {
+        let cycles = stepped.run_frame();
+        let result = batched.run_cycles_within_frame(u64::MAX);
+        assert_eq!(result.cycles, cycles);
+        assert_eq!(result.frames_completed, 1);
+    }
+    assert_eq!(batched.master_cycle(), stepped.master_cycle());
+}
+
+#[test]
+fn test_run_cycles_stops_at_breakpoint() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+
+    // Break at the NMI handler, which the menu runs once per frame.
+    let lo = nes.cpu.borrow_mut().load_memory(0xFFFA) as u16;
+    let hi = nes.cpu.borrow_mut().load_memory(0xFFFB) as u16;
+    let nmi_handler = (hi << 8) | lo;
+    nes.add_breakpoint(nmi_handler);
+    assert!(nes.has_breakpoint(nmi_handler));
+
+    let first = nes.run_cycles(100_000_000);
+    assert_eq!(first.breakpoint, Some(nmi_handler));
+    assert!(first.cycles < 100_000_000);
+    assert_eq!(nes.cpu.borrow().pc(), nmi_handler);
+
+    // Running again carries on to the next frame's NMI.
+    let second = nes.run_cycles(100_000_000);
+    assert_eq!(second.breakpoint, Some(nmi_handler));
+    assert_eq!(second.frames_completed, 1);
+
+    nes.remove_breakpoint(nmi_handler);
+    assert!(!nes.has_breakpoint(nmi_handler));
+    let third = nes.run_cycles(1_000_000);
+    assert_eq!(third.breakpoint, None);
+}
+
+#[test]
+fn test_tick_to_keeps_ppu_in_step() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+
+    // Part way down the first full frame, so the dot count doesn't wrap between the two points.
+    let start = nes.tick_to(100_000);
+    assert!(start >= 100_000);
+    // No instruction takes more than 8 CPU cycles.
+    assert!(start < 100_000 + 8 * 12);
+    let dots = |nes: &NES| {
+        let stats = nes.ppu.borrow().stats();
+        u64::from(stats.scanline) * 341 + u64::from(stats.dot)
+    };
+    let start_dots = dots(&nes);
+
+    let end = nes.tick_to(200_000);
+    assert_eq!(nes.master_cycle(), end);
+    assert_eq!(dots(&nes) - start_dots, (end - start) / 4);
+
+    // Already there.
+    assert_eq!(nes.tick_to(150_000), end);
+}
+
+#[test]
+fn test_run_cycles_stops_at_watchpoint() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+
+    // The menu polls PPUSTATUS while waiting for vblank.
+    nes.add_watchpoint(0x2002, Access::Read);
+    let result = nes.run_cycles(100_000_000);
+    assert!(result.watchpoint_hit);
+    assert!(result.cycles < 100_000_000);
+
+    let accesses = nes.take_memory_accesses();
+    assert_eq!(accesses.len(), 1);
+    assert_eq!(accesses[0].access, Access::Read);
+    assert_eq!(accesses[0].address, 0x2002);
+    assert!(nes.take_memory_accesses().is_empty());
+
+    nes.remove_watchpoint(0x2002, Access::Read);
+    let result = nes.run_cycles(1_000_000);
+    assert!(!result.watchpoint_hit);
+    assert!(nes.take_memory_accesses().is_empty());
+}
@@ -0,0 +1,46 @@
+use crate::emulator::state::SaveState;
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::test_resource_path;
+
+#[test]
+fn test_save_state_waits_for_dma() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    nes.run_frame();
+
+    // Between instructions, the state is captured straight away.
+    assert!(nes.at_instruction_boundary());
+    nes.request_save_state();
+    assert!(nes.take_save_state().is_some());
+    assert!(nes.take_save_state().is_none());
+
+    // Kick off an OAM DMA.  The CPU is stalled until all 256 bytes are copied.
+    nes.cpu.borrow_mut().store_memory(0x4014, 0x02);
+    assert!(!nes.at_instruction_boundary());
+    nes.request_save_state();
+    assert!(nes.take_save_state().is_none());
+
+    let mut ticks = 0;
+    while nes.take_save_state().is_none() {
+        nes.tick();
+        ticks += 1;
+        assert!(ticks < 1000, "DMA never finished");
+    }
+    assert!(ticks >= 256);
+    assert!(nes.at_instruction_boundary());
+}
+
+#[test]
+fn test_save_state_keeps_nmi_edge() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    nes.run_frame();
+
+    nes.request_save_state();
+    let mut state = nes.take_save_state().unwrap();
+    assert!(!state.nmi_pin);
+
+    state.nmi_pin = true;
+    nes.hydrate(state);
+    assert!(nes.freeze().nmi_pin);
+}
@@ -0,0 +1,81 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::config::Config;
+use crate::emulator::ines::ROM;
+use crate::emulator::io;
+use crate::emulator::io::event::EventBus;
+use crate::emulator::movie::MovieSession;
+use crate::emulator::soak::{soak, SoakOptions};
+use crate::emulator::test::{nrom_with_program, test_resource_path};
+use crate::emulator::NES;
+
+// NROM cart which polls the first controller and jams the CPU as soon as A is pressed.
+fn crash_on_a_rom() -> ROM {
+    let program = [
+        0xA9, 0x01, // LDA #$01
+        0x8D, 0x16, 0x40, // STA $4016
+        0xA9, 0x00, // LDA #$00
+        0x8D, 0x16, 0x40, // STA $4016
+        0xAD, 0x16, 0x40, // LDA $4016
+        0x29, 0x01, // AND #$01
+        0xF0, 0xEF, // BEQ $8000
+        0x02, // KIL
+    ];
+
+    nrom_with_program(&program)
+}
+
+#[test]
+fn test_soak_is_deterministic() {
+    let rom = ROM::load(test_resource_path("mappers/M1_P128K_C128K.nes"));
+    let options = SoakOptions {
+        seed: 1234,
+        frames: 120,
+        hash_interval: 30,
+    };
+
+    let report = soak(&rom, "M1_P128K_C128K.nes", &options).unwrap();
+    assert_eq!(report.frames, 120);
+    assert_eq!(report.hashes.len(), 4);
+    assert_eq!(report.crash, None);
+    assert_eq!(soak(&rom, "M1_P128K_C128K.nes", &options), Ok(report));
+}
+
+#[test]
+fn test_soak_crash_replays() {
+    let rom = crash_on_a_rom();
+    let report = soak(
+        &rom,
+        "crash.nes",
+        &SoakOptions {
+            seed: 1,
+            frames: 1000,
+            hash_interval: 0,
+        },
+    )
+    .unwrap();
+
+    let crash = report.crash.expect("Soak should have crashed");
+    assert_eq!(crash.message, "CPU jammed on $02 at $8011");
+    assert_eq!(crash.movie.frames.len() as u64, crash.frame + 1);
+    assert_eq!(crash.movie.frames.last().unwrap().joy1 & 0x01, 0x01);
+
+    // Replaying the movie on a fresh machine crashes on the same frame.
+    let screen = Rc::new(RefCell::new(io::Screen::new()));
+    let event_bus = Rc::new(RefCell::new(EventBus::new()));
+    let mut nes = NES::new(
+        event_bus,
+        screen,
+        io::nop::DummyAudio {},
+        rom,
+        &Config::default(),
+    );
+    let mut session = MovieSession::play(&mut nes, crash.movie);
+    for _ in 0..crash.frame {
+        session.step(&mut nes, Default::default());
+    }
+    assert_eq!(nes.cpu_jam(), None);
+    session.step(&mut nes, Default::default());
+    assert!(nes.cpu_jam().is_some());
+}
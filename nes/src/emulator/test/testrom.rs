@@ -0,0 +1,54 @@
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::test_resource_path;
+use crate::emulator::testrom::{screen_text, TestRomResult, TestRomStatus};
+
+#[test]
+fn test_poll_reads_status_and_text() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+
+    for (ix, byte) in [0x80, 0xDE, 0xB0, 0x61].iter().enumerate() {
+        nes.poke(0x6000 + ix as u16, *byte);
+    }
+    for (ix, byte) in b"\nPassed\n\0".iter().enumerate() {
+        nes.poke(0x6004 + ix as u16, *byte);
+    }
+    let result = TestRomResult::poll(&mut nes);
+    assert_eq!(result.status, TestRomStatus::Running);
+    assert_eq!(result.text, "\nPassed\n");
+
+    nes.poke(0x6000, 0x81);
+    assert_eq!(
+        TestRomResult::poll(&mut nes).status,
+        TestRomStatus::NeedsReset
+    );
+
+    nes.poke(0x6000, 0x03);
+    let result = TestRomResult::poll(&mut nes);
+    assert_eq!(result.status, TestRomStatus::Finished(3));
+    assert!(result.is_finished());
+    assert!(!result.passed());
+}
+
+#[test]
+fn test_poll_falls_back_to_screen_text() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    for address in 0x2000..0x23C0 {
+        nes.poke_ppu(address, 0x00);
+    }
+
+    // Status without the signature is ignored.
+    nes.poke(0x6000, 0x00);
+    for (ix, byte) in b"FAILED #2".iter().enumerate() {
+        nes.poke_ppu(0x2000 + 32 * 3 + 2 + ix as u16, *byte);
+    }
+    for (ix, byte) in b"OK".iter().enumerate() {
+        nes.poke_ppu(0x2000 + 32 * 5 + ix as u16, *byte);
+    }
+
+    let result = TestRomResult::poll(&mut nes);
+    assert_eq!(result.status, TestRomStatus::NoSignature);
+    assert_eq!(result.text, "  FAILED #2\nOK");
+    assert_eq!(screen_text(&nes), result.text);
+}
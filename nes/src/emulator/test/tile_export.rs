@@ -0,0 +1,28 @@
+use crate::emulator::ppu::debug::PPUDebug;
+use crate::emulator::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+
+use crate::emulator::test::prepare_ete_test;
+use crate::emulator::test::run_for;
+use crate::emulator::test::test_resource_path;
+
+#[test]
+fn test_export_matches_screen() {
+    let path = test_resource_path("nestest/nestest.nes");
+    let (mut nes, _, _) = prepare_ete_test(&path);
+    run_for(&mut nes, 2_000_000);
+
+    let mut frame = vec![];
+    nes.screen.borrow().do_render(|rgb| frame = rgb.to_vec());
+
+    // The nestest menu is drawn unscrolled from the first nametable, with no sprites.
+    let mut debug = PPUDebug::new(nes.ppu.clone());
+    let nametable = debug.export_nametable(0);
+    assert_eq!(nametable.len(), FRAME_WIDTH * FRAME_HEIGHT * 3);
+    assert!(nametable == frame);
+
+    let patterns = debug.export_pattern_tables(0);
+    assert_eq!(
+        patterns.len(),
+        PPUDebug::PATTERN_WIDTH * PPUDebug::PATTERN_HEIGHT * 3
+    );
+}
@@ -0,0 +1,67 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::config::Config;
+use crate::emulator::ines::ROM;
+use crate::emulator::io;
+use crate::emulator::io::event::EventBus;
+use crate::emulator::test::nrom_with_program;
+use crate::emulator::NES;
+
+// NROM cart which writes to PPUDATA over and over, with rendering turned on or not.
+fn vram_writing_rom(rendering: bool) -> ROM {
+    let mask = if rendering { 0x1E } else { 0x00 };
+    let program = [
+        0xA9, mask, // LDA #mask
+        0x8D, 0x01, 0x20, // STA $2001
+        0x8D, 0x07, 0x20, // STA $2007
+        0x4C, 0x05, 0x80, // JMP $8005
+    ];
+
+    nrom_with_program(&program)
+}
+
+fn new_nes(rendering: bool) -> NES {
+    NES::new(
+        Rc::new(RefCell::new(EventBus::new())),
+        Rc::new(RefCell::new(io::Screen::new())),
+        io::nop::DummyAudio {},
+        vram_writing_rom(rendering),
+        &Config::default(),
+    )
+}
+
+#[test]
+fn test_logs_writes_while_rendering() {
+    let mut nes = new_nes(true);
+    nes.enable_vram_write_check();
+    nes.run_frame();
+    nes.run_frame();
+
+    let writes = nes.take_rendering_writes();
+    assert!(!writes.is_empty());
+    for write in writes.iter() {
+        assert_eq!(write.pc, 0x8005);
+        assert_eq!(write.byte, 0x1E);
+        assert!(write.scanline < 240 || write.scanline == 261, "{}", write);
+    }
+    // Taken, so not reported again.
+    assert!(nes.take_rendering_writes().is_empty());
+}
+
+#[test]
+fn test_writes_with_rendering_off_are_fine() {
+    let mut nes = new_nes(false);
+    nes.enable_vram_write_check();
+    nes.run_frame();
+    nes.run_frame();
+    assert!(nes.take_rendering_writes().is_empty());
+}
+
+#[test]
+fn test_check_off_by_default() {
+    let mut nes = new_nes(true);
+    nes.run_frame();
+    nes.run_frame();
+    assert!(nes.take_rendering_writes().is_empty());
+}
@@ -0,0 +1,124 @@
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::emulator::NES;
+
+// Reads results out of test ROMs which follow blargg's conventions.  While running they keep a
+// status byte at $6000, with $DE $B0 $61 at $6001-$6003 to show it's valid, and a NUL-terminated
+// message from $6004.  ROMs which predate that only report on screen, so without the signature
+// the text comes from the first nametable instead.
+
+const STATUS: u16 = 0x6000;
+const SIGNATURE_ADDRESS: u16 = 0x6001;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const TEXT: u16 = 0x6004;
+const TEXT_END: u16 = 0x7FFF;
+
+const RUNNING: u8 = 0x80;
+const NEEDS_RESET: u8 = 0x81;
+
+const NAMETABLE: u16 = 0x2000;
+const NAMETABLE_COLUMNS: u16 = 32;
+const NAMETABLE_ROWS: u16 = 30;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TestRomStatus {
+    // Nothing at $6000 yet.  Either the ROM hasn't started, or it only reports on screen.
+    NoSignature,
+    Running,
+    // The ROM wants the reset button pressed, at least 100ms from when it asked.
+    NeedsReset,
+    // Zero is a pass, anything else is the result code of the test which failed.
+    Finished(u8),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TestRomResult {
+    pub status: TestRomStatus,
+    pub text: String,
+}
+
+impl TestRomResult {
+    // Reads the ROM's current status and message.  Has no effect on the emulation.
+    pub fn poll(nes: &mut NES) -> TestRomResult {
+        let signed = (0..3).all(|ix| nes.peek(SIGNATURE_ADDRESS + ix) == SIGNATURE[ix as usize]);
+        if !signed {
+            return TestRomResult {
+                status: TestRomStatus::NoSignature,
+                text: screen_text(nes),
+            };
+        }
+
+        let status = match nes.peek(STATUS) {
+            RUNNING => TestRomStatus::Running,
+            NEEDS_RESET => TestRomStatus::NeedsReset,
+            code => TestRomStatus::Finished(code),
+        };
+        TestRomResult {
+            status,
+            text: memory_text(nes),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status, TestRomStatus::Finished(_))
+    }
+
+    pub fn passed(&self) -> bool {
+        self.status == TestRomStatus::Finished(0)
+    }
+}
+
+// Runs a test ROM until it finishes, pressing reset whenever it asks.  Gives up after
+// `max_cycles` master cycles, or as soon as the CPU jams, and returns the status at that point.
+pub fn run_test_rom(nes: &mut NES, max_cycles: u64) -> TestRomResult {
+    let reset_delay = nes.region().master_clock_hz() / 10;
+    let mut cycles = 0;
+    let mut reset_at = None;
+    loop {
+        let result = TestRomResult::poll(nes);
+        let jammed = nes.cpu.borrow().jam().is_some();
+        if result.is_finished() || jammed || cycles > max_cycles {
+            return result;
+        }
+
+        match (result.status, reset_at) {
+            (TestRomStatus::NeedsReset, None) => reset_at = Some(cycles + reset_delay),
+            (TestRomStatus::NeedsReset, Some(at)) if cycles >= at => {
+                nes.reset();
+                reset_at = None;
+            }
+            _ => (),
+        }
+        cycles += nes.tick();
+    }
+}
+
+fn memory_text(nes: &NES) -> String {
+    let bytes: Vec<u8> = (TEXT..=TEXT_END)
+        .map(|address| nes.peek(address))
+        .take_while(|&byte| byte != 0x00)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// Blargg's fonts put each character at the tile with its ASCII code, so the nametable reads as
+// text.  Blank rows are dropped and anything else unprintable shows as a space.
+pub fn screen_text(nes: &NES) -> String {
+    let mut lines = vec![];
+    for row in 0..NAMETABLE_ROWS {
+        let line: String = (0..NAMETABLE_COLUMNS)
+            .map(|column| nes.peek_ppu(NAMETABLE + row * NAMETABLE_COLUMNS + column))
+            .map(|tile| match tile {
+                0x20..=0x7E => tile as char,
+                _ => ' ',
+            })
+            .collect();
+        let line = line.trim_end();
+        if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+    }
+    lines.join("\n")
+}
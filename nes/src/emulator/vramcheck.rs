@@ -0,0 +1,88 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+
+use crate::emulator::ppu::PPU;
+
+// Catches writes to PPUDATA while the PPU is rendering.  VRAM is busy with the PPU's own fetches
+// then, so the write lands somewhere unexpected and corrupts the nametables.  Games with bad
+// timing do this, but so does an emulator whose timing is off, so each write is logged with where
+// the PPU was and the instruction which made it, to tell the two apart.
+
+// Writes kept between calls to `take_writes`.  Past this they're only counted.
+const MAX_WRITES: usize = 256;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RenderingWrite {
+    pub frame: u64,
+    pub scanline: u16,
+    pub dot: u16,
+    // Address of the instruction which wrote.
+    pub pc: u16,
+    // VRAM address the write went to, and the byte written.
+    pub address: u16,
+    pub byte: u8,
+}
+
+impl fmt::Display for RenderingWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Frame {} scanline {} dot {}: ${:04X} wrote ${:02X} to VRAM ${:04X} while rendering",
+            self.frame, self.scanline, self.dot, self.pc, self.byte, self.address
+        )
+    }
+}
+
+pub struct VramWriteLog {
+    ppu: Rc<RefCell<PPU>>,
+    writes: Vec<RenderingWrite>,
+    dropped: u64,
+}
+
+impl VramWriteLog {
+    pub fn new(ppu: Rc<RefCell<PPU>>) -> VramWriteLog {
+        VramWriteLog {
+            ppu,
+            writes: Vec::new(),
+            dropped: 0,
+        }
+    }
+
+    // Called by the CPU for every write to PPUDATA, before the PPU sees it.
+    pub fn record(&mut self, pc: u16, byte: u8) {
+        let ppu = match self.ppu.try_borrow() {
+            Err(_) => return,
+            Ok(ppu) => ppu,
+        };
+        let (address, rendering) = ppu.vram_write_target();
+        if !rendering {
+            return;
+        }
+        if self.writes.len() >= MAX_WRITES {
+            self.dropped += 1;
+            return;
+        }
+
+        let stats = ppu.stats();
+        self.writes.push(RenderingWrite {
+            frame: stats.frame_count,
+            scanline: stats.scanline,
+            dot: stats.dot,
+            pc,
+            address,
+            byte,
+        });
+    }
+
+    // Writes logged since the last call, oldest first.
+    pub fn take_writes(&mut self) -> Vec<RenderingWrite> {
+        core::mem::take(&mut self.writes)
+    }
+
+    // Writes which didn't fit in the log.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
@@ -0,0 +1,148 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Addresses whose reads or writes stop emulation, for tools which need to act on each access as
+// it happens.  The CPU notes every access to a watched address, and the run stops after the
+// instruction which made it, the same as for a breakpoint.  Mirrors aren't folded together, so
+// watching RAM at $0010 doesn't catch an access through $0810.
+
+const READ: u8 = 0x01;
+const WRITE: u8 = 0x02;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    fn flag(self) -> u8 {
+        match self {
+            Access::Read => READ,
+            Access::Write => WRITE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MemoryAccess {
+    pub access: Access,
+    pub address: u16,
+    // The byte read or written.
+    pub value: u8,
+    // Address of the instruction which made the access.
+    pub pc: u16,
+}
+
+pub struct Watchpoints {
+    // READ and WRITE bits for every address.
+    flags: Vec<u8>,
+    watched: usize,
+    hits: Vec<MemoryAccess>,
+}
+
+impl Default for Watchpoints {
+    fn default() -> Watchpoints {
+        Watchpoints {
+            flags: vec![0; 0x10000],
+            watched: 0,
+            hits: Vec::new(),
+        }
+    }
+}
+
+impl Watchpoints {
+    pub fn watch(&mut self, address: u16, access: Access) {
+        let flags = &mut self.flags[address as usize];
+        if *flags == 0 {
+            self.watched += 1;
+        }
+        *flags |= access.flag();
+    }
+
+    pub fn unwatch(&mut self, address: u16, access: Access) {
+        let flags = &mut self.flags[address as usize];
+        if *flags == 0 {
+            return;
+        }
+        *flags &= !access.flag();
+        if *flags == 0 {
+            self.watched -= 1;
+        }
+    }
+
+    pub fn is_watched(&self, address: u16, access: Access) -> bool {
+        self.flags[address as usize] & access.flag() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watched == 0
+    }
+
+    #[inline]
+    pub fn record(&mut self, access: Access, address: u16, value: u8, pc: u16) {
+        if self.is_watched(address, access) {
+            self.hits.push(MemoryAccess {
+                access,
+                address,
+                value,
+                pc,
+            });
+        }
+    }
+
+    pub fn has_hits(&self) -> bool {
+        !self.hits.is_empty()
+    }
+
+    // Accesses since the last call, oldest first.
+    pub fn take_hits(&mut self) -> Vec<MemoryAccess> {
+        core::mem::take(&mut self.hits)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_records_watched_accesses() {
+        let mut watchpoints = Watchpoints::default();
+        assert!(watchpoints.is_empty());
+        watchpoints.watch(0x0075, Access::Write);
+        watchpoints.watch(0x0075, Access::Read);
+        watchpoints.watch(0x6000, Access::Read);
+        assert!(!watchpoints.is_empty());
+
+        watchpoints.record(Access::Write, 0x0075, 3, 0x8000);
+        watchpoints.record(Access::Write, 0x6000, 1, 0x8003);
+        watchpoints.record(Access::Read, 0x6000, 2, 0x8006);
+        watchpoints.record(Access::Read, 0x0076, 4, 0x8009);
+        assert_eq!(
+            watchpoints.take_hits(),
+            vec![
+                MemoryAccess {
+                    access: Access::Write,
+                    address: 0x0075,
+                    value: 3,
+                    pc: 0x8000,
+                },
+                MemoryAccess {
+                    access: Access::Read,
+                    address: 0x6000,
+                    value: 2,
+                    pc: 0x8006,
+                },
+            ]
+        );
+        assert!(!watchpoints.has_hits());
+
+        // Unwatching one kind of access leaves the other.
+        watchpoints.unwatch(0x0075, Access::Write);
+        watchpoints.unwatch(0x6000, Access::Read);
+        assert!(watchpoints.is_watched(0x0075, Access::Read));
+        assert!(!watchpoints.is_watched(0x0075, Access::Write));
+        watchpoints.unwatch(0x0075, Access::Read);
+        assert!(watchpoints.is_empty());
+    }
+}
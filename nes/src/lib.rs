@@ -1 +1,9 @@
+// Without the `std` feature the core only needs `alloc`.  ROMs, palettes and the like are then
+// handed over as bytes, as there are no files to load them from.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod embed;
 pub mod emulator;
+pub mod prelude;
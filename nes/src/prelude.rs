@@ -0,0 +1,18 @@
+// Everything a frontend normally needs, in one place.  Modules under `emulator` get reorganised as
+// the core changes, but the names exported here are kept stable, so prefer `nes::prelude` over
+// reaching into the emulator's internals.
+
+pub use crate::embed::{ButtonState, Emulator};
+pub use crate::emulator::apu::AudioOut;
+pub use crate::emulator::config::Config;
+pub use crate::emulator::controller::{Button, KeyMap};
+pub use crate::emulator::ines::ROM;
+pub use crate::emulator::inputscript::InputScript;
+pub use crate::emulator::io::dirty::{DirtyTiles, Rect};
+pub use crate::emulator::io::event::{Event, EventBus, EventHandler, Key};
+pub use crate::emulator::io::palette::{Palette, PaletteKind};
+pub use crate::emulator::io::{Screen, SimpleAudioOut};
+pub use crate::emulator::movie::{FrameInput, Movie, MovieSession};
+pub use crate::emulator::ppu::{Colour, VideoOut};
+pub use crate::emulator::state::{NESState, SaveState};
+pub use crate::emulator::{Region, RunResult, NES};
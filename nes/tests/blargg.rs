@@ -0,0 +1,121 @@
+// Runs blargg's test ROMs headlessly, reading each one's result from $6000 and its message from
+// $6004.  Run with `cargo test --features rom-tests`.
+#![cfg(feature = "rom-tests")]
+
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use nes::emulator::config::Config;
+use nes::emulator::ines::ROM;
+use nes::emulator::io;
+use nes::emulator::io::event::EventBus;
+use nes::emulator::testrom::{run_test_rom, TestRomStatus};
+use nes::emulator::NES;
+
+// Of emulated time, far longer than any of them take.
+const MAX_SECONDS: u64 = 30;
+
+fn suite_path(suite: &str) -> PathBuf {
+    let mut buf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    buf.push("src/emulator/test/resources/");
+    buf.push(suite);
+    buf.push("rom_singles");
+    buf
+}
+
+// Why a ROM fails, or None if it passes.
+fn run(path: &PathBuf) -> Option<String> {
+    let rom = ROM::load(path);
+    let event_bus = Rc::new(RefCell::new(EventBus::new()));
+    let screen = Rc::new(RefCell::new(io::Screen::new()));
+    let audio = io::nop::DummyAudio {};
+    // instr_test covers the undocumented opcodes too.
+    let config = Config {
+        illegal_opcodes: true,
+        ..Config::default()
+    };
+    let mut nes = NES::new(event_bus, screen, audio, rom, &config);
+
+    let max_cycles = MAX_SECONDS * nes.region().master_clock_hz();
+    let result = run_test_rom(&mut nes, max_cycles);
+    if let Some(jam) = nes.cpu.borrow().jam() {
+        return Some(format!("{}: {}", jam, result.text));
+    }
+    match result.status {
+        TestRomStatus::Finished(0) => None,
+        TestRomStatus::Finished(code) => {
+            Some(format!("failed with code {}: {}", code, result.text))
+        }
+        _ => Some(format!(
+            "didn't finish after {}s: {}",
+            MAX_SECONDS, result.text
+        )),
+    }
+}
+
+// Runs every ROM in a suite, and fails listing each one which didn't pass, other than those
+// `known_failures` names.  Those must still fail, so they're noticed once fixed.  The suites
+// aren't all checked in, so a missing one is skipped.
+fn run_suite(suite: &str, known_failures: &[&str]) {
+    let dir = suite_path(suite);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => {
+            println!("Skipping {}, no ROMs at {}", suite, dir.display());
+            return;
+        }
+    };
+    let mut roms: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension() == Some(OsStr::new("nes")))
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty(), "No ROMs in {}", dir.display());
+
+    let mut problems = vec![];
+    for path in roms {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let known = known_failures.contains(&name.as_str());
+        match (run(&path), known) {
+            (None, false) | (Some(_), true) => (),
+            (Some(failure), false) => problems.push(format!("{}/{} {}", suite, name, failure)),
+            (None, true) => problems.push(format!("{}/{} passes now", suite, name)),
+        }
+    }
+    assert!(problems.is_empty(), "\n{}", problems.join("\n"));
+}
+
+#[test]
+fn instr_test_v5() {
+    // These use undocumented opcodes we don't emulate yet, such as SLO and the spare SBC.
+    run_suite(
+        "instr_test-v5",
+        &[
+            "03-immediate",
+            "04-zero_page",
+            "05-zp_xy",
+            "06-absolute",
+            "07-abs_xy",
+            "08-ind_x",
+            "09-ind_y",
+        ],
+    );
+}
+
+#[test]
+fn ppu_vbl_nmi() {
+    run_suite("ppu_vbl_nmi", &[]);
+}
+
+#[test]
+fn ppu_sprite_hit() {
+    run_suite("ppu_sprite_hit", &[]);
+}
+
+#[test]
+fn apu_test() {
+    run_suite("apu_test", &[]);
+}
@@ -0,0 +1,43 @@
+// Checks the CPU against the golden nestest log.  Run with `cargo test --features rom-tests`.
+#![cfg(feature = "rom-tests")]
+
+use std::fs;
+use std::path::PathBuf;
+
+use nes::emulator::cpu::nestest;
+use nes::emulator::ines::ROM;
+
+fn resource_path(name: &str) -> PathBuf {
+    let mut buf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    buf.push("src/emulator/test/resources/nestest/");
+    buf.push(name);
+    buf
+}
+
+fn load_log() -> String {
+    fs::read_to_string(resource_path("nestest.trace")).unwrap()
+}
+
+#[test]
+fn nestest_documented_opcodes() {
+    let rom = ROM::load(resource_path("nestest.nes"));
+    match nestest::verify(&rom, &load_log(), nestest::DOCUMENTED_INSTRUCTIONS) {
+        Ok(verified) => assert_eq!(verified, nestest::DOCUMENTED_INSTRUCTIONS),
+        Err(divergence) => panic!("{}", divergence),
+    }
+}
+
+#[test]
+fn nestest_full_log() {
+    // Undocumented opcodes aren't implemented yet, so the full log is expected to diverge once it
+    // reaches them.  Anything earlier is a regression.
+    let rom = ROM::load(resource_path("nestest.nes"));
+    let log = load_log();
+    match nestest::verify(&rom, &log, usize::MAX) {
+        Ok(verified) => assert_eq!(verified, log.lines().count()),
+        Err(divergence) => {
+            println!("{}", divergence);
+            assert!(divergence.line > nestest::DOCUMENTED_INSTRUCTIONS);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use nes::emulator::state::{NESState, SaveState};
+use nes::emulator::NES;
+
+// Plays the same stretch of a game over and over: a snapshot is taken at point A, and restored
+// whenever point B is reached.  Handy for watching a jingle or a raster effect repeatedly while
+// changing debug settings.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopEnd {
+    // This many frames after A.
+    Frames(u64),
+    // The CPU arriving at this address.
+    Breakpoint(u16),
+}
+
+pub struct ABLoop {
+    // Taken at the first instruction boundary after A was marked, since states can't be restored
+    // from the middle of a DMA.
+    start: Option<NESState>,
+    start_frame: u64,
+    end: Option<LoopEnd>,
+    // Whether the end breakpoint is ours to remove.
+    added_breakpoint: bool,
+    loops: u64,
+}
+
+impl ABLoop {
+    // Marks point A where the NES is now.
+    pub fn new(nes: &mut NES) -> ABLoop {
+        let mut ab_loop = ABLoop {
+            start: None,
+            start_frame: 0,
+            end: None,
+            added_breakpoint: false,
+            loops: 0,
+        };
+        ab_loop.try_snapshot(nes);
+        ab_loop
+    }
+
+    // Marks point B, and jumps straight back to A if the loop has started.
+    pub fn set_end(&mut self, nes: &mut NES, end: LoopEnd) {
+        self.remove_breakpoint(nes);
+        if let LoopEnd::Breakpoint(address) = end {
+            self.added_breakpoint = !nes.has_breakpoint(address);
+            nes.add_breakpoint(address);
+        }
+        self.end = Some(end);
+        self.restore(nes);
+    }
+
+    // Marks point B at the current frame.
+    pub fn end_here(&mut self, nes: &mut NES) {
+        let frames = frame_count(nes).saturating_sub(self.start_frame).max(1);
+        self.set_end(nes, LoopEnd::Frames(frames));
+    }
+
+    pub fn end(&self) -> Option<LoopEnd> {
+        self.end
+    }
+
+    // How many times B has been reached.
+    pub fn loops(&self) -> u64 {
+        self.loops
+    }
+
+    // Call after each run.  Returns whether the NES went back to A.
+    pub fn after_run(&mut self, nes: &mut NES, breakpoint: Option<u16>) -> bool {
+        if self.start.is_none() {
+            self.try_snapshot(nes);
+            return false;
+        }
+
+        let reached = match self.end {
+            None => false,
+            Some(LoopEnd::Frames(frames)) => frame_count(nes) >= self.start_frame + frames,
+            Some(LoopEnd::Breakpoint(address)) => breakpoint == Some(address),
+        };
+        if reached {
+            self.loops += 1;
+            self.restore(nes);
+        }
+        reached
+    }
+
+    // Call before dropping the loop.
+    pub fn clear(&mut self, nes: &mut NES) {
+        self.remove_breakpoint(nes);
+        self.end = None;
+    }
+
+    fn try_snapshot(&mut self, nes: &mut NES) {
+        if nes.at_instruction_boundary() {
+            self.start = Some(nes.freeze());
+            self.start_frame = frame_count(nes);
+        }
+    }
+
+    fn restore(&mut self, nes: &mut NES) {
+        if let Some(ref start) = self.start {
+            nes.hydrate(start.clone());
+        }
+    }
+
+    fn remove_breakpoint(&mut self, nes: &mut NES) {
+        if let Some(LoopEnd::Breakpoint(address)) = self.end {
+            if self.added_breakpoint {
+                nes.remove_breakpoint(address);
+            }
+        }
+        self.added_breakpoint = false;
+    }
+}
+
+fn frame_count(nes: &NES) -> u64 {
+    nes.ppu.borrow().stats().frame_count
+}
@@ -1,41 +1,104 @@
+use std::collections::VecDeque;
+
 use crate::portal::Portal;
+use crate::profile::{Tracer, Track};
 
 use sdl2::audio;
 
-pub const SAMPLE_RATE: f32 = 48_000.0;
+// Cap on buffered audio, in seconds.  Past this we're running fast and drop the oldest samples
+// rather than let latency build up.
+const MAX_BUFFERED_SECONDS: f32 = 0.1;
 
+// Samples handed over by the emulator thread.  `buffered` goes the other way, reporting how much
+// audio is still waiting to be played.
+#[derive(Clone, Debug, Default)]
 pub struct AudioQueue {
-    output: Portal<Vec<f32>>,
-    queue: audio::AudioQueue<f32>,
+    pub samples: Vec<f32>,
+    pub buffered: usize,
+}
+
+// Pulls resampled audio from the emulator and plays it through an SDL callback.
+pub struct AudioOutput {
+    output: Portal<AudioQueue>,
+    buffer: Portal<VecDeque<f32>>,
+    max_buffered: usize,
+    _device: audio::AudioDevice<Playback>,
 }
 
-impl AudioQueue {
-    pub fn new(audio: sdl2::AudioSubsystem, output: Portal<Vec<f32>>) -> AudioQueue {
+impl AudioOutput {
+    pub fn new(
+        audio: sdl2::AudioSubsystem,
+        output: Portal<AudioQueue>,
+        sample_rate: f32,
+        tracer: Tracer,
+    ) -> AudioOutput {
         let spec = audio::AudioSpecDesired {
-            freq: Some(SAMPLE_RATE as i32),
+            freq: Some(sample_rate as i32),
             channels: Some(1),
             samples: Some(1024),
         };
 
-        let queue = match audio.open_queue(None, &spec) {
-            Err(cause) => panic!("Failed to open audio queue: {}", cause),
-            Ok(q) => q,
+        let max_buffered = (sample_rate * MAX_BUFFERED_SECONDS) as usize;
+        let buffer = Portal::new(VecDeque::with_capacity(max_buffered));
+        let device = match audio.open_playback(None, &spec, |_| Playback {
+            buffer: buffer.clone(),
+            last_sample: 0.0,
+            tracer,
+        }) {
+            Err(cause) => panic!("Failed to open audio device: {}", cause),
+            Ok(d) => d,
         };
 
-        queue.resume();
+        device.resume();
 
-        AudioQueue { output, queue }
+        AudioOutput {
+            output,
+            buffer,
+            max_buffered,
+            _device: device,
+        }
     }
 
     pub fn flush(&mut self) {
-        let queue = &mut self.queue;
-        self.output.consume(|data| {
-            queue.queue(&data);
-            data.clear();
+        let buffer = &self.buffer;
+        let max_buffered = self.max_buffered;
+        self.output.consume(|queue| {
+            buffer.consume(|buffer| {
+                buffer.extend(queue.samples.iter());
+                let excess = buffer.len().saturating_sub(max_buffered);
+                buffer.drain(..excess);
+                queue.buffered = buffer.len();
+            });
+            queue.samples.clear();
         });
     }
 
-    pub fn size(&self) -> u32 {
-        self.queue.size()
+    pub fn size(&self) -> usize {
+        self.buffer.consume(|buffer| buffer.len())
+    }
+}
+
+struct Playback {
+    buffer: Portal<VecDeque<f32>>,
+    last_sample: f32,
+    tracer: Tracer,
+}
+
+impl audio::AudioCallback for Playback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let _span = self.tracer.span(Track::Audio, "audio callback");
+        let last_sample = &mut self.last_sample;
+        self.buffer.consume(|buffer| {
+            for sample in out.iter_mut() {
+                *last_sample = match buffer.pop_front() {
+                    Some(s) => s,
+                    // Running slow.  Ease towards silence instead of clicking.
+                    None => *last_sample * 0.99,
+                };
+                *sample = *last_sample;
+            }
+        });
     }
 }
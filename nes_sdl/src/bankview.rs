@@ -0,0 +1,163 @@
+use nes::emulator::cdl::{CODE, DATA};
+use nes::emulator::io::event::Key;
+use nes::emulator::NES;
+
+// Shows all of PRG-ROM, coloured by whether the code/data log has seen each byte run as code or
+// read as data, with the banks the CPU can see right now outlined.  PRG is cut into 8KB banks,
+// drawn as blocks four to a row, and big ROMs get more bytes to a pixel so they still fit.
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 256;
+
+const BANK_SIZE: usize = 0x2000;
+const BANKS_PER_ROW: usize = 4;
+const BANK_WIDTH: usize = WIDTH / BANKS_PER_ROW;
+// Most bytes squeezed into one pixel.  2MB of PRG fills the view at this scale.
+const MAX_BYTES_PER_PIXEL: usize = 32;
+
+const UNUSED: [u8; 3] = [0x20, 0x20, 0x20];
+const CODE_COLOUR: [u8; 3] = [0x30, 0xD0, 0x30];
+const DATA_COLOUR: [u8; 3] = [0x30, 0x60, 0xF0];
+const BOTH_COLOUR: [u8; 3] = [0x30, 0xE0, 0xE0];
+
+// Each 8KB of the CPU's view of the cartridge, and the colour its bank is outlined in.
+const WINDOWS: [(u16, [u8; 3]); 4] = [
+    (0x8000, [0xF0, 0x30, 0x30]),
+    (0xA000, [0xF0, 0xE0, 0x30]),
+    (0xC000, [0xF0, 0x30, 0xF0]),
+    (0xE000, [0xFF, 0xFF, 0xFF]),
+];
+
+#[derive(Default)]
+pub struct BankView;
+
+impl BankView {
+    // C clears the log, to see what one part of a game uses.  Returns whether the key was used.
+    pub fn handle_key(&mut self, key: Key, nes: &mut NES) -> bool {
+        match key {
+            Key::C => nes.clear_code_data_log(),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn lines(&self, nes: &NES) -> Vec<String> {
+        let cdl = match nes.code_data_log() {
+            None => return vec![String::from("CODE/DATA LOG OFF")],
+            Some(cdl) => cdl,
+        };
+
+        let counts = cdl.counts();
+        let percent = |count: usize| count as f64 * 100.0 / cdl.len().max(1) as f64;
+        let mut lines = vec![
+            format!(
+                "PRG {}KB  CODE {:.1}%  DATA {:.1}%  UNUSED {:.1}%",
+                cdl.len() / 1024,
+                percent(counts.code),
+                percent(counts.data),
+                percent(counts.unused)
+            ),
+            String::from("GREEN CODE  BLUE DATA  CYAN BOTH  C CLEAR"),
+        ];
+        for (address, _) in WINDOWS.iter() {
+            lines.push(match nes.prg_rom_offset(*address) {
+                None => format!("${:04X} -", address),
+                Some(offset) => format!(
+                    "${:04X} 8KB BANK {:3} AT ${:06X}",
+                    address,
+                    offset / BANK_SIZE,
+                    offset
+                ),
+            });
+        }
+        lines
+    }
+
+    // Draws into an RGB buffer of WIDTH x HEIGHT.
+    pub fn render(&self, nes: &NES, buffer: &mut [u8]) {
+        buffer.iter_mut().for_each(|b| *b = 0);
+        let cdl = match nes.code_data_log() {
+            None => return,
+            Some(cdl) => cdl,
+        };
+
+        let layout = Layout::for_size(cdl.len());
+        for bank in 0..layout.banks {
+            for pixel in 0..BANK_SIZE / layout.bytes_per_pixel {
+                let start = bank * BANK_SIZE + pixel * layout.bytes_per_pixel;
+                let flags = (start..start + layout.bytes_per_pixel)
+                    .fold(0, |flags, offset| flags | cdl.flags(offset));
+                let colour = match (flags & CODE != 0, flags & DATA != 0) {
+                    (true, true) => BOTH_COLOUR,
+                    (true, false) => CODE_COLOUR,
+                    (false, true) => DATA_COLOUR,
+                    (false, false) => UNUSED,
+                };
+                let (x, y) = layout.bank_origin(bank);
+                let (dx, dy) = (pixel % BANK_WIDTH, pixel / BANK_WIDTH);
+                if y + dy < HEIGHT {
+                    set_pixel(buffer, x + dx, y + dy, colour);
+                }
+            }
+        }
+
+        for (address, colour) in WINDOWS.iter() {
+            if let Some(offset) = nes.prg_rom_offset(*address) {
+                layout.outline(buffer, offset / BANK_SIZE, *colour);
+            }
+        }
+    }
+}
+
+struct Layout {
+    banks: usize,
+    bytes_per_pixel: usize,
+    bank_height: usize,
+}
+
+impl Layout {
+    // As few bytes to a pixel as still fit every bank in.
+    fn for_size(prg_len: usize) -> Layout {
+        let banks = prg_len.div_ceil(BANK_SIZE);
+        let rows = banks.div_ceil(BANKS_PER_ROW);
+        let mut bytes_per_pixel = 1;
+        while bytes_per_pixel < MAX_BYTES_PER_PIXEL
+            && rows * (BANK_SIZE / bytes_per_pixel / BANK_WIDTH) > HEIGHT
+        {
+            bytes_per_pixel *= 2;
+        }
+        Layout {
+            banks,
+            bytes_per_pixel,
+            bank_height: BANK_SIZE / bytes_per_pixel / BANK_WIDTH,
+        }
+    }
+
+    fn bank_origin(&self, bank: usize) -> (usize, usize) {
+        (
+            (bank % BANKS_PER_ROW) * BANK_WIDTH,
+            (bank / BANKS_PER_ROW) * self.bank_height,
+        )
+    }
+
+    fn outline(&self, buffer: &mut [u8], bank: usize, colour: [u8; 3]) {
+        let (left, top) = self.bank_origin(bank);
+        let (right, bottom) = (left + BANK_WIDTH - 1, top + self.bank_height - 1);
+        if bottom >= HEIGHT {
+            return;
+        }
+        for x in left..=right {
+            set_pixel(buffer, x, top, colour);
+            set_pixel(buffer, x, bottom, colour);
+        }
+        for y in top..=bottom {
+            set_pixel(buffer, left, y, colour);
+            set_pixel(buffer, right, y, colour);
+        }
+    }
+}
+
+fn set_pixel(buffer: &mut [u8], x: usize, y: usize, colour: [u8; 3]) {
+    let ix = (y * WIDTH + x) * 3;
+    buffer[ix..ix + 3].copy_from_slice(&colour);
+}
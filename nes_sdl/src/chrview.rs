@@ -0,0 +1,83 @@
+use nes::emulator::io::event::Key;
+use nes::emulator::memory::ChrBanks;
+use nes::emulator::NES;
+
+// Lists the CHR banks the pattern tables held over the last frame, and which 1KB slots have been
+// animating.  Any recent set of banks can be frozen in the pattern viewer, so tiles which only
+// show for a frame or two can be looked at properly.
+
+// Beyond this, a game is swapping banks every few scanlines and the list stops being readable.
+const MAX_SETS: usize = 4;
+
+#[derive(Default)]
+pub struct ChrBankView {
+    frozen: Option<ChrBanks>,
+}
+
+impl ChrBankView {
+    pub fn frozen(&self) -> Option<ChrBanks> {
+        self.frozen
+    }
+
+    // K freezes or unfreezes the pattern viewer, -/= step through recently seen banks while
+    // frozen.  Returns whether the key was used.
+    pub fn handle_key(&mut self, key: Key, nes: &NES) -> bool {
+        let sets = match nes.chr_bank_tracker() {
+            Some(tracker) if !tracker.recent_sets().is_empty() => tracker.recent_sets(),
+            _ => return false,
+        };
+        let step = match key {
+            Key::K => {
+                self.frozen = match self.frozen {
+                    Some(_) => None,
+                    None => sets.last().copied(),
+                };
+                return true;
+            }
+            Key::Minus => sets.len() - 1,
+            Key::Equals => 1,
+            _ => return false,
+        };
+        if let Some(frozen) = self.frozen {
+            let ix = sets.iter().position(|banks| *banks == frozen).unwrap_or(0);
+            self.frozen = sets.get((ix + step) % sets.len()).copied();
+        }
+        true
+    }
+
+    pub fn lines(&self, nes: &NES) -> Vec<String> {
+        let tracker = match nes.chr_bank_tracker() {
+            None => return vec![String::from("CHR NOT BANKED")],
+            Some(tracker) => tracker,
+        };
+
+        let mut lines = vec![String::from("CHR BANKS LAST FRAME")];
+        let last_frame = tracker.last_frame();
+        for banks in last_frame.iter().take(MAX_SETS) {
+            lines.push(format_banks(banks));
+        }
+        if last_frame.len() > MAX_SETS {
+            lines.push(format!("+{} MORE", last_frame.len() - MAX_SETS));
+        }
+
+        for slot in 0..8 {
+            let history = tracker.slot_history(slot);
+            if history.len() > 1 {
+                let banks: Vec<String> = history.iter().map(|b| format!("{:02X}", b)).collect();
+                lines.push(format!("${:04X} ANIM {}", slot * 0x400, banks.join(" ")));
+            }
+        }
+
+        lines.push(match self.frozen {
+            Some(banks) => format!("FROZEN {}", format_banks(&banks)),
+            None => String::from("LIVE"),
+        });
+        lines.push(String::from("K FREEZE  -/= BANKS"));
+        lines
+    }
+}
+
+fn format_banks(banks: &ChrBanks) -> String {
+    let banks: Vec<String> = banks.iter().map(|b| format!("{:02X}", b)).collect();
+    banks.join(" ")
+}
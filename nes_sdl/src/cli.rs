@@ -0,0 +1,404 @@
+use std::path::PathBuf;
+
+use nes::emulator::Region;
+
+use crate::netplay::{NetplayRole, DEFAULT_DELAY_FRAMES};
+
+pub const USAGE: &str = "\
+Usage:
+  nes_sdl [options] <rom.nes>
+  nes_sdl --fix-header <in.nes> <out.nes> [--db <romdb.toml>]
+  nes_sdl --soak <rom.nes>... [--seed <n>] [--frames <n>] [--crash-dir <path>]
+  nes_sdl --test-rom <rom.nes> [--frames <n>]
+  nes_sdl --recent
+
+Options:
+  --rom <path>         ROM to load, .nes or .zip (may also be given as a bare argument,
+                       or as rom_path under [emulator] in config.toml, else the last
+                       ROM played)
+  --scale <n>          Window scale factor [default: 4]
+  --headless           Run without a window or audio
+  --frames <n>         Exit after emulating n frames
+  --trace <path>       Write the CPU trace to this file on exit
+  --trace-size <n>     Keep the last n instructions in the trace [default: 2000000]
+  --pal, --ntsc        Override the region from the ROM header
+  --hardware <chip>    Same, by the console's CPU or PPU: 2a03 or 2c02 for NTSC, 2a07 or
+                       2c07 for PAL
+  --palette <file.pal> Use the colours from a .pal file, with 64 or 512 entries
+  --save-dir <path>    Directory for save states
+  --record <file.fm2>  Record a movie
+  --play <file.fm2>    Play back a movie
+  --script <file>      Play back an input script
+  --rhai <file.rhai>   Run a Rhai script with hooks on each frame and on memory accesses
+  --profile <trace.json>
+                       Write a timeline of each frame's work on exit, for chrome://tracing
+  --compare <rom.nes>  Run a second ROM alongside the first, in the same window
+  --compare-play <file.fm2>
+                       Play back a movie on the second ROM
+  --compare-pal, --compare-ntsc, --compare-hardware <chip>
+                       Override the second ROM's region
+  --fbdev <device>     Draw to a Linux framebuffer such as /dev/fb0 instead of an SDL window.
+                       Runs without sound
+  --evdev <device>     With --fbdev, read the keyboard from an input device such as
+                       /dev/input/event0
+  --netplay <host|address>
+                       Play two players over the network, either waiting for the other player
+                       (host or host:<port>) or connecting to them (address[:port])
+  --netplay-delay <n>  Frames of input delay when hosting, to hide network lag [default: 3]
+  --vram-check         Print where the game writes to VRAM while the PPU is rendering, which
+                       corrupts the picture
+  --recent             List the ROMs played lately, most recent first
+  --help               Show this message
+
+Soak mode plays random input into each ROM in turn.  If the emulator panics, a movie which
+replays up to the crash is written to the crash directory.
+
+Test ROM mode runs a blargg-style test ROM without a window, prints its output and exits with 0 if
+it passed, 1 if it failed or 2 if it didn't finish in time (by default a minute of game time).
+
+Input scripts are plain text, one command per line, e.g. `frame 120: P1 A+RIGHT for 10`.  The
+commands are P1 to P4 followed by buttons joined with +, RESET and WAIT.  Buttons are held for a
+single frame unless given `for <n>` frames.
+
+Rhai scripts register callbacks with on_frame(|frame| ...), on_read(address, |address, value| ...)
+and on_write(address, |address, value| ...).  They can call peek(address), poke(address, value),
+frame(), buttons(player) and set_buttons(player, mask), where players count from 1 and masks are
+in controller order, A first.
+
+Netplay needs both players to have the same ROM.  The host is player 1 and the other player is
+player 2, and each plays with their own player 1 keys.  Saves and cheats are left alone, as
+for movies.
+
+--fbdev is for boards like the Raspberry Pi running without a desktop.  The picture is scaled up
+by as much as fits the screen.
+
+With --compare, F10 switches which game the keyboard controls and only the first game is heard.
+The same ROM can be given twice, e.g. to compare regions or race two movies.";
+
+pub const DEFAULT_SCALE: u32 = 4;
+
+// An hour of play at 60 FPS.
+pub const DEFAULT_SOAK_FRAMES: u64 = 60 * 60 * 60;
+
+pub const DEFAULT_TEST_ROM_FRAMES: u64 = 60 * 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunOptions {
+    pub rom: String,
+    pub scale: u32,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub trace: Option<String>,
+    pub trace_size: Option<usize>,
+    pub region: Option<Region>,
+    pub palette: Option<PathBuf>,
+    pub save_dir: Option<PathBuf>,
+    pub record_movie: Option<String>,
+    pub play_movie: Option<String>,
+    pub script: Option<String>,
+    pub rhai: Option<String>,
+    pub profile: Option<String>,
+    pub compare: Option<CompareOptions>,
+    pub fbdev: Option<String>,
+    pub evdev: Option<String>,
+    pub vram_check: bool,
+    pub netplay: Option<NetplayRole>,
+    pub netplay_delay: u32,
+}
+
+// A second emulator shown beside the first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompareOptions {
+    pub rom: String,
+    pub region: Option<Region>,
+    pub play_movie: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Run(Box<RunOptions>),
+    FixHeader {
+        input: String,
+        output: String,
+        db: Option<PathBuf>,
+    },
+    Soak {
+        roms: Vec<String>,
+        seed: u64,
+        frames: u64,
+        crash_dir: PathBuf,
+    },
+    TestRom {
+        rom: String,
+        frames: u64,
+    },
+    Recent,
+    Help,
+}
+
+// Splits the arguments into flags and bare arguments.  Flags take their value either as
+// `--flag=value` or as the next argument.
+struct Args {
+    flags: Vec<(String, Option<String>)>,
+    positional: Vec<String>,
+}
+
+// Flags which take a value.  Anything else starting with `--` is a switch.
+const VALUE_FLAGS: [&str; 23] = [
+    "rom",
+    "scale",
+    "frames",
+    "trace",
+    "trace-size",
+    "palette",
+    "save-dir",
+    "record",
+    "play",
+    "script",
+    "rhai",
+    "profile",
+    "db",
+    "seed",
+    "crash-dir",
+    "compare",
+    "compare-play",
+    "fbdev",
+    "evdev",
+    "netplay",
+    "netplay-delay",
+    "hardware",
+    "compare-hardware",
+];
+
+fn split_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args {
+        flags: vec![],
+        positional: vec![],
+    };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with("--") {
+            parsed.positional.push(arg.clone());
+            continue;
+        }
+
+        let mut parts = arg[2..].splitn(2, '=');
+        let name = parts.next().unwrap_or("").to_string();
+        let mut value = parts.next().map(String::from);
+        if value.is_none() && VALUE_FLAGS.contains(&name.as_str()) {
+            value = match iter.next() {
+                Some(v) => Some(v.clone()),
+                None => return Err(format!("--{} needs a value", name)),
+            };
+        }
+        parsed.flags.push((name, value));
+    }
+
+    Ok(parsed)
+}
+
+impl Args {
+    fn value(&self, name: &str) -> Option<String> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(flag, _)| flag == name)
+            .and_then(|(_, value)| value.clone())
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.flags.iter().any(|(flag, _)| flag == name)
+    }
+
+    fn region(&self, pal: &str, ntsc: &str, hardware: &str) -> Result<Option<Region>, String> {
+        if let Some(name) = self.value(hardware) {
+            Region::parse(&name).map(Some)
+        } else if self.switch(pal) {
+            Ok(Some(Region::PAL))
+        } else if self.switch(ntsc) {
+            Ok(Some(Region::NTSC))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn number(&self, name: &str) -> Result<Option<u64>, String> {
+        match self.value(name) {
+            None => Ok(None),
+            Some(v) => v
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("--{} expects a number, got '{}'", name, v)),
+        }
+    }
+}
+
+// `args` excludes the program name.
+// `default_rom` is run if no ROM is given.
+pub fn parse_args(args: &[String], default_rom: Option<&str>) -> Result<Command, String> {
+    let parsed = split_args(args)?;
+
+    if parsed.switch("help") {
+        return Ok(Command::Help);
+    }
+
+    if parsed.switch("recent") {
+        return Ok(Command::Recent);
+    }
+
+    // Utility mode which rewrites a ROM's header and exits without starting the emulator.
+    if parsed.switch("fix-header") {
+        if parsed.positional.len() != 2 {
+            return Err(String::from("--fix-header needs an input and output path"));
+        }
+        return Ok(Command::FixHeader {
+            input: parsed.positional[0].clone(),
+            output: parsed.positional[1].clone(),
+            db: parsed.value("db").map(PathBuf::from),
+        });
+    }
+
+    // Runs a test ROM to completion for CI.
+    if parsed.switch("test-rom") {
+        let rom = match parsed.value("rom").or(parsed.positional.first().cloned()) {
+            None => return Err(String::from("--test-rom needs a ROM")),
+            Some(rom) => rom,
+        };
+        return Ok(Command::TestRom {
+            rom,
+            frames: parsed.number("frames")?.unwrap_or(DEFAULT_TEST_ROM_FRAMES),
+        });
+    }
+
+    // Headless stress test over any number of ROMs.
+    if parsed.switch("soak") {
+        let mut roms = parsed.positional.clone();
+        roms.extend(parsed.value("rom"));
+        if roms.is_empty() {
+            return Err(String::from("--soak needs at least one ROM"));
+        }
+        return Ok(Command::Soak {
+            roms,
+            seed: parsed.number("seed")?.unwrap_or(0),
+            frames: parsed.number("frames")?.unwrap_or(DEFAULT_SOAK_FRAMES),
+            crash_dir: PathBuf::from(parsed.value("crash-dir").unwrap_or(String::from("."))),
+        });
+    }
+
+    for (flag, _) in parsed.flags.iter() {
+        let known = VALUE_FLAGS.contains(&flag.as_str())
+            || [
+                "headless",
+                "pal",
+                "ntsc",
+                "compare-pal",
+                "compare-ntsc",
+                "vram-check",
+            ]
+            .contains(&flag.as_str());
+        let other_mode = ["db", "seed", "crash-dir"].contains(&flag.as_str());
+        if !known || other_mode {
+            return Err(format!("Unknown option --{}", flag));
+        }
+    }
+
+    let rom = parsed
+        .value("rom")
+        .or(parsed.positional.first().cloned())
+        .or(default_rom.map(String::from));
+    let rom = match rom {
+        None => return Err(String::from("No ROM given")),
+        Some(rom) => rom,
+    };
+
+    // By default the region comes from the ROM header, but many dumps don't set it.
+    let region = parsed.region("pal", "ntsc", "hardware")?;
+
+    let scale = parsed.number("scale")?.unwrap_or(DEFAULT_SCALE as u64) as u32;
+    if scale == 0 {
+        return Err(String::from("--scale must be at least 1"));
+    }
+
+    let headless = parsed.switch("headless");
+    let frames = parsed.number("frames")?;
+    let play_movie = parsed.value("play");
+    let script = parsed.value("script");
+    if play_movie.is_some() && script.is_some() {
+        return Err(String::from("--play and --script can't be used together"));
+    }
+    if headless && frames.is_none() && play_movie.is_none() && script.is_none() {
+        return Err(String::from(
+            "--headless needs --frames, --play or --script to know when to stop",
+        ));
+    }
+
+    let trace_size = parsed.number("trace-size")?.map(|n| n as usize);
+    if trace_size == Some(0) {
+        return Err(String::from("--trace-size must be at least 1"));
+    }
+
+    let compare_region = parsed.region("compare-pal", "compare-ntsc", "compare-hardware")?;
+    let compare = parsed.value("compare").map(|rom| CompareOptions {
+        rom,
+        region: compare_region,
+        play_movie: parsed.value("compare-play"),
+    });
+    if compare.is_none() && parsed.value("compare-play").is_some() {
+        return Err(String::from("--compare-play needs --compare"));
+    }
+    if headless && compare.is_some() {
+        return Err(String::from("--compare needs a window"));
+    }
+
+    let fbdev = parsed.value("fbdev");
+    let evdev = parsed.value("evdev");
+    if fbdev.is_some() && (headless || compare.is_some()) {
+        return Err(String::from(
+            "--fbdev can't be used with --headless or --compare",
+        ));
+    }
+    if evdev.is_some() && fbdev.is_none() {
+        return Err(String::from("--evdev needs --fbdev"));
+    }
+
+    let netplay = match parsed.value("netplay") {
+        None => None,
+        Some(arg) => Some(NetplayRole::parse(&arg)?),
+    };
+    let netplay_delay = parsed.number("netplay-delay")?;
+    if netplay.is_none() && netplay_delay.is_some() {
+        return Err(String::from("--netplay-delay needs --netplay"));
+    }
+    let movie = play_movie.is_some() || script.is_some() || parsed.value("record").is_some();
+    if netplay.is_some() && (headless || compare.is_some() || fbdev.is_some() || movie) {
+        return Err(String::from(
+            "--netplay can't be used with --headless, --compare, --fbdev or movies",
+        ));
+    }
+
+    Ok(Command::Run(Box::new(RunOptions {
+        rom,
+        scale,
+        headless,
+        frames,
+        trace: parsed.value("trace"),
+        trace_size,
+        region,
+        palette: parsed.value("palette").map(PathBuf::from),
+        save_dir: parsed.value("save-dir").map(PathBuf::from),
+        record_movie: parsed.value("record"),
+        play_movie,
+        script,
+        rhai: parsed.value("rhai"),
+        profile: parsed.value("profile"),
+        compare,
+        fbdev,
+        evdev,
+        vram_check: parsed.switch("vram-check"),
+        netplay,
+        netplay_delay: netplay_delay
+            .map(|n| n as u32)
+            .unwrap_or(DEFAULT_DELAY_FRAMES),
+    })))
+}
@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+
+use crate::abloop::LoopEnd;
+
+// Requests other threads can make of the running emulator.  They're handled between frames, so a
+// command never lands half way through one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EmulatorCommand {
+    Pause,
+    Resume,
+    // Save states are named like the ones on the number keys, e.g. "zelda.1".
+    SaveState(String),
+    LoadState(String),
+    Screenshot,
+    // Pattern tables and nametables as PNGs, in the screenshot directory.
+    ExportTiles,
+    LoadRom(PathBuf),
+    // Emulation speed in master clock Hz.  0 pauses.
+    SetTargetHz(u64),
+    // Snapshots the game now, as the start of an A/B loop.
+    MarkLoopStart,
+    // Goes back to the start of the loop every time this is reached.
+    MarkLoopEnd(LoopEnd),
+    ClearLoop,
+}
+
+pub type CommandResult = Result<(), String>;
+
+struct Envelope {
+    command: EmulatorCommand,
+    reply: Option<Sender<CommandResult>>,
+}
+
+// Cheap to clone, and each clone can be moved to a different thread.
+#[derive(Clone)]
+pub struct CommandSender {
+    sender: Sender<Envelope>,
+}
+
+pub struct CommandReceiver {
+    receiver: Receiver<Envelope>,
+}
+
+pub fn command_channel() -> (CommandSender, CommandReceiver) {
+    let (sender, receiver) = channel();
+    (CommandSender { sender }, CommandReceiver { receiver })
+}
+
+const EMULATOR_GONE: &str = "The emulator has stopped";
+
+impl CommandSender {
+    // Queues a command without waiting for it to run.
+    pub fn send(&self, command: EmulatorCommand) -> CommandResult {
+        self.sender
+            .send(Envelope {
+                command,
+                reply: None,
+            })
+            .map_err(|_| String::from(EMULATOR_GONE))
+    }
+
+    // Queues a command and blocks until the emulator has run it.  Don't call this from the
+    // emulation thread, since it will never get to the command.
+    pub fn request(&self, command: EmulatorCommand) -> CommandResult {
+        let (reply, result) = channel();
+        self.sender
+            .send(Envelope {
+                command,
+                reply: Some(reply),
+            })
+            .map_err(|_| String::from(EMULATOR_GONE))?;
+        result.recv().map_err(|_| String::from(EMULATOR_GONE))?
+    }
+}
+
+impl CommandReceiver {
+    // Runs every queued command through `handle`, replying to any sender which is waiting.
+    pub fn drain<F: FnMut(EmulatorCommand) -> CommandResult>(&self, mut handle: F) {
+        loop {
+            let envelope = match self.receiver.try_recv() {
+                Ok(envelope) => envelope,
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => return,
+            };
+
+            let result = handle(envelope.command);
+            match envelope.reply {
+                // The sender may have given up waiting, which is fine.
+                Some(reply) => {
+                    let _ = reply.send(result);
+                }
+                None => {
+                    if let Err(cause) = result {
+                        println!("Command failed: {}", cause);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,38 +1,91 @@
+use std::time::Duration;
+
 use nes::emulator::apu::debug::APUDebug;
 use nes::emulator::ppu::debug::{PPUDebug, PPUDebugRender};
 
+use crate::bankview;
 use crate::controller::DebugMode;
+use crate::frames::FrameReceiver;
+use crate::heatmap;
+use crate::osd::{draw_text, Stats};
 use crate::portal::Portal;
 
 use sdl2::{pixels, rect, render, video};
 
-const SCALE: u8 = 4;
+// Height of each row of text in the text debug views, in debug window pixels.
+const LINE_HEIGHT: i32 = 7;
+
+// Where the pause menu goes, counted in its own double size pixels.
+const MENU_LEFT: i32 = 16;
+const MENU_TOP: i32 = 40;
+
+// What the emulator thread sends for the debug window to show.
+#[derive(Clone)]
+pub struct DebugPortals {
+    pub ppu: Portal<PPUDebugRender>,
+    pub apu: Portal<Box<[u8]>>,
+    pub text: Portal<Vec<String>>,
+    pub heatmap: Portal<Box<[u8]>>,
+    pub banks: Portal<Box<[u8]>>,
+}
+
+impl Default for DebugPortals {
+    fn default() -> DebugPortals {
+        DebugPortals {
+            ppu: Portal::new(PPUDebugRender::new()),
+            apu: Portal::new(
+                vec![0; APUDebug::WAVEFORM_WIDTH * APUDebug::WAVEFORM_HEIGHT * 3]
+                    .into_boxed_slice(),
+            ),
+            text: Portal::new(vec![]),
+            heatmap: Portal::new(vec![0; heatmap::WIDTH * heatmap::HEIGHT * 3].into_boxed_slice()),
+            banks: Portal::new(vec![0; bankview::WIDTH * bankview::HEIGHT * 3].into_boxed_slice()),
+        }
+    }
+}
 
 pub struct Compositor {
     canvas: render::Canvas<video::Window>,
-    nes_texture: render::Texture,
+    // One per emulator, shown left to right.
+    nes_textures: Vec<render::Texture>,
+    // The frame before each of those, and whether it's worth blending from, i.e. the emulator
+    // sent a new frame last time one was due.  Only kept up when interpolating.
+    previous_textures: Vec<render::Texture>,
+    blend_from_previous: Vec<bool>,
+    interpolate: bool,
+    // How much of the newest frame shows over the previous one.
+    blend: u8,
     debug_canvas: render::Canvas<video::Window>,
     pattern_texture: render::Texture,
     nametable_texture: render::Texture,
     sprite_texture: render::Texture,
     palette_texture: render::Texture,
     waveform_texture: render::Texture,
+    heatmap_texture: render::Texture,
+    bank_texture: render::Texture,
 
-    nes_output: Portal<Box<[u8]>>,
-    ppu_debug: Portal<PPUDebugRender>,
-    apu_debug: Portal<Box<[u8]>>,
+    frames: Vec<FrameReceiver>,
+    // Which emulator has the keyboard.  Only marked when there's more than one.
+    focus: usize,
+    debug: DebugPortals,
+    stats: Portal<Stats>,
+    scale: u32,
     debug_mode: DebugMode,
+    show_osd: bool,
+    // The focused emulator's pause menu, empty when it's closed.
+    menu: Vec<String>,
 }
 
 impl Compositor {
     pub fn new(
         video: sdl2::VideoSubsystem,
-        nes_output: Portal<Box<[u8]>>,
-        ppu_debug: Portal<PPUDebugRender>,
-        apu_debug: Portal<Box<[u8]>>,
+        frames: Vec<FrameReceiver>,
+        debug: DebugPortals,
+        stats: Portal<Stats>,
+        scale: u32,
     ) -> Compositor {
         let mut main_window = video
-            .window("NES", 256 * SCALE as u32, 240 * SCALE as u32)
+            .window("NES", 256 * scale * frames.len() as u32, 240 * scale)
             .position_centered()
             .opengl()
             .build()
@@ -40,20 +93,38 @@ impl Compositor {
 
         main_window.raise();
 
-        let canvas = main_window.into_canvas().accelerated().build().unwrap();
+        // Presenting waits for vsync, which is what paces the UI thread.
+        let canvas = main_window
+            .into_canvas()
+            .accelerated()
+            .present_vsync()
+            .build()
+            .unwrap();
 
         let texture_creator = canvas.texture_creator();
-        let nes_texture = match texture_creator.create_texture_static(
-            Some(pixels::PixelFormatEnum::RGB24),
-            256,
-            240,
-        ) {
-            Err(cause) => panic!("Failed to create texture: {}", cause),
-            Ok(t) => t,
+        let create_nes_textures = || -> Vec<render::Texture> {
+            frames
+                .iter()
+                .map(|_| {
+                    match texture_creator.create_texture_static(
+                        Some(pixels::PixelFormatEnum::RGB24),
+                        256,
+                        240,
+                    ) {
+                        Err(cause) => panic!("Failed to create texture: {}", cause),
+                        Ok(mut t) => {
+                            t.set_blend_mode(render::BlendMode::Blend);
+                            t
+                        }
+                    }
+                })
+                .collect()
         };
+        let nes_textures = create_nes_textures();
+        let previous_textures = create_nes_textures();
 
         let debug_window = video
-            .window("NES (Debug)", 256 * 2 as u32, 472 * 2 as u32)
+            .window("NES (Debug)", 256 * 2 as u32, 600 * 2 as u32)
             .opengl()
             .hidden()
             .build()
@@ -103,8 +174,26 @@ impl Compositor {
 
         let waveform_texture = match debug_texture_creator.create_texture_static(
             Some(pixels::PixelFormatEnum::RGB24),
-            256,
-            160,
+            APUDebug::WAVEFORM_WIDTH as u32,
+            APUDebug::WAVEFORM_HEIGHT as u32,
+        ) {
+            Err(cause) => panic!("Failed to create texture: {}", cause),
+            Ok(t) => t,
+        };
+
+        let heatmap_texture = match debug_texture_creator.create_texture_static(
+            Some(pixels::PixelFormatEnum::RGB24),
+            heatmap::WIDTH as u32,
+            heatmap::HEIGHT as u32,
+        ) {
+            Err(cause) => panic!("Failed to create texture: {}", cause),
+            Ok(t) => t,
+        };
+
+        let bank_texture = match debug_texture_creator.create_texture_static(
+            Some(pixels::PixelFormatEnum::RGB24),
+            bankview::WIDTH as u32,
+            bankview::HEIGHT as u32,
         ) {
             Err(cause) => panic!("Failed to create texture: {}", cause),
             Ok(t) => t,
@@ -112,17 +201,27 @@ impl Compositor {
 
         Compositor {
             canvas,
-            nes_texture,
+            nes_textures,
+            previous_textures,
+            blend_from_previous: vec![false; frames.len()],
+            interpolate: false,
+            blend: 0xFF,
             debug_canvas,
             pattern_texture,
             nametable_texture,
             sprite_texture,
             palette_texture,
             waveform_texture,
-            nes_output,
-            ppu_debug,
-            apu_debug,
+            heatmap_texture,
+            bank_texture,
+            frames,
+            focus: 0,
+            debug,
+            stats,
+            scale,
             debug_mode: DebugMode::OFF,
+            show_osd: false,
+            menu: vec![],
         }
     }
 
@@ -132,10 +231,54 @@ impl Compositor {
         match self.debug_mode {
             DebugMode::PPU => self.render_ppu_debug(),
             DebugMode::APU => self.render_apu_debug(),
+            DebugMode::SPRITES | DebugMode::MEMORY | DebugMode::EVENTS => self.render_text_debug(),
+            DebugMode::HEATMAP => self.render_heatmap_debug(),
+            DebugMode::BANKS => self.render_bank_debug(),
             _ => (),
         }
     }
 
+    // Picks up the newest frame from each emulator, waiting up to `timeout` for the first one.
+    // The others just show whatever they've finished by then.  Returns whether the first one
+    // arrived.
+    pub fn receive_frame(&mut self, timeout: Duration) -> bool {
+        let mut arrived = false;
+        for ix in 0..self.frames.len() {
+            let wait = if ix == 0 { timeout } else { Duration::ZERO };
+            let frame = self.frames[ix].latest(wait);
+            if self.interpolate {
+                if frame.is_some() {
+                    std::mem::swap(&mut self.nes_textures[ix], &mut self.previous_textures[ix]);
+                }
+                self.blend_from_previous[ix] = frame.is_some();
+            }
+            if let Some(frame) = frame {
+                let _ = self.nes_textures[ix].update(None, &frame, 256 * 3);
+                arrived |= ix == 0;
+            }
+        }
+        arrived
+    }
+
+    // Blends each new frame in over the last rather than just replacing it, for displays which
+    // show each frame more than once.
+    pub fn set_interpolate(&mut self, interpolate: bool) {
+        self.interpolate = interpolate;
+        self.blend_from_previous.iter_mut().for_each(|b| *b = false);
+    }
+
+    // From 0, showing only the previous frame, to 1 for only the newest.
+    pub fn set_blend(&mut self, blend: f64) {
+        self.blend = (blend.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+
+    pub fn refresh_rate(&self) -> Option<u32> {
+        match self.canvas.window().display_mode() {
+            Ok(mode) if mode.refresh_rate > 0 => Some(mode.refresh_rate as u32),
+            _ => None,
+        }
+    }
+
     pub fn set_window_title(&mut self, title: &str) {
         match self.canvas.window_mut().set_title(title) {
             Err(cause) => panic!("failed to set window title: {}", cause),
@@ -150,18 +293,58 @@ impl Compositor {
 
         self.debug_mode = mode;
         match self.debug_mode {
-            DebugMode::PPU | DebugMode::APU => self.debug_canvas.window_mut().show(),
-            _ => self.debug_canvas.window_mut().hide(),
+            DebugMode::OFF => self.debug_canvas.window_mut().hide(),
+            _ => self.debug_canvas.window_mut().show(),
         }
     }
 
+    pub fn set_osd(&mut self, show: bool) {
+        self.show_osd = show;
+    }
+
+    pub fn set_menu(&mut self, lines: Vec<String>) {
+        self.menu = lines;
+    }
+
+    pub fn set_focus(&mut self, focus: usize) {
+        self.focus = focus;
+    }
+
     fn render_main(&mut self) {
         self.canvas.clear();
-        let texture = &mut self.nes_texture;
-        self.nes_output.consume(|data| {
-            let _ = texture.update(None, data, 256 * 3);
-        });
-        let _ = self.canvas.copy(&texture, None, None);
+        let width = 256 * self.scale;
+        let height = 240 * self.scale;
+        for (ix, texture) in self.nes_textures.iter_mut().enumerate() {
+            let dest = rect::Rect::new((ix as u32 * width) as i32, 0, width, height);
+            if self.blend_from_previous[ix] {
+                let previous = &mut self.previous_textures[ix];
+                previous.set_alpha_mod(0xFF);
+                let _ = self.canvas.copy(previous, None, dest);
+                texture.set_alpha_mod(self.blend);
+            } else {
+                texture.set_alpha_mod(0xFF);
+            }
+            let _ = self.canvas.copy(texture, None, dest);
+        }
+        if self.nes_textures.len() > 1 {
+            let x = (self.focus as u32 * width) as i32;
+            self.canvas
+                .set_draw_color(pixels::Color::RGBA(0xFF, 0xFF, 0xFF, 0xFF));
+            let _ = self.canvas.draw_rect(rect::Rect::new(x, 0, width, height));
+            self.canvas
+                .set_draw_color(pixels::Color::RGBA(0, 0, 0, 0xFF));
+        }
+        if self.show_osd {
+            let summary = self.stats.consume(|stats| stats.summary());
+            draw_text(&mut self.canvas, 0, 0, self.scale as i32, &summary);
+        }
+        // Over the middle of the game it belongs to, at double size.
+        let scale = 2 * self.scale as i32;
+        let left = (self.focus as u32 * width) as i32 + MENU_LEFT * scale;
+        for (ix, line) in self.menu.iter().enumerate() {
+            let top = MENU_TOP * scale + ix as i32 * LINE_HEIGHT * scale;
+            draw_text(&mut self.canvas, left, top, scale, line);
+        }
         self.canvas.present();
     }
 
@@ -172,7 +355,7 @@ impl Compositor {
         let sprite_texture = &mut self.sprite_texture;
         let palette_texture = &mut self.palette_texture;
 
-        self.ppu_debug.consume(|buffers| {
+        self.debug.ppu.consume(|buffers| {
             pattern_texture
                 .update(None, &buffers.patterns, PPUDebug::PATTERN_WIDTH * 3)
                 .unwrap();
@@ -199,6 +382,7 @@ impl Compositor {
         let _ = self
             .debug_canvas
             .copy(&palette_texture, None, rect::Rect::new(0, 440, 256, 32));
+        self.draw_debug_text_at(480);
         self.debug_canvas.present();
     }
 
@@ -206,15 +390,86 @@ impl Compositor {
         self.debug_canvas.clear();
         let waveform_texture = &mut self.waveform_texture;
 
-        self.apu_debug.consume(|waveforms| {
+        self.debug.apu.consume(|waveforms| {
             waveform_texture
                 .update(None, waveforms, APUDebug::WAVEFORM_WIDTH * 3)
                 .unwrap()
         });
 
-        let _ = self
-            .debug_canvas
-            .copy(&waveform_texture, None, rect::Rect::new(0, 0, 256, 160));
+        let _ = self.debug_canvas.copy(
+            &waveform_texture,
+            None,
+            rect::Rect::new(
+                0,
+                0,
+                APUDebug::WAVEFORM_WIDTH as u32,
+                APUDebug::WAVEFORM_HEIGHT as u32,
+            ),
+        );
+        self.draw_debug_text_at(APUDebug::WAVEFORM_HEIGHT as i32 + LINE_HEIGHT);
         self.debug_canvas.present();
     }
+
+    fn render_text_debug(&mut self) {
+        self.debug_canvas.clear();
+        self.draw_debug_text();
+        self.debug_canvas.present();
+    }
+
+    // The heatmap goes under its labels.
+    fn render_heatmap_debug(&mut self) {
+        self.debug_canvas.clear();
+        let top = self.draw_debug_text() + LINE_HEIGHT;
+
+        let heatmap_texture = &mut self.heatmap_texture;
+        self.debug.heatmap.consume(|image| {
+            heatmap_texture
+                .update(None, image, heatmap::WIDTH * 3)
+                .unwrap()
+        });
+        let _ = self.debug_canvas.copy(
+            heatmap_texture,
+            None,
+            rect::Rect::new(0, top, heatmap::WIDTH as u32, heatmap::HEIGHT as u32),
+        );
+        self.debug_canvas.present();
+    }
+
+    // Likewise the PRG-ROM banks.
+    fn render_bank_debug(&mut self) {
+        self.debug_canvas.clear();
+        let top = self.draw_debug_text() + LINE_HEIGHT;
+
+        let bank_texture = &mut self.bank_texture;
+        self.debug.banks.consume(|image| {
+            bank_texture
+                .update(None, image, bankview::WIDTH * 3)
+                .unwrap()
+        });
+        let _ = self.debug_canvas.copy(
+            bank_texture,
+            None,
+            rect::Rect::new(0, top, bankview::WIDTH as u32, bankview::HEIGHT as u32),
+        );
+        self.debug_canvas.present();
+    }
+
+    // Returns the height drawn.
+    fn draw_debug_text(&mut self) -> i32 {
+        self.draw_debug_text_at(0)
+    }
+
+    fn draw_debug_text_at(&mut self, top: i32) -> i32 {
+        let lines = self.debug.text.consume(|lines| lines.clone());
+        for (ix, line) in lines.iter().enumerate() {
+            draw_text(
+                &mut self.debug_canvas,
+                0,
+                top + ix as i32 * LINE_HEIGHT,
+                1,
+                line,
+            );
+        }
+        lines.len() as i32 * LINE_HEIGHT
+    }
 }
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use dirs;
+use serde::{Deserialize, Serialize};
+
+use nes::emulator::config::Config as EmulatorConfig;
+use nes::emulator::controller::{Button, KeyMap};
+use nes::emulator::io::event::Key;
+use nes::emulator::io::palette::PaletteKind;
+
+// User settings, stored as TOML in the platform config directory.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub joy1: Bindings,
+    // Other players have no keys unless they're set here.
+    pub joy2: Option<Bindings>,
+    pub joy3: Option<Bindings>,
+    pub joy4: Option<Bindings>,
+    // Plugs in a Four Score, so players 3 and 4 are read too.
+    pub four_score: bool,
+    pub rewind: RewindConfig,
+    pub fast_forward: FastForwardConfig,
+    pub turbo: TurboConfig,
+    // Colour palette, cycled through with F4.
+    pub palette: PaletteKind,
+    pub thread: ThreadConfig,
+    pub display: DisplayConfig,
+    // Passed on to the NES itself, under [emulator].
+    pub emulator: EmulatorConfig,
+}
+
+// Keys `Controller::handle_event` always acts on, so a button given one would also, say, take a
+// screenshot.  Shift and Control pick between saving and loading a state with a digit.
+const HOTKEYS: [Key; 26] = [
+    Key::Escape,
+    Key::Tab,
+    Key::Return,
+    Key::Backquote,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::Num0,
+    Key::Backspace,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F11,
+    Key::F12,
+    Key::Shift,
+    Key::Control,
+];
+
+impl Config {
+    // Every player's keys, numbered from 1.
+    fn players(&self) -> Vec<(usize, &Bindings)> {
+        let others = [&self.joy2, &self.joy3, &self.joy4];
+        let others = others
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, joy)| joy.as_ref().map(|bindings| (ix + 2, bindings)));
+        Some((1, &self.joy1)).into_iter().chain(others).collect()
+    }
+
+    // Keys used for something other than a button, and what for.
+    fn hotkeys(&self) -> Vec<(Key, &'static str)> {
+        let configured = [
+            (self.rewind.key, "rewind"),
+            (self.fast_forward.key, "fast forward"),
+            (self.turbo.a, "turbo A"),
+            (self.turbo.b, "turbo B"),
+        ];
+        HOTKEYS
+            .iter()
+            .map(|key| (*key, "a hotkey"))
+            .chain(configured.iter().cloned())
+            .collect()
+    }
+
+    // Whether `key` is used for anything besides player 1's buttons, so they can't be given it.
+    pub fn joy1_key_taken(&self, key: Key) -> bool {
+        let others = self.players().into_iter().skip(1);
+        self.hotkeys().iter().any(|(used, _)| *used == key)
+            || others
+                .flat_map(|(_, bindings)| bindings.to_keymap())
+                .any(|(used, _)| used == key)
+    }
+
+    // Fails if a key is given to two buttons, which `Bindings::to_keymap` can't keep, or to a
+    // button and a hotkey.
+    pub fn check_keys(&self) -> Result<(), String> {
+        let mut uses: HashMap<Key, String> = HashMap::new();
+        for (player, bindings) in self.players() {
+            for button in Bindings::BUTTONS.iter() {
+                let key = bindings.key(*button);
+                let what = player_button(player, *button);
+                if let Some(first) = uses.insert(key, what.clone()) {
+                    return Err(format!("{:?} is bound to both {} and {}", key, first, what));
+                }
+            }
+        }
+        for (key, what) in self.hotkeys() {
+            if let Some(button) = uses.get(&key) {
+                return Err(format!(
+                    "{:?} is bound to {} but used for {}",
+                    key, button, what
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn player_button(player: usize, button: Button) -> String {
+    format!("player {}'s {:?}", player, button)
+}
+
+// Scheduling for the emulator thread, to cut down on frame pacing jitter on a busy machine.
+// Raising the priority usually needs extra permissions, e.g. CAP_SYS_NICE on Linux.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThreadConfig {
+    pub high_priority: bool,
+    // Pins the thread to this CPU core, counting from 0.
+    pub core: Option<usize>,
+}
+
+// How frames are shown on monitors which refresh faster than the NES runs.  The refresh rate is
+// asked of the display unless it's set here, for drivers which report it wrongly.  `interpolate`
+// blends each frame in over the one before rather than repeating it, which is smoother but shows
+// everything a frame later.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    pub refresh_hz: Option<u32>,
+    pub interpolate: bool,
+}
+
+// Holding `key` steps back through snapshots taken every `interval_frames` frames, keeping at
+// most `capacity` of them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RewindConfig {
+    pub key: Key,
+    pub interval_frames: u64,
+    pub capacity: usize,
+}
+
+impl Default for RewindConfig {
+    fn default() -> RewindConfig {
+        RewindConfig {
+            key: Key::R,
+            interval_frames: 2,
+            capacity: 300,
+        }
+    }
+}
+
+// Which key drives each button on a controller.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bindings {
+    pub a: Key,
+    pub b: Key,
+    pub select: Key,
+    pub start: Key,
+    pub up: Key,
+    pub down: Key,
+    pub left: Key,
+    pub right: Key,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        Bindings {
+            a: Key::Z,
+            b: Key::X,
+            select: Key::S,
+            start: Key::A,
+            up: Key::Up,
+            down: Key::Down,
+            left: Key::Left,
+            right: Key::Right,
+        }
+    }
+}
+
+// Holding `key` runs the emulator at `multiplier` times normal speed.  With `frame_skip`, any
+// speed above normal only draws as many frames as the display shows, which is what usually limits
+// how fast it can go.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FastForwardConfig {
+    pub key: Key,
+    pub multiplier: u64,
+    pub frame_skip: bool,
+}
+
+impl Default for FastForwardConfig {
+    fn default() -> FastForwardConfig {
+        FastForwardConfig {
+            key: Key::F,
+            multiplier: 4,
+            frame_skip: true,
+        }
+    }
+}
+
+// Holding a turbo key presses and releases its button every `rate_frames` frames.  A rate of 0
+// turns turbo off.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TurboConfig {
+    pub a: Key,
+    pub b: Key,
+    pub rate_frames: u32,
+}
+
+impl Default for TurboConfig {
+    fn default() -> TurboConfig {
+        TurboConfig {
+            a: Key::C,
+            b: Key::V,
+            rate_frames: 2,
+        }
+    }
+}
+
+impl TurboConfig {
+    pub fn to_keymap(&self) -> KeyMap {
+        [(self.a, Button::A), (self.b, Button::B)]
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    // Turbo buttons whose keys are held, as a bitmask in strobe order.
+    pub fn held_buttons(&self, key_states: &HashMap<Key, bool>) -> u8 {
+        let held = |key| *key_states.get(&key).unwrap_or(&false);
+        (if held(self.a) { 0x01 } else { 0 }) | (if held(self.b) { 0x02 } else { 0 })
+    }
+}
+
+impl Bindings {
+    // The order buttons are prompted for when remapping, which matches the controller's strobe
+    // order.
+    pub const BUTTONS: [Button; 8] = [
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+    ];
+
+    pub fn set(&mut self, button: Button, key: Key) {
+        match button {
+            Button::A => self.a = key,
+            Button::B => self.b = key,
+            Button::Select => self.select = key,
+            Button::Start => self.start = key,
+            Button::Up => self.up = key,
+            Button::Down => self.down = key,
+            Button::Left => self.left = key,
+            Button::Right => self.right = key,
+        }
+    }
+
+    pub fn key(&self, button: Button) -> Key {
+        match button {
+            Button::A => self.a,
+            Button::B => self.b,
+            Button::Select => self.select,
+            Button::Start => self.start,
+            Button::Up => self.up,
+            Button::Down => self.down,
+            Button::Left => self.left,
+            Button::Right => self.right,
+        }
+    }
+
+    // Same as `held_buttons`, for players who might have no keys.
+    pub fn held_buttons_for(bindings: &Option<Bindings>, key_states: &HashMap<Key, bool>) -> u8 {
+        match bindings {
+            Some(bindings) => bindings.held_buttons(key_states),
+            None => 0,
+        }
+    }
+
+    // Buttons whose keys are held, as a bitmask in `BUTTONS` order.
+    pub fn held_buttons(&self, key_states: &HashMap<Key, bool>) -> u8 {
+        Bindings::BUTTONS
+            .iter()
+            .enumerate()
+            .filter(|(_, button)| *key_states.get(&self.key(**button)).unwrap_or(&false))
+            .fold(0, |acc, (ix, _)| acc | (1 << ix))
+    }
+
+    // Each key maps to one button, so this relies on `Config::check_keys` having passed.
+    pub fn to_keymap(&self) -> KeyMap {
+        let keymap: KeyMap = Bindings::BUTTONS
+            .iter()
+            .map(|button| (self.key(*button), *button))
+            .collect();
+        debug_assert_eq!(
+            keymap.len(),
+            Bindings::BUTTONS.len(),
+            "A key is bound twice"
+        );
+        keymap
+    }
+}
+
+pub fn config_dir() -> PathBuf {
+    let mut path = match dirs::config_dir() {
+        Some(path) => path,
+        None => panic!("Couldn't get config dir!"),
+    };
+
+    path.push("nes");
+    path
+}
+
+fn config_file_path() -> PathBuf {
+    let mut path = config_dir();
+    path.push("config.toml");
+    path
+}
+
+// Missing config is not an error, we just fall back to defaults.
+pub fn load_config() -> Result<Config, String> {
+    let mut file = match File::open(config_file_path()) {
+        Err(_) => return Ok(Config::default()),
+        Ok(file) => file,
+    };
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|e| e.to_string())?;
+    let config: Config = toml::from_str(&contents).map_err(|e| e.to_string())?;
+    config.check_keys()?;
+    Ok(config)
+}
+
+pub fn save_config(config: &Config) -> Result<(), String> {
+    create_dir_all(config_dir()).map_err(|e| e.to_string())?;
+    let contents = toml::to_string(config).map_err(|e| e.to_string())?;
+    let mut file = File::create(config_file_path()).map_err(|e| e.to_string())?;
+    file.write_all(contents.as_bytes())
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
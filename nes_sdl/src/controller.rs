@@ -1,50 +1,104 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::fs::{create_dir_all, File};
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{create_dir_all, read, read_to_string, write, File};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use dirs;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Serializer;
 
+use nes::emulator::apu::debug::APUDebug;
+use nes::emulator::cheats::Cheats;
+use nes::emulator::cpu::Jam;
+use nes::emulator::eventlog::{Severity, Subsystem, DEFAULT_CAPACITY};
+use nes::emulator::hexdump::HexDumpOptions;
+use nes::emulator::ines::ROM;
+use nes::emulator::inputscript::InputScript;
 use nes::emulator::io::event::{Event, EventHandler, Key};
+use nes::emulator::io::palette::Palette;
 use nes::emulator::io::{Screen, SimpleAudioOut};
-use nes::emulator::state::SaveState;
-use nes::emulator::{NES, NES_MASTER_CLOCK_HZ};
+use nes::emulator::memory::{ChrBanks, MapperRef};
+use nes::emulator::metadata::Metadata;
+use nes::emulator::movie::{FrameInput, Movie, MovieMode, MovieSession};
+use nes::emulator::ppu::debug::PPUDebug;
+use nes::emulator::state::{NESState, SaveState};
+use nes::emulator::{Region, NES, NES_MASTER_CLOCK_HZ};
 
+use crate::abloop::{ABLoop, LoopEnd};
+use crate::bankview::BankView;
+use crate::chrview::ChrBankView;
+use crate::command::{CommandReceiver, CommandResult, EmulatorCommand};
+use crate::config::{config_dir, save_config, Bindings, Config};
+use crate::debugsession::{DebugSession, SessionStore};
+use crate::eventlog::EventLogView;
+use crate::games::{default_games_path, load_games, name_from_path, save_games, Game};
+use crate::heatmap::HeatmapView;
+use crate::memview::MemoryView;
+use crate::mixerview::MixerView;
+use crate::netplay::{Connection, Netplay};
+use crate::pausemenu::{MenuAction, PauseMenu};
 use crate::portal::Portal;
+use crate::rewind::Rewind;
+use crate::romdb::{default_romdb_path, load_romdb};
+use crate::rumble::{load_rumble_triggers, Rumble, RumbleSender, RumbleWatcher};
+use crate::screenshot::{default_screenshot_dir, save_screenshot, save_tile_export};
+use crate::scripting::Script;
+use crate::spritetrace::SpriteTraceView;
+use crate::threads::ThreadStatus;
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+// Instructions shown either side of where the CPU jammed.
+const JAM_CONTEXT: usize = 4;
+
+// Scroll splits listed under the nametable view.  Wavy effects change the scroll every line.
+const MAX_SCROLL_SPLITS: usize = 4;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum DebugMode {
     OFF,
     PPU,
+    SPRITES,
     APU,
+    MEMORY,
+    HEATMAP,
+    BANKS,
+    EVENTS,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct EmulatorState {
+    // The game as shown in the window title.
+    pub title: String,
+    // The pause menu's lines while it's open.
+    pub menu: Vec<String>,
     pub is_running: bool,
     pub is_tracing: bool,
     pub target_hz: u64,
     pub debug_mode: DebugMode,
+    pub show_osd: bool,
+    pub thread: ThreadStatus,
 }
 
 impl EmulatorState {
     pub fn new() -> EmulatorState {
         EmulatorState {
+            title: String::new(),
+            menu: vec![],
             is_running: true,
             is_tracing: false,
             target_hz: NES_MASTER_CLOCK_HZ,
             debug_mode: DebugMode::APU,
+            show_osd: true,
+            thread: ThreadStatus::default(),
         }
     }
 }
 
-fn save_state_dir() -> PathBuf {
+pub fn default_save_state_dir() -> PathBuf {
     let mut path = match dirs::data_dir() {
         Some(path) => path,
         None => panic!("Couldn't get data dir!"),
@@ -55,20 +109,24 @@ fn save_state_dir() -> PathBuf {
     path
 }
 
-fn save_state_file_path(name: &str) -> PathBuf {
-    let mut state_file_path = save_state_dir();
+// Audio is only worth playing from half speed up.  Faster than normal, it's cut into stretches
+// which keep their pitch.
+fn audio_enabled_at(hz: u64) -> bool {
+    hz >= 10_000_000
+}
+
+fn save_state_file_path(dir: &Path, name: &str) -> PathBuf {
+    let mut state_file_path = dir.to_path_buf();
     state_file_path.push(format!("{}.gz", name));
     state_file_path
 }
 
-fn save_state(nes: &mut NES, name: &str) -> Result<(), String> {
-    create_dir_all(save_state_dir()).map_err(|e| e.to_string())?;
-    let state_file = File::create(save_state_file_path(name)).map_err(|e| e.to_string())?;
+fn save_state(state: &NESState, dir: &Path, name: &str) -> Result<(), String> {
+    create_dir_all(dir).map_err(|e| e.to_string())?;
+    let state_file = File::create(save_state_file_path(dir, name)).map_err(|e| e.to_string())?;
     let gzip = GzEncoder::new(state_file, Compression::best());
     let mut serializer = Serializer::new(gzip);
 
-    let state = nes.freeze();
-
     state
         .serialize(&mut serializer)
         .map_err(|e| e.to_string())?;
@@ -81,36 +139,214 @@ fn save_state(nes: &mut NES, name: &str) -> Result<(), String> {
     Ok(())
 }
 
-pub fn load_state(nes: &mut NES, name: &str) -> Result<(), String> {
-    let state_file = File::open(save_state_file_path(name)).map_err(|e| e.to_string())?;
+pub fn load_state(nes: &mut NES, dir: &Path, name: &str) -> Result<(), String> {
+    let state_file = File::open(save_state_file_path(dir, name)).map_err(|e| e.to_string())?;
     let gzip = GzDecoder::new(state_file);
-    let state = serde_json::from_reader(gzip).map_err(|e| e.to_string())?;
+    let state: NESState = serde_json::from_reader(gzip).map_err(|e| e.to_string())?;
+    check_metadata(state.metadata.as_ref(), &nes.metadata(), "State")?;
     nes.hydrate(state);
     Ok(())
 }
 
+// Refuses anything made with a different ROM or settings, and warns about a different emulator
+// version.  Files from before metadata was recorded get the benefit of the doubt.
+fn check_metadata(
+    recorded: Option<&Metadata>,
+    current: &Metadata,
+    what: &str,
+) -> Result<(), String> {
+    let recorded = match recorded {
+        Some(recorded) => recorded,
+        None => return Ok(()),
+    };
+    let warnings = recorded
+        .check(current)
+        .map_err(|mismatch| format!("{} was {}", what, mismatch))?;
+    for warning in warnings {
+        println!("Warning: {} was {}", what, warning);
+    }
+    Ok(())
+}
+
+pub fn load_movie(path: &str) -> Result<Movie, String> {
+    let text = read_to_string(path).map_err(|e| e.to_string())?;
+    Movie::from_fm2(&text)
+}
+
+// Input scripts play back the same way as movies.
+pub fn load_script(path: &str, rom_name: &str, pal: bool) -> Result<Movie, String> {
+    let text = read_to_string(path).map_err(|e| e.to_string())?;
+    Ok(InputScript::parse(&text)?.to_movie(rom_name, pal))
+}
+
+pub fn save_movie(path: &str, movie: &Movie) -> Result<(), String> {
+    write(path, movie.to_fm2()).map_err(|e| e.to_string())
+}
+
+// Returns None if there's no save yet.
+fn load_battery_save(path: &Path) -> Result<Option<Vec<u8>>, String> {
+    match read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Cheats live in the config directory as `cheats/<rom name>.cht`.  Returns None if there are none.
+fn load_cheats(rom_name: &str) -> Result<Option<Cheats>, String> {
+    let mut path = config_dir();
+    path.push("cheats");
+    path.push(format!("{}.cht", rom_name));
+    match read_to_string(&path) {
+        Ok(text) => Cheats::from_text(&text).map(Some),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn save_battery_save(path: &Path, data: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    write(path, data).map_err(|e| e.to_string())
+}
+
 pub struct Controller {
     nes: NES,
+    config: Config,
+    // Either built in or loaded from a .pal file.  The palette kind is applied on top.
+    base_palette: Palette,
+    pause_menu: Option<PauseMenu>,
+    rewind: Rewind,
+    ab_loop: Option<ABLoop>,
+    fast_forward_resume_hz: Option<u64>,
+    // Speed to go back to when a `Resume` command arrives.
+    pause_resume_hz: Option<u64>,
+    commands: Option<CommandReceiver>,
+    rumble: Option<RumbleSender>,
+    rumble_watcher: RumbleWatcher,
+    movie: Option<MovieSession>,
+    movie_path: String,
+    // Resets wait for a frame boundary during movies and netplay.
+    reset_pending: bool,
+    netplay: Option<Netplay>,
     rom_name: Option<String>,
+    save_dir: PathBuf,
+    // Name of a save state waiting for the CPU to finish what it's doing.
+    pending_save: Option<String>,
+    // Where battery-backed PRG-RAM is kept between runs, once it's been loaded.
+    battery_path: Option<PathBuf>,
+    trace_path: String,
+    trace_on_exit: bool,
+    // Stop once the PPU reaches this frame.
+    frame_limit: Option<u64>,
     screen: Rc<RefCell<Screen>>,
     audio_output: Rc<RefCell<SimpleAudioOut>>,
     key_states: HashMap<Key, bool>,
+    memory_view: MemoryView,
+    sprite_trace_view: SpriteTraceView,
+    heatmap_view: HeatmapView,
+    event_log_view: EventLogView,
+    bank_view: BankView,
+    mixer_view: MixerView,
+    chr_view: ChrBankView,
+    // Instructions already reported for writing to VRAM while rendering, if checking.
+    vram_write_pcs: Option<BTreeSet<u16>>,
+    // The jam last reported, so it's only printed once.
+    reported_jam: Option<Jam>,
+    // Where to keep the debugger's setup, and how it was when the game started.
+    session_store: Option<(SessionStore, DebugSession)>,
+    script: Option<Script>,
+    // From the command line, for games loaded later on too.
+    region_override: Option<Region>,
+    cartridge_swaps: u64,
     state_portal: Portal<EmulatorState>,
 }
 
 impl Controller {
     pub fn new(
-        nes: NES,
+        mut nes: NES,
+        config: Config,
         screen: Rc<RefCell<Screen>>,
         audio_output: Rc<RefCell<SimpleAudioOut>>,
         state_portal: Portal<EmulatorState>,
     ) -> Controller {
+        let region = nes.region();
+        let revision = region.revision();
+        println!(
+            "Emulating {:?} hardware, with an {} CPU and {} PPU",
+            region, revision.cpu, revision.ppu
+        );
+        state_portal.consume(|state| state.target_hz = region.master_clock_hz());
+        audio_output.borrow_mut().set_region(region);
+        nes.joy1.borrow_mut().set_keymap(config.joy1.to_keymap());
+        let others = [
+            (&nes.joy2, &config.joy2),
+            (&nes.joy3, &config.joy3),
+            (&nes.joy4, &config.joy4),
+        ];
+        for (joy, bindings) in others.iter() {
+            if let Some(bindings) = bindings {
+                joy.borrow_mut().set_keymap(bindings.to_keymap());
+            }
+        }
+        nes.set_four_score(config.four_score);
+        nes.joy1
+            .borrow_mut()
+            .set_turbo(config.turbo.to_keymap(), config.turbo.rate_frames);
+        let base_palette = match config.emulator.palette_file {
+            None => Palette::default(),
+            Some(ref path) => Palette::from_file(path).unwrap_or_else(|cause| {
+                println!("Couldn't load palette {}: {}", path.display(), cause);
+                Palette::default()
+            }),
+        };
+        screen
+            .borrow_mut()
+            .set_palette(base_palette.filtered(config.palette));
+        let rewind = Rewind::new(config.rewind.interval_frames, config.rewind.capacity);
+        // Cheap enough to leave on, and only useful if it was on before something went wrong.
+        nes.enable_event_log(DEFAULT_CAPACITY);
+
         Controller {
             nes,
+            config,
+            base_palette,
+            pause_menu: None,
+            rewind,
+            ab_loop: None,
+            fast_forward_resume_hz: None,
+            pause_resume_hz: None,
+            commands: None,
+            rumble: None,
+            rumble_watcher: RumbleWatcher::new(vec![]),
+            movie: None,
+            movie_path: String::new(),
+            reset_pending: false,
+            netplay: None,
             rom_name: None,
+            save_dir: default_save_state_dir(),
+            pending_save: None,
+            battery_path: None,
+            trace_path: String::from("./cpu.trace"),
+            trace_on_exit: false,
+            frame_limit: None,
             screen,
             audio_output,
             key_states: HashMap::new(),
+            memory_view: MemoryView::default(),
+            sprite_trace_view: SpriteTraceView::default(),
+            heatmap_view: HeatmapView::default(),
+            event_log_view: EventLogView::default(),
+            bank_view: BankView,
+            mixer_view: MixerView,
+            chr_view: ChrBankView::default(),
+            vram_write_pcs: None,
+            reported_jam: None,
+            session_store: None,
+            script: None,
+            region_override: None,
+            cartridge_swaps: 0,
             state_portal,
         }
     }
@@ -119,8 +355,488 @@ impl Controller {
         self.nes.tick()
     }
 
-    pub fn tick_multi(&mut self, ticks: u32) -> u64 {
-        self.nes.tick_multi(ticks)
+    pub fn run_cycles(&mut self, cycles: u64) -> u64 {
+        let (elapsed, frames) = if self.netplay.is_some() {
+            (self.tick_netplay_frame(), 1)
+        } else if self.movie.is_some() {
+            (self.tick_movie_frame(), 1)
+        } else {
+            let result = self.nes.run_cycles_within_frame(cycles);
+            self.check_ab_loop(result.breakpoint);
+            self.check_breakpoint(result.breakpoint);
+            (result.cycles, result.frames_completed)
+        };
+        self.run_script(frames);
+        self.write_pending_save();
+        self.check_rumble();
+        self.report_vram_writes();
+        self.report_jam();
+        self.check_frame_limit();
+        elapsed
+    }
+
+    // Runs a whole frame, for when there's no need to pace against real time.
+    pub fn run_frame(&mut self) -> u64 {
+        let elapsed = if self.netplay.is_some() {
+            self.tick_netplay_frame()
+        } else if self.movie.is_some() {
+            self.tick_movie_frame()
+        } else {
+            let elapsed = self.nes.run_frame();
+            self.check_ab_loop(None);
+            elapsed
+        };
+        self.run_script(1);
+        self.write_pending_save();
+        self.check_rumble();
+        self.report_vram_writes();
+        self.report_jam();
+        self.check_frame_limit();
+        elapsed
+    }
+
+    pub fn set_frame_limit(&mut self, frames: u64) {
+        self.frame_limit = Some(self.frame_count() + frames);
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.nes.ppu.borrow().stats().frame_count
+    }
+
+    fn check_frame_limit(&mut self) {
+        if let Some(limit) = self.frame_limit {
+            if self.frame_count() >= limit {
+                println!("Reached frame {}, stopping.", limit);
+                self.frame_limit = None;
+                self.stop();
+            }
+        }
+    }
+
+    pub fn set_save_dir(&mut self, dir: PathBuf) {
+        self.save_dir = dir;
+    }
+
+    // Restores the cart's battery save, if it has one, and writes it back when the emulator
+    // stops.  Call after setting the ROM name and save directory, and before the first tick.
+    pub fn use_battery_save(&mut self) {
+        if self.nes.battery_ram().is_none() {
+            return;
+        }
+
+        let mut path = self.save_dir.clone();
+        path.push(format!("{}.sav", self.rom_name()));
+        match load_battery_save(&path) {
+            Err(cause) => println!("Failed to load battery save: {}", cause),
+            Ok(None) => (),
+            Ok(Some(data)) => {
+                self.nes.load_battery_ram(&data);
+                println!("Loaded battery save from {}", path.display());
+            }
+        };
+        self.battery_path = Some(path);
+    }
+
+    pub fn use_cheats(&mut self) {
+        match load_cheats(&self.rom_name()) {
+            Err(cause) => println!("Failed to load cheats: {}", cause),
+            Ok(None) => (),
+            Ok(Some(cheats)) => {
+                println!("Loaded {} cheats.  F3 toggles them.", cheats.cheats().len());
+                *self.nes.cheats.borrow_mut() = cheats;
+            }
+        };
+    }
+
+    pub fn use_script(&mut self, path: &str) {
+        match Script::load(path, &mut self.nes) {
+            Err(cause) => println!("Failed to load script {}: {}", path, cause),
+            Ok(script) => {
+                println!("Loaded script {}", path);
+                self.script = Some(script);
+            }
+        }
+    }
+
+    // Calls back into the script for the accesses it's watching, then for the end of each frame.
+    // A script which fails is stopped rather than left to fail again every frame.
+    fn run_script(&mut self, frames: u64) {
+        let script = match self.script {
+            None => return,
+            Some(ref mut script) => script,
+        };
+        let accesses = self.nes.take_memory_accesses();
+        let mut result = script.memory_accesses(&mut self.nes, &accesses);
+        if frames > 0 && result.is_ok() {
+            let frame = self.nes.ppu.borrow().stats().frame_count;
+            result = script.end_frame(&mut self.nes, frame);
+        }
+        if let Err(cause) = result {
+            let message = format!("Stopped script {}: {}", script.path(), cause);
+            script.unload(&mut self.nes);
+            self.script = None;
+            self.report(Severity::Error, Subsystem::Frontend, message);
+        }
+    }
+
+    // Problems go in the event log as well, so they can be seen alongside what led up to them.
+    fn report(&self, severity: Severity, subsystem: Subsystem, message: String) {
+        println!("{}", message);
+        self.nes.log_event(severity, subsystem, message);
+    }
+
+    pub fn check_vram_writes(&mut self) {
+        self.nes.enable_vram_write_check();
+        self.vram_write_pcs = Some(BTreeSet::new());
+    }
+
+    // A game with the bug usually makes the same write every frame, so each instruction is only
+    // reported the first time.
+    fn report_vram_writes(&mut self) {
+        let pcs = match self.vram_write_pcs {
+            None => return,
+            Some(ref mut pcs) => pcs,
+        };
+        for write in self.nes.take_rendering_writes() {
+            if pcs.insert(write.pc) {
+                println!("{}", write);
+                self.nes
+                    .log_event(Severity::Warning, Subsystem::Ppu, write.to_string());
+            }
+        }
+    }
+
+    // The game carries on after a reset or loading a state, so a later jam is reported again.
+    fn report_jam(&mut self) {
+        let jam = self.nes.cpu_jam();
+        if jam == self.reported_jam {
+            return;
+        }
+        self.reported_jam = jam;
+        let jam = match jam {
+            None => return,
+            Some(jam) => jam,
+        };
+
+        if jam.is_kil() {
+            println!("{}.  Reset to continue.", jam);
+        } else {
+            println!(
+                "CPU stopped on undocumented opcode ${:02X} at ${:04X}.  Setting \
+                 illegal_opcodes under [emulator] in the config may help.",
+                jam.opcode, jam.pc
+            );
+        }
+        for instruction in self
+            .nes
+            .disassemble_around(jam.pc, JAM_CONTEXT, JAM_CONTEXT)
+        {
+            let marker = if instruction.address == jam.pc {
+                ">"
+            } else {
+                " "
+            };
+            println!("{} {:04X}  {}", marker, instruction.address, instruction);
+        }
+    }
+
+    // F5 marks A, F6 marks B at the current frame and starts looping, F7 stops.  Restoring states
+    // would knock a movie out of sync, so there's no looping while one is running.
+    fn mark_loop_start(&mut self) -> CommandResult {
+        if self.movie.is_some() {
+            return Err(String::from("Can't loop during a movie"));
+        }
+        if self.netplay.is_some() {
+            return Err(String::from("Can't loop during netplay"));
+        }
+        self.clear_loop();
+        self.ab_loop = Some(ABLoop::new(&mut self.nes));
+        println!("Loop: A marked at frame {}", self.frame_count());
+        Ok(())
+    }
+
+    fn mark_loop_end(&mut self, end: Option<LoopEnd>) -> CommandResult {
+        let ab_loop = match self.ab_loop {
+            None => return Err(String::from("Mark loop point A first")),
+            Some(ref mut ab_loop) => ab_loop,
+        };
+        match end {
+            None => ab_loop.end_here(&mut self.nes),
+            Some(end) => ab_loop.set_end(&mut self.nes, end),
+        }
+        println!("Loop: B set to {:?}", ab_loop.end());
+        self.state_loaded();
+        Ok(())
+    }
+
+    fn clear_loop(&mut self) {
+        if let Some(mut ab_loop) = self.ab_loop.take() {
+            ab_loop.clear(&mut self.nes);
+            println!("Loop: off after {} loops", ab_loop.loops());
+        }
+    }
+
+    fn check_ab_loop(&mut self, breakpoint: Option<u16>) {
+        let looped = match self.ab_loop {
+            None => false,
+            Some(ref mut ab_loop) => ab_loop.after_run(&mut self.nes, breakpoint),
+        };
+        if looped {
+            self.state_loaded();
+        }
+    }
+
+    fn check_breakpoint(&mut self, breakpoint: Option<u16>) {
+        match breakpoint {
+            Some(address) if self.memory_view.breakpoints().contains(&address) => {
+                self.stop_fast_forward();
+                self.set_target_hz(0);
+                println!(
+                    "Breakpoint: ${:04X}.  The number keys set a speed to carry on.",
+                    address
+                );
+            }
+            _ => (),
+        }
+    }
+
+    // Times round the A/B loop, if there is one.
+    pub fn loop_count(&self) -> Option<u64> {
+        self.ab_loop.as_ref().map(|ab_loop| ab_loop.loops())
+    }
+
+    fn toggle_cheats(&mut self) {
+        let on = self.nes.cheats.borrow_mut().toggle_all();
+        println!("Cheats: {}", if on { "ON" } else { "OFF" });
+    }
+
+    // The state is written once the CPU reaches the end of its current instruction, which is
+    // usually straight away.
+    fn save_state(&mut self, name: String) {
+        self.pending_save = Some(name);
+        self.nes.request_save_state();
+        self.write_pending_save();
+    }
+
+    // The other player's game would carry on from where it was.
+    fn check_can_load_state(&self) -> Result<(), String> {
+        match self.netplay {
+            Some(_) => Err(String::from("Can't load states during netplay")),
+            None => Ok(()),
+        }
+    }
+
+    // Anything tracking the old timeline is out of date.
+    fn state_loaded(&mut self) {
+        self.pending_save = None;
+        self.rewind.clear();
+        self.rumble_watcher.reset();
+    }
+
+    fn write_pending_save(&mut self) {
+        if self.pending_save.is_none() {
+            return;
+        }
+        if let Some(state) = self.nes.take_save_state() {
+            let name = self.pending_save.take().unwrap();
+            if let Err(cause) = save_state(&state, &self.save_dir, &name) {
+                let message = format!("Failed to save state: {}", cause);
+                self.report(Severity::Error, Subsystem::Frontend, message);
+            }
+        }
+    }
+
+    // A save state still waiting for an OAM DMA to finish would otherwise be lost.  The copy
+    // carries on whatever the CPU is doing, even if it has jammed, so this always gets there.
+    fn finish_pending_save(&mut self) {
+        while self.pending_save.is_some() && !self.nes.at_instruction_boundary() {
+            self.nes.tick();
+        }
+        self.write_pending_save();
+    }
+
+    fn cycle_palette(&mut self) {
+        self.config.palette = self.config.palette.next();
+        self.screen
+            .borrow_mut()
+            .set_palette(self.base_palette.filtered(self.config.palette));
+        println!("Palette: {:?}", self.config.palette);
+        if let Err(cause) = save_config(&self.config) {
+            println!("Failed to save config: {}", cause);
+        }
+    }
+
+    fn write_battery_save(&mut self) {
+        let path = match self.battery_path {
+            None => return,
+            Some(ref path) => path,
+        };
+        if let Some(data) = self.nes.battery_ram() {
+            if let Err(cause) = save_battery_save(path, &data) {
+                let message = format!("Failed to write battery save: {}", cause);
+                self.report(Severity::Error, Subsystem::Frontend, message);
+            }
+        }
+    }
+
+    // Dump the trace buffer to `path` when the emulator stops, as well as on request.
+    pub fn trace_to(&mut self, path: &str) {
+        self.trace_path = String::from(path);
+        self.trace_on_exit = true;
+    }
+
+    // How many instructions the trace buffer holds.  Bigger buffers trace further back but use
+    // more memory, 24 bytes per instruction.
+    pub fn set_trace_size(&mut self, instructions: usize) {
+        self.nes.cpu.borrow_mut().set_trace_capacity(instructions);
+    }
+
+    // Movies must start from power-on, so this should be called before the first tick.
+    pub fn record_movie(&mut self, path: &str) {
+        let pal = self.nes.region() == Region::PAL;
+        let movie = Movie::new(&self.rom_name(), pal);
+        self.movie = Some(MovieSession::record(&mut self.nes, movie));
+        self.movie_path = String::from(path);
+        println!("Recording movie to {}", path);
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        match self.movie {
+            Some(ref session) => session.mode() == MovieMode::Playback,
+            None => false,
+        }
+    }
+
+    pub fn play_movie(&mut self, path: &str, movie: Movie) -> Result<(), String> {
+        check_metadata(movie.metadata.as_ref(), &self.nes.metadata(), "Movie")?;
+        self.movie = Some(MovieSession::play(&mut self.nes, movie));
+        self.movie_path = String::from(path);
+        println!("Playing movie from {}", path);
+        Ok(())
+    }
+
+    pub fn turbo_rate(&self) -> u32 {
+        self.nes.joy1.borrow().turbo_rate()
+    }
+
+    // The controllers ignore the keyboard during movies and netplay, so turbo is applied here
+    // instead.
+    fn held_joy1(&self) -> u8 {
+        let mut joy1 = self.config.joy1.held_buttons(&self.key_states);
+        if self.nes.joy1.borrow().turbo_pressed() {
+            joy1 |= self.config.turbo.held_buttons(&self.key_states);
+        }
+        joy1
+    }
+
+    // Runs a whole frame with input from the movie, or from the keyboard if recording.
+    fn tick_movie_frame(&mut self) -> u64 {
+        let live = FrameInput {
+            reset: self.reset_pending,
+            joy1: self.held_joy1(),
+            joy2: Bindings::held_buttons_for(&self.config.joy2, &self.key_states),
+            joy3: Bindings::held_buttons_for(&self.config.joy3, &self.key_states),
+            joy4: Bindings::held_buttons_for(&self.config.joy4, &self.key_states),
+        };
+        self.reset_pending = false;
+
+        let session = match self.movie.as_mut() {
+            None => return 0,
+            Some(session) => session,
+        };
+        let cycles = session.step(&mut self.nes, live);
+
+        if session.is_finished() {
+            println!("Movie finished, returning control to the keyboard.");
+            self.end_movie();
+        }
+        cycles
+    }
+
+    // Plays against whoever is on the other end of `connection`.  Like a movie, this starts from a
+    // blank cart with no cheats, and this side's player is driven by the player 1 keys.
+    pub fn start_netplay(&mut self, connection: Connection, delay: u32) {
+        match Netplay::start(connection, &self.nes.metadata(), delay) {
+            Err(cause) => {
+                println!("Couldn't start netplay: {}", cause);
+                self.stop();
+            }
+            Ok(netplay) => {
+                println!(
+                    "Playing as player {} with {} frames of input delay",
+                    netplay.player(),
+                    netplay.delay()
+                );
+                for joy in self.nes.joypads().iter() {
+                    joy.borrow_mut().set_keyboard_enabled(false);
+                }
+                self.netplay = Some(netplay);
+            }
+        }
+    }
+
+    // Runs a whole frame once both players' input for it is in.
+    fn tick_netplay_frame(&mut self) -> u64 {
+        let (buttons, reset) = (self.held_joy1(), self.reset_pending);
+        self.reset_pending = false;
+        let netplay = match self.netplay.as_mut() {
+            None => return 0,
+            Some(netplay) => netplay,
+        };
+        let input = match netplay.exchange(buttons, reset) {
+            Err(cause) => {
+                let message = format!("{}.  Carrying on alone.", cause);
+                self.report(Severity::Error, Subsystem::Frontend, message);
+                self.end_netplay();
+                return 0;
+            }
+            Ok(input) => input,
+        };
+
+        if input.reset {
+            self.nes.reset();
+        }
+        self.nes.joy1.borrow_mut().set_buttons(input.joy1);
+        self.nes.joy2.borrow_mut().set_buttons(input.joy2);
+        self.nes.run_frame()
+    }
+
+    fn end_netplay(&mut self) {
+        if self.netplay.take().is_none() {
+            return;
+        }
+        for joy in self.nes.joypads().iter() {
+            let mut joy = joy.borrow_mut();
+            joy.set_buttons(0);
+            joy.set_keyboard_enabled(true);
+        }
+    }
+
+    fn end_movie(&mut self) {
+        let session = match self.movie.take() {
+            None => return,
+            Some(session) => session,
+        };
+        let mode = session.mode();
+        let movie = session.finish(&mut self.nes);
+
+        if mode == MovieMode::Recording {
+            match save_movie(&self.movie_path, &movie) {
+                Err(cause) => println!("Failed to save movie: {}", cause),
+                Ok(_) => println!(
+                    "Saved {} frames of input to {}",
+                    movie.frames.len(),
+                    self.movie_path
+                ),
+            };
+        }
+    }
+
+    fn rom_name(&self) -> String {
+        match self.rom_name {
+            Some(ref name) => name.clone(),
+            None => String::from("unknown"),
+        }
     }
 
     pub fn is_running(&self) -> bool {
@@ -135,10 +851,191 @@ impl Controller {
         self.state_portal.consume(|state| state.is_tracing = on)
     }
 
+    pub fn set_command_receiver(&mut self, commands: CommandReceiver) {
+        self.commands = Some(commands);
+    }
+
+    pub fn set_rumble_sender(&mut self, rumble: RumbleSender) {
+        self.rumble = Some(rumble);
+    }
+
+    // Shakes the gamepad, if there is one that can.  For hooks which want to react to the game.
+    pub fn rumble(&self, rumble: Rumble) {
+        if let Some(ref sender) = self.rumble {
+            sender.rumble(rumble);
+        }
+    }
+
+    pub fn use_rumble_triggers(&mut self) {
+        match load_rumble_triggers(&self.rom_name()) {
+            Err(cause) => println!("Failed to load rumble triggers: {}", cause),
+            Ok(triggers) => self.rumble_watcher = RumbleWatcher::new(triggers),
+        };
+    }
+
+    fn check_rumble(&mut self) {
+        if self.rumble_watcher.is_empty() {
+            return;
+        }
+        // Memory jumps about while rewinding, which isn't the game doing anything.
+        if self.is_rewinding() {
+            self.rumble_watcher.reset();
+            return;
+        }
+        let nes = &self.nes;
+        if let Some(rumble) = self.rumble_watcher.check(|address| nes.peek(address)) {
+            self.rumble(rumble);
+        }
+    }
+
+    // Runs any commands sent from other threads.  Call between frames.
+    pub fn process_commands(&mut self) {
+        if let Some(commands) = self.commands.take() {
+            commands.drain(|command| self.handle_command(command));
+            self.commands = Some(commands);
+        }
+    }
+
+    fn handle_command(&mut self, command: EmulatorCommand) -> CommandResult {
+        match command {
+            EmulatorCommand::Pause => {
+                if self.pause_resume_hz.is_none() {
+                    self.stop_fast_forward();
+                    self.pause_resume_hz = Some(self.target_hz());
+                    self.set_target_hz(0);
+                }
+                Ok(())
+            }
+            EmulatorCommand::Resume => {
+                if let Some(hz) = self.pause_resume_hz.take() {
+                    self.set_target_hz(hz);
+                }
+                Ok(())
+            }
+            EmulatorCommand::SaveState(name) => {
+                self.save_state(name);
+                Ok(())
+            }
+            EmulatorCommand::LoadState(name) => {
+                self.check_can_load_state()?;
+                load_state(&mut self.nes, &self.save_dir, &name)?;
+                self.state_loaded();
+                Ok(())
+            }
+            EmulatorCommand::Screenshot => self.take_screenshot().map(|_| ()),
+            EmulatorCommand::ExportTiles => self.take_tile_export().map(|_| ()),
+            EmulatorCommand::LoadRom(path) => self.load_rom(&path),
+            EmulatorCommand::MarkLoopStart => self.mark_loop_start(),
+            EmulatorCommand::MarkLoopEnd(end) => self.mark_loop_end(Some(end)),
+            EmulatorCommand::ClearLoop => {
+                self.clear_loop();
+                Ok(())
+            }
+            EmulatorCommand::SetTargetHz(hz) => {
+                self.pause_resume_hz = None;
+                self.fast_forward_resume_hz = None;
+                self.set_target_hz(hz);
+                Ok(())
+            }
+        }
+    }
+
     pub fn set_rom_name(&mut self, name: &str) {
         self.rom_name = Some(String::from(name));
     }
 
+    pub fn set_region_override(&mut self, region: Option<Region>) {
+        self.region_override = region;
+    }
+
+    // Goes up each time a game is loaded, so anything holding on to the old CPU, PPU or APU knows
+    // to fetch them again.
+    pub fn cartridge_swaps(&self) -> u64 {
+        self.cartridge_swaps
+    }
+
+    // Switches to another game, as if it had been started from the command line.  The old game's
+    // battery save and debugger session are written out first.  Not allowed during movies or
+    // netplay, which only make sense for the game they started with.
+    pub fn load_rom(&mut self, path: &Path) -> CommandResult {
+        if self.movie.is_some() || self.netplay.is_some() {
+            return Err(String::from(
+                "Can't switch games while a movie or netplay is running",
+            ));
+        }
+        let data = read(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        let rom = ROM::parse(data)?;
+        let games_path = default_games_path();
+        let mut games = load_games(&games_path)?;
+        let romdb = load_romdb(&default_romdb_path())?;
+        let path = path.to_string_lossy().to_string();
+        let name = name_from_path(&path);
+        let game = Game::new(&romdb, &games, &name, rom);
+        // Find out whether the new game can run before letting go of anything from the old one.
+        game.rom.get_mapper()?;
+
+        self.finish_pending_save();
+        self.write_battery_save();
+        self.save_debug_session();
+        self.clear_loop();
+        self.rewind.clear();
+
+        let region = self.nes.region();
+        let config = game.emulator_config(self.region_override, &self.config.emulator);
+        self.nes.load_rom_with_config(game.rom, &config)?;
+        if let Some(gain) = game.expansion_gain {
+            self.nes.set_expansion_audio_gain(gain);
+        }
+        if self.nes.region() != region {
+            self.audio_output.borrow_mut().set_region(self.nes.region());
+            self.set_target_hz(self.nes.region().master_clock_hz());
+        }
+        if let Some(palette) = game.settings.palette {
+            self.config.palette = palette;
+            self.screen
+                .borrow_mut()
+                .set_palette(self.base_palette.filtered(palette));
+        }
+        if self.is_tracing() {
+            self.nes.cpu.borrow_mut().start_tracing();
+        }
+        self.reported_jam = None;
+        if let Some(ref mut pcs) = self.vram_write_pcs {
+            pcs.clear();
+        }
+
+        // Everything kept per game.
+        self.set_rom_name(&name);
+        self.state_portal
+            .consume(|state| state.title = name.clone());
+        self.battery_path = None;
+        self.use_battery_save();
+        if game.settings.cheats_enabled() {
+            self.use_cheats();
+        }
+        self.use_rumble_triggers();
+        if let Some(script) = self.script.take() {
+            self.use_script(script.path());
+        }
+        self.memory_view = MemoryView::default();
+        self.sprite_trace_view = SpriteTraceView::default();
+        self.heatmap_view = HeatmapView::default();
+        self.event_log_view = EventLogView::default();
+        self.use_debug_session(
+            SessionStore::new(games_path.clone(), game.key),
+            game.settings.debug,
+        );
+        self.set_debug_mode(self.debug_mode());
+
+        games.add_recent(&path);
+        if let Err(cause) = save_games(&games_path, &games) {
+            println!("Couldn't save recent ROMs: {}", cause);
+        }
+        self.cartridge_swaps += 1;
+        println!("Switched to {}", name);
+        Ok(())
+    }
+
     pub fn start(&mut self) {
         self.state_portal.consume(|state| {
             state.is_running = true;
@@ -148,21 +1045,105 @@ impl Controller {
     }
 
     pub fn stop(&mut self) {
+        self.finish_pending_save();
+        self.end_movie();
+        self.write_battery_save();
+        self.save_debug_session();
+        if self.trace_on_exit {
+            self.dump_trace();
+        }
         self.state_portal.consume(|state| {
             state.is_running = false;
         });
     }
 
     pub fn reset(&mut self) {
+        // Resets need to land on a frame boundary to be replayable, or to reach the other player.
+        if self.movie.is_some() || self.netplay.is_some() {
+            self.reset_pending = true;
+            return;
+        }
+
         self.nes.reset();
+        self.rewind.clear();
+    }
+
+    // Rewinding is unavailable during movies since it would desync the input log, and likewise
+    // during netplay since the other player's game can't follow.
+    pub fn is_rewinding(&self) -> bool {
+        self.pause_menu.is_none()
+            && self.movie.is_none()
+            && self.netplay.is_none()
+            && *self
+                .key_states
+                .get(&self.config.rewind.key)
+                .unwrap_or(&false)
+    }
+
+    // Jumps back to the previous snapshot.  The caller still needs to run the emulator for a bit
+    // afterwards so that the restored frame actually gets drawn.
+    pub fn rewind_step(&mut self) {
+        self.rewind.step_back(&mut self.nes);
+    }
+
+    pub fn record_rewind(&mut self) {
+        self.rewind.record(&mut self.nes);
+    }
+
+    // Speed to run at this frame.  Rewinding always plays back at normal speed, even if paused or
+    // fast-forwarding, otherwise the restored frames would never be drawn.
+    pub fn frame_target_hz(&self) -> u64 {
+        if self.is_rewinding() {
+            self.nes.region().master_clock_hz()
+        } else {
+            self.target_hz()
+        }
     }
 
+    fn start_fast_forward(&mut self) {
+        if self.fast_forward_resume_hz.is_some() {
+            return;
+        }
+
+        let resume_hz = self.target_hz();
+        let base_hz = self.nes.region().master_clock_hz();
+        self.fast_forward_resume_hz = Some(resume_hz);
+        self.set_target_hz(base_hz * self.config.fast_forward.multiplier);
+    }
+
+    fn stop_fast_forward(&mut self) {
+        if let Some(resume_hz) = self.fast_forward_resume_hz.take() {
+            self.set_target_hz(resume_hz);
+        }
+    }
+
+    // Clamped to the configured top speed.
     pub fn set_target_hz(&mut self, hz: u64) {
+        let hz = hz.min(self.nes.region().master_clock_hz() * self.nes.config().max_speed);
         self.state_portal.consume(|state| state.target_hz = hz);
         self.screen.borrow_mut().set_double_buffering(hz > 200_000);
-        self.audio_output
-            .borrow_mut()
-            .set_enabled(hz >= 10_000_000 && hz <= 50_000_000);
+
+        // Rounded to a whole multiple of normal speed, and never below it.
+        let base_hz = self.nes.region().master_clock_hz();
+        let speedup = ((hz + base_hz / 2) / base_hz).max(1) as u32;
+        let frame_skip = if self.config.fast_forward.frame_skip {
+            speedup - 1
+        } else {
+            0
+        };
+        self.screen.borrow_mut().set_frame_skip(frame_skip);
+
+        let mut audio_output = self.audio_output.borrow_mut();
+        audio_output.set_enabled(audio_enabled_at(hz));
+        audio_output.set_speedup(speedup);
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.nes.config().sample_rate
+    }
+
+    pub fn is_audio_enabled(&self) -> bool {
+        audio_enabled_at(self.target_hz())
     }
 
     pub fn target_hz(&self) -> u64 {
@@ -173,20 +1154,180 @@ impl Controller {
         self.state_portal.consume(|state| state.debug_mode)
     }
 
-    pub fn cycle_debug_mode(&self) {
-        self.state_portal.consume(|state| {
-            state.debug_mode = match state.debug_mode {
-                DebugMode::OFF => DebugMode::PPU,
-                DebugMode::PPU => DebugMode::APU,
-                DebugMode::APU => DebugMode::OFF,
-            };
-        });
+    pub fn cycle_debug_mode(&mut self) {
+        let mode = match self.debug_mode() {
+            DebugMode::OFF => DebugMode::PPU,
+            DebugMode::PPU => DebugMode::SPRITES,
+            DebugMode::SPRITES => DebugMode::APU,
+            DebugMode::APU => DebugMode::MEMORY,
+            DebugMode::MEMORY => DebugMode::HEATMAP,
+            DebugMode::HEATMAP => DebugMode::BANKS,
+            DebugMode::BANKS => DebugMode::EVENTS,
+            DebugMode::EVENTS => DebugMode::OFF,
+        };
+        self.set_debug_mode(mode);
+    }
+
+    fn set_debug_mode(&mut self, mode: DebugMode) {
+        self.state_portal.consume(|state| state.debug_mode = mode);
+
+        // Only trace sprite evaluation while someone is looking at it.
+        let scanline = match mode {
+            DebugMode::SPRITES => Some(self.sprite_trace_view.scanline()),
+            _ => None,
+        };
+        self.nes
+            .ppu
+            .borrow_mut()
+            .set_sprite_trace_scanline(scanline);
+
+        // Counting accesses costs a little on every one, so likewise for the heatmap.
+        if mode == DebugMode::HEATMAP {
+            self.nes.enable_heatmap(self.heatmap_view.frames());
+            self.nes.enable_bus_stats();
+        } else {
+            self.nes.disable_heatmap();
+            self.nes.disable_bus_stats();
+        }
+        if mode == DebugMode::BANKS {
+            self.nes.enable_code_data_log();
+        } else {
+            self.nes.disable_code_data_log();
+        }
+
+        // The pattern viewer follows CHR bank switches.
+        if mode == DebugMode::PPU {
+            self.nes.enable_chr_bank_tracking();
+        } else {
+            self.nes.disable_chr_bank_tracking();
+        }
+        self.chr_view = ChrBankView::default();
+    }
+
+    // Puts the debugger back how it was left last time, and saves it again when the emulator
+    // stops.
+    pub fn use_debug_session(&mut self, store: SessionStore, session: Option<DebugSession>) {
+        if let Some(session) = session {
+            self.memory_view = session.memory;
+            self.sprite_trace_view = session.sprites;
+            self.heatmap_view = session.heatmap;
+            self.event_log_view = session.events;
+            for address in self.memory_view.breakpoints().iter() {
+                self.nes.add_breakpoint(*address);
+            }
+            if let Some(mode) = session.mode {
+                self.set_debug_mode(mode);
+            }
+        }
+        self.session_store = Some((store, self.debug_session()));
+    }
+
+    fn debug_session(&self) -> DebugSession {
+        DebugSession {
+            mode: Some(self.debug_mode()),
+            memory: self.memory_view.clone(),
+            sprites: self.sprite_trace_view.clone(),
+            heatmap: self.heatmap_view.clone(),
+            events: self.event_log_view.clone(),
+        }
+    }
+
+    // Left alone if nothing changed, so games which were never debugged don't get an entry.
+    fn save_debug_session(&self) {
+        let session = self.debug_session();
+        if let Some((ref store, ref initial)) = self.session_store {
+            if session != *initial {
+                if let Err(cause) = store.save(&session) {
+                    println!("Couldn't save debugger session: {}", cause);
+                }
+            }
+        }
+    }
+
+    pub fn memory_view_lines(&self) -> Vec<String> {
+        self.memory_view.lines(&self.nes)
+    }
+
+    pub fn sprite_trace_lines(&self) -> Vec<String> {
+        self.sprite_trace_view.lines(&self.nes)
+    }
+
+    pub fn heatmap_lines(&self) -> Vec<String> {
+        self.heatmap_view.lines(&self.nes)
+    }
+
+    pub fn render_heatmap(&self, buffer: &mut [u8]) {
+        self.heatmap_view.render(&self.nes, buffer);
+    }
+
+    pub fn event_log_lines(&self) -> Vec<String> {
+        self.event_log_view.lines(&self.nes)
+    }
+
+    pub fn bank_lines(&self) -> Vec<String> {
+        self.bank_view.lines(&self.nes)
+    }
+
+    pub fn render_banks(&self, buffer: &mut [u8]) {
+        self.bank_view.render(&self.nes, buffer);
+    }
+
+    pub fn mixer_lines(&self) -> Vec<String> {
+        self.mixer_view.lines(&self.nes)
+    }
+
+    pub fn chr_bank_lines(&self) -> Vec<String> {
+        self.chr_view.lines(&self.nes)
+    }
+
+    // Where each scroll split of the last frame was drawn from, to go with their outlines in the
+    // nametable view.
+    pub fn scroll_split_lines(&self) -> Vec<String> {
+        let splits = self.nes.ppu.borrow().scroll_splits();
+        let mut lines: Vec<String> = splits
+            .iter()
+            .take(MAX_SCROLL_SPLITS)
+            .map(|split| {
+                format!(
+                    "LINES {:3}-{:3} FROM {:3},{:3}",
+                    split.first_line,
+                    split.first_line + split.lines - 1,
+                    split.x,
+                    split.y
+                )
+            })
+            .collect();
+        if splits.len() > MAX_SCROLL_SPLITS {
+            lines.push(format!("+{} MORE SPLITS", splits.len() - MAX_SCROLL_SPLITS));
+        }
+        lines
+    }
+
+    // The banks to show in the pattern viewer instead of whatever is mapped now, if any.
+    // Renderers for the PPU and APU debug views.  Loading a game replaces both, so these need
+    // fetching again afterwards.
+    pub fn debug_renderers(&self) -> (PPUDebug, APUDebug) {
+        (
+            PPUDebug::new(self.nes.ppu.clone()),
+            APUDebug::new(self.nes.apu.clone()),
+        )
+    }
+
+    pub fn frozen_chr(&self) -> Option<(MapperRef, ChrBanks)> {
+        self.chr_view
+            .frozen()
+            .map(|banks| (self.nes.mapper.clone(), banks))
+    }
+
+    pub fn toggle_osd(&self) {
+        self.state_portal
+            .consume(|state| state.show_osd = !state.show_osd);
     }
 
     pub fn dump_trace(&mut self) {
         if self.is_tracing() {
-            println!("Flushing CPU trace buffer to ./cpu.trace");
-            let mut trace_file = match File::create("./cpu.trace") {
+            println!("Flushing CPU trace buffer to {}", self.trace_path);
+            let mut trace_file = match File::create(&self.trace_path) {
                 Err(_) => panic!("Couldn't open trace file"),
                 Ok(f) => f,
             };
@@ -195,52 +1336,134 @@ impl Controller {
         }
     }
 
-    pub fn debug_print(&mut self, start: u16, len: u16) {
-        println!("CPU Memory starting from ${:X}", start);
-        for ix in 0..len {
-            print!("{:02X} ", self.nes.cpu.borrow_mut().load_memory(start + ix));
+    pub fn debug_print(&self, start: u16, len: usize) {
+        print!(
+            "{}",
+            self.nes.hexdump(start, len, &HexDumpOptions::default())
+        );
+    }
+
+    pub fn screenshot(&mut self) {
+        match self.take_screenshot() {
+            Err(cause) => println!("Failed to save screenshot: {}", cause),
+            Ok(path) => println!("Saved screenshot to {}", path.display()),
+        };
+    }
+
+    pub fn export_tiles(&mut self) {
+        match self.take_tile_export() {
+            Err(cause) => println!("Failed to export tiles: {}", cause),
+            Ok(paths) => {
+                for path in paths {
+                    println!("Exported {}", path.display());
+                }
+            }
+        };
+    }
+
+    fn take_tile_export(&self) -> Result<Vec<PathBuf>, String> {
+        let mut ppu_debug = PPUDebug::new(self.nes.ppu.clone());
+        save_tile_export(&mut ppu_debug, &default_screenshot_dir(), &self.rom_name())
+    }
+
+    fn take_screenshot(&self) -> Result<PathBuf, String> {
+        let screen = self.screen.borrow();
+        save_screenshot(&screen, &default_screenshot_dir(), &self.rom_name())
+    }
+
+    // Pauses the game and shows the menu over it.  The joypads stop listening to the keyboard
+    // until it's closed, so keys pressed in the menu don't press buttons too.  Movies and netplay
+    // already drive the joypads themselves.
+    pub fn open_pause_menu(&mut self) {
+        if self.movie.is_some() || self.netplay.is_some() {
+            println!("The menu isn't available during a movie or netplay");
+            return;
+        }
+        self.stop_fast_forward();
+        let menu = PauseMenu::new(self.target_hz());
+        self.set_target_hz(0);
+        for joy in self.nes.joypads().iter() {
+            let mut joy = joy.borrow_mut();
+            joy.set_buttons(0);
+            joy.set_keyboard_enabled(false);
+        }
+        self.show_menu(Some(menu));
+    }
+
+    fn close_pause_menu(&mut self, menu: PauseMenu) {
+        for joy in self.nes.joypads().iter() {
+            joy.borrow_mut().set_keyboard_enabled(true);
+        }
+        self.set_target_hz(menu.resume_hz());
+        self.show_menu(None);
+    }
+
+    fn show_menu(&mut self, menu: Option<PauseMenu>) {
+        let lines = menu.as_ref().map(|menu| menu.lines()).unwrap_or_default();
+        self.state_portal.consume(|state| state.menu = lines);
+        self.pause_menu = menu;
+    }
+
+    fn handle_menu_key(&mut self, key: Key) {
+        let mut menu = match self.pause_menu.take() {
+            None => return,
+            Some(menu) => menu,
+        };
+        match menu.handle_key(key, &self.config) {
+            MenuAction::None => self.show_menu(Some(menu)),
+            MenuAction::Resume => self.close_pause_menu(menu),
+            MenuAction::Remapped(bindings) => {
+                self.nes.joy1.borrow_mut().set_keymap(bindings.to_keymap());
+                self.config.joy1 = bindings;
+                match save_config(&self.config) {
+                    Err(cause) => println!("Failed to save config: {}", cause),
+                    Ok(_) => println!("Saved new controls."),
+                };
+                self.show_menu(Some(menu));
+            }
         }
-        println!("");
     }
 
     fn handle_num_key(&mut self, num: u8) {
         let shift_modifier = *self.key_states.get(&Key::Shift).unwrap_or(&false);
         let ctrl_modifier = *self.key_states.get(&Key::Control).unwrap_or(&false);
-        let rom_name = match self.rom_name {
-            Some(ref name) => name.clone(),
-            None => String::from("unknown"),
-        };
-        let state_name = format!("{}.{}", rom_name, num);
+        let state_name = format!("{}.{}", self.rom_name(), num);
 
         if shift_modifier {
             // Save state.
             println!("Saving state: {}", state_name);
-            match save_state(&mut self.nes, &state_name) {
-                Err(cause) => println!("Failed to save state: {}", cause),
-                Ok(_) => (),
-            };
+            self.save_state(state_name);
         } else if ctrl_modifier {
             // Load state.
             println!("Loading state: {}", state_name);
-            match load_state(&mut self.nes, &state_name) {
-                Err(cause) => println!("Failed to save state: {}", cause),
-                Ok(_) => (),
+            let loaded = self
+                .check_can_load_state()
+                .and_then(|_| load_state(&mut self.nes, &self.save_dir, &state_name));
+            match loaded {
+                Err(cause) => self.report(
+                    Severity::Error,
+                    Subsystem::Frontend,
+                    format!("Failed to load state: {}", cause),
+                ),
+                Ok(_) => self.state_loaded(),
             };
         } else {
             // Set speed.
+            let base_hz = self.nes.region().master_clock_hz();
             let target_hz = match num {
                 1 => 0,          // Paused.
                 2 => 20_000,     // Scanlines.
                 3 => 200_000,    // Frames.
                 4 => 2_000_000,  // 1/10 Slow-mo.
                 5 => 10_000_000, // 1/2 Slow-mo.
-                6 => NES_MASTER_CLOCK_HZ,
-                7 => NES_MASTER_CLOCK_HZ * 2,
-                8 => NES_MASTER_CLOCK_HZ * 3,
-                9 => NES_MASTER_CLOCK_HZ * 4,
-                0 => NES_MASTER_CLOCK_HZ * 5,
+                6 => base_hz,
+                7 => base_hz * 2,
+                8 => base_hz * 3,
+                9 => base_hz * 4,
+                0 => base_hz * 5,
                 _ => panic!("Unexpected num key: {}", num),
             };
+            self.fast_forward_resume_hz = None;
             self.set_target_hz(target_hz);
         }
     }
@@ -251,6 +1474,52 @@ impl EventHandler for Controller {
         match event {
             Event::KeyDown(key) => {
                 self.key_states.insert(key, true);
+                if self.pause_menu.is_some() {
+                    self.handle_menu_key(key);
+                    return;
+                }
+
+                if key == self.config.fast_forward.key {
+                    self.start_fast_forward();
+                    return;
+                }
+
+                // The memory editor takes hex digits, so they can't load states while it's open.
+                if self.debug_mode() == DebugMode::MEMORY
+                    && self.memory_view.handle_key(key, &mut self.nes)
+                {
+                    return;
+                }
+                if self.debug_mode() == DebugMode::SPRITES
+                    && self.sprite_trace_view.handle_key(key, &mut self.nes)
+                {
+                    return;
+                }
+                if self.debug_mode() == DebugMode::HEATMAP
+                    && self.heatmap_view.handle_key(key, &mut self.nes)
+                {
+                    return;
+                }
+                if self.debug_mode() == DebugMode::BANKS
+                    && self.bank_view.handle_key(key, &mut self.nes)
+                {
+                    return;
+                }
+                if self.debug_mode() == DebugMode::EVENTS
+                    && self.event_log_view.handle_key(key, &mut self.nes)
+                {
+                    return;
+                }
+                let shift = *self.key_states.get(&Key::Shift).unwrap_or(&false);
+                if self.debug_mode() == DebugMode::APU
+                    && self.mixer_view.handle_key(key, shift, &mut self.nes)
+                {
+                    return;
+                }
+                if self.debug_mode() == DebugMode::PPU && self.chr_view.handle_key(key, &self.nes) {
+                    return;
+                }
+
                 match key {
                     Key::Escape => self.stop(),
                     Key::Tab => {
@@ -281,11 +1550,31 @@ impl EventHandler for Controller {
                     Key::Num9 => self.handle_num_key(9),
                     Key::Num0 => self.handle_num_key(0),
                     Key::Backspace => self.reset(),
+                    Key::F1 => self.open_pause_menu(),
+                    Key::F2 => self.toggle_osd(),
+                    Key::F3 => self.toggle_cheats(),
+                    Key::F4 => self.cycle_palette(),
+                    Key::F5 => {
+                        if let Err(cause) = self.mark_loop_start() {
+                            println!("{}", cause);
+                        }
+                    }
+                    Key::F6 => {
+                        if let Err(cause) = self.mark_loop_end(None) {
+                            println!("{}", cause);
+                        }
+                    }
+                    Key::F7 => self.clear_loop(),
+                    Key::F11 => self.export_tiles(),
+                    Key::F12 => self.screenshot(),
                     _ => (),
                 };
             }
             Event::KeyUp(key) => {
                 self.key_states.insert(key, false);
+                if key == self.config.fast_forward.key {
+                    self.stop_fast_forward();
+                }
             }
         };
     }
@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::controller::DebugMode;
+use crate::eventlog::EventLogView;
+use crate::games::{load_games, save_games};
+use crate::heatmap::HeatmapView;
+use crate::memview::MemoryView;
+use crate::spritetrace::SpriteTraceView;
+
+// How the debugger was left for one game: the debug view that was open, where each view was, and
+// the memory view's watches and breakpoints.  It goes in the game's settings when the emulator
+// stops, and is put back the next time the game starts.
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugSession {
+    pub mode: Option<DebugMode>,
+    pub memory: MemoryView,
+    pub sprites: SpriteTraceView,
+    pub heatmap: HeatmapView,
+    pub events: EventLogView,
+}
+
+// Where in games.toml a game's session is kept.
+pub struct SessionStore {
+    path: PathBuf,
+    key: String,
+}
+
+impl SessionStore {
+    pub fn new(path: PathBuf, key: String) -> SessionStore {
+        SessionStore { path, key }
+    }
+
+    // The file is read again first, since a second emulator may have written it since.
+    pub fn save(&self, session: &DebugSession) -> Result<(), String> {
+        let mut games = load_games(&self.path)?;
+        games.settings_mut(&self.key).debug = Some(session.clone());
+        save_games(&self.path, &games)
+    }
+}
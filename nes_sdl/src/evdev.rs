@@ -0,0 +1,160 @@
+use std::fs::File;
+use std::io::Read;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use nes::emulator::io::event::{Event, Key};
+
+use crate::inputqueue::TimedEvent;
+
+// Reads a keyboard straight from a Linux input device such as /dev/input/event0, for when there's
+// no SDL window to take key presses from.  The device is grabbed, so keys meant for the emulator
+// don't also end up typed into the console underneath.
+
+const EV_KEY: u16 = 0x01;
+
+// Key event values.  Auto-repeats are dropped, as SDL's are.
+const KEY_UP: i32 = 0;
+const KEY_DOWN: i32 = 1;
+
+// Starts a thread which forwards key presses until the device or the emulator goes away.
+pub fn spawn_keyboard(path: &str, events: Sender<TimedEvent>) -> Result<(), String> {
+    let mut file = File::open(path).map_err(|e| format!("Couldn't open {}: {}", path, e))?;
+    grab(&file)?;
+
+    let _ = thread::spawn(move || {
+        let mut buffer = vec![0; event_size()];
+        while file.read_exact(&mut buffer).is_ok() {
+            let (kind, code, value) = parse_event(&buffer);
+            let event = match (kind, value) {
+                (EV_KEY, KEY_DOWN) => convert_linux_keycode_to_internal(code).map(Event::KeyDown),
+                (EV_KEY, KEY_UP) => convert_linux_keycode_to_internal(code).map(Event::KeyUp),
+                _ => None,
+            };
+            if let Some(event) = event {
+                if events.send(TimedEvent::now(event)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+// A struct input_event is a timestamp, whose size depends on the platform, then the type, code
+// and value.
+fn event_size() -> usize {
+    timeval_size() + 8
+}
+
+fn parse_event(buffer: &[u8]) -> (u16, u16, i32) {
+    let fields = &buffer[timeval_size()..];
+    (
+        u16::from_ne_bytes([fields[0], fields[1]]),
+        u16::from_ne_bytes([fields[2], fields[3]]),
+        i32::from_ne_bytes([fields[4], fields[5], fields[6], fields[7]]),
+    )
+}
+
+#[cfg(unix)]
+fn timeval_size() -> usize {
+    std::mem::size_of::<libc::timeval>()
+}
+
+#[cfg(not(unix))]
+fn timeval_size() -> usize {
+    16
+}
+
+// _IOW('E', 0x90, int)
+#[cfg(target_os = "linux")]
+const EVIOCGRAB: libc::c_ulong = 0x4004_4590;
+
+#[cfg(target_os = "linux")]
+fn grab(file: &File) -> Result<(), String> {
+    use std::os::unix::io::AsRawFd;
+
+    if unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGRAB as _, 1 as libc::c_int) } == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "Couldn't grab input device: {}",
+            std::io::Error::last_os_error()
+        ))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn grab(_file: &File) -> Result<(), String> {
+    Err(String::from("Input devices are only supported on Linux"))
+}
+
+// Codes from linux/input-event-codes.h.
+fn convert_linux_keycode_to_internal(code: u16) -> Option<Key> {
+    match code {
+        1 => Some(Key::Escape),
+        2 => Some(Key::Num1),
+        3 => Some(Key::Num2),
+        4 => Some(Key::Num3),
+        5 => Some(Key::Num4),
+        6 => Some(Key::Num5),
+        7 => Some(Key::Num6),
+        8 => Some(Key::Num7),
+        9 => Some(Key::Num8),
+        10 => Some(Key::Num9),
+        11 => Some(Key::Num0),
+        12 => Some(Key::Minus),
+        13 => Some(Key::Equals),
+        14 => Some(Key::Backspace),
+        15 => Some(Key::Tab),
+        16 => Some(Key::Q),
+        17 => Some(Key::W),
+        18 => Some(Key::E),
+        19 => Some(Key::R),
+        20 => Some(Key::T),
+        21 => Some(Key::Y),
+        22 => Some(Key::U),
+        23 => Some(Key::I),
+        24 => Some(Key::O),
+        25 => Some(Key::P),
+        28 => Some(Key::Return),
+        29 => Some(Key::Control),
+        30 => Some(Key::A),
+        31 => Some(Key::S),
+        32 => Some(Key::D),
+        33 => Some(Key::F),
+        34 => Some(Key::G),
+        35 => Some(Key::H),
+        36 => Some(Key::J),
+        37 => Some(Key::K),
+        38 => Some(Key::L),
+        41 => Some(Key::Backquote),
+        42 => Some(Key::Shift),
+        44 => Some(Key::Z),
+        45 => Some(Key::X),
+        46 => Some(Key::C),
+        47 => Some(Key::V),
+        48 => Some(Key::B),
+        49 => Some(Key::N),
+        50 => Some(Key::M),
+        57 => Some(Key::Space),
+        59 => Some(Key::F1),
+        60 => Some(Key::F2),
+        61 => Some(Key::F3),
+        62 => Some(Key::F4),
+        63 => Some(Key::F5),
+        64 => Some(Key::F6),
+        65 => Some(Key::F7),
+        66 => Some(Key::F8),
+        67 => Some(Key::F9),
+        68 => Some(Key::F10),
+        87 => Some(Key::F11),
+        88 => Some(Key::F12),
+        103 => Some(Key::Up),
+        105 => Some(Key::Left),
+        106 => Some(Key::Right),
+        108 => Some(Key::Down),
+
+        _ => None,
+    }
+}
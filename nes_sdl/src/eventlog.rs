@@ -0,0 +1,143 @@
+use std::fs::File;
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+
+use nes::emulator::eventlog::{EventFilter, LogEvent, Severity, Subsystem};
+use nes::emulator::io::event::Key;
+use nes::emulator::NES;
+
+// Browses the emulator's event log, newest at the bottom.  The whole log can be written out to
+// a file, since the window only has room for the last screenful.
+
+pub const DUMP_PATH: &str = "./events.log";
+
+// As many events as fit in the debug window under the header.
+const MAX_EVENT_LINES: usize = 80;
+
+// The debug font is 4 pixels a character, over 256 pixels.
+const LINE_WIDTH: usize = 64;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventLogView {
+    filter: EventFilter,
+    // How many events back from the newest the bottom line is.
+    #[serde(skip)]
+    scroll: usize,
+}
+
+impl EventLogView {
+    // Up and down scroll one event, -/= a screenful.  S steps through the lowest severity shown,
+    // and C, P, A, M and F show or hide the CPU, PPU, APU, mapper and frontend.  D writes the
+    // log out and X clears it.  Returns whether the key was used.
+    pub fn handle_key(&mut self, key: Key, nes: &mut NES) -> bool {
+        match key {
+            Key::Up => self.scroll += 1,
+            Key::Down => self.scroll = self.scroll.saturating_sub(1),
+            Key::Minus => self.scroll += MAX_EVENT_LINES,
+            Key::Equals => self.scroll = self.scroll.saturating_sub(MAX_EVENT_LINES),
+            Key::S => {
+                let ix = Severity::ALL
+                    .iter()
+                    .position(|severity| *severity == self.filter.min_severity)
+                    .unwrap_or(0);
+                self.filter.min_severity = Severity::ALL[(ix + 1) % Severity::ALL.len()];
+            }
+            Key::C => self.filter.toggle(Subsystem::Cpu),
+            Key::P => self.filter.toggle(Subsystem::Ppu),
+            Key::A => self.filter.toggle(Subsystem::Apu),
+            Key::M => self.filter.toggle(Subsystem::Mapper),
+            Key::F => self.filter.toggle(Subsystem::Frontend),
+            Key::D => match dump(nes, DUMP_PATH) {
+                Err(cause) => println!("Failed to write event log: {}", cause),
+                Ok(count) => println!("Wrote {} events to {}", count, DUMP_PATH),
+            },
+            Key::X => {
+                nes.clear_event_log();
+                self.scroll = 0;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn lines(&self, nes: &NES) -> Vec<String> {
+        let log = match nes.event_log() {
+            None => return vec![String::from("EVENT LOG OFF")],
+            Some(log) => log,
+        };
+
+        let shown: Vec<&str> = Subsystem::ALL
+            .iter()
+            .filter(|subsystem| self.filter.shows(**subsystem))
+            .map(|subsystem| subsystem.name())
+            .collect();
+        let mut lines = vec![
+            format!("{} EVENTS  {} DROPPED", log.len(), log.dropped()),
+            format!(
+                "{} AND UP FROM {}",
+                self.filter.min_severity.name(),
+                if shown.is_empty() {
+                    String::from("NOTHING")
+                } else {
+                    shown.join(" ")
+                }
+            ),
+            format!("{:>6} {:>4} {:>3}", "FRAME", "LINE", "DOT"),
+        ];
+
+        // Long messages wrap, so take events from the newest back until the window is full.
+        let mut events: Vec<Vec<String>> = vec![];
+        let mut used = 0;
+        for event in log.filtered(&self.filter).rev().skip(self.scroll) {
+            let event_lines = event_lines(event);
+            used += event_lines.len();
+            if used > MAX_EVENT_LINES {
+                break;
+            }
+            events.push(event_lines);
+        }
+        for event_lines in events.into_iter().rev() {
+            lines.extend(event_lines);
+        }
+        if self.scroll > 0 {
+            lines.push(format!("{} NEWER", self.scroll));
+        }
+        lines
+    }
+}
+
+fn event_lines(event: &LogEvent) -> Vec<String> {
+    let text = format!(
+        "{:>6} {:>4} {:>3} {} {} {}",
+        event.frame,
+        event.scanline,
+        event.dot,
+        &event.severity.name()[..1],
+        event.subsystem.name(),
+        event.message
+    )
+    .to_uppercase();
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(LINE_WIDTH)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+// Writes every event in the log, whatever the filter.  Returns how many there were.
+fn dump(nes: &NES, path: &str) -> Result<usize, String> {
+    let log = match nes.event_log() {
+        None => return Ok(0),
+        Some(log) => log,
+    };
+    let mut file = File::create(path).map_err(|e| e.to_string())?;
+    if log.dropped() > 0 {
+        writeln!(file, "{} older events dropped", log.dropped()).map_err(|e| e.to_string())?;
+    }
+    for event in log.events() {
+        writeln!(file, "{}", event).map_err(|e| e.to_string())?;
+    }
+    Ok(log.len())
+}
@@ -0,0 +1,47 @@
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Lets every thread know the process is on its way out, so each emulator gets to write its battery
+// save, movie and trace before it goes.  Set by Ctrl-C, SIGTERM, a panic on any thread, or the
+// window closing.  Anything stuck, like waiting for a netplay partner, can be cut short by asking
+// a second time.
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// Call once at startup, before starting any emulators.
+pub fn install() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        request();
+    }));
+    install_signal_handlers();
+}
+
+#[cfg(unix)]
+fn install_signal_handlers() {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_signal_handlers() {}
+
+// Only async-signal-safe calls in here.
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(128 + signal) };
+    }
+}
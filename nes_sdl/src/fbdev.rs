@@ -0,0 +1,268 @@
+use std::fs::{File, OpenOptions};
+
+// Draws frames straight into a Linux framebuffer device such as /dev/fb0, for boards like the
+// Raspberry Pi which run without X, Wayland or a working SDL video driver.  The picture is scaled
+// up by the largest whole number which fits and centred on the screen.
+
+const FRAME_WIDTH: usize = 256;
+const FRAME_HEIGHT: usize = 240;
+
+// Where one colour sits in a pixel, as the driver reports it.
+#[derive(Clone, Copy, Debug, Default)]
+struct Channel {
+    offset: u32,
+    length: u32,
+}
+
+impl Channel {
+    fn pack(self, value: u8) -> u32 {
+        if self.length == 0 {
+            return 0;
+        }
+        (u32::from(value) >> 8u32.saturating_sub(self.length)) << self.offset
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ScreenInfo {
+    width: usize,
+    height: usize,
+    // Bytes from the start of one row to the next, which may include padding.
+    line_length: usize,
+    bytes_per_pixel: usize,
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+}
+
+pub struct Framebuffer {
+    file: File,
+    info: ScreenInfo,
+    scale: usize,
+    left: usize,
+    top: usize,
+    // One row of the screen, reused for each line drawn.
+    row: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn open(path: &str) -> Result<Framebuffer, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| format!("Couldn't open {}: {}", path, e))?;
+        let info = screen_info(&file)?;
+        if !(2..=4).contains(&info.bytes_per_pixel) {
+            return Err(format!(
+                "{} bits per pixel isn't supported",
+                info.bytes_per_pixel * 8
+            ));
+        }
+
+        // Screens smaller than a frame show the top left of it.
+        let scale = (info.width / FRAME_WIDTH)
+            .min(info.height / FRAME_HEIGHT)
+            .max(1);
+        let mut framebuffer = Framebuffer {
+            file,
+            info,
+            scale,
+            left: info.width.saturating_sub(FRAME_WIDTH * scale) / 2,
+            top: info.height.saturating_sub(FRAME_HEIGHT * scale) / 2,
+            row: vec![0; info.line_length],
+        };
+        framebuffer.clear()?;
+        Ok(framebuffer)
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.info.width, self.info.height)
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    fn clear(&mut self) -> Result<(), String> {
+        let blank = vec![0; self.info.line_length];
+        for y in 0..self.info.height {
+            self.write_row(&blank, y)?;
+        }
+        Ok(())
+    }
+
+    // Draws one RGB frame of 256x240.
+    pub fn draw(&mut self, frame: &[u8]) -> Result<(), String> {
+        let info = self.info;
+        let width = FRAME_WIDTH.min(info.width - self.left);
+        let mut row = std::mem::take(&mut self.row);
+        for y in 0..FRAME_HEIGHT {
+            for x in 0..width {
+                let rgb = &frame[(y * FRAME_WIDTH + x) * 3..(y * FRAME_WIDTH + x) * 3 + 3];
+                let pixel =
+                    info.red.pack(rgb[0]) | info.green.pack(rgb[1]) | info.blue.pack(rgb[2]);
+                let bytes = pixel.to_le_bytes();
+                for dx in 0..self.scale {
+                    let screen_x = self.left + x * self.scale + dx;
+                    if screen_x >= info.width {
+                        break;
+                    }
+                    let ix = screen_x * info.bytes_per_pixel;
+                    row[ix..ix + info.bytes_per_pixel]
+                        .copy_from_slice(&bytes[..info.bytes_per_pixel]);
+                }
+            }
+            for dy in 0..self.scale {
+                let screen_y = self.top + y * self.scale + dy;
+                if screen_y >= info.height {
+                    break;
+                }
+                self.write_row(&row, screen_y)?;
+            }
+        }
+        self.row = row;
+        Ok(())
+    }
+
+    fn write_row(&self, row: &[u8], y: usize) -> Result<(), String> {
+        write_at(&self.file, row, (y * self.info.line_length) as u64)
+    }
+}
+
+// Mirrors the kernel's struct fb_bitfield.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct FbBitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+// Mirrors the kernel's struct fb_var_screeninfo.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct FbVarScreeninfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    transp: FbBitfield,
+    nonstd: u32,
+    activate: u32,
+    height: u32,
+    width: u32,
+    accel_flags: u32,
+    pixclock: u32,
+    left_margin: u32,
+    right_margin: u32,
+    upper_margin: u32,
+    lower_margin: u32,
+    hsync_len: u32,
+    vsync_len: u32,
+    sync: u32,
+    vmode: u32,
+    rotate: u32,
+    colorspace: u32,
+    reserved: [u32; 4],
+}
+
+// Mirrors the kernel's struct fb_fix_screeninfo.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct FbFixScreeninfo {
+    id: [u8; 16],
+    smem_start: libc::c_ulong,
+    smem_len: u32,
+    kind: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    line_length: u32,
+    mmio_start: libc::c_ulong,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+}
+
+#[cfg(target_os = "linux")]
+const FBIOGET_VSCREENINFO: libc::c_ulong = 0x4600;
+#[cfg(target_os = "linux")]
+const FBIOGET_FSCREENINFO: libc::c_ulong = 0x4602;
+
+#[cfg(target_os = "linux")]
+fn screen_info(file: &File) -> Result<ScreenInfo, String> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut var = FbVarScreeninfo::default();
+    let mut fix = FbFixScreeninfo::default();
+    unsafe {
+        check(libc::ioctl(
+            file.as_raw_fd(),
+            FBIOGET_VSCREENINFO as _,
+            &mut var,
+        ))?;
+        check(libc::ioctl(
+            file.as_raw_fd(),
+            FBIOGET_FSCREENINFO as _,
+            &mut fix,
+        ))?;
+    }
+
+    let channel = |field: &FbBitfield| Channel {
+        offset: field.offset,
+        length: field.length.min(8),
+    };
+    Ok(ScreenInfo {
+        width: var.xres as usize,
+        height: var.yres as usize,
+        line_length: fix.line_length as usize,
+        bytes_per_pixel: (var.bits_per_pixel as usize).div_ceil(8),
+        red: channel(&var.red),
+        green: channel(&var.green),
+        blue: channel(&var.blue),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn screen_info(_file: &File) -> Result<ScreenInfo, String> {
+    Err(String::from(
+        "Framebuffer devices are only supported on Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn write_at(file: &File, bytes: &[u8], offset: u64) -> Result<(), String> {
+    use std::os::unix::fs::FileExt;
+
+    file.write_all_at(bytes, offset).map_err(|e| e.to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write_at(_file: &File, _bytes: &[u8], _offset: u64) -> Result<(), String> {
+    Err(String::from(
+        "Framebuffer devices are only supported on Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn check(result: libc::c_int) -> Result<(), String> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
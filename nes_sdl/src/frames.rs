@@ -0,0 +1,47 @@
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+// Finished frames go from the emulator thread to the UI thread through a short queue.  The
+// emulator is paced by the audio, so if the UI falls behind it drops frames rather than waiting.
+const QUEUED_FRAMES: usize = 2;
+
+pub struct FrameSender {
+    sender: SyncSender<Box<[u8]>>,
+}
+
+pub struct FrameReceiver {
+    receiver: Receiver<Box<[u8]>>,
+}
+
+pub fn frame_channel() -> (FrameSender, FrameReceiver) {
+    let (sender, receiver) = sync_channel(QUEUED_FRAMES);
+    (FrameSender { sender }, FrameReceiver { receiver })
+}
+
+impl FrameSender {
+    // Returns false if the frame was dropped.
+    pub fn send(&self, frame: &[u8]) -> bool {
+        match self.sender.try_send(frame.to_vec().into_boxed_slice()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+impl FrameReceiver {
+    // Waits up to `timeout` for a frame, then skips ahead to the newest one queued.  None if
+    // nothing arrived, e.g. because the emulator is paused.
+    pub fn latest(&self, timeout: Duration) -> Option<Box<[u8]>> {
+        let first = match self.receiver.recv_timeout(timeout) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => return None,
+            Err(RecvTimeoutError::Disconnected) => {
+                // The emulator thread has gone, so don't spin while the UI winds down.
+                thread::sleep(timeout);
+                return None;
+            }
+        };
+        Some(self.receiver.try_iter().last().unwrap_or(first))
+    }
+}
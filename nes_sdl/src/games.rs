@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use nes::emulator::config::Config as EmulatorConfig;
+use nes::emulator::ines::ROM;
+use nes::emulator::io::palette::PaletteKind;
+use nes::emulator::Region;
+
+use crate::config::{config_dir, Config};
+use crate::debugsession::DebugSession;
+use crate::romdb::{apply_romdb, GameEntry, RomDb};
+
+// The player's own settings for each game, and the ROMs they've played lately.  Unlike the ROM
+// database this is written back every time a game starts, so comments in it are lost.
+
+// Most ROMs kept in the recent list.
+const MAX_RECENT: usize = 10;
+
+// Anything left out falls back to the config file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameSettings {
+    // Header fixes and mapper quirks, applied on top of the ROM database's.  Takes the same keys
+    // as an entry there.
+    #[serde(flatten)]
+    pub header: GameEntry,
+    pub palette: Option<PaletteKind>,
+    pub palette_file: Option<PathBuf>,
+    // False stops the game's cheats file being loaded.
+    pub cheats: Option<bool>,
+    // Written when the emulator stops, if the debugger was touched.
+    pub debug: Option<DebugSession>,
+}
+
+impl GameSettings {
+    // The settings which live in the frontend's config rather than the ROM header.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(palette) = self.palette {
+            config.palette = palette;
+        }
+        if let Some(ref path) = self.palette_file {
+            config.emulator.palette_file = Some(path.clone());
+        }
+    }
+
+    pub fn cheats_enabled(&self) -> bool {
+        self.cheats.unwrap_or(true)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameDb {
+    // Full paths, most recent first.
+    pub recent: Vec<String>,
+    // Keyed by CRC32, the same as the ROM database.
+    pub games: BTreeMap<String, GameSettings>,
+}
+
+impl GameDb {
+    pub fn key(rom: &ROM) -> String {
+        format!("{:08X}", rom.crc32())
+    }
+
+    pub fn lookup(&self, rom: &ROM) -> Option<&GameSettings> {
+        let key = GameDb::key(rom);
+        self.games
+            .iter()
+            .find(|(crc, _)| crc.eq_ignore_ascii_case(&key))
+            .map(|(_, settings)| settings)
+    }
+
+    // Adds an entry for the game if there isn't one.
+    pub fn settings_mut(&mut self, key: &str) -> &mut GameSettings {
+        let key = self
+            .games
+            .keys()
+            .find(|crc| crc.eq_ignore_ascii_case(key))
+            .cloned()
+            .unwrap_or_else(|| key.to_string());
+        self.games.entry(key).or_default()
+    }
+
+    pub fn add_recent(&mut self, rom_path: &str) {
+        let path = Path::new(rom_path)
+            .canonicalize()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|_| rom_path.to_string());
+        self.recent.retain(|recent| *recent != path);
+        self.recent.insert(0, path);
+        self.recent.truncate(MAX_RECENT);
+    }
+}
+
+// A ROM ready to run, along with the player's settings for it.
+pub struct Game {
+    pub rom: ROM,
+    pub settings: GameSettings,
+    pub expansion_gain: Option<f32>,
+    pub expansion_ram: Option<bool>,
+    // Where the game's settings are kept in games.toml.
+    pub key: String,
+}
+
+impl Game {
+    // Known games get their header fixed up from the ROM database, e.g. to pick the right mapper
+    // revision, then from the player's own settings for the game.  Anything still odd about the
+    // dump is reported under `name`, but it's run anyway.
+    pub fn new(romdb: &RomDb, games: &GameDb, name: &str, rom: ROM) -> Game {
+        let rom = apply_romdb(romdb, rom);
+        for problem in rom.diagnostics() {
+            println!("{}: {}", name, problem);
+        }
+        let expansion_gain = romdb.lookup(&rom).and_then(|entry| entry.expansion_gain);
+        let expansion_ram = romdb.lookup(&rom).and_then(|entry| entry.expansion_ram);
+        let key = GameDb::key(&rom);
+        let settings = match games.lookup(&rom) {
+            None => {
+                return Game {
+                    rom,
+                    settings: GameSettings::default(),
+                    expansion_gain,
+                    expansion_ram,
+                    key,
+                }
+            }
+            Some(settings) => settings.clone(),
+        };
+
+        let mut header = rom.header();
+        settings.header.apply(&mut header);
+        Game {
+            rom: rom.with_header(&header),
+            expansion_gain: settings.header.expansion_gain.or(expansion_gain),
+            expansion_ram: settings.header.expansion_ram.or(expansion_ram),
+            settings,
+            key,
+        }
+    }
+
+    // A region given on the command line beats the game's settings, which beat the config file.
+    pub fn emulator_config(
+        &self,
+        region: Option<Region>,
+        config: &EmulatorConfig,
+    ) -> EmulatorConfig {
+        EmulatorConfig {
+            region: region.or(self.settings.header.region).or(config.region),
+            expansion_ram: self.expansion_ram.unwrap_or(config.expansion_ram),
+            ..config.clone()
+        }
+    }
+}
+
+// What saves, cheats and the like for the ROM are named after.
+pub fn name_from_path(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or(String::from("unknown"))
+}
+
+pub fn default_games_path() -> PathBuf {
+    let mut path = config_dir();
+    path.push("games.toml");
+    path
+}
+
+// Nothing has been played yet if there's no file.
+pub fn load_games(path: &Path) -> Result<GameDb, String> {
+    let contents = match read_to_string(path) {
+        Err(_) => return Ok(GameDb::default()),
+        Ok(contents) => contents,
+    };
+    toml::from_str(&contents).map_err(|e| e.to_string())
+}
+
+pub fn save_games(path: &Path, db: &GameDb) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let contents = toml::to_string(db).map_err(|e| e.to_string())?;
+    write(path, contents).map_err(|e| e.to_string())
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+
+use nes::emulator::busstats::Device;
+use nes::emulator::heatmap::AccessHeatmap;
+use nes::emulator::io::event::Key;
+use nes::emulator::NES;
+
+// Shows how often each byte of RAM, and optionally PRG-ROM, was read and written over the last
+// few frames.  Reads light up green and writes red, so bytes which are both show yellow.  Counts
+// go on a log scale, otherwise the stack and a few hot loops drown out everything else.  Below the
+// busiest addresses, the last frame's traffic to each device on the bus, and how many cycles ago
+// each was last touched.
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = PRG_TOP + PRG_ROWS;
+
+// RAM is drawn 64 bytes to a row, with each byte 4 pixels square.
+const RAM_SIZE: usize = 0x800;
+const RAM_COLUMNS: usize = 64;
+const RAM_CELL: usize = 4;
+
+// PRG-ROM, as the CPU sees it at $8000-$FFFF, is one pixel per byte.
+const PRG_START: usize = 0x8000;
+const PRG_SIZE: usize = 0x8000;
+const PRG_ROWS: usize = PRG_SIZE / WIDTH;
+const PRG_TOP: usize = (RAM_SIZE / RAM_COLUMNS) * RAM_CELL + 8;
+
+const DEFAULT_FRAMES: usize = 60;
+const MAX_FRAMES: usize = 3600;
+
+// How many of the busiest RAM addresses to list.
+const HOTTEST: usize = 8;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeatmapView {
+    frames: usize,
+    show_prg: bool,
+}
+
+impl Default for HeatmapView {
+    fn default() -> HeatmapView {
+        HeatmapView {
+            frames: DEFAULT_FRAMES,
+            show_prg: false,
+        }
+    }
+}
+
+impl HeatmapView {
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    // P shows or hides PRG-ROM, -/= halve or double the number of frames counted.  Changing the
+    // window starts counting again.  Returns whether the key was used.
+    pub fn handle_key(&mut self, key: Key, nes: &mut NES) -> bool {
+        let frames = match key {
+            Key::P => {
+                self.show_prg = !self.show_prg;
+                return true;
+            }
+            Key::Minus => (self.frames / 2).max(1),
+            Key::Equals => (self.frames * 2).min(MAX_FRAMES),
+            _ => return false,
+        };
+        self.frames = frames;
+        nes.enable_heatmap(frames);
+        true
+    }
+
+    pub fn lines(&self, nes: &NES) -> Vec<String> {
+        let heatmap = match nes.heatmap() {
+            None => return vec![String::from("HEATMAP OFF")],
+            Some(heatmap) => heatmap,
+        };
+
+        let mut lines = vec![
+            format!(
+                "RAM OVER {}/{} FRAMES{}",
+                heatmap.frames(),
+                heatmap.window(),
+                if self.show_prg { " + PRG" } else { "" }
+            ),
+            String::from("GREEN READ  RED WRITE  P PRG  -/= FRAMES"),
+        ];
+
+        let mut hottest: Vec<(u16, u32, u32)> = (0..RAM_SIZE as u16)
+            .map(|address| (address, heatmap.reads(address), heatmap.writes(address)))
+            .filter(|(_, reads, writes)| reads + writes > 0)
+            .collect();
+        hottest
+            .sort_by_key(|(address, reads, writes)| (std::cmp::Reverse(reads + writes), *address));
+        for (address, reads, writes) in hottest.iter().take(HOTTEST) {
+            lines.push(format!("${:04X} R {:6} W {:6}", address, reads, writes));
+        }
+
+        if let Some(stats) = nes.bus_stats() {
+            let cycles = nes.cpu.borrow().cycles();
+            lines.push(String::from("LAST FRAME BY DEVICE  CYCLES AGO"));
+            for device in Device::ALL.iter() {
+                let frame = stats.frame(*device);
+                let last = match frame.last_access {
+                    Some(cycle) => format!("{:>8}", cycles.saturating_sub(cycle)),
+                    None => String::from("   NEVER"),
+                };
+                lines.push(format!(
+                    "{:8} R {:5} W {:5} {}",
+                    device.name(),
+                    frame.reads,
+                    frame.writes,
+                    last
+                ));
+            }
+        }
+        lines
+    }
+
+    // Draws into an RGB buffer of WIDTH x HEIGHT.
+    pub fn render(&self, nes: &NES, buffer: &mut [u8]) {
+        buffer.iter_mut().for_each(|b| *b = 0);
+        let heatmap = match nes.heatmap() {
+            None => return,
+            Some(heatmap) => heatmap,
+        };
+
+        let ram = Scale::over(&heatmap, 0..RAM_SIZE);
+        for address in 0..RAM_SIZE {
+            let colour = ram.colour(&heatmap, address as u16);
+            let x = (address % RAM_COLUMNS) * RAM_CELL;
+            let y = (address / RAM_COLUMNS) * RAM_CELL;
+            for dy in 0..RAM_CELL {
+                for dx in 0..RAM_CELL {
+                    set_pixel(buffer, x + dx, y + dy, colour);
+                }
+            }
+        }
+
+        if self.show_prg {
+            let prg = Scale::over(&heatmap, PRG_START..PRG_START + PRG_SIZE);
+            for offset in 0..PRG_SIZE {
+                let colour = prg.colour(&heatmap, (PRG_START + offset) as u16);
+                set_pixel(buffer, offset % WIDTH, PRG_TOP + offset / WIDTH, colour);
+            }
+        }
+    }
+}
+
+// Busiest counts in one region, which show at full brightness.
+struct Scale {
+    max_reads: f32,
+    max_writes: f32,
+}
+
+impl Scale {
+    fn over(heatmap: &AccessHeatmap, addresses: std::ops::Range<usize>) -> Scale {
+        let (mut max_reads, mut max_writes) = (1, 1);
+        for address in addresses {
+            max_reads = max_reads.max(heatmap.reads(address as u16));
+            max_writes = max_writes.max(heatmap.writes(address as u16));
+        }
+        Scale {
+            max_reads: (max_reads as f32).ln_1p(),
+            max_writes: (max_writes as f32).ln_1p(),
+        }
+    }
+
+    fn colour(&self, heatmap: &AccessHeatmap, address: u16) -> [u8; 3] {
+        let brightness = |count: u32, max: f32| ((count as f32).ln_1p() / max * 255.0) as u8;
+        [
+            brightness(heatmap.writes(address), self.max_writes),
+            brightness(heatmap.reads(address), self.max_reads),
+            0,
+        ]
+    }
+}
+
+fn set_pixel(buffer: &mut [u8], x: usize, y: usize, colour: [u8; 3]) {
+    let ix = (y * WIDTH + x) * 3;
+    buffer[ix..ix + 3].copy_from_slice(&colour);
+}
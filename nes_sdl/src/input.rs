@@ -1,30 +1,90 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+
 use nes::emulator::io::event::{Event, Key};
 use sdl2::event;
 use sdl2::keyboard::Keycode;
 
-use crate::portal::Portal;
+use crate::command::{CommandSender, EmulatorCommand};
+use crate::exit;
+use crate::inputqueue::TimedEvent;
+
+// Moves the keyboard to the next emulator when more than one is running.
+const SWITCH_FOCUS_KEY: Key = Key::F10;
 
-// Responsible for collecting SDL events and rebroadcasting them as internal events.
+// Responsible for collecting SDL events and rebroadcasting them as internal events.  With several
+// emulators running, only the one with focus hears the keyboard.
 pub struct InputPump {
     event_pump: sdl2::EventPump,
-    events: Portal<Vec<Event>>,
+    events: Vec<Sender<TimedEvent>>,
+    focus: usize,
+    held: HashSet<Key>,
+    // Where ROMs dropped on the window go.
+    roms: Option<CommandSender>,
 }
 
 impl InputPump {
-    pub fn new(event_pump: sdl2::EventPump, events: Portal<Vec<Event>>) -> InputPump {
-        InputPump { event_pump, events }
+    pub fn new(event_pump: sdl2::EventPump, events: Vec<Sender<TimedEvent>>) -> InputPump {
+        InputPump {
+            event_pump,
+            events,
+            focus: 0,
+            held: HashSet::new(),
+            roms: None,
+        }
+    }
+
+    pub fn send_dropped_roms_to(&mut self, commands: CommandSender) {
+        self.roms = Some(commands);
+    }
+
+    pub fn focus(&self) -> usize {
+        self.focus
     }
 
     pub fn pump(&mut self) {
         while let Some(e) = self.event_pump.poll_event() {
-            let internal_event = convert_sdl_event_to_internal(e);
+            // Closing the window stops every emulator, as if escape had been pressed in each.
+            if let event::Event::Quit { .. } = e {
+                exit::request();
+                continue;
+            }
+            if let event::Event::DropFile { filename, .. } = e {
+                if let Some(ref roms) = self.roms {
+                    let _ = roms.send(EmulatorCommand::LoadRom(PathBuf::from(filename)));
+                }
+                continue;
+            }
+            let internal_event = match convert_sdl_event_to_internal(e) {
+                None => continue,
+                Some(e) => e,
+            };
 
-            if let Some(e) = internal_event {
-                self.events.consume(|portal| {
-                    portal.push(e);
-                });
+            match internal_event {
+                Event::KeyDown(SWITCH_FOCUS_KEY) if self.events.len() > 1 => {
+                    self.switch_focus();
+                    continue;
+                }
+                Event::KeyDown(key) => {
+                    self.held.insert(key);
+                }
+                Event::KeyUp(key) => {
+                    self.held.remove(&key);
+                }
             }
+
+            // The emulator thread only goes away when we're shutting down anyway.
+            let _ = self.events[self.focus].send(TimedEvent::now(internal_event));
+        }
+    }
+
+    fn switch_focus(&mut self) {
+        // Let go of everything first, so nothing stays held down in the game being left.
+        for key in self.held.drain() {
+            let _ = self.events[self.focus].send(TimedEvent::now(Event::KeyUp(key)));
         }
+        self.focus = (self.focus + 1) % self.events.len();
     }
 }
 
@@ -59,6 +119,7 @@ fn convert_sdl_keycode_to_internal(keycode: Keycode) -> Option<Key> {
         Keycode::O => Some(Key::O),
         Keycode::P => Some(Key::P),
         Keycode::Q => Some(Key::Q),
+        Keycode::R => Some(Key::R),
         Keycode::S => Some(Key::S),
         Keycode::T => Some(Key::T),
         Keycode::U => Some(Key::U),
@@ -79,6 +140,18 @@ fn convert_sdl_keycode_to_internal(keycode: Keycode) -> Option<Key> {
         Keycode::Num8 => Some(Key::Num8),
         Keycode::Num9 => Some(Key::Num9),
         Keycode::Num0 => Some(Key::Num0),
+        Keycode::F1 => Some(Key::F1),
+        Keycode::F2 => Some(Key::F2),
+        Keycode::F3 => Some(Key::F3),
+        Keycode::F4 => Some(Key::F4),
+        Keycode::F5 => Some(Key::F5),
+        Keycode::F6 => Some(Key::F6),
+        Keycode::F7 => Some(Key::F7),
+        Keycode::F8 => Some(Key::F8),
+        Keycode::F9 => Some(Key::F9),
+        Keycode::F10 => Some(Key::F10),
+        Keycode::F11 => Some(Key::F11),
+        Keycode::F12 => Some(Key::F12),
         Keycode::Minus => Some(Key::Minus),
         Keycode::Equals => Some(Key::Equals),
         Keycode::Backspace => Some(Key::Backspace),
@@ -0,0 +1,70 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Instant;
+
+use nes::emulator::io::event::{Event, Key};
+
+// Input waits here until the emulator reaches the end of a frame, so games only ever see buttons
+// change between frames, the same as when playing back a movie or running headless.
+
+#[derive(Clone, Copy, Debug)]
+pub struct TimedEvent {
+    pub event: Event,
+    pub at: Instant,
+}
+
+impl TimedEvent {
+    pub fn now(event: Event) -> TimedEvent {
+        TimedEvent {
+            event,
+            at: Instant::now(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct InputQueue {
+    pending: VecDeque<TimedEvent>,
+}
+
+impl InputQueue {
+    pub fn push(&mut self, event: TimedEvent) {
+        // Events normally arrive in order, but keep the queue sorted in case they didn't.
+        let ix = self
+            .pending
+            .iter()
+            .rposition(|queued| queued.at <= event.at)
+            .map_or(0, |ix| ix + 1);
+        self.pending.insert(ix, event);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    // Events to apply at this frame boundary, oldest first.  A key which is pressed and released
+    // within one frame has its release held back to the next, so quick taps aren't lost.
+    pub fn take_frame(&mut self, now: Instant) -> Vec<Event> {
+        let mut pressed: HashSet<Key> = HashSet::new();
+        let mut events = vec![];
+        while let Some(next) = self.pending.front() {
+            if next.at > now {
+                break;
+            }
+            match next.event {
+                Event::KeyDown(key) => {
+                    pressed.insert(key);
+                }
+                Event::KeyUp(key) if pressed.contains(&key) => break,
+                Event::KeyUp(_) => (),
+            }
+            events.push(next.event);
+            self.pending.pop_front();
+        }
+        events
+    }
+
+    // Everything queued, for when no frames are being run, e.g. while paused.
+    pub fn take_all(&mut self) -> Vec<Event> {
+        self.pending.drain(..).map(|timed| timed.event).collect()
+    }
+}
@@ -1,236 +1,982 @@
+pub mod abloop;
 pub mod audio;
+pub mod bankview;
+pub mod chrview;
+pub mod cli;
+pub mod command;
 pub mod compositor;
+pub mod config;
 pub mod controller;
+pub mod debugsession;
+pub mod evdev;
+pub mod eventlog;
+pub mod exit;
+pub mod fbdev;
+pub mod frames;
+pub mod games;
 pub mod governer;
+pub mod heatmap;
 pub mod input;
+pub mod inputqueue;
+pub mod memview;
+pub mod mixerview;
+pub mod netplay;
+pub mod osd;
+pub mod pausemenu;
 pub mod portal;
+pub mod profile;
+pub mod refresh;
+pub mod rewind;
+pub mod romdb;
+pub mod rumble;
+pub mod screenshot;
+pub mod scripting;
+pub mod spritetrace;
+pub mod sync;
+pub mod threads;
+
+#[cfg(test)]
+mod test;
 
 use std::cell::RefCell;
+use std::cmp::min;
 use std::env;
+use std::fs::create_dir_all;
+use std::panic;
 use std::path::Path;
+use std::process;
 use std::rc::Rc;
-use std::sync::{Arc, Condvar, Mutex};
-use std::time::Duration;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use nes::emulator::apu::debug::APUDebug;
+use nes::emulator::config::Config as EmulatorConfig;
 use nes::emulator::ines;
 use nes::emulator::io;
 use nes::emulator::io::event::{Event, EventBus};
-use nes::emulator::ppu::debug::{PPUDebug, PPUDebugRender};
-use nes::emulator::NES;
-
-use crate::audio::{AudioQueue, SAMPLE_RATE};
-use crate::compositor::Compositor;
-use crate::controller::{Controller, DebugMode, EmulatorState};
+use nes::emulator::movie::Movie;
+use nes::emulator::soak::{soak, SoakOptions};
+use nes::emulator::testrom::{self, TestRomStatus};
+use nes::emulator::{Region, NES};
+
+use crate::audio::{AudioOutput, AudioQueue};
+use crate::cli::{parse_args, Command, CompareOptions, RunOptions, USAGE};
+use crate::command::command_channel;
+use crate::compositor::{Compositor, DebugPortals};
+use crate::config::{load_config, Config};
+use crate::controller::{
+    load_movie, load_script, save_movie, Controller, DebugMode, EmulatorState,
+};
+use crate::debugsession::SessionStore;
+use crate::fbdev::Framebuffer;
+use crate::frames::{frame_channel, FrameSender};
+use crate::games::{default_games_path, load_games, name_from_path, save_games, Game, GameDb};
 use crate::governer::Governer;
 use crate::input::InputPump;
+use crate::inputqueue::{InputQueue, TimedEvent};
+use crate::netplay::connect;
+use crate::osd::Stats;
 use crate::portal::Portal;
+use crate::profile::{Tracer, Track};
+use crate::refresh::Cadence;
+use crate::romdb::{default_romdb_path, fix_header, load_romdb, RomDb};
+use crate::rumble::{rumble_channel, RumbleDevice};
+use crate::sync::{Correction, SyncMonitor};
+use crate::threads::configure_current_thread;
 
 pub const RENDER_FPS: u64 = 60;
 
+// How much to emulate between checks of whether the frame is running over time.
+const RUN_BATCH_CYCLES: u64 = 5_000;
+
+// About once every 10 seconds of game time.
+const SOAK_HASH_INTERVAL: u64 = 600;
+
 fn main() {
     // -- Handle Args --
 
-    let args: Vec<String> = env::args().collect();
+    let mut config = match load_config() {
+        Err(cause) => panic!("Couldn't load config: {}", cause),
+        Ok(config) => config,
+    };
+
+    let games_path = default_games_path();
+    let mut games = match load_games(&games_path) {
+        Err(cause) => panic!("Couldn't load game settings: {}", cause),
+        Ok(games) => games,
+    };
 
-    let rom_path = match args.get(1) {
-        None => panic!("You must pass in a path to a iNes ROM file."),
-        Some(path) => path,
+    let args: Vec<String> = env::args().skip(1).collect();
+    let default_rom = config
+        .emulator
+        .rom_path
+        .as_ref()
+        .map(|path| path.to_string_lossy().to_string())
+        .or_else(|| games.recent.first().cloned());
+    let options = match parse_args(&args, default_rom.as_deref()) {
+        Err(cause) => {
+            eprintln!("{}\n\n{}", cause, USAGE);
+            process::exit(2);
+        }
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Ok(Command::Recent) => {
+            for (ix, path) in games.recent.iter().enumerate() {
+                println!("{:2}. {}", ix + 1, path);
+            }
+            return;
+        }
+        Ok(Command::FixHeader { input, output, db }) => {
+            let db_path = db.unwrap_or_else(default_romdb_path);
+            let db = match load_romdb(&db_path) {
+                Err(cause) => panic!("Couldn't load ROM database: {}", cause),
+                Ok(db) => db,
+            };
+
+            match fix_header(&input, &output, &db) {
+                Err(cause) => panic!("Couldn't fix header: {}", cause),
+                Ok(report) => println!("{}", report),
+            }
+            return;
+        }
+        Ok(Command::Soak {
+            roms,
+            seed,
+            frames,
+            crash_dir,
+        }) => {
+            if !run_soak(&roms, seed, frames, &crash_dir) {
+                process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::TestRom { rom, frames }) => {
+            process::exit(run_test_rom(&rom, frames));
+        }
+        Ok(Command::Run(options)) => *options,
     };
 
     // -- Initialize --
 
-    let rom = ines::ROM::load(rom_path);
-    let rom_name = Path::new(rom_path)
-        .file_stem()
-        .map(|s| s.to_string_lossy().to_string())
-        .unwrap_or(String::from("unknown"));
+    let romdb = match load_romdb(&default_romdb_path()) {
+        Err(cause) => panic!("Couldn't load ROM database: {}", cause),
+        Ok(db) => db,
+    };
+    let game = load_game(&romdb, &games, &options.rom);
+    game.settings.apply(&mut config);
+    if let Some(ref palette) = options.palette {
+        config.emulator.palette_file = Some(palette.clone());
+    }
+    let cheats = game.settings.cheats_enabled();
+
+    let rom_name = name_from_path(&options.rom);
+    let play_movie = options
+        .play_movie
+        .clone()
+        .map(|path| match load_movie(&path) {
+            Err(cause) => panic!("Couldn't load movie: {}", cause),
+            Ok(movie) => (path, movie),
+        });
+    let pal = options.region == Some(Region::PAL);
+    let play_movie = play_movie.or_else(|| {
+        options
+            .script
+            .clone()
+            .map(|path| match load_script(&path, &rom_name, pal) {
+                Err(cause) => panic!("Couldn't load input script: {}", cause),
+                Ok(movie) => (path, movie),
+            })
+    });
+
+    // Only the windowed mode has a debugger.
+    let debug_store = SessionStore::new(games_path.clone(), game.key.clone());
+    let debug_session = game.settings.debug.clone();
+    let new_nes = nes_builder(game, options.region, &config.emulator);
+
+    let tracer = match options.profile {
+        Some(_) => Tracer::enabled(),
+        None => Tracer::default(),
+    };
+
+    // From here on, Ctrl-C and crashes let the emulators write out saves and traces first.
+    exit::install();
+
+    if options.headless {
+        run_headless(
+            &options, config, new_nes, &rom_name, play_movie, cheats, &tracer,
+        );
+        save_trace(&tracer, options.profile.as_deref());
+        return;
+    }
+
+    // Only games played for real go in the recent list.
+    games.add_recent(&options.rom);
+    if let Err(cause) = save_games(&games_path, &games) {
+        println!("Couldn't save recent ROMs: {}", cause);
+    }
+
+    if options.fbdev.is_some() {
+        let profile = options.profile.clone();
+        run_framebuffer(
+            options, config, new_nes, rom_name, play_movie, cheats, &tracer,
+        );
+        save_trace(&tracer, profile.as_deref());
+        return;
+    }
+
+    // Both players need to be there before either game starts.
+    let netplay = options.netplay.as_ref().map(|role| match connect(role) {
+        Err(cause) => panic!("Couldn't start netplay: {}", cause),
+        Ok(connection) => connection,
+    });
+    let netplay_delay = options.netplay_delay;
 
     let sdl_context = sdl2::init().unwrap();
     let video = sdl_context.video().unwrap();
     let audio = sdl_context.audio().unwrap();
 
-    let video_portal = Portal::new(vec![0; 256 * 240 * 3].into_boxed_slice());
-    let ppu_debug_portal: Portal<PPUDebugRender> = Portal::new(PPUDebugRender::new());
-    let apu_debug_portal = Portal::new(
-        vec![0; APUDebug::WAVEFORM_WIDTH * APUDebug::WAVEFORM_HEIGHT * 3].into_boxed_slice(),
-    );
-    let audio_portal = Portal::new(Vec::new());
-    let event_portal = Portal::new(Vec::new());
-
-    let mut compositor = Compositor::new(
-        video,
-        video_portal.clone(),
-        ppu_debug_portal.clone(),
-        apu_debug_portal.clone(),
-    );
-    let mut audio_queue = AudioQueue::new(audio, audio_portal.clone());
-    let mut input = InputPump::new(sdl_context.event_pump().unwrap(), event_portal.clone());
+    // Frames go out to the UI thread and input comes back in.
+    let (frame_sender, frame_receiver) = frame_channel();
+    let (event_sender, event_receiver) = channel();
+    let mut frame_receivers = vec![frame_receiver];
+    let mut event_senders = vec![event_sender];
+    let debug_portals = DebugPortals::default();
+    let audio_portal = Portal::new(AudioQueue::default());
+    let stats_portal = Portal::new(Stats::default());
 
-    compositor.set_window_title(&format!("[NES] {}", rom_name));
+    let (rumble_sender, rumble_receiver) = rumble_channel();
+    let mut rumble_device = RumbleDevice::new(&sdl_context, rumble_receiver);
 
     let state = Portal::new(EmulatorState::new());
-    let emu_state = state.clone();
+    state.consume(|state| state.title = rom_name.clone());
+    let mut states = vec![state.clone()];
 
-    let ui_sync = Arc::new((Mutex::new(()), Condvar::new()));
-    let emu_sync = ui_sync.clone();
+    // Other threads drive the emulator through clones of `commands`.
+    let (commands, command_receiver) = command_channel();
 
     // -- Run --
-    let _ = std::thread::spawn(std::panic::AssertUnwindSafe(move || {
+    let ports = InstancePorts {
+        state,
+        frames: frame_sender,
+        events: event_receiver,
+        debug: debug_portals.clone(),
+        audio: Some(audio_portal.clone()),
+        stats: Some(stats_portal.clone()),
+        tracer: tracer.clone(),
+        track: Track::Emulator(0),
+    };
+    let scale = options.scale;
+    let display = config.display.clone();
+    let profile = options.profile.clone();
+    let sample_rate = config.emulator.sample_rate;
+    let compare = options.compare.clone();
+    let emulator = spawn_emulator(new_nes, config.clone(), ports, move |controller| {
+        configure_controller(controller, &options, &rom_name, play_movie, cheats);
+        controller.set_command_receiver(command_receiver);
+        controller.set_rumble_sender(rumble_sender);
+        controller.use_debug_session(debug_store, debug_session);
+        if let Some(connection) = netplay {
+            controller.start_netplay(connection, netplay_delay);
+        }
+    });
+    let mut emulators = vec![emulator];
+
+    if let Some(compare) = compare {
+        let (frame_sender, frame_receiver) = frame_channel();
+        let (event_sender, event_receiver) = channel();
+        frame_receivers.push(frame_receiver);
+        event_senders.push(event_sender);
+
+        // The debug window is shared, so it starts out following the first emulator.
+        let state = Portal::new(EmulatorState::new());
+        state.consume(|state| state.debug_mode = DebugMode::OFF);
+        state.consume(|state| state.title = name_from_path(&compare.rom));
+        states.push(state.clone());
+
+        let ports = InstancePorts {
+            state,
+            frames: frame_sender,
+            events: event_receiver,
+            debug: debug_portals.clone(),
+            audio: None,
+            stats: None,
+            tracer: tracer.clone(),
+            track: Track::Emulator(1),
+        };
+        let compare_game = load_game(&romdb, &games, &compare.rom);
+        let new_nes = nes_builder(compare_game, compare.region, &config.emulator);
+        emulators.push(spawn_emulator(new_nes, config, ports, move |controller| {
+            configure_compare(controller, &compare)
+        }));
+    }
+
+    let mut compositor =
+        Compositor::new(video, frame_receivers, debug_portals, stats_portal, scale);
+    let mut audio_device = AudioOutput::new(audio, audio_portal, sample_rate, tracer.clone());
+    let mut input = InputPump::new(sdl_context.event_pump().unwrap(), event_senders);
+    input.send_dropped_roms_to(commands);
+
+    let refresh_hz = display.refresh_hz.or_else(|| compositor.refresh_rate());
+    let cadence = Cadence::new(RENDER_FPS as f64, refresh_hz.unwrap_or(0) as f64);
+    if cadence.is_active() {
+        println!(
+            "Display runs at {}Hz, showing each frame for {:.2} refreshes",
+            refresh_hz.unwrap_or(0),
+            cadence.refreshes_per_frame()
+        );
+        compositor.set_interpolate(display.interpolate);
+    }
+
+    let ui_res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        ui_loop(
+            &mut compositor,
+            &mut audio_device,
+            &mut input,
+            &mut rumble_device,
+            &states,
+            cadence,
+            &tracer,
+        );
+    }));
+
+    match ui_res {
+        Ok(_) => (),
+        Err(_) => {
+            println!("Panic in main loop.  Exiting.");
+        }
+    }
+
+    // Whichever emulator stopped first, the rest still need to write out their saves.
+    exit::request();
+    for emulator in emulators {
+        let _ = emulator.join();
+    }
+    save_trace(&tracer, profile.as_deref());
+}
+
+fn save_trace(tracer: &Tracer, path: Option<&str>) {
+    if let Some(path) = path {
+        match tracer.save(path) {
+            Err(cause) => println!("Failed to save trace: {}", cause),
+            Ok(()) => println!("Saved trace to {}", path),
+        }
+    }
+}
+
+// Builds an NES wired up to the given outputs.
+type NewNes = Box<
+    dyn FnOnce(
+            Rc<RefCell<io::Screen>>,
+            Rc<RefCell<io::SimpleAudioOut>>,
+            Rc<RefCell<EventBus>>,
+        ) -> NES
+        + Send,
+>;
+
+// Reads the ROM and gets it ready to run.  Exits if the emulator can't run it.
+fn load_game(romdb: &RomDb, games: &GameDb, path: &str) -> Game {
+    let rom = ines::ROM::load(path);
+    if let Err(cause) = rom.get_mapper() {
+        eprintln!("Couldn't run {}: {}", path, cause);
+        process::exit(1);
+    }
+    Game::new(romdb, games, &name_from_path(path), rom)
+}
+
+// Returns a constructor for an NES running the game.
+fn nes_builder(game: Game, region: Option<Region>, config: &EmulatorConfig) -> NewNes {
+    let config = game.emulator_config(region, config);
+    let Game {
+        rom,
+        expansion_gain,
+        ..
+    } = game;
+
+    Box::new(move |screen, audio, event_bus| {
+        let mut nes = NES::new(event_bus, screen, audio, rom, &config);
+        if let Some(gain) = expansion_gain {
+            nes.set_expansion_audio_gain(gain);
+        }
+        nes
+    })
+}
+
+// Everything an emulator thread shares with the UI thread.
+struct InstancePorts {
+    state: Portal<EmulatorState>,
+    frames: FrameSender,
+    events: Receiver<TimedEvent>,
+    debug: DebugPortals,
+    // Only one emulator at a time can be heard, or report its speed.
+    audio: Option<Portal<AudioQueue>>,
+    stats: Option<Portal<Stats>>,
+    // Profiling, with each emulator on its own row.
+    tracer: Tracer,
+    track: Track,
+}
+
+// Starts an emulator on its own thread.  The NES can't cross threads, so it's built over there.
+// The thread ends once the emulator stops, which it does by itself when asked to exit.
+fn spawn_emulator<S>(
+    new_nes: NewNes,
+    config: Config,
+    ports: InstancePorts,
+    setup: S,
+) -> JoinHandle<()>
+where
+    S: FnOnce(&mut Controller) + Send + 'static,
+{
+    std::thread::spawn(panic::AssertUnwindSafe(move || {
+        let thread = configure_current_thread(&config.thread);
+        ports.state.consume(|state| state.thread = thread);
+
         let event_bus = Rc::new(RefCell::new(EventBus::new()));
         let video_output = Rc::new(RefCell::new(io::Screen::new()));
-        let audio_output = Rc::new(RefCell::new(io::SimpleAudioOut::new(SAMPLE_RATE)));
+        let audio_output = Rc::new(RefCell::new(io::SimpleAudioOut::new(
+            config.emulator.sample_rate,
+        )));
 
-        let nes = NES::new(
-            event_bus.clone(),
+        let nes = new_nes(
             video_output.clone(),
             audio_output.clone(),
-            rom,
+            event_bus.clone(),
         );
-        let ppu_debug = PPUDebug::new(nes.ppu.clone());
-        let apu_debug = APUDebug::new(nes.apu.clone());
-
         let controller = Rc::new(RefCell::new(Controller::new(
             nes,
+            config,
             video_output.clone(),
             audio_output.clone(),
-            emu_state,
+            ports.state.clone(),
         )));
-        controller.borrow_mut().set_rom_name(&rom_name);
+        setup(&mut controller.borrow_mut());
         controller.borrow_mut().start();
         event_bus
             .borrow_mut()
             .register(Box::new(controller.clone()));
-        main_loop(
-            emu_sync,
-            controller,
-            video_output.clone(),
-            video_portal.clone(),
-            ppu_debug,
-            ppu_debug_portal.clone(),
-            apu_debug,
-            apu_debug_portal.clone(),
-            audio_output.clone(),
-            audio_portal.clone(),
-            event_bus.clone(),
-            event_portal.clone(),
-        );
-    }));
+        let state = ports.state.clone();
+        let crashed = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            main_loop(
+                controller.clone(),
+                video_output,
+                audio_output,
+                event_bus,
+                ports,
+            )
+        }))
+        .is_err();
+        if crashed {
+            salvage(&mut controller.borrow_mut());
+            state.consume(|state| state.is_running = false);
+        }
+    }))
+}
 
-    let ui_res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-        ui_loop(
-            ui_sync,
-            &mut compositor,
-            &mut audio_queue,
-            &mut input,
-            state.clone(),
-        );
-    }));
+// After a panic, the controller still knows what needs writing out.  Whatever went wrong may get
+// in the way again, but then there's nothing more to be done.
+fn salvage(controller: &mut Controller) {
+    println!("Emulator crashed.  Saving what we can.");
+    let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| controller.stop()));
+}
 
-    match ui_res {
-        Ok(_) => (),
-        Err(_) => {
-            println!("Panic in main loop.  Exiting.");
+// The second emulator is for comparing against the first, so it leaves the battery save alone
+// and runs without cheats.
+fn configure_compare(controller: &mut Controller, options: &CompareOptions) {
+    controller.set_rom_name(&format!("{}.compare", name_from_path(&options.rom)));
+    controller.use_rumble_triggers();
+    if let Some(ref path) = options.play_movie {
+        let played = load_movie(path).and_then(|movie| controller.play_movie(path, movie));
+        if let Err(cause) = played {
+            panic!("Couldn't play movie: {}", cause);
+        }
+    }
+}
+
+// Applies the command line options which the windowed and headless modes share.
+fn configure_controller(
+    controller: &mut Controller,
+    options: &RunOptions,
+    rom_name: &str,
+    play_movie: Option<(String, Movie)>,
+    cheats: bool,
+) {
+    controller.set_rom_name(rom_name);
+    controller.set_region_override(options.region);
+    controller.use_rumble_triggers();
+    if let Some(ref dir) = options.save_dir {
+        controller.set_save_dir(dir.clone());
+    }
+    if let Some(ref path) = options.trace {
+        controller.trace_to(path);
+    }
+    if let Some(instructions) = options.trace_size {
+        controller.set_trace_size(instructions);
+    }
+    if let Some(frames) = options.frames {
+        controller.set_frame_limit(frames);
+    }
+    if options.vram_check {
+        controller.check_vram_writes();
+    }
+    if let Some(ref path) = options.rhai {
+        controller.use_script(path);
+    }
+    // Movies start from a blank cart with no cheats, so they neither load nor overwrite the
+    // battery save.  Netplay does the same, so that both players start from the same place.
+    if let Some(ref path) = options.record_movie {
+        controller.record_movie(path);
+    } else if let Some((path, movie)) = play_movie {
+        if let Err(cause) = controller.play_movie(&path, movie) {
+            panic!("Couldn't play movie: {}", cause);
+        }
+    } else if options.netplay.is_none() {
+        controller.use_battery_save();
+        if cheats {
+            controller.use_cheats();
+        }
+    }
+}
+
+// Runs as fast as possible with no window or sound, until the frame limit or the end of the movie.
+fn run_headless<F>(
+    options: &RunOptions,
+    config: Config,
+    new_nes: F,
+    rom_name: &str,
+    play_movie: Option<(String, Movie)>,
+    cheats: bool,
+    tracer: &Tracer,
+) where
+    F: FnOnce(
+        Rc<RefCell<io::Screen>>,
+        Rc<RefCell<io::SimpleAudioOut>>,
+        Rc<RefCell<EventBus>>,
+    ) -> NES,
+{
+    let event_bus = Rc::new(RefCell::new(EventBus::new()));
+    let video_output = Rc::new(RefCell::new(io::Screen::new()));
+    let audio_output = Rc::new(RefCell::new(io::SimpleAudioOut::new(
+        config.emulator.sample_rate,
+    )));
+    let nes = new_nes(video_output.clone(), audio_output.clone(), event_bus);
+
+    let mut controller = Controller::new(
+        nes,
+        config,
+        video_output,
+        audio_output.clone(),
+        Portal::new(EmulatorState::new()),
+    );
+    let playing = play_movie.is_some();
+    configure_controller(&mut controller, options, rom_name, play_movie, cheats);
+    controller.start();
+
+    let start = Instant::now();
+    let mut frames = 0u64;
+    let crashed = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        while controller.is_running() {
+            let _span = tracer.span(Track::Emulator(0), "frame");
+            controller.run_frame();
+            frames += 1;
+
+            // Nobody is listening, but the samples still need draining.
+            audio_output.borrow_mut().consume(0, 0, |_| ());
+
+            if (playing && !controller.is_playing_movie()) || exit::requested() {
+                controller.stop();
+            }
+        }
+    }))
+    .is_err();
+    if crashed {
+        salvage(&mut controller);
+    }
+
+    println!(
+        "Ran {} frames in {:.2}s",
+        frames,
+        start.elapsed().as_secs_f64()
+    );
+}
+
+// Runs one emulator drawing straight to a Linux framebuffer, without SDL.  There's no sound, and
+// keys only come in if an input device was given.
+fn run_framebuffer(
+    options: RunOptions,
+    config: Config,
+    new_nes: NewNes,
+    rom_name: String,
+    play_movie: Option<(String, Movie)>,
+    cheats: bool,
+    tracer: &Tracer,
+) {
+    let device = options.fbdev.clone().unwrap_or_default();
+    let mut framebuffer = match Framebuffer::open(&device) {
+        Err(cause) => panic!("Couldn't open framebuffer: {}", cause),
+        Ok(framebuffer) => framebuffer,
+    };
+    let (width, height) = framebuffer.size();
+    println!(
+        "Drawing to {} at {}x{}, scaled {}x",
+        device,
+        width,
+        height,
+        framebuffer.scale()
+    );
+
+    let (frame_sender, frame_receiver) = frame_channel();
+    let (event_sender, event_receiver) = channel();
+    if let Some(ref path) = options.evdev {
+        if let Err(cause) = evdev::spawn_keyboard(path, event_sender) {
+            panic!("Couldn't read keyboard: {}", cause);
+        }
+    }
+
+    // There's nowhere to show the debug views.
+    let state = Portal::new(EmulatorState::new());
+    state.consume(|state| state.debug_mode = DebugMode::OFF);
+    let ports = InstancePorts {
+        state: state.clone(),
+        frames: frame_sender,
+        events: event_receiver,
+        debug: DebugPortals::default(),
+        audio: None,
+        stats: None,
+        tracer: tracer.clone(),
+        track: Track::Emulator(0),
+    };
+    let emulator = spawn_emulator(new_nes, config, ports, move |controller| {
+        configure_controller(controller, &options, &rom_name, play_movie, cheats);
+    });
+
+    while !exit::requested() && state.consume(|state| state.is_running) {
+        let frame = {
+            let _span = tracer.span(Track::Ui, "wait for frame");
+            frame_receiver.latest(Duration::from_millis(1000 / RENDER_FPS))
+        };
+        if let Some(frame) = frame {
+            let _span = tracer.span(Track::Ui, "present");
+            if let Err(cause) = framebuffer.draw(&frame) {
+                println!("Couldn't draw to framebuffer: {}", cause);
+                break;
+            }
+        }
+    }
+
+    exit::request();
+    let _ = emulator.join();
+}
+
+// Soaks each ROM in turn, saving a movie for any which crash.  Returns false if any did.
+fn run_soak(roms: &[String], seed: u64, frames: u64, crash_dir: &Path) -> bool {
+    let mut all_ok = true;
+    for (ix, rom_path) in roms.iter().enumerate() {
+        let rom = ines::ROM::load(rom_path);
+        let rom_name = Path::new(rom_path)
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or(String::from("unknown"));
+
+        // Each ROM gets its own seed, so one can be re-run alone with the seed printed here.
+        let options = SoakOptions {
+            seed: seed.wrapping_add(ix as u64),
+            frames,
+            hash_interval: SOAK_HASH_INTERVAL,
+        };
+        println!("Soaking {} with seed {}", rom_name, options.seed);
+        let report = match soak(&rom, &rom_name, &options) {
+            Err(cause) => {
+                println!("  Couldn't run: {}", cause);
+                all_ok = false;
+                continue;
+            }
+            Ok(report) => report,
+        };
+        for (frame, hash) in report.hashes.iter() {
+            println!("  frame {}: {:016x}", frame, hash);
+        }
+
+        let crash = match report.crash {
+            None => {
+                println!("  OK after {} frames", report.frames);
+                continue;
+            }
+            Some(crash) => crash,
+        };
+
+        all_ok = false;
+        println!("  CRASHED on frame {}: {}", crash.frame, crash.message);
+        let mut movie_path = crash_dir.to_path_buf();
+        movie_path.push(format!("{}-seed{}.fm2", rom_name, options.seed));
+        let movie_path = movie_path.to_string_lossy().to_string();
+        let saved = create_dir_all(crash_dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| save_movie(&movie_path, &crash.movie));
+        match saved {
+            Err(cause) => println!("  Failed to save crash movie: {}", cause),
+            Ok(()) => println!("  Replay with --play {} {}", movie_path, rom_path),
         }
     }
+    all_ok
 }
 
+// Runs a test ROM headlessly and returns the exit code for its result.
+fn run_test_rom(rom_path: &str, frames: u64) -> i32 {
+    let rom = ines::ROM::load(rom_path);
+    let event_bus = Rc::new(RefCell::new(EventBus::new()));
+    let screen = Rc::new(RefCell::new(io::Screen::new()));
+    let audio = io::nop::DummyAudio {};
+    let mut nes = match NES::try_new(event_bus, screen, audio, rom, &EmulatorConfig::default()) {
+        Err(cause) => {
+            println!("{}", cause);
+            return 2;
+        }
+        Ok(nes) => nes,
+    };
+
+    let max_cycles = frames * nes.region().master_clock_hz() / RENDER_FPS;
+    let result = testrom::run_test_rom(&mut nes, max_cycles);
+    println!("{}", result.text);
+    match result.status {
+        TestRomStatus::Finished(0) => {
+            println!("Passed");
+            0
+        }
+        TestRomStatus::Finished(code) => {
+            println!("Failed with code {}", code);
+            1
+        }
+        _ => {
+            match nes.cpu.borrow().jam() {
+                Some(jam) => println!("{}", jam),
+                None => println!("Didn't finish after {} frames", frames),
+            }
+            2
+        }
+    }
+}
+
+// Shows each frame from the emulator thread as it arrives.  Rendering blocks on vsync, so this
+// runs at the display's refresh rate, only falling back to the frame timeout while paused.  On a
+// faster display, the cadence says which refreshes repeat the last frame instead.
 fn ui_loop(
-    sync: Arc<(Mutex<()>, Condvar)>,
     compositor: &mut Compositor,
-    audio_queue: &mut AudioQueue,
+    audio_device: &mut AudioOutput,
     input: &mut InputPump,
-    state_portal: Portal<EmulatorState>,
+    rumble: &mut RumbleDevice,
+    states: &[Portal<EmulatorState>],
+    mut cadence: Cadence,
+    tracer: &Tracer,
 ) {
-    while state_portal.consume(|state| state.is_running) {
-        audio_queue.flush();
-        compositor.render();
+    let mut title = String::new();
+
+    // Quitting any of the emulators closes the window.
+    while !exit::requested()
+        && states
+            .iter()
+            .all(|state| state.consume(|state| state.is_running))
+    {
+        audio_device.flush();
+        {
+            let _span = tracer.span(Track::Ui, "wait for frame");
+            if !cadence.is_active() {
+                compositor.receive_frame(Duration::from_millis(1000 / RENDER_FPS));
+            } else if cadence.next_refresh() && !compositor.receive_frame(cadence.frame_timeout()) {
+                cadence.frame_late();
+            }
+            compositor.set_blend(cadence.blend());
+        }
+        {
+            let _span = tracer.span(Track::Ui, "present");
+            compositor.render();
+        }
         input.pump();
-        compositor.set_debug(state_portal.consume(|state| state.debug_mode));
-
-        let &(ref lock, ref cvar) = &*sync;
-        let guard = lock.lock().unwrap();
-        let _ = cvar
-            .wait_timeout(guard, Duration::from_millis(1000 / RENDER_FPS))
-            .unwrap();
+        rumble.play();
+
+        // Debug and OSD toggles come from whichever emulator has the keyboard.
+        let focused = &states[input.focus()];
+        compositor.set_focus(input.focus());
+        compositor.set_debug(focused.consume(|state| state.debug_mode));
+        compositor.set_osd(focused.consume(|state| state.show_osd));
+        compositor.set_menu(focused.consume(|state| state.menu.clone()));
+
+        // Games can be switched while running.
+        let titles: Vec<String> = states
+            .iter()
+            .map(|state| state.consume(|state| state.title.clone()))
+            .collect();
+        let new_title = format!("[NES] {}", titles.join(" | "));
+        if new_title != title {
+            compositor.set_window_title(&new_title);
+            title = new_title;
+        }
     }
 }
 
 fn main_loop(
-    sync: Arc<(Mutex<()>, Condvar)>,
     controller: Rc<RefCell<Controller>>,
     video_output: Rc<RefCell<io::Screen>>,
-    video_portal: Portal<Box<[u8]>>,
-    mut ppu_debug: PPUDebug,
-    ppu_debug_portal: Portal<PPUDebugRender>,
-    mut apu_debug: APUDebug,
-    apu_debug_portal: Portal<Box<[u8]>>,
     audio_output: Rc<RefCell<io::SimpleAudioOut>>,
-    audio_portal: Portal<Vec<f32>>,
     event_bus: Rc<RefCell<EventBus>>,
-    event_portal: Portal<Vec<Event>>,
+    ports: InstancePorts,
 ) {
     let mut frame_count: u64 = 0;
     let mut agg_cycles: u64 = 0;
     let mut governer = Governer::new(RENDER_FPS);
+    let mut sync_monitor = SyncMonitor::new(controller.borrow().sample_rate(), RENDER_FPS);
+    let mut input = InputQueue::default();
+    let (mut ppu_debug, mut apu_debug) = controller.borrow().debug_renderers();
+    let mut cartridge_swaps = controller.borrow().cartridge_swaps();
+    let broadcast = |events: Vec<Event>| {
+        for e in events {
+            event_bus.borrow_mut().broadcast(e);
+        }
+    };
 
+    let tracer = ports.tracer.clone();
     while controller.borrow().is_running() {
-        let target_hz = controller.borrow().target_hz();
-        let target_frame_cycles = target_hz / RENDER_FPS;
+        if exit::requested() {
+            controller.borrow_mut().stop();
+            break;
+        }
+        let _frame_span = tracer.span(ports.track, "frame");
+        controller.borrow_mut().process_commands();
+        if controller.borrow().cartridge_swaps() != cartridge_swaps {
+            cartridge_swaps = controller.borrow().cartridge_swaps();
+            (ppu_debug, apu_debug) = controller.borrow().debug_renderers();
+        }
+        for e in ports.events.try_iter() {
+            input.push(e);
+        }
+
+        // Input is applied between frames, but with none running there's nothing to wait for,
+        // and keys like the one which stops rewinding still need to get through.
+        if controller.borrow().target_hz() == 0 || controller.borrow().is_rewinding() {
+            broadcast(input.take_all());
+        }
+
+        let target_hz = controller.borrow().frame_target_hz();
+        let rewinding = controller.borrow().is_rewinding();
+
+        // No audio is produced while rewinding or at odd speeds, so there's nothing to sync to.
+        let correction = match ports.audio {
+            Some(ref audio) if !rewinding && controller.borrow().is_audio_enabled() => {
+                sync_monitor.update(audio.consume(|queue| queue.buffered))
+            }
+            _ => {
+                sync_monitor.reset();
+                Correction::None
+            }
+        };
+        let frames_to_run = match correction {
+            Correction::None => 1,
+            Correction::DropFrame => 2,
+            Correction::DuplicateFrame => 0,
+        };
+        let target_frame_cycles = frames_to_run * target_hz / RENDER_FPS;
 
         let mut cycles_this_frame = 0;
 
-        event_portal.consume(|events| {
-            events
-                .drain(..)
-                .for_each(|e| event_bus.borrow_mut().broadcast(e));
-        });
+        if rewinding {
+            controller.borrow_mut().rewind_step();
+        }
 
         while cycles_this_frame < target_frame_cycles && !governer.taking_too_long() {
-            // Batching ticks here is a massive perf win since finding the elapsed time is costly.
-            cycles_this_frame += controller.borrow_mut().tick_multi(100);
+            // Batching cycles here is a massive perf win since finding the elapsed time is costly.
+            let batch = min(target_frame_cycles - cycles_this_frame, RUN_BATCH_CYCLES);
+            let frame = controller.borrow().frame_count();
+            let span = tracer.span(ports.track, "cpu batch");
+            cycles_this_frame += controller.borrow_mut().run_cycles(batch);
+            drop(span);
+            if !input.is_empty() && controller.borrow().frame_count() != frame {
+                broadcast(input.take_frame(Instant::now()));
+            }
+        }
+
+        if !rewinding {
+            controller.borrow_mut().record_rewind();
         }
 
         // Drive rendering.
+        let render_span = tracer.span(ports.track, "ppu render");
         video_output.borrow().do_render(|data| {
-            video_portal.consume(|portal| {
-                copy_buffer(data, portal);
-            });
+            ports.frames.send(data);
         });
 
         match controller.borrow().debug_mode() {
-            DebugMode::PPU => ppu_debug.do_render(|buffers| {
-                ppu_debug_portal.consume(|portal| {
-                    copy_buffer(&buffers.patterns, &mut portal.patterns);
-                    copy_buffer(&buffers.nametables, &mut portal.nametables);
-                    copy_buffer(&buffers.sprites, &mut portal.sprites);
-                    copy_buffer(&buffers.palettes, &mut portal.palettes);
+            DebugMode::PPU => {
+                ppu_debug.freeze_chr(controller.borrow().frozen_chr());
+                ppu_debug.do_render(|buffers| {
+                    ports.debug.ppu.consume(|portal| {
+                        copy_buffer(&buffers.patterns, &mut portal.patterns);
+                        copy_buffer(&buffers.nametables, &mut portal.nametables);
+                        copy_buffer(&buffers.sprites, &mut portal.sprites);
+                        copy_buffer(&buffers.palettes, &mut portal.palettes);
+                    });
                 });
-            }),
+                let mut lines = controller.borrow().scroll_split_lines();
+                lines.extend(controller.borrow().chr_bank_lines());
+                ports.debug.text.consume(|portal| *portal = lines);
+            }
             DebugMode::APU => {
                 apu_debug.do_render(|data| {
-                    apu_debug_portal.consume(|portal| {
+                    ports.debug.apu.consume(|portal| {
                         copy_buffer(data, portal);
                     });
                 });
+                let lines = controller.borrow().mixer_lines();
+                ports.debug.text.consume(|portal| *portal = lines);
+            }
+            DebugMode::MEMORY => {
+                let lines = controller.borrow().memory_view_lines();
+                ports.debug.text.consume(|portal| *portal = lines);
+            }
+            DebugMode::SPRITES => {
+                let lines = controller.borrow().sprite_trace_lines();
+                ports.debug.text.consume(|portal| *portal = lines);
+            }
+            DebugMode::HEATMAP => {
+                let lines = controller.borrow().heatmap_lines();
+                ports.debug.text.consume(|portal| *portal = lines);
+                ports
+                    .debug
+                    .heatmap
+                    .consume(|portal| controller.borrow().render_heatmap(portal));
+            }
+            DebugMode::EVENTS => {
+                let lines = controller.borrow().event_log_lines();
+                ports.debug.text.consume(|portal| *portal = lines);
+            }
+            DebugMode::BANKS => {
+                let lines = controller.borrow().bank_lines();
+                ports.debug.text.consume(|portal| *portal = lines);
+                ports
+                    .debug
+                    .banks
+                    .consume(|portal| controller.borrow().render_banks(portal));
             }
             _ => (),
         }
-
-        let request_samples = SAMPLE_RATE / (RENDER_FPS as f32);
+        drop(render_span);
+
+        // Audio played while rewinding is just noise, so drop it.
+        let request_samples = if rewinding {
+            0.0
+        } else {
+            sync_monitor.samples_for_frame() * (frames_to_run as f64)
+        };
         audio_output
             .borrow_mut()
             .consume(target_frame_cycles, request_samples as u64, |data| {
-                audio_portal.consume(|portal| {
-                    portal.extend_from_slice(data);
-                });
+                if let Some(ref audio) = ports.audio {
+                    audio.consume(|queue| queue.samples.extend_from_slice(data));
+                }
             });
 
-        // Wake up the render thread immediately if it's waiting.
-        let &(_, ref cvar) = &*sync;
-        cvar.notify_one();
-
+        let sleep_span = tracer.span(ports.track, "sleep");
         governer.synchronize();
+        drop(sleep_span);
 
         // Calaculate stats.
         frame_count += 1;
@@ -239,12 +985,18 @@ fn main_loop(
             let avg_cycles = (agg_cycles as f64) / (RENDER_FPS as f64);
             let avg_frame_ns = governer.avg_frame_duration_ns();
             let avg_hz = (avg_cycles / avg_frame_ns) * 1_000_000_000f64;
-            println!(
-                "FPS: {:.1}, Target Freq: {:.3}MHz, Current Freq: {:.3}MHz",
-                1_000_000_000f64 / avg_frame_ns,
-                (target_hz as f64) / 1_000_000f64,
-                avg_hz / 1_000_000f64
-            );
+            let stats = Stats {
+                fps: 1_000_000_000f64 / avg_frame_ns,
+                target_hz,
+                actual_hz: avg_hz,
+                sync: sync_monitor.stats(),
+                turbo_rate: controller.borrow().turbo_rate(),
+                loops: controller.borrow().loop_count(),
+                thread: ports.state.consume(|state| state.thread),
+            };
+            if let Some(ref portal) = ports.stats {
+                portal.consume(|portal| *portal = stats);
+            }
             agg_cycles = 0;
         }
     }
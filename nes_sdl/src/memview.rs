@@ -0,0 +1,214 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use nes::emulator::io::event::Key;
+use nes::emulator::NES;
+
+// Hex dump of CPU or PPU memory for the debug window.  The cursor byte can be overwritten by
+// typing two hex digits.  CPU addresses can also be watched, to keep an eye on them from anywhere
+// in memory, or made breakpoints, which pause the emulator when the CPU gets there.
+
+pub const BYTES_PER_ROW: u16 = 16;
+pub const ROWS: u16 = 64;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MemorySpace {
+    #[default]
+    CPU,
+    // Nametables and palettes.  Pattern tables live on the cartridge and can't be read safely.
+    PPU,
+}
+
+impl MemorySpace {
+    fn first(self) -> u16 {
+        match self {
+            MemorySpace::CPU => 0x0000,
+            MemorySpace::PPU => 0x2000,
+        }
+    }
+
+    fn last(self) -> u16 {
+        match self {
+            MemorySpace::CPU => 0xFFFF,
+            MemorySpace::PPU => 0x3FFF,
+        }
+    }
+}
+
+// Saved as part of the debug session, so it's left where it was next time.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryView {
+    space: MemorySpace,
+    // First address on screen, always at the start of a row.
+    top: u16,
+    cursor: u16,
+    watches: BTreeSet<u16>,
+    breakpoints: BTreeSet<u16>,
+    // First digit of a byte being typed in.
+    #[serde(skip)]
+    high_nibble: Option<u8>,
+}
+
+impl MemoryView {
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    // Arrows move the cursor, -/= page up and down, space switches between CPU and PPU memory
+    // and hex digits poke.  In CPU memory W watches the cursor address and K makes it a
+    // breakpoint, or stops either.  Returns whether the key was used.
+    pub fn handle_key(&mut self, key: Key, nes: &mut NES) -> bool {
+        let page = i32::from(BYTES_PER_ROW * ROWS);
+        match key {
+            Key::Left => self.move_cursor(-1),
+            Key::Right => self.move_cursor(1),
+            Key::Up => self.move_cursor(-i32::from(BYTES_PER_ROW)),
+            Key::Down => self.move_cursor(i32::from(BYTES_PER_ROW)),
+            Key::Minus => self.move_cursor(-page),
+            Key::Equals => self.move_cursor(page),
+            Key::Space => self.switch_space(),
+            Key::W => self.toggle_watch(),
+            Key::K => self.toggle_breakpoint(nes),
+            _ => match hex_digit(key) {
+                Some(digit) => self.type_digit(digit, nes),
+                None => return false,
+            },
+        }
+        true
+    }
+
+    pub fn lines(&self, nes: &NES) -> Vec<String> {
+        let peek = |address| match self.space {
+            MemorySpace::CPU => nes.peek(address),
+            MemorySpace::PPU => nes.peek_ppu(address),
+        };
+
+        let name = match self.space {
+            MemorySpace::CPU => "CPU",
+            MemorySpace::PPU => "PPU",
+        };
+        let mut lines = vec![format!("{} ${:04X}", name, self.cursor)];
+        if !self.watches.is_empty() {
+            let watches: Vec<String> = self
+                .watches
+                .iter()
+                .map(|address| format!("${:04X}={:02X}", address, nes.peek(*address)))
+                .collect();
+            lines.push(format!("WATCH {}", watches.join(" ")));
+        }
+        if !self.breakpoints.is_empty() {
+            let breakpoints: Vec<String> = self
+                .breakpoints
+                .iter()
+                .map(|address| format!("${:04X}", address))
+                .collect();
+            lines.push(format!("BREAK {}", breakpoints.join(" ")));
+        }
+
+        for row in 0..ROWS {
+            let start = u32::from(self.top) + u32::from(row * BYTES_PER_ROW);
+            if start > u32::from(self.space.last()) {
+                break;
+            }
+
+            let mut line = format!("${:04X}", start);
+            for address in start..start + u32::from(BYTES_PER_ROW) {
+                let address = address as u16;
+                let separator = if address == self.cursor { '>' } else { ' ' };
+                line.push(separator);
+                match self.high_nibble {
+                    Some(high) if address == self.cursor => line.push_str(&format!("{:X}-", high)),
+                    _ => line.push_str(&format!("{:02X}", peek(address))),
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn move_cursor(&mut self, delta: i32) {
+        let first = i32::from(self.space.first());
+        let last = i32::from(self.space.last());
+        self.cursor = (i32::from(self.cursor) + delta).clamp(first, last) as u16;
+        self.high_nibble = None;
+
+        // Scroll just far enough to keep the cursor on screen.
+        let row_start = self.cursor - self.cursor % BYTES_PER_ROW;
+        let visible = u32::from(BYTES_PER_ROW * ROWS);
+        if row_start < self.top {
+            self.top = row_start;
+        } else if u32::from(row_start) >= u32::from(self.top) + visible {
+            self.top = row_start - (ROWS - 1) * BYTES_PER_ROW;
+        }
+    }
+
+    fn switch_space(&mut self) {
+        self.space = match self.space {
+            MemorySpace::CPU => MemorySpace::PPU,
+            MemorySpace::PPU => MemorySpace::CPU,
+        };
+        self.top = self.space.first();
+        self.cursor = self.space.first();
+        self.high_nibble = None;
+    }
+
+    fn toggle_watch(&mut self) {
+        if self.space != MemorySpace::CPU {
+            return;
+        }
+        if !self.watches.remove(&self.cursor) {
+            self.watches.insert(self.cursor);
+        }
+    }
+
+    fn toggle_breakpoint(&mut self, nes: &mut NES) {
+        if self.space != MemorySpace::CPU {
+            return;
+        }
+        if self.breakpoints.remove(&self.cursor) {
+            nes.remove_breakpoint(self.cursor);
+        } else {
+            self.breakpoints.insert(self.cursor);
+            nes.add_breakpoint(self.cursor);
+        }
+    }
+
+    fn type_digit(&mut self, digit: u8, nes: &mut NES) {
+        match self.high_nibble.take() {
+            None => self.high_nibble = Some(digit),
+            Some(high) => {
+                let byte = (high << 4) | digit;
+                match self.space {
+                    MemorySpace::CPU => nes.poke(self.cursor, byte),
+                    MemorySpace::PPU => nes.poke_ppu(self.cursor, byte),
+                }
+                self.move_cursor(1);
+            }
+        }
+    }
+}
+
+fn hex_digit(key: Key) -> Option<u8> {
+    let digit = match key {
+        Key::Num0 => 0x0,
+        Key::Num1 => 0x1,
+        Key::Num2 => 0x2,
+        Key::Num3 => 0x3,
+        Key::Num4 => 0x4,
+        Key::Num5 => 0x5,
+        Key::Num6 => 0x6,
+        Key::Num7 => 0x7,
+        Key::Num8 => 0x8,
+        Key::Num9 => 0x9,
+        Key::A => 0xA,
+        Key::B => 0xB,
+        Key::C => 0xC,
+        Key::D => 0xD,
+        Key::E => 0xE,
+        Key::F => 0xF,
+        _ => return None,
+    };
+    Some(digit)
+}
@@ -0,0 +1,54 @@
+use nes::emulator::apu::Channel;
+use nes::emulator::io::event::Key;
+use nes::emulator::NES;
+
+// Lists each APU channel under the waveforms, and whether it's heard.  Silencing channels picks
+// out one part of a tune, or finds which channel makes an unwanted noise.
+
+const KEYS: [Key; 6] = [
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+];
+
+#[derive(Default)]
+pub struct MixerView;
+
+impl MixerView {
+    // 1-6 mute or unmute a channel, and with shift solo it.  These take over from loading states
+    // and setting the speed while the view is open.  Returns whether the key was used.
+    pub fn handle_key(&mut self, key: Key, shift: bool, nes: &mut NES) -> bool {
+        let channel = match KEYS.iter().position(|k| *k == key) {
+            None => return false,
+            Some(ix) => Channel::ALL[ix],
+        };
+        let mut apu = nes.apu.borrow_mut();
+        let mut mixer = apu.mixer_control();
+        if shift {
+            mixer.toggle_solo(channel);
+        } else {
+            mixer.toggle(channel);
+        }
+        apu.set_mixer_control(mixer);
+        true
+    }
+
+    pub fn lines(&self, nes: &NES) -> Vec<String> {
+        let mixer = nes.apu.borrow().mixer_control();
+        let mut lines = vec![String::from("1-6 MUTE  SHIFT SOLO")];
+        for (ix, channel) in Channel::ALL.iter().enumerate() {
+            let state = if mixer.solo() == Some(*channel) {
+                "SOLO"
+            } else if !mixer.is_audible(*channel) {
+                "OFF"
+            } else {
+                "ON"
+            };
+            lines.push(format!("{} {:9} {}", ix + 1, channel.name(), state));
+        }
+        lines
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use nes::emulator::metadata::Metadata;
+
+// Two players on two machines, each running the same game.  Only controller input crosses the
+// network: every frame each side sends its player's buttons, and neither runs a frame until it has
+// both players' input for it.  The emulator is deterministic, so the two games stay in step
+// without ever comparing state.  Input is used `delay` frames after it's sent, which hides that
+// much network latency at the cost of that much input lag.
+//
+// After a line of JSON from each side saying what they're running, every frame is two bytes:
+// flags, then buttons in strobe order.
+
+pub const DEFAULT_PORT: u16 = 7845;
+pub const DEFAULT_DELAY_FRAMES: u32 = 3;
+
+const PROTOCOL_VERSION: u32 = 1;
+
+// Longest to wait for the other player's input before giving up on them.
+const INPUT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Flags.
+const RESET: u8 = 0x01;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NetplayRole {
+    Host(u16),
+    Connect(String),
+}
+
+impl NetplayRole {
+    // `host`, `host:<port>`, or the address to connect to, whose port is optional.
+    pub fn parse(arg: &str) -> Result<NetplayRole, String> {
+        if arg == "host" {
+            return Ok(NetplayRole::Host(DEFAULT_PORT));
+        }
+        if let Some(port) = arg.strip_prefix("host:") {
+            return port
+                .parse()
+                .map(NetplayRole::Host)
+                .map_err(|_| format!("Bad netplay port '{}'", port));
+        }
+        if arg.contains(':') {
+            Ok(NetplayRole::Connect(arg.to_string()))
+        } else {
+            Ok(NetplayRole::Connect(format!("{}:{}", arg, DEFAULT_PORT)))
+        }
+    }
+}
+
+// Whatever carries bytes between the two players.  Normally TCP, but tests put a simulated network
+// here instead.
+pub trait Link: Read + Write + Send {
+    // A second handle on the same link, so one can be buffered for reading while the other writes.
+    fn try_clone_link(&self) -> io::Result<Box<dyn Link>>;
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Link for TcpStream {
+    fn try_clone_link(&self) -> io::Result<Box<dyn Link>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+// A link to the other player, before either side knows what the other is running.
+pub struct Connection {
+    link: Box<dyn Link>,
+    host: bool,
+}
+
+impl Connection {
+    pub fn new(link: Box<dyn Link>, host: bool) -> Connection {
+        Connection { link, host }
+    }
+}
+
+// Blocks until the other player is there.
+pub fn connect(role: &NetplayRole) -> Result<Connection, String> {
+    let (stream, host) = match role {
+        NetplayRole::Host(port) => {
+            let listener = TcpListener::bind(("0.0.0.0", *port))
+                .map_err(|e| format!("Couldn't listen on port {}: {}", port, e))?;
+            println!("Waiting for the other player on port {}", port);
+            let (stream, address) = listener.accept().map_err(|e| e.to_string())?;
+            println!("Player 2 connected from {}", address);
+            (stream, true)
+        }
+        NetplayRole::Connect(address) => {
+            let stream = TcpStream::connect(address)
+                .map_err(|e| format!("Couldn't connect to {}: {}", address, e))?;
+            println!("Connected to {}", address);
+            (stream, false)
+        }
+    };
+    // Each message is tiny and needed straight away.
+    stream.set_nodelay(true).map_err(|e| e.to_string())?;
+    Ok(Connection::new(Box::new(stream), host))
+}
+
+// What each side says before the first frame.  The host's delay is the one both use.
+#[derive(Debug, Serialize, Deserialize)]
+struct Hello {
+    version: u32,
+    delay: u32,
+    metadata: Metadata,
+}
+
+// Both players' input for one frame.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NetInput {
+    pub reset: bool,
+    pub joy1: u8,
+    pub joy2: u8,
+}
+
+pub struct Netplay {
+    reader: BufReader<Box<dyn Link>>,
+    writer: Box<dyn Link>,
+    host: bool,
+    delay: u32,
+    // This side's messages still waiting for their frame, oldest first.
+    pending: VecDeque<[u8; 2]>,
+    frame: u64,
+}
+
+impl Netplay {
+    // Checks both sides are running the same game on the same emulator.
+    pub fn start(
+        connection: Connection,
+        metadata: &Metadata,
+        delay: u32,
+    ) -> Result<Netplay, String> {
+        let Connection { link, host } = connection;
+        let writer = link.try_clone_link().map_err(|e| e.to_string())?;
+        let mut netplay = Netplay {
+            reader: BufReader::new(link),
+            writer,
+            host,
+            delay,
+            pending: VecDeque::new(),
+            frame: 0,
+        };
+
+        let hello = Hello {
+            version: PROTOCOL_VERSION,
+            delay,
+            metadata: metadata.clone(),
+        };
+        let mut line = serde_json::to_string(&hello).map_err(|e| e.to_string())?;
+        line.push('\n');
+        netplay.send(line.as_bytes())?;
+
+        let mut line = String::new();
+        netplay
+            .reader
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?;
+        let theirs: Hello = serde_json::from_str(&line)
+            .map_err(|e| format!("The other player didn't say hello: {}", e))?;
+        if theirs.version != PROTOCOL_VERSION {
+            return Err(format!(
+                "The other player's emulator speaks netplay version {}, not {}",
+                theirs.version, PROTOCOL_VERSION
+            ));
+        }
+        let warnings = metadata
+            .check(&theirs.metadata)
+            .map_err(|mismatch| format!("The other player's game was {}", mismatch))?;
+        for warning in warnings {
+            println!("Warning: the other player's game was {}", warning);
+        }
+
+        if !host {
+            netplay.delay = theirs.delay;
+        }
+        // Nobody has pressed anything yet for the first few frames.
+        netplay.pending = (0..netplay.delay).map(|_| [0, 0]).collect();
+        netplay
+            .reader
+            .get_ref()
+            .set_read_timeout(Some(INPUT_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+        Ok(netplay)
+    }
+
+    // 1 for the host, 2 for whoever connected.
+    pub fn player(&self) -> usize {
+        if self.host {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn delay(&self) -> u32 {
+        self.delay
+    }
+
+    // Sends this side's input, which will be used `delay` frames from now, and returns the input
+    // for the frame about to run.  Blocks until the other player's input for it arrives.
+    pub fn exchange(&mut self, buttons: u8, reset: bool) -> Result<NetInput, String> {
+        let message = [if reset { RESET } else { 0 }, buttons];
+        self.send(&message)?;
+        self.pending.push_back(message);
+        let ours = self.pending.pop_front().unwrap_or([0, 0]);
+
+        let theirs = if self.frame < self.delay as u64 {
+            [0, 0]
+        } else {
+            self.receive()?
+        };
+        self.frame += 1;
+
+        let (joy1, joy2) = if self.host {
+            (ours[1], theirs[1])
+        } else {
+            (theirs[1], ours[1])
+        };
+        Ok(NetInput {
+            reset: (ours[0] | theirs[0]) & RESET != 0,
+            joy1,
+            joy2,
+        })
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.writer
+            .write_all(bytes)
+            .map_err(|e| format!("Lost the other player: {}", e))
+    }
+
+    fn receive(&mut self) -> Result<[u8; 2], String> {
+        let mut message = [0; 2];
+        match self.reader.read_exact(&mut message) {
+            Ok(()) => Ok(message),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                Err(String::from("The other player left"))
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                Err(String::from("The other player stopped responding"))
+            }
+            Err(e) => Err(format!("Lost the other player: {}", e)),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use sdl2::{pixels, rect, render, video};
+
+use crate::sync::SyncStats;
+use crate::threads::ThreadStatus;
+
+// Performance figures reported once a second by the main loop.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub fps: f64,
+    pub target_hz: u64,
+    pub actual_hz: f64,
+    pub sync: SyncStats,
+    // Frames between turbo button toggles, or 0 when turbo is off.
+    pub turbo_rate: u32,
+    // Times round the A/B loop, while there is one.
+    pub loops: Option<u64>,
+    pub thread: ThreadStatus,
+}
+
+impl Stats {
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{:.1} FPS {:.3}/{:.3} MHZ AV {:+.1}MS",
+            self.fps,
+            self.actual_hz / 1_000_000f64,
+            (self.target_hz as f64) / 1_000_000f64,
+            self.sync.drift_ms
+        );
+        if self.turbo_rate != 0 {
+            summary.push_str(&format!(" TURBO {}", self.turbo_rate));
+        }
+        if let Some(loops) = self.loops {
+            summary.push_str(&format!(" LOOP {}", loops));
+        }
+        summary.push_str(&self.thread.summary());
+        summary
+    }
+}
+
+const GLYPH_WIDTH: i32 = 3;
+const GLYPH_HEIGHT: i32 = 5;
+
+// Each row is 3 bits wide, MSB on the left.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '$' => [0b011, 0b110, 0b010, 0b011, 0b110],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b111, 0b100, 0b100, 0b100, 0b111],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b111, 0b100, 0b101, 0b101, 0b111],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b111],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        'P' => [0b111, 0b101, 0b111, 0b100, 0b100],
+        'Q' => [0b111, 0b101, 0b101, 0b111, 0b001],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b111, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        _ => [0; 5],
+    }
+}
+
+// Draws text in a tiny built-in font, on a dark backing box so it stays readable over the game.
+pub fn draw_text(
+    canvas: &mut render::Canvas<video::Window>,
+    x: i32,
+    y: i32,
+    scale: i32,
+    text: &str,
+) {
+    let chars = text.chars().count() as i32;
+    let width = (chars * (GLYPH_WIDTH + 1) + 1) * scale;
+    let height = (GLYPH_HEIGHT + 2) * scale;
+
+    canvas.set_draw_color(pixels::Color::RGBA(0, 0, 0, 0xFF));
+    let _ = canvas.fill_rect(rect::Rect::new(x, y, width as u32, height as u32));
+
+    canvas.set_draw_color(pixels::Color::RGBA(0xFF, 0xFF, 0xFF, 0xFF));
+    for (ix, c) in text.chars().enumerate() {
+        let glyph_x = x + (1 + (ix as i32) * (GLYPH_WIDTH + 1)) * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if (bits >> (GLYPH_WIDTH - 1 - col)) & 1 == 0 {
+                    continue;
+                }
+
+                let _ = canvas.fill_rect(rect::Rect::new(
+                    glyph_x + col * scale,
+                    y + (1 + row as i32) * scale,
+                    scale as u32,
+                    scale as u32,
+                ));
+            }
+        }
+    }
+
+    canvas.set_draw_color(pixels::Color::RGBA(0, 0, 0, 0xFF));
+}
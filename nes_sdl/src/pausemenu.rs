@@ -0,0 +1,100 @@
+use nes::emulator::io::event::Key;
+
+use crate::config::{Bindings, Config};
+
+// Shown over the game while it's paused by F1, for things which don't deserve a key of their own.
+// For now that's remapping the keyboard, which asks for a key for each button in turn and saves
+// them to config.toml, so nobody has to edit it by hand.
+
+pub enum MenuAction {
+    None,
+    Resume,
+    // Every button has been given a key.
+    Remapped(Bindings),
+}
+
+pub struct PauseMenu {
+    // Speed to go back to on leaving the menu.
+    resume_hz: u64,
+    // While remapping, the next button to ask for and the keys given so far.
+    remap: Option<(usize, Bindings)>,
+    // The last key given while remapping, if it was turned down for being in use already.
+    refused: Option<Key>,
+}
+
+impl PauseMenu {
+    pub fn new(resume_hz: u64) -> PauseMenu {
+        PauseMenu {
+            resume_hz,
+            remap: None,
+            refused: None,
+        }
+    }
+
+    pub fn resume_hz(&self) -> u64 {
+        self.resume_hz
+    }
+
+    // Every key goes to the menu while it's open, so none reach the game.  Remapping starts from
+    // player 1's keys in `config`, and won't take a key `config` uses for anything else.
+    pub fn handle_key(&mut self, key: Key, config: &Config) -> MenuAction {
+        self.refused = None;
+        let (button_ix, mut remapped) = match self.remap.take() {
+            None => {
+                return match key {
+                    Key::F1 | Key::Escape => MenuAction::Resume,
+                    Key::R => {
+                        self.remap = Some((0, config.joy1.clone()));
+                        MenuAction::None
+                    }
+                    _ => MenuAction::None,
+                }
+            }
+            Some(remap) => remap,
+        };
+
+        // Back to the menu, keeping the old controls.
+        if key == Key::Escape {
+            return MenuAction::None;
+        }
+
+        // Ask for the same button again if the key's taken.
+        let given = Bindings::BUTTONS[..button_ix]
+            .iter()
+            .any(|button| remapped.key(*button) == key);
+        if given || config.joy1_key_taken(key) {
+            self.refused = Some(key);
+            self.remap = Some((button_ix, remapped));
+            return MenuAction::None;
+        }
+
+        remapped.set(Bindings::BUTTONS[button_ix], key);
+        if button_ix + 1 < Bindings::BUTTONS.len() {
+            self.remap = Some((button_ix + 1, remapped));
+            return MenuAction::None;
+        }
+        MenuAction::Remapped(remapped)
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        match self.remap {
+            None => vec![
+                String::from("PAUSED"),
+                String::from("R   REMAP CONTROLS"),
+                String::from("F1  RESUME"),
+            ],
+            Some((button_ix, _)) => {
+                let button = format!("{:?}", Bindings::BUTTONS[button_ix]).to_uppercase();
+                let mut lines = vec![
+                    String::from("REMAP CONTROLS"),
+                    format!("PRESS A KEY FOR {}", button),
+                    String::from("ESCAPE CANCELS"),
+                ];
+                if let Some(key) = self.refused {
+                    lines.push(format!("{:?} IS ALREADY IN USE", key).to_uppercase());
+                }
+                lines
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::fs::write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::Serialize;
+
+// Records how long each thread spends on each part of a frame, in Chrome's trace event format, so
+// pacing problems can be looked at on a timeline in chrome://tracing or Perfetto.  A disabled
+// tracer doesn't even read the clock.
+
+// Several minutes of play.  Spans past this are dropped, so a long session can't eat all memory.
+const MAX_EVENTS: usize = 2_000_000;
+
+// Which timeline row a span goes on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Track {
+    Ui,
+    Audio,
+    // Numbered in the order the emulators were started.
+    Emulator(usize),
+}
+
+impl Track {
+    fn tid(self) -> u64 {
+        match self {
+            Track::Ui => 0,
+            Track::Audio => 1,
+            Track::Emulator(ix) => 2 + ix as u64,
+        }
+    }
+
+    fn name(self) -> String {
+        match self {
+            Track::Ui => String::from("ui"),
+            Track::Audio => String::from("audio"),
+            Track::Emulator(ix) => format!("emulator {}", ix),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ThreadName {
+    name: String,
+}
+
+// Times are in microseconds.
+#[derive(Serialize)]
+struct TraceEvent {
+    name: &'static str,
+    ph: &'static str,
+    ts: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<f64>,
+    pid: u32,
+    tid: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<ThreadName>,
+}
+
+#[derive(Serialize)]
+struct TraceFile<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: &'a [TraceEvent],
+    #[serde(rename = "displayTimeUnit")]
+    display_time_unit: &'static str,
+}
+
+struct TraceLog {
+    start: Instant,
+    events: Vec<TraceEvent>,
+    tracks: Vec<Track>,
+    dropped: u64,
+}
+
+// Cheap to clone, and each clone can be moved to a different thread.
+#[derive(Clone, Default)]
+pub struct Tracer {
+    log: Option<Arc<Mutex<TraceLog>>>,
+}
+
+impl Tracer {
+    pub fn enabled() -> Tracer {
+        Tracer {
+            log: Some(Arc::new(Mutex::new(TraceLog {
+                start: Instant::now(),
+                events: vec![],
+                tracks: vec![],
+                dropped: 0,
+            }))),
+        }
+    }
+
+    // Times from now until the span is dropped.
+    pub fn span(&self, track: Track, name: &'static str) -> Span<'_> {
+        Span {
+            tracer: self,
+            track,
+            name,
+            start: self.log.as_ref().map(|_| Instant::now()),
+        }
+    }
+
+    fn record(&self, track: Track, name: &'static str, start: Instant, end: Instant) {
+        let log = match self.log {
+            None => return,
+            Some(ref log) => log,
+        };
+        let mut log = log.lock().expect("Could not lock mutex");
+        if log.events.len() >= MAX_EVENTS {
+            log.dropped += 1;
+            return;
+        }
+
+        // Each row is named the first time it's used.
+        if !log.tracks.contains(&track) {
+            log.tracks.push(track);
+            log.events.push(TraceEvent {
+                name: "thread_name",
+                ph: "M",
+                ts: 0.0,
+                dur: None,
+                pid: 1,
+                tid: track.tid(),
+                args: Some(ThreadName { name: track.name() }),
+            });
+        }
+
+        let micros = |instant: Instant| {
+            instant.saturating_duration_since(log.start).as_secs_f64() * 1_000_000.0
+        };
+        let (start, end) = (micros(start), micros(end));
+        log.events.push(TraceEvent {
+            name,
+            ph: "X",
+            ts: start,
+            dur: Some(end - start),
+            pid: 1,
+            tid: track.tid(),
+            args: None,
+        });
+    }
+
+    // Writes everything recorded so far as JSON.  Does nothing if tracing is off.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let log = match self.log {
+            None => return Ok(()),
+            Some(ref log) => log,
+        };
+        let log = log.lock().expect("Could not lock mutex");
+        let file = TraceFile {
+            trace_events: &log.events,
+            display_time_unit: "ms",
+        };
+        let (json, dropped) = (serde_json::to_string(&file), log.dropped);
+        write(path, json.map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        if dropped > 0 {
+            println!("Trace was full, {} spans were dropped", dropped);
+        }
+        Ok(())
+    }
+}
+
+pub struct Span<'a> {
+    tracer: &'a Tracer,
+    track: Track,
+    name: &'static str,
+    start: Option<Instant>,
+}
+
+impl Drop for Span<'_> {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            self.tracer
+                .record(self.track, self.name, start, Instant::now());
+        }
+    }
+}
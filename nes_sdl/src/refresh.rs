@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+// Shows each frame for the right number of refreshes on monitors faster than the NES, e.g. two
+// at 120Hz and alternately two and three at 144Hz.  The emulator is paced by the audio rather
+// than the display, so putting frames up on whichever refresh follows their arrival would make
+// scrolling judder as the number of refreshes per frame wanders.
+
+// Refresh rates within this much of the emulator's are really the same, give or take a driver's
+// rounding, so every refresh just takes the newest frame.
+const SAME_RATE_TOLERANCE: f64 = 0.02;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cadence {
+    // Both in millihertz, so the pattern of refreshes repeats exactly.
+    frame_mhz: u64,
+    refresh_mhz: u64,
+    // Counts up by the frame rate each refresh, and a frame is due on reaching the refresh rate.
+    phase: u64,
+}
+
+impl Cadence {
+    pub fn new(frame_hz: f64, refresh_hz: f64) -> Cadence {
+        let refresh_hz = if refresh_hz > frame_hz * (1.0 + SAME_RATE_TOLERANCE) {
+            refresh_hz
+        } else {
+            frame_hz
+        };
+        let frame_mhz = (frame_hz * 1000.0).round() as u64;
+        let refresh_mhz = (refresh_hz * 1000.0).round() as u64;
+        Cadence {
+            frame_mhz,
+            refresh_mhz,
+            // So the very first refresh takes a frame.
+            phase: refresh_mhz - frame_mhz,
+        }
+    }
+
+    // False if every refresh gets a new frame anyway.
+    pub fn is_active(&self) -> bool {
+        self.refresh_mhz > self.frame_mhz
+    }
+
+    pub fn refreshes_per_frame(&self) -> f64 {
+        self.refresh_mhz as f64 / self.frame_mhz as f64
+    }
+
+    // Longest to wait for a frame before presenting what's already there, so as not to miss a
+    // refresh.
+    pub fn frame_timeout(&self) -> Duration {
+        Duration::from_secs_f64(1000.0 / self.refresh_mhz as f64)
+    }
+
+    // Moves on to the next refresh, returning whether a new frame should go up on it.
+    pub fn next_refresh(&mut self) -> bool {
+        self.phase += self.frame_mhz;
+        if self.phase >= self.refresh_mhz {
+            self.phase -= self.refresh_mhz;
+            true
+        } else {
+            false
+        }
+    }
+
+    // The frame due on this refresh hadn't arrived yet, so take it on the next one instead.
+    pub fn frame_late(&mut self) {
+        self.phase = self.refresh_mhz - self.frame_mhz;
+    }
+
+    // How much of the newest frame to show over the one before, when blending between them.  It
+    // fades in over the refreshes the frame is up for and is fully shown on the last of them.
+    pub fn blend(&self) -> f64 {
+        ((self.phase + self.frame_mhz) as f64 / self.refresh_mhz as f64).min(1.0)
+    }
+}
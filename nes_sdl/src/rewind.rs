@@ -0,0 +1,56 @@
+use std::collections::VecDeque;
+
+use nes::emulator::state::{NESState, SaveState};
+use nes::emulator::NES;
+
+// Keeps a bounded history of save states so gameplay can be stepped backwards.
+pub struct Rewind {
+    interval_frames: u64,
+    capacity: usize,
+    states: VecDeque<NESState>,
+    last_snapshot_frame: u64,
+}
+
+impl Rewind {
+    pub fn new(interval_frames: u64, capacity: usize) -> Rewind {
+        Rewind {
+            interval_frames,
+            capacity,
+            states: VecDeque::with_capacity(capacity),
+            last_snapshot_frame: 0,
+        }
+    }
+
+    // Called after running some emulation.  Takes a snapshot once every `interval_frames`
+    // emulated frames, dropping the oldest if the buffer is full.
+    pub fn record(&mut self, nes: &mut NES) {
+        let frame = nes.ppu.borrow().stats().frame_count;
+        // Mid-DMA states can't be restored, so try again after the next run.
+        if frame < self.last_snapshot_frame + self.interval_frames || !nes.at_instruction_boundary()
+        {
+            return;
+        }
+
+        if self.states.len() >= self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(nes.freeze());
+        self.last_snapshot_frame = frame;
+    }
+
+    // Restores the most recent snapshot.  Returns false once history runs out.
+    pub fn step_back(&mut self, nes: &mut NES) -> bool {
+        match self.states.pop_back() {
+            None => false,
+            Some(state) => {
+                nes.hydrate(state);
+                self.last_snapshot_frame = nes.ppu.borrow().stats().frame_count;
+                true
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+}
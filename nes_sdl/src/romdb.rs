@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::fs::{read, read_to_string, write};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use nes::emulator::ines::{Header, ROM};
+use nes::emulator::ppu::MirrorMode;
+use nes::emulator::Region;
+
+use crate::config::config_dir;
+
+// Known-good header values and settings for a game.  Anything left out is kept from the ROM's own
+// header.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameEntry {
+    pub name: Option<String>,
+    pub mapper: Option<u16>,
+    pub submapper: Option<u8>,
+    pub mirroring: Option<MirrorMode>,
+    pub four_screen: Option<bool>,
+    pub battery: Option<bool>,
+    pub region: Option<Region>,
+    // Bytes of work RAM at $6000-$7FFF, e.g. 2048 or 4096 for Family BASIC.
+    pub prg_ram: Option<u32>,
+    // Mixing level for the cartridge's expansion audio, relative to the APU.
+    pub expansion_gain: Option<f32>,
+    // Whether the board has work RAM at $4020-$5FFF.  There's no header field for it.
+    pub expansion_ram: Option<bool>,
+}
+
+impl GameEntry {
+    pub fn apply(&self, header: &mut Header) {
+        if let Some(mapper) = self.mapper {
+            header.mapper = mapper;
+        }
+        if let Some(submapper) = self.submapper {
+            header.submapper = submapper;
+        }
+        if let Some(mirroring) = self.mirroring {
+            header.mirroring = mirroring;
+        }
+        if let Some(four_screen) = self.four_screen {
+            header.four_screen = four_screen;
+        }
+        if let Some(battery) = self.battery {
+            header.battery = battery;
+        }
+        if let Some(region) = self.region {
+            header.region = region;
+        }
+        if let Some(size) = self.prg_ram {
+            header.prg_ram_size = size;
+        }
+    }
+}
+
+// Games keyed by the CRC32 of their PRG and CHR data, as 8 hex digits.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RomDb {
+    pub games: HashMap<String, GameEntry>,
+}
+
+impl RomDb {
+    pub fn lookup(&self, rom: &ROM) -> Option<&GameEntry> {
+        let key = format!("{:08X}", rom.crc32());
+        self.games
+            .iter()
+            .find(|(crc, _)| crc.eq_ignore_ascii_case(&key))
+            .map(|(_, entry)| entry)
+    }
+}
+
+// The ROM with its header corrected from the database, if the game is known.
+pub fn apply_romdb(db: &RomDb, rom: ROM) -> ROM {
+    match db.lookup(&rom) {
+        None => rom,
+        Some(entry) => {
+            let mut header = rom.header();
+            entry.apply(&mut header);
+            rom.with_header(&header)
+        }
+    }
+}
+
+pub fn default_romdb_path() -> PathBuf {
+    let mut path = config_dir();
+    path.push("romdb.toml");
+    path
+}
+
+// As with the config, a missing database just means we know no games.
+pub fn load_romdb(path: &PathBuf) -> Result<RomDb, String> {
+    let contents = match read_to_string(path) {
+        Err(_) => return Ok(RomDb::default()),
+        Ok(contents) => contents,
+    };
+    toml::from_str(&contents).map_err(|e| e.to_string())
+}
+
+// Rewrites `in_path` with a clean NES 2.0 header, taking corrections from the database where the
+// game is known.  Returns a description of what was done.
+pub fn fix_header(in_path: &str, out_path: &str, db: &RomDb) -> Result<String, String> {
+    let data = read(in_path).map_err(|e| e.to_string())?;
+    if data.len() < 16 || &data[0..4] != b"NES\x1A" {
+        return Err(format!("{} is not an iNES ROM", in_path));
+    }
+
+    let rom = ROM::from_bytes(data);
+    let original = rom.header();
+    let mut header = original.clone();
+
+    let mut report = rom.fingerprint().to_string();
+    match db.lookup(&rom) {
+        Some(entry) => {
+            entry.apply(&mut header);
+            if let Some(ref name) = entry.name {
+                report.push_str(&format!(": {}", name));
+            }
+        }
+        None => report.push_str(": not in database, cleaning header only"),
+    }
+
+    let fixed = rom.with_header(&header);
+    for problem in fixed.diagnostics() {
+        report.push_str(&format!("\n  {}", problem));
+    }
+
+    if header != original {
+        report.push_str(&format!("\n  was {:?}\n  now {:?}", original, header));
+    }
+
+    write(out_path, fixed.bytes()).map_err(|e| e.to_string())?;
+    Ok(report)
+}
@@ -0,0 +1,165 @@
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::config_dir;
+
+// Shaking the host gamepad.  Requests come from the emulator thread, e.g. from memory watches, and
+// are played on the UI thread, since that's where SDL lives.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rumble {
+    // 0.0 to 1.0.
+    pub strength: f32,
+    pub duration_ms: u32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Decrease,
+    Increase,
+    Any,
+}
+
+// Rumble whenever the byte at `address` changes in the given direction, e.g. when the lives
+// counter goes down.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RumbleTrigger {
+    pub address: u16,
+    pub when: Change,
+    pub strength: f32,
+    pub duration_ms: u32,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct RumbleFile {
+    #[serde(default)]
+    trigger: Vec<RumbleTrigger>,
+}
+
+// Triggers for a game live in `<config dir>/rumble/<rom>.toml`.
+pub fn load_rumble_triggers(rom_name: &str) -> Result<Vec<RumbleTrigger>, String> {
+    let mut path = config_dir();
+    path.push("rumble");
+    path.push(format!("{}.toml", rom_name));
+    let text = match read_to_string(&path) {
+        Ok(text) => text,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.to_string()),
+    };
+    let file: RumbleFile = toml::from_str(&text).map_err(|e| e.to_string())?;
+    Ok(file.trigger)
+}
+
+pub struct RumbleWatcher {
+    triggers: Vec<RumbleTrigger>,
+    last_values: Vec<Option<u8>>,
+}
+
+impl RumbleWatcher {
+    pub fn new(triggers: Vec<RumbleTrigger>) -> RumbleWatcher {
+        let last_values = vec![None; triggers.len()];
+        RumbleWatcher {
+            triggers,
+            last_values,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    // Compares memory against what it was last time.  Returns the strongest rumble triggered.
+    pub fn check<F: Fn(u16) -> u8>(&mut self, peek: F) -> Option<Rumble> {
+        let mut rumble: Option<Rumble> = None;
+        for (trigger, last) in self.triggers.iter().zip(self.last_values.iter_mut()) {
+            let value = peek(trigger.address);
+            let fired = match *last {
+                None => false,
+                Some(old) => match trigger.when {
+                    Change::Decrease => value < old,
+                    Change::Increase => value > old,
+                    Change::Any => value != old,
+                },
+            };
+            *last = Some(value);
+
+            let stronger = match rumble {
+                None => true,
+                Some(r) => trigger.strength > r.strength,
+            };
+            if fired && stronger {
+                rumble = Some(Rumble {
+                    strength: trigger.strength,
+                    duration_ms: trigger.duration_ms,
+                });
+            }
+        }
+        rumble
+    }
+
+    // Forgets the old values, e.g. after loading a state, so the jump doesn't count as a change.
+    pub fn reset(&mut self) {
+        for last in self.last_values.iter_mut() {
+            *last = None;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RumbleSender {
+    sender: Sender<Rumble>,
+}
+
+impl RumbleSender {
+    pub fn rumble(&self, rumble: Rumble) {
+        // Nobody listening just means there's no window.
+        let _ = self.sender.send(rumble);
+    }
+}
+
+pub struct RumbleReceiver {
+    receiver: Receiver<Rumble>,
+}
+
+pub fn rumble_channel() -> (RumbleSender, RumbleReceiver) {
+    let (sender, receiver) = channel();
+    (RumbleSender { sender }, RumbleReceiver { receiver })
+}
+
+// The first gamepad which can rumble, if any.
+pub struct RumbleDevice {
+    haptic: Option<sdl2::haptic::Haptic>,
+    requests: RumbleReceiver,
+    _joystick: Option<sdl2::JoystickSubsystem>,
+}
+
+impl RumbleDevice {
+    pub fn new(sdl_context: &sdl2::Sdl, requests: RumbleReceiver) -> RumbleDevice {
+        let joystick = sdl_context.joystick().ok();
+        let count = joystick
+            .as_ref()
+            .and_then(|j| j.num_joysticks().ok())
+            .unwrap_or(0);
+        let haptic = sdl_context
+            .haptic()
+            .ok()
+            .and_then(|haptic| (0..count).find_map(|ix| haptic.open_from_joystick_id(ix).ok()));
+        RumbleDevice {
+            haptic,
+            requests,
+            _joystick: joystick,
+        }
+    }
+
+    // Plays the latest request.  Older ones would be cut off by it anyway.
+    pub fn play(&mut self) {
+        let latest = self.requests.receiver.try_iter().last();
+        if let (Some(haptic), Some(rumble)) = (self.haptic.as_mut(), latest) {
+            haptic.rumble_play(rumble.strength.clamp(0.0, 1.0), rumble.duration_ms);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs::{create_dir_all, write};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dirs;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use nes::emulator::io::Screen;
+use nes::emulator::ppu::debug::PPUDebug;
+use nes::emulator::ppu::{FRAME_HEIGHT, FRAME_WIDTH};
+use nes::emulator::util::crc32;
+
+pub fn default_screenshot_dir() -> PathBuf {
+    let mut path = match dirs::data_dir() {
+        Some(path) => path,
+        None => panic!("Couldn't get data dir!"),
+    };
+
+    path.push("nes");
+    path.push("screenshots");
+    path
+}
+
+fn unix_millis() -> Result<u128, String> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_millis())
+        .map_err(|e| e.to_string())
+}
+
+// Writes the current picture to `<dir>/<name>-<unix time in ms>.png` and returns the path.
+pub fn save_screenshot(screen: &Screen, dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let mut path = dir.to_path_buf();
+    path.push(format!("{}-{}.png", name, unix_millis()?));
+
+    let mut png = Ok(vec![]);
+    screen.do_render(|rgb| png = encode_png(FRAME_WIDTH as u32, FRAME_HEIGHT as u32, rgb));
+
+    create_dir_all(dir).map_err(|e| e.to_string())?;
+    write(&path, png?).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+// Writes the pattern tables and all four nametables next to the screenshots, as
+// `<name>-<unix time in ms>-patterns.png` and `...-nametable<n>.png`.  Returns the paths.
+pub fn save_tile_export(
+    ppu_debug: &mut PPUDebug,
+    dir: &Path,
+    name: &str,
+) -> Result<Vec<PathBuf>, String> {
+    let prefix = format!("{}-{}", name, unix_millis()?);
+    let mut images = vec![(
+        format!("{}-patterns.png", prefix),
+        encode_png(
+            PPUDebug::PATTERN_WIDTH as u32,
+            PPUDebug::PATTERN_HEIGHT as u32,
+            &ppu_debug.export_pattern_tables(0),
+        )?,
+    )];
+    for table in 0..4 {
+        images.push((
+            format!("{}-nametable{}.png", prefix, table),
+            encode_png(
+                FRAME_WIDTH as u32,
+                FRAME_HEIGHT as u32,
+                &ppu_debug.export_nametable(table),
+            )?,
+        ));
+    }
+
+    create_dir_all(dir).map_err(|e| e.to_string())?;
+    let mut paths = vec![];
+    for (file_name, png) in images {
+        let mut path = dir.to_path_buf();
+        path.push(file_name);
+        write(&path, png).map_err(|e| e.to_string())?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+// Just enough PNG to hold an 8-bit RGB image: a header, one compressed data chunk and an end
+// marker.
+pub fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Result<Vec<u8>, String> {
+    let stride = width as usize * 3;
+    if rgb.len() != stride * height as usize {
+        return Err(format!(
+            "Expected {} bytes of pixels, got {}",
+            stride * height as usize,
+            rgb.len()
+        ));
+    }
+
+    let mut header = vec![];
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, truecolour, default compression and filtering, no interlacing.
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    // Each row starts with its filter type, and we don't filter.
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    for row in rgb.chunks(stride) {
+        encoder.write_all(&[0]).map_err(|e| e.to_string())?;
+        encoder.write_all(row).map_err(|e| e.to_string())?;
+    }
+    let data = encoder.finish().map_err(|e| e.to_string())?;
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &data);
+    write_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::fs::read_to_string;
+use std::rc::Rc;
+
+use rhai::{Engine, EvalAltResult, FnPtr, AST, INT};
+
+use nes::emulator::watchpoints::{Access, MemoryAccess};
+use nes::emulator::NES;
+
+// Rhai scripts which watch and drive the game, for speedrun research and bots.  The script runs
+// once when it's loaded, registering callbacks as it goes:
+//
+//   on_frame(|frame| ...)                    after every frame
+//   on_read(address, |address, value| ...)   after an instruction reads the address
+//   on_write(address, |address, value| ...)  after an instruction writes it
+//
+// Anywhere in the script, peek(address) and poke(address, value) get at CPU memory the same way
+// the memory viewer does, frame() is the PPU's frame count, and buttons(player) and
+// set_buttons(player, mask) read and hold a controller's buttons.  Players count from 1, and masks
+// are in strobe order, so 1 is A and 128 is Right.  Held buttons stay held until set again or the
+// keyboard changes them, and a movie being played overrides them.
+
+#[derive(Default)]
+struct Hooks {
+    frame: Vec<FnPtr>,
+    reads: Vec<(u16, FnPtr)>,
+    writes: Vec<(u16, FnPtr)>,
+}
+
+pub struct Script {
+    path: String,
+    engine: Engine,
+    ast: AST,
+    hooks: Rc<RefCell<Hooks>>,
+    // How many of the read and write hooks have had their addresses watched.
+    watched_reads: usize,
+    watched_writes: usize,
+}
+
+impl Script {
+    // Runs the script, then watches the addresses it asked about.
+    pub fn load(path: &str, nes: &mut NES) -> Result<Script, String> {
+        let source = read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+        let hooks = Rc::new(RefCell::new(Hooks::default()));
+        let mut engine = Engine::new();
+        register_api(&mut engine, nes, &hooks);
+
+        let ast = engine.compile(&source).map_err(|e| e.to_string())?;
+        engine.run_ast(&ast).map_err(|e| e.to_string())?;
+
+        let mut script = Script {
+            path: path.to_string(),
+            engine,
+            ast,
+            hooks,
+            watched_reads: 0,
+            watched_writes: 0,
+        };
+        script.watch_new_hooks(nes);
+        Ok(script)
+    }
+
+    // Callbacks can register more callbacks, so this runs after each batch of them.
+    fn watch_new_hooks(&mut self, nes: &mut NES) {
+        let hooks = self.hooks.borrow();
+        for (address, _) in hooks.reads[self.watched_reads..].iter() {
+            nes.add_watchpoint(*address, Access::Read);
+        }
+        for (address, _) in hooks.writes[self.watched_writes..].iter() {
+            nes.add_watchpoint(*address, Access::Write);
+        }
+        self.watched_reads = hooks.reads.len();
+        self.watched_writes = hooks.writes.len();
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // Takes the script's watchpoints back off, once it's failed or is being replaced.
+    pub fn unload(&self, nes: &mut NES) {
+        for (address, _) in self.hooks.borrow().reads.iter() {
+            nes.remove_watchpoint(*address, Access::Read);
+        }
+        for (address, _) in self.hooks.borrow().writes.iter() {
+            nes.remove_watchpoint(*address, Access::Write);
+        }
+    }
+
+    pub fn memory_accesses(
+        &mut self,
+        nes: &mut NES,
+        accesses: &[MemoryAccess],
+    ) -> Result<(), String> {
+        for access in accesses {
+            let hooks = match access.access {
+                Access::Read => self.matching(|hooks| &hooks.reads, access.address),
+                Access::Write => self.matching(|hooks| &hooks.writes, access.address),
+            };
+            for hook in hooks {
+                self.call(&hook, (access.address as INT, access.value as INT))?;
+            }
+        }
+        self.watch_new_hooks(nes);
+        Ok(())
+    }
+
+    pub fn end_frame(&mut self, nes: &mut NES, frame: u64) -> Result<(), String> {
+        let hooks = self.hooks.borrow().frame.clone();
+        for hook in hooks {
+            self.call(&hook, (frame as INT,))?;
+        }
+        self.watch_new_hooks(nes);
+        Ok(())
+    }
+
+    // Copied out, since the hook list can't stay borrowed while callbacks add to it.
+    fn matching<F>(&self, list: F, address: u16) -> Vec<FnPtr>
+    where
+        F: Fn(&Hooks) -> &Vec<(u16, FnPtr)>,
+    {
+        list(&self.hooks.borrow())
+            .iter()
+            .filter(|(watched, _)| *watched == address)
+            .map(|(_, hook)| hook.clone())
+            .collect()
+    }
+
+    fn call(&self, hook: &FnPtr, args: impl rhai::FuncArgs) -> Result<(), String> {
+        hook.call::<()>(&self.engine, &self.ast, args)
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn register_api(engine: &mut Engine, nes: &NES, hooks: &Rc<RefCell<Hooks>>) {
+    let memory = nes.debug_memory();
+    engine.register_fn("peek", move |address: INT| {
+        memory.peek(address as u16) as INT
+    });
+    let memory = nes.debug_memory();
+    engine.register_fn("poke", move |address: INT, value: INT| {
+        memory.poke(address as u16, value as u8)
+    });
+
+    let ppu = nes.ppu.clone();
+    engine.register_fn("frame", move || ppu.borrow().stats().frame_count as INT);
+
+    let joypads = nes.joypads();
+    engine.register_fn(
+        "buttons",
+        move |player: INT| -> Result<INT, Box<EvalAltResult>> {
+            let joypad = joypad(&joypads, player)?;
+            let buttons = joypad.borrow().buttons();
+            Ok(buttons as INT)
+        },
+    );
+    let joypads = nes.joypads();
+    engine.register_fn(
+        "set_buttons",
+        move |player: INT, mask: INT| -> Result<(), Box<EvalAltResult>> {
+            joypad(&joypads, player)?
+                .borrow_mut()
+                .set_buttons(mask as u8);
+            Ok(())
+        },
+    );
+
+    let frame_hooks = hooks.clone();
+    engine.register_fn("on_frame", move |hook: FnPtr| {
+        frame_hooks.borrow_mut().frame.push(hook)
+    });
+    let read_hooks = hooks.clone();
+    engine.register_fn("on_read", move |address: INT, hook: FnPtr| {
+        read_hooks.borrow_mut().reads.push((address as u16, hook))
+    });
+    let write_hooks = hooks.clone();
+    engine.register_fn("on_write", move |address: INT, hook: FnPtr| {
+        write_hooks.borrow_mut().writes.push((address as u16, hook))
+    });
+}
+
+fn joypad<T: Clone>(joypads: &[T; 4], player: INT) -> Result<T, Box<EvalAltResult>> {
+    match player {
+        1..=4 => Ok(joypads[player as usize - 1].clone()),
+        _ => Err(format!("There's no player {}", player).into()),
+    }
+}
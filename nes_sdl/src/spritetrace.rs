@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use nes::emulator::io::event::Key;
+use nes::emulator::ppu::debug::{SpriteEvalPhase, SpriteEvalTrace};
+use nes::emulator::NES;
+
+// Shows how the PPU picked sprites on one scanline: every OAM byte it compared, which sprites made
+// it into secondary OAM and where the overflow flag got set.
+
+const LAST_VISIBLE_SCANLINE: u16 = 239;
+
+// As many checks as fit in the debug window under the summary.
+const MAX_CHECK_LINES: usize = 60;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpriteTraceView {
+    scanline: u16,
+}
+
+impl SpriteTraceView {
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    // Up and down step through scanlines, -/= jump 16 at a time.  Returns whether the key was used.
+    pub fn handle_key(&mut self, key: Key, nes: &mut NES) -> bool {
+        let delta = match key {
+            Key::Up => -1,
+            Key::Down => 1,
+            Key::Minus => -16,
+            Key::Equals => 16,
+            _ => return false,
+        };
+        self.scanline =
+            (i32::from(self.scanline) + delta).clamp(0, i32::from(LAST_VISIBLE_SCANLINE)) as u16;
+        nes.ppu
+            .borrow_mut()
+            .set_sprite_trace_scanline(Some(self.scanline));
+        true
+    }
+
+    pub fn lines(&self, nes: &NES) -> Vec<String> {
+        let ppu = nes.ppu.borrow();
+        let mut lines = vec![format!("SCANLINE {}", self.scanline)];
+        match ppu.sprite_trace() {
+            Some(trace) if trace.scanline == self.scanline => lines.extend(trace_lines(trace)),
+            _ => lines.push(String::from("WAITING FOR SCANLINE")),
+        }
+        lines
+    }
+}
+
+fn trace_lines(trace: &SpriteEvalTrace) -> Vec<String> {
+    let copied: Vec<String> = trace.copied.iter().map(|n| format!("{:02X}", n)).collect();
+    let mut lines = vec![format!("COPIED {}", copied.join(" "))];
+    lines.push(match trace.overflow {
+        Some(check) => format!(
+            "OVERFLOW AT DOT {} N {:02X} M {}",
+            check.dot, check.n, check.m
+        ),
+        None => String::from("NO OVERFLOW"),
+    });
+
+    lines.push(String::from("DOT PHASE N  M BYTE"));
+    for check in trace.checks.iter().take(MAX_CHECK_LINES) {
+        let phase = match check.phase {
+            SpriteEvalPhase::Copy => "COPY",
+            SpriteEvalPhase::Overflow => "OVER",
+        };
+        lines.push(format!(
+            "{:3} {}  {:02X} {} {:02X} {}",
+            check.dot,
+            phase,
+            check.n,
+            check.m,
+            check.byte,
+            if check.in_range { "IN" } else { "OUT" }
+        ));
+    }
+    if trace.checks.len() > MAX_CHECK_LINES {
+        lines.push(format!("+{} MORE", trace.checks.len() - MAX_CHECK_LINES));
+    }
+    lines
+}
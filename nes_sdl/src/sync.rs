@@ -0,0 +1,117 @@
+use crate::governer::MovingAverage;
+
+// The sound card and the governer run off different clocks, so over a long session the audio
+// queue slowly fills up or runs dry.  This watches how much audio is queued once per frame and
+// nudges the emulator to keep it near a target.
+
+// How much audio we aim to keep queued ahead of the sound card.
+const TARGET_BUFFERED_MS: f64 = 50.0;
+
+// Largest change to the number of samples produced per frame.  Half a percent is well below the
+// point where anyone could hear the pitch change.
+const MAX_RESAMPLE_ADJUST: f64 = 0.005;
+
+// Past this much drift, resampling is too slow to catch up and we drop or duplicate a frame.
+const FRAME_CORRECTION_MS: f64 = 40.0;
+
+// What the main loop should do this frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Correction {
+    None,
+    // The audio queue is running dry, so run two frames and only present the second.
+    DropFrame,
+    // Too much audio is queued, so present the last frame again without running.
+    DuplicateFrame,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SyncStats {
+    // Positive when more audio is queued than we'd like, i.e. sound lags the picture.
+    pub drift_ms: f64,
+    pub resample_ratio: f64,
+    pub dropped_frames: u64,
+    pub duplicated_frames: u64,
+}
+
+pub struct SyncMonitor {
+    sample_rate: f64,
+    samples_per_frame: f64,
+    drift: MovingAverage,
+    resample_ratio: f64,
+    // Frames to wait after a correction before trusting the drift again.
+    settle_frames: u64,
+    fps: u64,
+    dropped_frames: u64,
+    duplicated_frames: u64,
+}
+
+impl SyncMonitor {
+    pub fn new(sample_rate: f32, fps: u64) -> SyncMonitor {
+        SyncMonitor {
+            sample_rate: sample_rate as f64,
+            samples_per_frame: (sample_rate as f64) / (fps as f64),
+            drift: MovingAverage::new(fps as usize),
+            resample_ratio: 1.0,
+            settle_frames: fps,
+            fps,
+            dropped_frames: 0,
+            duplicated_frames: 0,
+        }
+    }
+
+    // Called once per frame with the amount of audio waiting to be played.
+    pub fn update(&mut self, buffered_samples: usize) -> Correction {
+        let target = self.ms_to_samples(TARGET_BUFFERED_MS);
+        self.drift.update(buffered_samples as f64 - target);
+
+        // The average needs a full window of samples before it means anything.
+        if self.settle_frames > 0 {
+            self.settle_frames -= 1;
+            return Correction::None;
+        }
+
+        let drift = self.drift.get();
+        let error = (drift / target).clamp(-1.0, 1.0);
+        self.resample_ratio = 1.0 - error * MAX_RESAMPLE_ADJUST;
+
+        let limit = self.ms_to_samples(FRAME_CORRECTION_MS);
+        let correction = if drift > limit {
+            self.duplicated_frames += 1;
+            Correction::DuplicateFrame
+        } else if drift < -limit {
+            self.dropped_frames += 1;
+            Correction::DropFrame
+        } else {
+            Correction::None
+        };
+
+        if correction != Correction::None {
+            self.reset();
+        }
+        correction
+    }
+
+    // Starts over, e.g. after the audio has been paused and the queue has drained.
+    pub fn reset(&mut self) {
+        self.drift = MovingAverage::new(self.fps as usize);
+        self.settle_frames = self.fps;
+    }
+
+    // How many samples to ask the emulator for this frame.
+    pub fn samples_for_frame(&self) -> f64 {
+        self.samples_per_frame * self.resample_ratio
+    }
+
+    pub fn stats(&self) -> SyncStats {
+        SyncStats {
+            drift_ms: self.drift.get() * 1000.0 / self.sample_rate,
+            resample_ratio: self.resample_ratio,
+            dropped_frames: self.dropped_frames,
+            duplicated_frames: self.duplicated_frames,
+        }
+    }
+
+    fn ms_to_samples(&self, ms: f64) -> f64 {
+        self.sample_rate * ms / 1000.0
+    }
+}
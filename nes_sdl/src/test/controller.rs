@@ -0,0 +1,236 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs::remove_file;
+use std::rc::Rc;
+
+use nes::emulator::config::Config as EmulatorConfig;
+use nes::emulator::controller::{Button, Controller as Joypad};
+use nes::emulator::ines::ROM;
+use nes::emulator::io::event::{Event, EventBus, Key};
+use nes::emulator::io::{Screen, SimpleAudioOut};
+use nes::emulator::NES;
+
+use crate::config::{Bindings, Config};
+use crate::controller::{Controller, DebugMode, EmulatorState};
+use crate::pausemenu::{MenuAction, PauseMenu};
+use crate::portal::Portal;
+
+struct Harness {
+    controller: Rc<RefCell<Controller>>,
+    event_bus: Rc<RefCell<EventBus>>,
+    state: Portal<EmulatorState>,
+    joy1: Rc<RefCell<Joypad>>,
+}
+
+impl Harness {
+    // A cartridge of NOPs, wired up the way the emulator thread does it.
+    fn new() -> Harness {
+        Harness::with_prg(vec![0xEA; 0x4000])
+    }
+
+    // Runs the given 16KB of PRG-ROM instead.
+    fn with_prg(prg: Vec<u8>) -> Harness {
+        let mut data = vec![
+            b'N', b'E', b'S', 0x1A, 1, 1, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        data.extend(prg);
+        data.extend(vec![0; 0x2000]);
+        let rom = ROM::from_bytes(data);
+
+        let event_bus = Rc::new(RefCell::new(EventBus::new()));
+        let screen = Rc::new(RefCell::new(Screen::new()));
+        let audio = Rc::new(RefCell::new(SimpleAudioOut::new(44_100.0)));
+        let config = EmulatorConfig::default();
+        let nes = NES::new(
+            event_bus.clone(),
+            screen.clone(),
+            audio.clone(),
+            rom,
+            &config,
+        );
+        let joy1 = nes.joy1.clone();
+        let state = Portal::new(EmulatorState::new());
+        let controller = Controller::new(nes, Config::default(), screen, audio, state.clone());
+        let controller = Rc::new(RefCell::new(controller));
+        event_bus
+            .borrow_mut()
+            .register(Box::new(controller.clone()));
+        Harness {
+            controller,
+            event_bus,
+            state,
+            joy1,
+        }
+    }
+
+    fn key_down(&self, key: Key) {
+        self.event_bus.borrow_mut().broadcast(Event::KeyDown(key));
+    }
+
+    fn key_up(&self, key: Key) {
+        self.event_bus.borrow_mut().broadcast(Event::KeyUp(key));
+    }
+
+    fn menu(&self) -> Vec<String> {
+        self.state.consume(|state| state.menu.clone())
+    }
+}
+
+#[test]
+fn test_pause_menu_keeps_keys_from_the_game() {
+    let harness = Harness::new();
+    let a = Bindings::default().key(Button::A);
+    let hz = harness.controller.borrow().target_hz();
+
+    harness.key_down(Key::F1);
+    assert_eq!(harness.controller.borrow().target_hz(), 0);
+    assert_eq!(harness.menu()[0], "PAUSED");
+    harness.key_down(a);
+    assert_eq!(harness.joy1.borrow().buttons(), 0);
+    harness.key_up(a);
+
+    // Nor does the key given for a button while remapping.
+    harness.key_down(Key::R);
+    harness.key_down(a);
+    assert_eq!(harness.joy1.borrow().buttons(), 0);
+    assert_eq!(harness.menu()[1], "PRESS A KEY FOR B");
+    harness.key_up(a);
+
+    harness.key_down(Key::Escape);
+    assert_eq!(harness.menu()[0], "PAUSED");
+    harness.key_down(Key::F1);
+    assert!(harness.menu().is_empty());
+    assert_eq!(harness.controller.borrow().target_hz(), hz);
+    harness.key_down(a);
+    assert_ne!(harness.joy1.borrow().buttons(), 0);
+}
+
+#[test]
+fn test_pause_menu_remaps_every_button() {
+    let config = Config::default();
+    let keys = [
+        Key::Q,
+        Key::W,
+        Key::E,
+        Key::O,
+        Key::T,
+        Key::Y,
+        Key::U,
+        Key::I,
+    ];
+    let mut menu = PauseMenu::new(0);
+    assert!(matches!(menu.handle_key(Key::R, &config), MenuAction::None));
+    for key in keys[..7].iter() {
+        assert!(matches!(menu.handle_key(*key, &config), MenuAction::None));
+    }
+    match menu.handle_key(keys[7], &config) {
+        MenuAction::Remapped(remapped) => {
+            for (button, key) in Bindings::BUTTONS.iter().zip(keys.iter()) {
+                assert_eq!(remapped.key(*button), *key);
+            }
+        }
+        _ => panic!("Remapping didn't finish"),
+    }
+    assert_eq!(menu.lines()[0], "PAUSED");
+}
+
+#[test]
+fn test_pause_menu_refuses_keys_in_use() {
+    let mut config = Config {
+        joy2: Some(Bindings {
+            a: Key::K,
+            b: Key::L,
+            select: Key::G,
+            start: Key::H,
+            up: Key::I,
+            down: Key::J,
+            left: Key::M,
+            right: Key::N,
+        }),
+        ..Config::default()
+    };
+    let mut menu = PauseMenu::new(0);
+    menu.handle_key(Key::R, &config);
+    menu.handle_key(Key::Q, &config);
+
+    // Given to A already, a hotkey, the rewind key, and player 2's A.
+    for key in [Key::Q, Key::F12, Key::R, Key::K].iter() {
+        assert!(matches!(menu.handle_key(*key, &config), MenuAction::None));
+        assert_eq!(menu.lines()[1], "PRESS A KEY FOR B");
+        assert_eq!(
+            menu.lines()[3],
+            format!("{:?} IS ALREADY IN USE", key).to_uppercase()
+        );
+    }
+
+    // Player 1's old keys are free to move between buttons.
+    let keys = [Key::Z, Key::X, Key::S, Key::A, Key::Up, Key::Down];
+    for key in keys.iter() {
+        assert_eq!(menu.lines().len(), if *key == Key::Z { 4 } else { 3 });
+        menu.handle_key(*key, &config);
+    }
+    match menu.handle_key(Key::Left, &config) {
+        MenuAction::Remapped(remapped) => {
+            assert_eq!(remapped.key(Button::A), Key::Q);
+            assert_eq!(remapped.key(Button::B), Key::Z);
+            config.joy1 = remapped;
+            assert_eq!(config.check_keys(), Ok(()));
+        }
+        _ => panic!("Remapping didn't finish"),
+    }
+}
+
+#[test]
+fn test_config_refuses_key_bound_twice() {
+    let mut config = Config::default();
+    assert_eq!(config.check_keys(), Ok(()));
+
+    config.joy1.b = config.joy1.a;
+    assert_eq!(
+        config.check_keys(),
+        Err(String::from(
+            "Z is bound to both player 1's A and player 1's B"
+        ))
+    );
+
+    config.joy1.b = Key::F12;
+    assert_eq!(
+        config.check_keys(),
+        Err(String::from(
+            "F12 is bound to player 1's B but used for a hotkey"
+        ))
+    );
+}
+
+#[test]
+fn test_stop_finishes_save_requested_during_oam_dma() {
+    // LDA #$02; STA $4014; JMP $8000, so the CPU spends nearly all its time stalled by OAM DMA.
+    let mut prg = vec![0xEA; 0x4000];
+    prg[..8].copy_from_slice(&[0xA9, 0x02, 0x8D, 0x14, 0x40, 0x4C, 0x00, 0x80]);
+    prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+    let harness = Harness::with_prg(prg);
+
+    let mut dir = env::temp_dir();
+    dir.push("nes_sdl_test_pending_save");
+    let mut path = dir.clone();
+    path.push("dma.1.gz");
+    let _ = remove_file(&path);
+    {
+        let mut controller = harness.controller.borrow_mut();
+        controller.set_save_dir(dir);
+        controller.set_rom_name("dma");
+        controller.run_frame();
+    }
+
+    // The APU view takes number keys for muting channels.
+    harness
+        .state
+        .consume(|state| state.debug_mode = DebugMode::OFF);
+    harness.key_down(Key::Shift);
+    harness.key_down(Key::Num1);
+    assert!(!path.exists(), "Save state should wait for the DMA");
+
+    harness.controller.borrow_mut().stop();
+    assert!(path.exists());
+    let _ = remove_file(&path);
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::netplay::Link;
+
+// Stands in for the network between two netplay players, so netplay can be tested in one process.
+// Each byte takes `latency`, plus up to `jitter` more, to reach the other end.  Bytes still arrive
+// in order, as over TCP, so jitter shows up as messages arriving in bursts or split in two.  The
+// jitter comes from a seeded PRNG, so every run with the same seed delays each byte the same.
+
+#[derive(Clone, Copy, Debug)]
+pub struct Conditions {
+    pub latency: Duration,
+    pub jitter: Duration,
+    pub seed: u64,
+}
+
+// Bytes heading one way, with when each arrives.
+#[derive(Default)]
+struct Pipe {
+    bytes: VecDeque<(Instant, u8)>,
+    // The sending end has gone.
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Direction {
+    pipe: Mutex<Pipe>,
+    changed: Condvar,
+}
+
+impl Direction {
+    fn close(&self) {
+        self.pipe.lock().unwrap().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+pub struct LaggyLink {
+    incoming: Arc<Direction>,
+    outgoing: Arc<Direction>,
+    conditions: Conditions,
+    // Shared with clones of this end, so the jitter carries on rather than repeating.
+    rng: Arc<Mutex<Rng>>,
+    read_timeout: Arc<Mutex<Option<Duration>>>,
+    // The other end sees this one close once every handle on it has gone.
+    handles: Arc<()>,
+}
+
+impl LaggyLink {
+    pub fn pair(conditions: Conditions) -> (LaggyLink, LaggyLink) {
+        let there = Arc::new(Direction::default());
+        let back = Arc::new(Direction::default());
+        let end = |incoming: &Arc<Direction>, outgoing: &Arc<Direction>, seed| LaggyLink {
+            incoming: incoming.clone(),
+            outgoing: outgoing.clone(),
+            conditions,
+            rng: Arc::new(Mutex::new(Rng::new(seed))),
+            read_timeout: Arc::new(Mutex::new(None)),
+            handles: Arc::new(()),
+        };
+        (
+            end(&back, &there, conditions.seed),
+            end(&there, &back, conditions.seed + 1),
+        )
+    }
+
+    fn delay(&self) -> Duration {
+        let jitter = self.conditions.jitter.as_nanos() as u64;
+        let extra = if jitter == 0 {
+            0
+        } else {
+            self.rng.lock().unwrap().next() % (jitter + 1)
+        };
+        self.conditions.latency + Duration::from_nanos(extra)
+    }
+}
+
+impl Link for LaggyLink {
+    fn try_clone_link(&self) -> io::Result<Box<dyn Link>> {
+        Ok(Box::new(LaggyLink {
+            incoming: self.incoming.clone(),
+            outgoing: self.outgoing.clone(),
+            conditions: self.conditions,
+            rng: self.rng.clone(),
+            read_timeout: self.read_timeout.clone(),
+            handles: self.handles.clone(),
+        }))
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        *self.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+}
+
+impl Read for LaggyLink {
+    // Blocks until at least one byte has arrived, like a socket.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = self
+            .read_timeout
+            .lock()
+            .unwrap()
+            .map(|timeout| Instant::now() + timeout);
+        let mut pipe = self.incoming.pipe.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let mut count = 0;
+            while count < buf.len() {
+                match pipe.bytes.front() {
+                    Some((at, byte)) if *at <= now => {
+                        buf[count] = *byte;
+                        count += 1;
+                        pipe.bytes.pop_front();
+                    }
+                    _ => break,
+                }
+            }
+            if count > 0 || buf.is_empty() {
+                return Ok(count);
+            }
+            if pipe.bytes.is_empty() && pipe.closed {
+                return Ok(0);
+            }
+
+            // Sleep until the next byte arrives, more is sent, or we give up.
+            let mut wait = pipe.bytes.front().map(|(at, _)| *at - now);
+            if let Some(deadline) = deadline {
+                if now >= deadline {
+                    return Err(io::Error::new(ErrorKind::TimedOut, "Nothing arrived"));
+                }
+                wait = Some(wait.map_or(deadline - now, |wait| wait.min(deadline - now)));
+            }
+            pipe = match wait {
+                None => self.incoming.changed.wait(pipe).unwrap(),
+                Some(wait) => self.incoming.changed.wait_timeout(pipe, wait).unwrap().0,
+            };
+        }
+    }
+}
+
+impl Write for LaggyLink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let now = Instant::now();
+        let mut pipe = self.outgoing.pipe.lock().unwrap();
+        for byte in buf {
+            // Nothing overtakes what was sent before it.
+            let mut at = now + self.delay();
+            if let Some((last, _)) = pipe.bytes.back() {
+                at = at.max(*last);
+            }
+            pipe.bytes.push_back((at, *byte));
+        }
+        self.outgoing.changed.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LaggyLink {
+    fn drop(&mut self) {
+        if Arc::strong_count(&self.handles) == 1 {
+            self.outgoing.close();
+        }
+    }
+}
+
+// xorshift64*, as in the soak test.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // The state must never be zero.
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
@@ -0,0 +1,3 @@
+mod controller;
+mod laggylink;
+mod netplay;
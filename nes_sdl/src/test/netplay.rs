@@ -0,0 +1,159 @@
+use std::thread;
+use std::time::Duration;
+
+use nes::emulator::ines::ROM;
+use nes::emulator::metadata::Metadata;
+use nes::emulator::Region;
+
+use crate::netplay::{Connection, NetInput, Netplay};
+use crate::test::laggylink::{Conditions, LaggyLink};
+
+const FRAMES: u64 = 60;
+
+fn metadata() -> Metadata {
+    let mut data = vec![
+        b'N', b'E', b'S', 0x1A, 1, 1, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    data.extend(vec![0xEA; 0x4000]);
+    data.extend(vec![0; 0x2000]);
+    Metadata::new(&ROM::from_bytes(data), Region::NTSC)
+}
+
+// What each player presses on each frame.  Changes every frame, so an input used on the wrong
+// frame shows up.
+fn buttons(player: usize, frame: u64) -> u8 {
+    (frame as u8)
+        .wrapping_mul(7)
+        .wrapping_add(player as u8 * 100)
+}
+
+// The host resets on frame 10.
+fn reset(player: usize, frame: u64) -> bool {
+    player == 1 && frame == 10
+}
+
+// Each player sends `FRAMES` frames of input, and gets back what their game would run with.  The
+// client runs on another thread, as it would on another machine.
+fn play(
+    conditions: Conditions,
+    host_delay: u32,
+    client_delay: u32,
+) -> (u32, Vec<NetInput>, Vec<NetInput>) {
+    let (host_link, client_link) = LaggyLink::pair(conditions);
+    let client = thread::spawn(move || {
+        let connection = Connection::new(Box::new(client_link), false);
+        let mut netplay = Netplay::start(connection, &metadata(), client_delay).unwrap();
+        let inputs: Vec<NetInput> = (0..FRAMES)
+            .map(|frame| {
+                netplay
+                    .exchange(buttons(2, frame), reset(2, frame))
+                    .unwrap()
+            })
+            .collect();
+        (netplay.delay(), inputs)
+    });
+
+    let connection = Connection::new(Box::new(host_link), true);
+    let mut netplay = Netplay::start(connection, &metadata(), host_delay).unwrap();
+    let host_inputs: Vec<NetInput> = (0..FRAMES)
+        .map(|frame| {
+            netplay
+                .exchange(buttons(1, frame), reset(1, frame))
+                .unwrap()
+        })
+        .collect();
+    let (client_delay, client_inputs) = client.join().unwrap();
+    assert_eq!(client_delay, netplay.delay());
+    (netplay.delay(), host_inputs, client_inputs)
+}
+
+// What both players should see, `delay` frames after it was pressed.
+fn expected(delay: u32) -> Vec<NetInput> {
+    let delay = u64::from(delay);
+    (0..FRAMES)
+        .map(|frame| match frame.checked_sub(delay) {
+            None => NetInput::default(),
+            Some(pressed) => NetInput {
+                reset: reset(1, pressed) || reset(2, pressed),
+                joy1: buttons(1, pressed),
+                joy2: buttons(2, pressed),
+            },
+        })
+        .collect()
+}
+
+#[test]
+fn test_input_used_after_delay() {
+    let conditions = Conditions {
+        latency: Duration::from_millis(1),
+        jitter: Duration::from_millis(2),
+        seed: 1,
+    };
+    let (delay, host, client) = play(conditions, 3, 3);
+    assert_eq!(delay, 3);
+    assert_eq!(host, expected(3));
+    assert_eq!(client, expected(3));
+}
+
+#[test]
+fn test_client_uses_hosts_delay() {
+    let conditions = Conditions {
+        latency: Duration::from_millis(1),
+        jitter: Duration::from_millis(0),
+        seed: 1,
+    };
+    let (delay, host, client) = play(conditions, 5, 1);
+    assert_eq!(delay, 5);
+    assert_eq!(host, expected(5));
+    assert_eq!(client, expected(5));
+}
+
+// However late or unevenly input arrives, lockstep only changes when frames run, never what they
+// run with.
+#[test]
+fn test_lag_does_not_change_input() {
+    for delay in 0..3 {
+        for seed in 0..4 {
+            let conditions = Conditions {
+                latency: Duration::from_micros(seed * 500),
+                jitter: Duration::from_millis(3),
+                seed,
+            };
+            let (_, host, client) = play(conditions, delay, delay);
+            assert_eq!(host, expected(delay), "delay {} seed {}", delay, seed);
+            assert_eq!(client, host, "delay {} seed {}", delay, seed);
+        }
+    }
+}
+
+#[test]
+fn test_other_player_leaving() {
+    let conditions = Conditions {
+        latency: Duration::from_millis(1),
+        jitter: Duration::from_millis(1),
+        seed: 7,
+    };
+    let (host_link, client_link) = LaggyLink::pair(conditions);
+    let client = thread::spawn(move || {
+        let connection = Connection::new(Box::new(client_link), false);
+        let mut netplay = Netplay::start(connection, &metadata(), 0).unwrap();
+        for frame in 0..10 {
+            netplay.exchange(buttons(2, frame), false).unwrap();
+        }
+    });
+
+    let connection = Connection::new(Box::new(host_link), true);
+    let mut netplay = Netplay::start(connection, &metadata(), 2).unwrap();
+    for frame in 0..10 {
+        netplay.exchange(buttons(1, frame), false).unwrap();
+    }
+    client.join().unwrap();
+    // The client's last two frames of input were already on their way.
+    for frame in 10..12 {
+        netplay.exchange(buttons(1, frame), false).unwrap();
+    }
+    assert_eq!(
+        netplay.exchange(0, false),
+        Err(String::from("The other player left"))
+    );
+}
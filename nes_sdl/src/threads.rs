@@ -0,0 +1,92 @@
+use crate::config::ThreadConfig;
+
+// Nice value for a high priority emulator thread.  Enough to win out over ordinary background
+// work without starving the UI thread.
+#[cfg(unix)]
+const HIGH_PRIORITY_NICE: libc::c_int = -10;
+
+// What was actually applied, since the platform or permissions may not allow everything asked for.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ThreadStatus {
+    pub high_priority: bool,
+    pub core: Option<usize>,
+}
+
+impl ThreadStatus {
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        if self.high_priority {
+            summary.push_str(" HIPRI");
+        }
+        if let Some(core) = self.core {
+            summary.push_str(&format!(" CPU{}", core));
+        }
+        summary
+    }
+}
+
+// Applies the config to the calling thread.  Failures are reported but not fatal.
+pub fn configure_current_thread(config: &ThreadConfig) -> ThreadStatus {
+    let mut status = ThreadStatus::default();
+    if config.high_priority {
+        match raise_priority() {
+            Err(cause) => println!("Couldn't raise emulator thread priority: {}", cause),
+            Ok(()) => status.high_priority = true,
+        }
+    }
+    if let Some(core) = config.core {
+        match pin_to_core(core) {
+            Err(cause) => println!("Couldn't pin emulator thread to core {}: {}", core, cause),
+            Ok(()) => status.core = Some(core),
+        }
+    }
+    status
+}
+
+// Each thread on Linux has its own nice value.
+#[cfg(target_os = "linux")]
+fn raise_priority() -> Result<(), String> {
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+    check(unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, HIGH_PRIORITY_NICE) })
+}
+
+// Elsewhere the nice value is per process, which still helps against other programs.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn raise_priority() -> Result<(), String> {
+    check(unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, HIGH_PRIORITY_NICE) })
+}
+
+#[cfg(not(unix))]
+fn raise_priority() -> Result<(), String> {
+    Err(String::from("not supported on this platform"))
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to_core(core: usize) -> Result<(), String> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(String::from("no such core"));
+    }
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        check(libc::sched_setaffinity(
+            0,
+            std::mem::size_of::<libc::cpu_set_t>(),
+            &set,
+        ))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_core(_core: usize) -> Result<(), String> {
+    Err(String::from("not supported on this platform"))
+}
+
+#[cfg(unix)]
+fn check(result: libc::c_int) -> Result<(), String> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
@@ -37,7 +37,13 @@ import("nes_web")
 
             var running = true;
 
-            nes = Emulator.new(new Uint8Array(buf));
+            try {
+                nes = Emulator.new(new Uint8Array(buf));
+            } catch (e) {
+                console.error("Couldn't load ROM:", e);
+                nes = null;
+                return;
+            }
 
             function step() {
                 var cycles = BigInt(0);
@@ -68,13 +74,13 @@ import("nes_web")
                     break;
                 case "keydown":
                     key = convertJsKeyCode(data.key);
-                    if (key !== null) {
+                    if (key !== null && nes !== null) {
                         nes.broadcast(Event.key_down(key));
                     }
                     break;
                 case "keyup":
                     key = convertJsKeyCode(data.key);
-                    if (key !== null) {
+                    if (key !== null && nes !== null) {
                         nes.broadcast(Event.key_up(key));
                     }
                     break;
@@ -5,41 +5,82 @@ use std::rc::Rc;
 
 use wasm_bindgen::prelude::*;
 
-use nes::emulator::ines;
-use nes::emulator::io;
-use nes::emulator::io::event::EventBus;
-use nes::emulator::NES;
+use nes::prelude::{Config, EventBus, Screen, SimpleAudioOut, NES, ROM};
 
 #[wasm_bindgen]
 pub struct Emulator {
     nes: NES,
     event_bus: Rc<RefCell<EventBus>>,
-    video_out: Rc<RefCell<io::Screen>>,
-    audio_out: Rc<RefCell<io::SimpleAudioOut>>,
+    video_out: Rc<RefCell<Screen>>,
+    audio_out: Rc<RefCell<SimpleAudioOut>>,
+}
+
+#[wasm_bindgen]
+pub struct DirtyFrame {
+    pixels: Vec<u8>,
+    rects: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl DirtyFrame {
+    pub fn pixels(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+
+    pub fn rects(&self) -> Vec<u32> {
+        self.rects.clone()
+    }
 }
 
 #[wasm_bindgen]
 impl Emulator {
-    pub fn new(rom_data: Vec<u8>) -> Emulator {
+    // Throws if the bytes aren't a ROM we can run, rather than panicking inside the worker.
+    pub fn new(rom_data: Vec<u8>) -> Result<Emulator, JsValue> {
         let event_bus = Rc::new(RefCell::new(EventBus::new()));
-        let video_out = Rc::new(RefCell::new(io::Screen::new()));
-        let audio_out = Rc::new(RefCell::new(io::SimpleAudioOut::new(48_000.0)));
-        let rom = ines::ROM::from_bytes(rom_data);
+        let video_out = Rc::new(RefCell::new(Screen::new()));
+        let config = Config {
+            sample_rate: 48_000.0,
+            ..Config::default()
+        };
+        let audio_out = Rc::new(RefCell::new(SimpleAudioOut::new(config.sample_rate)));
+        let rom = ROM::parse(rom_data).map_err(|cause| JsValue::from_str(&cause))?;
+        let nes = NES::try_new(
+            event_bus.clone(),
+            video_out.clone(),
+            audio_out.clone(),
+            rom,
+            &config,
+        )
+        .map_err(|cause| JsValue::from_str(&cause))?;
 
-        let nes = NES::new(event_bus.clone(), video_out.clone(), audio_out.clone(), rom);
-
-        Emulator {
+        Ok(Emulator {
             nes,
             event_bus,
             video_out,
             audio_out,
-        }
+        })
     }
 
     pub fn run(&mut self, ticks: u32) -> u64 {
         self.nes.tick_multi(ticks)
     }
 
+    // Runs until the next frame has been drawn.  Returns master cycles elapsed, for `get_audio`.
+    pub fn step_frame(&mut self) -> u64 {
+        self.nes.run_frame()
+    }
+
+    // For gamepads, which don't come through as key events.  Bit 0 is A, as in `Controller`.
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        if let Some(joy) = self.nes.joypads().get(player) {
+            joy.borrow_mut().set_buttons(buttons);
+        }
+    }
+
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.nes.peek(address)
+    }
+
     pub fn get_frame(&self) -> Vec<u8> {
         let mut buf = [0; 256 * 240 * 3];
         self.video_out.borrow().do_render(|frame| {
@@ -51,6 +92,29 @@ impl Emulator {
         return buf.to_vec();
     }
 
+    pub fn set_dirty_tracking(&mut self, on: bool) {
+        self.video_out.borrow_mut().set_dirty_tracking(on);
+    }
+
+    // The frame, plus areas changed since the last call as a flat list of x, y, width, height.
+    pub fn get_dirty_frame(&self) -> DirtyFrame {
+        let mut frame = DirtyFrame {
+            pixels: vec![],
+            rects: vec![],
+        };
+        self.video_out
+            .borrow_mut()
+            .do_render_dirty(|pixels, dirty| {
+                frame.pixels = pixels.to_vec();
+                for rect in dirty.rects() {
+                    frame
+                        .rects
+                        .extend_from_slice(&[rect.x, rect.y, rect.width, rect.height]);
+                }
+            });
+        frame
+    }
+
     pub fn get_audio(&self, master_cycles: u64, num_samples: u64) -> Vec<f32> {
         let mut buf: Vec<f32> = vec![];
         self.audio_out